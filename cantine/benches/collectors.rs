@@ -0,0 +1,89 @@
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use once_cell::sync::Lazy;
+use tantivy::{query::Query, schema::SchemaBuilder, Index, IndexReader};
+
+use cantine::{
+    index::RecipeIndex,
+    model::{Recipe, Sort},
+    synthetic::Synthetic,
+};
+use tique::QueryParser;
+
+const CORPUS_SIZE: usize = 50_000;
+
+struct Corpus {
+    reader: IndexReader,
+    recipe_index: RecipeIndex,
+    query: Box<dyn Query>,
+}
+
+static CORPUS: Lazy<Corpus> = Lazy::new(|| {
+    let templates = include_str!("../tests/sample_recipes.jsonlines")
+        .lines()
+        .map(|line| serde_json::from_str::<Recipe>(line).expect("valid recipe json"))
+        .collect::<Vec<_>>();
+
+    let mut builder = SchemaBuilder::new();
+    let recipe_index = RecipeIndex::from(&mut builder);
+    let index = Index::create_in_ram(builder.build());
+
+    let mut writer = index.writer_with_num_threads(1, 50_000_000).unwrap();
+    for recipe in Synthetic::new(&templates, 42).take(CORPUS_SIZE) {
+        writer.add_document(recipe_index.make_document(&recipe));
+    }
+    writer.commit().unwrap();
+
+    let parser =
+        QueryParser::new(&index, vec![recipe_index.name, recipe_index.ingredients]).unwrap();
+    let query = parser.parse("chicken garlic").expect("something to search");
+
+    Corpus {
+        reader: index.reader().unwrap(),
+        recipe_index,
+        query,
+    }
+});
+
+fn top_k(c: &mut Criterion) {
+    let corpus = &*CORPUS;
+    let searcher = corpus.reader.searcher();
+
+    let mut group = c.benchmark_group("top_k");
+    for sort in &[Sort::Relevance, Sort::NumIngredients, Sort::Calories] {
+        let name = format!("{:?}", sort);
+
+        group.bench_function(BenchmarkId::new("first_page", &name), |b| {
+            b.iter(|| {
+                corpus
+                    .recipe_index
+                    .search(&searcher, corpus.query.as_ref(), 20, sort.clone(), None)
+                    .unwrap()
+            })
+        });
+
+        // Past the first page, the collectors check every candidate
+        // against the cursor
+        let (_, _, after) = corpus
+            .recipe_index
+            .search(&searcher, corpus.query.as_ref(), 20, sort.clone(), None)
+            .unwrap();
+        group.bench_function(BenchmarkId::new("with_condition", &name), |b| {
+            b.iter(|| {
+                corpus
+                    .recipe_index
+                    .search(
+                        &searcher,
+                        corpus.query.as_ref(),
+                        20,
+                        sort.clone(),
+                        after.clone(),
+                    )
+                    .unwrap()
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, top_k);
+criterion_main!(benches);
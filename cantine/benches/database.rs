@@ -0,0 +1,83 @@
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion};
+use once_cell::sync::Lazy;
+use tempfile::TempDir;
+
+use cantine::{
+    database::{DatabaseReader, DatabaseWriter},
+    model::Recipe,
+    synthetic::Synthetic,
+};
+
+const CORPUS_SIZE: usize = 20_000;
+
+static TEMPLATES: Lazy<Vec<Recipe>> = Lazy::new(|| {
+    include_str!("../tests/sample_recipes.jsonlines")
+        .lines()
+        .map(|line| serde_json::from_str(line).expect("valid recipe json"))
+        .collect()
+});
+
+fn populated() -> (TempDir, Vec<Recipe>) {
+    let dir = tempfile::tempdir().unwrap();
+    let recipes = Synthetic::new(&TEMPLATES, 42)
+        .take(CORPUS_SIZE)
+        .collect::<Vec<_>>();
+
+    let mut writer = DatabaseWriter::new(dir.path()).unwrap();
+    for recipe in &recipes {
+        writer.append(recipe).unwrap();
+    }
+    writer.flush().unwrap();
+
+    (dir, recipes)
+}
+
+fn add(c: &mut Criterion) {
+    let recipes = Synthetic::new(&TEMPLATES, 7).take(1000).collect::<Vec<_>>();
+
+    c.bench_function("database/add_1000", |b| {
+        b.iter_batched(
+            || tempfile::tempdir().unwrap(),
+            |dir| {
+                let mut writer = DatabaseWriter::new(dir.path()).unwrap();
+                for recipe in &recipes {
+                    writer.append(recipe).unwrap();
+                }
+                writer.flush().unwrap();
+                dir
+            },
+            BatchSize::PerIteration,
+        )
+    });
+}
+
+fn get(c: &mut Criterion) {
+    let (dir, recipes) = populated();
+    let reader = DatabaseReader::<Recipe>::open(dir.path()).unwrap();
+
+    let mut idx = 0;
+    c.bench_function("database/get_by_id", |b| {
+        b.iter(|| {
+            idx = (idx + 7919) % recipes.len();
+            reader.find_by_id(recipes[idx].recipe_id).unwrap().unwrap()
+        })
+    });
+    c.bench_function("database/get_by_uuid", |b| {
+        b.iter(|| {
+            idx = (idx + 7919) % recipes.len();
+            reader.find_by_uuid(&recipes[idx].uuid).unwrap().unwrap()
+        })
+    });
+}
+
+// Opening a database replays its offsets log
+fn startup(c: &mut Criterion) {
+    let (dir, _recipes) = populated();
+
+    c.bench_function("database/open", |b| {
+        b.iter(|| DatabaseReader::<Recipe>::open(dir.path()).unwrap())
+    });
+}
+
+criterion_group!(benches, add, get, startup);
+criterion_main!(benches);
@@ -0,0 +1,278 @@
+use std::{
+    fs::File,
+    io::{self, BufReader, BufWriter, Result},
+    path::Path,
+    str::FromStr,
+};
+
+use serde::{Deserialize, Serialize};
+use tantivy::{
+    tokenizer::{
+        self, AsciiFoldingFilter, LowerCaser, RemoveLongFilter, SimpleTokenizer, Stemmer,
+        StopWordFilter, TextAnalyzer,
+    },
+    Index,
+};
+
+/// How the text fields (name, ingredients, instructions) are broken
+/// into terms, both when indexing and when parsing queries.
+///
+/// The default is equivalent to tantivy's "default" tokenizer, so
+/// indices created without an explicit analysis keep working.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Analysis {
+    /// Reduce words to their stem using the rules of a language
+    pub stemmer: Option<Language>,
+    /// Words to drop entirely. Compared after lowercasing and folding
+    pub stopwords: Vec<String>,
+    pub lowercase: bool,
+    /// Replace accented characters with their ascii counterparts so
+    /// that "creme" finds "crème"
+    pub ascii_folding: bool,
+}
+
+impl Default for Analysis {
+    fn default() -> Self {
+        Self {
+            stemmer: None,
+            stopwords: Vec::new(),
+            lowercase: true,
+            ascii_folding: false,
+        }
+    }
+}
+
+const DEFAULT_TOKENIZER: &str = "default";
+const CANTINE_TOKENIZER: &str = "cantine";
+const ANALYSIS_FILE: &str = "analysis.json";
+
+// Same as tantivy's default tokenizer
+const MAX_TOKEN_LENGTH: usize = 40;
+
+impl Analysis {
+    /// The name text fields should be indexed with
+    pub fn tokenizer_name(&self) -> &'static str {
+        if *self == Self::default() {
+            DEFAULT_TOKENIZER
+        } else {
+            CANTINE_TOKENIZER
+        }
+    }
+
+    pub fn analyzer(&self) -> TextAnalyzer {
+        let mut analyzer =
+            TextAnalyzer::from(SimpleTokenizer).filter(RemoveLongFilter::limit(MAX_TOKEN_LENGTH));
+
+        if self.lowercase {
+            analyzer = analyzer.filter(LowerCaser);
+        }
+
+        if self.ascii_folding {
+            analyzer = analyzer.filter(AsciiFoldingFilter);
+        }
+
+        if !self.stopwords.is_empty() {
+            analyzer = analyzer.filter(StopWordFilter::remove(self.normalized_stopwords()));
+        }
+
+        if let Some(language) = self.stemmer {
+            analyzer = analyzer.filter(Stemmer::new(language.into()));
+        }
+
+        analyzer
+    }
+
+    /// Makes the analyzer available to the given index. Must be called
+    /// before writing to or parsing queries for an index created with
+    /// this analysis
+    pub fn register(&self, index: &Index) {
+        index
+            .tokenizers()
+            .register(self.tokenizer_name(), self.analyzer());
+    }
+
+    /// Reads the analysis persisted in `base_dir`, falling back to the
+    /// default when there's none (indices created before it existed)
+    pub fn load<P: AsRef<Path>>(base_dir: P) -> Result<Self> {
+        match File::open(base_dir.as_ref().join(ANALYSIS_FILE)) {
+            Ok(file) => serde_json::from_reader(BufReader::new(file))
+                .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err)),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(err) => Err(err),
+        }
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, base_dir: P) -> Result<()> {
+        let file = File::create(base_dir.as_ref().join(ANALYSIS_FILE))?;
+        serde_json::to_writer_pretty(BufWriter::new(file), self)
+            .map_err(|err| io::Error::new(io::ErrorKind::Other, err))
+    }
+
+    // Stopwords are filtered after lowercasing and folding, so the
+    // list must go through the same steps to match anything
+    fn normalized_stopwords(&self) -> Vec<String> {
+        let mut normalizer = TextAnalyzer::from(SimpleTokenizer);
+
+        if self.lowercase {
+            normalizer = normalizer.filter(LowerCaser);
+        }
+
+        if self.ascii_folding {
+            normalizer = normalizer.filter(AsciiFoldingFilter);
+        }
+
+        let mut words = Vec::with_capacity(self.stopwords.len());
+        for word in &self.stopwords {
+            normalizer
+                .token_stream(word)
+                .process(&mut |token| words.push(token.text.clone()));
+        }
+        words
+    }
+}
+
+/// Languages with stemming support
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Language {
+    Arabic,
+    Danish,
+    Dutch,
+    English,
+    Finnish,
+    French,
+    German,
+    Greek,
+    Hungarian,
+    Italian,
+    Norwegian,
+    Portuguese,
+    Romanian,
+    Russian,
+    Spanish,
+    Swedish,
+    Tamil,
+    Turkish,
+}
+
+impl From<Language> for tokenizer::Language {
+    fn from(src: Language) -> Self {
+        match src {
+            Language::Arabic => Self::Arabic,
+            Language::Danish => Self::Danish,
+            Language::Dutch => Self::Dutch,
+            Language::English => Self::English,
+            Language::Finnish => Self::Finnish,
+            Language::French => Self::French,
+            Language::German => Self::German,
+            Language::Greek => Self::Greek,
+            Language::Hungarian => Self::Hungarian,
+            Language::Italian => Self::Italian,
+            Language::Norwegian => Self::Norwegian,
+            Language::Portuguese => Self::Portuguese,
+            Language::Romanian => Self::Romanian,
+            Language::Russian => Self::Russian,
+            Language::Spanish => Self::Spanish,
+            Language::Swedish => Self::Swedish,
+            Language::Tamil => Self::Tamil,
+            Language::Turkish => Self::Turkish,
+        }
+    }
+}
+
+impl FromStr for Language {
+    type Err = String;
+
+    fn from_str(input: &str) -> std::result::Result<Self, Self::Err> {
+        match input.to_lowercase().as_str() {
+            "ar" | "arabic" => Ok(Language::Arabic),
+            "da" | "danish" => Ok(Language::Danish),
+            "nl" | "dutch" => Ok(Language::Dutch),
+            "en" | "english" => Ok(Language::English),
+            "fi" | "finnish" => Ok(Language::Finnish),
+            "fr" | "french" => Ok(Language::French),
+            "de" | "german" => Ok(Language::German),
+            "el" | "greek" => Ok(Language::Greek),
+            "hu" | "hungarian" => Ok(Language::Hungarian),
+            "it" | "italian" => Ok(Language::Italian),
+            "no" | "nb" | "norwegian" => Ok(Language::Norwegian),
+            "pt" | "portuguese" => Ok(Language::Portuguese),
+            "ro" | "romanian" => Ok(Language::Romanian),
+            "ru" | "russian" => Ok(Language::Russian),
+            "es" | "spanish" => Ok(Language::Spanish),
+            "sv" | "swedish" => Ok(Language::Swedish),
+            "ta" | "tamil" => Ok(Language::Tamil),
+            "tr" | "turkish" => Ok(Language::Turkish),
+            other => Err(format!("Unknown stemmer language: {}", other)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tokens(analysis: &Analysis, input: &str) -> Vec<String> {
+        let mut found = Vec::new();
+        analysis
+            .analyzer()
+            .token_stream(input)
+            .process(&mut |token| found.push(token.text.clone()));
+        found
+    }
+
+    #[test]
+    fn default_is_like_tantivy() {
+        let analysis = Analysis::default();
+        assert_eq!("default", analysis.tokenizer_name());
+        assert_eq!(vec!["crème", "brûlée"], tokens(&analysis, "Crème Brûlée"));
+    }
+
+    #[test]
+    fn french_analysis() {
+        let analysis = Analysis {
+            stemmer: Some(Language::French),
+            stopwords: vec!["Les".to_owned(), "à".to_owned(), "de".to_owned()],
+            ascii_folding: true,
+            ..Analysis::default()
+        };
+
+        assert_eq!("cantine", analysis.tokenizer_name());
+        assert_eq!(
+            tokens(&analysis, "pomme terre"),
+            tokens(&analysis, "Les Pommes de Terre à l'ail")[..2].to_vec()
+        );
+        assert_eq!(
+            tokens(&analysis, "creme brulee"),
+            tokens(&analysis, "Crème brûlée")
+        );
+        assert!(tokens(&analysis, "les à de").is_empty());
+    }
+
+    #[test]
+    fn persistence() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+
+        assert_eq!(Analysis::default(), Analysis::load(dir.path())?);
+
+        let analysis = Analysis {
+            stemmer: Some(Language::Portuguese),
+            stopwords: vec!["de".to_owned()],
+            lowercase: true,
+            ascii_folding: true,
+        };
+        analysis.save(dir.path())?;
+
+        assert_eq!(analysis, Analysis::load(dir.path())?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn language_from_str() {
+        assert_eq!(Ok(Language::Portuguese), "pt".parse());
+        assert_eq!(Ok(Language::French), "French".parse());
+        assert!("klingon".parse::<Language>().is_err());
+    }
+}
@@ -0,0 +1,436 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    fs,
+    io::{self, ErrorKind},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    thread::{self, JoinHandle},
+};
+
+use crossbeam_channel::{bounded, Receiver, Sender, TrySendError};
+use serde::{Deserialize, Serialize};
+use uuid::{self, Uuid};
+
+use crate::{
+    clock::Clock,
+    database::{DatabaseReader, DatabaseRecord, DatabaseWriter},
+    error::{Error, Result},
+    instant,
+};
+
+const QUERIES_KEYSPACE: &str = "queries";
+
+// Flushing makes records visible to readers, but it syncs to disk
+const FLUSH_EVERY: usize = 100;
+
+// Searches waiting to be logged; past this many they're dropped
+const MAX_QUEUED: usize = 1024;
+
+// How many of the latest searches `QueryLog::recent_queries` keeps
+const NUM_RECENT_QUERIES: usize = 1000;
+
+/// A search, as kept by `QueryLog`
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct QueryRecord {
+    /// When the search happened, in microseconds since the epoch as
+    /// told by the log's clock. Unique within a log: set by
+    /// `QueryLog::record`
+    pub id: u64,
+    /// The full-text part of the search, normalized so that
+    /// equivalent ones are the same
+    pub fulltext: Option<String>,
+    /// The feature filter of the search, as json
+    pub filter: Option<String>,
+    /// How many recipes matched
+    pub num_hits: u64,
+    pub latency_micros: u64,
+    /// The cursor of searches past the first page, as json
+    pub after: Option<String>,
+    /// The experiment arm whose ranking served the search
+    pub ranking_profile: Option<String>,
+}
+
+impl DatabaseRecord for QueryRecord {
+    fn get_id(&self) -> u64 {
+        self.id
+    }
+
+    fn get_uuid(&self) -> uuid::Bytes {
+        *Uuid::from_u128(u128::from(self.id)).as_bytes()
+    }
+}
+
+/// Where the searches done on the recipe database at `db_path` are
+/// logged
+pub fn queries_path(db_path: &Path) -> PathBuf {
+    db_path.join(QUERIES_KEYSPACE)
+}
+
+/// Appends every search it's told about to the queries keyspace, for
+/// `top_queries` to make sense of. Writing happens in a background
+/// thread so that logging never holds up a search: records show up
+/// to readers in batches, on `flush` and when the log is dropped
+pub struct QueryLog {
+    sender: Option<Sender<Message>>,
+    worker: Option<JoinHandle<()>>,
+    clock: Box<dyn Clock>,
+    recent: Arc<RecentQueries>,
+}
+
+enum Message {
+    Record(QueryRecord),
+    Flush(Sender<Result<()>>),
+}
+
+impl QueryLog {
+    /// Opens the queries keyspace for appending, creating it if
+    /// needed. Records are stamped with the time `clock` tells
+    pub fn open(db_path: &Path, clock: Box<dyn Clock>) -> Result<Self> {
+        let path = queries_path(db_path);
+        fs::create_dir_all(&path)?;
+
+        // Ids must not go back, whatever the clock does
+        let last_id = match DatabaseReader::<QueryRecord>::open(&path) {
+            Ok(reader) => reader.ids().copied().max().unwrap_or(0),
+            Err(Error::Io(err)) if err.kind() == ErrorKind::NotFound => 0,
+            Err(err) => return Err(err),
+        };
+
+        let writer = match DatabaseWriter::open(&path) {
+            Err(Error::Io(err)) if err.kind() == ErrorKind::NotFound => DatabaseWriter::new(&path),
+            other => other,
+        }?;
+
+        let recent = Arc::new(RecentQueries::new(NUM_RECENT_QUERIES));
+
+        let (sender, receiver) = bounded(MAX_QUEUED);
+        let worker_recent = recent.clone();
+        let worker = thread::Builder::new()
+            .name("cantine-query-log".to_owned())
+            .spawn(move || {
+                LogWorker {
+                    writer,
+                    last_id,
+                    num_unflushed: 0,
+                    recent: worker_recent,
+                }
+                .run(receiver)
+            })?;
+
+        Ok(Self {
+            sender: Some(sender),
+            worker: Some(worker),
+            clock,
+            recent,
+        })
+    }
+
+    /// The latest searches that found something, as logged so far
+    pub fn recent_queries(&self) -> &RecentQueries {
+        &self.recent
+    }
+
+    /// Queues `record` for appending, stamped with the current time.
+    /// Doesn't wait for the write: a record is dropped (with an error)
+    /// instead when the writer is too far behind
+    pub fn record(&self, mut record: QueryRecord) -> Result<()> {
+        record.id = self.clock.now_micros();
+        self.sender
+            .as_ref()
+            .expect("sender is only taken on drop")
+            .try_send(Message::Record(record))
+            .map_err(|err| match err {
+                TrySendError::Full(_) => {
+                    io::Error::new(ErrorKind::WouldBlock, "Query log is falling behind").into()
+                }
+                TrySendError::Disconnected(_) => worker_gone(),
+            })
+    }
+
+    /// Makes every record so far visible to readers
+    pub fn flush(&self) -> Result<()> {
+        let (ack_sender, ack_receiver) = bounded(1);
+        self.sender
+            .as_ref()
+            .expect("sender is only taken on drop")
+            .send(Message::Flush(ack_sender))
+            .map_err(|_| worker_gone())?;
+        ack_receiver.recv().map_err(|_| worker_gone())?
+    }
+}
+
+impl Drop for QueryLog {
+    fn drop(&mut self) {
+        // Disconnecting makes the worker flush and exit
+        drop(self.sender.take());
+        if let Some(Err(_)) = self.worker.take().map(JoinHandle::join) {
+            log::error!("Query log thread panicked");
+        }
+    }
+}
+
+fn worker_gone() -> Error {
+    io::Error::new(ErrorKind::BrokenPipe, "Query log thread is gone").into()
+}
+
+struct LogWorker {
+    writer: DatabaseWriter<QueryRecord>,
+    last_id: u64,
+    num_unflushed: usize,
+    recent: Arc<RecentQueries>,
+}
+
+impl LogWorker {
+    fn run(mut self, receiver: Receiver<Message>) {
+        for message in receiver.iter() {
+            match message {
+                Message::Record(record) => {
+                    if let Err(err) = self.append(record) {
+                        log::warn!("Failed to log a search: {}", err);
+                    }
+                }
+                Message::Flush(ack) => {
+                    // The caller may have given up waiting
+                    let _ = ack.send(self.flush());
+                }
+            }
+        }
+
+        if let Err(err) = self.flush() {
+            log::warn!("Failed to flush the query log: {}", err);
+        }
+    }
+
+    fn append(&mut self, mut record: QueryRecord) -> Result<()> {
+        // Searches stamped within the same tick still get their own id
+        record.id = record.id.max(self.last_id + 1);
+        self.last_id = record.id;
+
+        self.writer.append(&record)?;
+        self.num_unflushed += 1;
+
+        if record.num_hits > 0 {
+            if let Some(fulltext) = record.fulltext {
+                self.recent.push(fulltext);
+            }
+        }
+
+        if self.num_unflushed >= FLUSH_EVERY {
+            self.flush()?;
+        }
+
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.writer.flush()?;
+        self.num_unflushed = 0;
+        Ok(())
+    }
+}
+
+/// The full-text part of the latest searches that found something,
+/// for suggesting what people are after while they type
+pub struct RecentQueries {
+    queries: Mutex<VecDeque<String>>,
+    capacity: usize,
+}
+
+impl RecentQueries {
+    /// Keeps up to `capacity` searches, forgetting the oldest first
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            queries: Mutex::new(VecDeque::with_capacity(capacity)),
+            capacity,
+        }
+    }
+
+    pub fn push(&self, fulltext: String) {
+        let mut queries = self.queries.lock().expect("lock not poisoned");
+        if queries.len() >= self.capacity {
+            queries.pop_front();
+        }
+        queries.push_back(fulltext);
+    }
+
+    /// The recent searches that what was typed so far could become:
+    /// every word of `input` starts a word of theirs. Yields up to
+    /// `limit` of them with how many times each was done, most
+    /// frequent first
+    pub fn completions(&self, input: &str, limit: usize) -> Vec<(String, usize)> {
+        let typed = instant::prefix_terms(input);
+        if typed.is_empty() {
+            return Vec::new();
+        }
+
+        let mut counts: HashMap<&str, usize> = HashMap::new();
+        let queries = self.queries.lock().expect("lock not poisoned");
+        for query in queries.iter() {
+            let words = instant::prefix_terms(query);
+            if typed
+                .iter()
+                .all(|prefix| words.iter().any(|word| word.starts_with(prefix.as_str())))
+            {
+                *counts.entry(query.as_str()).or_insert(0) += 1;
+            }
+        }
+
+        let mut completions: Vec<_> = counts
+            .into_iter()
+            .map(|(query, count)| (query.to_owned(), count))
+            .collect();
+        completions.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        completions.truncate(limit);
+        completions
+    }
+}
+
+/// How a search (a full-text and filter pair) did, over every time
+/// it was done
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct QueryStats {
+    pub fulltext: Option<String>,
+    pub filter: Option<String>,
+    pub count: u64,
+    /// How many times nothing matched
+    pub zero_hits: u64,
+    pub mean_latency_micros: u64,
+}
+
+/// Groups the searches logged for the recipe database at `db_path`,
+/// most frequent first. Empty if nothing was logged
+pub fn top_queries(db_path: &Path) -> Result<Vec<QueryStats>> {
+    let path = queries_path(db_path);
+    if !path.is_dir() {
+        return Ok(Vec::new());
+    }
+    let reader = DatabaseReader::<QueryRecord>::open(&path)?;
+
+    let mut grouped: HashMap<(Option<String>, Option<String>), (u64, u64, u64)> = HashMap::new();
+    for id in reader.ids() {
+        // Records whose data isn't flushed yet can't be read
+        let record = match reader.find_by_id(*id) {
+            Some(Ok(record)) => record,
+            _ => continue,
+        };
+
+        let (count, zero_hits, total_latency) =
+            grouped.entry((record.fulltext, record.filter)).or_default();
+        *count += 1;
+        if record.num_hits == 0 {
+            *zero_hits += 1;
+        }
+        *total_latency += record.latency_micros;
+    }
+
+    let mut stats = grouped
+        .into_iter()
+        .map(
+            |((fulltext, filter), (count, zero_hits, total_latency))| QueryStats {
+                fulltext,
+                filter,
+                count,
+                zero_hits,
+                mean_latency_micros: total_latency / count,
+            },
+        )
+        .collect::<Vec<_>>();
+
+    stats.sort_by(|a, b| {
+        b.count
+            .cmp(&a.count)
+            .then_with(|| a.fulltext.cmp(&b.fulltext))
+            .then_with(|| a.filter.cmp(&b.filter))
+    });
+    Ok(stats)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::clock::FixedClock;
+
+    fn search(fulltext: &str, num_hits: u64, latency_micros: u64) -> QueryRecord {
+        QueryRecord {
+            fulltext: Some(fulltext.to_owned()),
+            num_hits,
+            latency_micros,
+            ..QueryRecord::default()
+        }
+    }
+
+    #[test]
+    fn aggregates_logged_searches() -> Result<()> {
+        let db_dir = tempfile::tempdir()?;
+        assert!(top_queries(db_dir.path())?.is_empty());
+
+        let log = QueryLog::open(db_dir.path(), Box::new(FixedClock(1_600_000_000)))?;
+        log.record(search("pancakes", 10, 100))?;
+        log.record(search("pancakes", 12, 300))?;
+        log.record(search("fluffy pancakes", 0, 50))?;
+        log.record(search("pancakes", 0, 200))?;
+        drop(log);
+
+        // Reopening keeps appending
+        let log = QueryLog::open(db_dir.path(), Box::new(FixedClock(1_600_000_000)))?;
+        log.record(search("fluffy pancakes", 0, 150))?;
+        log.flush()?;
+
+        let stats = top_queries(db_dir.path())?;
+        assert_eq!(
+            vec![
+                QueryStats {
+                    fulltext: Some("pancakes".to_owned()),
+                    filter: None,
+                    count: 3,
+                    zero_hits: 1,
+                    mean_latency_micros: 200,
+                },
+                QueryStats {
+                    fulltext: Some("fluffy pancakes".to_owned()),
+                    filter: None,
+                    count: 2,
+                    zero_hits: 2,
+                    mean_latency_micros: 100,
+                }
+            ],
+            stats
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn recent_queries_complete_what_was_typed() {
+        let recent = RecentQueries::new(4);
+        for fulltext in &[
+            "chocolate cake",
+            "cheesecake",
+            "chocolate cake",
+            "carrot cake",
+        ] {
+            recent.push((*fulltext).to_owned());
+        }
+
+        assert_eq!(
+            vec![
+                ("chocolate cake".to_owned(), 2),
+                ("carrot cake".to_owned(), 1)
+            ],
+            recent.completions("cake c", 10)
+        );
+        assert_eq!(
+            vec![("chocolate cake".to_owned(), 2)],
+            recent.completions("Choc", 10)
+        );
+        assert!(recent.completions("", 10).is_empty());
+
+        // The oldest are forgotten
+        recent.push("pancakes".to_owned());
+        assert_eq!(
+            vec![("chocolate cake".to_owned(), 1)],
+            recent.completions("choc", 10)
+        );
+    }
+}
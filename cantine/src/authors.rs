@@ -0,0 +1,95 @@
+use std::{
+    collections::HashMap,
+    fs,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    database::{DatabaseReader, DatabaseWriter},
+    error::{Error, Result},
+    model::{Author, AuthorId},
+};
+
+const AUTHORS_KEYSPACE: &str = "authors";
+
+/// Where the author profiles of the recipe database at `db_path`
+/// are kept
+pub fn authors_path(db_path: &Path) -> PathBuf {
+    db_path.join(AUTHORS_KEYSPACE)
+}
+
+/// Opens the authors keyspace for appending, creating it if needed
+pub fn open_writer(db_path: &Path) -> Result<DatabaseWriter<Author>> {
+    let path = authors_path(db_path);
+    fs::create_dir_all(&path)?;
+
+    match DatabaseWriter::open(&path) {
+        Err(Error::Io(err)) if err.kind() == ErrorKind::NotFound => DatabaseWriter::new(&path),
+        other => other,
+    }
+}
+
+/// Opens the authors keyspace for lookups. None if there's no
+/// such keyspace, like in databases created before it existed
+pub fn open_reader(db_path: &Path) -> Result<Option<DatabaseReader<Author>>> {
+    let path = authors_path(db_path);
+    if path.is_dir() {
+        DatabaseReader::open(&path).map(Some)
+    } else {
+        Ok(None)
+    }
+}
+
+/// Reads every author into memory, so that the ingest pipeline can
+/// copy their attributes into the documents of their recipes
+pub fn load_all(db_path: &Path) -> Result<HashMap<AuthorId, Author>> {
+    match open_reader(db_path)? {
+        Some(reader) => {
+            let ids = reader.ids().copied().collect::<Vec<_>>();
+            reader.find_many(ids)
+        }
+        None => Ok(HashMap::new()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use uuid::Uuid;
+
+    fn author(author_id: AuthorId, verified: bool) -> Author {
+        Author {
+            uuid: Uuid::new_v4(),
+            author_id,
+            name: format!("Author {}", author_id),
+            profile_url: None,
+            verified,
+        }
+    }
+
+    #[test]
+    fn keyspace_is_created_on_demand() -> Result<()> {
+        let db_dir = tempfile::tempdir()?;
+
+        assert!(open_reader(db_dir.path())?.is_none());
+        assert!(load_all(db_dir.path())?.is_empty());
+
+        let mut writer = open_writer(db_dir.path())?;
+        writer.append(&author(1, false))?;
+        writer.append(&author(2, true))?;
+        drop(writer);
+
+        // Reopening appends instead of starting over
+        let mut writer = open_writer(db_dir.path())?;
+        writer.append(&author(1, true))?;
+        drop(writer);
+
+        let authors = load_all(db_dir.path())?;
+        assert_eq!(2, authors.len());
+        assert!(authors.values().all(|author| author.verified));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,215 @@
+use std::path::Path;
+
+use tantivy::{IndexWriter, Result, TantivyError, Term};
+
+use crate::{
+    authors,
+    clock::Clock,
+    database::{DatabaseReader, DatabaseWriter},
+    index::RecipeIndex,
+    model::{Recipe, RecipeId, RecipeSummary},
+    summaries,
+};
+
+/// Computes the value of a (usually newly introduced) field for an
+/// existing recipe
+pub trait Backfill: Send + Sync {
+    /// Updates the recipe in place. Must return `false` when nothing
+    /// changed so that untouched recipes are not rewritten.
+    fn apply(&self, recipe: &mut Recipe) -> bool;
+}
+
+impl<F> Backfill for F
+where
+    F: Send + Sync + Fn(&mut Recipe) -> bool,
+{
+    fn apply(&self, recipe: &mut Recipe) -> bool {
+        (self)(recipe)
+    }
+}
+
+/// Finds a known backfill by the name of the field it computes
+pub fn find_backfill(field: &str, clock: &dyn Clock) -> Option<Box<dyn Backfill>> {
+    match field {
+        // Recipes added before `added_at` existed are considered
+        // as just added
+        "added_at" => {
+            let now = clock.now();
+            let backfill = move |recipe: &mut Recipe| {
+                if recipe.features.added_at.is_none() {
+                    recipe.features.added_at = Some(now);
+                    true
+                } else {
+                    false
+                }
+            };
+            Some(Box::new(backfill))
+        }
+        _ => None,
+    }
+}
+
+/// Applies `backfill` to every recipe in the database at `db_path`,
+/// appending the changed recipes to it and replacing their documents
+/// in the index.
+///
+/// Commits every `commit_every` changes and once more when done.
+/// Returns how many recipes changed.
+///
+/// # Errors
+///
+/// Fails if the index can't address documents by id (the id field
+/// must be `INDEXED`, which old indices lack), or on any io error.
+pub fn run<B: Backfill + ?Sized>(
+    db_path: &Path,
+    writer: &mut IndexWriter,
+    recipe_index: &RecipeIndex,
+    backfill: &B,
+    commit_every: usize,
+) -> Result<usize> {
+    let mut ids = DatabaseReader::<Recipe>::open(db_path)?
+        .ids()
+        .copied()
+        .collect::<Vec<_>>();
+    ids.sort();
+
+    apply(db_path, writer, recipe_index, &ids, backfill, commit_every)
+}
+
+/// Like `run`, but only for the recipes in `ids`. Unknown ids
+/// are skipped
+pub fn apply<B: Backfill + ?Sized>(
+    db_path: &Path,
+    writer: &mut IndexWriter,
+    recipe_index: &RecipeIndex,
+    ids: &[RecipeId],
+    backfill: &B,
+    commit_every: usize,
+) -> Result<usize> {
+    if !writer
+        .index()
+        .schema()
+        .get_field_entry(recipe_index.id)
+        .is_indexed()
+    {
+        return Err(TantivyError::SchemaError(
+            "The id field is not indexed. A full reindex is required".to_owned(),
+        ));
+    }
+
+    let reader = DatabaseReader::<Recipe>::open(db_path)?;
+    let mut db = DatabaseWriter::open(db_path)?;
+    let mut summaries = summaries::open_writer(db_path)?;
+    let authors = authors::load_all(db_path)?;
+
+    let mut num_changed = 0;
+    for &id in ids {
+        let mut recipe = match reader.find_by_id(id) {
+            Some(found) => found?,
+            None => continue,
+        };
+
+        if !backfill.apply(&mut recipe) {
+            continue;
+        }
+
+        db.append(&recipe)?;
+        summaries.append(&RecipeSummary::from(&recipe))?;
+
+        writer.delete_term(Term::from_field_u64(recipe_index.id, id));
+        writer.add_document(
+            recipe_index.make_document_with_author(
+                &recipe,
+                recipe.author_id.and_then(|id| authors.get(&id)),
+            ),
+        );
+
+        num_changed += 1;
+        if num_changed % commit_every == 0 {
+            writer.commit()?;
+            log::info!("Backfill: {} recipes changed so far", num_changed);
+        }
+    }
+
+    writer.commit()?;
+
+    Ok(num_changed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use tantivy::{collector::Count, query::RangeQuery, schema::SchemaBuilder, Index};
+
+    use crate::{clock::FixedClock, model::Features};
+
+    fn recipe(recipe_id: u64) -> Recipe {
+        Recipe {
+            features: Features {
+                added_at: if recipe_id % 2 == 0 { None } else { Some(1) },
+                ..Features::default()
+            },
+            ..Recipe::sample(recipe_id, &format!("Recipe {}", recipe_id))
+        }
+    }
+
+    #[test]
+    fn backfill_patches_database_and_index() -> Result<()> {
+        let db_dir = tempfile::tempdir()?;
+
+        let mut builder = SchemaBuilder::new();
+        let recipe_index = RecipeIndex::from(&mut builder);
+        let index = Index::create_in_ram(builder.build());
+        let mut writer = index.writer_with_num_threads(1, 3_000_000)?;
+
+        let mut db = DatabaseWriter::new(db_dir.path())?;
+        for id in 0..10 {
+            let recipe = recipe(id);
+            db.append(&recipe)?;
+            writer.add_document(recipe_index.make_document(&recipe));
+        }
+        drop(db);
+        writer.commit()?;
+
+        let backfill = find_backfill("added_at", &FixedClock(42)).unwrap();
+        let changed = run(
+            db_dir.path(),
+            &mut writer,
+            &recipe_index,
+            backfill.as_ref(),
+            2,
+        )?;
+        assert_eq!(5, changed);
+
+        let db = DatabaseReader::<Recipe>::open(db_dir.path())?;
+        for id in 0..10 {
+            let found = db.find_by_id(id).unwrap()?;
+            let wanted = if id % 2 == 0 { 42 } else { 1 };
+            assert_eq!(Some(wanted), found.features.added_at);
+        }
+
+        let searcher = index.reader()?.searcher();
+        assert_eq!(10, searcher.num_docs());
+
+        let backfilled = RangeQuery::new_u64(recipe_index.features.added_at, 42..43);
+        assert_eq!(5, searcher.search(&backfilled, &Count)?);
+
+        // Nothing left to do
+        let changed = run(
+            db_dir.path(),
+            &mut writer,
+            &recipe_index,
+            backfill.as_ref(),
+            2,
+        )?;
+        assert_eq!(0, changed);
+
+        Ok(())
+    }
+
+    #[test]
+    fn unknown_backfill() {
+        assert!(find_backfill("nope", &FixedClock(0)).is_none());
+    }
+}
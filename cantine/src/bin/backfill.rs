@@ -0,0 +1,91 @@
+use std::{convert::TryFrom, env, path::Path, str::FromStr, time::Instant};
+
+use env_logger;
+
+use tantivy::{Index, Result};
+
+use cantine::{
+    analysis::Analysis, backfill, clock::SystemClock, collation::Collation, index::RecipeIndex,
+};
+
+/// Computes a field for every recipe in an existing database and
+/// patches the index with the changed documents
+#[derive(Debug)]
+pub struct BackfillOptions {
+    /// Size for tantivy's writer buffer in MBs
+    buffer_size: usize,
+    /// How many changed recipes to write before comitting
+    commit_every: usize,
+    /// Name of the field to backfill
+    field: String,
+    /// Path to the directory created by `load`
+    base_dir: String,
+}
+
+fn run(options: BackfillOptions) -> Result<()> {
+    log::info!("Started with {:?}", &options);
+
+    let base_path = Path::new(options.base_dir.as_str());
+    let db_path = base_path.join("database");
+    let index_path = base_path.join("tantivy");
+
+    let backfill = backfill::find_backfill(options.field.as_str(), &SystemClock)
+        .unwrap_or_else(|| panic!("Don't know how to backfill {}", options.field));
+
+    let index = Index::open_in_dir(&index_path)?;
+    Analysis::load(base_path)?.register(&index);
+    // Name keys must keep being generated like the indexed ones were
+    let recipe_index =
+        RecipeIndex::try_from(&index.schema())?.with_collation(Collation::load(base_path)?);
+    let mut writer = index.writer(options.buffer_size * 1_000_000)?;
+
+    let cur = Instant::now();
+    let num_changed = backfill::run(
+        &db_path,
+        &mut writer,
+        &recipe_index,
+        backfill.as_ref(),
+        options.commit_every,
+    )?;
+
+    log::info!(
+        "Backfilled {} recipes in {} seconds",
+        num_changed,
+        cur.elapsed().as_secs()
+    );
+
+    Ok(())
+}
+
+const BUFFER_SIZE: &str = "BUFFER_SIZE";
+const COMMIT_EVERY: &str = "COMMIT_EVERY";
+
+fn get_usize_from_env_or(key: &str, default: usize) -> usize {
+    env::var(key)
+        .ok()
+        .map(|v| usize::from_str(&v).expect("valid usize"))
+        .unwrap_or(default)
+}
+
+fn main() -> Result<()> {
+    env_logger::init();
+
+    let mut args = env::args().skip(1);
+    let base_dir = args
+        .next()
+        .expect("First parameter must be the base directory");
+
+    let field = match (args.next().as_deref(), args.next()) {
+        (Some("--field"), Some(field)) => field,
+        _ => panic!("Usage: backfill BASE_DIR --field FIELD_NAME"),
+    };
+
+    let options = BackfillOptions {
+        base_dir,
+        field,
+        buffer_size: get_usize_from_env_or(BUFFER_SIZE, 1000),
+        commit_every: get_usize_from_env_or(COMMIT_EVERY, 300_000),
+    };
+
+    run(options)
+}
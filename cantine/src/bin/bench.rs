@@ -0,0 +1,180 @@
+use std::{
+    env,
+    io::{self, BufRead},
+    path::Path,
+    time::{Duration, Instant},
+};
+
+use env_logger;
+use serde_json;
+
+use tantivy::{directory::MmapDirectory, schema::SchemaBuilder, Index, Result};
+
+use cantine::{
+    database::{DatabaseReader, DatabaseWriter},
+    index::RecipeIndex,
+    model::{Recipe, Sort},
+    synthetic::Synthetic,
+};
+use tique::QueryParser;
+
+/// Generates a synthetic corpus out of the recipes read from stdin
+/// and times the hot paths over it: indexing, top-k collection with
+/// and without a pagination cursor, database appends and lookups and
+/// opening everything again, as a quick check for regressions
+#[derive(Debug)]
+pub struct BenchOptions {
+    /// How many recipes to generate
+    size: usize,
+    /// Picks the generated recipes
+    seed: u64,
+    /// How many times each search is timed
+    rounds: usize,
+    /// Path to a non-existing directory for the corpus
+    output_dir: String,
+}
+
+/// The median and the worst of some timings, in microseconds
+fn summarize(mut timings: Vec<Duration>) -> (u128, u128) {
+    timings.sort_unstable();
+    let median = timings[timings.len() / 2].as_micros();
+    let worst = timings.last().expect("timings not empty").as_micros();
+    (median, worst)
+}
+
+fn report(name: &str, timings: Vec<Duration>) {
+    let (median, worst) = summarize(timings);
+    println!("{}\tmedian={}us\tmax={}us", name, median, worst);
+}
+
+fn run(options: BenchOptions, templates: Vec<Recipe>) -> Result<()> {
+    log::info!("Started with {:?}", &options);
+
+    let base_path = Path::new(options.output_dir.as_str());
+    let db_path = base_path.join("database");
+    let index_path = base_path.join("tantivy");
+    std::fs::create_dir_all(&db_path)?;
+    std::fs::create_dir(&index_path)?;
+
+    let mut builder = SchemaBuilder::new();
+    let recipe_index = RecipeIndex::from(&mut builder);
+    let index = Index::open_or_create(MmapDirectory::open(&index_path)?, builder.build())?;
+
+    let recipes = Synthetic::new(&templates, options.seed)
+        .take(options.size)
+        .collect::<Vec<_>>();
+
+    let cur = Instant::now();
+    let mut writer = index.writer(200_000_000)?;
+    for recipe in &recipes {
+        writer.add_document(recipe_index.make_document(recipe));
+    }
+    writer.commit()?;
+    let elapsed = cur.elapsed();
+    println!(
+        "index\t{} recipes in {}ms\t{:.0} recipes/s",
+        recipes.len(),
+        elapsed.as_millis(),
+        recipes.len() as f64 / elapsed.as_secs_f64()
+    );
+
+    let mut db_writer = DatabaseWriter::new(&db_path)?;
+    let mut appends = Vec::with_capacity(recipes.len());
+    for recipe in &recipes {
+        let cur = Instant::now();
+        db_writer.append(recipe)?;
+        appends.push(cur.elapsed());
+    }
+    let cur = Instant::now();
+    db_writer.flush()?;
+    let flush = cur.elapsed();
+    drop(db_writer);
+    report("database_add", appends);
+    println!("database_flush\t{}us", flush.as_micros());
+
+    // What a restart goes through
+    let cur = Instant::now();
+    let database = DatabaseReader::<Recipe>::open(&db_path)?;
+    println!("database_open\t{}us", cur.elapsed().as_micros());
+
+    let cur = Instant::now();
+    let index = Index::open_in_dir(&index_path)?;
+    let reader = index.reader()?;
+    println!("index_open\t{}us", cur.elapsed().as_micros());
+
+    let mut gets = Vec::with_capacity(options.rounds);
+    for round in 0..options.rounds {
+        let recipe = &recipes[round * 7919 % recipes.len()];
+        let cur = Instant::now();
+        database
+            .find_by_id(recipe.recipe_id)
+            .expect("generated recipe in the database")?;
+        gets.push(cur.elapsed());
+    }
+    report("database_get", gets);
+
+    let parser = QueryParser::new(&index, vec![recipe_index.name, recipe_index.ingredients])?;
+    let query = parser
+        .parse("chicken garlic")
+        .expect("something to search for");
+    let searcher = reader.searcher();
+
+    for sort in &[Sort::Relevance, Sort::NumIngredients, Sort::Calories] {
+        let mut first_page = Vec::with_capacity(options.rounds);
+        let mut with_condition = Vec::with_capacity(options.rounds);
+
+        for _ in 0..options.rounds {
+            let cur = Instant::now();
+            let (_total, _ids, after) =
+                recipe_index.search(&searcher, query.as_ref(), 20, sort.clone(), None)?;
+            first_page.push(cur.elapsed());
+
+            // Checks every candidate against the cursor
+            let cur = Instant::now();
+            recipe_index.search(&searcher, query.as_ref(), 20, sort.clone(), after)?;
+            with_condition.push(cur.elapsed());
+        }
+
+        report(&format!("top_k/{:?}/first_page", sort), first_page);
+        report(&format!("top_k/{:?}/with_condition", sort), with_condition);
+    }
+
+    Ok(())
+}
+
+const USAGE: &str = "Usage: bench OUTPUT_DIR [--size N] [--seed S] [--rounds N] < recipes.jsonl";
+
+fn main() -> Result<()> {
+    env_logger::init();
+
+    let mut args = env::args().skip(1);
+    let output_dir = args.next().expect(USAGE);
+
+    let mut size = 100_000;
+    let mut seed = 42;
+    let mut rounds = 100;
+    while let Some(option) = args.next() {
+        let arg = args.next().expect(USAGE);
+        match option.as_str() {
+            "--size" => size = arg.parse().expect("size must be a positive integer"),
+            "--seed" => seed = arg.parse().expect("seed must be an integer"),
+            "--rounds" => rounds = arg.parse().expect("rounds must be a positive integer"),
+            _ => panic!("{}", USAGE),
+        }
+    }
+    assert!(size > 0 && rounds > 0, "{}", USAGE);
+
+    let mut templates = Vec::new();
+    for line in io::stdin().lock().lines() {
+        templates.push(serde_json::from_str(&line?).expect("valid recipe json"));
+    }
+
+    let options = BenchOptions {
+        size,
+        seed,
+        rounds,
+        output_dir,
+    };
+
+    run(options, templates)
+}
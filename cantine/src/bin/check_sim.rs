@@ -10,6 +10,7 @@ use crossbeam_channel;
 use tantivy::{schema::Term, Index, Result};
 
 use cantine::{
+    analysis::Analysis,
     database::DatabaseReader,
     index::RecipeIndex,
     model::{Recipe, RecipeId, Sort},
@@ -41,9 +42,11 @@ fn main() -> Result<()> {
     let db_path = base_path.join("database");
 
     let index = Index::open_in_dir(&index_path)?;
+    Analysis::load(base_path)?.register(&index);
     let reader = index.reader()?;
 
     let recipe_index = Arc::new(RecipeIndex::try_from(&index.schema())?);
+    recipe_index.install_tokenizers(&index)?;
     let database = Arc::new(DatabaseReader::<Recipe>::open(&db_path)?);
     let topterms = Arc::new(TopTerms::new(
         &index,
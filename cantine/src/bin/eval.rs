@@ -0,0 +1,151 @@
+use std::{convert::TryFrom, env, path::Path, str::FromStr};
+
+use env_logger;
+use uuid::Uuid;
+
+use tantivy::{Index, Result};
+
+use cantine::{
+    analysis::Analysis,
+    database::DatabaseReader,
+    index::RecipeIndex,
+    model::{Recipe, Sort},
+};
+use tique::{
+    eval::{Evaluation, Grades, Judgments},
+    QueryParser,
+};
+
+/// Scores the relevance ranking of an existing index against a
+/// judgments file (see `tique::eval`) whose queries are `fulltext`
+/// inputs and whose ids are recipe uuids
+#[derive(Debug)]
+pub struct EvalOptions {
+    /// How many of the top recipes of each query are scored
+    k: usize,
+    /// Also print the scores of each query
+    verbose: bool,
+    /// Path to the judgments file
+    judgments: String,
+    /// Path to the directory created by `load`
+    base_dir: String,
+}
+
+fn run(options: EvalOptions) -> Result<()> {
+    log::info!("Started with {:?}", &options);
+
+    let base_path = Path::new(options.base_dir.as_str());
+    let db_path = base_path.join("database");
+    let index_path = base_path.join("tantivy");
+
+    let index = Index::open_in_dir(&index_path)?;
+    Analysis::load(base_path)?.register(&index);
+    let recipe_index = RecipeIndex::try_from(&index.schema())?;
+    recipe_index.install_tokenizers(&index)?;
+
+    // Same as the API ranks
+    let mut parser = QueryParser::new(
+        &index,
+        vec![
+            recipe_index.name,
+            recipe_index.ingredients,
+            recipe_index.instructions,
+        ],
+    )?;
+    parser.set_boost(recipe_index.instructions, Some(0.7));
+    parser.set_boost(recipe_index.name, Some(1.15));
+
+    let database = DatabaseReader::<Recipe>::open(&db_path)?;
+    let judgments = Judgments::load(&options.judgments)?;
+    let searcher = index.reader()?.searcher();
+
+    let mut evaluation = Evaluation::new(options.k);
+    for (fulltext, judged) in judgments.iter() {
+        // Results are recipe ids, so judgments must be too
+        let mut grades = Grades::with_capacity(judged.len());
+        for (uuid, grade) in judged {
+            match Uuid::from_str(uuid)
+                .ok()
+                .and_then(|uuid| database.id_for_uuid(&uuid))
+            {
+                Some(id) => {
+                    grades.insert(id.to_string(), *grade);
+                }
+                None => log::warn!("Judged recipe {} of {:?} not found", uuid, fulltext),
+            }
+        }
+
+        let ranked = match parser.parse(fulltext) {
+            Some(query) => {
+                let (_total, recipe_ids, _after) = recipe_index.search(
+                    &searcher,
+                    query.as_ref(),
+                    options.k,
+                    Sort::Relevance,
+                    None,
+                )?;
+                recipe_ids.iter().map(ToString::to_string).collect()
+            }
+            None => {
+                log::warn!("Nothing to search for in {:?}", fulltext);
+                Vec::new()
+            }
+        };
+
+        let scores = evaluation.add(&ranked, &grades);
+        if options.verbose {
+            println!(
+                "{:.4}\t{:.4}\t{:.4}\t{}",
+                scores.ndcg, scores.reciprocal_rank, scores.recall, fulltext
+            );
+        }
+    }
+
+    let report = evaluation.report();
+    println!(
+        "queries={} ndcg@{k}={:.4} mrr={:.4} recall@{k}={:.4}",
+        report.num_queries,
+        report.ndcg,
+        report.mrr,
+        report.recall,
+        k = report.k
+    );
+
+    Ok(())
+}
+
+const USAGE: &str = "Usage: eval BASE_DIR JUDGMENTS [--k N] [--verbose]";
+
+fn main() -> Result<()> {
+    env_logger::init();
+
+    let mut args = env::args().skip(1);
+    let base_dir = args.next().expect(USAGE);
+    let judgments = args.next().expect(USAGE);
+
+    let mut k = 10;
+    let mut verbose = false;
+    while let Some(option) = args.next() {
+        match option.as_str() {
+            "--k" => {
+                k = args
+                    .next()
+                    .expect(USAGE)
+                    .parse()
+                    .expect("k must be a positive integer");
+            }
+            "--verbose" => verbose = true,
+            _ => panic!("{}", USAGE),
+        }
+    }
+    assert!(k > 0, "k must be a positive integer");
+
+    let options = EvalOptions {
+        k,
+        verbose,
+        judgments,
+        base_dir,
+    };
+
+    run(options)
+}
@@ -0,0 +1,140 @@
+use std::{
+    convert::TryFrom,
+    env,
+    io::{self, BufWriter},
+    path::Path,
+    time::Instant,
+};
+
+use env_logger;
+use serde::Deserialize;
+
+use tantivy::{
+    query::{AllQuery, BooleanQuery, Occur, Query},
+    Index, Result,
+};
+
+use cantine::{
+    analysis::Analysis,
+    database::DatabaseReader,
+    export::{self, Format},
+    index::RecipeIndex,
+    model::{FeaturesFilterQuery, Recipe},
+};
+use tique::QueryParser;
+
+/// Which recipes to export: like a search, but only its full-text
+/// and feature filter parts
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct ExportQuery {
+    fulltext: Option<String>,
+    filter: Option<FeaturesFilterQuery>,
+}
+
+/// Writes recipes from an existing database to stdout
+#[derive(Debug)]
+pub struct ExportOptions {
+    /// How to write the recipes
+    format: Format,
+    /// The recipes to export. All of them without one
+    query: Option<ExportQuery>,
+    /// Path to the directory created by `load`
+    base_dir: String,
+}
+
+fn run(options: ExportOptions) -> Result<()> {
+    log::info!("Started with {:?}", &options);
+
+    let base_path = Path::new(options.base_dir.as_str());
+    let db_path = base_path.join("database");
+    let index_path = base_path.join("tantivy");
+
+    let index = Index::open_in_dir(&index_path)?;
+    Analysis::load(base_path)?.register(&index);
+    let recipe_index = RecipeIndex::try_from(&index.schema())?;
+    recipe_index.install_tokenizers(&index)?;
+
+    let mut subqueries: Vec<(Occur, Box<dyn Query>)> = Vec::new();
+    if let Some(query) = &options.query {
+        if let Some(fulltext) = &query.fulltext {
+            let parser = QueryParser::new(
+                &index,
+                vec![
+                    recipe_index.name,
+                    recipe_index.ingredients,
+                    recipe_index.instructions,
+                ],
+            )?;
+            let parsed = parser
+                .parse(fulltext)
+                .unwrap_or_else(|| panic!("Nothing to search for in {:?}", fulltext));
+            subqueries.push((Occur::Must, parsed));
+        }
+        if let Some(filter) = &query.filter {
+            for query in recipe_index.features.interpret(filter) {
+                subqueries.push((Occur::Must, query));
+            }
+        }
+    }
+    let query: Box<dyn Query> = if subqueries.is_empty() {
+        Box::new(AllQuery)
+    } else {
+        Box::new(BooleanQuery::from(subqueries))
+    };
+
+    let database = DatabaseReader::<Recipe>::open(&db_path)?;
+    database.advise_sequential()?;
+
+    let searcher = index.reader()?.searcher();
+    let stdout = io::stdout();
+    let mut output = BufWriter::new(stdout.lock());
+
+    let cur = Instant::now();
+    let num_exported = export::run(
+        &searcher,
+        &recipe_index,
+        query.as_ref(),
+        &database,
+        options.format,
+        &mut output,
+    )?;
+
+    log::info!(
+        "Exported {} recipes in {} seconds",
+        num_exported,
+        cur.elapsed().as_secs()
+    );
+
+    Ok(())
+}
+
+const USAGE: &str = "Usage: export BASE_DIR [--format jsonl|csv] [--query QUERY_JSON]";
+
+fn main() -> Result<()> {
+    env_logger::init();
+
+    let mut args = env::args().skip(1);
+    let base_dir = args.next().expect(USAGE);
+
+    let mut format = Format::Jsonl;
+    let mut query = None;
+    while let Some(option) = args.next() {
+        let arg = args.next().expect(USAGE);
+        match option.as_str() {
+            "--format" => format = arg.parse().unwrap_or_else(|err| panic!("{}", err)),
+            "--query" => {
+                query = Some(serde_json::from_str(&arg).expect("valid json query"));
+            }
+            _ => panic!("{}", USAGE),
+        }
+    }
+
+    let options = ExportOptions {
+        base_dir,
+        format,
+        query,
+    };
+
+    run(options)
+}
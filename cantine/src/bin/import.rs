@@ -0,0 +1,111 @@
+use std::{
+    env,
+    fs::OpenOptions,
+    io::{self, BufRead, BufWriter, Result, Write},
+    str::FromStr,
+};
+
+use env_logger;
+
+use cantine::{
+    ingest::adapters::{Adapters, Found, Page},
+    model::RecipeId,
+};
+
+/// Turns fetched pages into recipes that `load` takes
+#[derive(Debug)]
+pub struct ImportOptions {
+    /// The id of the first recipe found. Must be past every id
+    /// already in use
+    first_id: RecipeId,
+    /// Where to append the links found (say, the entries of a feed)
+    /// for the crawler to fetch
+    links: Option<String>,
+}
+
+fn run(options: ImportOptions) -> Result<()> {
+    log::info!("Started with {:?}", &options);
+
+    let adapters = Adapters::default();
+
+    let mut links = match &options.links {
+        Some(path) => Some(BufWriter::new(
+            OpenOptions::new().create(true).append(true).open(path)?,
+        )),
+        None => None,
+    };
+
+    let stdout = io::stdout();
+    let mut output = BufWriter::new(stdout.lock());
+
+    let mut next_id = options.first_id;
+    let mut num_pages = 0;
+    let mut num_links = 0;
+
+    for line in io::stdin().lock().lines() {
+        let page: Page = serde_json::from_str(&line?).expect("valid page json");
+        num_pages += 1;
+
+        let found = match adapters.parse(&page) {
+            Some((adapter, found)) => {
+                log::debug!("{}: {} items via {}", page.url, found.len(), adapter);
+                found
+            }
+            None => {
+                log::warn!("No adapter for {}", page.url);
+                continue;
+            }
+        };
+
+        for item in found {
+            match item {
+                Found::Recipe(scraped) => {
+                    let recipe = scraped.into_recipe(next_id);
+                    next_id += 1;
+                    serde_json::to_writer(&mut output, &recipe)?;
+                    writeln!(output)?;
+                }
+                Found::Link(link) => {
+                    num_links += 1;
+                    if let Some(links) = links.as_mut() {
+                        writeln!(links, "{}", link)?;
+                    }
+                }
+            }
+        }
+    }
+
+    output.flush()?;
+    if let Some(mut links) = links {
+        links.flush()?;
+    } else if num_links > 0 {
+        log::warn!("Dropped {} links: {} is not set", num_links, LINKS);
+    }
+
+    log::info!(
+        "Imported {} recipes from {} pages. Next id: {}",
+        next_id - options.first_id,
+        num_pages,
+        next_id
+    );
+
+    Ok(())
+}
+
+const LINKS: &str = "LINKS";
+
+fn main() -> Result<()> {
+    env_logger::init();
+
+    let first_id = env::args()
+        .nth(1)
+        .map(|arg| RecipeId::from_str(&arg).expect("valid recipe id"))
+        .expect("Usage: import FIRST_ID < pages.jsonlines");
+
+    let options = ImportOptions {
+        first_id,
+        links: env::var(LINKS).ok(),
+    };
+
+    run(options)
+}
@@ -1,5 +1,6 @@
 use std::{
-    env,
+    collections::HashMap,
+    env, fs,
     io::{self, BufRead},
     path::Path,
     str::FromStr,
@@ -14,9 +15,18 @@ use serde_json;
 
 use tantivy::{self, directory::MmapDirectory, schema::SchemaBuilder, Index, Result};
 
+use cantine::analysis::{Analysis, Language};
+use cantine::authors;
+use cantine::collation::Collation;
 use cantine::database::DatabaseWriter;
+use cantine::estimate;
+use cantine::freshness::commit_with_checkpoint;
 use cantine::index::RecipeIndex;
-use cantine::model::Recipe;
+use cantine::ingest::IngestRules;
+#[cfg(feature = "scripting")]
+use cantine::ingest::IngestScripts;
+use cantine::model::{Author, Recipe, RecipeSummary};
+use cantine::summaries;
 
 /// Loads recipes as json into cantine's database and index
 #[derive(Debug)]
@@ -29,9 +39,57 @@ pub struct LoadOptions {
     num_producers: usize,
     /// Path to a non-existing directory
     output_dir: String,
+    /// Locale rules used to generate the name sort keys
+    collation: Collation,
+    /// How text gets broken into terms. Persisted alongside the
+    /// index since queries must be analyzed the same way
+    analysis: Analysis,
+    /// Path to a file with one author as json per line
+    authors: Option<String>,
+    /// Per-site fixes to apply to the recipes before loading them
+    rules: Option<IngestRules>,
+    /// Per-site scripts to run on the recipes after the rules
+    #[cfg(feature = "scripting")]
+    scripts: Option<IngestScripts>,
 }
 
-fn load(options: LoadOptions) -> Result<()> {
+// What the raw json of the recipes goes through before decoding
+struct Fixes {
+    rules: Option<IngestRules>,
+    #[cfg(feature = "scripting")]
+    scripts: Option<IngestScripts>,
+}
+
+impl Fixes {
+    fn decode(&self, line: &str) -> Recipe {
+        #[cfg(feature = "scripting")]
+        let has_scripts = self.scripts.is_some();
+        #[cfg(not(feature = "scripting"))]
+        let has_scripts = false;
+
+        if self.rules.is_none() && !has_scripts {
+            return serde_json::from_str(line).expect("valid recipe json");
+        }
+
+        let mut raw = serde_json::from_str(line).expect("valid json");
+        if let Some(rules) = &self.rules {
+            rules.apply(&mut raw);
+        }
+
+        #[cfg(feature = "scripting")]
+        {
+            if let Some(scripts) = &self.scripts {
+                if let Err(err) = scripts.apply(&mut raw) {
+                    log::warn!("Ingest script failed, loading the recipe as is: {}", err);
+                }
+            }
+        }
+
+        serde_json::from_value(raw).expect("valid recipe json after the fixes")
+    }
+}
+
+fn load(mut options: LoadOptions) -> Result<()> {
     log::info!("Started with {:?}", &options);
 
     let base_path = Path::new(options.output_dir.as_str());
@@ -43,9 +101,33 @@ fn load(options: LoadOptions) -> Result<()> {
 
     let mut builder = SchemaBuilder::new();
 
-    let fields = RecipeIndex::from(&mut builder);
+    let fields =
+        RecipeIndex::create(&mut builder, &options.analysis).with_collation(options.collation);
 
     let index = Index::open_or_create(MmapDirectory::open(&index_path)?, builder.build())?;
+    options.analysis.register(&index);
+    fields.install_tokenizers(&index)?;
+    options.analysis.save(base_path)?;
+    options.collation.save(base_path)?;
+
+    // Authors go in first so that recipes are indexed with their
+    // (denormalized) author fields
+    let mut authors = HashMap::new();
+    if let Some(path) = &options.authors {
+        let mut authors_db = authors::open_writer(&db_path)?;
+        for line in io::BufReader::new(fs::File::open(path)?).lines() {
+            let author: Author = serde_json::from_str(line?.as_ref()).expect("valid author json");
+            authors_db.append(&author)?;
+            authors.insert(author.author_id, author);
+        }
+        log::info!("Loaded {} authors", authors.len());
+    }
+    let authors = Arc::new(authors);
+    let fixes = Arc::new(Fixes {
+        rules: options.rules.take(),
+        #[cfg(feature = "scripting")]
+        scripts: options.scripts.take(),
+    });
 
     // A SpMc channel to paralellize decode and index preparation
     let (line_sender, line_receiver) = unbounded::<String>();
@@ -63,15 +145,20 @@ fn load(options: LoadOptions) -> Result<()> {
         let recipe_sender = recipe_sender.clone();
 
         let fields = fields.clone();
+        let authors = authors.clone();
+        let fixes = fixes.clone();
         workers.push(spawn(move || {
             for line in receiver {
-                let recipe: Recipe =
-                    serde_json::from_str(line.as_ref()).expect("valid recipe json");
+                let mut recipe = fixes.decode(&line);
+                estimate::fill_in(&mut recipe);
 
                 writer
                     .read()
                     .unwrap()
-                    .add_document(fields.make_document(&recipe));
+                    .add_document(fields.make_document_with_author(
+                        &recipe,
+                        recipe.author_id.and_then(|id| authors.get(&id)),
+                    ));
 
                 recipe_sender.send(recipe).expect("send always works");
             }
@@ -79,6 +166,7 @@ fn load(options: LoadOptions) -> Result<()> {
     }
 
     let disk_writer = spawn(move || -> Result<()> {
+        let mut summaries = summaries::open_writer(&db_path)?;
         let mut db = DatabaseWriter::new(db_path)?;
 
         let cur = Instant::now();
@@ -87,24 +175,31 @@ fn load(options: LoadOptions) -> Result<()> {
         for recipe in recipe_receiver {
             num_recipes += 1;
             db.append(&recipe)?;
+            summaries.append(&RecipeSummary::from(&recipe))?;
 
             if num_recipes % options.commit_every == 0 {
-                writer.write()?.commit()?;
+                db.flush()?;
+                summaries.flush()?;
+                let generation = commit_with_checkpoint(&mut writer.write()?, &db.checkpoint()?)?;
 
                 log::info!(
-                    "DiskWriter: {} Documents so far (@ {} secs).",
+                    "DiskWriter: {} Documents so far (@ {} secs). Generation {}",
                     num_recipes,
-                    cur.elapsed().as_secs()
+                    cur.elapsed().as_secs(),
+                    generation
                 );
             }
         }
 
-        writer.write()?.commit()?;
+        db.flush()?;
+        summaries.flush()?;
+        let generation = commit_with_checkpoint(&mut writer.write()?, &db.checkpoint()?)?;
 
         log::info!(
-            "DiskWriter: Wrote {} documents in {} seconds",
+            "DiskWriter: Wrote {} documents in {} seconds. Generation {}",
             num_recipes,
-            cur.elapsed().as_secs()
+            cur.elapsed().as_secs(),
+            generation
         );
 
         Ok(())
@@ -132,6 +227,14 @@ fn load(options: LoadOptions) -> Result<()> {
 const BUFFER_SIZE: &str = "BUFFER_SIZE";
 const COMMIT_EVERY: &str = "COMMIT_EVERY";
 const NUM_PRODUCERS: &str = "NUM_PRODUCERS";
+const COLLATION: &str = "COLLATION";
+const STEMMER: &str = "STEMMER";
+const STOPWORDS: &str = "STOPWORDS";
+const ASCII_FOLDING: &str = "ASCII_FOLDING";
+const AUTHORS: &str = "AUTHORS";
+const INGEST_RULES: &str = "INGEST_RULES";
+#[cfg(feature = "scripting")]
+const INGEST_SCRIPTS: &str = "INGEST_SCRIPTS";
 
 fn get_usize_from_env_or(key: &str, default: usize) -> usize {
     env::var(key)
@@ -153,11 +256,52 @@ fn main() -> Result<()> {
 
     let num_producers = get_usize_from_env_or(NUM_PRODUCERS, 4);
 
+    let collation = env::var(COLLATION)
+        .ok()
+        .map(|v| Collation::from_str(&v).expect("valid collation locale"))
+        .unwrap_or_default();
+
+    let stemmer = env::var(STEMMER)
+        .ok()
+        .map(|v| Language::from_str(&v).expect("valid stemmer language"));
+
+    // A file with one stopword per line
+    let stopwords = env::var(STOPWORDS)
+        .ok()
+        .map(|path| {
+            fs::read_to_string(path)
+                .expect("readable stopwords file")
+                .lines()
+                .map(str::trim)
+                .filter(|word| !word.is_empty())
+                .map(String::from)
+                .collect()
+        })
+        .unwrap_or_default();
+
+    let analysis = Analysis {
+        stemmer,
+        stopwords,
+        ascii_folding: env::var(ASCII_FOLDING).map_or(false, |v| v == "1" || v == "true"),
+        ..Analysis::default()
+    };
+
     let options = LoadOptions {
         output_dir,
         buffer_size,
         commit_every,
         num_producers,
+        collation,
+        analysis,
+        authors: env::var(AUTHORS).ok(),
+        rules: env::var(INGEST_RULES)
+            .ok()
+            .map(|path| IngestRules::load(path).expect("valid ingest rules file")),
+        // A directory with one script per site
+        #[cfg(feature = "scripting")]
+        scripts: env::var(INGEST_SCRIPTS)
+            .ok()
+            .map(|dir| IngestScripts::load(dir).expect("valid ingest scripts")),
     };
 
     load(options)
@@ -0,0 +1,71 @@
+use std::{convert::TryFrom, env, path::Path, str::FromStr, time::Instant};
+
+use env_logger;
+
+use tantivy::{Index, Result};
+
+use cantine::{analysis::Analysis, collation::Collation, index::RecipeIndex, popularity};
+
+/// Bakes the counts of the popularity keyspace into the index. Meant
+/// to be run periodically, say: daily
+#[derive(Debug)]
+pub struct PopularityOptions {
+    /// Size for tantivy's writer buffer in MBs
+    buffer_size: usize,
+    /// How many recipes to reindex before comitting
+    commit_every: usize,
+    /// Path to the directory created by `load`
+    base_dir: String,
+}
+
+fn run(options: PopularityOptions) -> Result<()> {
+    log::info!("Started with {:?}", &options);
+
+    let base_path = Path::new(options.base_dir.as_str());
+    let db_path = base_path.join("database");
+    let index_path = base_path.join("tantivy");
+
+    let index = Index::open_in_dir(&index_path)?;
+    Analysis::load(base_path)?.register(&index);
+    // Name keys must keep being generated like the indexed ones were
+    let recipe_index =
+        RecipeIndex::try_from(&index.schema())?.with_collation(Collation::load(base_path)?);
+    let mut writer = index.writer(options.buffer_size * 1_000_000)?;
+
+    let cur = Instant::now();
+    let num_baked = popularity::bake(&db_path, &mut writer, &recipe_index, options.commit_every)?;
+
+    log::info!(
+        "Baked the popularity of {} recipes in {} seconds",
+        num_baked,
+        cur.elapsed().as_secs()
+    );
+
+    Ok(())
+}
+
+const BUFFER_SIZE: &str = "BUFFER_SIZE";
+const COMMIT_EVERY: &str = "COMMIT_EVERY";
+
+fn get_usize_from_env_or(key: &str, default: usize) -> usize {
+    env::var(key)
+        .ok()
+        .map(|v| usize::from_str(&v).expect("valid usize"))
+        .unwrap_or(default)
+}
+
+fn main() -> Result<()> {
+    env_logger::init();
+
+    let base_dir = env::args()
+        .nth(1)
+        .expect("First parameter must be the base directory");
+
+    let options = PopularityOptions {
+        base_dir,
+        buffer_size: get_usize_from_env_or(BUFFER_SIZE, 1000),
+        commit_every: get_usize_from_env_or(COMMIT_EVERY, 300_000),
+    };
+
+    run(options)
+}
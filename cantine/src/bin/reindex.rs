@@ -0,0 +1,170 @@
+use std::{env, fs, path::Path, str::FromStr, time::Instant};
+
+use env_logger;
+
+use tantivy::{directory::MmapDirectory, schema::SchemaBuilder, Index, Result};
+
+use cantine::{
+    analysis::{Analysis, Language},
+    authors,
+    collation::Collation,
+    database::DatabaseReader,
+    index::RecipeIndex,
+    model::Recipe,
+    popularity,
+};
+
+/// Recreates the index of a directory created by `load` from its
+/// database, picking up any schema or analysis changes
+#[derive(Debug)]
+pub struct ReindexOptions {
+    /// Size for tantivy's writer buffer in MBs
+    buffer_size: usize,
+    /// How many recipes to index before comitting
+    commit_every: usize,
+    /// Number of indexing threads
+    num_threads: usize,
+    /// Locale rules used to generate the name sort keys. None keeps
+    /// what the existing index uses
+    collation: Option<Collation>,
+    /// How text gets broken into terms. None keeps what the
+    /// existing index uses
+    analysis: Option<Analysis>,
+    /// Path to the directory created by `load`
+    base_dir: String,
+}
+
+fn run(options: ReindexOptions) -> Result<()> {
+    log::info!("Started with {:?}", &options);
+
+    let base_path = Path::new(options.base_dir.as_str());
+    let db_path = base_path.join("database");
+    let index_path = base_path.join("tantivy");
+
+    // The new index is built on the side and only replaces the
+    // current one when complete
+    let new_index_path = base_path.join("tantivy.new");
+    let old_index_path = base_path.join("tantivy.old");
+    fs::create_dir(&new_index_path)?;
+
+    let analysis = match options.analysis {
+        Some(analysis) => analysis,
+        None => Analysis::load(base_path)?,
+    };
+
+    let collation = match options.collation {
+        Some(collation) => collation,
+        None => Collation::load(base_path)?,
+    };
+
+    let mut builder = SchemaBuilder::new();
+    let recipe_index = RecipeIndex::create(&mut builder, &analysis).with_collation(collation);
+
+    let index = Index::create(MmapDirectory::open(&new_index_path)?, builder.build())?;
+    analysis.register(&index);
+    recipe_index.install_tokenizers(&index)?;
+
+    let database = DatabaseReader::<Recipe>::open(&db_path)?;
+    database.advise_sequential()?;
+    let authors = authors::load_all(&db_path)?;
+    let popularity = popularity::load_all(&db_path)?;
+
+    let mut writer =
+        index.writer_with_num_threads(options.num_threads, options.buffer_size * 1_000_000)?;
+
+    let cur = Instant::now();
+    let num_recipes = recipe_index.rebuild_from(
+        &database,
+        &authors,
+        &popularity,
+        &mut writer,
+        options.commit_every,
+    )?;
+    writer.wait_merging_threads()?;
+
+    // Only the last replaced index is kept around
+    if old_index_path.exists() {
+        fs::remove_dir_all(&old_index_path)?;
+    }
+    fs::rename(&index_path, &old_index_path)?;
+    fs::rename(&new_index_path, &index_path)?;
+    analysis.save(base_path)?;
+    collation.save(base_path)?;
+
+    log::info!(
+        "Reindexed {} recipes in {} seconds. The previous index is at {:?}",
+        num_recipes,
+        cur.elapsed().as_secs(),
+        old_index_path
+    );
+
+    Ok(())
+}
+
+const BUFFER_SIZE: &str = "BUFFER_SIZE";
+const COMMIT_EVERY: &str = "COMMIT_EVERY";
+const NUM_THREADS: &str = "NUM_THREADS";
+const COLLATION: &str = "COLLATION";
+const STEMMER: &str = "STEMMER";
+const STOPWORDS: &str = "STOPWORDS";
+const ASCII_FOLDING: &str = "ASCII_FOLDING";
+
+fn get_usize_from_env_or(key: &str, default: usize) -> usize {
+    env::var(key)
+        .ok()
+        .map(|v| usize::from_str(&v).expect("valid usize"))
+        .unwrap_or(default)
+}
+
+fn main() -> Result<()> {
+    env_logger::init();
+
+    let base_dir = env::args()
+        .nth(1)
+        .expect("First parameter must be the base directory");
+
+    let collation = env::var(COLLATION)
+        .ok()
+        .map(|v| Collation::from_str(&v).expect("valid collation locale"));
+
+    // Same as `load`, but only when any of them is given
+    let analysis = if [STEMMER, STOPWORDS, ASCII_FOLDING]
+        .iter()
+        .any(|key| env::var(key).is_ok())
+    {
+        let stopwords = env::var(STOPWORDS)
+            .ok()
+            .map(|path| {
+                fs::read_to_string(path)
+                    .expect("readable stopwords file")
+                    .lines()
+                    .map(str::trim)
+                    .filter(|word| !word.is_empty())
+                    .map(String::from)
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        Some(Analysis {
+            stemmer: env::var(STEMMER)
+                .ok()
+                .map(|v| Language::from_str(&v).expect("valid stemmer language")),
+            stopwords,
+            ascii_folding: env::var(ASCII_FOLDING).map_or(false, |v| v == "1" || v == "true"),
+            ..Analysis::default()
+        })
+    } else {
+        None
+    };
+
+    let options = ReindexOptions {
+        base_dir,
+        collation,
+        analysis,
+        buffer_size: get_usize_from_env_or(BUFFER_SIZE, 1000),
+        commit_every: get_usize_from_env_or(COMMIT_EVERY, 300_000),
+        num_threads: get_usize_from_env_or(NUM_THREADS, 4),
+    };
+
+    run(options)
+}
@@ -0,0 +1,53 @@
+use std::{convert::TryFrom, env, io, path::Path};
+
+use env_logger;
+use serde::Serialize;
+
+use tantivy::{Index, Result};
+
+use cantine::{
+    database::{DatabaseReader, DatabaseStats},
+    index::RecipeIndex,
+    model::Recipe,
+    stats::IndexStats,
+};
+
+/// What gets printed
+#[derive(Serialize)]
+struct StorageStats {
+    index: IndexStats,
+    database: DatabaseStats,
+}
+
+/// Prints figures about the index and the database at `base_dir`
+/// as json, same as the API's `/stats`
+fn run(base_dir: &str) -> Result<()> {
+    let base_path = Path::new(base_dir);
+    let db_path = base_path.join("database");
+    let index_path = base_path.join("tantivy");
+
+    let index = Index::open_in_dir(&index_path)?;
+    let recipe_index = RecipeIndex::try_from(&index.schema())?;
+    let searcher = index.reader()?.searcher();
+
+    let stats = StorageStats {
+        index: recipe_index.stats(&searcher, &index_path)?,
+        database: DatabaseReader::<Recipe>::open(&db_path)?.stats()?,
+    };
+
+    serde_json::to_writer_pretty(io::stdout(), &stats)
+        .map_err(|err| io::Error::new(io::ErrorKind::Other, err))?;
+    println!();
+
+    Ok(())
+}
+
+fn main() -> Result<()> {
+    env_logger::init();
+
+    let base_dir = env::args()
+        .nth(1)
+        .expect("First parameter must be the base directory");
+
+    run(&base_dir)
+}
@@ -0,0 +1,84 @@
+use std::{env, path::Path};
+
+use env_logger;
+
+use tantivy::Result;
+
+use cantine::analytics::{self, QueryStats};
+
+/// Reports on the searches logged with `QUERY_LOG` enabled
+#[derive(Debug)]
+pub struct TopQueriesOptions {
+    /// Only report searches that matched nothing at least once
+    zero_hits: bool,
+    /// How many searches to report
+    limit: usize,
+    /// Path to the directory created by `load`
+    base_dir: String,
+}
+
+fn run(options: TopQueriesOptions) -> Result<()> {
+    log::info!("Started with {:?}", &options);
+
+    let db_path = Path::new(options.base_dir.as_str()).join("database");
+
+    let mut stats = analytics::top_queries(&db_path)?;
+    if options.zero_hits {
+        stats.retain(|stats| stats.zero_hits > 0);
+        stats.sort_by(|a, b| b.zero_hits.cmp(&a.zero_hits));
+    }
+
+    println!("count\tzero_hits\tmean_latency_micros\tfulltext\tfilter");
+    for QueryStats {
+        fulltext,
+        filter,
+        count,
+        zero_hits,
+        mean_latency_micros,
+    } in stats.into_iter().take(options.limit)
+    {
+        println!(
+            "{}\t{}\t{}\t{}\t{}",
+            count,
+            zero_hits,
+            mean_latency_micros,
+            fulltext.unwrap_or_default(),
+            filter.unwrap_or_default()
+        );
+    }
+
+    Ok(())
+}
+
+const USAGE: &str = "Usage: top_queries BASE_DIR [--zero-hits] [--limit N]";
+
+fn main() -> Result<()> {
+    env_logger::init();
+
+    let mut args = env::args().skip(1);
+    let base_dir = args.next().expect(USAGE);
+
+    let mut zero_hits = false;
+    let mut limit = 20;
+    while let Some(option) = args.next() {
+        match option.as_str() {
+            "--zero-hits" => zero_hits = true,
+            "--limit" => {
+                limit = args
+                    .next()
+                    .expect(USAGE)
+                    .parse()
+                    .expect("limit must be a positive integer");
+            }
+            _ => panic!("{}", USAGE),
+        }
+    }
+
+    let options = TopQueriesOptions {
+        zero_hits,
+        limit,
+        base_dir,
+    };
+
+    run(options)
+}
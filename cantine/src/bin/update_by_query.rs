@@ -0,0 +1,146 @@
+use std::{convert::TryFrom, env, path::Path, str::FromStr, time::Instant};
+
+use env_logger;
+use serde::Deserialize;
+
+use tantivy::{
+    query::{BooleanQuery, Occur, Query},
+    Index, Result,
+};
+
+use cantine::{
+    analysis::Analysis,
+    collation::Collation,
+    index::RecipeIndex,
+    model::FeaturesFilterQuery,
+    update::{self, Transform},
+};
+use tique::QueryParser;
+
+/// Which recipes to update: like a search, but only its full-text
+/// and feature filter parts
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct UpdateQuery {
+    fulltext: Option<String>,
+    filter: Option<FeaturesFilterQuery>,
+}
+
+/// Changes every recipe matching a query in an existing database
+/// and patches the index with the changed documents
+#[derive(Debug)]
+pub struct UpdateOptions {
+    /// Size for tantivy's writer buffer in MBs
+    buffer_size: usize,
+    /// How many changed recipes to write before comitting
+    commit_every: usize,
+    /// The recipes to change
+    query: UpdateQuery,
+    /// What to change in them, in order
+    transforms: Vec<Transform>,
+    /// Path to the directory created by `load`
+    base_dir: String,
+}
+
+fn run(options: UpdateOptions) -> Result<()> {
+    log::info!("Started with {:?}", &options);
+
+    let base_path = Path::new(options.base_dir.as_str());
+    let db_path = base_path.join("database");
+    let index_path = base_path.join("tantivy");
+
+    let index = Index::open_in_dir(&index_path)?;
+    Analysis::load(base_path)?.register(&index);
+    // Name keys must keep being generated like the indexed ones were
+    let recipe_index =
+        RecipeIndex::try_from(&index.schema())?.with_collation(Collation::load(base_path)?);
+    recipe_index.install_tokenizers(&index)?;
+
+    let mut subqueries: Vec<(Occur, Box<dyn Query>)> = Vec::new();
+    if let Some(fulltext) = &options.query.fulltext {
+        let parser = QueryParser::new(
+            &index,
+            vec![
+                recipe_index.name,
+                recipe_index.ingredients,
+                recipe_index.instructions,
+            ],
+        )?;
+        let parsed = parser
+            .parse(fulltext)
+            .unwrap_or_else(|| panic!("Nothing to search for in {:?}", fulltext));
+        subqueries.push((Occur::Must, parsed));
+    }
+    if let Some(filter) = &options.query.filter {
+        for query in recipe_index.features.interpret(filter) {
+            subqueries.push((Occur::Must, query));
+        }
+    }
+    let query = BooleanQuery::from(subqueries);
+
+    let mut writer = index.writer(options.buffer_size * 1_000_000)?;
+
+    let cur = Instant::now();
+    let num_changed = update::run(
+        &db_path,
+        &mut writer,
+        &recipe_index,
+        &query,
+        &options.transforms,
+        options.commit_every,
+    )?;
+
+    log::info!(
+        "Updated {} recipes in {} seconds",
+        num_changed,
+        cur.elapsed().as_secs()
+    );
+
+    Ok(())
+}
+
+const BUFFER_SIZE: &str = "BUFFER_SIZE";
+const COMMIT_EVERY: &str = "COMMIT_EVERY";
+
+const USAGE: &str = "Usage: update_by_query BASE_DIR QUERY_JSON \
+                     [--set FEATURE=VALUE] [--clear FEATURE] [--flag DIET=on|off]...";
+
+fn get_usize_from_env_or(key: &str, default: usize) -> usize {
+    env::var(key)
+        .ok()
+        .map(|v| usize::from_str(&v).expect("valid usize"))
+        .unwrap_or(default)
+}
+
+fn main() -> Result<()> {
+    env_logger::init();
+
+    let mut args = env::args().skip(1);
+    let base_dir = args.next().expect(USAGE);
+
+    let query: UpdateQuery =
+        serde_json::from_str(&args.next().expect(USAGE)).expect("valid json query");
+    // Updating everything by accident is too easy otherwise
+    if query.fulltext.is_none() && query.filter.is_none() {
+        panic!("The query must have a fulltext or a filter");
+    }
+
+    let mut transforms = Vec::new();
+    while let Some(option) = args.next() {
+        let arg = args.next().expect(USAGE);
+        transforms.push(Transform::from_arg(&option, &arg).unwrap_or_else(|err| panic!("{}", err)));
+    }
+    if transforms.is_empty() {
+        panic!("{}", USAGE);
+    }
+
+    let options = UpdateOptions {
+        base_dir,
+        query,
+        transforms,
+        buffer_size: get_usize_from_env_or(BUFFER_SIZE, 1000),
+        commit_every: get_usize_from_env_or(COMMIT_EVERY, 300_000),
+    };
+
+    run(options)
+}
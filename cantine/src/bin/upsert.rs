@@ -0,0 +1,141 @@
+use std::{
+    convert::TryFrom,
+    env,
+    io::{self, BufRead},
+    path::Path,
+    str::FromStr,
+    time::Instant,
+};
+
+use env_logger;
+
+use tantivy::{Index, Result};
+
+use cantine::{
+    analysis::Analysis, collation::Collation, estimate, index::RecipeIndex, model::Recipe,
+    store::Cantine,
+};
+
+/// Adds (or replaces) recipes read as json lines from stdin in an
+/// existing database, going through `Cantine` so that the index
+/// keeps agreeing with the database
+#[derive(Debug)]
+pub struct UpsertOptions {
+    /// Size for tantivy's writer buffer in MBs
+    buffer_size: usize,
+    /// How many recipes to upsert before comitting
+    commit_every: usize,
+    /// Identifies the batch, so that retrying it doesn't add the
+    /// recipes it already added again
+    key: Option<String>,
+    /// For how long batch keys are remembered, in seconds
+    window: Option<u64>,
+    /// Path to the directory created by `load`
+    base_dir: String,
+}
+
+fn run(options: UpsertOptions) -> Result<()> {
+    log::info!("Started with {:?}", &options);
+
+    let base_path = Path::new(options.base_dir.as_str());
+    let db_path = base_path.join("database");
+    let index_path = base_path.join("tantivy");
+
+    let index = Index::open_in_dir(&index_path)?;
+    Analysis::load(base_path)?.register(&index);
+    let recipe_index =
+        RecipeIndex::try_from(&index.schema())?.with_collation(Collation::load(base_path)?);
+
+    let mut cantine = Cantine::open(
+        &db_path,
+        index.writer(options.buffer_size * 1_000_000)?,
+        recipe_index,
+    )?;
+    if let Some(window) = options.window {
+        cantine = cantine.with_idempotency_window(window);
+    }
+
+    let cur = Instant::now();
+    let mut num_upserted = 0;
+    let mut num_skipped = 0;
+
+    for (line_number, line) in io::stdin().lock().lines().enumerate() {
+        let mut recipe: Recipe = serde_json::from_str(line?.as_ref()).expect("valid recipe json");
+        estimate::fill_in(&mut recipe);
+
+        // One key per line: a retry of a batch that failed midway
+        // only adds the recipes that didn't make it
+        let upserted = match &options.key {
+            Some(key) => cantine.upsert_with_key(&format!("{}:{}", key, line_number), &recipe)?,
+            None => {
+                cantine.upsert(&recipe)?;
+                true
+            }
+        };
+
+        if !upserted {
+            num_skipped += 1;
+            continue;
+        }
+
+        num_upserted += 1;
+        if num_upserted % options.commit_every == 0 {
+            let generation = cantine.commit()?;
+            log::info!(
+                "Upserted {} recipes so far. Generation {}",
+                num_upserted,
+                generation
+            );
+        }
+    }
+
+    let generation = cantine.commit()?;
+
+    log::info!(
+        "Upserted {} recipes in {} seconds, skipped {} already upserted by this batch. Generation {}",
+        num_upserted,
+        cur.elapsed().as_secs(),
+        num_skipped,
+        generation
+    );
+
+    Ok(())
+}
+
+const BUFFER_SIZE: &str = "BUFFER_SIZE";
+const COMMIT_EVERY: &str = "COMMIT_EVERY";
+const IDEMPOTENCY_WINDOW: &str = "IDEMPOTENCY_WINDOW";
+
+const USAGE: &str = "Usage: upsert BASE_DIR [--key BATCH_KEY] < recipes.jsonlines";
+
+fn get_usize_from_env_or(key: &str, default: usize) -> usize {
+    env::var(key)
+        .ok()
+        .map(|v| usize::from_str(&v).expect("valid usize"))
+        .unwrap_or(default)
+}
+
+fn main() -> Result<()> {
+    env_logger::init();
+
+    let mut args = env::args().skip(1);
+    let base_dir = args.next().expect(USAGE);
+
+    let key = match args.next().as_deref() {
+        Some("--key") => Some(args.next().expect(USAGE)),
+        Some(_) => panic!("{}", USAGE),
+        None => None,
+    };
+
+    let options = UpsertOptions {
+        base_dir,
+        key,
+        window: env::var(IDEMPOTENCY_WINDOW)
+            .ok()
+            .map(|v| u64::from_str(&v).expect("valid window, in seconds")),
+        buffer_size: get_usize_from_env_or(BUFFER_SIZE, 1000),
+        commit_every: get_usize_from_env_or(COMMIT_EVERY, 300_000),
+    };
+
+    run(options)
+}
@@ -0,0 +1,203 @@
+use std::{
+    future::Future,
+    io,
+    panic::{self, AssertUnwindSafe},
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll, Waker},
+    thread,
+};
+
+use crossbeam_channel::{unbounded, Sender};
+
+use crate::error::{Error, Result};
+
+type Task = Box<dyn FnOnce() + Send>;
+
+/// Threads for the blocking work (searching, reading the memory-mapped
+/// database) behind the `*_async` methods, so that async servers don't
+/// need to wrap every call themselves.
+///
+/// The futures it yields don't depend on any runtime: they work the
+/// same under tokio, actix or a plain executor. The threads go away
+/// once every clone of the pool is dropped and the queued tasks ran
+#[derive(Clone)]
+pub struct BlockingPool {
+    sender: Sender<Task>,
+}
+
+impl BlockingPool {
+    pub fn new(num_threads: usize) -> Result<Self> {
+        let (sender, receiver) = unbounded::<Task>();
+
+        for idx in 0..num_threads.max(1) {
+            let receiver = receiver.clone();
+            thread::Builder::new()
+                .name(format!("cantine-blocking-{}", idx))
+                .spawn(move || {
+                    for task in receiver {
+                        task();
+                    }
+                })?;
+        }
+
+        Ok(Self { sender })
+    }
+
+    /// Runs `task` in one of the threads. A task that panics yields
+    /// an error instead of taking the thread down with it
+    pub fn run<F, R>(&self, task: F) -> Blocking<R>
+    where
+        F: FnOnce() -> Result<R> + Send + 'static,
+        R: Send + 'static,
+    {
+        let slot = Arc::new(Mutex::new(Slot {
+            outcome: None,
+            waker: None,
+        }));
+
+        let task_slot = slot.clone();
+        let queued = self.sender.send(Box::new(move || {
+            let outcome = panic::catch_unwind(AssertUnwindSafe(task))
+                .unwrap_or_else(|_| Err(failure("Blocking task panicked")));
+            complete(&task_slot, outcome);
+        }));
+
+        if queued.is_err() {
+            complete(&slot, Err(failure("Blocking pool is gone")));
+        }
+
+        Blocking(slot)
+    }
+}
+
+/// The outcome of a task handed to a `BlockingPool`
+pub struct Blocking<R>(Arc<Mutex<Slot<R>>>);
+
+struct Slot<R> {
+    outcome: Option<Result<R>>,
+    waker: Option<Waker>,
+}
+
+impl<R> Future for Blocking<R> {
+    type Output = Result<R>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut slot = self.0.lock().expect("lock not poisoned");
+        match slot.outcome.take() {
+            Some(outcome) => Poll::Ready(outcome),
+            None => {
+                slot.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+fn complete<R>(slot: &Mutex<Slot<R>>, outcome: Result<R>) {
+    let mut slot = slot.lock().expect("lock not poisoned");
+    slot.outcome = Some(outcome);
+    if let Some(waker) = slot.waker.take() {
+        waker.wake();
+    }
+}
+
+fn failure(reason: &str) -> Error {
+    Error::Io(io::Error::new(io::ErrorKind::Other, reason))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use actix_rt::System;
+    use tantivy::{query::AllQuery, schema::SchemaBuilder, Index};
+
+    use crate::{
+        database::{DatabaseReader, DatabaseWriter},
+        index::RecipeIndex,
+        model::{Recipe, Sort},
+    };
+
+    #[test]
+    fn runs_tasks_off_the_caller_thread() -> Result<()> {
+        let pool = BlockingPool::new(2)?;
+        let caller = thread::current().id();
+
+        let tasks = (0..10)
+            .map(|idx| pool.run(move || Ok((idx, thread::current().id()))))
+            .collect::<Vec<_>>();
+
+        let mut system = System::new("test");
+        for (expected, task) in tasks.into_iter().enumerate() {
+            let (idx, ran_on) = system.block_on(task)?;
+            assert_eq!(expected, idx);
+            assert_ne!(caller, ran_on);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn failures_reach_the_caller() -> Result<()> {
+        let pool = BlockingPool::new(1)?;
+        let mut system = System::new("test");
+
+        let failed = pool.run(|| -> Result<()> { Err(Error::QueryParse("nope".to_owned())) });
+        match system.block_on(failed) {
+            Err(Error::QueryParse(reason)) => assert_eq!("nope", reason),
+            other => panic!("Unexpected outcome: {:?}", other),
+        }
+
+        let panicked = pool.run(|| -> Result<()> { panic!("oops") });
+        assert!(system.block_on(panicked).is_err());
+
+        // The thread survives the panic
+        assert_eq!(42, system.block_on(pool.run(|| Ok(42)))?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn async_searches_and_lookups() -> Result<()> {
+        let recipe = Recipe {
+            crawl_url: "https://example.com/shakshuka".to_owned(),
+            ingredients: vec!["eggs".to_owned(), "tomatoes".to_owned()],
+            instructions: vec!["poach the eggs in the sauce".to_owned()],
+            ..Recipe::sample(7, "Shakshuka")
+        };
+
+        let db_dir = tempfile::tempdir()?;
+        let mut db = DatabaseWriter::new(db_dir.path())?;
+        db.append(&recipe)?;
+        drop(db);
+        let db = Arc::new(DatabaseReader::<Recipe>::open(db_dir.path())?);
+
+        let mut builder = SchemaBuilder::new();
+        let recipe_index = RecipeIndex::from(&mut builder);
+        let index = Index::create_in_ram(builder.build());
+        let mut writer = index.writer_with_num_threads(1, 3_000_000)?;
+        writer.add_document(recipe_index.make_document(&recipe));
+        writer.commit()?;
+        let reader = index.reader()?;
+
+        let pool = BlockingPool::new(1)?;
+        let mut system = System::new("test");
+
+        let (total, found, _after) = system.block_on(recipe_index.search_async(
+            &pool,
+            &reader,
+            Box::new(AllQuery),
+            10,
+            Sort::Relevance,
+            None,
+        ))?;
+        assert_eq!(1, total);
+        assert_eq!(vec![7], found);
+
+        assert_eq!(Some(recipe), system.block_on(db.get_async(&pool, 7))?);
+        assert_eq!(None, system.block_on(db.get_async(&pool, 8))?);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,320 @@
+use std::{
+    collections::{hash_map::DefaultHasher, BTreeMap, HashMap},
+    hash::{Hash, Hasher},
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use tantivy::{query::Query, Result, Searcher};
+
+use crate::{
+    index::After,
+    metrics,
+    model::{RecipeId, Sort, TotalCount},
+};
+
+/// What `RecipeIndex::search_with_total` yields
+pub type SearchOutput = (TotalCount, Vec<RecipeId>, Option<After>);
+
+/// Remembers the results of recent searches, so that popular ones
+/// aren't recomputed over and over.
+///
+/// Results are kept for the generation of the index (as seen by a
+/// `Searcher`) they were computed for: everything is forgotten as
+/// soon as a searcher sees a different one, like after a commit gets
+/// picked up by the reader. Otherwise, entries go away once they're
+/// older than the ttl or when they are the least recently used and
+/// there's no room left.
+pub struct SearchCache {
+    max_entries: usize,
+    ttl: Duration,
+    inner: Mutex<Inner>,
+}
+
+#[derive(Default)]
+struct Inner {
+    generation: u64,
+    tick: u64,
+    entries: HashMap<String, Entry>,
+    // Least recently used first
+    recency: BTreeMap<u64, String>,
+}
+
+struct Entry {
+    output: SearchOutput,
+    inserted_at: Instant,
+    tick: u64,
+}
+
+impl SearchCache {
+    /// Creates a cache of up to `max_entries` results, each kept
+    /// for at most `ttl`
+    ///
+    /// # Panics
+    ///
+    /// Panics if `max_entries` is zero
+    pub fn new(max_entries: usize, ttl: Duration) -> Self {
+        assert!(max_entries > 0, "Cache size must be greater than zero");
+
+        Self {
+            max_entries,
+            ttl,
+            inner: Mutex::new(Inner::default()),
+        }
+    }
+
+    /// Identifies the state of the index the searcher sees. Changes
+    /// whenever segments are added, merged or get deletes
+    pub fn generation(searcher: &Searcher) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        for reader in searcher.segment_readers() {
+            reader.segment_id().hash(&mut hasher);
+            reader.num_deleted_docs().hash(&mut hasher);
+        }
+        hasher.finish()
+    }
+
+    /// The cache key for a search. The full-text part of the query
+    /// is normalized before parsing (see `tique::Ast::normalize`), so
+    /// equivalent requests share their key
+    pub fn key(query: &dyn Query, limit: usize, sort: &Sort, after: &Option<After>) -> String {
+        format!("{:?}|{}|{:?}|{:?}", query, limit, sort, after)
+    }
+
+    /// Yields the cached result for `key` if there's a fresh one for
+    /// the given generation. Otherwise computes it via `search` and
+    /// keeps it for next time
+    pub fn get_or_search<F>(&self, generation: u64, key: String, search: F) -> Result<SearchOutput>
+    where
+        F: FnOnce() -> Result<SearchOutput>,
+    {
+        let cached = self.get(generation, &key);
+        metrics::cache_lookup(cached.is_some());
+        if let Some(output) = cached {
+            return Ok(output);
+        }
+
+        // The lock is not held while searching, so concurrent misses
+        // for the same key may search more than once
+        let output = search()?;
+        self.insert(generation, key, output.clone());
+
+        Ok(output)
+    }
+
+    /// Forgets the result for `key`, if any
+    pub fn remove(&self, key: &str) {
+        let mut inner = self.inner.lock().expect("lock not poisoned");
+        if let Some(entry) = inner.entries.remove(key) {
+            inner.recency.remove(&entry.tick);
+        }
+    }
+
+    /// How many results are cached
+    pub fn len(&self) -> usize {
+        self.inner.lock().expect("lock not poisoned").entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn get(&self, generation: u64, key: &str) -> Option<SearchOutput> {
+        let mut inner = self.inner.lock().expect("lock not poisoned");
+        inner.check_generation(generation);
+
+        let (inserted_at, old_tick) = match inner.entries.get(key) {
+            Some(entry) => (entry.inserted_at, entry.tick),
+            None => return None,
+        };
+
+        inner.recency.remove(&old_tick);
+        if inserted_at.elapsed() >= self.ttl {
+            inner.entries.remove(key);
+            return None;
+        }
+
+        let tick = inner.next_tick();
+        inner.recency.insert(tick, key.to_owned());
+
+        let entry = inner.entries.get_mut(key).expect("entry exists");
+        entry.tick = tick;
+        Some(entry.output.clone())
+    }
+
+    fn insert(&self, generation: u64, key: String, output: SearchOutput) {
+        let mut inner = self.inner.lock().expect("lock not poisoned");
+        inner.check_generation(generation);
+
+        if let Some(previous) = inner.entries.remove(&key) {
+            inner.recency.remove(&previous.tick);
+        }
+
+        while inner.entries.len() >= self.max_entries {
+            let oldest = match inner.recency.keys().next() {
+                Some(&tick) => tick,
+                None => break,
+            };
+            let evicted = inner.recency.remove(&oldest).expect("tick exists");
+            inner.entries.remove(&evicted);
+        }
+
+        let tick = inner.next_tick();
+        inner.recency.insert(tick, key.clone());
+        inner.entries.insert(
+            key,
+            Entry {
+                output,
+                inserted_at: Instant::now(),
+                tick,
+            },
+        );
+    }
+}
+
+impl Inner {
+    fn check_generation(&mut self, generation: u64) {
+        if self.generation != generation {
+            self.entries.clear();
+            self.recency.clear();
+            self.generation = generation;
+        }
+    }
+
+    fn next_tick(&mut self) -> u64 {
+        self.tick += 1;
+        self.tick
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::cell::Cell;
+
+    use tantivy::{
+        doc,
+        schema::{SchemaBuilder, INDEXED},
+        Index,
+    };
+
+    fn output(total: usize) -> SearchOutput {
+        (TotalCount::exact(total), vec![total as RecipeId], None)
+    }
+
+    // Searches that count how many times they actually ran
+    fn counted(calls: &Cell<usize>, total: usize) -> impl FnOnce() -> Result<SearchOutput> + '_ {
+        move || {
+            calls.set(calls.get() + 1);
+            Ok(output(total))
+        }
+    }
+
+    #[test]
+    fn hits_and_lru_eviction() -> Result<()> {
+        let cache = SearchCache::new(2, Duration::from_secs(60));
+        let calls = Cell::new(0);
+
+        assert_eq!(
+            1,
+            cache
+                .get_or_search(0, "a".into(), counted(&calls, 1))?
+                .0
+                .value
+        );
+        assert_eq!(
+            2,
+            cache
+                .get_or_search(0, "b".into(), counted(&calls, 2))?
+                .0
+                .value
+        );
+        assert_eq!(2, calls.get());
+
+        // Hit: "a" becomes the most recently used
+        assert_eq!(
+            1,
+            cache
+                .get_or_search(0, "a".into(), counted(&calls, 42))?
+                .0
+                .value
+        );
+        assert_eq!(2, calls.get());
+
+        // No room for "c": "b" goes away
+        cache.get_or_search(0, "c".into(), counted(&calls, 3))?;
+        assert_eq!(2, cache.len());
+        assert_eq!(
+            2,
+            cache
+                .get_or_search(0, "b".into(), counted(&calls, 2))?
+                .0
+                .value
+        );
+        assert_eq!(4, calls.get());
+
+        Ok(())
+    }
+
+    #[test]
+    fn expired_entries_are_recomputed() -> Result<()> {
+        let cache = SearchCache::new(10, Duration::from_secs(0));
+        let calls = Cell::new(0);
+
+        cache.get_or_search(0, "a".into(), counted(&calls, 1))?;
+        cache.get_or_search(0, "a".into(), counted(&calls, 1))?;
+        assert_eq!(2, calls.get());
+
+        Ok(())
+    }
+
+    #[test]
+    fn removed_entries_are_recomputed() -> Result<()> {
+        let cache = SearchCache::new(10, Duration::from_secs(60));
+        let calls = Cell::new(0);
+
+        cache.get_or_search(0, "a".into(), counted(&calls, 1))?;
+        cache.remove("a");
+        assert!(cache.is_empty());
+        // Nothing to remove
+        cache.remove("a");
+
+        cache.get_or_search(0, "a".into(), counted(&calls, 1))?;
+        assert_eq!(2, calls.get());
+
+        Ok(())
+    }
+
+    #[test]
+    fn commits_invalidate_everything() -> Result<()> {
+        let mut builder = SchemaBuilder::new();
+        let id = builder.add_u64_field("id", INDEXED);
+        let index = Index::create_in_ram(builder.build());
+        let mut writer = index.writer_with_num_threads(1, 3_000_000)?;
+        let reader = index.reader()?;
+
+        writer.add_document(doc!(id => 1u64));
+        writer.commit()?;
+        reader.reload()?;
+        let before = SearchCache::generation(&reader.searcher());
+        assert_eq!(before, SearchCache::generation(&reader.searcher()));
+
+        let cache = SearchCache::new(10, Duration::from_secs(60));
+        let calls = Cell::new(0);
+        cache.get_or_search(before, "a".into(), counted(&calls, 1))?;
+        cache.get_or_search(before, "b".into(), counted(&calls, 2))?;
+
+        writer.add_document(doc!(id => 2u64));
+        writer.commit()?;
+        reader.reload()?;
+        let after = SearchCache::generation(&reader.searcher());
+        assert_ne!(before, after);
+
+        cache.get_or_search(after, "a".into(), counted(&calls, 1))?;
+        assert_eq!(3, calls.get());
+        assert_eq!(1, cache.len());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,152 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Source of the current time for everything that resolves relative
+/// dates, so that searches can be reproduced against any point in
+/// time. Timestamps are seconds since the unix epoch, in UTC.
+pub trait Clock: Send + Sync {
+    fn now(&self) -> u64;
+
+    /// The current time in microseconds, for when seconds are too
+    /// coarse. Clocks without the precision just scale `now`
+    fn now_micros(&self) -> u64 {
+        self.now() * 1_000_000
+    }
+}
+
+/// The wall clock
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> u64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .expect("system time is after the epoch")
+            .as_secs()
+    }
+
+    fn now_micros(&self) -> u64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .expect("system time is after the epoch")
+            .as_micros() as u64
+    }
+}
+
+/// A clock that's stuck at a given timestamp
+#[derive(Debug, Clone, Copy)]
+pub struct FixedClock(pub u64);
+
+impl Clock for FixedClock {
+    fn now(&self) -> u64 {
+        self.0
+    }
+}
+
+pub const SECONDS_PER_DAY: u64 = 86_400;
+
+pub fn start_of_day(ts: u64) -> u64 {
+    ts - ts % SECONDS_PER_DAY
+}
+
+/// Weeks start on monday
+pub fn start_of_week(ts: u64) -> u64 {
+    let days = ts / SECONDS_PER_DAY;
+    // 1970-01-01 was a thursday
+    let weekday = (days + 3) % 7;
+    (days - weekday) * SECONDS_PER_DAY
+}
+
+pub fn start_of_month(ts: u64) -> u64 {
+    let (year, month, _day) = civil_from_days(ts / SECONDS_PER_DAY);
+    days_from_civil(year, month, 1) * SECONDS_PER_DAY
+}
+
+/// Adds `months` to the month `ts` is at, returning the start of the
+/// resulting month
+pub fn add_months(ts: u64, months: u32) -> u64 {
+    let (year, month, _day) = civil_from_days(ts / SECONDS_PER_DAY);
+    let zero_based = month - 1 + months;
+    days_from_civil(year + zero_based / 12, zero_based % 12 + 1, 1) * SECONDS_PER_DAY
+}
+
+/// Meteorological seasons (northern hemisphere): winter starts in
+/// december, spring in march, summer in june and autumn in september
+pub fn start_of_season(ts: u64) -> u64 {
+    let (year, month, _day) = civil_from_days(ts / SECONDS_PER_DAY);
+    let (year, first_month) = match month {
+        1 | 2 => (year - 1, 12),
+        m => (year, m - m % 3),
+    };
+    days_from_civil(year, first_month, 1) * SECONDS_PER_DAY
+}
+
+// Source: http://howardhinnant.github.io/date_algorithms.html
+// Simplified for dates after the epoch
+fn civil_from_days(days: u64) -> (u32, u32, u32) {
+    let z = days + 719_468;
+    let era = z / 146_097;
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    (year as u32, month as u32, day as u32)
+}
+
+fn days_from_civil(year: u32, month: u32, day: u32) -> u64 {
+    let year = u64::from(if month <= 2 { year - 1 } else { year });
+    let era = year / 400;
+    let yoe = year - era * 400;
+    let month = u64::from(month);
+    let mp = if month > 2 { month - 3 } else { month + 9 };
+    let doy = (153 * mp + 2) / 5 + u64::from(day) - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+
+    era * 146_097 + doe - 719_468
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 2020-02-29T13:37:00Z, a saturday
+    const LEAP_DAY: u64 = 1_582_983_420;
+
+    #[test]
+    fn civil_round_trip() {
+        for days in (0..100_000).step_by(17) {
+            let (y, m, d) = civil_from_days(days);
+            assert_eq!(days, days_from_civil(y, m, d));
+        }
+
+        assert_eq!((1970, 1, 1), civil_from_days(0));
+        assert_eq!((2020, 2, 29), civil_from_days(LEAP_DAY / SECONDS_PER_DAY));
+    }
+
+    #[test]
+    fn boundaries() {
+        // 2020-02-29T00:00:00Z
+        assert_eq!(1_582_934_400, start_of_day(LEAP_DAY));
+        // 2020-02-24T00:00:00Z, monday
+        assert_eq!(1_582_502_400, start_of_week(LEAP_DAY));
+        // 2020-02-01T00:00:00Z
+        assert_eq!(1_580_515_200, start_of_month(LEAP_DAY));
+        // 2019-12-01T00:00:00Z
+        assert_eq!(1_575_158_400, start_of_season(LEAP_DAY));
+        // 2020-03-01T00:00:00Z
+        assert_eq!(1_583_020_800, add_months(LEAP_DAY, 1));
+        // 2021-01-01T00:00:00Z
+        assert_eq!(1_609_459_200, add_months(LEAP_DAY, 11));
+    }
+
+    #[test]
+    fn fixed_clock_is_fixed() {
+        let clock = FixedClock(LEAP_DAY);
+        assert_eq!(LEAP_DAY, clock.now());
+        assert_eq!(clock.now(), clock.now());
+    }
+}
@@ -0,0 +1,486 @@
+use std::{
+    cmp::Ordering,
+    convert::TryInto,
+    fs,
+    io::{self, ErrorKind},
+    path::Path,
+    str::FromStr,
+};
+
+use tantivy::{
+    collector::{Collector, SegmentCollector},
+    fastfield::{BytesFastFieldReader, FastFieldReader},
+    schema::Field,
+    DocId, Result, Score, SegmentLocalId, SegmentReader, TantivyError,
+};
+
+use crate::model::RecipeId;
+
+/// Locale-aware rules for turning a recipe name into a binary sort key
+///
+/// The generated keys compare correctly as raw bytes, so they can be
+/// stored in the index and sorted on without any knowledge of the
+/// original locale.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Collation {
+    /// Accents are secondary: "Äpfel" sorts right next to "Apfel"
+    Root,
+    /// Like `Root`, but "ß" expands to "ss" (DIN 5007-1)
+    German,
+    /// "å", "ä" and "ö" are distinct letters sorted after "z"
+    Swedish,
+    /// "æ", "ø" and "å" are distinct letters sorted after "z"
+    Danish,
+}
+
+impl Default for Collation {
+    fn default() -> Self {
+        Collation::Root
+    }
+}
+
+impl FromStr for Collation {
+    type Err = String;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        match input.to_lowercase().as_str() {
+            "root" | "und" | "en" => Ok(Collation::Root),
+            "de" | "german" => Ok(Collation::German),
+            "sv" | "fi" | "swedish" => Ok(Collation::Swedish),
+            "da" | "nb" | "no" | "danish" => Ok(Collation::Danish),
+            other => Err(format!("Unknown collation locale: {}", other)),
+        }
+    }
+}
+
+// Where the collation an index was built with is recorded, next to
+// its analysis
+const COLLATION_FILE: &str = "collation";
+
+// Separates the primary (base letters) and the tertiary (original
+// lowercase text) levels of a key. Lower than any primary weight so
+// that "pie" sorts before "pie crust".
+const LEVEL_SEPARATOR: u8 = 0;
+
+impl Collation {
+    /// The locale name `from_str` reads back as this collation
+    pub fn name(&self) -> &'static str {
+        match self {
+            Collation::Root => "root",
+            Collation::German => "de",
+            Collation::Swedish => "sv",
+            Collation::Danish => "da",
+        }
+    }
+
+    /// Reads the collation the index in `base_dir` was built with,
+    /// falling back to the default when there's none recorded
+    /// (indices created before it was)
+    pub fn load<P: AsRef<Path>>(base_dir: P) -> io::Result<Self> {
+        match fs::read_to_string(base_dir.as_ref().join(COLLATION_FILE)) {
+            Ok(name) => Collation::from_str(name.trim())
+                .map_err(|err| io::Error::new(ErrorKind::InvalidData, err)),
+            Err(err) if err.kind() == ErrorKind::NotFound => Ok(Self::default()),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Records the collation of the index in `base_dir`. Every name
+    /// key in it must have been generated with this collation
+    pub fn save<P: AsRef<Path>>(&self, base_dir: P) -> io::Result<()> {
+        fs::write(base_dir.as_ref().join(COLLATION_FILE), self.name())
+    }
+
+    /// Computes the binary collation key for `input`
+    pub fn sort_key(&self, input: &str) -> Vec<u8> {
+        let lowercase = input.to_lowercase();
+        let mut key = Vec::with_capacity(lowercase.len() * 2 + 1);
+
+        for c in lowercase.chars() {
+            self.push_primary(c, &mut key);
+        }
+
+        key.push(LEVEL_SEPARATOR);
+        key.extend_from_slice(lowercase.as_bytes());
+        key
+    }
+
+    fn push_primary(&self, c: char, key: &mut Vec<u8>) {
+        // Locale-specific letters sort after 'z', taking the bytes
+        // that follow it in the ascii table
+        let tailored = match (self, c) {
+            (Collation::Swedish, 'å') => Some(b'{'),
+            (Collation::Swedish, 'ä') | (Collation::Swedish, 'æ') => Some(b'|'),
+            (Collation::Swedish, 'ö') | (Collation::Swedish, 'ø') => Some(b'}'),
+            (Collation::Danish, 'æ') | (Collation::Danish, 'ä') => Some(b'{'),
+            (Collation::Danish, 'ø') | (Collation::Danish, 'ö') => Some(b'|'),
+            (Collation::Danish, 'å') => Some(b'}'),
+            _ => None,
+        };
+
+        if let Some(weight) = tailored {
+            key.push(weight);
+            return;
+        }
+
+        match c {
+            'a'..='z' | '0'..='9' => key.push(c as u8),
+            ' ' | '\t' | '-' | '_' => {
+                // Collapse runs of whitespace-ish chars
+                if key.last() != Some(&b' ') {
+                    key.push(b' ')
+                }
+            }
+            'à' | 'á' | 'â' | 'ã' | 'ä' | 'å' | 'ā' | 'ă' | 'ą' => key.push(b'a'),
+            'ç' | 'ć' | 'č' => key.push(b'c'),
+            'ď' | 'đ' => key.push(b'd'),
+            'è' | 'é' | 'ê' | 'ë' | 'ē' | 'ė' | 'ę' | 'ě' => key.push(b'e'),
+            'ğ' => key.push(b'g'),
+            'ì' | 'í' | 'î' | 'ï' | 'ī' | 'ı' => key.push(b'i'),
+            'ł' => key.push(b'l'),
+            'ñ' | 'ń' | 'ň' => key.push(b'n'),
+            'ò' | 'ó' | 'ô' | 'õ' | 'ö' | 'ø' | 'ō' | 'ő' => key.push(b'o'),
+            'ř' => key.push(b'r'),
+            'ś' | 'š' | 'ş' => key.push(b's'),
+            'ť' | 'ţ' => key.push(b't'),
+            'ù' | 'ú' | 'û' | 'ü' | 'ū' | 'ů' | 'ű' => key.push(b'u'),
+            'ý' | 'ÿ' => key.push(b'y'),
+            'ź' | 'ż' | 'ž' => key.push(b'z'),
+            'ß' => key.extend_from_slice(b"ss"),
+            'æ' => key.extend_from_slice(b"ae"),
+            'œ' => key.extend_from_slice(b"oe"),
+            c if c.is_alphanumeric() => {
+                // No tailoring known: fallback to code point order,
+                // which always sorts after the latin letters
+                let mut buf = [0u8; 4];
+                key.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+            }
+            // Punctuation and symbols are ignored at the primary level
+            _ => {}
+        }
+    }
+}
+
+/// Extracts a sortable u64 out of a collation key
+///
+/// Only the first 8 bytes of the key are taken into account, so names
+/// that share a long prefix are considered equal: good enough for
+/// pagination cursors, which is what it's for, but not for sorting.
+/// See `NameSortCollector`
+pub fn key_prefix(key: &[u8]) -> u64 {
+    let mut buf = [0u8; 8];
+    let len = key.len().min(8);
+    buf[..len].copy_from_slice(&key[..len]);
+    u64::from_be_bytes(buf[..].try_into().expect("buf has 8 bytes"))
+}
+
+/// Collects the top recipes by the full collation key of their name
+/// (kept in the bytes fast field `key_field`), ties broken by their
+/// id (from the u64 fast field `id_field`).
+///
+/// Keys are too long to fit the `Copy` scores `TopCollector` works
+/// with, so this one holds on to (a copy of) the keys of the best
+/// recipes instead.
+pub struct NameSortCollector {
+    key_field: Field,
+    id_field: Field,
+    limit: usize,
+    ascending: bool,
+    after: Option<(Vec<u8>, RecipeId)>,
+}
+
+/// The top recipes found by `NameSortCollector`
+#[derive(Debug, Clone, PartialEq)]
+pub struct NameSortResult {
+    /// How many recipes matched
+    pub total: usize,
+    /// The collation key and id of the top recipes, best first
+    pub items: Vec<(Vec<u8>, RecipeId)>,
+    /// How many recipes came after the cursor, if any. More than
+    /// there are `items` means there's a next page
+    pub visited: usize,
+}
+
+impl NameSortResult {
+    pub fn has_next(&self) -> bool {
+        self.visited > self.items.len()
+    }
+}
+
+impl NameSortCollector {
+    pub fn new(key_field: Field, id_field: Field, limit: usize, ascending: bool) -> Self {
+        Self {
+            key_field,
+            id_field,
+            limit,
+            ascending,
+            after: None,
+        }
+    }
+
+    /// Only collects the recipes that sort after the one with the
+    /// given key and id
+    pub fn after(self, key: Vec<u8>, recipe_id: RecipeId) -> Self {
+        Self {
+            after: Some((key, recipe_id)),
+            ..self
+        }
+    }
+}
+
+// How the recipe with `key` and `id` ranks against the `other` one:
+// Less means it comes first
+fn compare_names(
+    ascending: bool,
+    (key, id): (&[u8], RecipeId),
+    (other_key, other_id): (&[u8], RecipeId),
+) -> Ordering {
+    let by_key = if ascending {
+        key.cmp(other_key)
+    } else {
+        other_key.cmp(key)
+    };
+    by_key.then(id.cmp(&other_id))
+}
+
+impl Collector for NameSortCollector {
+    type Fruit = NameSortResult;
+    type Child = NameSortSegmentCollector;
+
+    fn for_segment(
+        &self,
+        _segment_id: SegmentLocalId,
+        reader: &SegmentReader,
+    ) -> Result<Self::Child> {
+        let keys = reader.fast_fields().bytes(self.key_field).ok_or_else(|| {
+            TantivyError::SchemaError(format!("{:?} is not a bytes fast field", self.key_field))
+        })?;
+        let ids = reader.fast_fields().u64(self.id_field).ok_or_else(|| {
+            TantivyError::SchemaError(format!("{:?} is not a u64 fast field", self.id_field))
+        })?;
+
+        Ok(NameSortSegmentCollector {
+            keys,
+            ids,
+            limit: self.limit,
+            ascending: self.ascending,
+            after: self.after.clone(),
+            result: NameSortResult {
+                total: 0,
+                items: Vec::with_capacity(self.limit),
+                visited: 0,
+            },
+        })
+    }
+
+    fn requires_scoring(&self) -> bool {
+        false
+    }
+
+    fn merge_fruits(&self, fruits: Vec<NameSortResult>) -> Result<NameSortResult> {
+        let mut merged = NameSortResult {
+            total: 0,
+            items: Vec::with_capacity(self.limit * fruits.len()),
+            visited: 0,
+        };
+
+        for fruit in fruits {
+            merged.total += fruit.total;
+            merged.visited += fruit.visited;
+            merged.items.extend(fruit.items);
+        }
+
+        let ascending = self.ascending;
+        merged.items.sort_by(|(key, id), (other_key, other_id)| {
+            compare_names(ascending, (key, *id), (other_key, *other_id))
+        });
+        merged.items.truncate(self.limit);
+
+        Ok(merged)
+    }
+}
+
+/// The per-segment part of `NameSortCollector`
+pub struct NameSortSegmentCollector {
+    keys: BytesFastFieldReader,
+    ids: FastFieldReader<u64>,
+    limit: usize,
+    ascending: bool,
+    after: Option<(Vec<u8>, RecipeId)>,
+    result: NameSortResult,
+}
+
+impl SegmentCollector for NameSortSegmentCollector {
+    type Fruit = NameSortResult;
+
+    fn collect(&mut self, doc: DocId, _score: Score) {
+        self.result.total += 1;
+
+        let key = self.keys.get_bytes(doc);
+        let id = self.ids.get(doc);
+        let ascending = self.ascending;
+
+        if let Some((after_key, after_id)) = &self.after {
+            if compare_names(ascending, (key, id), (after_key, *after_id)) != Ordering::Greater {
+                return;
+            }
+        }
+        self.result.visited += 1;
+
+        // Items are kept sorted, so only the ones that would make it
+        // to the top get their key copied
+        let position = self
+            .result
+            .items
+            .binary_search_by(|(other_key, other_id)| {
+                compare_names(ascending, (other_key, *other_id), (key, id))
+            })
+            .unwrap_or_else(|position| position);
+
+        if position < self.limit {
+            self.result.items.insert(position, (key.to_vec(), id));
+            self.result.items.truncate(self.limit);
+        }
+    }
+
+    fn harvest(self) -> Self::Fruit {
+        self.result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use tantivy::{
+        doc,
+        query::AllQuery,
+        schema::{SchemaBuilder, FAST},
+        Index,
+    };
+
+    fn sorted(collation: Collation, input: &[&str]) -> Vec<String> {
+        let mut items = input.to_vec();
+        items.sort_by_key(|name| collation.sort_key(name));
+        items.into_iter().map(String::from).collect()
+    }
+
+    #[test]
+    fn accents_are_not_sorted_by_raw_bytes() {
+        assert_eq!(
+            vec![
+                "Äpfelkuchen",
+                "Apfelstrudel",
+                "Bienenstich",
+                "Zwiebelkuchen"
+            ],
+            sorted(
+                Collation::German,
+                &[
+                    "Zwiebelkuchen",
+                    "Äpfelkuchen",
+                    "Bienenstich",
+                    "Apfelstrudel"
+                ]
+            )
+        );
+    }
+
+    #[test]
+    fn tailored_letters_sort_after_z() {
+        let input = ["Ärtsoppa", "Zucchinipaj", "Köttbullar", "Ostkaka"];
+
+        assert_eq!(
+            vec!["Köttbullar", "Ostkaka", "Zucchinipaj", "Ärtsoppa"],
+            sorted(Collation::Swedish, &input)
+        );
+
+        assert_eq!(
+            vec!["Ärtsoppa", "Köttbullar", "Ostkaka", "Zucchinipaj"],
+            sorted(Collation::Root, &input)
+        );
+    }
+
+    #[test]
+    fn shorter_prefix_comes_first() {
+        assert_eq!(
+            vec!["Pie", "Pie Crust", "Piece of cake"],
+            sorted(Collation::Root, &["Piece of cake", "Pie Crust", "Pie"])
+        );
+    }
+
+    #[test]
+    fn names_sort_on_the_full_key() -> Result<()> {
+        let mut builder = SchemaBuilder::new();
+        let key_field = builder.add_bytes_field("key");
+        let id_field = builder.add_u64_field("id", FAST);
+        let index = Index::create_in_ram(builder.build());
+        let mut writer = index.writer_with_num_threads(1, 3_000_000)?;
+
+        // Same first 8 bytes, inserted out of order
+        let names = [
+            "Chocolate chip cookies",
+            "Chocolate brownies",
+            "Chocolate cake",
+            "Chocolate",
+        ];
+        for (id, name) in names.iter().enumerate() {
+            let key = Collation::Root.sort_key(name);
+            writer.add_document(doc!(key_field => key, id_field => id as u64));
+        }
+        writer.commit()?;
+
+        let searcher = index.reader()?.searcher();
+        let ids = |collector: NameSortCollector| -> Result<(Vec<RecipeId>, bool)> {
+            let result = searcher.search(&AllQuery, &collector)?;
+            let has_next = result.has_next();
+            Ok((
+                result.items.into_iter().map(|(_, id)| id).collect(),
+                has_next,
+            ))
+        };
+
+        assert_eq!(
+            (vec![3, 1, 2, 0], false),
+            ids(NameSortCollector::new(key_field, id_field, 10, true))?
+        );
+        assert_eq!(
+            (vec![0, 2], true),
+            ids(NameSortCollector::new(key_field, id_field, 2, false))?
+        );
+
+        let cake = Collation::Root.sort_key("Chocolate cake");
+        assert_eq!(
+            (vec![0], false),
+            ids(NameSortCollector::new(key_field, id_field, 10, true).after(cake.clone(), 2))?
+        );
+        assert_eq!(
+            (vec![1, 3], false),
+            ids(NameSortCollector::new(key_field, id_field, 10, false).after(cake, 2))?
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn collation_is_recorded() -> io::Result<()> {
+        let dir = tempfile::tempdir()?;
+        assert_eq!(Collation::Root, Collation::load(dir.path())?);
+
+        Collation::Swedish.save(dir.path())?;
+        assert_eq!(Collation::Swedish, Collation::load(dir.path())?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn key_prefix_preserves_order() {
+        let a = Collation::Root.sort_key("Apple pie");
+        let b = Collation::Root.sort_key("Banana bread");
+        let short = Collation::Root.sort_key("A");
+
+        assert!(key_prefix(&a) < key_prefix(&b));
+        assert!(key_prefix(&short) < key_prefix(&a));
+        assert_eq!(0, key_prefix(&[]));
+    }
+}
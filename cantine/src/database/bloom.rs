@@ -0,0 +1,193 @@
+use std::{
+    fs::{self, File},
+    io::{self, BufReader, BufWriter, Read, Result, Write},
+    path::Path,
+};
+
+use byteorder::{NativeEndian, ReadBytesExt, WriteBytesExt};
+
+/// A bloom filter over record ids, sized for a 1% false positive
+/// rate at `capacity` items.
+///
+/// `len` counts insertions, not distinct ids: it's compared against
+/// the number of entries in the offsets log to detect that the
+/// persisted filter is stale.
+pub(crate) struct BloomFilter {
+    bits: Vec<u64>,
+    num_hashes: u32,
+    capacity: u64,
+    len: u64,
+}
+
+// ~9.6 bits per item and 7 hashes gives a 1% false positive rate
+const BITS_PER_ITEM: u64 = 10;
+const NUM_HASHES: u32 = 7;
+const MIN_CAPACITY: u64 = 1024;
+
+impl BloomFilter {
+    pub fn with_capacity(capacity: usize) -> Self {
+        let capacity = (capacity as u64).max(MIN_CAPACITY);
+        let num_words = (capacity * BITS_PER_ITEM + 63) / 64;
+
+        Self {
+            bits: vec![0; num_words as usize],
+            num_hashes: NUM_HASHES,
+            capacity,
+            len: 0,
+        }
+    }
+
+    pub fn insert(&mut self, id: u64) {
+        let num_bits = self.num_bits();
+        for pos in positions(id, self.num_hashes, num_bits) {
+            self.bits[(pos / 64) as usize] |= 1 << (pos % 64);
+        }
+        self.len += 1;
+    }
+
+    /// `false` means the id was definitely never inserted
+    pub fn contains(&self, id: u64) -> bool {
+        let num_bits = self.num_bits();
+        positions(id, self.num_hashes, num_bits)
+            .all(|pos| self.bits[(pos / 64) as usize] & (1 << (pos % 64)) != 0)
+    }
+
+    /// Wether more items were inserted than the filter was sized for,
+    /// so the false positive rate is higher than intended
+    pub fn is_overfull(&self) -> bool {
+        self.len > self.capacity
+    }
+
+    fn num_bits(&self) -> u64 {
+        self.bits.len() as u64 * 64
+    }
+
+    /// Reads a filter written by `save`, yielding `None` when there's
+    /// no file at `path` or when it doesn't account for exactly
+    /// `num_entries` insertions
+    pub fn load_fresh<P: AsRef<Path>>(path: P, num_entries: usize) -> Result<Option<Self>> {
+        let file = match File::open(path) {
+            Ok(file) => file,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(err),
+        };
+        let mut reader = BufReader::new(file);
+
+        let capacity = reader.read_u64::<NativeEndian>()?;
+        let len = reader.read_u64::<NativeEndian>()?;
+        let num_hashes = reader.read_u32::<NativeEndian>()?;
+        let num_words = reader.read_u64::<NativeEndian>()?;
+
+        if len != num_entries as u64 {
+            return Ok(None);
+        }
+
+        if num_hashes == 0 || num_words == 0 || num_words != (capacity * BITS_PER_ITEM + 63) / 64 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Corrupted bloom filter header",
+            ));
+        }
+
+        let mut bits = vec![0; num_words as usize];
+        reader.read_u64_into::<NativeEndian>(&mut bits)?;
+
+        let mut trailing = [0u8; 1];
+        if reader.read(&mut trailing)? != 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Trailing data after bloom filter",
+            ));
+        }
+
+        Ok(Some(Self {
+            bits,
+            num_hashes,
+            capacity,
+            len,
+        }))
+    }
+
+    /// Persists the filter. Writes to a temporary file first so that
+    /// a crash never leaves a truncated filter behind
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let tmp_path = path.as_ref().with_extension("tmp");
+
+        let mut writer = BufWriter::new(File::create(&tmp_path)?);
+        writer.write_u64::<NativeEndian>(self.capacity)?;
+        writer.write_u64::<NativeEndian>(self.len)?;
+        writer.write_u32::<NativeEndian>(self.num_hashes)?;
+        writer.write_u64::<NativeEndian>(self.bits.len() as u64)?;
+        for word in &self.bits {
+            writer.write_u64::<NativeEndian>(*word)?;
+        }
+        writer.flush()?;
+        drop(writer);
+
+        fs::rename(tmp_path, path)
+    }
+}
+
+// Double hashing on top of splitmix64: stable across runs, which
+// the persisted filter relies on
+fn positions(id: u64, num_hashes: u32, num_bits: u64) -> impl Iterator<Item = u64> {
+    let h1 = splitmix64(id);
+    let h2 = splitmix64(h1) | 1;
+    (0..u64::from(num_hashes)).map(move |i| h1.wrapping_add(i.wrapping_mul(h2)) % num_bits)
+}
+
+fn splitmix64(input: u64) -> u64 {
+    let mut z = input.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn no_false_negatives() {
+        let mut filter = BloomFilter::with_capacity(10_000);
+        for id in (0..20_000).step_by(2) {
+            filter.insert(id);
+        }
+
+        assert_eq!(10_000, filter.len);
+        assert!(!filter.is_overfull());
+        assert!((0..20_000).step_by(2).all(|id| filter.contains(id)));
+
+        let false_positives = (1..20_000)
+            .step_by(2)
+            .filter(|id| filter.contains(*id))
+            .count();
+        // 1% is the target, give it some slack
+        assert!(false_positives < 200, "{} false positives", false_positives);
+    }
+
+    #[test]
+    fn persistence() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("ids.bloom");
+
+        assert!(BloomFilter::load_fresh(&path, 0)?.is_none());
+
+        let mut filter = BloomFilter::with_capacity(10);
+        for id in 0..100 {
+            filter.insert(id * 7);
+        }
+        assert!(filter.is_overfull());
+        filter.save(&path)?;
+
+        // Stale: doesn't match the number of entries
+        assert!(BloomFilter::load_fresh(&path, 99)?.is_none());
+
+        let loaded = BloomFilter::load_fresh(&path, 100)?.expect("fresh filter");
+        assert_eq!(filter.len, loaded.len);
+        assert_eq!(filter.bits, loaded.bits);
+        assert!((0..100).all(|id| loaded.contains(id * 7)));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,201 @@
+use std::{fs, io, path::Path};
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::{Error, Result};
+
+/// How a database encodes its items
+///
+/// The codec a database is created with is recorded next to its data,
+/// so opening it with any other fails instead of yielding garbage.
+/// Databases from before codecs existed are bincode.
+pub trait Codec {
+    /// What gets recorded for the database. Must be unique
+    const NAME: &'static str;
+
+    fn encode<T: Serialize>(item: &T) -> Result<Vec<u8>>;
+
+    /// Decodes the item at the start of `bytes`, ignoring whatever
+    /// comes after it
+    fn decode<'a, T: Deserialize<'a>>(bytes: &'a [u8]) -> Result<T>;
+}
+
+/// Compact and fast, but only readable via serde. The default
+pub struct Bincode;
+
+impl Codec for Bincode {
+    const NAME: &'static str = "bincode";
+
+    fn encode<T: Serialize>(item: &T) -> Result<Vec<u8>> {
+        Ok(bincode::serialize(item)?)
+    }
+
+    fn decode<'a, T: Deserialize<'a>>(bytes: &'a [u8]) -> Result<T> {
+        // Reading past the end of `bytes` means truncated data, not
+        // an I/O failure
+        bincode::deserialize(bytes).map_err(|err| Error::Serialization(err.to_string()))
+    }
+}
+
+/// One json object per line, so that the data can be read from
+/// anywhere. Larger and slower than `Bincode`, and borrowed strings
+/// fail to decode when they contain escapes
+pub struct Json;
+
+impl Codec for Json {
+    const NAME: &'static str = "json";
+
+    fn encode<T: Serialize>(item: &T) -> Result<Vec<u8>> {
+        let mut encoded =
+            serde_json::to_vec(item).map_err(|err| Error::Serialization(err.to_string()))?;
+        encoded.push(b'\n');
+        Ok(encoded)
+    }
+
+    fn decode<'a, T: Deserialize<'a>>(bytes: &'a [u8]) -> Result<T> {
+        serde_json::Deserializer::from_slice(bytes)
+            .into_iter()
+            .next()
+            .unwrap_or_else(|| Err(serde::de::Error::custom("Nothing to decode at offset")))
+            .map_err(|err| Error::Serialization(err.to_string()))
+    }
+}
+
+/// CBOR, a standard binary format that other tools can read without
+/// knowing the types, at about the size of `Bincode`. Needs the
+/// `cbor` feature
+#[cfg(feature = "cbor")]
+pub struct Cbor;
+
+#[cfg(feature = "cbor")]
+impl Codec for Cbor {
+    const NAME: &'static str = "cbor";
+
+    fn encode<T: Serialize>(item: &T) -> Result<Vec<u8>> {
+        serde_cbor::to_vec(item).map_err(|err| Error::Serialization(err.to_string()))
+    }
+
+    fn decode<'a, T: Deserialize<'a>>(bytes: &'a [u8]) -> Result<T> {
+        // Unlike `serde_cbor::from_slice`, doesn't check that nothing
+        // comes after the item
+        let mut deserializer = serde_cbor::Deserializer::from_slice(bytes);
+        T::deserialize(&mut deserializer).map_err(|err| Error::Serialization(err.to_string()))
+    }
+}
+
+const CODEC_FILE: &str = "codec";
+
+/// Records that the database at `base_dir` uses `C`
+pub(crate) fn record<C: Codec, P: AsRef<Path>>(base_dir: P) -> io::Result<()> {
+    fs::write(base_dir.as_ref().join(CODEC_FILE), C::NAME)
+}
+
+/// Fails unless the database at `base_dir` uses `C`
+pub(crate) fn check<C: Codec, P: AsRef<Path>>(base_dir: P) -> Result<()> {
+    let recorded = match fs::read_to_string(base_dir.as_ref().join(CODEC_FILE)) {
+        Ok(name) => name,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Bincode::NAME.to_owned(),
+        Err(err) => return Err(err.into()),
+    };
+
+    if recorded.trim() == C::NAME {
+        Ok(())
+    } else {
+        Err(Error::Serialization(format!(
+            "Database at {:?} is encoded with {}, not {}",
+            base_dir.as_ref(),
+            recorded.trim(),
+            C::NAME
+        )))
+    }
+}
+
+/// Copies the codec record of the database at `base_dir`, if any
+pub(crate) fn copy<P: AsRef<Path>, Q: AsRef<Path>>(base_dir: P, dest_dir: Q) -> io::Result<()> {
+    match fs::copy(
+        base_dir.as_ref().join(CODEC_FILE),
+        dest_dir.as_ref().join(CODEC_FILE),
+    ) {
+        Ok(_) => Ok(()),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(()),
+        Err(err) => Err(err),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    struct Item {
+        id: u64,
+        name: String,
+    }
+
+    fn roundtrip<C: Codec>() -> Result<()> {
+        let first = Item {
+            id: 1,
+            name: "first \"quoted\"".to_owned(),
+        };
+        let second = Item {
+            id: 2,
+            name: "second".to_owned(),
+        };
+
+        let mut data = C::encode(&first)?;
+        let offset = data.len();
+        data.extend(C::encode(&second)?);
+
+        assert_eq!(first, C::decode::<Item>(&data)?);
+        assert_eq!(second, C::decode::<Item>(&data[offset..])?);
+        assert!(C::decode::<Item>(&[]).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn codecs_decode_what_they_encode() -> Result<()> {
+        roundtrip::<Bincode>()?;
+        roundtrip::<Json>()
+    }
+
+    #[cfg(feature = "cbor")]
+    #[test]
+    fn cbor_decodes_what_it_encodes() -> Result<()> {
+        roundtrip::<Cbor>()?;
+
+        // Borrowed strings point into the data
+        let encoded = Cbor::encode(&"borrowed")?;
+        let decoded: &str = Cbor::decode(&encoded)?;
+        assert!(encoded.as_ptr_range().contains(&decoded.as_ptr()));
+        Ok(())
+    }
+
+    #[test]
+    fn json_is_one_object_per_line() -> Result<()> {
+        let encoded = Json::encode(&Item {
+            id: 1,
+            name: "line\nbreak".to_owned(),
+        })?;
+        assert_eq!(
+            "{\"id\":1,\"name\":\"line\\nbreak\"}\n",
+            String::from_utf8(encoded).unwrap()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn codecs_are_checked() -> Result<()> {
+        let basedir = tempfile::tempdir()?;
+
+        // Nothing recorded means bincode
+        check::<Bincode, _>(basedir.path())?;
+        assert!(check::<Json, _>(basedir.path()).is_err());
+
+        record::<Json, _>(basedir.path())?;
+        check::<Json, _>(basedir.path())?;
+        assert!(check::<Bincode, _>(basedir.path()).is_err());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,128 @@
+use std::{
+    collections::HashMap,
+    fs::{self, File},
+    io::{self, BufReader, BufWriter, Result, Write},
+    path::Path,
+};
+
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+/// The lookups a `DatabaseReader` builds from the offsets log, as of
+/// some point of it, so that opening a reader only needs to go
+/// through the entries appended since.
+///
+/// Checkpoints are only trusted if the log still has the last entry
+/// they cover where they expect it: anything else (a missing or
+/// unreadable file, a log that got rewritten) means replaying the
+/// whole log, like before checkpoints existed.
+#[derive(Serialize, Deserialize, Default)]
+pub(crate) struct IndexCheckpoint {
+    /// How many log entries it covers
+    pub num_entries: usize,
+    /// The id and offset of the last of them
+    pub last_entry: Option<(u64, u64)>,
+    pub id_index: HashMap<u64, usize>,
+    pub uuid_index: HashMap<Uuid, u64>,
+    /// How long the latest version of each item is. Items go until
+    /// the next one starts, so the last appended one isn't in it.
+    /// Last, so that checkpoints from before it fail to load
+    pub id_lens: HashMap<u64, usize>,
+}
+
+impl IndexCheckpoint {
+    /// Reads the checkpoint at `path` if there's a usable one for a
+    /// log with `num_entries`
+    pub fn load<P: AsRef<Path>>(path: P, num_entries: usize) -> Option<Self> {
+        let file = match File::open(path.as_ref()) {
+            Ok(file) => file,
+            Err(err) => {
+                if err.kind() != io::ErrorKind::NotFound {
+                    log::warn!("Ignoring index checkpoint {:?}: {}", path.as_ref(), err);
+                }
+                return None;
+            }
+        };
+
+        match bincode::deserialize_from::<_, Self>(BufReader::new(file)) {
+            Ok(checkpoint)
+                if checkpoint.num_entries > 0 && checkpoint.num_entries <= num_entries =>
+            {
+                Some(checkpoint)
+            }
+            Ok(_) => None,
+            Err(err) => {
+                log::warn!("Ignoring index checkpoint {:?}: {}", path.as_ref(), err);
+                None
+            }
+        }
+    }
+
+    /// Replaces the checkpoint at `path` at once, so that readers
+    /// never see a partial one
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let staged = path.as_ref().with_extension("new");
+
+        let mut writer = BufWriter::new(File::create(&staged)?);
+        bincode::serialize_into(&mut writer, self)
+            .map_err(|err| io::Error::new(io::ErrorKind::Other, err))?;
+        writer.flush()?;
+        writer.get_ref().sync_all()?;
+
+        fs::rename(&staged, path)
+    }
+
+    /// Applies a log entry on top of the checkpoint
+    pub fn add(&mut self, id: u64, uuid: Uuid, offset: u64) {
+        if let Some((last_id, last_offset)) = self.last_entry {
+            if self.id_index.get(&last_id) == Some(&(last_offset as usize)) {
+                self.id_lens
+                    .insert(last_id, (offset - last_offset) as usize);
+            }
+        }
+
+        self.id_index.insert(id, offset as usize);
+        self.id_lens.remove(&id);
+        self.uuid_index.insert(uuid, id);
+        self.num_entries += 1;
+        self.last_entry = Some((id, offset));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn persistence() -> Result<()> {
+        let tmpdir = tempfile::tempdir()?;
+        let path = tmpdir.path().join("checkpoint");
+
+        assert!(IndexCheckpoint::load(&path, 10).is_none());
+
+        let mut checkpoint = IndexCheckpoint::default();
+        let uuid = Uuid::new_v4();
+        checkpoint.add(1, Uuid::new_v4(), 0);
+        checkpoint.add(1, uuid, 42);
+        checkpoint.add(2, Uuid::new_v4(), 50);
+        checkpoint.save(&path)?;
+
+        let loaded = IndexCheckpoint::load(&path, 3).expect("checkpoint is usable");
+        assert_eq!(3, loaded.num_entries);
+        assert_eq!(Some((2, 50)), loaded.last_entry);
+        assert_eq!(Some(&42), loaded.id_index.get(&1));
+        assert_eq!(Some(&8), loaded.id_lens.get(&1));
+        // The last one goes until the end of the data
+        assert_eq!(None, loaded.id_lens.get(&2));
+        assert_eq!(Some(&1), loaded.uuid_index.get(&uuid));
+
+        // Covers more than the log has
+        assert!(IndexCheckpoint::load(&path, 2).is_none());
+
+        // Garbage
+        fs::write(&path, b"garbage")?;
+        assert!(IndexCheckpoint::load(&path, 3).is_none());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,267 @@
+use std::{
+    collections::BTreeMap,
+    fs::{self, File},
+    io::{self, BufWriter, Result, Write},
+    path::Path,
+};
+
+/// Maps the string keys of records (see `DatabaseRecord::get_key`)
+/// to their ids.
+///
+/// Keys are kept sorted and prefix-compressed: each one only stores
+/// what it doesn't share with the one before it, except for every
+/// `RESTART_INTERVAL`th key which is stored whole so that lookups
+/// can binary search them and then scan a handful of entries.
+///
+/// On disk it's the same layout: the number of keys followed by the
+/// entries, each being the length of the shared prefix, the length
+/// of the rest, the rest and the id, with lengths and ids as varints.
+#[derive(Default)]
+pub(crate) struct KeyIndex {
+    entries: Vec<u8>,
+    restarts: Vec<usize>,
+    len: usize,
+}
+
+const RESTART_INTERVAL: usize = 16;
+
+impl KeyIndex {
+    /// Reads the index at `path`. Empty if there's none
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let bytes = match fs::read(path) {
+            Ok(bytes) => bytes,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(err) => return Err(err),
+        };
+
+        let mut cursor = &bytes[..];
+        let len = read_varint(&mut cursor)? as usize;
+        let entries = cursor.to_vec();
+
+        // Validates the whole thing while finding the restarts
+        let mut restarts = Vec::with_capacity(len / RESTART_INTERVAL + 1);
+        let mut remaining = &entries[..];
+        for i in 0..len {
+            if i % RESTART_INTERVAL == 0 {
+                restarts.push(entries.len() - remaining.len());
+            }
+            let (shared, _suffix, _id) = read_entry(&mut remaining)?;
+            if i % RESTART_INTERVAL == 0 && shared != 0 {
+                return Err(invalid("Restart entry is prefix-compressed"));
+            }
+        }
+
+        if !remaining.is_empty() {
+            return Err(invalid("Trailing bytes after the last key"));
+        }
+
+        Ok(Self {
+            entries,
+            restarts,
+            len,
+        })
+    }
+
+    /// Replaces the index at `path` with `keys` at once, so that
+    /// readers never see a partial one. Removes it when there are
+    /// no keys
+    pub fn save<P: AsRef<Path>>(path: P, keys: &BTreeMap<String, u64>) -> Result<()> {
+        if keys.is_empty() {
+            return match fs::remove_file(path) {
+                Err(err) if err.kind() != io::ErrorKind::NotFound => Err(err),
+                _ => Ok(()),
+            };
+        }
+
+        let staged = path.as_ref().with_extension("new");
+        let mut writer = BufWriter::new(File::create(&staged)?);
+
+        write_varint(&mut writer, keys.len() as u64)?;
+        let mut previous: &[u8] = &[];
+        for (i, (key, id)) in keys.iter().enumerate() {
+            let key = key.as_bytes();
+            let shared = if i % RESTART_INTERVAL == 0 {
+                0
+            } else {
+                previous.iter().zip(key).take_while(|(a, b)| a == b).count()
+            };
+
+            write_varint(&mut writer, shared as u64)?;
+            write_varint(&mut writer, (key.len() - shared) as u64)?;
+            writer.write_all(&key[shared..])?;
+            write_varint(&mut writer, *id)?;
+
+            previous = key;
+        }
+
+        writer.flush()?;
+        writer.get_ref().sync_all()?;
+
+        fs::rename(&staged, path)
+    }
+
+    /// Every key and its id, in key order
+    pub fn entries(&self) -> Result<Vec<(String, u64)>> {
+        let mut found = Vec::with_capacity(self.len);
+        let mut key = Vec::new();
+        let mut remaining = &self.entries[..];
+        for _ in 0..self.len {
+            let (shared, suffix, id) = read_entry(&mut remaining)?;
+            key.truncate(shared);
+            key.extend_from_slice(suffix);
+            found.push((to_string(&key)?, id));
+        }
+        Ok(found)
+    }
+
+    pub fn get(&self, key: &str) -> Option<u64> {
+        let key = key.as_bytes();
+
+        // The last restart whose key isn't after the wanted one
+        let block = match self
+            .restarts
+            .binary_search_by(|&offset| self.restart_key(offset).cmp(key))
+        {
+            Ok(block) => block,
+            Err(0) => return None,
+            Err(after) => after - 1,
+        };
+
+        let mut remaining = &self.entries[self.restarts[block]..];
+        let mut current = Vec::with_capacity(key.len());
+        let num_entries = RESTART_INTERVAL.min(self.len - block * RESTART_INTERVAL);
+        for _ in 0..num_entries {
+            // Validated when loading
+            let (shared, suffix, id) = read_entry(&mut remaining).ok()?;
+            current.truncate(shared);
+            current.extend_from_slice(suffix);
+
+            match current.as_slice().cmp(key) {
+                std::cmp::Ordering::Less => continue,
+                std::cmp::Ordering::Equal => return Some(id),
+                std::cmp::Ordering::Greater => return None,
+            }
+        }
+
+        None
+    }
+
+    fn restart_key(&self, offset: usize) -> &[u8] {
+        let mut remaining = &self.entries[offset..];
+        read_entry(&mut remaining)
+            .map(|(_shared, suffix, _id)| suffix)
+            .unwrap_or_default()
+    }
+}
+
+fn read_entry<'a>(bytes: &mut &'a [u8]) -> Result<(usize, &'a [u8], u64)> {
+    let shared = read_varint(bytes)? as usize;
+    let suffix_len = read_varint(bytes)? as usize;
+    if suffix_len > bytes.len() {
+        return Err(invalid("Key goes past the end of the index"));
+    }
+    let (suffix, rest) = bytes.split_at(suffix_len);
+    *bytes = rest;
+    let id = read_varint(bytes)?;
+    Ok((shared, suffix, id))
+}
+
+fn write_varint<W: Write>(writer: &mut W, mut value: u64) -> Result<()> {
+    while value >= 0x80 {
+        writer.write_all(&[(value as u8) | 0x80])?;
+        value >>= 7;
+    }
+    writer.write_all(&[value as u8])
+}
+
+fn read_varint(bytes: &mut &[u8]) -> Result<u64> {
+    let mut value = 0;
+    for shift in (0..64).step_by(7) {
+        let (&byte, rest) = bytes
+            .split_first()
+            .ok_or_else(|| invalid("Truncated varint"))?;
+        *bytes = rest;
+        value |= u64::from(byte & 0x7f) << shift;
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+    Err(invalid("Varint too long"))
+}
+
+fn to_string(key: &[u8]) -> Result<String> {
+    String::from_utf8(key.to_vec()).map_err(|_| invalid("Key is not valid utf-8"))
+}
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lookups_across_restarts() -> Result<()> {
+        let tmpdir = tempfile::tempdir()?;
+        let path = tmpdir.path().join("keys");
+
+        assert!(KeyIndex::load(&path)?.entries()?.is_empty());
+
+        let keys: BTreeMap<String, u64> = (0..100u64)
+            .map(|i| (format!("pasta-carbonara-{}", i), i * 1000))
+            .chain(vec![("".to_owned(), 7), ("ñoquis".to_owned(), 8)])
+            .collect();
+        KeyIndex::save(&path, &keys)?;
+
+        let index = KeyIndex::load(&path)?;
+        for (key, id) in &keys {
+            assert_eq!(Some(*id), index.get(key));
+        }
+
+        for missing in &[
+            "a",
+            "pasta",
+            "pasta-carbonara-",
+            "pasta-carbonara-999",
+            "zzz",
+        ] {
+            assert_eq!(None, index.get(missing));
+        }
+
+        assert_eq!(keys.into_iter().collect::<Vec<_>>(), index.entries()?);
+
+        // Shared prefixes are only stored once
+        assert!(fs::metadata(&path)?.len() < 100 * "pasta-carbonara-".len() as u64);
+
+        Ok(())
+    }
+
+    #[test]
+    fn saving_nothing_removes_the_index() -> Result<()> {
+        let tmpdir = tempfile::tempdir()?;
+        let path = tmpdir.path().join("keys");
+
+        let mut keys = BTreeMap::new();
+        keys.insert("key".to_owned(), 1);
+        KeyIndex::save(&path, &keys)?;
+        assert!(path.exists());
+
+        KeyIndex::save(&path, &BTreeMap::new())?;
+        assert!(!path.exists());
+        assert!(KeyIndex::load(&path)?.entries()?.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn garbage_is_rejected() -> Result<()> {
+        let tmpdir = tempfile::tempdir()?;
+        let path = tmpdir.path().join("keys");
+
+        fs::write(&path, [3, 0, 200])?;
+        assert!(KeyIndex::load(&path).is_err());
+
+        Ok(())
+    }
+}
@@ -1,4 +1,14 @@
+mod bloom;
+mod codec;
+mod indexcheckpoint;
+mod keyindex;
 mod readerwriter;
 mod structuredlog;
 
-pub use readerwriter::{DatabaseReader, DatabaseRecord, DatabaseWriter};
+#[cfg(feature = "cbor")]
+pub use codec::Cbor;
+pub use codec::{Bincode, Codec, Json};
+pub use readerwriter::{
+    Checkpoint, Chunk, DatabaseReader, DatabaseRecord, DatabaseStats, DatabaseWriter,
+};
+pub(crate) use structuredlog::StructuredLog;
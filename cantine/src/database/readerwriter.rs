@@ -1,45 +1,114 @@
 use std::{
-    collections::HashMap,
-    fs::{File, OpenOptions},
-    io::{self, BufWriter, Result, Seek, SeekFrom, Write},
+    collections::{BTreeMap, HashMap, HashSet},
+    fs::{self, File, OpenOptions},
+    io::{self, BufWriter, Read, Seek, SeekFrom, Write},
     marker::PhantomData,
-    path::Path,
+    mem::size_of,
+    path::{Path, PathBuf},
+    sync::Arc,
 };
 
 use byteorder::NativeEndian;
 use memmap::Mmap;
-use serde::{de::Deserialize, Serialize};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use uuid::{self, Uuid};
-use zerocopy::{AsBytes, FromBytes, U64};
+use zerocopy::{AsBytes, FromBytes, LayoutVerified, U64};
 
-use super::structuredlog::StructuredLog;
+use crate::{
+    blocking::{Blocking, BlockingPool},
+    error::{Error, Result},
+    metrics,
+};
+
+use super::{
+    bloom::BloomFilter,
+    codec::{self, Bincode, Codec},
+    indexcheckpoint::IndexCheckpoint,
+    keyindex::KeyIndex,
+    structuredlog::StructuredLog,
+};
 
 pub trait DatabaseRecord {
     fn get_id(&self) -> u64;
     fn get_uuid(&self) -> uuid::Bytes;
+
+    /// An optional unique string to look the record up by (say: a
+    /// slug or an external id). See `DatabaseReader::find_by_key`
+    fn get_key(&self) -> Option<&str> {
+        None
+    }
 }
 
-pub struct DatabaseReader<T> {
+/// Reads a database written by a `DatabaseWriter`
+///
+/// The data file is mapped once, when opening: items appended after
+/// that are only visible to readers opened afterwards.
+///
+/// Items are decoded straight from the mapping with the lifetime of
+/// the reader, so types that borrow (`&'a str`, `&'a [u8]`, ...)
+/// don't copy anything. For going further, `find_raw_by_id` yields
+/// the encoded bytes as they are.
+///
+/// Items are bincode-encoded unless the database was created with
+/// another `Codec`, which the reader must then be opened with (see
+/// `open_with_codec`).
+pub struct DatabaseReader<T, C = Bincode> {
+    bloom: BloomFilter,
     uuid_index: HashMap<Uuid, u64>,
     id_index: HashMap<u64, usize>,
+    id_lens: HashMap<u64, usize>,
+    keys: KeyIndex,
     data: Mmap,
-    _marker: PhantomData<T>,
+    base_dir: PathBuf,
+    // How many log entries there were when opening
+    num_entries: usize,
+    _marker: PhantomData<(T, C)>,
 }
 
 impl<'a, T: Deserialize<'a>> DatabaseReader<T> {
     pub fn open<P: AsRef<Path>>(base_dir: P) -> Result<Self> {
+        Self::open_with_codec(base_dir)
+    }
+}
+
+impl<'a, T: Deserialize<'a>, C: Codec> DatabaseReader<T, C> {
+    /// Opens a database created with the codec `C`. Fails if it was
+    /// created with any other
+    pub fn open_with_codec<P: AsRef<Path>>(base_dir: P) -> Result<Self> {
+        codec::check::<C, _>(&base_dir)?;
+
         let log = StructuredLog::new(base_dir.as_ref().join(OFFSETS_FILE))?;
         let num_items = log.len()?;
 
-        let mut id_index = HashMap::with_capacity(num_items);
-        let mut uuid_index = HashMap::with_capacity(num_items);
+        let checkpoint_path = base_dir.as_ref().join(CHECKPOINT_FILE);
+        let (checkpoint, num_replayed) = replay(&log, &checkpoint_path, num_items)?;
 
-        log.for_each_entry(|entry: &LogEntry| {
-            let offset = entry.offset.get() as usize;
-            let id = entry.id.get();
-            id_index.insert(id, offset);
-            uuid_index.insert(Uuid::from_bytes(entry.uuid), id);
-        })?;
+        // Periodically, so that the next open has less to replay
+        if num_replayed >= CHECKPOINT_EVERY {
+            if let Err(err) = checkpoint.save(&checkpoint_path) {
+                log::warn!("Failed to save the index checkpoint: {}", err);
+            }
+        }
+
+        let IndexCheckpoint {
+            id_index,
+            id_lens,
+            uuid_index,
+            ..
+        } = checkpoint;
+
+        // A missing or stale filter (say, the writer is still going)
+        // is rebuilt in memory and left for the writer to persist
+        let bloom = match BloomFilter::load_fresh(base_dir.as_ref().join(BLOOM_FILE), num_items)? {
+            Some(bloom) => bloom,
+            None => {
+                let mut bloom = BloomFilter::with_capacity(id_index.len());
+                id_index.keys().for_each(|id| bloom.insert(*id));
+                bloom
+            }
+        };
+
+        let keys = KeyIndex::load(base_dir.as_ref().join(KEYS_FILE))?;
 
         let datafile = OpenOptions::new()
             .read(true)
@@ -47,9 +116,14 @@ impl<'a, T: Deserialize<'a>> DatabaseReader<T> {
             .open(base_dir.as_ref().join(DATA_FILE))?;
 
         Ok(Self {
+            bloom,
             id_index,
+            id_lens,
             uuid_index,
+            keys,
             data: unsafe { Mmap::map(&datafile)? },
+            base_dir: base_dir.as_ref().to_path_buf(),
+            num_entries: num_items,
             _marker: PhantomData,
         })
     }
@@ -58,11 +132,84 @@ impl<'a, T: Deserialize<'a>> DatabaseReader<T> {
         self.id_index.keys()
     }
 
+    /// Cheap check that never touches the index nor the data:
+    /// `false` means there's definitely no item with the given id
+    pub fn may_contain_id(&self, id: u64) -> bool {
+        self.bloom.contains(id)
+    }
+
     pub fn find_by_id(&'a self, id: u64) -> Option<Result<T>> {
-        self.id_index.get(&id).map(|offset| {
-            bincode::deserialize(&self.data[*offset..]).map_err(|_| {
-                io::Error::new(io::ErrorKind::InvalidData, "Failure decoding at offset")
+        if !self.may_contain_id(id) {
+            return None;
+        }
+
+        self.id_index.get(&id).map(|offset| self.decode(*offset))
+    }
+
+    /// The encoded item with the given id, for decoding only the
+    /// parts of it that are needed
+    pub fn find_raw_by_id(&self, id: u64) -> Option<&[u8]> {
+        if !self.may_contain_id(id) {
+            return None;
+        }
+
+        // Only the last item appended has no length: it goes until
+        // the end of the data as mapped
+        self.id_index
+            .get(&id)
+            .map(|&offset| match self.id_lens.get(&id) {
+                Some(&len) => &self.data[offset..offset + len],
+                None => &self.data[offset..],
             })
+    }
+
+    /// Looks up every given id in one go, decoding each distinct item
+    /// only once. Ids that aren't in the database are left out
+    pub fn find_many<I>(&'a self, ids: I) -> Result<HashMap<u64, T>>
+    where
+        I: IntoIterator<Item = u64>,
+    {
+        let mut ids: Vec<_> = ids.into_iter().collect();
+        ids.sort_unstable();
+        ids.dedup();
+
+        let found = self.find_each(&ids)?;
+        Ok(ids
+            .into_iter()
+            .zip(found)
+            .filter_map(|(id, item)| item.map(|item| (id, item)))
+            .collect())
+    }
+
+    /// Looks up every given id, yielding the items in the same order
+    /// (None for ids that aren't in the database).
+    ///
+    /// Items are read in the order they're laid out in the data, so
+    /// fetching a page of results from a cold cache reads the disk
+    /// mostly sequentially instead of jumping all over it
+    pub fn find_each(&'a self, ids: &[u64]) -> Result<Vec<Option<T>>> {
+        let mut located: Vec<(usize, usize)> = ids
+            .iter()
+            .enumerate()
+            .filter(|(_pos, id)| self.may_contain_id(**id))
+            .filter_map(|(pos, id)| self.id_index.get(id).map(|offset| (*offset, pos)))
+            .collect();
+        located.sort_unstable();
+
+        let mut found: Vec<Option<T>> = ids.iter().map(|_| None).collect();
+        for (offset, pos) in located {
+            found[pos] = Some(self.decode(offset)?);
+        }
+        Ok(found)
+    }
+
+    fn decode(&'a self, offset: usize) -> Result<T> {
+        C::decode(&self.data[offset..]).map_err(|err| match err {
+            Error::Serialization(reason) => Error::Corruption {
+                offset: offset as u64,
+                reason,
+            },
+            other => other,
         })
     }
 
@@ -75,12 +222,151 @@ impl<'a, T: Deserialize<'a>> DatabaseReader<T> {
     pub fn id_for_uuid(&self, uuid: &Uuid) -> Option<&u64> {
         self.uuid_index.get(uuid)
     }
+
+    /// Looks an item up by its `DatabaseRecord::get_key`. Keys show
+    /// up once the writer flushes
+    pub fn find_by_key(&'a self, key: &str) -> Option<Result<T>> {
+        self.id_for_key(key).and_then(|id| self.find_by_id(id))
+    }
+
+    pub fn id_for_key(&self, key: &str) -> Option<u64> {
+        self.keys.get(key)
+    }
+
+    /// Hints that the data is about to be read front to back (say:
+    /// when reindexing), so the kernel reads ahead aggressively and
+    /// drops pages soon after they're used
+    pub fn advise_sequential(&self) -> Result<()> {
+        Ok(mapping::advise(&self.data, mapping::Advice::Sequential)?)
+    }
+
+    /// Hints that the data is read in no particular order (the case
+    /// when serving searches), so the kernel doesn't waste the page
+    /// cache reading ahead
+    pub fn advise_random(&self) -> Result<()> {
+        Ok(mapping::advise(&self.data, mapping::Advice::Random)?)
+    }
+
+    /// Keeps the whole data in memory, so that lookups never wait on
+    /// the disk. Subject to the process' `RLIMIT_MEMLOCK`; the pages
+    /// are unlocked when the reader is dropped
+    pub fn lock_in_memory(&self) -> Result<()> {
+        Ok(mapping::lock(&self.data)?)
+    }
+
+    /// Figures about the database as of when the reader was opened,
+    /// except for the file sizes, which are as they are now. Reads
+    /// the whole offsets log, so it's not for every request
+    pub fn stats(&self) -> Result<DatabaseStats> {
+        let log = StructuredLog::<LogEntry>::new(self.base_dir.join(OFFSETS_FILE))?;
+
+        // Items are laid out in the order they were appended, so
+        // each one goes until where the next one starts
+        let data_len = self.data.len() as u64;
+        let mut live_bytes = 0;
+        let mut previous: Option<(u64, u64)> = None;
+        let mut num_seen = 0;
+        let mut add = |(id, offset): (u64, u64), end: u64| {
+            if self.id_index.get(&id) == Some(&(offset as usize)) {
+                live_bytes += end.saturating_sub(offset);
+            }
+        };
+
+        log.for_each_entry(|entry: &LogEntry| {
+            if num_seen < self.num_entries {
+                let current = (entry.id.get(), entry.offset.get());
+                if let Some(previous) = previous {
+                    add(previous, current.1);
+                }
+                previous = Some(current);
+                num_seen += 1;
+            }
+        })?;
+        if let Some(last) = previous {
+            add(last, data_len);
+        }
+
+        let mut file_sizes = BTreeMap::new();
+        for entry in fs::read_dir(&self.base_dir)? {
+            let entry = entry?;
+            let metadata = entry.metadata()?;
+            if metadata.is_file() {
+                file_sizes.insert(
+                    entry.file_name().to_string_lossy().into_owned(),
+                    metadata.len(),
+                );
+            }
+        }
+
+        let dead_bytes = data_len.saturating_sub(live_bytes);
+        Ok(DatabaseStats {
+            num_records: self.id_index.len(),
+            num_entries: self.num_entries,
+            data_bytes: data_len,
+            dead_bytes,
+            dead_ratio: if data_len == 0 {
+                0.0
+            } else {
+                dead_bytes as f64 / data_len as f64
+            },
+            file_sizes,
+        })
+    }
+}
+
+/// Figures about a database. See `DatabaseReader::stats`
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct DatabaseStats {
+    /// How many items can be looked up: one per id
+    pub num_records: usize,
+    /// How many items were appended, replaced versions included
+    pub num_entries: usize,
+    /// The size of the data, in bytes
+    pub data_bytes: u64,
+    /// How much of the data is taken by versions that were replaced
+    pub dead_bytes: u64,
+    /// `dead_bytes` over `data_bytes`. Zero for empty databases
+    pub dead_ratio: f64,
+    /// The size of every file in the database directory, by name
+    pub file_sizes: BTreeMap<String, u64>,
 }
 
-pub struct DatabaseWriter<T> {
+impl<T, C> DatabaseReader<T, C>
+where
+    T: DeserializeOwned + Send + Sync + 'static,
+    C: Codec + Send + Sync + 'static,
+{
+    /// Like `find_by_id`, run on `pool` for async callers
+    pub fn get_async(self: &Arc<Self>, pool: &BlockingPool, id: u64) -> Blocking<Option<T>> {
+        let reader = self.clone();
+        pool.run(move || reader.find_by_id(id).transpose())
+    }
+}
+
+/// Appends items to a database
+///
+/// Keeps a bloom filter of every id written next to the data so that
+/// readers can reject missing ids quickly. The filter is persisted
+/// when the writer is dropped.
+///
+/// Records with a key get it indexed too, persisted whenever the
+/// writer flushes. Unlike the data, the key index is rewritten as a
+/// whole, so `Checkpoint`s and `Chunk`s carry the keys of the items
+/// they cover, as indexed when copying or reading them.
+///
+/// Appends go through a buffered file, not a mapping, so growing the
+/// database never remaps anything: bulk loads only pay for the
+/// buffer flushes.
+pub struct DatabaseWriter<T, C = Bincode> {
     log: StructuredLog<LogEntry>,
     writer: BufWriter<File>,
-    _marker: PhantomData<T>,
+    bloom: BloomFilter,
+    bloom_path: PathBuf,
+    keys: BTreeMap<String, u64>,
+    key_of: HashMap<u64, String>,
+    keys_path: PathBuf,
+    keys_changed: bool,
+    _marker: PhantomData<(T, C)>,
 }
 
 impl<T> DatabaseWriter<T>
@@ -88,27 +374,472 @@ where
     T: DatabaseRecord + Serialize,
 {
     pub fn new<P: AsRef<Path>>(base_dir: P) -> Result<Self> {
+        Self::new_with_codec(base_dir)
+    }
+
+    /// Opens an existing database for appending. Appending an item
+    /// with an id that already exists replaces it: readers always
+    /// pick the latest version.
+    pub fn open<P: AsRef<Path>>(base_dir: P) -> Result<Self> {
+        Self::open_with_codec(base_dir)
+    }
+}
+
+impl<T, C> DatabaseWriter<T, C>
+where
+    T: DatabaseRecord + Serialize,
+    C: Codec,
+{
+    /// Creates a database whose items are encoded with `C`, which
+    /// gets recorded so that opening it with another codec fails
+    pub fn new_with_codec<P: AsRef<Path>>(base_dir: P) -> Result<Self> {
+        codec::record::<C, _>(&base_dir)?;
+
         Ok(Self {
             writer: BufWriter::new(File::create(base_dir.as_ref().join(DATA_FILE))?),
             log: StructuredLog::new(base_dir.as_ref().join(OFFSETS_FILE))?,
+            bloom: BloomFilter::with_capacity(DEFAULT_BLOOM_CAPACITY),
+            bloom_path: base_dir.as_ref().join(BLOOM_FILE),
+            keys: BTreeMap::new(),
+            key_of: HashMap::new(),
+            keys_path: base_dir.as_ref().join(KEYS_FILE),
+            // Clears whatever a previous database left behind
+            keys_changed: true,
+            _marker: PhantomData,
+        })
+    }
+
+    /// Like `open`, for a database created with the codec `C`
+    pub fn open_with_codec<P: AsRef<Path>>(base_dir: P) -> Result<Self> {
+        codec::check::<C, _>(&base_dir)?;
+
+        let mut datafile = OpenOptions::new()
+            .append(true)
+            .open(base_dir.as_ref().join(DATA_FILE))?;
+        // Appending doesn't move the cursor until the first write
+        datafile.seek(SeekFrom::End(0))?;
+
+        let log = StructuredLog::new(base_dir.as_ref().join(OFFSETS_FILE))?;
+        let bloom_path = base_dir.as_ref().join(BLOOM_FILE);
+
+        let bloom = match BloomFilter::load_fresh(&bloom_path, log.len()?)? {
+            Some(bloom) if !bloom.is_overfull() => bloom,
+            // Missing, stale or too small: rebuild with room to grow
+            _ => {
+                let num_entries = log.len()?;
+                let mut bloom =
+                    BloomFilter::with_capacity(DEFAULT_BLOOM_CAPACITY.max(num_entries * 2));
+                log.for_each_entry(|entry: &LogEntry| bloom.insert(entry.id.get()))?;
+                bloom
+            }
+        };
+
+        let keys_path = base_dir.as_ref().join(KEYS_FILE);
+        let keys: BTreeMap<_, _> = KeyIndex::load(&keys_path)?.entries()?.into_iter().collect();
+        let key_of = keys.iter().map(|(key, id)| (*id, key.clone())).collect();
+
+        Ok(Self {
+            writer: BufWriter::new(datafile),
+            log,
+            bloom,
+            bloom_path,
+            keys,
+            key_of,
+            keys_path,
+            keys_changed: false,
             _marker: PhantomData,
         })
     }
 
     pub fn append(&mut self, item: &T) -> Result<()> {
-        let encoded = bincode::serialize(item)
-            .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "Failure encoding input"))?;
+        let encoded = C::encode(item)?;
         let offset = self.writer.seek(SeekFrom::Current(0))?;
         self.writer.write_all(&encoded)?;
+        metrics::appended(encoded.len());
 
         let entry = LogEntry::new(item.get_id(), item.get_uuid(), offset);
         self.log.append(&entry)?;
+        self.bloom.insert(item.get_id());
+        self.index_key(item.get_id(), item.get_key());
+        Ok(())
+    }
+
+    /// Makes sure every appended item (and its key) is on disk
+    pub fn flush(&mut self) -> Result<()> {
+        self.writer.flush()?;
+        self.writer.get_ref().sync_data()?;
+        // After the data, so that no offset points past what's synced
+        self.log.sync()?;
+        self.save_keys()
+    }
+
+    // Replacing an item replaces its key too: a key always points to
+    // the latest item that had it
+    fn index_key(&mut self, id: u64, key: Option<&str>) {
+        if self.key_of.get(&id).map(String::as_str) == key {
+            return;
+        }
+        self.keys_changed = true;
+
+        if let Some(previous) = self.key_of.remove(&id) {
+            self.keys.remove(&previous);
+        }
+
+        if let Some(key) = key {
+            if let Some(previous_id) = self.keys.insert(key.to_owned(), id) {
+                self.key_of.remove(&previous_id);
+            }
+            self.key_of.insert(id, key.to_owned());
+        }
+    }
+
+    /// Marks the current state of the database, so that it can be
+    /// copied later on. Fails if not every appended item is flushed
+    pub fn checkpoint(&self) -> Result<Checkpoint> {
+        if !self.writer.buffer().is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::Other,
+                "Cannot checkpoint with unflushed items",
+            )
+            .into());
+        }
+
+        Ok(Checkpoint {
+            offsets_len: (self.log.len()? * size_of::<LogEntry>()) as u64,
+            data_len: self.writer.get_ref().metadata()?.len(),
+        })
+    }
+}
+
+/// How large the files of a database were at some point
+///
+/// Databases only ever grow, so copying this much of each file
+/// yields the database as it was at that point, even if the writer
+/// kept appending items since.
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq)]
+pub struct Checkpoint {
+    offsets_len: u64,
+    data_len: u64,
+}
+
+impl Checkpoint {
+    /// The checkpoint of the database at `base_dir` as found on disk.
+    /// Only meaningful when nothing is writing to it. Zero-sized for
+    /// missing files
+    pub fn of<P: AsRef<Path>>(base_dir: P) -> Result<Self> {
+        let len_of = |name| match base_dir.as_ref().join(name).metadata() {
+            Ok(metadata) => Ok(metadata.len()),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(0),
+            Err(err) => Err(err),
+        };
+
+        let entry_len = size_of::<LogEntry>() as u64;
+        Ok(Self {
+            offsets_len: len_of(OFFSETS_FILE)? / entry_len * entry_len,
+            data_len: len_of(DATA_FILE)?,
+        })
+    }
+
+    /// How many items (new ones and new versions alike) were
+    /// appended up to this checkpoint
+    pub fn num_entries(&self) -> u64 {
+        self.offsets_len / size_of::<LogEntry>() as u64
+    }
+
+    /// How large the data file was, in bytes
+    pub fn data_len(&self) -> u64 {
+        self.data_len
+    }
+
+    /// Reads what was appended to the database at `base_dir` after
+    /// `since` and up to this checkpoint
+    pub fn read_since<P: AsRef<Path>>(&self, base_dir: P, since: &Checkpoint) -> Result<Chunk> {
+        if since.offsets_len > self.offsets_len || since.data_len > self.data_len {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Cannot read from a checkpoint ahead of this one",
+            )
+            .into());
+        }
+
+        let read_range = |name, start: u64, end: u64| -> Result<Vec<u8>> {
+            let mut file = File::open(base_dir.as_ref().join(name))?;
+            file.seek(SeekFrom::Start(start))?;
+
+            let mut bytes = vec![0; (end - start) as usize];
+            file.read_exact(&mut bytes)?;
+            Ok(bytes)
+        };
+
+        let offsets = read_range(OFFSETS_FILE, since.offsets_len, self.offsets_len)?;
+        Ok(Chunk {
+            since: *since,
+            keys: keys_of(&base_dir, &ids_in(&offsets))?,
+            offsets,
+            data: read_range(DATA_FILE, since.data_len, self.data_len)?,
+        })
+    }
+
+    /// Copies the database at `base_dir`, as of this checkpoint, to
+    /// the existing directory `dest_dir`
+    pub fn copy_to<P: AsRef<Path>, Q: AsRef<Path>>(&self, base_dir: P, dest_dir: Q) -> Result<()> {
+        for &(name, len) in &[(OFFSETS_FILE, self.offsets_len), (DATA_FILE, self.data_len)] {
+            let mut src = File::open(base_dir.as_ref().join(name))?.take(len);
+            let mut dest = File::create(dest_dir.as_ref().join(name))?;
+
+            if io::copy(&mut src, &mut dest)? != len {
+                return Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    format!("{} is smaller than at the checkpoint", name),
+                )
+                .into());
+            }
+
+            dest.sync_all()?;
+        }
+
+        let mut ids = HashSet::new();
+        StructuredLog::<LogEntry>::new(dest_dir.as_ref().join(OFFSETS_FILE))?.for_each_entry(
+            |entry| {
+                ids.insert(entry.id.get());
+            },
+        )?;
+        KeyIndex::save(
+            dest_dir.as_ref().join(KEYS_FILE),
+            &keys_of(&base_dir, &ids)?
+                .into_iter()
+                .collect::<BTreeMap<_, _>>(),
+        )?;
+
+        Ok(codec::copy(base_dir, dest_dir)?)
+    }
+}
+
+/// What gets appended to a database between two checkpoints. See
+/// `Checkpoint::read_since`
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Chunk {
+    since: Checkpoint,
+    offsets: Vec<u8>,
+    data: Vec<u8>,
+    /// The keys of the items in the chunk
+    #[serde(default)]
+    keys: Vec<(String, u64)>,
+}
+
+impl Chunk {
+    /// Where the database will be after appending this chunk
+    pub fn until(&self) -> Checkpoint {
+        Checkpoint {
+            offsets_len: self.since.offsets_len + self.offsets.len() as u64,
+            data_len: self.since.data_len + self.data.len() as u64,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.offsets.is_empty() && self.data.is_empty()
+    }
+
+    /// Appends the chunk to the database at `base_dir`, which must be
+    /// exactly where the chunk starts from. Items are written before
+    /// their offsets, so readers never find offsets without data, and
+    /// their keys replace whatever keys the items had before
+    pub fn append_to<P: AsRef<Path>>(&self, base_dir: P) -> Result<()> {
+        if Checkpoint::of(&base_dir)? != self.since {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "The database is not where the chunk starts from",
+            )
+            .into());
+        }
+
+        for &(name, bytes) in &[(DATA_FILE, &self.data), (OFFSETS_FILE, &self.offsets)] {
+            let mut file = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(base_dir.as_ref().join(name))?;
+            file.write_all(bytes)?;
+            file.sync_data()?;
+        }
+
+        let ids = ids_in(&self.offsets);
+        let keys_path = base_dir.as_ref().join(KEYS_FILE);
+        let mut keys: BTreeMap<_, _> = KeyIndex::load(&keys_path)?
+            .entries()?
+            .into_iter()
+            .filter(|(_key, id)| !ids.contains(id))
+            .collect();
+        keys.extend(self.keys.iter().cloned());
+        KeyIndex::save(&keys_path, &keys)?;
+
         Ok(())
     }
 }
 
+// The ids of the entries in `offsets`, a part of the offsets log
+fn ids_in(offsets: &[u8]) -> HashSet<u64> {
+    LayoutVerified::<_, [LogEntry]>::new_slice(offsets).map_or_else(HashSet::new, |entries| {
+        entries.iter().map(|entry| entry.id.get()).collect()
+    })
+}
+
+// The keys the database at `base_dir` has indexed for `ids`
+fn keys_of<P: AsRef<Path>>(base_dir: P, ids: &HashSet<u64>) -> Result<Vec<(String, u64)>> {
+    Ok(KeyIndex::load(base_dir.as_ref().join(KEYS_FILE))?
+        .entries()?
+        .into_iter()
+        .filter(|(_key, id)| ids.contains(id))
+        .collect())
+}
+
+impl<T, C> DatabaseWriter<T, C> {
+    fn save_keys(&mut self) -> Result<()> {
+        if self.keys_changed {
+            KeyIndex::save(&self.keys_path, &self.keys)?;
+            self.keys_changed = false;
+        }
+        Ok(())
+    }
+}
+
+impl<T, C> Drop for DatabaseWriter<T, C> {
+    fn drop(&mut self) {
+        if let Err(err) = self.save_keys() {
+            log::warn!("Failed to persist the key index: {}", err);
+        }
+
+        // Readers ignore a filter that doesn't match the log, so
+        // failing here only costs them a rebuild
+        if let Err(err) = self.bloom.save(&self.bloom_path) {
+            log::warn!("Failed to persist the id bloom filter: {}", err);
+        }
+    }
+}
+
+#[cfg(unix)]
+mod mapping {
+    use std::io::{Error, Result};
+
+    pub enum Advice {
+        Sequential,
+        Random,
+    }
+
+    pub fn advise(data: &[u8], advice: Advice) -> Result<()> {
+        if data.is_empty() {
+            return Ok(());
+        }
+
+        let advice = match advice {
+            Advice::Sequential => libc::MADV_SEQUENTIAL,
+            Advice::Random => libc::MADV_RANDOM,
+        };
+        // The mapping starts at a page boundary, as madvise requires
+        let ret = unsafe { libc::madvise(data.as_ptr() as *mut libc::c_void, data.len(), advice) };
+        if ret == 0 {
+            Ok(())
+        } else {
+            Err(Error::last_os_error())
+        }
+    }
+
+    pub fn lock(data: &[u8]) -> Result<()> {
+        if data.is_empty() {
+            return Ok(());
+        }
+
+        let ret = unsafe { libc::mlock(data.as_ptr() as *const libc::c_void, data.len()) };
+        if ret == 0 {
+            Ok(())
+        } else {
+            Err(Error::last_os_error())
+        }
+    }
+}
+
+// Hints are just hints: elsewhere they do nothing
+#[cfg(not(unix))]
+mod mapping {
+    use std::io::{Error, ErrorKind, Result};
+
+    pub enum Advice {
+        Sequential,
+        Random,
+    }
+
+    pub fn advise(_data: &[u8], _advice: Advice) -> Result<()> {
+        Ok(())
+    }
+
+    pub fn lock(_data: &[u8]) -> Result<()> {
+        Err(Error::new(
+            ErrorKind::Other,
+            "Locking in memory is not supported on this platform",
+        ))
+    }
+}
+
+// Builds the lookups from the last checkpoint on, if usable. Yields
+// how many entries had to be replayed
+fn replay(
+    log: &StructuredLog<LogEntry>,
+    checkpoint_path: &Path,
+    num_items: usize,
+) -> Result<(IndexCheckpoint, usize)> {
+    if let Some(mut checkpoint) = IndexCheckpoint::load(checkpoint_path, num_items) {
+        let covered = checkpoint.num_entries;
+        let expected = checkpoint.last_entry;
+        let mut valid = None;
+
+        // Starts at the last covered entry, which must be the same
+        log.for_each_entry_from(covered - 1, |entry: &LogEntry| match valid {
+            None => valid = Some(expected == Some((entry.id.get(), entry.offset.get()))),
+            Some(true) => checkpoint.add(
+                entry.id.get(),
+                Uuid::from_bytes(entry.uuid),
+                entry.offset.get(),
+            ),
+            Some(false) => {}
+        })?;
+
+        if valid == Some(true) {
+            let num_replayed = checkpoint.num_entries - covered;
+            return Ok((checkpoint, num_replayed));
+        }
+        log::warn!(
+            "Index checkpoint {:?} doesn't match the log. Replaying all of it",
+            checkpoint_path
+        );
+    }
+
+    let mut checkpoint = IndexCheckpoint {
+        id_index: HashMap::with_capacity(num_items),
+        uuid_index: HashMap::with_capacity(num_items),
+        id_lens: HashMap::with_capacity(num_items),
+        ..IndexCheckpoint::default()
+    };
+    log.for_each_entry(|entry: &LogEntry| {
+        checkpoint.add(
+            entry.id.get(),
+            Uuid::from_bytes(entry.uuid),
+            entry.offset.get(),
+        )
+    })?;
+
+    let num_replayed = checkpoint.num_entries;
+    Ok((checkpoint, num_replayed))
+}
+
 const OFFSETS_FILE: &str = "offsets.bin";
 const DATA_FILE: &str = "data.bin";
+const BLOOM_FILE: &str = "ids.bloom";
+const CHECKPOINT_FILE: &str = "ids.checkpoint";
+const KEYS_FILE: &str = "keys.bin";
+
+/// How many log entries a reader replays before saving a checkpoint
+const CHECKPOINT_EVERY: usize = 100_000;
+
+const DEFAULT_BLOOM_CAPACITY: usize = 1_000_000;
 
 #[derive(FromBytes, AsBytes)]
 #[repr(C)]
@@ -132,8 +863,11 @@ impl LogEntry {
 mod tests {
 
     use super::*;
+    use std::fs;
     use tempfile;
 
+    use crate::database::Json;
+
     use serde::Deserialize;
 
     #[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
@@ -186,4 +920,433 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn stats_tell_replaced_versions_apart() -> Result<()> {
+        let basedir = tempfile::tempdir()?;
+
+        let mut db_writer = DatabaseWriter::new(basedir.path())?;
+        db_writer.append(&Named(0, Uuid::new_v4(), "aaaa"))?;
+        db_writer.append(&Named(1, Uuid::new_v4(), "bbbb"))?;
+        drop(db_writer);
+
+        let stats = DatabaseReader::<Named>::open(basedir.path())?.stats()?;
+        assert_eq!(2, stats.num_records);
+        assert_eq!(2, stats.num_entries);
+        assert_eq!(0, stats.dead_bytes);
+        assert_eq!(0.0, stats.dead_ratio);
+        assert_eq!(Some(&stats.data_bytes), stats.file_sizes.get(DATA_FILE));
+        assert!(stats.file_sizes.contains_key(OFFSETS_FILE));
+
+        // Same size as the version it replaces
+        let mut db_writer = DatabaseWriter::open(basedir.path())?;
+        db_writer.append(&Named(0, Uuid::new_v4(), "cccc"))?;
+        drop(db_writer);
+
+        let stats = DatabaseReader::<Named>::open(basedir.path())?.stats()?;
+        assert_eq!(2, stats.num_records);
+        assert_eq!(3, stats.num_entries);
+        assert_eq!(stats.data_bytes / 3, stats.dead_bytes);
+        assert!((stats.dead_ratio - 1.0 / 3.0).abs() < 1e-9);
+
+        Ok(())
+    }
+
+    #[test]
+    fn checkpoints_only_need_the_tail_replayed() -> Result<()> {
+        let basedir = tempfile::tempdir()?;
+        let checkpoint_path = basedir.path().join(CHECKPOINT_FILE);
+
+        let mut db_writer = DatabaseWriter::new(basedir.path())?;
+        db_writer.append(&Named(0, Uuid::new_v4(), "a"))?;
+        db_writer.append(&Named(1, Uuid::new_v4(), "b"))?;
+        db_writer.flush()?;
+
+        let log = StructuredLog::new(basedir.path().join(OFFSETS_FILE))?;
+        let (checkpoint, num_replayed) = replay(&log, &checkpoint_path, 2)?;
+        assert_eq!(2, num_replayed);
+        checkpoint.save(&checkpoint_path)?;
+
+        let updated = Named(0, Uuid::new_v4(), "c");
+        db_writer.append(&updated)?;
+        db_writer.flush()?;
+
+        let (checkpoint, num_replayed) = replay(&log, &checkpoint_path, 3)?;
+        assert_eq!(1, num_replayed);
+        assert_eq!(3, checkpoint.num_entries);
+
+        let db_reader = DatabaseReader::<Named>::open(basedir.path())?;
+        assert_eq!(Some(updated.clone()), db_reader.find_by_id(0).transpose()?);
+        assert_eq!(
+            Some(updated.clone()),
+            db_reader.find_by_uuid(&updated.1).transpose()?
+        );
+        assert_eq!(2, db_reader.ids().count());
+
+        // A checkpoint of some other log is ignored
+        IndexCheckpoint {
+            num_entries: 1,
+            last_entry: Some((42, 0)),
+            ..IndexCheckpoint::default()
+        }
+        .save(&checkpoint_path)?;
+        let (checkpoint, num_replayed) = replay(&log, &checkpoint_path, 3)?;
+        assert_eq!(3, num_replayed);
+        assert_eq!(2, checkpoint.id_index.len());
+
+        Ok(())
+    }
+
+    #[test]
+    fn access_hints_keep_reads_working() -> Result<()> {
+        let basedir = tempfile::tempdir()?;
+
+        // Hints on an empty database are fine too
+        drop(DatabaseWriter::<Named>::new(basedir.path())?);
+        let db_reader = DatabaseReader::<Named>::open(basedir.path())?;
+        db_reader.advise_sequential()?;
+        db_reader.advise_random()?;
+
+        let mut db_writer = DatabaseWriter::new(basedir.path())?;
+        let uuid = Uuid::new_v4();
+        db_writer.append(&Named(42, uuid, "item"))?;
+        drop(db_writer);
+
+        let db_reader = DatabaseReader::<Named>::open(basedir.path())?;
+        db_reader.advise_sequential()?;
+        db_reader.advise_random()?;
+        assert_eq!(
+            Some(Named(42, uuid, "item")),
+            db_reader.find_by_id(42).transpose()?
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn find_many_skips_missing_and_repeated_ids() -> Result<()> {
+        let basedir = tempfile::tempdir()?;
+
+        let mut db_writer = DatabaseWriter::new(basedir.path())?;
+        for id in 0..5 {
+            db_writer.append(&Named(id, Uuid::new_v4(), "item"))?;
+        }
+        drop(db_writer);
+
+        let db_reader = DatabaseReader::<Named>::open(basedir.path())?;
+        let found = db_reader.find_many(vec![3, 1, 3, 42])?;
+
+        assert_eq!(2, found.len());
+        assert_eq!(Some(3), found.get(&3).map(|item| item.0));
+        assert_eq!(Some(1), found.get(&1).map(|item| item.0));
+
+        Ok(())
+    }
+
+    #[test]
+    fn find_each_keeps_the_order_of_ids() -> Result<()> {
+        let basedir = tempfile::tempdir()?;
+
+        let mut db_writer = DatabaseWriter::new(basedir.path())?;
+        for id in 0..5 {
+            db_writer.append(&Named(id, Uuid::new_v4(), "item"))?;
+        }
+        // Replaced, so it's now the last item in the data
+        db_writer.append(&Named(0, Uuid::new_v4(), "replaced"))?;
+        drop(db_writer);
+
+        let db_reader = DatabaseReader::<Named>::open(basedir.path())?;
+        let found = db_reader.find_each(&[4, 42, 0, 2, 4])?;
+
+        assert_eq!(
+            vec![Some(4), None, Some(0), Some(2), Some(4)],
+            found
+                .iter()
+                .map(|item| item.as_ref().map(|item| item.0))
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(Some("replaced"), found[2].as_ref().map(|item| item.2));
+        assert!(db_reader.find_each(&[])?.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn borrowed_items_point_into_the_data() -> Result<()> {
+        let basedir = tempfile::tempdir()?;
+
+        let one = Named(1, Uuid::new_v4(), "one");
+        let zero = Named(0, Uuid::new_v4(), "zero again");
+        let mut db_writer = DatabaseWriter::new(basedir.path())?;
+        db_writer.append(&Named(0, Uuid::new_v4(), "zero"))?;
+        db_writer.append(&one)?;
+        db_writer.append(&zero)?;
+        drop(db_writer);
+
+        let db_reader = DatabaseReader::<Named>::open(basedir.path())?;
+        let item = db_reader.find_by_id(1).transpose()?.expect("item exists");
+        let raw = db_reader.find_raw_by_id(1).expect("item exists");
+
+        // The name wasn't copied out of the mapping
+        let name = item.2.as_ptr() as usize;
+        let start = raw.as_ptr() as usize;
+        assert!(name > start && name < start + raw.len());
+
+        // Decoding just a prefix of the item
+        let (id, _uuid): (u64, Uuid) = bincode::deserialize(raw).expect("valid prefix");
+        assert_eq!(1, id);
+
+        // Exactly the item, be it followed by another one or not
+        assert_eq!(Bincode::encode(&one)?, raw);
+        assert_eq!(
+            Bincode::encode(&zero)?,
+            db_reader.find_raw_by_id(0).expect("item exists")
+        );
+
+        assert_eq!(None, db_reader.find_raw_by_id(42));
+
+        Ok(())
+    }
+
+    #[test]
+    fn appending_replaces_existing() -> Result<()> {
+        let basedir = tempfile::tempdir()?;
+
+        let original = Named(0, Uuid::new_v4(), "original");
+        let unchanged = Named(1, Uuid::new_v4(), "unchanged");
+
+        let mut db_writer = DatabaseWriter::new(basedir.path())?;
+        db_writer.append(&original)?;
+        db_writer.append(&unchanged)?;
+        drop(db_writer);
+
+        let updated = Named(0, original.1, "updated");
+
+        let mut db_writer = DatabaseWriter::open(basedir.path())?;
+        db_writer.append(&updated)?;
+        drop(db_writer);
+
+        let db_reader = DatabaseReader::open(&basedir)?;
+
+        assert_eq!(2, db_reader.ids().count());
+        assert_eq!(Some(updated), db_reader.find_by_id(0).transpose()?);
+        assert_eq!(Some(unchanged), db_reader.find_by_id(1).transpose()?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn bloom_filter_is_persisted_and_kept_fresh() -> Result<()> {
+        let basedir = tempfile::tempdir()?;
+        let bloom_path = basedir.path().join(BLOOM_FILE);
+
+        let mut db_writer = DatabaseWriter::new(basedir.path())?;
+        for id in 0..10 {
+            db_writer.append(&Named(id, Uuid::new_v4(), "item"))?;
+        }
+        drop(db_writer);
+
+        assert!(BloomFilter::load_fresh(&bloom_path, 10)?.is_some());
+
+        let db_reader = DatabaseReader::<Named>::open(basedir.path())?;
+        assert!((0..10).all(|id| db_reader.may_contain_id(id)));
+        assert_eq!(None, db_reader.find_by_id(4242).transpose()?);
+
+        // Losing the filter is harmless
+        fs::remove_file(&bloom_path)?;
+        let mut db_writer = DatabaseWriter::open(basedir.path())?;
+        db_writer.append(&Named(10, Uuid::new_v4(), "new"))?;
+        drop(db_writer);
+
+        assert!(BloomFilter::load_fresh(&bloom_path, 11)?.is_some());
+
+        let db_reader = DatabaseReader::<Named>::open(basedir.path())?;
+        for id in 0..=10 {
+            assert!(db_reader.find_by_id(id).is_some());
+        }
+
+        Ok(())
+    }
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+    struct Slugged(u64, Uuid, Option<String>);
+
+    impl DatabaseRecord for Slugged {
+        fn get_id(&self) -> u64 {
+            self.0
+        }
+
+        fn get_uuid(&self) -> uuid::Bytes {
+            *self.1.as_bytes()
+        }
+
+        fn get_key(&self) -> Option<&str> {
+            self.2.as_deref()
+        }
+    }
+
+    #[test]
+    fn items_can_be_found_by_key() -> Result<()> {
+        let basedir = tempfile::tempdir()?;
+        let slugged = |id, slug: Option<&str>| Slugged(id, Uuid::new_v4(), slug.map(String::from));
+
+        let mut db_writer = DatabaseWriter::new(basedir.path())?;
+        let pasta = slugged(0, Some("pasta"));
+        db_writer.append(&pasta)?;
+        db_writer.append(&slugged(1, Some("soup")))?;
+        db_writer.append(&slugged(2, None))?;
+        db_writer.flush()?;
+
+        let db_reader = DatabaseReader::<Slugged>::open(basedir.path())?;
+        assert_eq!(Some(pasta), db_reader.find_by_key("pasta").transpose()?);
+        assert_eq!(Some(1), db_reader.id_for_key("soup"));
+        assert_eq!(None, db_reader.id_for_key("salad"));
+
+        // Replacing items replaces their keys
+        let mut db_writer = DatabaseWriter::open(basedir.path())?;
+        db_writer.append(&slugged(0, Some("spaghetti")))?;
+        db_writer.append(&slugged(1, None))?;
+        db_writer.append(&slugged(2, Some("pasta")))?;
+        drop(db_writer);
+
+        let db_reader = DatabaseReader::<Slugged>::open(basedir.path())?;
+        assert_eq!(Some(0), db_reader.id_for_key("spaghetti"));
+        assert_eq!(Some(2), db_reader.id_for_key("pasta"));
+        assert_eq!(None, db_reader.id_for_key("soup"));
+
+        // A new database starts without keys
+        drop(DatabaseWriter::<Slugged>::new(basedir.path())?);
+        let db_reader = DatabaseReader::<Slugged>::open(basedir.path())?;
+        assert_eq!(None, db_reader.id_for_key("pasta"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn checkpoints_copy_a_consistent_prefix() -> Result<()> {
+        let basedir = tempfile::tempdir()?;
+        let copydir = tempfile::tempdir()?;
+
+        let mut db_writer = DatabaseWriter::new(basedir.path())?;
+        db_writer.append(&Named(0, Uuid::new_v4(), "before"))?;
+        db_writer.append(&Named(1, Uuid::new_v4(), "before"))?;
+        assert!(db_writer.checkpoint().is_err());
+
+        db_writer.flush()?;
+        let checkpoint = db_writer.checkpoint()?;
+
+        db_writer.append(&Named(0, Uuid::new_v4(), "after"))?;
+        db_writer.append(&Named(2, Uuid::new_v4(), "after"))?;
+        db_writer.flush()?;
+
+        checkpoint.copy_to(basedir.path(), copydir.path())?;
+
+        let db_reader = DatabaseReader::<Named>::open(copydir.path())?;
+        assert_eq!(2, db_reader.ids().count());
+        assert_eq!(
+            Some("before"),
+            db_reader.find_by_id(0).transpose()?.map(|item| item.2)
+        );
+        assert_eq!(None, db_reader.find_by_id(2).transpose()?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn copies_and_chunks_carry_keys() -> Result<()> {
+        let basedir = tempfile::tempdir()?;
+        let copydir = tempfile::tempdir()?;
+        let slugged = |id, slug: &str| Slugged(id, Uuid::new_v4(), Some(slug.to_owned()));
+
+        let mut db_writer = DatabaseWriter::new(basedir.path())?;
+        db_writer.append(&slugged(0, "pasta"))?;
+        db_writer.flush()?;
+        let checkpoint = db_writer.checkpoint()?;
+
+        db_writer.append(&slugged(1, "soup"))?;
+        db_writer.flush()?;
+
+        // Only the keys of what the checkpoint covers
+        checkpoint.copy_to(basedir.path(), copydir.path())?;
+        let db_reader = DatabaseReader::<Slugged>::open(copydir.path())?;
+        assert_eq!(Some(0), db_reader.id_for_key("pasta"));
+        assert_eq!(None, db_reader.id_for_key("soup"));
+
+        db_writer.append(&slugged(0, "spaghetti"))?;
+        db_writer.flush()?;
+
+        db_writer
+            .checkpoint()?
+            .read_since(basedir.path(), &checkpoint)?
+            .append_to(copydir.path())?;
+        let db_reader = DatabaseReader::<Slugged>::open(copydir.path())?;
+        assert_eq!(Some(0), db_reader.id_for_key("spaghetti"));
+        assert_eq!(Some(1), db_reader.id_for_key("soup"));
+        assert_eq!(None, db_reader.id_for_key("pasta"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn unreadable_items_are_reported_as_corruption() -> Result<()> {
+        let basedir = tempfile::tempdir()?;
+
+        let mut db_writer = DatabaseWriter::new(basedir.path())?;
+        db_writer.append(&Named(0, Uuid::new_v4(), "a"))?;
+        db_writer.append(&Named(1, Uuid::new_v4(), "b"))?;
+        drop(db_writer);
+
+        // Both items have the same size, the last byte being the
+        // name of the second one: makes it invalid utf-8
+        let data_path = basedir.path().join(DATA_FILE);
+        let mut data = fs::read(&data_path)?;
+        let second_offset = (data.len() / 2) as u64;
+        *data.last_mut().expect("not empty") = 0xff;
+        fs::write(&data_path, data)?;
+
+        let db_reader = DatabaseReader::<Named>::open(basedir.path())?;
+        assert_eq!(
+            Some("a"),
+            db_reader.find_by_id(0).transpose()?.map(|item| item.2)
+        );
+        match db_reader.find_by_id(1) {
+            Some(Err(Error::Corruption { offset, .. })) => assert_eq!(second_offset, offset),
+            other => panic!("Expected corruption, got {:?}", other),
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn databases_are_read_with_the_codec_they_were_created_with() -> Result<()> {
+        let basedir = tempfile::tempdir()?;
+        let copydir = tempfile::tempdir()?;
+
+        let mut db_writer = DatabaseWriter::<_, Json>::new_with_codec(basedir.path())?;
+        db_writer.append(&Named(0, Uuid::new_v4(), "a"))?;
+        db_writer.append(&Named(1, Uuid::new_v4(), "b"))?;
+        db_writer.flush()?;
+        let checkpoint = db_writer.checkpoint()?;
+        drop(db_writer);
+
+        let data = fs::read_to_string(basedir.path().join(DATA_FILE))?;
+        assert_eq!(2, data.lines().count());
+
+        assert!(DatabaseReader::<Named>::open(basedir.path()).is_err());
+        assert!(DatabaseWriter::<Named>::open(basedir.path()).is_err());
+
+        let db_reader = DatabaseReader::<Named, Json>::open_with_codec(basedir.path())?;
+        assert_eq!(
+            Some("b"),
+            db_reader.find_by_id(1).transpose()?.map(|item| item.2)
+        );
+
+        // Copies keep the codec
+        checkpoint.copy_to(basedir.path(), copydir.path())?;
+        assert!(DatabaseReader::<Named>::open(copydir.path()).is_err());
+        let db_reader = DatabaseReader::<Named, Json>::open_with_codec(copydir.path())?;
+        assert_eq!(2, db_reader.ids().count());
+
+        Ok(())
+    }
 }
@@ -1,6 +1,6 @@
 use std::{
     fs::{File, OpenOptions},
-    io::{self, BufRead, BufReader, Result, Write},
+    io::{self, BufRead, BufReader, Result, Seek, SeekFrom, Write},
     marker::PhantomData,
     mem::size_of,
     path::Path,
@@ -8,6 +8,12 @@ use std::{
 
 use zerocopy::{AsBytes, FromBytes, LayoutVerified};
 
+/// An append-only file of fixed-size records of type `T`
+///
+/// Records are read and written as they are laid out in memory (see
+/// `zerocopy`), so there's no framing nor manual parsing involved: a
+/// new kind of record (like a tombstone) is a new `T`, in a log of
+/// its own.
 pub(crate) struct StructuredLog<T> {
     file: File,
     _header: PhantomData<T>,
@@ -50,12 +56,26 @@ where
         Ok(self.file.metadata()?.len() as usize / size_of::<T>())
     }
 
-    pub fn for_each_entry<F>(&self, mut each_entry: F) -> std::io::Result<()>
+    pub fn for_each_entry<F>(&self, each_entry: F) -> std::io::Result<()>
+    where
+        F: FnMut(&T),
+    {
+        self.for_each_entry_from(0, each_entry)
+    }
+
+    /// Like `for_each_entry`, skipping the entries before `first`
+    pub fn for_each_entry_from<F>(&self, first: usize, mut each_entry: F) -> std::io::Result<()>
     where
         F: FnMut(&T),
     {
         let entry_len = size_of::<T>();
-        let mut log_reader = BufReader::with_capacity((8192 / entry_len) * entry_len, &self.file);
+
+        // Reads share the cursor with appends, which leave it at the
+        // end of the file
+        let mut file = &self.file;
+        file.seek(SeekFrom::Start((first * entry_len) as u64))?;
+
+        let mut log_reader = BufReader::with_capacity((8192 / entry_len) * entry_len, file);
 
         loop {
             let buf = log_reader.fill_buf()?;
@@ -84,6 +104,16 @@ where
     pub fn append(&mut self, item: &T) -> Result<()> {
         self.file.write_all(item.as_bytes())
     }
+
+    pub fn sync(&self) -> Result<()> {
+        self.file.sync_data()
+    }
+
+    /// Drops every entry
+    pub fn clear(&mut self) -> Result<()> {
+        self.file.set_len(0)?;
+        self.file.sync_data()
+    }
 }
 
 #[cfg(test)]
@@ -119,6 +149,23 @@ mod tests {
             wanted += 1;
         })?;
 
+        let mut log = log;
+
+        // Appending doesn't get in the way of reading again
+        log.append(&U64::<NativeEndian>::new(100))?;
+        let mut tail = Vec::new();
+        log.for_each_entry_from(98, |e: &U64<NativeEndian>| tail.push(e.get()))?;
+        assert_eq!(vec![98, 99, 100], tail);
+
+        let mut count = 0;
+        log.for_each_entry(|_e: &U64<NativeEndian>| count += 1)?;
+        assert_eq!(101, count);
+
+        log.clear()?;
+        assert_eq!(0, log.len()?);
+        log.append(&U64::<NativeEndian>::new(42))?;
+        assert_eq!(1, log.len()?);
+
         Ok(())
     }
 }
@@ -0,0 +1,202 @@
+use std::{
+    collections::hash_map::DefaultHasher,
+    collections::HashMap,
+    hash::{Hash, Hasher},
+};
+
+use crate::model::{Recipe, RecipeId};
+
+// How many consecutive words make a feature of the text
+const SHINGLE_SIZE: usize = 2;
+
+/// A 64-bit SimHash of the text of a recipe (its name, ingredients
+/// and instructions). Recipes with similar text have fingerprints
+/// that differ in few bits
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Fingerprint(pub u64);
+
+impl Fingerprint {
+    pub fn of(recipe: &Recipe) -> Self {
+        let words = std::iter::once(&recipe.name)
+            .chain(recipe.ingredients.iter())
+            .chain(recipe.instructions.iter())
+            .flat_map(|text| text.split(|c: char| !c.is_alphanumeric()))
+            .filter(|word| !word.is_empty())
+            .map(str::to_lowercase)
+            .collect::<Vec<_>>();
+
+        let mut weights = [0i64; 64];
+        let mut add = |feature: &[String]| {
+            let mut hasher = DefaultHasher::new();
+            feature.hash(&mut hasher);
+            let hash = hasher.finish();
+
+            for (bit, weight) in weights.iter_mut().enumerate() {
+                if hash & (1 << bit) == 0 {
+                    *weight -= 1;
+                } else {
+                    *weight += 1;
+                }
+            }
+        };
+
+        if words.len() < SHINGLE_SIZE {
+            add(&words);
+        } else {
+            for shingle in words.windows(SHINGLE_SIZE) {
+                add(shingle);
+            }
+        }
+
+        let mut fingerprint = 0u64;
+        for (bit, weight) in weights.iter().enumerate() {
+            if *weight > 0 {
+                fingerprint |= 1 << bit;
+            }
+        }
+
+        Fingerprint(fingerprint)
+    }
+
+    /// From 0 (every bit differs) to 1 (same fingerprint)
+    pub fn similarity(self, other: Self) -> f32 {
+        1.0 - (self.0 ^ other.0).count_ones() as f32 / 64.0
+    }
+}
+
+/// A recipe that looks a lot like another
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Duplicate {
+    pub recipe_id: RecipeId,
+    pub similarity: f32,
+}
+
+/// Finds recipes that are near duplicates of others, via the
+/// fingerprints of every recipe it was told about
+pub struct DuplicateDetector {
+    threshold: f32,
+    fingerprints: HashMap<RecipeId, Fingerprint>,
+}
+
+impl DuplicateDetector {
+    /// Recipes are taken as duplicates when the similarity of their
+    /// fingerprints is at least `threshold`. Around 0.9 works well
+    pub fn new(threshold: f32) -> Self {
+        Self {
+            threshold,
+            fingerprints: HashMap::new(),
+        }
+    }
+
+    /// Remembers the recipe, replacing what was known of any recipe
+    /// with the same id
+    pub fn insert(&mut self, recipe: &Recipe) {
+        self.fingerprints
+            .insert(recipe.recipe_id, Fingerprint::of(recipe));
+    }
+
+    pub fn len(&self) -> usize {
+        self.fingerprints.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.fingerprints.is_empty()
+    }
+
+    /// The known recipes (other than the recipe itself, by id) that
+    /// are near duplicates of `recipe`, most similar first
+    pub fn candidates(&self, recipe: &Recipe) -> Vec<Duplicate> {
+        let fingerprint = Fingerprint::of(recipe);
+
+        let mut found = self
+            .fingerprints
+            .iter()
+            .filter(|(id, _)| **id != recipe.recipe_id)
+            .map(|(id, other)| Duplicate {
+                recipe_id: *id,
+                similarity: fingerprint.similarity(*other),
+            })
+            .filter(|candidate| candidate.similarity >= self.threshold)
+            .collect::<Vec<_>>();
+
+        found.sort_by(|a, b| {
+            b.similarity
+                .partial_cmp(&a.similarity)
+                .expect("similarities are finite")
+                .then_with(|| a.recipe_id.cmp(&b.recipe_id))
+        });
+        found
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn recipe(recipe_id: RecipeId, name: &str, ingredients: &[&str]) -> Recipe {
+        Recipe {
+            ingredients: ingredients.iter().map(|i| (*i).to_owned()).collect(),
+            instructions: vec![
+                "Whisk the eggs with the milk and the sugar until smooth".to_owned(),
+                "Fold in the flour, then rest the batter for half an hour".to_owned(),
+                "Cook on a hot buttered pan until golden on both sides".to_owned(),
+            ],
+            ..Recipe::sample(recipe_id, name)
+        }
+    }
+
+    #[test]
+    fn finds_near_duplicates() {
+        let ingredients = ["2 eggs", "1 cup of milk", "1 cup of flour", "1 tbsp sugar"];
+        let pancakes = recipe(1, "Fluffy Pancakes", &ingredients);
+
+        let mut stew = recipe(
+            2,
+            "Beef stew",
+            &["1kg beef", "3 carrots", "2 onions", "1 bottle of red wine"],
+        );
+        stew.instructions = vec![
+            "Brown the beef in batches and set it aside".to_owned(),
+            "Soften the onions and carrots, pour the wine in".to_owned(),
+            "Put the beef back and simmer for three hours".to_owned(),
+        ];
+
+        let mut detector = DuplicateDetector::new(0.8);
+        detector.insert(&pancakes);
+        detector.insert(&stew);
+        assert_eq!(2, detector.len());
+
+        // Same text, other case and punctuation
+        let copy = recipe(
+            3,
+            "fluffy pancakes!",
+            &["2 Eggs", "1 cup of milk", "1 cup of flour", "1 tbsp sugar"],
+        );
+        assert_eq!(
+            vec![Duplicate {
+                recipe_id: 1,
+                similarity: 1.0
+            }],
+            detector.candidates(&copy)
+        );
+
+        let tweaked = recipe(
+            4,
+            "Fluffy Pancakes",
+            &["2 eggs", "1 cup of milk", "1 cup of flour", "2 tbsp sugar"],
+        );
+        let found = detector.candidates(&tweaked);
+        assert_eq!(1, found.len());
+        assert_eq!(1, found[0].recipe_id);
+
+        // A recipe is not a duplicate of itself
+        assert!(detector.candidates(&pancakes).is_empty());
+    }
+
+    #[test]
+    fn similarity_of_fingerprints() {
+        assert_eq!(1.0, Fingerprint(42).similarity(Fingerprint(42)));
+        assert_eq!(0.0, Fingerprint(0).similarity(Fingerprint(std::u64::MAX)));
+        assert_eq!(0.75, Fingerprint(0).similarity(Fingerprint(0xffff)));
+    }
+}
@@ -0,0 +1,195 @@
+use std::{collections::HashSet, sync::Mutex};
+
+use crate::{
+    geo::GeoPoint,
+    model::{AuthorId, Diversity, DiversitySummary, Recipe, RecipeSummary},
+};
+
+/// How many of the top results count towards the diversity
+pub const TOP_RESULTS: usize = 20;
+
+/// Measures the `Diversity` of the top results of a search, given
+/// in order
+#[derive(Default)]
+pub struct DiversityCounter {
+    num_items: usize,
+    sites: HashSet<String>,
+    authors: HashSet<AuthorId>,
+    origins: HashSet<u64>,
+}
+
+impl DiversityCounter {
+    /// Accounts for the next result. Does nothing past the top ones
+    pub fn add(&mut self, recipe: &Recipe) {
+        self.add_parts(&recipe.crawl_url, recipe.author_id, recipe.origin);
+    }
+
+    /// Like `add`, for results hydrated from their summaries
+    pub fn add_summary(&mut self, summary: &RecipeSummary) {
+        self.add_parts(&summary.crawl_url, summary.author_id, summary.origin);
+    }
+
+    fn add_parts(
+        &mut self,
+        crawl_url: &str,
+        author_id: Option<AuthorId>,
+        origin: Option<GeoPoint>,
+    ) {
+        if self.num_items >= TOP_RESULTS {
+            return;
+        }
+        self.num_items += 1;
+
+        self.sites.insert(site(crawl_url).to_owned());
+        if let Some(author_id) = author_id {
+            self.authors.insert(author_id);
+        }
+        if let Some(origin) = origin.filter(|origin| origin.is_valid()) {
+            self.origins.insert(origin.encode());
+        }
+    }
+
+    pub fn finish(self) -> Diversity {
+        Diversity {
+            num_items: self.num_items,
+            distinct_sites: self.sites.len(),
+            distinct_authors: self.authors.len(),
+            distinct_origins: self.origins.len(),
+        }
+    }
+}
+
+/// The host of the url, without any leading "www."
+pub fn site(url: &str) -> &str {
+    let without_scheme = url.splitn(2, "://").last().unwrap_or(url);
+    let host = without_scheme
+        .split(|c| c == '/' || c == '?' || c == '#')
+        .next()
+        .unwrap_or(without_scheme);
+    host.trim_start_matches("www.")
+}
+
+/// Running averages of the diversity of every search with results,
+/// so that the effect of ranking changes can be followed over time
+#[derive(Default)]
+pub struct DiversityMetrics(Mutex<Totals>);
+
+#[derive(Default)]
+struct Totals {
+    num_searches: u64,
+    items: u64,
+    sites: u64,
+    authors: u64,
+    origins: u64,
+}
+
+impl DiversityMetrics {
+    pub fn record(&self, diversity: &Diversity) {
+        if diversity.num_items == 0 {
+            return;
+        }
+
+        let mut totals = self.0.lock().expect("lock not poisoned");
+        totals.num_searches += 1;
+        totals.items += diversity.num_items as u64;
+        totals.sites += diversity.distinct_sites as u64;
+        totals.authors += diversity.distinct_authors as u64;
+        totals.origins += diversity.distinct_origins as u64;
+    }
+
+    pub fn summary(&self) -> DiversitySummary {
+        let totals = self.0.lock().expect("lock not poisoned");
+        let mean = |total: u64| {
+            if totals.num_searches == 0 {
+                0.0
+            } else {
+                total as f64 / totals.num_searches as f64
+            }
+        };
+
+        DiversitySummary {
+            num_searches: totals.num_searches,
+            mean_items: mean(totals.items),
+            mean_distinct_sites: mean(totals.sites),
+            mean_distinct_authors: mean(totals.authors),
+            mean_distinct_origins: mean(totals.origins),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn recipe(crawl_url: &str, author_id: Option<AuthorId>, origin: Option<GeoPoint>) -> Recipe {
+        Recipe {
+            crawl_url: crawl_url.to_owned(),
+            ingredients: vec!["pasta".to_owned()],
+            instructions: vec!["Boil the pasta".to_owned()],
+            author_id,
+            origin,
+            ..Recipe::sample(1, "Pasta")
+        }
+    }
+
+    #[test]
+    fn sites() {
+        assert_eq!("example.com", site("https://www.example.com/pasta?x=1"));
+        assert_eq!("blog.example.com", site("http://blog.example.com"));
+        assert_eq!("example.com", site("example.com/pasta#top"));
+    }
+
+    #[test]
+    fn counting() {
+        let rome = GeoPoint {
+            lat: 41.9,
+            lon: 12.5,
+        };
+
+        let mut counter = DiversityCounter::default();
+        counter.add(&recipe("https://example.com/1", Some(1), Some(rome)));
+        counter.add(&recipe("https://www.example.com/2", Some(1), None));
+        counter.add(&recipe("https://other.com/3", Some(2), Some(rome)));
+        for _ in 0..TOP_RESULTS {
+            counter.add(&recipe("https://third.com/4", Some(3), None));
+        }
+
+        assert_eq!(
+            Diversity {
+                num_items: TOP_RESULTS,
+                distinct_sites: 3,
+                distinct_authors: 3,
+                distinct_origins: 1,
+            },
+            counter.finish()
+        );
+    }
+
+    #[test]
+    fn metrics() {
+        let metrics = DiversityMetrics::default();
+        assert_eq!(0, metrics.summary().num_searches);
+
+        metrics.record(&Diversity {
+            num_items: 10,
+            distinct_sites: 4,
+            distinct_authors: 2,
+            distinct_origins: 0,
+        });
+        metrics.record(&Diversity {
+            num_items: 20,
+            distinct_sites: 8,
+            distinct_authors: 3,
+            distinct_origins: 1,
+        });
+        // Searches without results are left out
+        metrics.record(&Diversity::default());
+
+        let summary = metrics.summary();
+        assert_eq!(2, summary.num_searches);
+        assert_eq!(15.0, summary.mean_items);
+        assert_eq!(6.0, summary.mean_distinct_sites);
+        assert_eq!(2.5, summary.mean_distinct_authors);
+        assert_eq!(0.5, summary.mean_distinct_origins);
+    }
+}
@@ -0,0 +1,148 @@
+use std::{error, fmt, io};
+
+use tantivy::TantivyError;
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// What can go wrong in cantine, told apart so that callers can tell
+/// a corrupted database from a bad query instead of digging through
+/// `io::ErrorKind`s
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    /// An item couldn't be encoded or decoded
+    Serialization(String),
+    /// The database item at `offset` of the data file is unreadable
+    Corruption {
+        offset: u64,
+        reason: String,
+    },
+    /// Someone else is already writing to the index
+    LockHeld(String),
+    /// The schema lacks a field the recipe index needs
+    UnknownField(String),
+    /// User input (like a score expression) isn't valid
+    QueryParse(String),
+    /// Any other failure from the search engine
+    Index(TantivyError),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io(err) => write!(f, "I/O error: {}", err),
+            Error::Serialization(reason) => write!(f, "Serialization error: {}", reason),
+            Error::Corruption { offset, reason } => {
+                write!(f, "Corrupted item at offset {}: {}", offset, reason)
+            }
+            Error::LockHeld(reason) => write!(f, "Index lock already held: {}", reason),
+            Error::UnknownField(name) => write!(f, "Unknown field {}", name),
+            Error::QueryParse(reason) => write!(f, "Invalid query: {}", reason),
+            Error::Index(err) => write!(f, "Index error: {}", err),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Error::Io(err) => Some(err),
+            Error::Index(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Self {
+        Error::Io(err)
+    }
+}
+
+impl From<bincode::Error> for Error {
+    fn from(err: bincode::Error) -> Self {
+        match *err {
+            bincode::ErrorKind::Io(err) => Error::Io(err),
+            other => Error::Serialization(other.to_string()),
+        }
+    }
+}
+
+impl From<TantivyError> for Error {
+    fn from(err: TantivyError) -> Self {
+        match err {
+            TantivyError::LockFailure(..) => Error::LockHeld(err.to_string()),
+            other => Error::Index(other),
+        }
+    }
+}
+
+// So that code still working with io and tantivy results can
+// use `?` on ours
+
+impl From<Error> for io::Error {
+    fn from(err: Error) -> Self {
+        match err {
+            Error::Io(err) => err,
+            Error::Serialization(_) | Error::Corruption { .. } => {
+                io::Error::new(io::ErrorKind::InvalidData, err)
+            }
+            other => io::Error::new(io::ErrorKind::Other, other),
+        }
+    }
+}
+
+impl From<Error> for TantivyError {
+    fn from(err: Error) -> Self {
+        match err {
+            Error::Io(err) => err.into(),
+            Error::Index(err) => err,
+            Error::UnknownField(_) => TantivyError::SchemaError(err.to_string()),
+            Error::QueryParse(_) => TantivyError::InvalidArgument(err.to_string()),
+            other => TantivyError::SystemError(other.to_string()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn conversions_keep_the_kind() {
+        let err: Error = io::Error::new(io::ErrorKind::NotFound, "gone").into();
+        assert!(matches!(err, Error::Io(ref err) if err.kind() == io::ErrorKind::NotFound));
+        assert_eq!(io::ErrorKind::NotFound, io::Error::from(err).kind());
+
+        let corrupted = Error::Corruption {
+            offset: 42,
+            reason: "truncated".to_owned(),
+        };
+        assert!(corrupted.to_string().contains("42"));
+        assert_eq!(
+            io::ErrorKind::InvalidData,
+            io::Error::from(corrupted).kind()
+        );
+
+        let err: Error = TantivyError::InvalidArgument("nope".to_owned()).into();
+        assert!(matches!(
+            err,
+            Error::Index(TantivyError::InvalidArgument(_))
+        ));
+        assert!(matches!(
+            TantivyError::from(err),
+            TantivyError::InvalidArgument(_)
+        ));
+
+        assert!(matches!(
+            TantivyError::from(Error::QueryParse("1 +".to_owned())),
+            TantivyError::InvalidArgument(_)
+        ));
+    }
+
+    #[test]
+    fn bincode_failures_are_serialization_errors() {
+        let err: Error = bincode::deserialize::<bool>(&[2]).unwrap_err().into();
+        assert!(matches!(err, Error::Serialization(_)));
+    }
+}
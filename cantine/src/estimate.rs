@@ -0,0 +1,182 @@
+use crate::model::Recipe;
+
+/// Minutes per step for reading and doing the hands-on work
+const MINUTES_PER_STEP: u32 = 2;
+/// Characters of instructions per extra minute of hands-on work
+const CHARS_PER_MINUTE: u32 = 100;
+
+/// How long steps that mention a technique but no explicit duration
+/// usually take, in minutes. The first one found in a step wins
+const TECHNIQUES: &[(&str, u32)] = &[
+    ("slow cooker", 360),
+    ("overnight", 480),
+    ("marinate", 60),
+    ("refrigerate", 60),
+    ("chill", 60),
+    ("freeze", 120),
+    ("rise", 60),
+    ("proof", 60),
+    ("braise", 90),
+    ("roast", 45),
+    ("bake", 30),
+    ("simmer", 20),
+    ("grill", 15),
+    ("boil", 10),
+    ("fry", 8),
+    ("saute", 8),
+    ("sauté", 8),
+];
+
+/// Guesses how long a recipe takes from start to finish, in minutes.
+///
+/// Every step accounts for some hands-on time, proportional to how
+/// long its text is. Durations mentioned in a step (like "bake for
+/// 20-25 minutes" or "chill for an hour") are added in full, taking
+/// the upper end of ranges; steps without any get a typical duration
+/// for the technique they mention, if any. The result is rounded up
+/// to multiples of 5 minutes.
+pub fn estimate_total_time(instructions: &[String]) -> u32 {
+    let mut minutes = 0;
+
+    for step in instructions {
+        let step = step.to_lowercase();
+
+        minutes += MINUTES_PER_STEP + step.chars().count() as u32 / CHARS_PER_MINUTE;
+        minutes += explicit_minutes(&step).unwrap_or_else(|| technique_minutes(&step));
+    }
+
+    ((minutes.max(1) + 4) / 5) * 5
+}
+
+/// Fills in `total_time_estimate` for recipes without a `total_time`.
+/// Yields whether an estimate was made
+pub fn fill_in(recipe: &mut Recipe) -> bool {
+    if recipe.features.total_time.is_some() || recipe.instructions.is_empty() {
+        recipe.features.total_time_estimate = None;
+        return false;
+    }
+
+    recipe.features.total_time_estimate = Some(estimate_total_time(&recipe.instructions));
+    true
+}
+
+fn technique_minutes(step: &str) -> u32 {
+    TECHNIQUES
+        .iter()
+        .find(|(technique, _)| step.contains(technique))
+        .map_or(0, |&(_, minutes)| minutes)
+}
+
+/// Sums every duration mentioned in the step, if any
+fn explicit_minutes(step: &str) -> Option<u32> {
+    let words: Vec<&str> = step
+        .split_whitespace()
+        .map(|word| word.trim_matches(|c: char| c.is_ascii_punctuation() && c != '-'))
+        .filter(|word| !word.is_empty())
+        .collect();
+
+    let mut total = None;
+
+    for (idx, word) in words.iter().enumerate() {
+        let per_unit = match unit_minutes(word) {
+            Some(per_unit) => per_unit,
+            None => continue,
+        };
+
+        let amount = match idx.checked_sub(1).map(|prev| words[prev]) {
+            Some("a") | Some("an") | Some("one") => 1.0,
+            Some(prev) => match amount(prev) {
+                Some(amount) => amount,
+                None => continue,
+            },
+            None => continue,
+        };
+
+        // "a half an hour", "half an hour"
+        let amount = match idx.checked_sub(2).map(|prev| words[prev]) {
+            Some("half") => amount / 2.0,
+            _ => amount,
+        };
+
+        *total.get_or_insert(0) += (amount * per_unit).ceil() as u32;
+    }
+
+    total
+}
+
+fn unit_minutes(word: &str) -> Option<f64> {
+    match word {
+        "min" | "mins" | "minute" | "minutes" => Some(1.0),
+        "hr" | "hrs" | "hour" | "hours" => Some(60.0),
+        _ => None,
+    }
+}
+
+/// Parses amounts like "20", "1.5" or ranges like "20-25" (yielding
+/// the upper end)
+fn amount(word: &str) -> Option<f64> {
+    let upper = word.rsplit(|c| c == '-' || c == '–').next()?;
+    upper.parse::<f64>().ok().filter(|amount| *amount > 0.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn steps(steps: &[&str]) -> Vec<String> {
+        steps.iter().map(|&step| String::from(step)).collect()
+    }
+
+    #[test]
+    fn explicit_durations() {
+        assert_eq!(None, explicit_minutes("whisk the eggs"));
+        assert_eq!(Some(25), explicit_minutes("bake for 20-25 minutes"));
+        assert_eq!(Some(90), explicit_minutes("roast for 1.5 hours"));
+        assert_eq!(Some(30), explicit_minutes("chill for a half an hour"));
+        assert_eq!(
+            Some(65),
+            explicit_minutes("simmer for an hour, then rest 5 min")
+        );
+    }
+
+    #[test]
+    fn estimation() {
+        // 2 minutes per step, plus 8 from the whipping
+        assert_eq!(
+            15,
+            estimate_total_time(&steps(&[
+                "Place egg yolks, agave, and salt in a medium saucepan",
+                "While heating over very low flame, whip for 6-8 minutes",
+                "Serve in small glasses",
+            ]))
+        );
+
+        // No durations: the technique decides
+        assert_eq!(
+            35,
+            estimate_total_time(&steps(&["Mix everything", "Bake until golden"]))
+        );
+
+        // Explicit durations win over the technique default
+        assert_eq!(
+            15,
+            estimate_total_time(&steps(&["Mix everything", "Bake for 10 minutes"]))
+        );
+    }
+
+    #[test]
+    fn only_missing_times_are_estimated() {
+        let mut recipe = Recipe {
+            ingredients: vec!["pasta".to_owned()],
+            instructions: steps(&["Boil the pasta"]),
+            ..Recipe::sample(1, "Pasta")
+        };
+
+        assert!(fill_in(&mut recipe));
+        assert_eq!(Some(15), recipe.features.total_time_estimate);
+
+        recipe.features.total_time = Some(42);
+        assert!(!fill_in(&mut recipe));
+        assert_eq!(None, recipe.features.total_time_estimate);
+    }
+}
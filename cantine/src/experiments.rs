@@ -0,0 +1,253 @@
+use std::{
+    fs,
+    io::{self, ErrorKind},
+    path::Path,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    error::Result,
+    model::{FieldBoosts, SearchQuery, Sort},
+    recency::RecencyBoost,
+};
+
+/// How relevance-sorted searches are ranked when they don't pick a
+/// ranking of their own
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct RankingProfile {
+    pub boost: Option<FieldBoosts>,
+    /// A score expression, same as a search's `score`
+    pub score: Option<String>,
+    pub recency: Option<RecencyBoost>,
+    #[serde(default)]
+    pub boost_popular: bool,
+}
+
+impl RankingProfile {
+    /// Makes `query` rank as the profile says
+    pub fn apply(&self, query: &mut SearchQuery) {
+        query.boost = self.boost.clone();
+        query.score = self.score.clone();
+        query.recency = self.recency;
+        query.boost_popular = self.boost_popular;
+    }
+}
+
+/// One of the rankings an experiment compares
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct Arm {
+    /// Reported along with every search it serves
+    pub name: String,
+    /// How many of the buckets it gets, relative to the other arms
+    #[serde(default = "default_weight")]
+    pub weight: u32,
+    #[serde(default)]
+    pub ranking: RankingProfile,
+}
+
+fn default_weight() -> u32 {
+    1
+}
+
+/// Splits searches across ranking profiles by who does them, so that
+/// each user keeps getting the same ranking for as long as the
+/// experiment runs
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct Experiment {
+    /// Renaming an experiment reshuffles its buckets
+    pub name: String,
+    pub arms: Vec<Arm>,
+}
+
+impl Experiment {
+    /// Reads a json experiment, say: `{"name": "recency", "arms":
+    /// [{"name": "control"}, {"name": "fresh", "ranking": {"recency":
+    /// {"half_life_days": 30}}}]}`
+    pub fn load(path: &Path) -> Result<Self> {
+        let contents = fs::read_to_string(path)?;
+        let experiment: Experiment = serde_json::from_str(&contents)
+            .map_err(|err| io::Error::new(ErrorKind::InvalidData, err))?;
+
+        if experiment.total_weight() == 0 {
+            return Err(io::Error::new(
+                ErrorKind::InvalidData,
+                "an experiment needs an arm with a positive weight",
+            )
+            .into());
+        }
+
+        for arm in &experiment.arms {
+            if !arm.ranking.recency.map_or(true, |boost| boost.is_valid()) {
+                return Err(io::Error::new(
+                    ErrorKind::InvalidData,
+                    format!("invalid recency boost for arm {}", arm.name),
+                )
+                .into());
+            }
+        }
+
+        Ok(experiment)
+    }
+
+    /// Parses the score expression of every arm with `parse` (say:
+    /// `SearchState::score_expression`), so that a bad one stops the
+    /// server from starting instead of failing every search of the
+    /// users bucketed into its arm
+    pub fn check_scores<T, F>(&self, parse: F) -> Result<()>
+    where
+        F: Fn(&str) -> Result<T>,
+    {
+        for arm in &self.arms {
+            if let Some(score) = &arm.ranking.score {
+                parse(score).map_err(|err| {
+                    io::Error::new(
+                        ErrorKind::InvalidData,
+                        format!("invalid score for arm {}: {}", arm.name, err),
+                    )
+                })?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn total_weight(&self) -> u64 {
+        self.arms.iter().map(|arm| u64::from(arm.weight)).sum()
+    }
+
+    /// The arm of whoever `token` stands for
+    pub fn assign(&self, token: &str) -> &Arm {
+        let mut bucket = self.bucket(token);
+        for arm in &self.arms {
+            let weight = u64::from(arm.weight);
+            if bucket < weight {
+                return arm;
+            }
+            bucket -= weight;
+        }
+        unreachable!("buckets are below the total weight")
+    }
+
+    /// Enrolls `query` in the experiment, unless it picks a ranking of
+    /// its own. Yields the arm that serves it, if any
+    pub fn enroll(&self, token: &str, query: &mut SearchQuery) -> Option<&Arm> {
+        if ranks_on_its_own(query) {
+            return None;
+        }
+
+        let arm = self.assign(token);
+        arm.ranking.apply(query);
+        Some(arm)
+    }
+
+    fn bucket(&self, token: &str) -> u64 {
+        // The name goes in so that experiments split users apart from
+        // each other. FNV-1a, since buckets mustn't change across
+        // releases
+        let hash = self
+            .name
+            .bytes()
+            .chain(Some(0))
+            .chain(token.bytes())
+            .fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+                (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+            });
+        hash % self.total_weight()
+    }
+}
+
+fn ranks_on_its_own(query: &SearchQuery) -> bool {
+    let sorted = match query.sort {
+        None | Some(Sort::Relevance) => false,
+        Some(_) => true,
+    };
+
+    sorted
+        || query.boost.is_some()
+        || query.score.is_some()
+        || query.recency.is_some()
+        || query.boost_popular
+        || query.runtime_sort.is_some()
+        || query.distance_sort.is_some()
+        || query.macro_profile.is_some()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::error::Error;
+
+    fn experiment(weights: &[u32]) -> Experiment {
+        Experiment {
+            name: "test".to_owned(),
+            arms: weights
+                .iter()
+                .enumerate()
+                .map(|(idx, weight)| Arm {
+                    name: format!("arm{}", idx),
+                    weight: *weight,
+                    ranking: RankingProfile {
+                        boost_popular: idx > 0,
+                        ..RankingProfile::default()
+                    },
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn assignment_is_stable_and_follows_the_weights() {
+        let experiment = experiment(&[1, 3, 0]);
+
+        let mut counts = [0; 3];
+        for user in 0..4000 {
+            let token = format!("user{}", user);
+            let arm = experiment.assign(&token);
+            assert_eq!(arm.name, experiment.assign(&token).name);
+            counts[arm.name[3..].parse::<usize>().unwrap()] += 1;
+        }
+
+        assert_eq!(0, counts[2]);
+        assert!(counts[0] > 800 && counts[0] < 1200, "{:?}", counts);
+    }
+
+    #[test]
+    fn searches_with_their_own_ranking_arent_enrolled() {
+        let experiment = experiment(&[0, 1]);
+
+        let mut query = SearchQuery::default();
+        let arm = experiment.enroll("user", &mut query).map(|arm| &arm.name);
+        assert_eq!(Some(&"arm1".to_owned()), arm);
+        assert!(query.boost_popular);
+
+        let mut query = SearchQuery {
+            sort: Some(Sort::Calories),
+            ..SearchQuery::default()
+        };
+        assert!(experiment.enroll("user", &mut query).is_none());
+        assert!(!query.boost_popular);
+    }
+
+    #[test]
+    fn arm_scores_are_checked() {
+        let mut experiment = experiment(&[1, 1]);
+        let parse = |input: &str| match input {
+            "_score * 2" => Ok(()),
+            other => Err(Error::QueryParse(other.to_owned())),
+        };
+
+        // Arms without a score of their own have nothing to check
+        assert!(experiment.check_scores(parse).is_ok());
+
+        experiment.arms[0].ranking.score = Some("_score * 2".to_owned());
+        assert!(experiment.check_scores(parse).is_ok());
+
+        experiment.arms[1].ranking.score = Some("_score *".to_owned());
+        assert!(experiment.check_scores(parse).is_err());
+    }
+}
@@ -0,0 +1,265 @@
+use std::{
+    io::{self, Write},
+    str::FromStr,
+};
+
+use tantivy::{query::Query, Searcher};
+
+use crate::{
+    database::DatabaseReader,
+    error::Result,
+    index::RecipeIndex,
+    model::{Features, Recipe},
+};
+
+/// How many matching ids to fetch from the index at a time
+const BATCH_SIZE: usize = 1000;
+
+/// How `run` writes the recipes out
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    /// The full recipes, one json object per line
+    Jsonl,
+    /// One row per recipe, after a header. Only the scalar parts of
+    /// the recipes are there: no ingredients, instructions, images
+    /// or similar recipes
+    Csv,
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(input: &str) -> std::result::Result<Self, String> {
+        match input {
+            "jsonl" => Ok(Format::Jsonl),
+            "csv" => Ok(Format::Csv),
+            _ => Err(format!("Unknown format {}. Try jsonl or csv", input)),
+        }
+    }
+}
+
+const CSV_COLUMNS: [&str; 22] = [
+    "recipe_id",
+    "uuid",
+    "name",
+    "crawl_url",
+    "author_id",
+    "tenant_id",
+    "num_ingredients",
+    "instructions_length",
+    "prep_time",
+    "total_time",
+    "cook_time",
+    "total_time_estimate",
+    "calories",
+    "fat_content",
+    "carb_content",
+    "protein_content",
+    "diet_lowcarb",
+    "diet_vegetarian",
+    "diet_vegan",
+    "diet_keto",
+    "diet_paleo",
+    "added_at",
+];
+
+/// Writes every recipe `query` matches to `output`, in the order
+/// they are in the index, yielding how many were written
+pub fn run<W: Write>(
+    searcher: &Searcher,
+    recipe_index: &RecipeIndex,
+    query: &dyn Query,
+    database: &DatabaseReader<Recipe>,
+    format: Format,
+    output: &mut W,
+) -> Result<usize> {
+    if format == Format::Csv {
+        write_row(output, CSV_COLUMNS.iter().map(|&column| column.to_owned()))?;
+    }
+
+    let mut num_written = 0;
+    for recipe_ids in recipe_index.scan(searcher, query, BATCH_SIZE)? {
+        for recipe in database.find_each(&recipe_ids?)?.into_iter().flatten() {
+            match format {
+                Format::Jsonl => {
+                    serde_json::to_writer(&mut *output, &recipe)
+                        .map_err(|err| io::Error::new(io::ErrorKind::Other, err))?;
+                    output.write_all(b"\n")?;
+                }
+                Format::Csv => write_row(output, csv_values(&recipe))?,
+            }
+            num_written += 1;
+        }
+    }
+
+    output.flush()?;
+    Ok(num_written)
+}
+
+fn csv_values(recipe: &Recipe) -> impl Iterator<Item = String> {
+    fn optional<T: ToString>(value: Option<T>) -> String {
+        value.map(|v| v.to_string()).unwrap_or_default()
+    }
+
+    let Features {
+        num_ingredients,
+        instructions_length,
+        prep_time,
+        total_time,
+        cook_time,
+        total_time_estimate,
+        calories,
+        fat_content,
+        carb_content,
+        protein_content,
+        diet_lowcarb,
+        diet_vegetarian,
+        diet_vegan,
+        diet_keto,
+        diet_paleo,
+        added_at,
+    } = &recipe.features;
+
+    vec![
+        recipe.recipe_id.to_string(),
+        recipe.uuid.to_string(),
+        recipe.name.clone(),
+        recipe.crawl_url.clone(),
+        optional(recipe.author_id),
+        optional(recipe.tenant_id),
+        num_ingredients.to_string(),
+        instructions_length.to_string(),
+        optional(*prep_time),
+        optional(*total_time),
+        optional(*cook_time),
+        optional(*total_time_estimate),
+        optional(*calories),
+        optional(*fat_content),
+        optional(*carb_content),
+        optional(*protein_content),
+        optional(*diet_lowcarb),
+        optional(*diet_vegetarian),
+        optional(*diet_vegan),
+        optional(*diet_keto),
+        optional(*diet_paleo),
+        optional(*added_at),
+    ]
+    .into_iter()
+}
+
+fn write_row<W: Write, I: Iterator<Item = String>>(output: &mut W, values: I) -> io::Result<()> {
+    for (idx, value) in values.enumerate() {
+        if idx > 0 {
+            output.write_all(b",")?;
+        }
+        write_csv_field(output, &value)?;
+    }
+    output.write_all(b"\r\n")
+}
+
+// As per RFC 4180: fields with separators, quotes or line breaks
+// are quoted, doubling the quotes within
+fn write_csv_field<W: Write>(output: &mut W, value: &str) -> io::Result<()> {
+    if value.contains(|c| c == ',' || c == '"' || c == '\r' || c == '\n') {
+        write!(output, "\"{}\"", value.replace('"', "\"\""))
+    } else {
+        output.write_all(value.as_bytes())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use tantivy::{query::AllQuery, schema::SchemaBuilder, Index};
+
+    use crate::database::DatabaseWriter;
+
+    fn recipe(recipe_id: u64, name: &str) -> Recipe {
+        Recipe {
+            ingredients: vec!["rice".to_owned()],
+            instructions: vec!["boil".to_owned()],
+            features: Features {
+                num_ingredients: 1,
+                calories: Some(120),
+                ..Features::default()
+            },
+            ..Recipe::sample(recipe_id, name)
+        }
+    }
+
+    #[test]
+    fn csv_fields_are_quoted_when_needed() -> Result<()> {
+        let mut output = Vec::new();
+        write_row(
+            &mut output,
+            vec!["plain", "a, b", "say \"hi\"", "two\nlines"]
+                .into_iter()
+                .map(str::to_owned),
+        )?;
+
+        assert_eq!(
+            "plain,\"a, b\",\"say \"\"hi\"\"\",\"two\nlines\"\r\n",
+            String::from_utf8(output).unwrap()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn exports_every_matching_recipe() -> Result<()> {
+        let recipes = vec![recipe(1, "Rice"), recipe(2, "Rice, fried")];
+
+        let db_dir = tempfile::tempdir()?;
+        let mut db = DatabaseWriter::new(db_dir.path())?;
+        for recipe in &recipes {
+            db.append(recipe)?;
+        }
+        drop(db);
+        let database = DatabaseReader::open(db_dir.path())?;
+
+        let mut builder = SchemaBuilder::new();
+        let recipe_index = RecipeIndex::from(&mut builder);
+        let index = Index::create_in_ram(builder.build());
+        let mut writer = index.writer_with_num_threads(1, 3_000_000)?;
+        for recipe in &recipes {
+            writer.add_document(recipe_index.make_document(recipe));
+        }
+        writer.commit()?;
+        let searcher = index.reader()?.searcher();
+
+        let mut jsonl = Vec::new();
+        let num_written = run(
+            &searcher,
+            &recipe_index,
+            &AllQuery,
+            &database,
+            Format::Jsonl,
+            &mut jsonl,
+        )?;
+        assert_eq!(2, num_written);
+        let exported = String::from_utf8(jsonl)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).expect("valid json"))
+            .collect::<Vec<Recipe>>();
+        assert_eq!(recipes, exported);
+
+        let mut csv = Vec::new();
+        run(
+            &searcher,
+            &recipe_index,
+            &AllQuery,
+            &database,
+            Format::Csv,
+            &mut csv,
+        )?;
+        let csv = String::from_utf8(csv).unwrap();
+        let rows = csv.lines().collect::<Vec<_>>();
+        assert_eq!(3, rows.len());
+        assert!(rows[0].starts_with("recipe_id,uuid,name,crawl_url,"));
+        assert!(rows[2].starts_with(&format!("2,{},\"Rice, fried\",", recipes[1].uuid)));
+        assert_eq!(CSV_COLUMNS.len(), rows[1].split(',').count());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,144 @@
+use bincode;
+use tantivy::{
+    collector::{Collector, SegmentCollector},
+    fastfield::BytesFastFieldReader,
+    schema::Field,
+    DocId, Result, Score, SegmentLocalId, SegmentReader, TantivyError,
+};
+
+use crate::model::{Features, FeaturesFilterQuery};
+
+/// Counts how many of the matching documents each of the given
+/// filters would match, in a single pass.
+///
+/// The fruit has one count per filter, in the order they were given.
+/// Filters are checked against the features decoded from `field`
+/// (the bincode-serialized `Features` bytes fast field), so a filter
+/// on an optional feature never counts documents without it.
+pub struct FilterBucketsCollector {
+    field: Field,
+    filters: Vec<FeaturesFilterQuery>,
+}
+
+impl FilterBucketsCollector {
+    pub fn new(field: Field, filters: Vec<FeaturesFilterQuery>) -> Self {
+        Self { field, filters }
+    }
+}
+
+impl Collector for FilterBucketsCollector {
+    type Fruit = Vec<u64>;
+    type Child = FilterBucketsSegmentCollector;
+
+    fn for_segment(
+        &self,
+        _segment_id: SegmentLocalId,
+        reader: &SegmentReader,
+    ) -> Result<Self::Child> {
+        let reader = reader.fast_fields().bytes(self.field).ok_or_else(|| {
+            TantivyError::SchemaError(format!("{:?} is not a bytes fast field", self.field))
+        })?;
+
+        Ok(FilterBucketsSegmentCollector {
+            reader,
+            filters: self.filters.clone(),
+            counts: vec![0; self.filters.len()],
+        })
+    }
+
+    fn requires_scoring(&self) -> bool {
+        false
+    }
+
+    fn merge_fruits(&self, fruits: Vec<Vec<u64>>) -> Result<Vec<u64>> {
+        let mut merged = vec![0; self.filters.len()];
+        for fruit in fruits.iter() {
+            for (total, count) in merged.iter_mut().zip(fruit.iter()) {
+                *total += count;
+            }
+        }
+        Ok(merged)
+    }
+}
+
+pub struct FilterBucketsSegmentCollector {
+    reader: BytesFastFieldReader,
+    filters: Vec<FeaturesFilterQuery>,
+    counts: Vec<u64>,
+}
+
+impl SegmentCollector for FilterBucketsSegmentCollector {
+    type Fruit = Vec<u64>;
+
+    fn collect(&mut self, doc: DocId, _score: Score) {
+        let features: Features = match bincode::deserialize(self.reader.get_bytes(doc)) {
+            Ok(features) => features,
+            Err(_) => return,
+        };
+
+        for (filter, count) in self.filters.iter().zip(self.counts.iter_mut()) {
+            if filter.matches(&features) {
+                *count += 1;
+            }
+        }
+    }
+
+    fn harvest(self) -> Vec<u64> {
+        self.counts
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use tantivy::{doc, query::AllQuery, schema::SchemaBuilder, Index};
+
+    #[test]
+    fn counts_per_filter() -> Result<()> {
+        let mut builder = SchemaBuilder::new();
+        let field = builder.add_bytes_field("features");
+        let index = Index::create_in_ram(builder.build());
+        let mut writer = index.writer_with_num_threads(1, 3_000_000)?;
+
+        for (idx, total_time) in [Some(10), Some(15), Some(30), Some(200), None]
+            .iter()
+            .enumerate()
+        {
+            let features = Features {
+                total_time: *total_time,
+                ..Features::default()
+            };
+            writer.add_document(doc!(field => bincode::serialize(&features).unwrap()));
+            // Multiple segments
+            if idx == 1 {
+                writer.commit()?;
+            }
+        }
+        writer.commit()?;
+
+        let searcher = index.reader()?.searcher();
+
+        let filters = vec![
+            FeaturesFilterQuery {
+                total_time: Some(0..16),
+                ..FeaturesFilterQuery::default()
+            },
+            FeaturesFilterQuery {
+                total_time: Some(0..31),
+                ..FeaturesFilterQuery::default()
+            },
+            FeaturesFilterQuery {
+                total_time: Some(121..std::u32::MAX),
+                ..FeaturesFilterQuery::default()
+            },
+            // An empty filter matches everything
+            FeaturesFilterQuery::default(),
+        ];
+
+        let counts = searcher.search(&AllQuery, &FilterBucketsCollector::new(field, filters))?;
+        assert_eq!(vec![2, 3, 1, 5], counts);
+
+        Ok(())
+    }
+}
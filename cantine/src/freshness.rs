@@ -0,0 +1,193 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::RwLock,
+    time::Instant,
+};
+
+use serde::Serialize;
+use tantivy::{Index, IndexMeta, IndexWriter, Searcher};
+
+use crate::{
+    database::Checkpoint,
+    error::{Error, Result},
+    generation::meta_of,
+    metrics,
+};
+
+/// How far searches are behind the recipe database
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+pub struct Freshness {
+    /// Recipe writes (new recipes and new versions alike) in the
+    /// database that aren't searchable yet
+    pub lag_records: u64,
+    /// How much recipe data, in bytes, they take
+    pub lag_bytes: u64,
+    /// For how long, in seconds, searches have been behind. Only as
+    /// precise as how often it's checked
+    pub lag_seconds: u64,
+}
+
+/// Commits `writer` tagged with how far the recipe database went (as
+/// of `checkpoint`), which is how `FreshnessTracker` tells what's
+/// searchable. Yields the generation of the commit
+pub fn commit_with_checkpoint(writer: &mut IndexWriter, checkpoint: &Checkpoint) -> Result<u64> {
+    let payload =
+        serde_json::to_string(checkpoint).map_err(|err| Error::Serialization(err.to_string()))?;
+
+    let started = Instant::now();
+    let mut prepared = writer.prepare_commit()?;
+    prepared.set_payload(&payload);
+    let generation = prepared.commit()?;
+    metrics::commit_done(started);
+
+    Ok(generation)
+}
+
+/// The database checkpoint the commit was tagged with, if any. See
+/// `commit_with_checkpoint`
+pub fn checkpoint_of(meta: &IndexMeta) -> Option<Checkpoint> {
+    meta.payload
+        .as_ref()
+        .and_then(|payload| serde_json::from_str(payload).ok())
+}
+
+/// Compares the recipe database on disk with what searchers see
+pub struct FreshnessTracker {
+    index: Index,
+    db_path: PathBuf,
+    state: RwLock<TrackerState>,
+}
+
+#[derive(Default)]
+struct TrackerState {
+    /// The checkpoint of the latest commit a searcher was seen at
+    searchable: Option<Checkpoint>,
+    /// When searches were first noticed to be behind
+    behind_since: Option<Instant>,
+}
+
+impl FreshnessTracker {
+    pub fn new<P: AsRef<Path>>(index: Index, db_path: P) -> Self {
+        Self {
+            index,
+            db_path: db_path.as_ref().to_owned(),
+            state: RwLock::new(TrackerState::default()),
+        }
+    }
+
+    /// How far `searcher` (or any searcher seen before, if it's
+    /// outdated) is behind the database. None while no searchable
+    /// commit is tagged with a checkpoint, like for indexes built
+    /// before commits were tagged
+    pub fn check(&self, searcher: &Searcher) -> Result<Option<Freshness>> {
+        let seen = meta_of(&self.index, searcher)?
+            .as_ref()
+            .and_then(checkpoint_of);
+        let on_disk = Checkpoint::of(&self.db_path)?;
+
+        let mut state = self.state.write().expect("lock not poisoned");
+        if let Some(checkpoint) = seen {
+            // Commits only move forward
+            if state.searchable.map_or(true, |known| {
+                checkpoint.num_entries() >= known.num_entries()
+            }) {
+                state.searchable = Some(checkpoint);
+            }
+        }
+
+        let searchable = match state.searchable {
+            Some(searchable) => searchable,
+            None => return Ok(None),
+        };
+
+        let lag_records = on_disk
+            .num_entries()
+            .saturating_sub(searchable.num_entries());
+        if lag_records == 0 {
+            state.behind_since = None;
+        } else if state.behind_since.is_none() {
+            state.behind_since = Some(Instant::now());
+        }
+
+        Ok(Some(Freshness {
+            lag_records,
+            lag_bytes: on_disk.data_len().saturating_sub(searchable.data_len()),
+            lag_seconds: state
+                .behind_since
+                .map_or(0, |since| since.elapsed().as_secs()),
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use tantivy::{doc, schema::SchemaBuilder, schema::STORED, ReloadPolicy};
+
+    use crate::{database::DatabaseWriter, model::Recipe};
+
+    fn recipe(recipe_id: u64) -> Recipe {
+        Recipe {
+            ingredients: vec!["flour".to_owned()],
+            instructions: vec!["mix".to_owned()],
+            ..Recipe::sample(recipe_id, "pancakes")
+        }
+    }
+
+    #[test]
+    fn tracks_the_lag_of_searchers() -> Result<()> {
+        let db_dir = tempfile::tempdir()?;
+        let mut db = DatabaseWriter::<Recipe>::new(db_dir.path())?;
+
+        let mut builder = SchemaBuilder::new();
+        let id = builder.add_u64_field("id", STORED);
+        let index = Index::create_in_ram(builder.build());
+        let mut writer = index.writer_with_num_threads(1, 3_000_000)?;
+        let reader = index
+            .reader_builder()
+            .reload_policy(ReloadPolicy::Manual)
+            .try_into()?;
+
+        let tracker = FreshnessTracker::new(index.clone(), db_dir.path());
+
+        // Commits without a checkpoint can't be told apart
+        writer.add_document(doc!(id => 0u64));
+        writer.commit()?;
+        reader.reload()?;
+        assert_eq!(None, tracker.check(&reader.searcher())?);
+
+        for recipe_id in 1..3 {
+            db.append(&recipe(recipe_id))?;
+            writer.add_document(doc!(id => recipe_id));
+        }
+        db.flush()?;
+        commit_with_checkpoint(&mut writer, &db.checkpoint()?)?;
+        reader.reload()?;
+
+        let fresh = tracker
+            .check(&reader.searcher())?
+            .expect("commit is tagged");
+        assert_eq!(0, fresh.lag_records);
+        assert_eq!(0, fresh.lag_bytes);
+        assert_eq!(0, fresh.lag_seconds);
+
+        db.append(&recipe(3))?;
+        db.flush()?;
+        let behind = tracker
+            .check(&reader.searcher())?
+            .expect("commit is tagged");
+        assert_eq!(1, behind.lag_records);
+        assert!(behind.lag_bytes > 0);
+
+        // Committed, but the reader hasn't picked it up yet
+        writer.add_document(doc!(id => 3u64));
+        commit_with_checkpoint(&mut writer, &db.checkpoint()?)?;
+        assert_eq!(Some(behind), tracker.check(&reader.searcher())?);
+
+        reader.reload()?;
+        assert_eq!(Some(fresh), tracker.check(&reader.searcher())?);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,105 @@
+use std::{collections::HashSet, sync::RwLock};
+
+use tantivy::{Index, IndexMeta, Result, Searcher};
+
+/// Tells which commits of an index are searchable.
+///
+/// Commits are identified by their generation: the opstamp tantivy
+/// assigns to them, as yielded by `Cantine::commit`. Generations only
+/// grow, so once a searcher sees one it also sees every earlier one.
+pub struct Visibility {
+    index: Index,
+    /// The last generation a searcher was seen at
+    visible: RwLock<u64>,
+}
+
+impl Visibility {
+    pub fn new(index: Index) -> Self {
+        Self {
+            index,
+            visible: RwLock::new(0),
+        }
+    }
+
+    /// The latest generation known to be searchable via `searcher`
+    /// or via any searcher handed out earlier
+    pub fn visible(&self, searcher: &Searcher) -> Result<u64> {
+        if let Some(generation) = generation_of(&self.index, searcher)? {
+            let mut visible = self.visible.write().expect("lock not poisoned");
+            *visible = (*visible).max(generation);
+        }
+        Ok(*self.visible.read().expect("lock not poisoned"))
+    }
+
+    /// Whether the commit `generation` is searchable yet
+    pub fn is_visible(&self, searcher: &Searcher, generation: u64) -> Result<bool> {
+        Ok(self.visible(searcher)? >= generation)
+    }
+}
+
+/// The generation of the last commit of the index if `searcher` sees
+/// it. None when the reader hasn't picked it up yet
+pub fn generation_of(index: &Index, searcher: &Searcher) -> Result<Option<u64>> {
+    Ok(meta_of(index, searcher)?.map(|meta| meta.opstamp))
+}
+
+/// Like `generation_of`, but yielding the whole metadata of the
+/// commit, payload included
+pub fn meta_of(index: &Index, searcher: &Searcher) -> Result<Option<IndexMeta>> {
+    let meta = index.load_metas()?;
+
+    let committed: HashSet<_> = meta
+        .segments
+        .iter()
+        .map(|segment| (segment.id(), u64::from(segment.num_deleted_docs())))
+        .collect();
+    let seen: HashSet<_> = searcher
+        .segment_readers()
+        .iter()
+        .map(|reader| (reader.segment_id(), u64::from(reader.num_deleted_docs())))
+        .collect();
+
+    Ok(if committed == seen { Some(meta) } else { None })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use tantivy::{
+        doc,
+        schema::{SchemaBuilder, STORED},
+        ReloadPolicy,
+    };
+
+    #[test]
+    fn commits_are_visible_once_the_reader_reloads() -> Result<()> {
+        let mut builder = SchemaBuilder::new();
+        let field = builder.add_u64_field("field", STORED);
+        let index = Index::create_in_ram(builder.build());
+
+        let reader = index
+            .reader_builder()
+            .reload_policy(ReloadPolicy::Manual)
+            .try_into()?;
+        let visibility = Visibility::new(index.clone());
+
+        let mut writer = index.writer_with_num_threads(1, 3_000_000)?;
+        writer.add_document(doc!(field => 1u64));
+        let generation = writer.commit()?;
+        assert!(generation > 0);
+
+        // Not reloaded yet
+        assert_eq!(None, generation_of(&index, &reader.searcher())?);
+        assert!(!visibility.is_visible(&reader.searcher(), generation)?);
+
+        reader.reload()?;
+        assert_eq!(Some(generation), generation_of(&index, &reader.searcher())?);
+        assert!(visibility.is_visible(&reader.searcher(), generation)?);
+
+        // Later generations are unknown until their commit
+        assert!(!visibility.is_visible(&reader.searcher(), generation + 1)?);
+
+        Ok(())
+    }
+}
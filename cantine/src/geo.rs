@@ -0,0 +1,366 @@
+use serde::{Deserialize, Serialize};
+use tantivy::{
+    fastfield::FastFieldReader,
+    query::{Explanation, Query, RangeQuery, Scorer, Weight},
+    schema::Field,
+    DocId, DocSet, Result, Score, Searcher, SegmentLocalId, SegmentReader, TantivyError,
+    TERMINATED,
+};
+use tique::conditional_collector::ConditionForSegment;
+
+/// A location on earth, in decimal degrees
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct GeoPoint {
+    pub lat: f64,
+    pub lon: f64,
+}
+
+const EARTH_RADIUS_KM: f64 = 6371.0088;
+
+// Quantized coordinates go from 1 to u32::MAX - 1: zero means that
+// there's no location and the upper end is left free so that ranges
+// over the encoded values never overflow
+const QUANTIZATION_STEPS: f64 = (std::u32::MAX - 2) as f64;
+
+impl GeoPoint {
+    pub fn is_valid(self) -> bool {
+        (-90.0..=90.0).contains(&self.lat) && (-180.0..=180.0).contains(&self.lon)
+    }
+
+    /// Great-circle distance to `other`, via the haversine formula
+    pub fn distance_km(self, other: GeoPoint) -> f64 {
+        let (lat1, lat2) = (self.lat.to_radians(), other.lat.to_radians());
+        let dlat = lat2 - lat1;
+        let dlon = (other.lon - self.lon).to_radians();
+
+        let a = (dlat / 2.0).sin().powi(2) + lat1.cos() * lat2.cos() * (dlon / 2.0).sin().powi(2);
+        2.0 * EARTH_RADIUS_KM * a.sqrt().asin()
+    }
+
+    /// Packs the point into a u64 fast field value, latitude on the
+    /// high bits so that a latitude band is a contiguous range.
+    /// Never zero, which is what documents without a location get
+    pub fn encode(self) -> u64 {
+        u64::from(quantize_lat(self.lat)) << 32 | u64::from(quantize(self.lon, 180.0))
+    }
+
+    /// The inverse of `encode`, with ~1cm precision. None for zero
+    pub fn decode(value: u64) -> Option<GeoPoint> {
+        if value == 0 {
+            return None;
+        }
+
+        Some(GeoPoint {
+            lat: dequantize((value >> 32) as u32, 90.0),
+            lon: dequantize(value as u32, 180.0),
+        })
+    }
+}
+
+fn quantize_lat(lat: f64) -> u32 {
+    quantize(lat, 90.0)
+}
+
+fn quantize(degrees: f64, max: f64) -> u32 {
+    let clamped = degrees.max(-max).min(max);
+    1 + ((clamped + max) / (2.0 * max) * QUANTIZATION_STEPS).round() as u32
+}
+
+fn dequantize(value: u32, max: f64) -> f64 {
+    f64::from(value - 1) / QUANTIZATION_STEPS * 2.0 * max - max
+}
+
+/// Restricts the location to a circle
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct GeoDistanceFilter {
+    pub center: GeoPoint,
+    pub radius_km: f64,
+}
+
+impl GeoDistanceFilter {
+    pub fn matches(&self, point: GeoPoint) -> bool {
+        self.center.distance_km(point) <= self.radius_km
+    }
+
+    /// Encoded values of every location within the latitude band
+    /// that contains the circle
+    fn encoded_band(&self) -> std::ops::Range<u64> {
+        // A tad wider than needed, so that rounding never leaves
+        // matching points out
+        let delta = (self.radius_km / EARTH_RADIUS_KM).to_degrees() * 1.01 + 1e-6;
+
+        let start = quantize_lat(self.center.lat - delta);
+        let end = quantize_lat(self.center.lat + delta) + 1;
+
+        u64::from(start) << 32..u64::from(end) << 32
+    }
+}
+
+/// A condition for tique's conditional collectors that only lets the
+/// documents whose location (a `FAST` field holding `GeoPoint::encode`
+/// values) is within `radius_km` of `lat`, `lon` through. Unlike a
+/// `GeoDistanceQuery` it doesn't narrow the candidates down, so it's
+/// for when the query alone already matches few documents
+pub fn within_radius<T: 'static>(
+    field: Field,
+    lat: f64,
+    lon: f64,
+    radius_km: f64,
+) -> impl ConditionForSegment<T> {
+    let filter = GeoDistanceFilter {
+        center: GeoPoint { lat, lon },
+        radius_km,
+    };
+
+    move |reader: &SegmentReader| {
+        let location_reader = reader
+            .fast_fields()
+            .u64(field)
+            .expect("location field is indexed with the FAST flag");
+
+        move |_segment_id: SegmentLocalId, doc_id: DocId, _score: T, _ascending: bool| {
+            GeoPoint::decode(location_reader.get(doc_id))
+                .map_or(false, |point| filter.matches(point))
+        }
+    }
+}
+
+/// Matches documents whose location (a field holding
+/// `GeoPoint::encode` values, `INDEXED` and `FAST`) is within the
+/// filter's circle. The index narrows the candidates down to a
+/// latitude band and the exact distance is checked via the fast
+/// field.
+#[derive(Debug, Clone)]
+pub struct GeoDistanceQuery {
+    field: Field,
+    filter: GeoDistanceFilter,
+}
+
+impl GeoDistanceQuery {
+    pub fn new(field: Field, filter: GeoDistanceFilter) -> Self {
+        Self { field, filter }
+    }
+}
+
+impl Query for GeoDistanceQuery {
+    fn weight(&self, searcher: &Searcher, scoring_enabled: bool) -> Result<Box<dyn Weight>> {
+        let band = RangeQuery::new_u64(self.field, self.filter.encoded_band());
+
+        Ok(Box::new(GeoDistanceWeight {
+            band: band.weight(searcher, scoring_enabled)?,
+            field: self.field,
+            filter: self.filter,
+        }))
+    }
+}
+
+struct GeoDistanceWeight {
+    band: Box<dyn Weight>,
+    field: Field,
+    filter: GeoDistanceFilter,
+}
+
+impl Weight for GeoDistanceWeight {
+    fn scorer(&self, reader: &SegmentReader, boost: f32) -> Result<Box<dyn Scorer>> {
+        let locations = reader.fast_fields().u64(self.field).ok_or_else(|| {
+            TantivyError::SchemaError(format!("{:?} is not a u64 fast field", self.field))
+        })?;
+
+        Ok(Box::new(GeoDistanceScorer::new(
+            self.band.scorer(reader, boost)?,
+            locations,
+            self.filter,
+        )))
+    }
+
+    fn explain(&self, reader: &SegmentReader, doc: DocId) -> Result<Explanation> {
+        let mut scorer = self.scorer(reader, 1.0)?;
+
+        if scorer.doc() > doc || scorer.seek(doc) != doc {
+            return Err(TantivyError::InvalidArgument("Not a match".to_owned()));
+        }
+
+        Ok(Explanation::new("GeoDistanceQuery", scorer.score()))
+    }
+}
+
+struct GeoDistanceScorer {
+    band: Box<dyn Scorer>,
+    locations: FastFieldReader<u64>,
+    filter: GeoDistanceFilter,
+}
+
+impl GeoDistanceScorer {
+    fn new(
+        band: Box<dyn Scorer>,
+        locations: FastFieldReader<u64>,
+        filter: GeoDistanceFilter,
+    ) -> Self {
+        let mut scorer = Self {
+            band,
+            locations,
+            filter,
+        };
+
+        // Scorers start positioned at their first match
+        if scorer.doc() != TERMINATED && !scorer.matches(scorer.doc()) {
+            scorer.advance();
+        }
+
+        scorer
+    }
+
+    fn matches(&self, doc: DocId) -> bool {
+        GeoPoint::decode(self.locations.get(doc)).map_or(false, |point| self.filter.matches(point))
+    }
+}
+
+impl Scorer for GeoDistanceScorer {
+    fn score(&mut self) -> Score {
+        self.band.score()
+    }
+}
+
+impl DocSet for GeoDistanceScorer {
+    fn advance(&mut self) -> DocId {
+        loop {
+            let doc = self.band.advance();
+            if doc == TERMINATED || self.matches(doc) {
+                return doc;
+            }
+        }
+    }
+
+    fn doc(&self) -> DocId {
+        self.band.doc()
+    }
+
+    fn size_hint(&self) -> u32 {
+        self.band.size_hint()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use tique::conditional_collector::{Descending, TopCollector};
+
+    use tantivy::{
+        collector::Count,
+        doc,
+        query::AllQuery,
+        schema::{SchemaBuilder, FAST, INDEXED},
+        Document, Index,
+    };
+
+    const LISBON: GeoPoint = GeoPoint {
+        lat: 38.7223,
+        lon: -9.1393,
+    };
+    const PORTO: GeoPoint = GeoPoint {
+        lat: 41.1579,
+        lon: -8.6291,
+    };
+    const MADRID: GeoPoint = GeoPoint {
+        lat: 40.4168,
+        lon: -3.7038,
+    };
+
+    #[test]
+    fn distance() {
+        assert_eq!(0.0, LISBON.distance_km(LISBON));
+        // Roughly 274km apart
+        assert!((LISBON.distance_km(PORTO) - 274.0).abs() < 2.0);
+        assert_eq!(LISBON.distance_km(MADRID), MADRID.distance_km(LISBON));
+    }
+
+    #[test]
+    fn encoding_round_trip() {
+        for point in &[
+            LISBON,
+            PORTO,
+            GeoPoint {
+                lat: -90.0,
+                lon: -180.0,
+            },
+            GeoPoint {
+                lat: 90.0,
+                lon: 180.0,
+            },
+        ] {
+            let encoded = point.encode();
+            assert_ne!(0, encoded);
+
+            let decoded = GeoPoint::decode(encoded).unwrap();
+            assert!(point.distance_km(decoded) < 0.001);
+        }
+
+        assert_eq!(None, GeoPoint::decode(0));
+
+        // Latitude rules the order
+        assert!(LISBON.encode() < MADRID.encode());
+        assert!(MADRID.encode() < PORTO.encode());
+    }
+
+    #[test]
+    fn geo_distance_query() -> Result<()> {
+        let mut builder = SchemaBuilder::new();
+        let location = builder.add_u64_field("location", INDEXED | FAST);
+        let index = Index::create_in_ram(builder.build());
+        let mut writer = index.writer_with_num_threads(1, 3_000_000)?;
+
+        for point in &[LISBON, PORTO, MADRID] {
+            writer.add_document(doc!(location => point.encode()));
+        }
+        // No location
+        writer.add_document(Document::new());
+        writer.commit()?;
+
+        let searcher = index.reader()?.searcher();
+        let count = |center, radius_km| {
+            let query = GeoDistanceQuery::new(location, GeoDistanceFilter { center, radius_km });
+            searcher.search(&query, &Count)
+        };
+
+        assert_eq!(1, count(LISBON, 100.0)?);
+        assert_eq!(2, count(LISBON, 300.0)?);
+        assert_eq!(3, count(LISBON, 600.0)?);
+        // Madrid is within the latitude band, but too far
+        assert_eq!(2, count(PORTO, 300.0)?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn within_radius_condition() -> Result<()> {
+        let mut builder = SchemaBuilder::new();
+        let location = builder.add_u64_field("location", FAST);
+        let index = Index::create_in_ram(builder.build());
+        let mut writer = index.writer_with_num_threads(1, 3_000_000)?;
+
+        for point in &[LISBON, PORTO, MADRID] {
+            writer.add_document(doc!(location => point.encode()));
+        }
+        writer.add_document(Document::new());
+        writer.commit()?;
+
+        let searcher = index.reader()?.searcher();
+        let near = |center: GeoPoint, radius_km| {
+            let condition = within_radius(location, center.lat, center.lon, radius_km);
+            let collector = TopCollector::<Score, Descending, _>::new(10, condition);
+            searcher
+                .search(&AllQuery, &collector)
+                .map(|result| result.items.len())
+        };
+
+        assert_eq!(1, near(LISBON, 100.0)?);
+        assert_eq!(2, near(LISBON, 300.0)?);
+        assert_eq!(3, near(LISBON, 600.0)?);
+        assert_eq!(0, near(GeoPoint { lat: 0.0, lon: 0.0 }, 1000.0)?);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,360 @@
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+use tantivy::{
+    collector::{Collector, SegmentCollector},
+    fastfield::FastFieldReader,
+    schema::Field,
+    DocId, Result, Score, SegmentLocalId, SegmentReader, TantivyError,
+};
+
+use crate::clock::{self, SECONDS_PER_DAY};
+
+/// The width of each bucket of a date histogram
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Interval {
+    Day,
+    /// Weeks start on monday
+    Week,
+    Month,
+}
+
+impl Interval {
+    /// The start of the bucket `ts` falls into
+    pub fn bucket_start(self, ts: u64) -> u64 {
+        match self {
+            Interval::Day => clock::start_of_day(ts),
+            Interval::Week => clock::start_of_week(ts),
+            Interval::Month => clock::start_of_month(ts),
+        }
+    }
+
+    /// The start of the bucket after the one starting at `start`
+    pub fn next_bucket(self, start: u64) -> u64 {
+        match self {
+            Interval::Day => start + SECONDS_PER_DAY,
+            Interval::Week => start + 7 * SECONDS_PER_DAY,
+            Interval::Month => clock::add_months(start, 1),
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+pub struct Bucket {
+    /// When the bucket starts, in seconds since the epoch
+    pub start: u64,
+    pub count: u64,
+}
+
+/// Counts of documents per interval
+#[derive(Debug, Clone, PartialEq)]
+pub struct DateHistogram {
+    interval: Interval,
+    counts: BTreeMap<u64, u64>,
+    missing: u64,
+}
+
+impl DateHistogram {
+    fn new(interval: Interval) -> Self {
+        Self {
+            interval,
+            counts: BTreeMap::new(),
+            missing: 0,
+        }
+    }
+
+    /// Every bucket from the earliest to the latest non-empty one,
+    /// in order. Gaps are filled with empty buckets so that the
+    /// result can be plotted as-is
+    pub fn buckets(&self) -> Vec<Bucket> {
+        let (first, last) = match (self.counts.keys().next(), self.counts.keys().last()) {
+            (Some(first), Some(last)) => (*first, *last),
+            _ => return Vec::new(),
+        };
+
+        let mut buckets = Vec::new();
+        let mut start = first;
+        while start <= last {
+            buckets.push(Bucket {
+                start,
+                count: self.counts.get(&start).copied().unwrap_or(0),
+            });
+            start = self.interval.next_bucket(start);
+        }
+
+        buckets
+    }
+
+    /// How many documents were counted
+    pub fn total(&self) -> u64 {
+        self.counts.values().sum()
+    }
+
+    /// How many documents were left out for not having a value
+    pub fn missing(&self) -> u64 {
+        self.missing
+    }
+
+    fn merge(&mut self, other: &Self) {
+        self.missing += other.missing;
+        for (start, count) in other.counts.iter() {
+            *self.counts.entry(*start).or_insert(0) += count;
+        }
+    }
+}
+
+/// Buckets the matching documents by the timestamp (seconds since
+/// the epoch) in a u64 fast field.
+///
+/// Documents without a value are read as `0` by tantivy, so every
+/// document with a `0` is skipped unless the presence of the value
+/// is known via `with_presence`.
+pub struct DateHistogramCollector {
+    field: Field,
+    interval: Interval,
+    presence: Option<(Field, u64)>,
+}
+
+impl DateHistogramCollector {
+    pub fn new(field: Field, interval: Interval) -> Self {
+        Self {
+            field,
+            interval,
+            presence: None,
+        }
+    }
+
+    /// Tells documents without a value apart by checking `mask`
+    /// against the bits in the `presence` u64 fast field
+    pub fn with_presence(mut self, presence: Field, mask: u64) -> Self {
+        self.presence = Some((presence, mask));
+        self
+    }
+}
+
+impl Collector for DateHistogramCollector {
+    type Fruit = DateHistogram;
+    type Child = DateHistogramSegmentCollector;
+
+    fn for_segment(
+        &self,
+        _segment_id: SegmentLocalId,
+        segment_reader: &SegmentReader,
+    ) -> Result<Self::Child> {
+        let reader = segment_reader
+            .fast_fields()
+            .u64(self.field)
+            .ok_or_else(|| {
+                TantivyError::SchemaError(format!("{:?} is not a u64 fast field", self.field))
+            })?;
+
+        let presence = match self.presence {
+            Some((field, mask)) => {
+                let presence_reader = segment_reader.fast_fields().u64(field).ok_or_else(|| {
+                    TantivyError::SchemaError(format!("{:?} is not a u64 fast field", field))
+                })?;
+                Some((presence_reader, mask))
+            }
+            None => None,
+        };
+
+        Ok(DateHistogramSegmentCollector {
+            reader,
+            presence,
+            histogram: DateHistogram::new(self.interval),
+            current: None,
+        })
+    }
+
+    fn requires_scoring(&self) -> bool {
+        false
+    }
+
+    fn merge_fruits(&self, fruits: Vec<DateHistogram>) -> Result<DateHistogram> {
+        let mut merged = DateHistogram::new(self.interval);
+        for fruit in fruits.iter() {
+            merged.merge(fruit);
+        }
+        Ok(merged)
+    }
+}
+
+pub struct DateHistogramSegmentCollector {
+    reader: FastFieldReader<u64>,
+    presence: Option<(FastFieldReader<u64>, u64)>,
+    histogram: DateHistogram,
+    // The bucket (start, end) of the previous doc: neighbouring
+    // documents tend to have been added around the same time, so
+    // this saves most of the calendar math
+    current: Option<(u64, u64)>,
+}
+
+impl SegmentCollector for DateHistogramSegmentCollector {
+    type Fruit = DateHistogram;
+
+    fn collect(&mut self, doc: DocId, _score: Score) {
+        let ts = self.reader.get(doc);
+        let is_missing = match &self.presence {
+            Some((reader, mask)) => reader.get(doc) & mask == 0,
+            None => ts == 0,
+        };
+
+        if is_missing {
+            self.histogram.missing += 1;
+            return;
+        }
+
+        let start = match self.current {
+            Some((start, end)) if start <= ts && ts < end => start,
+            _ => {
+                let interval = self.histogram.interval;
+                let start = interval.bucket_start(ts);
+                self.current = Some((start, interval.next_bucket(start)));
+                start
+            }
+        };
+
+        *self.histogram.counts.entry(start).or_insert(0) += 1;
+    }
+
+    fn harvest(self) -> DateHistogram {
+        self.histogram
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use tantivy::{
+        doc,
+        query::AllQuery,
+        schema::{SchemaBuilder, FAST},
+        Index,
+    };
+
+    // 2020-02-29T13:37:00Z, a saturday
+    const LEAP_DAY: u64 = 1_582_983_420;
+
+    #[test]
+    fn histogram() -> Result<()> {
+        let mut builder = SchemaBuilder::new();
+        let added_at = builder.add_u64_field("added_at", FAST);
+        let index = Index::create_in_ram(builder.build());
+        let mut writer = index.writer_with_num_threads(1, 3_000_000)?;
+
+        let timestamps = [
+            LEAP_DAY,
+            LEAP_DAY + 60,
+            // 2020-03-01, sunday
+            LEAP_DAY + SECONDS_PER_DAY,
+            // 2020-03-02, monday
+            LEAP_DAY + 2 * SECONDS_PER_DAY,
+            // 2020-05-01
+            1_588_291_200,
+        ];
+        for (idx, ts) in timestamps.iter().enumerate() {
+            writer.add_document(doc!(added_at => *ts));
+            // Multiple segments
+            if idx == 1 {
+                writer.commit()?;
+            }
+        }
+        // Missing values are ignored
+        writer.add_document(doc!());
+        writer.commit()?;
+
+        let searcher = index.reader()?.searcher();
+
+        let by_month = searcher.search(
+            &AllQuery,
+            &DateHistogramCollector::new(added_at, Interval::Month),
+        )?;
+        assert_eq!(5, by_month.total());
+        assert_eq!(1, by_month.missing());
+        assert_eq!(
+            vec![
+                Bucket {
+                    start: 1_580_515_200,
+                    count: 2
+                },
+                Bucket {
+                    start: 1_583_020_800,
+                    count: 2
+                },
+                // April is empty
+                Bucket {
+                    start: 1_585_699_200,
+                    count: 0
+                },
+                Bucket {
+                    start: 1_588_291_200,
+                    count: 1
+                },
+            ],
+            by_month.buckets()
+        );
+
+        let by_week = searcher.search(
+            &AllQuery,
+            &DateHistogramCollector::new(added_at, Interval::Week),
+        )?;
+        let buckets = by_week.buckets();
+        assert_eq!(Some(3), buckets.first().map(|b| b.count));
+        assert_eq!(Some(1), buckets.get(1).map(|b| b.count));
+        assert!(buckets
+            .windows(2)
+            .all(|pair| pair[1].start - pair[0].start == 7 * SECONDS_PER_DAY));
+
+        let by_day = searcher.search(
+            &AllQuery,
+            &DateHistogramCollector::new(added_at, Interval::Day),
+        )?;
+        assert_eq!(
+            vec![2, 1, 1],
+            by_day.buckets()[..3]
+                .iter()
+                .map(|b| b.count)
+                .collect::<Vec<_>>()
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn presence_tells_zeros_and_missing_values_apart() -> Result<()> {
+        let mut builder = SchemaBuilder::new();
+        let added_at = builder.add_u64_field("added_at", FAST);
+        let present = builder.add_u64_field("present", FAST);
+        let index = Index::create_in_ram(builder.build());
+        let mut writer = index.writer_with_num_threads(1, 3_000_000)?;
+
+        writer.add_document(doc!(added_at => 0u64, present => 0b10u64));
+        writer.add_document(doc!(added_at => SECONDS_PER_DAY, present => 0b10u64));
+        // Has something else, but not added_at
+        writer.add_document(doc!(present => 0b01u64));
+        writer.commit()?;
+
+        let searcher = index.reader()?.searcher();
+
+        let histogram = searcher.search(
+            &AllQuery,
+            &DateHistogramCollector::new(added_at, Interval::Day).with_presence(present, 0b10),
+        )?;
+
+        assert_eq!(
+            vec![
+                Bucket { start: 0, count: 1 },
+                Bucket {
+                    start: SECONDS_PER_DAY,
+                    count: 1
+                }
+            ],
+            histogram.buckets()
+        );
+        assert_eq!(1, histogram.missing());
+
+        Ok(())
+    }
+}
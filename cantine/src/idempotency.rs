@@ -0,0 +1,197 @@
+use std::{collections::HashMap, fs, io::Result, path::PathBuf};
+
+use byteorder::NativeEndian;
+use zerocopy::{AsBytes, FromBytes, U64};
+
+use crate::{clock::Clock, database::StructuredLog};
+
+/// Remembers the keys of recently applied requests, so that retries
+/// of a request are only applied once.
+///
+/// Keys are kept for `window` seconds after they're first seen and
+/// persisted to an append-only log, which gets compacted once most
+/// of it is expired. Only hashes are stored, so keys can be of any
+/// length.
+pub struct IdempotencyKeys {
+    path: PathBuf,
+    window: u64,
+    clock: Box<dyn Clock>,
+    log: StructuredLog<KeyEntry>,
+    seen: HashMap<KeyHash, u64>,
+}
+
+type KeyHash = (u64, u64);
+
+#[derive(FromBytes, AsBytes)]
+#[repr(C)]
+struct KeyEntry {
+    hash: U64<NativeEndian>,
+    check: U64<NativeEndian>,
+    seen_at: U64<NativeEndian>,
+}
+
+impl IdempotencyKeys {
+    /// Opens (or creates) the key log at `path`, remembering keys
+    /// for `window` seconds as told by `clock`
+    pub fn open<P: Into<PathBuf>>(path: P, window: u64, clock: Box<dyn Clock>) -> Result<Self> {
+        let path = path.into();
+        let log = StructuredLog::new(&path)?;
+
+        let mut seen = HashMap::new();
+        log.for_each_entry(|entry: &KeyEntry| {
+            seen.insert((entry.hash.get(), entry.check.get()), entry.seen_at.get());
+        })?;
+
+        Ok(Self {
+            path,
+            window,
+            clock,
+            log,
+            seen,
+        })
+    }
+
+    /// Changes for how long keys are remembered, in seconds
+    pub fn set_window(&mut self, window: u64) {
+        self.window = window;
+    }
+
+    /// Whether `key` was seen within the window
+    pub fn contains(&self, key: &str) -> bool {
+        let oldest = self.clock.now().saturating_sub(self.window);
+        self.seen
+            .get(&hash(key))
+            .map_or(false, |&seen_at| seen_at >= oldest)
+    }
+
+    /// Records `key` as seen now. It's durable once this returns
+    pub fn insert(&mut self, key: &str) -> Result<()> {
+        let hash = hash(key);
+        let now = self.clock.now();
+
+        self.log.append(&KeyEntry::new(hash, now))?;
+        self.log.sync()?;
+        self.seen.insert(hash, now);
+
+        self.compact_if_needed()
+    }
+
+    /// How many keys are within the window
+    pub fn len(&self) -> usize {
+        let oldest = self.clock.now().saturating_sub(self.window);
+        self.seen
+            .values()
+            .filter(|&&seen_at| seen_at >= oldest)
+            .count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // Rewrites the log without the expired keys once they're the
+    // majority of it
+    fn compact_if_needed(&mut self) -> Result<()> {
+        let oldest = self.clock.now().saturating_sub(self.window);
+        self.seen.retain(|_hash, seen_at| *seen_at >= oldest);
+
+        if self.log.len()? <= 2 * self.seen.len().max(MIN_ENTRIES_TO_COMPACT) {
+            return Ok(());
+        }
+
+        let compacted_path = self.path.with_extension("compacting");
+        let mut compacted = StructuredLog::new(&compacted_path)?;
+        compacted.clear()?;
+        for (&hash, &seen_at) in &self.seen {
+            compacted.append(&KeyEntry::new(hash, seen_at))?;
+        }
+        compacted.sync()?;
+
+        fs::rename(&compacted_path, &self.path)?;
+        self.log = StructuredLog::new(&self.path)?;
+
+        Ok(())
+    }
+}
+
+const MIN_ENTRIES_TO_COMPACT: usize = 512;
+
+impl KeyEntry {
+    fn new((hash, check): KeyHash, seen_at: u64) -> Self {
+        Self {
+            hash: U64::new(hash),
+            check: U64::new(check),
+            seen_at: U64::new(seen_at),
+        }
+    }
+}
+
+// FNV-1a, which (unlike std's hashers) is stable across releases, as
+// needed for persisting. The key is hashed twice with different
+// offsets so that collisions are practically impossible
+fn hash(key: &str) -> KeyHash {
+    (
+        fnv1a(0xcbf2_9ce4_8422_2325, key),
+        fnv1a(0x6c62_272e_07bb_0142, key),
+    )
+}
+
+fn fnv1a(offset: u64, key: &str) -> u64 {
+    key.bytes().fold(offset, |hash, byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::clock::FixedClock;
+
+    const DAY: u64 = 86_400;
+
+    #[test]
+    fn keys_are_remembered_within_the_window() -> Result<()> {
+        let tmpdir = tempfile::tempdir()?;
+        let path = tmpdir.path().join("keys.bin");
+
+        let mut keys = IdempotencyKeys::open(&path, DAY, Box::new(FixedClock(DAY)))?;
+        assert!(!keys.contains("upload-1"));
+
+        keys.insert("upload-1")?;
+        assert!(keys.contains("upload-1"));
+        assert!(!keys.contains("upload-2"));
+
+        // Persisted
+        drop(keys);
+        let keys = IdempotencyKeys::open(&path, DAY, Box::new(FixedClock(DAY + 10)))?;
+        assert!(keys.contains("upload-1"));
+        assert_eq!(1, keys.len());
+
+        // Expired
+        let keys = IdempotencyKeys::open(&path, DAY, Box::new(FixedClock(3 * DAY)))?;
+        assert!(!keys.contains("upload-1"));
+        assert!(keys.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn expired_keys_get_compacted_away() -> Result<()> {
+        let tmpdir = tempfile::tempdir()?;
+        let path = tmpdir.path().join("keys.bin");
+
+        let mut keys = IdempotencyKeys::open(&path, DAY, Box::new(FixedClock(DAY)))?;
+        for i in 0..2000 {
+            keys.insert(&format!("old-{}", i))?;
+        }
+        drop(keys);
+
+        let mut keys = IdempotencyKeys::open(&path, DAY, Box::new(FixedClock(3 * DAY)))?;
+        keys.insert("new")?;
+        assert_eq!(1, keys.log.len()?);
+        assert!(keys.contains("new"));
+
+        Ok(())
+    }
+}
@@ -1,25 +1,76 @@
-use std::{cmp::Ordering, convert::TryFrom};
+use std::{
+    cmp::Ordering,
+    collections::{BTreeMap, BTreeSet, HashMap},
+    convert::TryFrom,
+    fs,
+    path::Path,
+    sync::{
+        atomic::{AtomicBool, Ordering as AtomicOrdering},
+        Arc, Mutex,
+    },
+    time::{Instant, UNIX_EPOCH},
+};
 
 use bincode;
 use serde::{Deserialize, Serialize};
 use tantivy::{
     self,
-    collector::Collector,
-    fastfield::FastFieldReader,
-    query::Query,
-    schema::{Field, Schema, SchemaBuilder, Value, FAST, INDEXED, STORED, TEXT},
-    DocId, Document, Result, Score, Searcher, SegmentLocalId, SegmentReader, TantivyError,
+    collector::{Collector, Count, MultiCollector, TopDocs},
+    fastfield::{DeleteBitSet, FastFieldReader},
+    query::{
+        AllQuery, BooleanQuery, BoostQuery, Occur, Query, RangeQuery, Scorer, TermQuery, Weight,
+    },
+    schema::{
+        Field, FieldType, IndexRecordOption, Schema, SchemaBuilder, TextFieldIndexing, TextOptions,
+        Value, FAST, INDEXED, STORED,
+    },
+    tokenizer::TextAnalyzer,
+    DocAddress, DocId, DocSet, Document, Index, IndexReader, IndexWriter, Score, Searcher,
+    SegmentLocalId, SegmentReader, TantivyError, Term, TERMINATED,
 };
 
+use crate::analysis::Analysis;
+use crate::blocking::{Blocking, BlockingPool};
+use crate::collation::{key_prefix, Collation, NameSortCollector};
+use crate::database::DatabaseReader;
+use crate::error::{Error, Result};
+use crate::filters::FilterBucketsCollector;
+use crate::geo::{self, GeoDistanceQuery, GeoPoint};
+use crate::histogram::{DateHistogram, DateHistogramCollector, Interval};
+use crate::instant;
+use crate::metrics;
 use crate::model::{
-    Features, FeaturesAggregationQuery, FeaturesAggregationResult, FeaturesFilterFields, Recipe,
-    RecipeId, Sort,
+    Author, AuthorId, Features, FeaturesAggregationQuery, FeaturesAggregationResult,
+    FeaturesFilterFields, FeaturesFilterQuery, FeaturesPercentiles, FilterCheck, GroupField,
+    NumericFeature, PercentileSummary, PresenceField, Recipe, RecipeExplanation, RecipeId, Sort,
+    TermMatch, TotalCount,
 };
+use crate::nutrition::MacroProfile;
+#[cfg(feature = "parallel")]
+use crate::parallel;
+use crate::popularity::{self, PopularityStore};
+use crate::recency::RecencyBoost;
+use crate::runtime::{RuntimeField, RuntimeFilterQuery};
+use crate::snapshot::META_FILE;
+use crate::stats::{GlobalStats, IndexStats};
+use crate::tenant::{self, TenantCollector, TenantId, DEFAULT_TENANT};
+use crate::warmup::{self, WarmupOptions, WarmupStats};
 
 use cantine_derive::{AggregableCollector, Filterable};
 
-use tique::conditional_collector::{
-    Ascending, CheckCondition, CollectionResult, ConditionForSegment, Descending, TopCollector,
+use tique::{
+    buckets::{BucketOrder, TopHitsPerBucket},
+    budget::{Budget, BudgetCollector},
+    conditional_collector::{
+        Ascending, CheckCondition, CollectionResult, ConditionForSegment, Descending, TopCollector,
+        TwoPhaseTopDocs,
+    },
+    expression::ScoreExpression,
+    partial::{PartialCollector, SegmentFailure},
+    percentiles::{PercentileCollector, TDigest},
+    rescore::RescoringCollector,
+    timing::{CollectionTimings, TimedCollector},
+    topterms, FuzzyPrefixQuery,
 };
 
 #[derive(Clone)]
@@ -29,9 +80,199 @@ pub struct RecipeIndex {
     pub name: Field,
     pub ingredients: Field,
     pub instructions: Field,
+    /// Every prefix of the words in the name. See `instant_search`
+    pub name_prefix: Field,
 
     pub features_bincode: Field,
     pub features: FeaturesFilterFields,
+    /// Which optional features each recipe has, as bits
+    pub features_present: Field,
+
+    pub author_id: Field,
+    /// Denormalized from the author profile: 1 if verified
+    pub author_verified: Field,
+
+    /// The id of the base recipe for variants, the recipe's own id
+    /// otherwise. Groups a recipe with every variant of it
+    pub parent_id: Field,
+
+    /// Where the dish comes from, encoded via `GeoPoint::encode`
+    pub origin: Field,
+
+    /// The parts the recipe has, as `PresenceField` values
+    pub present: Field,
+
+    /// Baked from the popularity keyspace. See `popularity::bake`
+    pub popularity: Field,
+
+    /// Who the recipe belongs to. See `TenantScopedIndex`
+    pub tenant_id: Field,
+
+    pub name_collation_key: Field,
+    pub collation: Collation,
+
+    pub two_phase_sample: Option<usize>,
+    pub timings: Option<TimingsRecorder>,
+    pub skipped_segments: Option<SkippedSegments>,
+    pub budget: Option<SearchBudget>,
+    /// Set for the index of a `TenantScopedIndex`
+    pub(crate) tenant_scope: Option<TenantId>,
+    #[cfg(feature = "parallel")]
+    pub parallel: bool,
+
+    /// Custom tokenizers, by name. See `register_tokenizer`
+    pub tokenizers: Vec<(String, TextAnalyzer)>,
+}
+
+/// Keeps the collection timings of the searches done via a
+/// `RecipeIndex` set up `with_timings`
+#[derive(Clone, Default)]
+pub struct TimingsRecorder(Arc<Mutex<Vec<CollectionTimings>>>);
+
+impl TimingsRecorder {
+    fn record(&self, timings: CollectionTimings) {
+        self.0.lock().expect("lock not poisoned").push(timings);
+    }
+
+    /// Every timing recorded so far, oldest first
+    pub fn take(&self) -> Vec<CollectionTimings> {
+        std::mem::take(&mut *self.0.lock().expect("lock not poisoned"))
+    }
+}
+
+/// Keeps the segments that top recipes searches done via a
+/// `RecipeIndex` set up `with_skipped_segments` failed on
+#[derive(Clone, Default)]
+pub struct SkippedSegments(Arc<Mutex<Vec<SegmentFailure>>>);
+
+impl SkippedSegments {
+    fn record(&self, failures: Vec<SegmentFailure>) {
+        for failure in &failures {
+            log::error!(
+                "Skipped segment {} of a search: {}",
+                failure.segment,
+                failure.error
+            );
+        }
+        self.0.lock().expect("lock not poisoned").extend(failures);
+    }
+
+    /// Every failure recorded so far, oldest first
+    pub fn take(&self) -> Vec<SegmentFailure> {
+        std::mem::take(&mut *self.0.lock().expect("lock not poisoned"))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.lock().expect("lock not poisoned").is_empty()
+    }
+}
+
+/// Limits the top recipes searches done via a `RecipeIndex` set up
+/// `with_budget`, remembering whether any of them had to stop early
+#[derive(Clone)]
+pub struct SearchBudget {
+    budget: Budget,
+    exhausted: Arc<AtomicBool>,
+}
+
+impl SearchBudget {
+    pub fn new(budget: Budget) -> Self {
+        Self {
+            budget,
+            exhausted: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    fn record(&self, exhausted: bool) {
+        if exhausted {
+            self.exhausted.store(true, AtomicOrdering::Relaxed);
+        }
+    }
+
+    /// Whether some search stopped before visiting every match
+    pub fn is_exhausted(&self) -> bool {
+        self.exhausted.load(AtomicOrdering::Relaxed)
+    }
+}
+
+/// The recipes matching a query, in batches. See `RecipeIndex::scan`
+pub struct Scan<'a> {
+    searcher: &'a Searcher,
+    id: Field,
+    weight: Box<dyn Weight>,
+    batch_size: usize,
+    // The next segment to go through
+    segment: usize,
+    current: Option<ScanSegment<'a>>,
+}
+
+struct ScanSegment<'a> {
+    scorer: Box<dyn Scorer>,
+    ids: FastFieldReader<u64>,
+    deleted: Option<&'a DeleteBitSet>,
+}
+
+impl<'a> Scan<'a> {
+    fn next_segment(&mut self) -> Result<Option<ScanSegment<'a>>> {
+        let reader = match self.searcher.segment_readers().get(self.segment) {
+            Some(reader) => reader,
+            None => return Ok(None),
+        };
+        self.segment += 1;
+
+        let ids = reader.fast_fields().u64(self.id).ok_or_else(|| {
+            TantivyError::SchemaError(format!("{:?} is not a u64 fast field", self.id))
+        })?;
+
+        Ok(Some(ScanSegment {
+            scorer: self.weight.scorer(reader, 1.0)?,
+            ids,
+            deleted: reader.delete_bitset(),
+        }))
+    }
+}
+
+impl<'a> Iterator for Scan<'a> {
+    type Item = Result<Vec<RecipeId>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut batch = Vec::with_capacity(self.batch_size);
+
+        while batch.len() < self.batch_size {
+            if self.current.is_none() {
+                match self.next_segment() {
+                    Ok(Some(segment)) => self.current = Some(segment),
+                    Ok(None) => break,
+                    Err(err) => {
+                        // Don't keep going after an error
+                        self.segment = self.searcher.segment_readers().len();
+                        return Some(Err(err));
+                    }
+                }
+            }
+
+            let current = self.current.as_mut().expect("segment set above");
+            let doc = current.scorer.doc();
+            if doc == TERMINATED {
+                self.current = None;
+                continue;
+            }
+
+            if current
+                .deleted
+                .map_or(true, |deleted| deleted.is_alive(doc))
+            {
+                batch.push(current.ids.get(doc));
+            }
+            current.scorer.advance();
+        }
+
+        if batch.is_empty() {
+            None
+        } else {
+            Some(Ok(batch))
+        }
+    }
 }
 
 const FIELD_ID: &str = "id";
@@ -39,93 +280,1129 @@ const FIELD_NAME: &str = "name";
 const FIELD_INGREDIENTS: &str = "ingredients";
 const FIELD_INSTRUCTIONS: &str = "instructions";
 const FIELD_FEATURES_BINCODE: &str = "features_bincode";
+const FIELD_FEATURES_PRESENT: &str = "features_present";
+const FIELD_NAME_COLLATION_KEY: &str = "name_collation_key";
+const FIELD_AUTHOR_ID: &str = "author_id";
+const FIELD_AUTHOR_VERIFIED: &str = "author_verified";
+const FIELD_PARENT_ID: &str = "parent_id";
+const FIELD_ORIGIN: &str = "origin";
+const FIELD_PRESENT: &str = "present";
+const FIELD_POPULARITY: &str = "popularity";
+const FIELD_TENANT_ID: &str = "tenant_id";
+const FIELD_NAME_PREFIX: &str = "name_prefix";
+
+// How many of the terms of a recipe `similar_to` searches for
+const MAX_SIMILARITY_KEYWORDS: usize = 20;
 
 impl RecipeIndex {
+    /// Like `make_document_with_author`, without the author attributes
     pub fn make_document(&self, recipe: &Recipe) -> Document {
+        self.make_document_with_author(recipe, None)
+    }
+
+    /// Creates the document for the recipe, copying the attributes
+    /// of its author so that they can be filtered on. The documents
+    /// of an author's recipes must be recreated whenever the author
+    /// changes
+    pub fn make_document_with_author(&self, recipe: &Recipe, author: Option<&Author>) -> Document {
         let mut doc = Document::new();
         doc.add_u64(self.id, recipe.recipe_id);
 
-        doc.add_text(self.name, recipe.name.as_str());
+        doc.add_text(self.name, recipe.name.as_str());
+        for prefix in instant::name_prefixes(&recipe.name) {
+            doc.add_text(self.name_prefix, &prefix);
+        }
+
+        recipe
+            .ingredients
+            .iter()
+            .for_each(|i| doc.add_text(self.ingredients, i));
+
+        recipe
+            .instructions
+            .iter()
+            .for_each(|i| doc.add_text(self.instructions, i));
+
+        doc.add_bytes(
+            self.features_bincode,
+            bincode::serialize(&recipe.features).unwrap(),
+        );
+
+        doc.add_bytes(
+            self.name_collation_key,
+            self.collation.sort_key(recipe.name.as_str()),
+        );
+
+        self.features.add_to_doc(&mut doc, &recipe.features);
+        doc.add_u64(
+            self.features_present,
+            FeaturesFilterFields::presence(&recipe.features),
+        );
+
+        if let Some(author_id) = recipe.author_id {
+            doc.add_u64(self.author_id, author_id);
+        }
+
+        if let Some(author) = author.filter(|author| Some(author.author_id) == recipe.author_id) {
+            doc.add_u64(self.author_verified, u64::from(author.verified));
+        }
+
+        doc.add_u64(
+            self.parent_id,
+            recipe.variant_of.unwrap_or(recipe.recipe_id),
+        );
+
+        if let Some(origin) = recipe.origin.filter(|origin| origin.is_valid()) {
+            doc.add_u64(self.origin, origin.encode());
+        }
+
+        for field in PresenceField::VALUES.iter() {
+            if field.is_present(recipe) {
+                doc.add_u64(self.present, *field as u64);
+            }
+        }
+
+        doc.add_u64(self.tenant_id, recipe.tenant_id.unwrap_or(DEFAULT_TENANT));
+
+        doc
+    }
+
+    /// Sets the popularity of the recipe `doc` was made for. Recipes
+    /// without one have a popularity of zero
+    pub fn add_popularity(&self, doc: &mut Document, popularity: u64) {
+        doc.add_u64(self.popularity, popularity);
+    }
+
+    /// Matches the recipes that have every part in `exists` and lack
+    /// every one in `missing`
+    pub fn presence_query(
+        &self,
+        exists: &[PresenceField],
+        missing: &[PresenceField],
+    ) -> BooleanQuery {
+        let term_query = |field: &PresenceField| -> Box<dyn Query> {
+            Box::new(TermQuery::new(
+                Term::from_field_u64(self.present, *field as u64),
+                IndexRecordOption::Basic,
+            ))
+        };
+
+        let mut subqueries: Vec<(Occur, Box<dyn Query>)> = exists
+            .iter()
+            .map(|field| (Occur::Must, term_query(field)))
+            .chain(
+                missing
+                    .iter()
+                    .map(|field| (Occur::MustNot, term_query(field))),
+            )
+            .collect();
+
+        // Nothing matches a query made of exclusions only
+        if exists.is_empty() {
+            subqueries.push((Occur::Must, Box::new(AllQuery)));
+        }
+
+        BooleanQuery::from(subqueries)
+    }
+
+    /// Uses a different collation for the name sort key. Only affects
+    /// documents created after the change, so an index should always
+    /// be built with a single collation: the one `Collation::load`
+    /// reads for it.
+    pub fn with_collation(mut self, collation: Collation) -> Self {
+        self.collation = collation;
+        self
+    }
+
+    /// Makes relevance-sorted searches (the default) sample up to
+    /// `sample_size` matches per segment before doing the actual
+    /// collection. Faster for queries that match a large portion of
+    /// the index, but the reported total becomes a lower bound.
+    /// Tenant scoped indexes ignore it
+    pub fn with_two_phase(mut self, sample_size: Option<usize>) -> Self {
+        self.two_phase_sample = sample_size;
+        self
+    }
+
+    /// Times the collection of every top recipes search (except for
+    /// two-phase ones), keeping the timings in `recorder`
+    pub fn with_timings(mut self, recorder: TimingsRecorder) -> Self {
+        self.timings = Some(recorder);
+        self
+    }
+
+    /// Makes top recipes searches (except for two-phase ones) skip
+    /// the index segments they fail on, say because of corruption,
+    /// yielding what the healthy ones have instead of an error. The
+    /// failures are logged and kept in `recorder`, so that callers
+    /// can tell the results are partial
+    pub fn with_skipped_segments(mut self, recorder: SkippedSegments) -> Self {
+        self.skipped_segments = Some(recorder);
+        self
+    }
+
+    /// Makes top recipes searches (except for two-phase ones) stop
+    /// collecting once `budget` runs out, yielding the recipes and
+    /// total found up to that point. Whether that happened is kept
+    /// in `budget`, so that callers can tell the results are partial
+    pub fn with_budget(mut self, budget: SearchBudget) -> Self {
+        self.budget = Some(budget);
+        self
+    }
+
+    /// Makes searches (except for two-phase ones) collect every
+    /// segment of the index at the same time instead of one after
+    /// the other. See `parallel::search`
+    #[cfg(feature = "parallel")]
+    pub fn with_parallel_collection(mut self, parallel: bool) -> Self {
+        self.parallel = parallel;
+        self
+    }
+
+    /// Makes a custom tokenizer (shingles, edge ngrams, ...) available
+    /// under `name` to every index set up via `install_tokenizers`.
+    /// Fields added to the schema alongside the recipe fields refer to
+    /// it by name, as usual. Registering a name again replaces it
+    pub fn register_tokenizer<A: Into<TextAnalyzer>>(mut self, name: &str, tokenizer: A) -> Self {
+        self.tokenizers.retain(|(existing, _)| existing != name);
+        self.tokenizers.push((name.to_owned(), tokenizer.into()));
+        self
+    }
+
+    /// Registers the custom tokenizers with the index, then checks
+    /// that every tokenizer its schema needs is there. Must be
+    /// called (after `Analysis::register`) before writing to or
+    /// searching the index
+    ///
+    /// # Errors
+    ///
+    /// Fails with the names of the missing tokenizers, if any
+    pub fn install_tokenizers(&self, index: &Index) -> Result<()> {
+        let manager = index.tokenizers();
+        for (name, tokenizer) in &self.tokenizers {
+            manager.register(name, tokenizer.clone());
+        }
+
+        let missing: Vec<_> = Self::required_tokenizers(&index.schema())
+            .into_iter()
+            .filter(|name| manager.get(name).is_none())
+            .collect();
+
+        if missing.is_empty() {
+            Ok(())
+        } else {
+            Err(
+                TantivyError::SchemaError(format!("Missing tokenizers: {}", missing.join(", ")))
+                    .into(),
+            )
+        }
+    }
+
+    /// The names of the tokenizers the text fields of the schema
+    /// were created with. They're part of the schema, so an index
+    /// always knows which tokenizers it needs
+    pub fn required_tokenizers(schema: &Schema) -> BTreeSet<String> {
+        schema
+            .fields()
+            .filter_map(|(_field, entry)| match entry.field_type() {
+                FieldType::Str(options) => options.get_indexing_options(),
+                _ => None,
+            })
+            .map(|indexing| indexing.tokenizer().to_owned())
+            .collect()
+    }
+
+    pub fn search(
+        &self,
+        searcher: &Searcher,
+        query: &dyn Query,
+        limit: usize,
+        sort: Sort,
+        after: Option<After>,
+    ) -> Result<(usize, Vec<RecipeId>, Option<After>)> {
+        let (total, recipe_ids, after) =
+            self.search_with_total(searcher, query, limit, sort, after)?;
+        Ok((total.value, recipe_ids, after))
+    }
+
+    /// Like `search`, run on `pool` for async callers. Searches what
+    /// `reader` sees by the time the task runs
+    pub fn search_async(
+        &self,
+        pool: &BlockingPool,
+        reader: &IndexReader,
+        query: Box<dyn Query>,
+        limit: usize,
+        sort: Sort,
+        after: Option<After>,
+    ) -> Blocking<(usize, Vec<RecipeId>, Option<After>)> {
+        let recipe_index = self.clone();
+        let reader = reader.clone();
+        pool.run(move || {
+            recipe_index.search(&reader.searcher(), query.as_ref(), limit, sort, after)
+        })
+    }
+
+    /// Like `search`, but tells whether the total is exact. Two-phase
+    /// searches skip matches, so theirs is an estimate
+    pub fn search_with_total(
+        &self,
+        searcher: &Searcher,
+        query: &dyn Query,
+        limit: usize,
+        sort: Sort,
+        after: Option<After>,
+    ) -> Result<(TotalCount, Vec<RecipeId>, Option<After>)> {
+        let started = Instant::now();
+        let span = crate::span!(
+            "search",
+            query = crate::spans::query_hash(query),
+            limit,
+            sort = ?sort,
+            hits = tracing::field::Empty,
+        );
+        let _entered = span.enter();
+
+        let result = if let (Sort::Relevance, Some(sample_size), None) =
+            (&sort, self.two_phase_sample, self.tenant_scope)
+        {
+            self.two_phase_search(searcher, query, limit, after, sample_size)
+        } else {
+            self.sorted(searcher, query, limit, sort, after)
+                .map(|(total, recipe_ids, after)| (TotalCount::exact(total), recipe_ids, after))
+        };
+
+        if let Ok((total, _recipe_ids, _after)) = &result {
+            span.record("hits", &total.value);
+        }
+        metrics::search_done(started);
+        result
+    }
+
+    /// How many recipes match `query`, without ranking any. Searches
+    /// like the top recipes ones do, so timings and budgets apply
+    pub fn count(&self, searcher: &Searcher, query: &dyn Query) -> Result<usize> {
+        self.collect(searcher, query, Count)
+    }
+
+    /// Goes through every recipe matching `query`, `batch_size` at a
+    /// time, in the order they are in the index. Nothing is scored or
+    /// ranked, so it's cheap enough to go over large result sets, like
+    /// for exports. Filters apply like they do for searches
+    pub fn scan<'a>(
+        &self,
+        searcher: &'a Searcher,
+        query: &dyn Query,
+        batch_size: usize,
+    ) -> Result<Scan<'a>> {
+        let weight = self.scoped_weight(searcher, query)?;
+
+        Ok(Scan {
+            searcher,
+            id: self.id,
+            weight,
+            batch_size: batch_size.max(1),
+            segment: 0,
+            current: None,
+        })
+    }
+
+    /// Whether any recipe matches `query`. Stops at the first match
+    pub fn exists(&self, searcher: &Searcher, query: &dyn Query) -> Result<bool> {
+        let weight = self.scoped_weight(searcher, query)?;
+
+        for reader in searcher.segment_readers() {
+            let mut scorer = weight.scorer(reader, 1.0)?;
+            let delete_bitset = reader.delete_bitset();
+
+            let mut doc = scorer.doc();
+            while doc != TERMINATED {
+                if delete_bitset.map_or(true, |deleted| deleted.is_alive(doc)) {
+                    return Ok(true);
+                }
+                doc = scorer.advance();
+            }
+        }
+
+        Ok(false)
+    }
+
+    /// Figures about the index as `searcher` sees it and, for its
+    /// size and last commit, as it is at `index_path` now
+    pub fn stats(&self, searcher: &Searcher, index_path: &Path) -> Result<IndexStats> {
+        let readers = searcher.segment_readers();
+
+        let mut size_bytes = 0;
+        for entry in fs::read_dir(index_path)? {
+            let metadata = entry?.metadata()?;
+            if metadata.is_file() {
+                size_bytes += metadata.len();
+            }
+        }
+
+        let last_commit = fs::metadata(index_path.join(META_FILE))?
+            .modified()
+            .ok()
+            .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+            .map(|since_epoch| since_epoch.as_secs());
+
+        Ok(IndexStats {
+            num_segments: readers.len(),
+            num_docs: searcher.num_docs(),
+            num_deleted_docs: readers
+                .iter()
+                .map(|reader| u64::from(reader.num_deleted_docs()))
+                .sum(),
+            size_bytes,
+            last_commit,
+        })
+    }
+
+    /// Finds the recipes with a name matching what was typed so far,
+    /// each word of `input` taken as a prefix. Meant to be fast above
+    /// all: no pagination, at most `instant::MAX_ITEMS` recipes.
+    ///
+    /// Recipes whose names match every prefix come first, ranked up
+    /// by the words of the `recent` searches (as given by
+    /// `RecentQueries::completions`) they have. When there aren't
+    /// enough of them, the rest is filled with recipes whose names
+    /// match with a typo or so
+    pub fn instant_search(
+        &self,
+        searcher: &Searcher,
+        input: &str,
+        recent: &[(String, usize)],
+        limit: usize,
+    ) -> Result<Vec<RecipeId>> {
+        let query = match self.instant_query(input, recent) {
+            Some(query) => query,
+            None => return Ok(Vec::new()),
+        };
+
+        let limit = limit.min(instant::MAX_ITEMS);
+        let (_total, mut recipe_ids, _after) =
+            self.sorted(searcher, &query, limit, Sort::Relevance, None)?;
+
+        if recipe_ids.len() < limit {
+            if let Some(fuzzy_query) = self.instant_fuzzy_query(input, recent) {
+                // The exact matches are found again, so they're
+                // asked for on top of the ones that are missing
+                let (_total, fuzzy_ids, _after) =
+                    self.sorted(searcher, &fuzzy_query, limit * 2, Sort::Relevance, None)?;
+
+                for recipe_id in fuzzy_ids {
+                    if recipe_ids.len() >= limit {
+                        break;
+                    }
+                    if !recipe_ids.contains(&recipe_id) {
+                        recipe_ids.push(recipe_id);
+                    }
+                }
+            }
+        }
+
+        Ok(recipe_ids)
+    }
+
+    /// The query `instant_search` runs first. None when there's
+    /// nothing to search for
+    pub fn instant_query(&self, input: &str, recent: &[(String, usize)]) -> Option<BooleanQuery> {
+        let prefixes = instant::prefix_terms(input);
+        if prefixes.is_empty() {
+            return None;
+        }
+
+        let mut subqueries: Vec<(Occur, Box<dyn Query>)> = prefixes
+            .iter()
+            .map(|prefix| (Occur::Must, self.name_prefix_query(prefix)))
+            .collect();
+        subqueries.extend(self.completion_queries(recent));
+
+        Some(BooleanQuery::from(subqueries))
+    }
+
+    // Like `instant_query`, but letting the longer words of `input`
+    // be a typo or so away from the start of a word in the name
+    fn instant_fuzzy_query(&self, input: &str, recent: &[(String, usize)]) -> Option<BooleanQuery> {
+        let prefixes = instant::prefix_terms(input);
+        if prefixes
+            .iter()
+            .all(|prefix| prefix.chars().count() < instant::FUZZY_MIN_LENGTH)
+        {
+            return None;
+        }
+
+        let mut subqueries: Vec<(Occur, Box<dyn Query>)> = prefixes
+            .iter()
+            .map(|prefix| {
+                if prefix.chars().count() < instant::FUZZY_MIN_LENGTH {
+                    return (Occur::Must, self.name_prefix_query(prefix));
+                }
+
+                let fuzzy: Box<dyn Query> = Box::new(FuzzyPrefixQuery::new(
+                    Term::from_field_text(self.name_prefix, prefix),
+                    instant::FUZZY_DISTANCE,
+                    instant::FUZZY_PREFIX_LENGTH,
+                    true,
+                ));
+                let either: Box<dyn Query> = Box::new(BooleanQuery::from(vec![
+                    (Occur::Should, self.name_prefix_query(prefix)),
+                    (Occur::Should, fuzzy),
+                ]));
+                (Occur::Must, either)
+            })
+            .collect();
+        subqueries.extend(self.completion_queries(recent));
+
+        Some(BooleanQuery::from(subqueries))
+    }
+
+    fn name_prefix_query(&self, prefix: &str) -> Box<dyn Query> {
+        let term = Term::from_field_text(self.name_prefix, prefix);
+        Box::new(TermQuery::new(term, IndexRecordOption::WithFreqs))
+    }
+
+    // Optional clauses for the words of recent searches, weighing
+    // more the more often they were done
+    fn completion_queries(&self, recent: &[(String, usize)]) -> Vec<(Occur, Box<dyn Query>)> {
+        recent
+            .iter()
+            .take(instant::MAX_COMPLETIONS)
+            .filter_map(|(fulltext, count)| {
+                let words: Vec<_> = instant::prefix_terms(fulltext)
+                    .iter()
+                    .map(|word| Term::from_field_text(self.name_prefix, word))
+                    .collect();
+                if words.is_empty() {
+                    return None;
+                }
+
+                let boost = instant::COMPLETION_BOOST * (*count as f32).ln_1p();
+                let query: Box<dyn Query> = Box::new(BoostQuery::new(
+                    Box::new(BooleanQuery::new_multiterms_query(words)),
+                    boost,
+                ));
+                Some((Occur::Should, query))
+            })
+            .collect()
+    }
+
+    fn sorted(
+        &self,
+        searcher: &Searcher,
+        query: &dyn Query,
+        limit: usize,
+        sort: Sort,
+        after: Option<After>,
+    ) -> Result<(usize, Vec<RecipeId>, Option<After>)> {
+        macro_rules! collect {
+            ($type: ty, $field:ident, $order:ident) => {
+                if let Some(after) = after {
+                    let top_collector =
+                        TopCollector::<$type, $order, _>::new(limit, after.as_paginator(self.id))
+                            .top_fast_field(self.features.$field);
+
+                    self.render::<$type, _>(&searcher, query, top_collector)
+                } else {
+                    let top_collector = TopCollector::<$type, $order, _>::new(limit, true)
+                        .top_fast_field(self.features.$field);
+
+                    self.render::<$type, _>(&searcher, query, top_collector)
+                }
+            };
+
+            ($order:ident) => {
+                if let Some(after) = after {
+                    let top_collector =
+                        TopCollector::<_, $order, _>::new(limit, after.as_paginator(self.id));
+
+                    self.render::<Score, _>(&searcher, query, top_collector)
+                } else {
+                    let top_collector = TopCollector::<_, $order, _>::new(limit, true);
+
+                    self.render::<Score, _>(&searcher, query, top_collector)
+                }
+            };
+        }
+
+        match sort {
+            Sort::Relevance => collect!(Descending),
+            Sort::RelevanceAsc => collect!(Ascending),
+            Sort::NumIngredients => collect!(u64, num_ingredients, Descending),
+            Sort::InstructionsLength => collect!(u64, instructions_length, Descending),
+            Sort::TotalTime => collect!(u64, total_time, Descending),
+            Sort::CookTime => collect!(u64, cook_time, Descending),
+            Sort::PrepTime => collect!(u64, prep_time, Descending),
+            Sort::Calories => collect!(u64, calories, Descending),
+            Sort::FatContent => collect!(f64, fat_content, Descending),
+            Sort::CarbContent => collect!(f64, carb_content, Descending),
+            Sort::ProteinContent => collect!(f64, protein_content, Descending),
+            Sort::NumIngredientsAsc => collect!(u64, num_ingredients, Ascending),
+            Sort::InstructionsLengthAsc => collect!(u64, instructions_length, Ascending),
+            Sort::TotalTimeAsc => collect!(u64, total_time, Ascending),
+            Sort::CookTimeAsc => collect!(u64, cook_time, Ascending),
+            Sort::PrepTimeAsc => collect!(u64, prep_time, Ascending),
+            Sort::CaloriesAsc => collect!(u64, calories, Ascending),
+            Sort::FatContentAsc => collect!(f64, fat_content, Ascending),
+            Sort::CarbContentAsc => collect!(f64, carb_content, Ascending),
+            Sort::ProteinContentAsc => collect!(f64, protein_content, Ascending),
+            Sort::Name => self.sorted_by_name(searcher, query, limit, false, after),
+            Sort::NameAsc => self.sorted_by_name(searcher, query, limit, true, after),
+            Sort::Random => self.shuffled(searcher, query, limit, 0, after),
+        }
+    }
+
+    // Names sort on their whole collation key, which `TopCollector`
+    // can't take. Their cursors only keep the first bytes of it, so
+    // the full key is looked up via the recipe id
+    fn sorted_by_name(
+        &self,
+        searcher: &Searcher,
+        query: &dyn Query,
+        limit: usize,
+        ascending: bool,
+        after: Option<After>,
+    ) -> Result<(usize, Vec<RecipeId>, Option<After>)> {
+        let collector = NameSortCollector::new(self.name_collation_key, self.id, limit, ascending);
+
+        let collector = match after {
+            Some(After::U64Field(prefix, recipe_id)) => {
+                // The recipe may be gone since, in which case the
+                // prefix is the best there is
+                let key = self
+                    .name_key_of(searcher, recipe_id)?
+                    .unwrap_or_else(|| prefix.to_be_bytes().to_vec());
+                collector.after(key, recipe_id)
+            }
+            Some(other) => {
+                return Err(Error::QueryParse(format!(
+                    "Can't paginate a name sort with {:?}",
+                    other
+                )))
+            }
+            None => collector,
+        };
+
+        let result = self.collect_with(searcher, query, collector, true)?;
+
+        let has_next = result.has_next();
+        let cursor = match result.items.last() {
+            Some((key, recipe_id)) if has_next => Some(key_prefix(key).as_after(*recipe_id)),
+            _ => None,
+        };
+        let recipe_ids = result.items.into_iter().map(|(_key, id)| id).collect();
+
+        Ok((result.total, recipe_ids, cursor))
+    }
+
+    // The collation key of the name of the given recipe, if it's in
+    // the index
+    fn name_key_of(&self, searcher: &Searcher, recipe_id: RecipeId) -> Result<Option<Vec<u8>>> {
+        let id_query = TermQuery::new(
+            Term::from_field_u64(self.id, recipe_id),
+            IndexRecordOption::Basic,
+        );
+
+        Ok(
+            match self
+                .scoped(searcher, &id_query, TopDocs::with_limit(1))?
+                .first()
+            {
+                Some((_score, DocAddress(segment_ord, doc))) => searcher
+                    .segment_reader(*segment_ord)
+                    .fast_fields()
+                    .bytes(self.name_collation_key)
+                    .map(|keys| keys.get_bytes(*doc).to_vec()),
+                None => None,
+            },
+        )
+    }
+
+    /// Like a `Sort::Random` search, but with the order picked by
+    /// `seed`: the same seed always yields the same order, so that
+    /// paginating through the results works as usual
+    pub fn shuffled(
+        &self,
+        searcher: &Searcher,
+        query: &dyn Query,
+        limit: usize,
+        seed: u64,
+        after: Option<After>,
+    ) -> Result<(usize, Vec<RecipeId>, Option<After>)> {
+        // Keyed by recipe id instead of DocId since the latter
+        // changes whenever segments get merged
+        let id_field = self.id;
+        let shuffle_scorer = move |reader: &SegmentReader| {
+            let id_reader = reader
+                .fast_fields()
+                .u64(id_field)
+                .expect("id field is indexed with the FAST flag");
+
+            move |doc: DocId| shuffle_key(seed, id_reader.get(doc))
+        };
+
+        if let Some(after) = after {
+            let top_collector =
+                TopCollector::<u64, Descending, _>::new(limit, after.as_paginator(self.id))
+                    .with_custom_scorer(shuffle_scorer);
+
+            self.render::<u64, _>(searcher, query, top_collector)
+        } else {
+            let top_collector = TopCollector::<u64, Descending, _>::new(limit, true)
+                .with_custom_scorer(shuffle_scorer);
+
+            self.render::<u64, _>(searcher, query, top_collector)
+        }
+    }
+
+    /// Sorts by the value of a runtime field. Recipes for which it
+    /// can't be computed always come last
+    pub fn runtime_sorted(
+        &self,
+        searcher: &Searcher,
+        query: &dyn Query,
+        limit: usize,
+        field: RuntimeField,
+        ascending: bool,
+        after: Option<After>,
+    ) -> Result<(usize, Vec<RecipeId>, Option<After>)> {
+        let missing = if ascending {
+            std::f64::INFINITY
+        } else {
+            std::f64::NEG_INFINITY
+        };
+
+        let features_field = self.features_bincode;
+        let runtime_scorer = move |reader: &SegmentReader| {
+            let features_reader = reader
+                .fast_fields()
+                .bytes(features_field)
+                .expect("bytes field is indexed");
+
+            move |doc: DocId| {
+                bincode::deserialize::<Features>(features_reader.get_bytes(doc))
+                    .ok()
+                    .and_then(|features| field.value(&features))
+                    .unwrap_or(missing)
+            }
+        };
+
+        macro_rules! collect {
+            ($order:ident) => {
+                if let Some(after) = after {
+                    let top_collector =
+                        TopCollector::<f64, $order, _>::new(limit, after.as_paginator(self.id))
+                            .with_custom_scorer(runtime_scorer);
+
+                    self.render::<f64, _>(searcher, query, top_collector)
+                } else {
+                    let top_collector = TopCollector::<f64, $order, _>::new(limit, true)
+                        .with_custom_scorer(runtime_scorer);
+
+                    self.render::<f64, _>(searcher, query, top_collector)
+                }
+            };
+        }
+
+        if ascending {
+            collect!(Ascending)
+        } else {
+            collect!(Descending)
+        }
+    }
+
+    /// A condition for tique's `TopCollector` that only lets the
+    /// recipes whose origin is within `radius_km` of `lat`, `lon`
+    /// through. See `geo::within_radius`
+    pub fn within_radius<T: 'static>(
+        &self,
+        lat: f64,
+        lon: f64,
+        radius_km: f64,
+    ) -> impl ConditionForSegment<T> {
+        geo::within_radius(self.origin, lat, lon, radius_km)
+    }
+
+    /// Sorts by the distance between the recipes' origin and
+    /// `center`, closest first. Recipes without an origin come last
+    pub fn distance_sorted(
+        &self,
+        searcher: &Searcher,
+        query: &dyn Query,
+        limit: usize,
+        center: GeoPoint,
+        after: Option<After>,
+    ) -> Result<(usize, Vec<RecipeId>, Option<After>)> {
+        let origin_field = self.origin;
+        let distance_scorer = move |reader: &SegmentReader| {
+            let origin_reader = reader
+                .fast_fields()
+                .u64(origin_field)
+                .expect("origin field is indexed with the FAST flag");
+
+            move |doc: DocId| {
+                GeoPoint::decode(origin_reader.get(doc))
+                    .map_or(std::f64::INFINITY, |origin| center.distance_km(origin))
+            }
+        };
+
+        if let Some(after) = after {
+            let top_collector =
+                TopCollector::<f64, Ascending, _>::new(limit, after.as_paginator(self.id))
+                    .with_custom_scorer(distance_scorer);
+
+            self.render::<f64, _>(searcher, query, top_collector)
+        } else {
+            let top_collector = TopCollector::<f64, Ascending, _>::new(limit, true)
+                .with_custom_scorer(distance_scorer);
+
+            self.render::<f64, _>(searcher, query, top_collector)
+        }
+    }
+
+    /// Parses a score expression over the numeric features, named
+    /// like `NumericFeature` is serialized (say: `num_ingredients`).
+    /// Invalid expressions fail with `Error::QueryParse`
+    pub fn score_expression(&self, searcher: &Searcher, input: &str) -> Result<ScoreExpression> {
+        self.parse_score_expression(searcher, input, |_name| None)
+    }
+
+    /// Like `score_expression`, with the figures of `stats` available
+    /// as constants (see `GlobalStats::constant`), so that features
+    /// can be weighed against the whole corpus: say,
+    /// `_score * calories_p50 / (calories + 1)`
+    pub fn score_expression_with_stats(
+        &self,
+        searcher: &Searcher,
+        input: &str,
+        stats: &GlobalStats,
+    ) -> Result<ScoreExpression> {
+        self.parse_score_expression(searcher, input, |name| stats.constant(name))
+    }
+
+    fn parse_score_expression<G>(
+        &self,
+        searcher: &Searcher,
+        input: &str,
+        constant: G,
+    ) -> Result<ScoreExpression>
+    where
+        G: Fn(&str) -> Option<f64>,
+    {
+        ScoreExpression::parse_with_constants(
+            input,
+            searcher.schema(),
+            |name| {
+                serde_json::from_value(serde_json::Value::String(name.to_owned()))
+                    .ok()
+                    .map(|feature| self.numeric_field(feature))
+            },
+            constant,
+        )
+        .map_err(|err| match err {
+            TantivyError::InvalidArgument(reason) => Error::QueryParse(reason),
+            other => other.into(),
+        })
+    }
+
+    /// The fast field a numeric feature is indexed as
+    pub fn numeric_field(&self, feature: NumericFeature) -> Field {
+        match feature {
+            NumericFeature::NumIngredients => self.features.num_ingredients,
+            NumericFeature::InstructionsLength => self.features.instructions_length,
+            NumericFeature::PrepTime => self.features.prep_time,
+            NumericFeature::TotalTime => self.features.total_time,
+            NumericFeature::CookTime => self.features.cook_time,
+            NumericFeature::Calories => self.features.calories,
+            NumericFeature::FatContent => self.features.fat_content,
+            NumericFeature::CarbContent => self.features.carb_content,
+            NumericFeature::ProteinContent => self.features.protein_content,
+        }
+    }
+
+    /// Ranks by the value of a score expression
+    pub fn expression_sorted(
+        &self,
+        searcher: &Searcher,
+        query: &dyn Query,
+        limit: usize,
+        expression: ScoreExpression,
+        ascending: bool,
+        after: Option<After>,
+    ) -> Result<(usize, Vec<RecipeId>, Option<After>)> {
+        macro_rules! collect {
+            ($order:ident) => {
+                if let Some(after) = after {
+                    let top_collector =
+                        TopCollector::<f64, $order, _>::new(limit, after.as_paginator(self.id))
+                            .with_score_tweaker(expression);
+
+                    self.render::<f64, _>(searcher, query, top_collector)
+                } else {
+                    let top_collector = TopCollector::<f64, $order, _>::new(limit, true)
+                        .with_score_tweaker(expression);
+
+                    self.render::<f64, _>(searcher, query, top_collector)
+                }
+            };
+        }
+
+        if ascending {
+            collect!(Ascending)
+        } else {
+            collect!(Descending)
+        }
+    }
+
+    /// Ranks by relevance times how close the recipes' nutrition
+    /// facts are to the profile's. Recipes lacking a targeted fact
+    /// come last
+    pub fn profile_scored(
+        &self,
+        searcher: &Searcher,
+        query: &dyn Query,
+        limit: usize,
+        profile: MacroProfile,
+        after: Option<After>,
+    ) -> Result<(usize, Vec<RecipeId>, Option<After>)> {
+        let features_field = self.features_bincode;
+        let profile_tweaker = move |reader: &SegmentReader| {
+            let features_reader = reader
+                .fast_fields()
+                .bytes(features_field)
+                .expect("bytes field is indexed");
+
+            move |doc: DocId, score: Score| {
+                bincode::deserialize::<Features>(features_reader.get_bytes(doc))
+                    .map_or(0.0, |features| {
+                        f64::from(score) * profile.closeness(&features)
+                    })
+            }
+        };
+
+        if let Some(after) = after {
+            let top_collector =
+                TopCollector::<f64, Descending, _>::new(limit, after.as_paginator(self.id))
+                    .with_score_tweaker(profile_tweaker);
+
+            self.render::<f64, _>(searcher, query, top_collector)
+        } else {
+            let top_collector = TopCollector::<f64, Descending, _>::new(limit, true)
+                .with_score_tweaker(profile_tweaker);
+
+            self.render::<f64, _>(searcher, query, top_collector)
+        }
+    }
+
+    /// Ranks by relevance boosted by how recently (as of `now`) the
+    /// recipes were added
+    pub fn recency_boosted(
+        &self,
+        searcher: &Searcher,
+        query: &dyn Query,
+        limit: usize,
+        boost: RecencyBoost,
+        now: u64,
+        after: Option<After>,
+    ) -> Result<(usize, Vec<RecipeId>, Option<After>)> {
+        let recency_tweaker = boost.tweaker(self.features.added_at, now);
+
+        if let Some(after) = after {
+            let top_collector =
+                TopCollector::<f64, Descending, _>::new(limit, after.as_paginator(self.id))
+                    .with_score_tweaker(recency_tweaker);
+
+            self.render::<f64, _>(searcher, query, top_collector)
+        } else {
+            let top_collector = TopCollector::<f64, Descending, _>::new(limit, true)
+                .with_score_tweaker(recency_tweaker);
+
+            self.render::<f64, _>(searcher, query, top_collector)
+        }
+    }
+
+    /// Indexes every recipe in the database (along with the
+    /// attributes of its author and its popularity), committing
+    /// every `commit_every` recipes. Meant for filling a new index after schema or
+    /// analysis changes without going back to the original data:
+    /// nothing is deleted from the writer's index.
+    ///
+    /// Recipes are indexed in parallel by as many threads as the
+    /// writer was created with
+    pub fn rebuild_from(
+        &self,
+        database: &DatabaseReader<Recipe>,
+        authors: &HashMap<AuthorId, Author>,
+        popularity: &HashMap<RecipeId, u64>,
+        writer: &mut IndexWriter,
+        commit_every: usize,
+    ) -> Result<usize> {
+        let mut ids = database.ids().copied().collect::<Vec<_>>();
+        ids.sort();
+
+        for (num_indexed, id) in ids.iter().enumerate() {
+            let recipe = database
+                .find_by_id(*id)
+                .expect("ids come from the database")?;
+
+            let mut doc = self.make_document_with_author(
+                &recipe,
+                recipe
+                    .author_id
+                    .and_then(|author_id| authors.get(&author_id)),
+            );
+            if let Some(popularity) = popularity.get(id) {
+                self.add_popularity(&mut doc, *popularity);
+            }
+            writer.add_document(doc);
+
+            if (num_indexed + 1) % commit_every == 0 {
+                writer.commit()?;
+                log::info!("Rebuild: {} recipes indexed so far", num_indexed + 1);
+            }
+        }
+
+        writer.commit()?;
+
+        Ok(ids.len())
+    }
+
+    /// Reads through the fast fields and term dictionaries picked
+    /// by `options` (`WarmupOptions::all` for everything), so that
+    /// the first searches after a reload don't pay for loading them
+    pub fn warm(&self, searcher: &Searcher, options: &WarmupOptions) -> Result<WarmupStats> {
+        Ok(warmup::warm(searcher, options)?)
+    }
+
+    /// Searches by relevance, yielding every family of variants (a
+    /// base recipe along with the variants of it) at most once. Each
+    /// item is the id of the base recipe and the id of its best
+    /// matching variant, which may be the base recipe itself.
+    ///
+    /// The total is the number of matching recipes, not families
+    pub fn collapsed(
+        &self,
+        searcher: &Searcher,
+        query: &dyn Query,
+        limit: usize,
+    ) -> Result<(usize, Vec<(RecipeId, RecipeId)>)> {
+        let collector =
+            TopHitsPerBucket::u64_field(self.parent_id, limit, 1).with_order(BucketOrder::TopScore);
+        let (total, buckets) = self.scoped(searcher, query, (Count, collector))?;
+
+        let mut found = Vec::with_capacity(buckets.len());
+        for bucket in buckets {
+            let (_score, addr) = bucket.hits[0];
+            if let Some(&Value::U64(recipe_id)) = searcher.doc(addr)?.get_first(self.id) {
+                found.push((bucket.key, recipe_id));
+            } else {
+                panic!("Found doc with non-U64 id field");
+            }
+        }
+
+        Ok((total, found))
+    }
 
-        recipe
-            .ingredients
-            .iter()
-            .for_each(|i| doc.add_text(self.ingredients, i));
+    /// Searches by relevance, then reranks the best `num_candidates`
+    /// matches by relevance boosted by popularity (see
+    /// `popularity::boost`). Popularity comes from `live` when it has
+    /// the recipe, from what was baked into the index otherwise.
+    ///
+    /// Yields the total and the ids of the best `limit` recipes
+    pub fn popularity_rescored(
+        &self,
+        searcher: &Searcher,
+        query: &dyn Query,
+        limit: usize,
+        num_candidates: usize,
+        live: Option<&PopularityStore>,
+    ) -> Result<(usize, Vec<RecipeId>)> {
+        let id_field = self.id;
+        let popularity_field = self.popularity;
+        let live = live.map(PopularityStore::counts);
 
-        recipe
-            .instructions
-            .iter()
-            .for_each(|i| doc.add_text(self.instructions, i));
+        let rescorer = move |reader: &SegmentReader| {
+            let fast_fields = reader.fast_fields();
+            let ids = fast_fields.u64(id_field).ok_or_else(|| {
+                TantivyError::SchemaError("id field is not a fast field".to_owned())
+            })?;
+            let baked = fast_fields.u64(popularity_field).ok_or_else(|| {
+                TantivyError::SchemaError("popularity field is not a fast field".to_owned())
+            })?;
+            let live = live.clone();
 
-        doc.add_bytes(
-            self.features_bincode,
-            bincode::serialize(&recipe.features).unwrap(),
-        );
+            Ok::<_, TantivyError>(move |doc: DocId, score: Score| {
+                let popularity = live
+                    .as_ref()
+                    .and_then(|counts| counts.get(&ids.get(doc)).copied())
+                    .unwrap_or_else(|| baked.get(doc));
+                popularity::boost(score, popularity)
+            })
+        };
 
-        self.features.add_to_doc(&mut doc, &recipe.features);
-        doc
+        let collector = RescoringCollector::new(num_candidates.max(limit), limit.max(1), rescorer);
+        let (total, rescored) = self.scoped(searcher, query, (Count, collector))?;
+
+        let mut recipe_ids = Vec::with_capacity(limit);
+        for item in rescored.into_iter().take(limit) {
+            let ids = searcher
+                .segment_reader(item.doc.segment_ord())
+                .fast_fields()
+                .u64(id_field)
+                .expect("id field is a fast field");
+            recipe_ids.push(ids.get(item.doc.doc()));
+        }
+
+        Ok((total, recipe_ids))
     }
 
-    pub fn search(
+    /// Groups the recipes matching the query by `field`, in a single
+    /// search: the `num_groups` groups with the most recipes, largest
+    /// first, each with its key, its total and the ids of its best
+    /// `per_group` recipes by relevance. Recipes without a value for
+    /// the field are in no group
+    pub fn grouped(
         &self,
         searcher: &Searcher,
         query: &dyn Query,
-        limit: usize,
-        sort: Sort,
-        after: Option<After>,
-    ) -> Result<(usize, Vec<RecipeId>, Option<After>)> {
-        macro_rules! collect {
-            ($type: ty, $field:ident, $order:ident) => {
-                if let Some(after) = after {
-                    let top_collector =
-                        TopCollector::<$type, $order, _>::new(limit, after.as_paginator(self.id))
-                            .top_fast_field(self.features.$field);
-
-                    self.render::<$type, _>(&searcher, query, top_collector)
-                } else {
-                    let top_collector = TopCollector::<$type, $order, _>::new(limit, true)
-                        .top_fast_field(self.features.$field);
+        field: GroupField,
+        num_groups: usize,
+        per_group: usize,
+    ) -> Result<Vec<(u64, usize, Vec<RecipeId>)>> {
+        let field = match field {
+            GroupField::Author => self.author_id,
+        };
 
-                    self.render::<$type, _>(&searcher, query, top_collector)
-                }
-            };
+        // Missing values read as zero from the fast field, so the
+        // recipes without one must not match at all
+        let query = BooleanQuery::from(vec![
+            (Occur::Must, query.box_clone()),
+            (
+                Occur::Must,
+                Box::new(RangeQuery::new_u64(field, 0..std::u64::MAX)) as Box<dyn Query>,
+            ),
+        ]);
 
-            ($order:ident) => {
-                if let Some(after) = after {
-                    let top_collector =
-                        TopCollector::<_, $order, _>::new(limit, after.as_paginator(self.id));
+        let buckets = self.collect(
+            searcher,
+            &query,
+            TopHitsPerBucket::u64_field(field, num_groups, per_group),
+        )?;
 
-                    self.render::<Score, _>(&searcher, query, top_collector)
+        let mut groups = Vec::with_capacity(buckets.len());
+        for bucket in buckets {
+            let mut recipe_ids = Vec::with_capacity(bucket.hits.len());
+            for (_score, addr) in bucket.hits {
+                if let Some(&Value::U64(recipe_id)) = searcher.doc(addr)?.get_first(self.id) {
+                    recipe_ids.push(recipe_id);
                 } else {
-                    let top_collector = TopCollector::<_, $order, _>::new(limit, true);
-
-                    self.render::<Score, _>(&searcher, query, top_collector)
+                    panic!("Found doc with non-U64 id field");
                 }
-            };
+            }
+            groups.push((bucket.key, bucket.count, recipe_ids));
         }
 
-        match sort {
-            Sort::Relevance => collect!(Descending),
-            Sort::RelevanceAsc => collect!(Ascending),
-            Sort::NumIngredients => collect!(u64, num_ingredients, Descending),
-            Sort::InstructionsLength => collect!(u64, instructions_length, Descending),
-            Sort::TotalTime => collect!(u64, total_time, Descending),
-            Sort::CookTime => collect!(u64, cook_time, Descending),
-            Sort::PrepTime => collect!(u64, prep_time, Descending),
-            Sort::Calories => collect!(u64, calories, Descending),
-            Sort::FatContent => collect!(f64, fat_content, Descending),
-            Sort::CarbContent => collect!(f64, carb_content, Descending),
-            Sort::ProteinContent => collect!(f64, protein_content, Descending),
-            Sort::NumIngredientsAsc => collect!(u64, num_ingredients, Ascending),
-            Sort::InstructionsLengthAsc => collect!(u64, instructions_length, Ascending),
-            Sort::TotalTimeAsc => collect!(u64, total_time, Ascending),
-            Sort::CookTimeAsc => collect!(u64, cook_time, Ascending),
-            Sort::PrepTimeAsc => collect!(u64, prep_time, Ascending),
-            Sort::CaloriesAsc => collect!(u64, calories, Ascending),
-            Sort::FatContentAsc => collect!(f64, fat_content, Ascending),
-            Sort::CarbContentAsc => collect!(f64, carb_content, Ascending),
-            Sort::ProteinContentAsc => collect!(f64, protein_content, Ascending),
-        }
+        Ok(groups)
     }
 
     pub fn aggregate_features(
@@ -145,7 +1422,285 @@ impl RecipeIndex {
                 move |doc| bincode::deserialize(features_reader.get_bytes(doc)).ok()
             });
 
-        Ok(searcher.search(query, &collector)?)
+        self.scoped(searcher, query, collector)
+    }
+
+    /// Counts the recipes matching the query by when they were added.
+    /// Recipes without `added_at` are not counted
+    pub fn added_histogram(
+        &self,
+        searcher: &Searcher,
+        query: &dyn Query,
+        interval: Interval,
+    ) -> Result<DateHistogram> {
+        let added_at = self.features.added_at;
+        let mask = self
+            .features
+            .presence_mask(added_at)
+            .expect("added_at is optional");
+
+        self.scoped(
+            searcher,
+            query,
+            DateHistogramCollector::new(added_at, interval)
+                .with_presence(self.features_present, mask),
+        )
+    }
+
+    /// Counts the recipes matching the query that would also be
+    /// matched by each of the named filters, in a single pass
+    pub fn filter_buckets(
+        &self,
+        searcher: &Searcher,
+        query: &dyn Query,
+        buckets: BTreeMap<String, FeaturesFilterQuery>,
+    ) -> Result<BTreeMap<String, u64>> {
+        let (names, filters): (Vec<_>, Vec<_>) = buckets.into_iter().unzip();
+        let counts = self.scoped(
+            searcher,
+            query,
+            FilterBucketsCollector::new(self.features_bincode, filters),
+        )?;
+
+        Ok(names.into_iter().zip(counts).collect())
+    }
+
+    /// Summarizes the distribution of the given features among the
+    /// recipes matching the query, in a single pass
+    pub fn feature_percentiles(
+        &self,
+        searcher: &Searcher,
+        query: &dyn Query,
+        features: &[NumericFeature],
+    ) -> Result<FeaturesPercentiles> {
+        let summaries = self.percentiles(
+            searcher,
+            query,
+            features
+                .iter()
+                .map(|&feature| move |features: &Features| feature.value(features))
+                .collect(),
+        )?;
+
+        Ok(features
+            .iter()
+            .zip(summaries)
+            .filter_map(|(&feature, summary)| summary.map(|summary| (feature, summary)))
+            .collect::<HashMap<_, _>>())
+    }
+
+    /// Like `feature_percentiles`, but for runtime fields. The result
+    /// has one entry per field, in the order they were given, which
+    /// is None when no matching recipe has a value for it
+    pub fn runtime_percentiles(
+        &self,
+        searcher: &Searcher,
+        query: &dyn Query,
+        fields: &[RuntimeField],
+    ) -> Result<Vec<Option<PercentileSummary>>> {
+        self.percentiles(
+            searcher,
+            query,
+            fields
+                .iter()
+                .map(|&field| move |features: &Features| field.value(features))
+                .collect(),
+        )
+    }
+
+    fn percentiles<V>(
+        &self,
+        searcher: &Searcher,
+        query: &dyn Query,
+        values: Vec<V>,
+    ) -> Result<Vec<Option<PercentileSummary>>>
+    where
+        V: 'static + Copy + Send + Sync + Fn(&Features) -> Option<f64>,
+    {
+        let mut collector = MultiCollector::new();
+        let mut handles = Vec::with_capacity(values.len());
+
+        for value in values {
+            let features_field = self.features_bincode;
+            let handle =
+                collector.add_collector(PercentileCollector::new(move |reader: &SegmentReader| {
+                    let features_reader = reader
+                        .fast_fields()
+                        .bytes(features_field)
+                        .expect("bytes field is indexed");
+
+                    Ok::<_, TantivyError>(move |doc: DocId| {
+                        bincode::deserialize::<Features>(features_reader.get_bytes(doc))
+                            .ok()
+                            .and_then(|features| value(&features))
+                    })
+                }));
+            handles.push(handle);
+        }
+
+        let mut fruits = self.scoped(searcher, query, collector)?;
+
+        Ok(handles
+            .into_iter()
+            .map(|handle| summarize(&handle.extract(&mut fruits)))
+            .collect())
+    }
+
+    /// Recipes like the given one: searches for the terms that best
+    /// describe it (its words that are frequent in it but rare in the
+    /// index), leaving the recipe itself out. Best matches first.
+    ///
+    /// Yields None if the recipe isn't in the index
+    pub fn similar_to(
+        &self,
+        searcher: &Searcher,
+        recipe_id: RecipeId,
+        limit: usize,
+    ) -> Result<Option<Vec<RecipeId>>> {
+        let id_query = TermQuery::new(
+            Term::from_field_u64(self.id, recipe_id),
+            IndexRecordOption::Basic,
+        );
+        let addr = match self
+            .scoped(searcher, &id_query, TopDocs::with_limit(1))?
+            .first()
+        {
+            Some((_score, addr)) => *addr,
+            None => return Ok(None),
+        };
+
+        let keywords = topterms::extract_from_searcher(
+            searcher,
+            &[self.name, self.ingredients, self.instructions],
+            MAX_SIMILARITY_KEYWORDS,
+            addr,
+            // Terms no other recipe has can't find anything
+            &|term: &Term, _tf, doc_freq, _num_docs| {
+                doc_freq > 1 && term.text().chars().count() > 2
+            },
+        );
+
+        if keywords.is_empty() {
+            return Ok(Some(Vec::new()));
+        }
+
+        let query = BooleanQuery::from(vec![
+            (
+                Occur::Must,
+                Box::new(keywords.into_boosted_query(1.0)) as Box<dyn Query>,
+            ),
+            (Occur::MustNot, Box::new(id_query)),
+        ]);
+
+        let (_total, recipe_ids, _after) =
+            self.sorted(searcher, &query, limit, Sort::Relevance, None)?;
+
+        Ok(Some(recipe_ids))
+    }
+
+    /// Breaks down how the given recipe fares against a search: its
+    /// score for the full-text `query`, which of the query terms it
+    /// has and whether it passes each of the `filters`.
+    ///
+    /// Yields None if the recipe isn't in the index
+    pub fn explain(
+        &self,
+        searcher: &Searcher,
+        query: &dyn Query,
+        filters: &[Box<dyn Query>],
+        recipe_id: RecipeId,
+    ) -> Result<Option<RecipeExplanation>> {
+        let id_query = TermQuery::new(
+            Term::from_field_u64(self.id, recipe_id),
+            IndexRecordOption::Basic,
+        );
+        let addr = match self
+            .scoped(searcher, &id_query, TopDocs::with_limit(1))?
+            .first()
+        {
+            Some((_score, addr)) => *addr,
+            None => return Ok(None),
+        };
+
+        let schema = searcher.schema();
+
+        let mut terms = BTreeSet::new();
+        query.query_terms(&mut terms);
+
+        let mut matches = BTreeMap::new();
+        for term in terms {
+            if let FieldType::Str(_) = schema.get_field_entry(term.field()).field_type() {
+                let term_query = TermQuery::new(term.clone(), IndexRecordOption::WithFreqs);
+                if let Some(score) = score_of(searcher, &term_query, addr, true)? {
+                    matches
+                        .entry(schema.get_field_name(term.field()).to_owned())
+                        .or_insert_with(Vec::new)
+                        .push(TermMatch {
+                            term: term.text().to_owned(),
+                            score,
+                        });
+                }
+            }
+        }
+
+        let mut checks = Vec::with_capacity(filters.len());
+        for filter in filters {
+            let field = if let Some(range) = filter.downcast_ref::<RangeQuery>() {
+                schema.get_field_name(range.field()).to_owned()
+            } else if let Some(term) = filter.downcast_ref::<TermQuery>() {
+                schema.get_field_name(term.term().field()).to_owned()
+            } else if filter.downcast_ref::<RuntimeFilterQuery>().is_some() {
+                "runtime_filter".to_owned()
+            } else if filter.downcast_ref::<GeoDistanceQuery>().is_some() {
+                "near".to_owned()
+            } else if let Some(field) = single_field(filter.as_ref()) {
+                schema.get_field_name(field).to_owned()
+            } else {
+                format!("{:?}", filter)
+            };
+
+            checks.push(FilterCheck {
+                field,
+                passed: score_of(searcher, filter.as_ref(), addr, false)?.is_some(),
+            });
+        }
+
+        Ok(Some(RecipeExplanation {
+            score: score_of(searcher, query, addr, true)?,
+            matches,
+            filters: checks,
+        }))
+    }
+
+    fn two_phase_search(
+        &self,
+        searcher: &Searcher,
+        query: &dyn Query,
+        limit: usize,
+        after: Option<After>,
+        sample_size: usize,
+    ) -> Result<(TotalCount, Vec<RecipeId>, Option<After>)> {
+        let (result, stats) = if let Some(after) = after {
+            TwoPhaseTopDocs::new(limit, after.as_paginator(self.id))
+                .with_sample_size(sample_size)
+                .search(searcher, query)?
+        } else {
+            TwoPhaseTopDocs::new(limit, true)
+                .with_sample_size(sample_size)
+                .search(searcher, query)?
+        };
+
+        log::debug!("Two-phase collection: {:?}", stats);
+        let (_lower_bound, recipe_ids, after) = self.render_result(searcher, result)?;
+
+        // Nothing gets skipped without a threshold
+        let total = if stats.threshold.is_none() {
+            TotalCount::exact(stats.estimated_total)
+        } else {
+            TotalCount::approx(stats.estimated_total, stats.estimated_total_error)
+        };
+
+        Ok((total, recipe_ids, after))
     }
 
     fn render<T, C>(
@@ -158,7 +1713,169 @@ impl RecipeIndex {
         T: 'static + Sync + Send + Copy + AsAfter,
         C: Collector<Fruit = CollectionResult<T>>,
     {
-        let result = searcher.search(query, &collector)?;
+        let result = self.collect_with(searcher, query, collector, true)?;
+        self.render_result(searcher, result)
+    }
+
+    fn collect<C: Collector>(
+        &self,
+        searcher: &Searcher,
+        query: &dyn Query,
+        collector: C,
+    ) -> Result<C::Fruit> {
+        self.collect_with(searcher, query, collector, false)
+    }
+
+    // The optional collectors wrap each other innermost first: the
+    // budget one drives the scorer by itself, so the tenant one must
+    // be inside it, and every one outside of it must forward
+    // `collect_segment`, the partial one last so that it catches what
+    // fails while creating the scorer
+    fn collect_with<C: Collector>(
+        &self,
+        searcher: &Searcher,
+        query: &dyn Query,
+        collector: C,
+        skip_failed: bool,
+    ) -> Result<C::Fruit> {
+        if let Some(tenant) = self.tenant_scope {
+            let query = tenant::scoped_query(self.tenant_id, tenant, query);
+            self.budgeted(
+                searcher,
+                &query,
+                TenantCollector::new(self.tenant_id, tenant, collector),
+                skip_failed,
+            )
+        } else {
+            self.budgeted(searcher, query, collector, skip_failed)
+        }
+    }
+
+    fn budgeted<C: Collector>(
+        &self,
+        searcher: &Searcher,
+        query: &dyn Query,
+        collector: C,
+        skip_failed: bool,
+    ) -> Result<C::Fruit> {
+        if let Some(budget) = &self.budget {
+            let (fruit, exhausted) = self.timed(
+                searcher,
+                query,
+                BudgetCollector::new(collector, budget.budget),
+                skip_failed,
+            )?;
+            budget.record(exhausted);
+            Ok(fruit)
+        } else {
+            self.timed(searcher, query, collector, skip_failed)
+        }
+    }
+
+    fn timed<C: Collector>(
+        &self,
+        searcher: &Searcher,
+        query: &dyn Query,
+        collector: C,
+        skip_failed: bool,
+    ) -> Result<C::Fruit> {
+        if let Some(recorder) = &self.timings {
+            let (fruit, timings) =
+                self.partial(searcher, query, TimedCollector::new(collector), skip_failed)?;
+            recorder.record(timings);
+            Ok(fruit)
+        } else {
+            self.partial(searcher, query, collector, skip_failed)
+        }
+    }
+
+    fn partial<C: Collector>(
+        &self,
+        searcher: &Searcher,
+        query: &dyn Query,
+        collector: C,
+        skip_failed: bool,
+    ) -> Result<C::Fruit> {
+        match &self.skipped_segments {
+            Some(recorder) if skip_failed => {
+                let (fruit, failures) =
+                    self.execute(searcher, query, &PartialCollector::new(collector))?;
+                recorder.record(failures);
+                Ok(fruit)
+            }
+            _ => self.execute(searcher, query, &collector),
+        }
+    }
+
+    /// What `scoped` is for searches, for the ones that go through
+    /// the documents by themselves instead of via a collector
+    fn scoped_weight(&self, searcher: &Searcher, query: &dyn Query) -> Result<Box<dyn Weight>> {
+        Ok(match self.tenant_scope {
+            Some(tenant) => {
+                tenant::scoped_query(self.tenant_id, tenant, query).weight(searcher, false)?
+            }
+            None => query.weight(searcher, false)?,
+        })
+    }
+
+    /// Every search goes through here (or `collect_with`, which
+    /// does the same), so that the index of a `TenantScopedIndex`
+    /// never sees the recipes of other tenants
+    fn scoped<C: Collector>(
+        &self,
+        searcher: &Searcher,
+        query: &dyn Query,
+        collector: C,
+    ) -> Result<C::Fruit> {
+        if let Some(tenant) = self.tenant_scope {
+            let query = tenant::scoped_query(self.tenant_id, tenant, query);
+            self.execute(
+                searcher,
+                &query,
+                &TenantCollector::new(self.tenant_id, tenant, collector),
+            )
+        } else {
+            self.execute(searcher, query, &collector)
+        }
+    }
+
+    fn execute<C: Collector>(
+        &self,
+        searcher: &Searcher,
+        query: &dyn Query,
+        collector: &C,
+    ) -> Result<C::Fruit> {
+        let readers = searcher.segment_readers();
+        let span = crate::span!("collect", segments = readers.len());
+        let _entered = span.enter();
+
+        #[cfg(feature = "parallel")]
+        {
+            if self.parallel {
+                return Ok(parallel::search(searcher, query, collector)?);
+            }
+        }
+
+        // What `Searcher::search` does, with room for spans
+        let weight = query.weight(searcher, collector.requires_scoring())?;
+        let mut fruits = Vec::with_capacity(readers.len());
+        for (segment_ord, reader) in readers.iter().enumerate() {
+            fruits.push(collector.collect_segment(weight.as_ref(), segment_ord as u32, reader)?);
+        }
+
+        let span = crate::span!("merge", fruits = fruits.len());
+        let _entered = span.enter();
+        Ok(collector.merge_fruits(fruits)?)
+    }
+
+    fn render_result<T>(
+        &self,
+        searcher: &Searcher,
+        result: CollectionResult<T>,
+    ) -> Result<(usize, Vec<RecipeId>, Option<After>)>
+    where
+        T: Copy + AsAfter,
+    {
         let mut recipe_ids = Vec::with_capacity(result.items.len());
 
         let has_next = result.has_next();
@@ -187,29 +1904,80 @@ impl RecipeIndex {
     }
 }
 
-impl From<&mut SchemaBuilder> for RecipeIndex {
-    fn from(builder: &mut SchemaBuilder) -> Self {
+impl RecipeIndex {
+    /// Adds the recipe fields to `builder`, with the text fields set
+    /// up to use the given analysis. The index created from the
+    /// resulting schema must have the analysis registered via
+    /// `Analysis::register`
+    pub fn create(builder: &mut SchemaBuilder, analysis: &Analysis) -> Self {
+        let text_options = TextOptions::default().set_indexing_options(
+            TextFieldIndexing::default()
+                .set_tokenizer(analysis.tokenizer_name())
+                .set_index_option(IndexRecordOption::WithFreqsAndPositions),
+        );
+
         RecipeIndex {
-            id: builder.add_u64_field(FIELD_ID, STORED | FAST),
+            id: builder.add_u64_field(FIELD_ID, STORED | FAST | INDEXED),
 
-            name: builder.add_text_field(FIELD_NAME, TEXT),
-            ingredients: builder.add_text_field(FIELD_INGREDIENTS, TEXT),
-            instructions: builder.add_text_field(FIELD_INSTRUCTIONS, TEXT),
+            name: builder.add_text_field(FIELD_NAME, text_options.clone()),
+            ingredients: builder.add_text_field(FIELD_INGREDIENTS, text_options.clone()),
+            instructions: builder.add_text_field(FIELD_INSTRUCTIONS, text_options),
+            // Prefixes are computed when making the document, so
+            // they're taken as they are
+            name_prefix: builder.add_text_field(
+                FIELD_NAME_PREFIX,
+                TextOptions::default().set_indexing_options(
+                    TextFieldIndexing::default()
+                        .set_tokenizer("raw")
+                        .set_index_option(IndexRecordOption::WithFreqs),
+                ),
+            ),
 
             features_bincode: builder.add_bytes_field(FIELD_FEATURES_BINCODE),
             features: Features::create_schema(builder, INDEXED | FAST),
+            features_present: builder.add_u64_field(FIELD_FEATURES_PRESENT, FAST),
+
+            author_id: builder.add_u64_field(FIELD_AUTHOR_ID, INDEXED | FAST),
+            author_verified: builder.add_u64_field(FIELD_AUTHOR_VERIFIED, INDEXED),
+
+            parent_id: builder.add_u64_field(FIELD_PARENT_ID, INDEXED | FAST),
+            origin: builder.add_u64_field(FIELD_ORIGIN, INDEXED | FAST),
+            present: builder.add_u64_field(FIELD_PRESENT, INDEXED),
+            popularity: builder.add_u64_field(FIELD_POPULARITY, INDEXED | FAST),
+            tenant_id: builder.add_u64_field(FIELD_TENANT_ID, INDEXED | FAST),
+
+            name_collation_key: builder.add_bytes_field(FIELD_NAME_COLLATION_KEY),
+            collation: Collation::default(),
+
+            two_phase_sample: None,
+            timings: None,
+            skipped_segments: None,
+            budget: None,
+            tenant_scope: None,
+            #[cfg(feature = "parallel")]
+            parallel: false,
+
+            tokenizers: Vec::new(),
         }
     }
 }
 
+impl From<&mut SchemaBuilder> for RecipeIndex {
+    fn from(builder: &mut SchemaBuilder) -> Self {
+        RecipeIndex::create(builder, &Analysis::default())
+    }
+}
+
 impl TryFrom<&Schema> for RecipeIndex {
-    type Error = TantivyError;
+    type Error = Error;
 
+    /// Fails with `Error::UnknownField` when the schema lacks any of
+    /// the recipe fields
     fn try_from(schema: &Schema) -> Result<Self> {
-        let get_field = |name| {
+        let get_field = |name: &str| {
             schema
                 .get_field(name)
-                .ok_or_else(|| TantivyError::SchemaError(format!("Missing field {}", name)))
+                .ok_or_else(|| Error::UnknownField(name.to_owned()))
         };
 
         Ok(RecipeIndex {
@@ -218,9 +1986,33 @@ impl TryFrom<&Schema> for RecipeIndex {
             name: get_field(FIELD_NAME)?,
             ingredients: get_field(FIELD_INGREDIENTS)?,
             instructions: get_field(FIELD_INSTRUCTIONS)?,
+            name_prefix: get_field(FIELD_NAME_PREFIX)?,
 
             features_bincode: get_field(FIELD_FEATURES_BINCODE)?,
             features: FeaturesFilterFields::try_from(schema)?,
+            features_present: get_field(FIELD_FEATURES_PRESENT)?,
+
+            author_id: get_field(FIELD_AUTHOR_ID)?,
+            author_verified: get_field(FIELD_AUTHOR_VERIFIED)?,
+
+            parent_id: get_field(FIELD_PARENT_ID)?,
+            origin: get_field(FIELD_ORIGIN)?,
+            present: get_field(FIELD_PRESENT)?,
+            popularity: get_field(FIELD_POPULARITY)?,
+            tenant_id: get_field(FIELD_TENANT_ID)?,
+
+            name_collation_key: get_field(FIELD_NAME_COLLATION_KEY)?,
+            collation: Collation::default(),
+
+            two_phase_sample: None,
+            timings: None,
+            skipped_segments: None,
+            budget: None,
+            tenant_scope: None,
+            #[cfg(feature = "parallel")]
+            parallel: false,
+
+            tokenizers: Vec::new(),
         })
     }
 }
@@ -365,3 +2157,56 @@ where
         }
     }
 }
+
+// splitmix64's finalizer, so that neighbouring ids end up far apart
+fn shuffle_key(seed: u64, recipe_id: RecipeId) -> u64 {
+    let mut z = (seed ^ recipe_id).wrapping_add(0x9E37_79B9_7F4A_7C15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
+
+// The field every term of `query` is on, if there's a single one
+fn single_field(query: &dyn Query) -> Option<Field> {
+    let mut terms = BTreeSet::new();
+    query.query_terms(&mut terms);
+
+    let mut fields = terms.iter().map(Term::field);
+    let first = fields.next()?;
+    if fields.all(|field| field == first) {
+        Some(first)
+    } else {
+        None
+    }
+}
+
+// Scores a single document without going through `Query::explain`
+// since TermWeight::explain trips a debug assertion when the scorer
+// has already moved past the target
+fn score_of(
+    searcher: &Searcher,
+    query: &dyn Query,
+    addr: DocAddress,
+    scoring_enabled: bool,
+) -> Result<Option<Score>> {
+    let DocAddress(segment_ord, doc) = addr;
+    let weight = query.weight(searcher, scoring_enabled)?;
+    let mut scorer = weight.scorer(searcher.segment_reader(segment_ord), 1.0)?;
+
+    if scorer.doc() > doc || scorer.seek(doc) != doc {
+        Ok(None)
+    } else {
+        Ok(Some(scorer.score()))
+    }
+}
+
+fn summarize(digest: &TDigest) -> Option<PercentileSummary> {
+    Some(PercentileSummary {
+        count: digest.count(),
+        p25: digest.quantile(0.25)?,
+        p50: digest.quantile(0.5)?,
+        p75: digest.quantile(0.75)?,
+        p90: digest.quantile(0.9)?,
+        p99: digest.quantile(0.99)?,
+    })
+}
@@ -0,0 +1,577 @@
+use serde::Deserialize;
+use serde_json::Value;
+use uuid::Uuid;
+
+use crate::model::{Features, Recipe, RecipeId};
+
+/// A document as fetched from a source site
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct Page {
+    pub url: String,
+    pub body: String,
+}
+
+/// What an `Adapter` finds in a page
+#[derive(Debug, Clone, PartialEq)]
+pub enum Found {
+    /// A recipe, yet without ids. See `Scraped::into_recipe`
+    Recipe(Scraped),
+    /// A page worth fetching, like the entries of a feed
+    Link(String),
+}
+
+/// Understands a publication format. Supporting a new kind of
+/// source takes implementing this and adding it to `Adapters`
+pub trait Adapter: Send + Sync {
+    /// How the adapter is referred to, say in logs
+    fn name(&self) -> &'static str;
+
+    /// Whether `page` looks like something the adapter understands
+    fn accepts(&self, page: &Page) -> bool;
+
+    /// Everything of interest in `page`. Parts that can't be made
+    /// sense of are skipped
+    fn parse(&self, page: &Page) -> Vec<Found>;
+}
+
+/// A set of adapters, tried in order: the first that accepts a page
+/// gets to parse it
+pub struct Adapters(Vec<Box<dyn Adapter>>);
+
+impl Default for Adapters {
+    /// Every adapter available: `Feed` and then `JsonLd`
+    fn default() -> Self {
+        Self(vec![Box::new(Feed), Box::new(JsonLd)])
+    }
+}
+
+impl Adapters {
+    /// A set without any adapter
+    pub fn empty() -> Self {
+        Self(Vec::new())
+    }
+
+    /// Adds `adapter` to the end of the set
+    pub fn with<A: Adapter + 'static>(mut self, adapter: A) -> Self {
+        self.0.push(Box::new(adapter));
+        self
+    }
+
+    /// What the first adapter that accepts `page` finds in it. None
+    /// when no adapter accepts it
+    pub fn parse(&self, page: &Page) -> Option<(&'static str, Vec<Found>)> {
+        self.0
+            .iter()
+            .find(|adapter| adapter.accepts(page))
+            .map(|adapter| (adapter.name(), adapter.parse(page)))
+    }
+}
+
+/// The recipe details publications usually carry. Times are in
+/// minutes, nutrition per serving
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Scraped {
+    pub name: String,
+    pub crawl_url: String,
+    pub ingredients: Vec<String>,
+    pub instructions: Vec<String>,
+    pub images: Vec<String>,
+    pub prep_time: Option<u32>,
+    pub cook_time: Option<u32>,
+    pub total_time: Option<u32>,
+    pub calories: Option<u32>,
+    pub fat_content: Option<f32>,
+    pub carb_content: Option<f32>,
+    pub protein_content: Option<f32>,
+}
+
+impl Scraped {
+    /// Turns it into a recipe that can be loaded. The uuid is derived
+    /// from the url and name, so scraping a page again yields the
+    /// same one
+    pub fn into_recipe(self, recipe_id: RecipeId) -> Recipe {
+        let features = Features {
+            num_ingredients: self.ingredients.len().min(usize::from(std::u8::MAX)) as u8,
+            instructions_length: self
+                .instructions
+                .iter()
+                .map(|step| step.chars().count() as u32)
+                .sum(),
+            prep_time: self.prep_time,
+            cook_time: self.cook_time,
+            total_time: self.total_time,
+            calories: self.calories,
+            fat_content: self.fat_content,
+            carb_content: self.carb_content,
+            protein_content: self.protein_content,
+            ..Features::default()
+        };
+
+        Recipe {
+            uuid: stable_uuid(&format!("{}#{}", self.crawl_url, self.name)),
+            recipe_id,
+            name: self.name,
+            crawl_url: self.crawl_url,
+            ingredients: self.ingredients,
+            instructions: self.instructions,
+            images: self.images,
+            similar_recipe_ids: Vec::new(),
+            features,
+            author_id: None,
+            variant_of: None,
+            origin: None,
+            tenant_id: None,
+        }
+    }
+}
+
+// 128-bit FNV-1a: stable across runs, unlike std's hashers
+fn stable_uuid(input: &str) -> Uuid {
+    Uuid::from_u128(
+        input
+            .bytes()
+            .fold(0x6c62_272e_07bb_0142_62b8_2175_6295_c58d, |hash, byte| {
+                (hash ^ u128::from(byte)).wrapping_mul(0x0000_0000_0100_0000_0000_0000_0000_013b)
+            }),
+    )
+}
+
+/// Reads the schema.org `Recipe`s of html pages, as embedded in
+/// their `application/ld+json` scripts
+pub struct JsonLd;
+
+impl Adapter for JsonLd {
+    fn name(&self) -> &'static str {
+        "json-ld"
+    }
+
+    fn accepts(&self, page: &Page) -> bool {
+        page.body
+            .to_ascii_lowercase()
+            .contains("application/ld+json")
+    }
+
+    fn parse(&self, page: &Page) -> Vec<Found> {
+        let mut nodes = Vec::new();
+        for script in scripts(&page.body) {
+            if let Ok(data) = serde_json::from_str::<Value>(script) {
+                recipe_nodes(&data, &mut nodes);
+            }
+        }
+
+        nodes
+            .into_iter()
+            .filter_map(|node| scrape(node, &page.url))
+            .map(Found::Recipe)
+            .collect()
+    }
+}
+
+// The contents of every json-ld script of an html page
+fn scripts(html: &str) -> Vec<&str> {
+    // Lowercasing ascii keeps the offsets valid for the original
+    let lowercase = html.to_ascii_lowercase();
+    let mut found = Vec::new();
+
+    let mut offset = 0;
+    while let Some(idx) = lowercase[offset..].find("application/ld+json") {
+        let after_type = offset + idx;
+        let start = match lowercase[after_type..].find('>') {
+            Some(idx) => after_type + idx + 1,
+            None => break,
+        };
+        let end = match lowercase[start..].find("</script") {
+            Some(idx) => start + idx,
+            None => break,
+        };
+        found.push(&html[start..end]);
+        offset = end;
+    }
+
+    found
+}
+
+// Recipes may be at the top, in a list or in a `@graph`
+fn recipe_nodes<'a>(data: &'a Value, found: &mut Vec<&'a Value>) {
+    match data {
+        Value::Array(items) => items.iter().for_each(|item| recipe_nodes(item, found)),
+        Value::Object(object) => {
+            if is_recipe(data) {
+                found.push(data);
+            } else if let Some(graph) = object.get("@graph") {
+                recipe_nodes(graph, found);
+            }
+        }
+        _ => {}
+    }
+}
+
+fn is_recipe(node: &Value) -> bool {
+    match node.get("@type") {
+        Some(Value::String(kind)) => kind == "Recipe",
+        Some(Value::Array(kinds)) => kinds.iter().any(|kind| kind == "Recipe"),
+        _ => false,
+    }
+}
+
+fn scrape(node: &Value, url: &str) -> Option<Scraped> {
+    let name = node.get("name")?.as_str()?.trim();
+    if name.is_empty() {
+        return None;
+    }
+
+    let ingredients = node
+        .get("recipeIngredient")
+        .or_else(|| node.get("ingredients"))
+        .map(texts)
+        .unwrap_or_default();
+
+    let instructions = node
+        .get("recipeInstructions")
+        .map(texts)
+        .unwrap_or_default()
+        .iter()
+        .flat_map(|text| text.lines())
+        .map(str::trim)
+        .filter(|step| !step.is_empty())
+        .map(str::to_owned)
+        .collect();
+
+    let nutrition = node.get("nutrition");
+    let nutrient = |key: &str| nutrition.and_then(|n| n.get(key)).and_then(leading_number);
+
+    Some(Scraped {
+        name: name.to_owned(),
+        crawl_url: url.to_owned(),
+        ingredients,
+        instructions,
+        images: node.get("image").map(texts).unwrap_or_default(),
+        prep_time: node.get("prepTime").and_then(minutes),
+        cook_time: node.get("cookTime").and_then(minutes),
+        total_time: node.get("totalTime").and_then(minutes),
+        calories: nutrient("calories").map(|calories| calories.round() as u32),
+        fat_content: nutrient("fatContent").map(|value| value as f32),
+        carb_content: nutrient("carbohydrateContent").map(|value| value as f32),
+        protein_content: nutrient("proteinContent").map(|value| value as f32),
+    })
+}
+
+// Every text in a value that may be a string, a list, a `HowToStep`
+// (`text`), a `HowToSection` (`itemListElement`) or an `ImageObject`
+// (`url`), nested in any way
+fn texts(value: &Value) -> Vec<String> {
+    let mut found = Vec::new();
+    collect_texts(value, &mut found);
+    found
+}
+
+fn collect_texts(value: &Value, found: &mut Vec<String>) {
+    match value {
+        Value::String(text) => {
+            let text = text.trim();
+            if !text.is_empty() {
+                found.push(text.to_owned());
+            }
+        }
+        Value::Array(items) => items.iter().for_each(|item| collect_texts(item, found)),
+        Value::Object(object) => {
+            if let Some(inner) = ["text", "itemListElement", "url"]
+                .iter()
+                .find_map(|key| object.get(*key))
+            {
+                collect_texts(inner, found);
+            }
+        }
+        _ => {}
+    }
+}
+
+// Nutrition values come as numbers or like "250 kcal" or "10,5 g"
+fn leading_number(value: &Value) -> Option<f64> {
+    let number = match value {
+        Value::Number(number) => number.as_f64(),
+        Value::String(text) => {
+            let text = text.trim_start();
+            let end = text
+                .find(|c: char| !(c.is_ascii_digit() || c == '.' || c == ','))
+                .unwrap_or_else(|| text.len());
+            text[..end].replace(',', ".").parse().ok()
+        }
+        _ => None,
+    };
+    number.filter(|number| number.is_finite() && *number >= 0.0)
+}
+
+// An ISO 8601 duration like "PT1H30M" or "P0DT45M", in minutes
+// (rounding seconds up)
+fn minutes(value: &Value) -> Option<u32> {
+    let duration = value.as_str()?.trim().to_ascii_uppercase();
+    let mut rest = duration.strip_prefix('P')?;
+
+    let mut seconds = 0u64;
+    let mut in_time = false;
+    while !rest.is_empty() {
+        if let Some(after) = rest.strip_prefix('T') {
+            in_time = true;
+            rest = after;
+            continue;
+        }
+
+        let end = rest.find(|c: char| !(c.is_ascii_digit() || c == '.'))?;
+        let amount: f64 = rest[..end].parse().ok()?;
+        let unit = match (rest[end..].chars().next()?, in_time) {
+            ('D', false) => 86_400.0,
+            ('H', true) => 3_600.0,
+            ('M', true) => 60.0,
+            ('S', true) => 1.0,
+            _ => return None,
+        };
+        seconds += (amount * unit).round() as u64;
+        rest = &rest[end + 1..];
+    }
+
+    Some(((seconds + 59) / 60) as u32)
+}
+
+/// Reads RSS and Atom feeds, yielding a link to every entry so that
+/// the pages get fetched (and parsed by the other adapters)
+pub struct Feed;
+
+impl Adapter for Feed {
+    fn name(&self) -> &'static str {
+        "feed"
+    }
+
+    fn accepts(&self, page: &Page) -> bool {
+        let start = page.body.trim_start();
+        let head = start.get(..512).unwrap_or(start);
+        head.contains("<rss") || head.contains("<feed")
+    }
+
+    fn parse(&self, page: &Page) -> Vec<Found> {
+        let mut links = Vec::new();
+
+        for item in elements(&page.body, "item") {
+            if let Some(link) = elements(item, "link").into_iter().next() {
+                links.push(unescape(link));
+            }
+        }
+
+        for entry in elements(&page.body, "entry") {
+            if let Some(href) = attribute(entry, "link", "href") {
+                links.push(unescape(href));
+            }
+        }
+
+        links
+            .into_iter()
+            .filter(|link| !link.is_empty())
+            .map(Found::Link)
+            .collect()
+    }
+}
+
+// The contents of every `<tag>...</tag>` in a document
+fn elements<'a>(xml: &'a str, tag: &str) -> Vec<&'a str> {
+    let open = format!("<{}", tag);
+    let close = format!("</{}>", tag);
+    let mut found = Vec::new();
+
+    let mut offset = 0;
+    while let Some(idx) = xml[offset..].find(&open) {
+        let after_name = offset + idx + open.len();
+        // Not a longer tag name, like <items> for <item>
+        if !xml[after_name..].starts_with(|c: char| c == '>' || c.is_whitespace()) {
+            offset = after_name;
+            continue;
+        }
+
+        let start = match xml[after_name..].find('>') {
+            Some(idx) => after_name + idx + 1,
+            None => break,
+        };
+        let end = match xml[start..].find(&close) {
+            Some(idx) => start + idx,
+            None => break,
+        };
+        found.push(xml[start..end].trim());
+        offset = end + close.len();
+    }
+
+    found
+}
+
+// The value of `name` in the first `<tag ...>`, like the `href` of
+// an Atom `<link href="..."/>`
+fn attribute<'a>(xml: &'a str, tag: &str, name: &str) -> Option<&'a str> {
+    let open = format!("<{} ", tag);
+    let start = xml.find(&open)? + open.len();
+    let end = start + xml[start..].find('>')?;
+    let attributes = &xml[start..end];
+
+    let key = format!("{}=", name);
+    let value = &attributes[attributes.find(&key)? + key.len()..];
+    let quote = value.chars().next().filter(|c| *c == '"' || *c == '\'')?;
+    let value = &value[1..];
+    Some(&value[..value.find(quote)?])
+}
+
+fn unescape(text: &str) -> String {
+    let text = text
+        .trim()
+        .trim_start_matches("<![CDATA[")
+        .trim_end_matches("]]>");
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+        .trim()
+        .to_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn page(url: &str, body: &str) -> Page {
+        Page {
+            url: url.to_owned(),
+            body: body.to_owned(),
+        }
+    }
+
+    const RECIPE_PAGE: &str = r#"<html><head>
+        <script type="application/ld+json">{"@type": "WebSite", "name": "Example"}</script>
+        <SCRIPT TYPE="application/ld+json">
+        {
+          "@context": "https://schema.org",
+          "@graph": [
+            {"@type": "Organization", "name": "Example"},
+            {
+              "@type": ["Recipe", "NewsArticle"],
+              "name": " Pão de Queijo ",
+              "image": [{"@type": "ImageObject", "url": "https://example.com/pao.jpg"}],
+              "recipeIngredient": ["500g polvilho", "2 ovos", " "],
+              "recipeInstructions": [
+                {"@type": "HowToSection", "name": "Dough", "itemListElement": [
+                  {"@type": "HowToStep", "text": "Mix everything"},
+                  {"@type": "HowToStep", "text": "Knead"}
+                ]},
+                "Bake for 25 minutes"
+              ],
+              "prepTime": "PT15M",
+              "cookTime": "PT25M",
+              "totalTime": "P0DT1H30S",
+              "nutrition": {"calories": "120 kcal", "fatContent": "4,5 g", "proteinContent": 3}
+            }
+          ]
+        }
+        </SCRIPT>
+        <script type="application/ld+json">{ not json </script>
+    </head></html>"#;
+
+    #[test]
+    fn json_ld_recipes() {
+        let page = page("https://example.com/pao", RECIPE_PAGE);
+        let adapters = Adapters::default();
+
+        let (name, found) = adapters.parse(&page).expect("page is accepted");
+        assert_eq!("json-ld", name);
+        assert_eq!(
+            vec![Found::Recipe(Scraped {
+                name: "Pão de Queijo".to_owned(),
+                crawl_url: "https://example.com/pao".to_owned(),
+                ingredients: vec!["500g polvilho".to_owned(), "2 ovos".to_owned()],
+                instructions: vec![
+                    "Mix everything".to_owned(),
+                    "Knead".to_owned(),
+                    "Bake for 25 minutes".to_owned()
+                ],
+                images: vec!["https://example.com/pao.jpg".to_owned()],
+                prep_time: Some(15),
+                cook_time: Some(25),
+                total_time: Some(61),
+                calories: Some(120),
+                fat_content: Some(4.5),
+                carb_content: None,
+                protein_content: Some(3.0),
+            })],
+            found
+        );
+
+        let recipe = match found.into_iter().next() {
+            Some(Found::Recipe(scraped)) => scraped.into_recipe(42),
+            other => panic!("Expected a recipe, got {:?}", other),
+        };
+        assert_eq!(42, recipe.recipe_id);
+        assert_eq!(2, recipe.features.num_ingredients);
+        assert_eq!(38, recipe.features.instructions_length);
+
+        // Same page, same uuid
+        let (_, again) = adapters.parse(&page).unwrap();
+        match again.into_iter().next() {
+            Some(Found::Recipe(scraped)) => {
+                assert_eq!(recipe.uuid, scraped.into_recipe(43).uuid);
+            }
+            other => panic!("Expected a recipe, got {:?}", other),
+        }
+
+        assert_eq!(
+            None,
+            adapters.parse(&self::page("https://example.com", "<html></html>"))
+        );
+    }
+
+    #[test]
+    fn durations() {
+        for (input, expected) in &[
+            ("PT15M", Some(15)),
+            ("pt1h30m", Some(90)),
+            ("P1DT2H", Some(1560)),
+            ("PT90S", Some(2)),
+            ("PT0.5H", Some(30)),
+            ("P", Some(0)),
+            ("15 minutes", None),
+            ("PT15", None),
+            ("P1H", None),
+        ] {
+            assert_eq!(*expected, minutes(&Value::from(*input)), "{}", input);
+        }
+    }
+
+    #[test]
+    fn feeds_link_to_their_entries() {
+        let rss = page(
+            "https://example.com/feed",
+            r#"<?xml version="1.0"?>
+            <rss version="2.0"><channel>
+              <link>https://example.com</link>
+              <item><title>One</title><link>https://example.com/one?a=1&amp;b=2</link></item>
+              <item><title>Two</title><link><![CDATA[https://example.com/two]]></link></item>
+              <item><title>No link</title></item>
+            </channel></rss>"#,
+        );
+        let (name, found) = Adapters::default().parse(&rss).unwrap();
+        assert_eq!("feed", name);
+        assert_eq!(
+            vec![
+                Found::Link("https://example.com/one?a=1&b=2".to_owned()),
+                Found::Link("https://example.com/two".to_owned()),
+            ],
+            found
+        );
+
+        let atom = page(
+            "https://example.com/atom",
+            r#"<?xml version="1.0" encoding="utf-8"?>
+            <feed xmlns="http://www.w3.org/2005/Atom">
+              <entry><title>One</title><link rel="alternate" href="https://example.com/one"/></entry>
+            </feed>"#,
+        );
+        assert_eq!(
+            vec![Found::Link("https://example.com/one".to_owned())],
+            Feed.parse(&atom)
+        );
+    }
+}
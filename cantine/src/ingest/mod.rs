@@ -0,0 +1,8 @@
+pub mod adapters;
+mod rules;
+#[cfg(feature = "scripting")]
+mod scripts;
+
+pub use rules::{IngestRules, Rule};
+#[cfg(feature = "scripting")]
+pub use scripts::IngestScripts;
@@ -0,0 +1,310 @@
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{self, BufReader, Result},
+    path::Path,
+};
+
+use serde::Deserialize;
+use serde_json::{Map, Value};
+
+/// Fixes to the raw json of recipes, applied when loading them
+/// (before decoding), so that the quirks of a single source site
+/// can be dealt with by configuration instead of code.
+///
+/// Rules are data instead of scripts: the worst a bad rule set can
+/// do is mangle the recipes of the sites it targets. For whatever
+/// they can't express there's `IngestScripts`. Fields are
+/// addressed by their dotted path, like `features.calories`, and
+/// rules that don't apply (say: the field is missing) do nothing.
+///
+/// ```json
+/// {
+///   "all": [{ "op": "trim", "field": "name" }],
+///   "sites": {
+///     "example.com": [
+///       { "op": "rename", "from": "title", "to": "name" },
+///       { "op": "scale", "field": "features.calories", "factor": 0.239 }
+///     ]
+///   }
+/// }
+/// ```
+#[derive(Deserialize, Debug, Default, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct IngestRules {
+    /// Applied to every recipe, after the rules of its site
+    #[serde(default)]
+    pub all: Vec<Rule>,
+    /// By the host of the recipe's `crawl_url`, as is
+    #[serde(default)]
+    pub sites: HashMap<String, Vec<Rule>>,
+}
+
+/// A fix in `IngestRules`, tagged by its `op`
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "op", rename_all = "snake_case", deny_unknown_fields)]
+pub enum Rule {
+    /// Moves a value to another field, replacing what's there
+    Rename {
+        from: String,
+        to: String,
+    },
+    /// Like `Rename`, but keeps the original
+    Copy {
+        from: String,
+        to: String,
+    },
+    Remove {
+        field: String,
+    },
+    /// Sets a value unless the field has one already
+    Default {
+        field: String,
+        value: Value,
+    },
+    /// Strips the whitespace around a string. For lists, it's done
+    /// to every string, dropping the ones left empty
+    Trim {
+        field: String,
+    },
+    /// Multiplies a number, like for converting kilojoules to
+    /// calories. Integers stay integers, rounded
+    Scale {
+        field: String,
+        factor: f64,
+    },
+}
+
+impl IngestRules {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        serde_json::from_reader(BufReader::new(File::open(path)?))
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+    }
+
+    /// Applies the rules for the site of `recipe` and then the ones
+    /// for every recipe, in order. So site rules can map fields to
+    /// their usual names before the common cleanup
+    pub fn apply(&self, recipe: &mut Value) {
+        let site = recipe
+            .get("crawl_url")
+            .and_then(Value::as_str)
+            .and_then(host)
+            .map(str::to_owned);
+        let site_rules = site
+            .and_then(|site| self.sites.get(&site))
+            .map_or(&[][..], Vec::as_slice);
+
+        for rule in site_rules.iter().chain(self.all.iter()) {
+            rule.apply(recipe);
+        }
+    }
+}
+
+impl Rule {
+    pub fn apply(&self, recipe: &mut Value) {
+        match self {
+            Rule::Rename { from, to } => {
+                if let Some(value) = take(recipe, from) {
+                    insert(recipe, to, value);
+                }
+            }
+            Rule::Copy { from, to } => {
+                if let Some(value) = get_mut(recipe, from).map(|value| value.clone()) {
+                    insert(recipe, to, value);
+                }
+            }
+            Rule::Remove { field } => {
+                take(recipe, field);
+            }
+            Rule::Default { field, value } => {
+                if get_mut(recipe, field).map_or(true, |current| current.is_null()) {
+                    insert(recipe, field, value.clone());
+                }
+            }
+            Rule::Trim { field } => match get_mut(recipe, field) {
+                Some(Value::String(text)) => *text = text.trim().to_owned(),
+                Some(Value::Array(items)) => {
+                    for item in items.iter_mut() {
+                        if let Value::String(text) = item {
+                            *text = text.trim().to_owned();
+                        }
+                    }
+                    items.retain(|item| item.as_str().map_or(true, |text| !text.is_empty()));
+                }
+                _ => {}
+            },
+            Rule::Scale { field, factor } => {
+                if let Some(current) = get_mut(recipe, field) {
+                    if let Some(scaled) = scale(current, *factor) {
+                        *current = scaled;
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn scale(value: &Value, factor: f64) -> Option<Value> {
+    let scaled = value.as_f64()? * factor;
+    if value.is_u64() && scaled >= 0.0 {
+        Some(Value::from(scaled.round() as u64))
+    } else if value.is_i64() || value.is_u64() {
+        Some(Value::from(scaled.round() as i64))
+    } else {
+        serde_json::Number::from_f64(scaled).map(Value::Number)
+    }
+}
+
+fn get_mut<'a>(value: &'a mut Value, path: &str) -> Option<&'a mut Value> {
+    path.split('.')
+        .try_fold(value, |current, key| current.get_mut(key))
+}
+
+fn take(value: &mut Value, path: &str) -> Option<Value> {
+    let (parent, key) = match path.rfind('.') {
+        Some(idx) => (get_mut(value, &path[..idx])?, &path[idx + 1..]),
+        None => (value, path),
+    };
+    parent.as_object_mut()?.remove(key)
+}
+
+// Creates the objects along the path as needed
+fn insert(value: &mut Value, path: &str, new: Value) {
+    let mut current = value;
+    let mut keys = path.split('.').peekable();
+    while let Some(key) = keys.next() {
+        let object = match current.as_object_mut() {
+            Some(object) => object,
+            None => return,
+        };
+
+        if keys.peek().is_none() {
+            object.insert(key.to_owned(), new);
+            return;
+        }
+
+        current = object
+            .entry(key)
+            .or_insert_with(|| Value::Object(Map::new()));
+    }
+}
+
+pub(super) fn host(url: &str) -> Option<&str> {
+    url.splitn(2, "://")
+        .nth(1)?
+        .split(|c| c == '/' || c == '?' || c == '#')
+        .next()
+        .filter(|host| !host.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use serde_json::json;
+
+    #[test]
+    fn rules_are_picked_by_site() {
+        let rules: IngestRules = serde_json::from_value(json!({
+            "all": [{ "op": "trim", "field": "name" }],
+            "sites": {
+                "example.com": [
+                    { "op": "rename", "from": "title", "to": "name" },
+                    { "op": "default", "field": "images", "value": [] }
+                ]
+            }
+        }))
+        .unwrap();
+
+        let mut from_example = json!({
+            "crawl_url": "https://example.com/recipes/1?ref=feed",
+            "title": "  Bolo de Fubá ",
+        });
+        rules.apply(&mut from_example);
+        assert_eq!(
+            json!({
+                "crawl_url": "https://example.com/recipes/1?ref=feed",
+                "name": "Bolo de Fubá",
+                "images": [],
+            }),
+            from_example
+        );
+
+        let mut elsewhere = json!({
+            "crawl_url": "https://example.org/1",
+            "name": " Pão de Queijo",
+            "title": "Other",
+        });
+        rules.apply(&mut elsewhere);
+        assert_eq!(
+            json!({
+                "crawl_url": "https://example.org/1",
+                "name": "Pão de Queijo",
+                "title": "Other",
+            }),
+            elsewhere
+        );
+    }
+
+    #[test]
+    fn rules_address_nested_fields() {
+        let mut recipe = json!({
+            "ingredients": [" flour ", "", "eggs"],
+            "features": { "calories": 1000, "fat_content": 10.0 },
+            "kj": 500,
+        });
+
+        for rule in &[
+            Rule::Trim {
+                field: "ingredients".to_owned(),
+            },
+            Rule::Scale {
+                field: "features.calories".to_owned(),
+                factor: 0.239,
+            },
+            Rule::Scale {
+                field: "features.fat_content".to_owned(),
+                factor: 0.5,
+            },
+            Rule::Copy {
+                from: "kj".to_owned(),
+                to: "extra.energy.kj".to_owned(),
+            },
+            Rule::Remove {
+                field: "kj".to_owned(),
+            },
+            Rule::Default {
+                field: "features.calories".to_owned(),
+                value: json!(1),
+            },
+            // Nothing to do
+            Rule::Rename {
+                from: "features.missing".to_owned(),
+                to: "name".to_owned(),
+            },
+            Rule::Scale {
+                field: "ingredients".to_owned(),
+                factor: 2.0,
+            },
+        ] {
+            rule.apply(&mut recipe);
+        }
+
+        assert_eq!(
+            json!({
+                "ingredients": ["flour", "eggs"],
+                "features": { "calories": 239, "fat_content": 5.0 },
+                "extra": { "energy": { "kj": 500 } },
+            }),
+            recipe
+        );
+    }
+
+    #[test]
+    fn hosts() {
+        assert_eq!(Some("example.com"), host("https://example.com"));
+        assert_eq!(Some("a.example.com"), host("http://a.example.com/x#y"));
+        assert_eq!(None, host("example.com/x"));
+        assert_eq!(None, host("https:///x"));
+    }
+}
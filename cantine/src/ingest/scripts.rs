@@ -0,0 +1,200 @@
+use std::{
+    collections::HashMap,
+    fmt, fs,
+    io::{self, Result},
+    path::Path,
+};
+
+use rhai::{
+    serde::{from_dynamic, to_dynamic},
+    Dynamic, Engine, Scope, AST,
+};
+use serde_json::Value;
+
+use super::rules::host;
+
+/// The variable scripts get the recipe in
+const RECIPE_VAR: &str = "recipe";
+const SCRIPT_EXTENSION: &str = "rhai";
+
+// How far a script may go before it's stopped
+const MAX_OPERATIONS: u64 = 100_000;
+const MAX_CALL_LEVELS: usize = 16;
+const MAX_EXPR_DEPTH: usize = 64;
+const MAX_STRING_SIZE: usize = 1 << 20;
+const MAX_COLLECTION_SIZE: usize = 10_000;
+
+/// Per-site scripts that get to change the raw json of recipes when
+/// loading them, for whatever `IngestRules` can't express (say: a
+/// field computed from others). Needs the `scripting` feature.
+///
+/// Scripts are written in [rhai](https://rhai.rs) and find the recipe
+/// as a map in the `recipe` variable, which they change in place:
+///
+/// ```rhai
+/// recipe.features.calories = recipe.energy_kj * 239 / 1000;
+/// recipe.remove("energy_kj");
+/// ```
+///
+/// They run sandboxed: rhai can't touch the file system nor the
+/// network, and scripts are stopped after a bounded number of
+/// operations, so the worst a bad one can do is fail.
+pub struct IngestScripts {
+    engine: Engine,
+    sites: HashMap<String, AST>,
+}
+
+impl IngestScripts {
+    /// Compiles every `.rhai` file in `dir`, each the script for the
+    /// site it's named after (like `example.com.rhai`). Fails on the
+    /// first script that doesn't compile
+    pub fn load<P: AsRef<Path>>(dir: P) -> Result<Self> {
+        let engine = sandboxed_engine();
+
+        let mut sites = HashMap::new();
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+            if path.extension().and_then(|ext| ext.to_str()) != Some(SCRIPT_EXTENSION) {
+                continue;
+            }
+
+            let site = match path.file_stem().and_then(|stem| stem.to_str()) {
+                Some(site) => site.to_owned(),
+                None => continue,
+            };
+
+            let ast = engine
+                .compile(&fs::read_to_string(&path)?)
+                .map_err(|err| invalid(format!("{:?}: {}", path, err)))?;
+            sites.insert(site, ast);
+        }
+
+        Ok(Self { engine, sites })
+    }
+
+    /// Runs the script for the site of `recipe`, if there's one. The
+    /// recipe is left as it was when the script fails
+    pub fn apply(&self, recipe: &mut Value) -> Result<()> {
+        let site = recipe
+            .get("crawl_url")
+            .and_then(Value::as_str)
+            .and_then(host);
+        let ast = match site.and_then(|site| self.sites.get(site)) {
+            Some(ast) => ast,
+            None => return Ok(()),
+        };
+
+        let mut scope = Scope::new();
+        scope.push_dynamic(
+            RECIPE_VAR,
+            to_dynamic(&*recipe).map_err(|err| invalid(err.to_string()))?,
+        );
+        self.engine
+            .consume_ast_with_scope(&mut scope, ast)
+            .map_err(|err| invalid(err.to_string()))?;
+
+        let scripted = scope
+            .get_value::<Dynamic>(RECIPE_VAR)
+            .ok_or_else(|| invalid(format!("The script dropped `{}`", RECIPE_VAR)))?;
+        *recipe = from_dynamic(&scripted).map_err(|err| invalid(err.to_string()))?;
+
+        Ok(())
+    }
+}
+
+impl fmt::Debug for IngestScripts {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut sites = self.sites.keys().collect::<Vec<_>>();
+        sites.sort();
+        f.debug_struct("IngestScripts")
+            .field("sites", &sites)
+            .finish()
+    }
+}
+
+fn sandboxed_engine() -> Engine {
+    let mut engine = Engine::new();
+    engine
+        .set_max_operations(MAX_OPERATIONS)
+        .set_max_call_levels(MAX_CALL_LEVELS)
+        .set_max_expr_depths(MAX_EXPR_DEPTH, MAX_EXPR_DEPTH)
+        .set_max_string_size(MAX_STRING_SIZE)
+        .set_max_array_size(MAX_COLLECTION_SIZE)
+        .set_max_map_size(MAX_COLLECTION_SIZE);
+    engine
+}
+
+fn invalid(reason: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, reason)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use serde_json::json;
+
+    fn scripts(sources: &[(&str, &str)]) -> Result<IngestScripts> {
+        let dir = tempfile::tempdir()?;
+        for (name, source) in sources {
+            fs::write(dir.path().join(name), source)?;
+        }
+        IngestScripts::load(dir.path())
+    }
+
+    #[test]
+    fn scripts_are_picked_by_site() -> Result<()> {
+        let scripts = scripts(&[
+            (
+                "example.com.rhai",
+                r#"
+                recipe.features.calories = recipe.energy_kj * 239 / 1000;
+                recipe.remove("energy_kj");
+                recipe.name = recipe.name + " (" + recipe.features.num_ingredients + ")";
+                "#,
+            ),
+            ("notes.txt", "not a script"),
+        ])?;
+
+        let mut from_example = json!({
+            "crawl_url": "https://example.com/recipes/1",
+            "name": "Bolo de Fubá",
+            "energy_kj": 1000,
+            "features": { "num_ingredients": 5 },
+        });
+        scripts.apply(&mut from_example)?;
+        assert_eq!(
+            json!({
+                "crawl_url": "https://example.com/recipes/1",
+                "name": "Bolo de Fubá (5)",
+                "features": { "num_ingredients": 5, "calories": 239 },
+            }),
+            from_example
+        );
+
+        let mut elsewhere = json!({
+            "crawl_url": "https://example.org/1",
+            "energy_kj": 1000,
+        });
+        let untouched = elsewhere.clone();
+        scripts.apply(&mut elsewhere)?;
+        assert_eq!(untouched, elsewhere);
+
+        Ok(())
+    }
+
+    #[test]
+    fn scripts_are_sandboxed() -> Result<()> {
+        assert!(scripts(&[("example.com.rhai", "recipe.name = ")]).is_err());
+
+        let scripts = scripts(&[("example.com.rhai", "recipe.name = \"x\"; loop {}")])?;
+        let mut recipe = json!({ "crawl_url": "https://example.com/1", "name": "Pão" });
+        let untouched = recipe.clone();
+
+        // Runs out of operations instead of hanging
+        assert!(scripts.apply(&mut recipe).is_err());
+        assert_eq!(untouched, recipe);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,96 @@
+use std::collections::BTreeSet;
+
+use tantivy::tokenizer::{AsciiFoldingFilter, LowerCaser, SimpleTokenizer, TextAnalyzer};
+
+/// Longer prefixes are not indexed: typing past this many characters
+/// keeps matching via the longest prefix that is
+pub const MAX_PREFIX_LENGTH: usize = 20;
+
+/// The most items an instant search may ask for
+pub const MAX_ITEMS: usize = 10;
+
+/// Typed words shorter than this are only taken as prefixes: there
+/// are too many words a single typo away from them
+pub const FUZZY_MIN_LENGTH: usize = 4;
+
+/// How many edits away from a typed word a misspelled match may be
+pub const FUZZY_DISTANCE: u8 = 1;
+
+/// How many of the first characters of a typed word must be right
+/// for it to find misspelled matches
+pub const FUZZY_PREFIX_LENGTH: usize = 2;
+
+/// How many recent searches an instant search takes hints from
+pub const MAX_COMPLETIONS: usize = 3;
+
+/// How much the words of a recent search weigh, per (logarithm of
+/// the) time it was done
+pub const COMPLETION_BOOST: f32 = 0.5;
+
+/// Every prefix of every word of a recipe name, normalized. Indexed
+/// as is (with the "raw" tokenizer) so that instant searches are
+/// just term lookups
+pub fn name_prefixes(name: &str) -> BTreeSet<String> {
+    let mut prefixes = BTreeSet::new();
+    for word in normalized_words(name) {
+        let mut prefix = String::with_capacity(word.len());
+        for c in word.chars().take(MAX_PREFIX_LENGTH) {
+            prefix.push(c);
+            prefixes.insert(prefix.clone());
+        }
+    }
+    prefixes
+}
+
+/// The terms to look up for what was typed so far: every word of it
+/// is taken as a prefix
+pub fn prefix_terms(input: &str) -> Vec<String> {
+    let mut terms: Vec<_> = normalized_words(input)
+        .into_iter()
+        .map(|word| word.chars().take(MAX_PREFIX_LENGTH).collect::<String>())
+        .collect();
+    terms.sort();
+    terms.dedup();
+    terms
+}
+
+// Lowercased and folded regardless of the index analysis, since
+// people rarely bother with case or accents while typing
+fn normalized_words(text: &str) -> Vec<String> {
+    let analyzer = TextAnalyzer::from(SimpleTokenizer)
+        .filter(LowerCaser)
+        .filter(AsciiFoldingFilter);
+
+    let mut words = Vec::new();
+    analyzer
+        .token_stream(text)
+        .process(&mut |token| words.push(token.text.clone()));
+    words
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn prefixes_of_every_word() {
+        let prefixes = name_prefixes("Crème Brûlée");
+        for expected in &["c", "cr", "creme", "b", "brul", "brulee"] {
+            assert!(prefixes.contains(*expected), "{}", expected);
+        }
+        assert!(!prefixes.contains("creme brulee"));
+        assert!(!prefixes.contains("rem"));
+
+        let long = "a".repeat(MAX_PREFIX_LENGTH * 2);
+        assert_eq!(MAX_PREFIX_LENGTH, name_prefixes(&long).len());
+    }
+
+    #[test]
+    fn typed_words_become_prefixes() {
+        assert_eq!(vec!["brul", "creme"], prefix_terms("CRÈME  brûl creme"));
+        assert!(prefix_terms("  ").is_empty());
+
+        let long = "a".repeat(MAX_PREFIX_LENGTH * 2);
+        assert_eq!(vec!["a".repeat(MAX_PREFIX_LENGTH)], prefix_terms(&long));
+    }
+}
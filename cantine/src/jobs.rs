@@ -0,0 +1,284 @@
+use std::{
+    collections::HashMap,
+    panic::{self, AssertUnwindSafe},
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex, RwLock,
+    },
+    thread,
+};
+
+use crossbeam_channel::{bounded, Sender, TrySendError};
+use tantivy::{Result, TantivyError};
+
+use crate::{
+    clock::Clock,
+    model::{JobState, JobStatus},
+};
+
+type Job = Box<dyn FnOnce() + Send>;
+
+/// Runs long tasks (like exports) in the background, on a few threads
+/// of their own, keeping track of how far along each one is.
+///
+/// Jobs wait in a queue of limited size for a thread to be free, so
+/// a burst of them can't take the machine down: once it's full, new
+/// ones are turned away. The statuses of finished jobs are forgotten
+/// a while after they finish.
+///
+/// Jobs only live in memory: restarting forgets about them, though
+/// not about what they already wrote.
+pub struct Jobs {
+    last_id: AtomicU64,
+    jobs: RwLock<HashMap<u64, Progress>>,
+    queue: Sender<Job>,
+    retention: u64,
+    clock: Arc<dyn Clock>,
+}
+
+/// How a running job reports its progress
+#[derive(Clone)]
+pub struct Progress(Arc<Mutex<JobStatus>>);
+
+impl Jobs {
+    /// Runs jobs on `num_threads` threads, with up to `max_queued`
+    /// of them waiting for one. Finished jobs are kept for
+    /// `retention` seconds, as told by `clock`
+    pub fn new(
+        num_threads: usize,
+        max_queued: usize,
+        retention: u64,
+        clock: Box<dyn Clock>,
+    ) -> Result<Self> {
+        let (queue, receiver) = bounded::<Job>(max_queued);
+
+        for idx in 0..num_threads.max(1) {
+            let receiver = receiver.clone();
+            thread::Builder::new()
+                .name(format!("cantine-jobs-{}", idx))
+                .spawn(move || {
+                    for job in receiver {
+                        job();
+                    }
+                })?;
+        }
+
+        Ok(Self {
+            last_id: AtomicU64::new(0),
+            jobs: RwLock::new(HashMap::new()),
+            queue,
+            retention,
+            clock: Arc::from(clock),
+        })
+    }
+
+    /// Queues `task` to run in one of the threads. The task yields
+    /// where it wrote its results to. Returns the id of the job, or
+    /// None when the queue is full, in which case `task` never runs
+    pub fn start<F>(&self, task: F) -> Result<Option<u64>>
+    where
+        F: FnOnce(&Progress) -> Result<PathBuf> + Send + 'static,
+    {
+        let id = self.last_id.fetch_add(1, Ordering::Relaxed) + 1;
+        let progress = Progress(Arc::new(Mutex::new(JobStatus {
+            id,
+            state: JobState::Queued,
+            total: None,
+            processed: 0,
+            output: None,
+            error: None,
+            finished_at: None,
+        })));
+
+        let mut jobs = self.jobs.write().expect("lock not poisoned");
+        let now = self.clock.now();
+        jobs.retain(|_id, progress| !progress.has_expired(now, self.retention));
+        jobs.insert(id, progress.clone());
+
+        let clock = self.clock.clone();
+        let job: Job = Box::new(move || {
+            progress.lock().state = JobState::Running;
+
+            let outcome = panic::catch_unwind(AssertUnwindSafe(|| task(&progress)))
+                .unwrap_or_else(|_| Err(TantivyError::SystemError("Job panicked".to_owned())));
+
+            let mut status = progress.lock();
+            match outcome {
+                Ok(output) => {
+                    status.state = JobState::Done;
+                    status.output = Some(output.to_string_lossy().into_owned());
+                }
+                Err(err) => {
+                    log::error!("Job {} failed: {}", id, err);
+                    status.state = JobState::Failed;
+                    status.error = Some(err.to_string());
+                }
+            }
+            status.finished_at = Some(clock.now());
+        });
+
+        match self.queue.try_send(job) {
+            Ok(()) => Ok(Some(id)),
+            Err(TrySendError::Full(_job)) => {
+                jobs.remove(&id);
+                Ok(None)
+            }
+            Err(TrySendError::Disconnected(_job)) => {
+                jobs.remove(&id);
+                Err(TantivyError::SystemError("Job threads are gone".to_owned()))
+            }
+        }
+    }
+
+    /// How the job `id` is doing, if there's such a job
+    pub fn status(&self, id: u64) -> Option<JobStatus> {
+        let now = self.clock.now();
+        self.jobs
+            .read()
+            .expect("lock not poisoned")
+            .get(&id)
+            .filter(|progress| !progress.has_expired(now, self.retention))
+            .map(|progress| progress.lock().clone())
+    }
+}
+
+impl Progress {
+    /// The id of the job reporting
+    pub fn job_id(&self) -> u64 {
+        self.lock().id
+    }
+
+    /// How many items the job will go through, when known
+    pub fn set_total(&self, total: usize) {
+        self.lock().total = Some(total);
+    }
+
+    /// Accounts for `num_items` more items done
+    pub fn advance(&self, num_items: usize) {
+        self.lock().processed += num_items;
+    }
+
+    fn has_expired(&self, now: u64, retention: u64) -> bool {
+        self.lock()
+            .finished_at
+            .map_or(false, |finished_at| finished_at + retention <= now)
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, JobStatus> {
+        self.0.lock().expect("lock not poisoned")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::mpsc;
+
+    use crate::clock::{FixedClock, SystemClock};
+
+    fn wait_for(jobs: &Jobs, id: u64, state: JobState) -> JobStatus {
+        loop {
+            let status = jobs.status(id).expect("job exists");
+            if status.state == state {
+                return status;
+            }
+            thread::yield_now();
+        }
+    }
+
+    #[test]
+    fn progress_is_reported() -> Result<()> {
+        let jobs = Jobs::new(1, 4, 60, Box::new(SystemClock))?;
+        let (proceed, wait) = mpsc::channel::<()>();
+
+        let id = jobs
+            .start(move |progress| {
+                progress.set_total(10);
+                progress.advance(4);
+                wait.recv().expect("test still running");
+                progress.advance(6);
+                Ok(PathBuf::from("/tmp/export"))
+            })?
+            .expect("queue has room");
+        assert_eq!(None, jobs.status(id + 1));
+
+        proceed.send(()).expect("job still running");
+        let status = wait_for(&jobs, id, JobState::Done);
+        assert_eq!(Some(10), status.total);
+        assert_eq!(10, status.processed);
+        assert_eq!(Some("/tmp/export".to_owned()), status.output);
+        assert!(status.finished_at.is_some());
+
+        let failing = jobs
+            .start(|_progress| Err(TantivyError::SystemError("disk full".to_owned())))?
+            .expect("queue has room");
+        assert_ne!(id, failing);
+        let status = wait_for(&jobs, failing, JobState::Failed);
+        assert!(status.error.expect("has error").contains("disk full"));
+
+        let panicking = jobs
+            .start(|_progress| panic!("oops"))?
+            .expect("queue has room");
+        wait_for(&jobs, panicking, JobState::Failed);
+
+        Ok(())
+    }
+
+    #[test]
+    fn jobs_are_turned_away_when_the_queue_is_full() -> Result<()> {
+        let jobs = Jobs::new(1, 1, 60, Box::new(SystemClock))?;
+        let (proceed, wait) = mpsc::channel::<()>();
+        let wait = Arc::new(Mutex::new(wait));
+
+        let blocked = |wait: Arc<Mutex<mpsc::Receiver<()>>>| {
+            move |_progress: &Progress| -> Result<PathBuf> {
+                wait.lock()
+                    .expect("lock not poisoned")
+                    .recv()
+                    .expect("test still running");
+                Ok(PathBuf::from("/tmp/export"))
+            }
+        };
+
+        let running = jobs.start(blocked(wait.clone()))?.expect("queue has room");
+        wait_for(&jobs, running, JobState::Running);
+
+        let queued = jobs.start(blocked(wait.clone()))?.expect("queue has room");
+        assert_eq!(
+            JobState::Queued,
+            jobs.status(queued).expect("job exists").state
+        );
+        assert_eq!(None, jobs.start(blocked(wait))?);
+
+        proceed.send(()).expect("job still running");
+        proceed.send(()).expect("job still running");
+        wait_for(&jobs, running, JobState::Done);
+        wait_for(&jobs, queued, JobState::Done);
+
+        Ok(())
+    }
+
+    #[test]
+    fn finished_jobs_expire() -> Result<()> {
+        let jobs = Jobs::new(1, 4, 0, Box::new(FixedClock(100)))?;
+
+        let id = jobs
+            .start(|_progress| Ok(PathBuf::from("/tmp/export")))?
+            .expect("queue has room");
+        while jobs.status(id).is_some() {
+            thread::yield_now();
+        }
+
+        // Forgotten for good when the next job starts
+        jobs.start(|_progress| Ok(PathBuf::from("/tmp/export")))?;
+        assert!(!jobs
+            .jobs
+            .read()
+            .expect("lock not poisoned")
+            .contains_key(&id));
+
+        Ok(())
+    }
+}
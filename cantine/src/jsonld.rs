@@ -0,0 +1,200 @@
+use serde::Serialize;
+use uuid::Uuid;
+
+use crate::model::{Author, Recipe};
+
+/// A recipe as a schema.org `Recipe`, the json-ld that search engines
+/// and partners read from `<script type="application/ld+json">` tags.
+/// Details the recipe lacks are left out instead of set to null
+#[derive(Serialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct RecipeJsonLd {
+    #[serde(rename = "@context")]
+    pub context: &'static str,
+    #[serde(rename = "@type")]
+    pub kind: &'static str,
+    pub identifier: Uuid,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub name: String,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub url: String,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub image: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub recipe_ingredient: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub recipe_instructions: Vec<HowToStep>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub prep_time: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cook_time: Option<String>,
+    /// Only when it comes from the source: estimates are not published
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub total_time: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub nutrition: Option<NutritionInformation>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub author: Option<Person>,
+}
+
+/// A single step of `recipeInstructions`
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct HowToStep {
+    #[serde(rename = "@type")]
+    pub kind: &'static str,
+    pub text: String,
+}
+
+/// Per serving, with units, like `"250 calories"` and `"10 g"`
+#[derive(Serialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct NutritionInformation {
+    #[serde(rename = "@type")]
+    pub kind: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub calories: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fat_content: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub carbohydrate_content: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub protein_content: Option<String>,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Person {
+    #[serde(rename = "@type")]
+    pub kind: &'static str,
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+}
+
+impl RecipeJsonLd {
+    pub fn new(recipe: &Recipe, author: Option<&Author>) -> Self {
+        let features = &recipe.features;
+
+        let grams = |value: Option<f32>| value.map(|value| format!("{} g", value));
+        let nutrition = NutritionInformation {
+            kind: "NutritionInformation",
+            calories: features
+                .calories
+                .map(|calories| format!("{} calories", calories)),
+            fat_content: grams(features.fat_content),
+            carbohydrate_content: grams(features.carb_content),
+            protein_content: grams(features.protein_content),
+        };
+        let has_nutrition = nutrition.calories.is_some()
+            || nutrition.fat_content.is_some()
+            || nutrition.carbohydrate_content.is_some()
+            || nutrition.protein_content.is_some();
+
+        Self {
+            context: "https://schema.org",
+            kind: "Recipe",
+            identifier: recipe.uuid,
+            name: recipe.name.clone(),
+            url: recipe.crawl_url.clone(),
+            image: recipe.images.clone(),
+            recipe_ingredient: recipe.ingredients.clone(),
+            recipe_instructions: recipe
+                .instructions
+                .iter()
+                .map(|text| HowToStep {
+                    kind: "HowToStep",
+                    text: text.clone(),
+                })
+                .collect(),
+            prep_time: features.prep_time.map(duration),
+            cook_time: features.cook_time.map(duration),
+            total_time: features.total_time.map(duration),
+            nutrition: if has_nutrition { Some(nutrition) } else { None },
+            author: author.map(|author| Person {
+                kind: "Person",
+                name: author.name.clone(),
+                url: author.profile_url.clone(),
+            }),
+        }
+    }
+}
+
+/// Minutes as an ISO 8601 duration, like `PT1H30M`
+pub fn duration(minutes: u32) -> String {
+    match (minutes / 60, minutes % 60) {
+        (0, minutes) => format!("PT{}M", minutes),
+        (hours, 0) => format!("PT{}H", hours),
+        (hours, minutes) => format!("PT{}H{}M", hours, minutes),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use serde_json::json;
+
+    use crate::model::Features;
+
+    #[test]
+    fn durations() {
+        assert_eq!("PT0M", duration(0));
+        assert_eq!("PT45M", duration(45));
+        assert_eq!("PT2H", duration(120));
+        assert_eq!("PT1H30M", duration(90));
+    }
+
+    #[test]
+    fn renders_recipes() {
+        let uuid = Uuid::from_u128(42);
+        let mut recipe = Recipe {
+            uuid,
+            crawl_url: "https://example.com/pao".to_owned(),
+            ingredients: vec!["500g polvilho".to_owned(), "2 ovos".to_owned()],
+            instructions: vec!["Mix".to_owned(), "Bake".to_owned()],
+            features: Features {
+                cook_time: Some(25),
+                total_time_estimate: Some(40),
+                calories: Some(120),
+                fat_content: Some(4.5),
+                ..Features::default()
+            },
+            author_id: Some(7),
+            ..Recipe::sample(1, "Pão de Queijo")
+        };
+        let author = Author {
+            uuid,
+            author_id: 7,
+            name: "Ana".to_owned(),
+            profile_url: None,
+            verified: true,
+        };
+
+        assert_eq!(
+            json!({
+                "@context": "https://schema.org",
+                "@type": "Recipe",
+                "identifier": uuid.to_string(),
+                "name": "Pão de Queijo",
+                "url": "https://example.com/pao",
+                "recipeIngredient": ["500g polvilho", "2 ovos"],
+                "recipeInstructions": [
+                    {"@type": "HowToStep", "text": "Mix"},
+                    {"@type": "HowToStep", "text": "Bake"},
+                ],
+                "cookTime": "PT25M",
+                "nutrition": {
+                    "@type": "NutritionInformation",
+                    "calories": "120 calories",
+                    "fatContent": "4.5 g",
+                },
+                "author": {"@type": "Person", "name": "Ana"},
+            }),
+            serde_json::to_value(RecipeJsonLd::new(&recipe, Some(&author))).unwrap()
+        );
+
+        recipe.features = Features::default();
+        let rendered = serde_json::to_value(RecipeJsonLd::new(&recipe, None)).unwrap();
+        assert_eq!(None, rendered.get("nutrition"));
+        assert_eq!(None, rendered.get("author"));
+    }
+}
@@ -1,3 +1,48 @@
+pub mod analysis;
+pub mod analytics;
+pub mod authors;
+pub mod backfill;
+pub mod blocking;
+pub mod cache;
+pub mod clock;
+pub mod collation;
 pub mod database;
+pub mod dedup;
+pub mod diversity;
+pub mod error;
+pub mod estimate;
+pub mod experiments;
+pub mod export;
+pub mod filters;
+pub mod freshness;
+pub mod generation;
+pub mod geo;
+pub mod histogram;
+pub mod idempotency;
 pub mod index;
+pub mod ingest;
+pub mod instant;
+pub mod jobs;
+pub mod jsonld;
+pub mod locale;
+pub mod metrics;
 pub mod model;
+pub mod nutrition;
+pub mod pantry;
+#[cfg(feature = "parallel")]
+pub mod parallel;
+pub mod popularity;
+pub mod projection;
+pub mod recency;
+pub mod replication;
+pub mod runtime;
+pub mod snapshot;
+pub mod spans;
+pub mod stats;
+pub mod store;
+pub mod summaries;
+pub mod synthetic;
+pub mod tenant;
+pub mod update;
+pub mod warmup;
+pub mod writer;
@@ -0,0 +1,272 @@
+use std::collections::BTreeMap;
+
+use serde::Serialize;
+
+/// The languages the labels of response values (features, diets,
+/// sorts, units) are translated to. See `Locale::label`
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Locale {
+    #[serde(rename = "en")]
+    English,
+    #[serde(rename = "pt")]
+    Portuguese,
+    #[serde(rename = "de")]
+    German,
+    #[serde(rename = "es")]
+    Spanish,
+}
+
+impl Default for Locale {
+    fn default() -> Self {
+        Locale::English
+    }
+}
+
+impl Locale {
+    pub const VALUES: [Self; 4] = [
+        Locale::English,
+        Locale::Portuguese,
+        Locale::German,
+        Locale::Spanish,
+    ];
+
+    /// The primary language subtag, as in `Accept-Language`
+    pub fn tag(self) -> &'static str {
+        match self {
+            Locale::English => "en",
+            Locale::Portuguese => "pt",
+            Locale::German => "de",
+            Locale::Spanish => "es",
+        }
+    }
+
+    /// Picks the supported locale the client prefers the most, given
+    /// an `Accept-Language` header like `"pt-BR,pt;q=0.9,en;q=0.5"`.
+    /// Regions are ignored. Falls back to the default locale
+    pub fn negotiate(accept_language: &str) -> Self {
+        let mut best: Option<(Self, f32)> = None;
+
+        for range in accept_language.split(',') {
+            let mut parts = range.split(';').map(str::trim);
+            let tag = parts.next().unwrap_or("");
+            let quality = parts
+                .filter_map(|param| param.strip_prefix("q="))
+                .next()
+                .map_or(Some(1.0), |q| q.parse::<f32>().ok())
+                .unwrap_or(0.0);
+
+            let primary = tag.split('-').next().unwrap_or("").to_lowercase();
+            let locale = match Self::VALUES.iter().find(|l| l.tag() == primary) {
+                Some(locale) => *locale,
+                None => continue,
+            };
+
+            // Earlier ranges win ties
+            if quality > 0.0 && best.map_or(true, |(_, q)| quality > q) {
+                best = Some((locale, quality));
+            }
+        }
+
+        best.map(|(locale, _)| locale).unwrap_or_default()
+    }
+
+    /// The label for an identifier in responses, like `diet_vegan` or
+    /// `prep_time`. Falls back to english, then to the identifier
+    pub fn label<'a>(self, identifier: &'a str) -> &'a str {
+        lookup(self.table(), identifier)
+            .or_else(|| lookup(ENGLISH, identifier))
+            .unwrap_or(identifier)
+    }
+
+    /// Every known identifier and its label
+    pub fn labels(self) -> BTreeMap<&'static str, &'static str> {
+        ENGLISH
+            .iter()
+            .map(|(identifier, _)| (*identifier, self.label(identifier)))
+            .collect()
+    }
+
+    fn table(self) -> &'static [(&'static str, &'static str)] {
+        match self {
+            Locale::English => ENGLISH,
+            Locale::Portuguese => PORTUGUESE,
+            Locale::German => GERMAN,
+            Locale::Spanish => SPANISH,
+        }
+    }
+}
+
+fn lookup(table: &'static [(&str, &'static str)], identifier: &str) -> Option<&'static str> {
+    table
+        .iter()
+        .find(|(key, _)| *key == identifier)
+        .map(|(_, label)| *label)
+}
+
+// Features (also the keys of aggregations and filters), presence
+// fields, sort orders and units. Every identifier is here
+const ENGLISH: &[(&str, &str)] = &[
+    ("num_ingredients", "Number of ingredients"),
+    ("instructions_length", "Instructions length"),
+    ("prep_time", "Preparation time"),
+    ("total_time", "Total time"),
+    ("cook_time", "Cooking time"),
+    ("total_time_estimate", "Estimated total time"),
+    ("calories", "Calories"),
+    ("fat_content", "Fat"),
+    ("carb_content", "Carbohydrates"),
+    ("protein_content", "Protein"),
+    ("diet_lowcarb", "Low carb"),
+    ("diet_vegetarian", "Vegetarian"),
+    ("diet_vegan", "Vegan"),
+    ("diet_keto", "Keto"),
+    ("diet_paleo", "Paleo"),
+    ("added_at", "Date added"),
+    ("image", "Photo"),
+    ("nutrition", "Nutrition facts"),
+    ("author", "Author"),
+    ("origin", "Region"),
+    ("relevance", "Relevance"),
+    ("name", "Name"),
+    ("random", "Random"),
+    ("ascending", "Ascending"),
+    ("descending", "Descending"),
+    ("minutes", "minutes"),
+    ("grams", "grams"),
+    ("kcal", "kcal"),
+];
+
+const PORTUGUESE: &[(&str, &str)] = &[
+    ("num_ingredients", "Número de ingredientes"),
+    ("instructions_length", "Tamanho do modo de preparo"),
+    ("prep_time", "Tempo de preparo"),
+    ("total_time", "Tempo total"),
+    ("cook_time", "Tempo de cozimento"),
+    ("total_time_estimate", "Tempo total estimado"),
+    ("calories", "Calorias"),
+    ("fat_content", "Gordura"),
+    ("carb_content", "Carboidratos"),
+    ("protein_content", "Proteína"),
+    ("diet_lowcarb", "Low carb"),
+    ("diet_vegetarian", "Vegetariana"),
+    ("diet_vegan", "Vegana"),
+    ("diet_keto", "Cetogênica"),
+    ("diet_paleo", "Paleolítica"),
+    ("added_at", "Data de inclusão"),
+    ("image", "Foto"),
+    ("nutrition", "Informação nutricional"),
+    ("author", "Autor"),
+    ("origin", "Região"),
+    ("relevance", "Relevância"),
+    ("name", "Nome"),
+    ("random", "Aleatória"),
+    ("ascending", "Crescente"),
+    ("descending", "Decrescente"),
+    ("minutes", "minutos"),
+    ("grams", "gramas"),
+    ("kcal", "kcal"),
+];
+
+const GERMAN: &[(&str, &str)] = &[
+    ("num_ingredients", "Anzahl der Zutaten"),
+    ("instructions_length", "Länge der Anleitung"),
+    ("prep_time", "Vorbereitungszeit"),
+    ("total_time", "Gesamtzeit"),
+    ("cook_time", "Kochzeit"),
+    ("total_time_estimate", "Geschätzte Gesamtzeit"),
+    ("calories", "Kalorien"),
+    ("fat_content", "Fett"),
+    ("carb_content", "Kohlenhydrate"),
+    ("protein_content", "Eiweiß"),
+    ("diet_lowcarb", "Low Carb"),
+    ("diet_vegetarian", "Vegetarisch"),
+    ("diet_vegan", "Vegan"),
+    ("diet_keto", "Ketogen"),
+    ("diet_paleo", "Paleo"),
+    ("added_at", "Hinzugefügt am"),
+    ("image", "Foto"),
+    ("nutrition", "Nährwerte"),
+    ("author", "Autor"),
+    ("origin", "Region"),
+    ("relevance", "Relevanz"),
+    ("name", "Name"),
+    ("random", "Zufällig"),
+    ("ascending", "Aufsteigend"),
+    ("descending", "Absteigend"),
+    ("minutes", "Minuten"),
+    ("grams", "Gramm"),
+    ("kcal", "kcal"),
+];
+
+const SPANISH: &[(&str, &str)] = &[
+    ("num_ingredients", "Número de ingredientes"),
+    ("instructions_length", "Longitud de las instrucciones"),
+    ("prep_time", "Tiempo de preparación"),
+    ("total_time", "Tiempo total"),
+    ("cook_time", "Tiempo de cocción"),
+    ("total_time_estimate", "Tiempo total estimado"),
+    ("calories", "Calorías"),
+    ("fat_content", "Grasas"),
+    ("carb_content", "Carbohidratos"),
+    ("protein_content", "Proteínas"),
+    ("diet_lowcarb", "Baja en carbohidratos"),
+    ("diet_vegetarian", "Vegetariana"),
+    ("diet_vegan", "Vegana"),
+    ("diet_keto", "Cetogénica"),
+    ("diet_paleo", "Paleolítica"),
+    ("added_at", "Fecha de alta"),
+    ("image", "Foto"),
+    ("nutrition", "Información nutricional"),
+    ("author", "Autor"),
+    ("origin", "Región"),
+    ("relevance", "Relevancia"),
+    ("name", "Nombre"),
+    ("random", "Aleatorio"),
+    ("ascending", "Ascendente"),
+    ("descending", "Descendente"),
+    ("minutes", "minutos"),
+    ("grams", "gramos"),
+    ("kcal", "kcal"),
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn negotiation() {
+        for (header, expected) in &[
+            ("pt-BR,pt;q=0.9,en;q=0.5", Locale::Portuguese),
+            ("en-US, de;q=0.8", Locale::English),
+            ("fr, de;q=0.8, es;q=0.9", Locale::Spanish),
+            ("DE-ch", Locale::German),
+            ("es;q=0, pt;q=0.1", Locale::Portuguese),
+            ("de;q=0.5, es;q=0.5", Locale::German),
+            ("es;q=bogus, de;q=0.1", Locale::German),
+            ("fr, *;q=0.5", Locale::English),
+            ("", Locale::English),
+        ] {
+            assert_eq!(*expected, Locale::negotiate(header), "{}", header);
+        }
+    }
+
+    #[test]
+    fn every_locale_labels_every_identifier() {
+        for &locale in Locale::VALUES.iter() {
+            let table = locale.table();
+            assert_eq!(ENGLISH.len(), table.len(), "{:?}", locale);
+            for (identifier, _) in ENGLISH {
+                assert!(
+                    lookup(table, identifier).is_some(),
+                    "{:?} lacks {}",
+                    locale,
+                    identifier
+                );
+            }
+        }
+
+        assert_eq!("Vegana", Locale::Portuguese.label("diet_vegan"));
+        assert_eq!("not_a_feature", Locale::German.label("not_a_feature"));
+        assert_eq!(ENGLISH.len(), Locale::Spanish.labels().len());
+    }
+}
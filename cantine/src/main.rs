@@ -1,53 +1,322 @@
-use std::{convert::TryFrom, env, io, path::Path, str::FromStr, sync::Arc};
+use std::{
+    borrow::Cow,
+    collections::{BTreeMap, HashMap},
+    convert::TryFrom,
+    env,
+    fs::{self, File},
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, RwLock,
+    },
+    thread,
+    time::{Duration, Instant},
+};
 
 use env_logger;
 use serde::Serialize;
-use tique::QueryParser;
+use tique::{
+    budget::Budget, expression::ScoreExpression, Ast, MinimumShouldMatch, QueryParser, SynonymMap,
+};
 use uuid::Uuid;
 
+use actix_rt::Arbiter;
 use actix_web::{
-    http::StatusCode, middleware::Logger, web, App, HttpResponse, HttpServer, Result as ActixResult,
+    error::{
+        ErrorBadRequest, ErrorForbidden, ErrorInternalServerError, ErrorServiceUnavailable,
+        ErrorUnauthorized,
+    },
+    http::{header, StatusCode},
+    middleware::Logger,
+    web, App, HttpRequest, HttpResponse, HttpServer, Result as ActixResult,
 };
 
 use tantivy::{
-    query::{AllQuery, BooleanQuery, Occur, Query},
-    Index, IndexReader, Result,
+    collector::Count,
+    query::{AllQuery, BooleanQuery, EmptyQuery, Occur, Query, RangeQuery, TermQuery},
+    schema::{FieldType, IndexRecordOption},
+    tokenizer::TextAnalyzer,
+    Index, IndexReader, Result, Searcher, Term,
 };
 
 use cantine::{
-    database::DatabaseReader,
-    index::{After, RecipeIndex},
+    analysis::Analysis,
+    analytics::{QueryLog, QueryRecord},
+    authors,
+    cache::SearchCache,
+    clock::{Clock, FixedClock, SystemClock},
+    collation::Collation,
+    database::{DatabaseReader, DatabaseStats},
+    diversity::{DiversityCounter, DiversityMetrics},
+    error::{self, Error},
+    experiments::Experiment,
+    freshness::{Freshness, FreshnessTracker},
+    generation::Visibility,
+    geo::{GeoDistanceQuery, GeoPoint},
+    histogram::Bucket,
+    index::{After, RecipeIndex, SearchBudget, SkippedSegments, TimingsRecorder},
+    instant,
+    jobs::{Jobs, Progress},
+    jsonld::RecipeJsonLd,
+    locale::Locale,
     model::{
-        FeaturesAggregationQuery, FeaturesAggregationResult, Recipe, RecipeCard, RecipeId,
-        RecipeInfo, SearchCursor, SearchQuery, SearchResult, Sort,
+        AggregationScope, Author, AuthorCard, CountResult, DiversitySummary, ExistsResult,
+        FeaturesAggregationQuery, FeaturesAggregationResult, FeaturesPercentiles, FieldBoosts,
+        GenerationStatus, GroupedQuery, GroupedResult, InstantItem, InstantQuery, InstantResult,
+        MatchedClauses, ParseMode, PercentileSummary, QueryError, QueryIssue, Recipe, RecipeCard,
+        RecipeExplanation, RecipeGroup, RecipeId, RecipeInfo, RecipeSummary, SearchCursor,
+        SearchOptions, SearchQuery, SearchResult, SimilarQuery, SimilarResult, Sort, TotalCount,
     },
+    pantry::PantryQuery,
+    popularity::PopularityStore,
+    projection::{ApiKeys, CardField, FieldRoles, Projection},
+    runtime::RuntimeFilterQuery,
+    stats::{GlobalStats, IndexStats},
+    summaries,
+    warmup::WarmupOptions,
 };
 
 type RecipeDatabase = Arc<DatabaseReader<Recipe>>;
+type AuthorDatabase = Arc<DatabaseReader<Author>>;
+type SummaryDatabase = Arc<DatabaseReader<RecipeSummary>>;
 
 pub async fn recipe(
+    req: HttpRequest,
     database: web::Data<RecipeDatabase>,
+    state: web::Data<Arc<SearchState>>,
     uuid: web::Path<Uuid>,
 ) -> ActixResult<HttpResponse> {
-    if let Some(recipe) = database
-        .find_by_uuid(&uuid)
-        .transpose()
-        .expect("db operational")
-    {
+    let projection = projection(&req, &state, None)?;
+
+    if let Some(recipe) = find_recipe(&database, &uuid, &projection)? {
         Ok(HttpResponse::Ok().json(RecipeInfo::from(recipe)))
     } else {
         Ok(HttpResponse::new(StatusCode::NOT_FOUND))
     }
 }
 
+/// The recipe as schema.org json-ld, for pages and feeds elsewhere
+pub async fn recipe_jsonld(
+    req: HttpRequest,
+    database: web::Data<RecipeDatabase>,
+    state: web::Data<Arc<SearchState>>,
+    uuid: web::Path<Uuid>,
+) -> ActixResult<HttpResponse> {
+    let projection = projection(&req, &state, None)?;
+
+    let recipe = match find_recipe(&database, &uuid, &projection)? {
+        Some(recipe) => recipe,
+        None => return Ok(HttpResponse::new(StatusCode::NOT_FOUND)),
+    };
+
+    let author = match (&state.authors, recipe.author_id) {
+        (Some(authors), Some(author_id)) => authors
+            .find_by_id(author_id)
+            .transpose()
+            .map_err(ErrorInternalServerError)?,
+        _ => None,
+    };
+
+    Ok(HttpResponse::Ok()
+        .content_type("application/ld+json")
+        .json(RecipeJsonLd::new(&recipe, author.as_ref())))
+}
+
+/// Recipes like the given one, by the words that describe it best
+pub async fn similar(
+    req: HttpRequest,
+    uuid: web::Path<Uuid>,
+    query: web::Query<SimilarQuery>,
+    state: web::Data<Arc<SearchState>>,
+    database: web::Data<RecipeDatabase>,
+) -> ActixResult<HttpResponse> {
+    let projection = projection(&req, &state, None)?;
+
+    let recipe_id = match database.id_for_uuid(&uuid) {
+        Some(&recipe_id) => recipe_id,
+        None => return Ok(HttpResponse::new(StatusCode::NOT_FOUND)),
+    };
+
+    let limit = query.num_items.unwrap_or(10) as usize;
+    let summaries = state.summaries.clone();
+    let found =
+        web::block(move || -> Result<Option<Vec<RecipeId>>> { state.similar(recipe_id, limit) })
+            .await?;
+
+    let recipe_ids = match found {
+        Some(recipe_ids) => recipe_ids,
+        None => return Ok(HttpResponse::new(StatusCode::NOT_FOUND)),
+    };
+
+    let items = cards(&database, summaries.as_deref(), &recipe_ids, &projection)?;
+
+    Ok(HttpResponse::Ok().json(SimilarResult { items }))
+}
+
+/// What the request may see of the recipes: whatever the role of its
+/// api key (sent as `Authorization: Bearer <key>`) may see, narrowed
+/// down to `requested`. Requests without a key get the default role
+fn projection(
+    req: &HttpRequest,
+    state: &SearchState,
+    requested: Option<&[CardField]>,
+) -> ActixResult<Projection> {
+    let role = match req.headers().get(header::AUTHORIZATION) {
+        Some(value) => {
+            let role = value
+                .to_str()
+                .ok()
+                .and_then(|value| value.strip_prefix(BEARER))
+                .and_then(|key| state.api_keys.role(key.trim()));
+            Some(role.ok_or_else(|| ErrorUnauthorized("unknown api key"))?)
+        }
+        None => None,
+    };
+
+    let allowed = state
+        .field_roles
+        .projection(role)
+        .ok_or_else(|| ErrorForbidden("unknown role"))?;
+
+    Ok(match requested {
+        Some(fields) => allowed.narrowed(fields),
+        None => allowed,
+    })
+}
+
+/// The cards of the recipes, in the same order, showing what
+/// `projection` allows. Recipes missing from the database are skipped
+fn cards(
+    database: &RecipeDatabase,
+    summaries: Option<&DatabaseReader<RecipeSummary>>,
+    recipe_ids: &[RecipeId],
+    projection: &Projection,
+) -> ActixResult<Vec<RecipeCard>> {
+    Ok(summaries::find_each(summaries, database, recipe_ids)
+        .map_err(ErrorInternalServerError)?
+        .into_iter()
+        .flatten()
+        .map(|summary| {
+            let mut card = RecipeCard::from(summary);
+            projection.apply(&mut card);
+            card
+        })
+        .collect())
+}
+
+/// The recipe with the given uuid, showing what `projection` allows
+fn find_recipe(
+    database: &RecipeDatabase,
+    uuid: &Uuid,
+    projection: &Projection,
+) -> ActixResult<Option<Recipe>> {
+    let recipe_id = match database.id_for_uuid(uuid) {
+        Some(&recipe_id) => recipe_id,
+        None => return Ok(None),
+    };
+
+    Ok(hydrate(database, &[recipe_id], projection)
+        .map_err(ErrorInternalServerError)?
+        .pop())
+}
+
+/// The full recipes, in the same order, stripped of what `projection`
+/// doesn't allow. Every response that shows more than the cards do
+/// reads recipes through here. Recipes missing from the database are
+/// skipped
+fn hydrate(
+    database: &DatabaseReader<Recipe>,
+    recipe_ids: &[RecipeId],
+    projection: &Projection,
+) -> error::Result<Vec<Recipe>> {
+    Ok(database
+        .find_each(recipe_ids)?
+        .into_iter()
+        .flatten()
+        .map(|mut recipe| {
+            projection.apply_to_recipe(&mut recipe);
+            recipe
+        })
+        .collect())
+}
+
 #[derive(Serialize, Clone)]
 pub struct IndexInfo {
     pub total_recipes: u64,
     pub features: FeaturesAggregationResult,
     pub sort: Vec<Sort>,
+    pub stats: Arc<GlobalStats>,
+}
+
+/// What the index has, as of the latest reload
+pub async fn index_info(state: web::Data<Arc<SearchState>>) -> ActixResult<HttpResponse> {
+    let info = web::block(move || state.index_info()).await?;
+    Ok(HttpResponse::Ok().json(info.as_ref()))
+}
+
+/// How to show our identifiers (features, diets, sorts, units) to
+/// people, in the language the client prefers
+#[derive(Serialize)]
+pub struct Labels {
+    pub locale: Locale,
+    pub labels: BTreeMap<&'static str, &'static str>,
+}
+
+pub async fn labels(req: HttpRequest) -> ActixResult<HttpResponse> {
+    let locale = req
+        .headers()
+        .get(header::ACCEPT_LANGUAGE)
+        .and_then(|value| value.to_str().ok())
+        .map(Locale::negotiate)
+        .unwrap_or_default();
+
+    Ok(HttpResponse::Ok()
+        .header(header::CONTENT_LANGUAGE, locale.tag())
+        .header(header::VARY, "Accept-Language")
+        .json(Labels {
+            locale,
+            labels: locale.labels(),
+        }))
+}
+
+/// What the index is made of and how `fulltext` gets at it
+#[derive(Serialize, Clone)]
+pub struct SchemaInfo {
+    pub fields: Vec<FieldInfo>,
+    pub fulltext: Vec<FulltextField>,
+}
+
+#[derive(Serialize, Clone)]
+pub struct FieldInfo {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub kind: &'static str,
+    pub indexed: bool,
+    pub stored: bool,
+    pub fast: bool,
+    /// For text fields: the analyzer and whether phrases work
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tokenizer: Option<String>,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub positions: bool,
 }
 
-pub async fn index_info(info: web::Data<IndexInfo>) -> ActixResult<HttpResponse> {
+/// A field `fulltext` searches
+#[derive(Serialize, Clone)]
+pub struct FulltextField {
+    pub field: String,
+    /// How queries refer to the field, like in `name:garlic`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub alias: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub boost: Option<f32>,
+    /// Whether words without a field name are looked up in it
+    pub default: bool,
+}
+
+pub async fn schema_info(info: web::Data<SchemaInfo>) -> ActixResult<HttpResponse> {
     Ok(HttpResponse::Ok().json(info.get_ref()))
 }
 
@@ -62,10 +331,47 @@ fn cursor_to_after(database: &RecipeDatabase, cursor: &SearchCursor) -> Option<A
 }
 
 pub async fn search(
-    query: web::Json<SearchQuery>,
+    req: HttpRequest,
+    mut query: web::Json<SearchQuery>,
     state: web::Data<Arc<SearchState>>,
     database: web::Data<RecipeDatabase>,
+    diversity_metrics: web::Data<Arc<DiversityMetrics>>,
 ) -> ActixResult<HttpResponse> {
+    let received = Instant::now();
+    let projection = projection(&req, &state, query.fields.as_deref())?;
+
+    // Only the searches of someone known take part in the experiment
+    let ranking_profile = match (&state.experiment, req.headers().get(USER_TOKEN_HEADER)) {
+        (Some(experiment), Some(token)) => {
+            let token = token
+                .to_str()
+                .map_err(|_| ErrorBadRequest("invalid user token"))?;
+            experiment
+                .enroll(token, &mut query)
+                .map(|arm| arm.name.clone())
+        }
+        _ => None,
+    };
+
+    // Neither collapsed nor reranked results can be paginated
+    if (query.collapse_variants || query.boost_popular) && query.after.is_some() {
+        return Ok(HttpResponse::new(StatusCode::BAD_REQUEST));
+    }
+
+    let valid_location = query
+        .near
+        .map_or(true, |near| near.center.is_valid() && near.radius_km >= 0.0)
+        && query.distance_sort.map_or(true, GeoPoint::is_valid);
+    if !valid_location || !query.recency.map_or(true, |boost| boost.is_valid()) {
+        return Ok(HttpResponse::new(StatusCode::BAD_REQUEST));
+    }
+
+    let warnings = state.fulltext_issues(&query);
+    let fingerprint = state.fulltext_fingerprint(&query);
+    if query.parse_mode == ParseMode::Strict && !warnings.is_empty() {
+        return Ok(HttpResponse::BadRequest().json(QueryError { issues: warnings }));
+    }
+
     let after = if let Some(cursor) = &query.after {
         let checked_after = cursor_to_after(&database, &cursor);
         if checked_after.is_none() {
@@ -76,17 +382,139 @@ pub async fn search(
         None
     };
 
-    let (total_found, recipe_ids, after, agg) =
-        web::block(move || -> Result<ExecuteResult> { state.search(query.0, after) }).await?;
+    let started = Instant::now();
+    let score = match &query.score {
+        Some(input) => match state.score_expression(input) {
+            Ok(expression) => Some(expression),
+            Err(Error::QueryParse(_)) => return Ok(HttpResponse::new(StatusCode::BAD_REQUEST)),
+            Err(err) => return Err(ErrorInternalServerError(err)),
+        },
+        None => None,
+    };
+    let score_parse = started.elapsed();
+
+    let authors = state.authors.clone();
+    let summaries = state.summaries.clone();
+    let debug = query.profile;
+
+    let query_log = state.query_log.clone();
+    let logged = query_log.as_ref().map(|_| QueryRecord {
+        fulltext: state.normalized_fulltext(&query).map(|ast| ast.to_string()),
+        filter: query
+            .filter
+            .as_ref()
+            .and_then(|filter| serde_json::to_string(filter).ok()),
+        after: query
+            .after
+            .as_ref()
+            .and_then(|cursor| serde_json::to_string(cursor).ok()),
+        ranking_profile: ranking_profile.clone(),
+        ..QueryRecord::default()
+    });
+
+    // Parents the spans of every phase, wherever they run
+    let request_span = cantine::span!("search_request", fulltext = ?fingerprint);
+    let search_span = request_span.clone();
+
+    let ExecuteResult {
+        total,
+        recipe_ids,
+        after,
+        agg,
+        percentiles,
+        histogram,
+        buckets,
+        global_agg,
+        runtime_percentiles,
+        mut profile,
+        matched_ids,
+        partial,
+        annotations,
+    } = web::block(move || -> Result<ExecuteResult> {
+        search_span.in_scope(|| state.search(query.0, after, score))
+    })
+    .await?;
 
+    let started = Instant::now();
     let num_results = recipe_ids.len();
+    let hydrate_span = request_span.in_scope(|| cantine::span!("hydrate", items = num_results));
+    let hydrating = hydrate_span.enter();
     let mut items = Vec::with_capacity(num_results);
-    for recipe_id in recipe_ids {
-        let recipe: Recipe = database
-            .find_by_id(recipe_id)
-            .expect("item in the index always present in the db")?;
-        items.push(RecipeCard::from(recipe));
+    let mut author_ids = Vec::with_capacity(num_results);
+    let mut diversity = DiversityCounter::default();
+    let matched_ids = matched_ids.unwrap_or_else(|| recipe_ids.clone());
+    // Only what the cards show is read from the database
+    let summaries = summaries.as_deref();
+    let found = summaries::find_each(summaries, &database, &recipe_ids)
+        .map_err(ErrorInternalServerError)?;
+    for (summary, matched_id) in found.into_iter().zip(matched_ids) {
+        // Collapsed variants show up as their base recipe, unless
+        // it's not in the database
+        let summary = match summary {
+            Some(summary) => summary,
+            None => summaries::find_by_id(summaries, &database, matched_id)
+                .map_err(ErrorInternalServerError)?
+                .expect("item in the index always present in the db"),
+        };
+
+        let matched_variant = if matched_id != summary.recipe_id {
+            summaries::find_by_id(summaries, &database, matched_id)
+                .map_err(ErrorInternalServerError)?
+                .map(|variant| variant.uuid)
+        } else {
+            None
+        };
+
+        diversity.add_summary(&summary);
+        author_ids.push(summary.author_id);
+        items.push(RecipeCard {
+            matched_variant,
+            ..RecipeCard::from(summary)
+        });
+    }
+
+    if let Some(annotations) = annotations {
+        for (card, matched) in items.iter_mut().zip(annotations) {
+            card.matched = Some(matched);
+        }
+    }
+
+    // A single lookup for the authors of every hit
+    if let Some(authors) = authors.filter(|_| projection.allows(CardField::Author)) {
+        let found = authors
+            .find_many(author_ids.iter().flatten().copied())
+            .map_err(ErrorInternalServerError)?;
+        for (card, author_id) in items.iter_mut().zip(author_ids) {
+            card.author = author_id
+                .and_then(|id| found.get(&id))
+                .cloned()
+                .map(AuthorCard::from);
+        }
+    }
+
+    for card in items.iter_mut() {
+        projection.apply(card);
     }
+    drop(hydrating);
+
+    if let (Some(query_log), Some(logged)) = (query_log, logged) {
+        let record = QueryRecord {
+            num_hits: total.value as u64,
+            latency_micros: micros(received.elapsed()),
+            ..logged
+        };
+        if let Err(err) = query_log.record(record) {
+            log::warn!("Failed to log a search: {}", err);
+        }
+    }
+
+    if let Some(profile) = profile.as_mut() {
+        profile.parse_micros += micros(score_parse);
+        profile.hydration_micros = micros(started.elapsed());
+    }
+
+    let diversity = diversity.finish();
+    diversity_metrics.record(&diversity);
 
     let next = after.map(|after| {
         let last_uuid = &items[num_results - 1].uuid;
@@ -99,81 +527,1143 @@ pub async fn search(
     });
 
     Ok(HttpResponse::Ok().json(SearchResult {
-        total_found,
+        total_found: total.value,
+        total,
         items,
         next,
         agg,
+        percentiles,
+        histogram,
+        buckets,
+        global_agg,
+        runtime_percentiles,
+        profile,
+        partial,
+        diversity: if debug { Some(diversity) } else { None },
+        warnings,
+        fingerprint,
+        ranking_profile,
+    }))
+}
+
+/// Search-as-you-type: recipes whose name starts like what was typed
+/// so far (or nearly, typos included), ranked up by what was searched
+/// lately. Runs on its own threads and skips everything that's not
+/// needed for suggesting recipes, so it stays fast regardless of
+/// what `/search` is up to
+pub async fn instant(
+    req: HttpRequest,
+    query: web::Query<InstantQuery>,
+    state: web::Data<Arc<SearchState>>,
+    database: web::Data<RecipeDatabase>,
+    lane: web::Data<InstantLane>,
+) -> ActixResult<HttpResponse> {
+    let projection = projection(&req, &state, None)?;
+    let InstantQuery { q, num_items } = query.into_inner();
+    let limit = num_items.map_or(instant::MAX_ITEMS, usize::from);
+
+    let state = state.get_ref().clone();
+    let recipe_ids = lane
+        .run(move || state.instant(&q, limit))
+        .await?
+        .map_err(ErrorInternalServerError)?;
+
+    let items = hydrate(&database, &recipe_ids, &projection)
+        .map_err(ErrorInternalServerError)?
+        .into_iter()
+        .map(InstantItem::from)
+        .collect();
+
+    Ok(HttpResponse::Ok().json(InstantResult { items }))
+}
+
+/// Threads dedicated to instant searches, so that they never queue
+/// behind the (much slower) regular searches in actix' blocking pool
+#[derive(Clone)]
+pub struct InstantLane {
+    arbiters: Arc<Vec<Arbiter>>,
+    next: Arc<AtomicUsize>,
+}
+
+impl InstantLane {
+    fn new(num_threads: usize) -> Self {
+        Self {
+            arbiters: Arc::new((0..num_threads.max(1)).map(|_| Arbiter::new()).collect()),
+            next: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+
+    async fn run<F, R>(&self, task: F) -> ActixResult<R>
+    where
+        F: FnOnce() -> R + Send + 'static,
+        R: Send + 'static,
+    {
+        let turn = self.next.fetch_add(1, Ordering::Relaxed) % self.arbiters.len();
+        self.arbiters[turn]
+            .exec(task)
+            .await
+            .map_err(|_| ErrorServiceUnavailable("Instant search unavailable"))
+    }
+}
+
+/// Starts exporting every recipe a search matches, in the background.
+/// Yields the job to follow via `/jobs/{id}`
+pub async fn export(
+    req: HttpRequest,
+    query: web::Json<SearchQuery>,
+    state: web::Data<Arc<SearchState>>,
+    database: web::Data<RecipeDatabase>,
+    jobs: web::Data<Arc<Jobs>>,
+) -> ActixResult<HttpResponse> {
+    let projection = projection(&req, &state, query.fields.as_deref())?;
+    let query = query.into_inner();
+    let state = state.get_ref().clone();
+    let database = database.get_ref().clone();
+
+    let started = jobs
+        .start(move |progress| state.export(query, &database, &projection, progress))
+        .map_err(ErrorInternalServerError)?;
+
+    if let Some(id) = started {
+        Ok(HttpResponse::Accepted().json(jobs.status(id).expect("job just started")))
+    } else {
+        Ok(HttpResponse::new(StatusCode::TOO_MANY_REQUESTS))
+    }
+}
+
+pub async fn job(id: web::Path<u64>, jobs: web::Data<Arc<Jobs>>) -> ActixResult<HttpResponse> {
+    if let Some(status) = jobs.status(*id) {
+        Ok(HttpResponse::Ok().json(status))
+    } else {
+        Ok(HttpResponse::new(StatusCode::NOT_FOUND))
+    }
+}
+
+/// Running figures about the searches served so far
+pub async fn metrics(
+    diversity_metrics: web::Data<Arc<DiversityMetrics>>,
+    state: web::Data<Arc<SearchState>>,
+) -> ActixResult<HttpResponse> {
+    let freshness = web::block(move || -> Result<Option<Freshness>> { state.freshness() }).await?;
+
+    Ok(HttpResponse::Ok().json(SearchMetrics {
+        diversity: diversity_metrics.summary(),
+        freshness,
     }))
 }
 
-type ExecuteResult = (
-    usize,
-    Vec<RecipeId>,
-    Option<After>,
-    Option<FeaturesAggregationResult>,
-);
+/// Every metric in `cantine::metrics::registry`, in Prometheus' text
+/// format
+#[cfg(feature = "metrics")]
+pub async fn prometheus_metrics() -> ActixResult<HttpResponse> {
+    use prometheus::{Encoder, TextEncoder};
+
+    let encoder = TextEncoder::new();
+    let mut body = Vec::new();
+    encoder
+        .encode(&cantine::metrics::registry().gather(), &mut body)
+        .map_err(ErrorInternalServerError)?;
+
+    Ok(HttpResponse::Ok()
+        .content_type(encoder.format_type())
+        .body(body))
+}
+
+#[cfg(feature = "metrics")]
+fn prometheus_routes(config: &mut web::ServiceConfig) {
+    config.service(web::resource("/metrics/prometheus").route(web::get().to(prometheus_metrics)));
+}
+
+#[cfg(not(feature = "metrics"))]
+fn prometheus_routes(_config: &mut web::ServiceConfig) {}
+
+#[derive(Serialize)]
+pub struct SearchMetrics {
+    diversity: DiversitySummary,
+    /// None when the index commits don't say how far the database went
+    freshness: Option<Freshness>,
+}
+
+/// How far searches are behind the database, for alerting when the
+/// indexer falls behind. Not found for untagged index commits
+pub async fn freshness(state: web::Data<Arc<SearchState>>) -> ActixResult<HttpResponse> {
+    let freshness = web::block(move || -> Result<Option<Freshness>> { state.freshness() }).await?;
+
+    Ok(match freshness {
+        Some(freshness) => HttpResponse::Ok().json(freshness),
+        None => HttpResponse::new(StatusCode::NOT_FOUND),
+    })
+}
+
+/// How large the index and the database are and how much of them is
+/// taken by deleted or replaced recipes
+pub async fn stats(
+    state: web::Data<Arc<SearchState>>,
+    database: web::Data<RecipeDatabase>,
+) -> ActixResult<HttpResponse> {
+    let stats = web::block(move || -> Result<StorageStats> {
+        Ok(StorageStats {
+            index: state.index_stats()?,
+            database: database.stats()?,
+        })
+    })
+    .await?;
+
+    Ok(HttpResponse::Ok().json(stats))
+}
+
+#[derive(Serialize)]
+pub struct StorageStats {
+    index: IndexStats,
+    database: DatabaseStats,
+}
+
+/// Whether the commit `generation` (as yielded when ingesting) is
+/// searchable yet, so that pipelines can wait until it is
+pub async fn generation(
+    generation: web::Path<u64>,
+    state: web::Data<Arc<SearchState>>,
+) -> ActixResult<HttpResponse> {
+    let generation = *generation;
+    let status =
+        web::block(move || -> Result<GenerationStatus> { state.generation_status(generation) })
+            .await?;
+
+    Ok(HttpResponse::Ok().json(status))
+}
+
+/// How many recipes a search finds, without ranking or fetching any
+pub async fn count(
+    query: web::Json<SearchQuery>,
+    state: web::Data<Arc<SearchState>>,
+) -> ActixResult<HttpResponse> {
+    let total = web::block(move || -> Result<usize> { state.count(&query.0) }).await?;
+    Ok(HttpResponse::Ok().json(CountResult { total }))
+}
+
+/// Whether a search finds anything, stopping at the first recipe
+pub async fn exists(
+    query: web::Json<SearchQuery>,
+    state: web::Data<Arc<SearchState>>,
+) -> ActixResult<HttpResponse> {
+    let exists = web::block(move || -> Result<bool> { state.exists(&query.0) }).await?;
+    Ok(HttpResponse::Ok().json(ExistsResult { exists }))
+}
+
+/// The best recipes of each group (say: the top 3 of each of the
+/// authors with the most matches) in a single search
+pub async fn grouped(
+    req: HttpRequest,
+    query: web::Json<GroupedQuery>,
+    state: web::Data<Arc<SearchState>>,
+    database: web::Data<RecipeDatabase>,
+) -> ActixResult<HttpResponse> {
+    let projection = projection(&req, &state, query.search.fields.as_deref())?;
+
+    if query.num_groups == Some(0) {
+        return Ok(HttpResponse::new(StatusCode::BAD_REQUEST));
+    }
+
+    let authors = state.authors.clone();
+    let summaries = state.summaries.clone();
+    let found =
+        web::block(move || -> Result<Vec<(u64, usize, Vec<RecipeId>)>> { state.grouped(&query.0) })
+            .await?;
+
+    let found_authors = match &authors {
+        Some(authors) => authors
+            .find_many(found.iter().map(|(key, _total, _ids)| *key))
+            .map_err(ErrorInternalServerError)?,
+        None => HashMap::new(),
+    };
+
+    let mut groups = Vec::with_capacity(found.len());
+    for (author_id, total, recipe_ids) in found {
+        let items = cards(&database, summaries.as_deref(), &recipe_ids, &projection)?;
+
+        groups.push(RecipeGroup {
+            author: found_authors.get(&author_id).cloned().map(AuthorCard::from),
+            total,
+            items,
+        });
+    }
+
+    Ok(HttpResponse::Ok().json(GroupedResult { groups }))
+}
+
+/// Debugging aid: how the given recipe fares against a search
+pub async fn explain(
+    uuid: web::Path<Uuid>,
+    query: web::Json<SearchQuery>,
+    state: web::Data<Arc<SearchState>>,
+    database: web::Data<RecipeDatabase>,
+) -> ActixResult<HttpResponse> {
+    let recipe_id = match database.id_for_uuid(&uuid) {
+        Some(&recipe_id) => recipe_id,
+        None => return Ok(HttpResponse::new(StatusCode::NOT_FOUND)),
+    };
+
+    let explanation = web::block(move || -> Result<Option<RecipeExplanation>> {
+        state.explain(query.0, recipe_id)
+    })
+    .await?;
+
+    if let Some(explanation) = explanation {
+        Ok(HttpResponse::Ok().json(explanation))
+    } else {
+        Ok(HttpResponse::new(StatusCode::NOT_FOUND))
+    }
+}
+
+pub struct ExecuteResult {
+    total: TotalCount,
+    recipe_ids: Vec<RecipeId>,
+    after: Option<After>,
+    agg: Option<FeaturesAggregationResult>,
+    percentiles: Option<FeaturesPercentiles>,
+    histogram: Option<Vec<Bucket>>,
+    buckets: Option<BTreeMap<String, u64>>,
+    global_agg: Option<FeaturesAggregationResult>,
+    runtime_percentiles: Option<Vec<Option<PercentileSummary>>>,
+    profile: Option<SearchProfile>,
+    /// With `collapse_variants`, the ids of the recipes that actually
+    /// matched, paired with `recipe_ids`
+    matched_ids: Option<Vec<RecipeId>>,
+    /// Whether some index segments had to be skipped
+    partial: bool,
+    /// With `annotate`, what each recipe matched, paired with
+    /// `recipe_ids`
+    annotations: Option<Vec<MatchedClauses>>,
+}
+
+fn search_budget(options: SearchOptions) -> Budget {
+    let mut budget = Budget::default();
+    if let Some(timeout) = options.timeout {
+        budget = budget.with_timeout(Duration::from_millis(timeout));
+    }
+    if let Some(max_docs) = options.max_docs_visited {
+        budget = budget.with_max_docs_visited(max_docs);
+    }
+    budget
+}
+
+fn micros(duration: Duration) -> u64 {
+    duration.as_micros() as u64
+}
+
+fn exact_total(
+    (total, recipe_ids, after): (usize, Vec<RecipeId>, Option<After>),
+) -> (TotalCount, Vec<RecipeId>, Option<After>) {
+    (TotalCount::exact(total), recipe_ids, after)
+}
 
 pub struct SearchState {
     reader: IndexReader,
+    visibility: Visibility,
+    freshness: FreshnessTracker,
     recipe_index: RecipeIndex,
     query_parser: QueryParser,
+    /// How pantry items are tokenized, same as the ingredients
+    ingredients_tokenizer: TextAnalyzer,
     agg_threshold: usize,
+    clock: Box<dyn Clock>,
+    stats: RwLock<Option<Arc<GlobalStats>>>,
+    /// What `/info` replies with, for the searcher its stats are from
+    info: RwLock<Option<Arc<IndexInfo>>>,
+    authors: Option<AuthorDatabase>,
+    /// Results of recent `sort`-based searches. Profiled searches
+    /// always skip it
+    cache: Option<SearchCache>,
+    /// Whether to serve what the healthy segments have when some fail
+    skip_failed_segments: bool,
+    /// Results of recent instant searches, apart from the rest so
+    /// that keystrokes don't evict regular searches
+    instant_cache: SearchCache,
+    /// Where exports are written to
+    exports_dir: PathBuf,
+    /// Where the index is, for `index_stats`
+    index_path: PathBuf,
+    /// Consulted by searches that `boost_popular`
+    popularity: PopularityStore,
+    /// What each role may see of the recipes
+    field_roles: FieldRoles,
+    /// Which role each api key stands for
+    api_keys: ApiKeys,
+    /// Read instead of the full recipes when hydrating results
+    summaries: Option<SummaryDatabase>,
+    /// Where searches are logged, if at all
+    query_log: Option<Arc<QueryLog>>,
+    /// Splits searches across ranking profiles
+    experiment: Option<Experiment>,
 }
 
 impl SearchState {
-    pub fn search(&self, query: SearchQuery, after: Option<After>) -> Result<ExecuteResult> {
+    /// Writes every recipe matching the query (regardless of its
+    /// `num_items` and `after`), as json lines, to a file in the
+    /// exports directory named after the job, showing what
+    /// `projection` allows. The file only shows up once complete.
+    /// Recipes come in the requested order, if any, or as they are
+    /// in the index (the faster way)
+    pub fn export(
+        &self,
+        query: SearchQuery,
+        database: &RecipeDatabase,
+        projection: &Projection,
+        progress: &Progress,
+    ) -> Result<PathBuf> {
+        let searcher = self.reader.searcher();
+        let interpreted_query = self.interpret_query(&query)?;
+        progress.set_total(searcher.search(&interpreted_query, &Count)?);
+
+        let path = self
+            .exports_dir
+            .join(format!("export-{}.jsonlines", progress.job_id()));
+        let staged = path.with_extension("partial");
+        let mut writer = BufWriter::new(File::create(&staged)?);
+
+        let mut write_batch = |recipe_ids: &[RecipeId]| -> Result<()> {
+            for recipe in hydrate(database, recipe_ids, projection)? {
+                serde_json::to_writer(&mut writer, &recipe)
+                    .map_err(|err| io::Error::new(io::ErrorKind::Other, err))?;
+                writer.write_all(b"\n")?;
+            }
+            progress.advance(recipe_ids.len());
+            Ok(())
+        };
+
+        if let Some(sort) = query.sort {
+            let mut after = None;
+            loop {
+                let (_total, recipe_ids, next) = self.recipe_index.search(
+                    &searcher,
+                    &interpreted_query,
+                    EXPORT_BATCH_SIZE,
+                    sort.clone(),
+                    after,
+                )?;
+                write_batch(&recipe_ids)?;
+
+                if next.is_none() {
+                    break;
+                }
+                after = next;
+            }
+        } else {
+            for recipe_ids in
+                self.recipe_index
+                    .scan(&searcher, &interpreted_query, EXPORT_BATCH_SIZE)?
+            {
+                write_batch(&recipe_ids?)?;
+            }
+        }
+
+        writer.flush()?;
+        writer.get_ref().sync_all()?;
+        fs::rename(&staged, &path)?;
+
+        Ok(path)
+    }
+
+    pub fn instant(&self, input: &str, limit: usize) -> Result<Vec<RecipeId>> {
+        let searcher = self.reader.searcher();
+        let limit = limit.min(instant::MAX_ITEMS);
+
+        // What was searched lately hints at what's being typed
+        let recent = self.query_log.as_ref().map_or_else(Vec::new, |query_log| {
+            query_log
+                .recent_queries()
+                .completions(input, instant::MAX_COMPLETIONS)
+        });
+
+        let query = match self.recipe_index.instant_query(input, &recent) {
+            Some(query) => query,
+            None => return Ok(Vec::new()),
+        };
+
+        let key = SearchCache::key(&query, limit, &Sort::Relevance, &None);
+        let (_total, recipe_ids, _after) =
+            self.instant_cache
+                .get_or_search(SearchCache::generation(&searcher), key, || {
+                    let recipe_ids = self
+                        .recipe_index
+                        .instant_search(&searcher, input, &recent, limit)?;
+                    Ok((TotalCount::exact(recipe_ids.len()), recipe_ids, None))
+                })?;
+
+        Ok(recipe_ids)
+    }
+
+    pub fn search(
+        &self,
+        query: SearchQuery,
+        after: Option<After>,
+        score: Option<ScoreExpression>,
+    ) -> Result<ExecuteResult> {
         let limit = query.num_items.unwrap_or(10) as usize;
+        let budget = query
+            .options
+            .map(|options| SearchBudget::new(search_budget(options)));
 
         let searcher = self.reader.searcher();
-        let interpreted_query = self.interpret_query(&query)?;
 
-        let (total_found, recipe_ids, after) = self.recipe_index.search(
-            &searcher,
-            &interpreted_query,
-            limit,
-            query.sort.unwrap_or(Sort::Relevance),
-            after,
-        )?;
+        let started = Instant::now();
+        let interpreted_query =
+            cantine::span!("parse").in_scope(|| self.interpret_query(&query))?;
+        let parse = started.elapsed();
+
+        let recorder = if query.profile {
+            Some(TimingsRecorder::default())
+        } else {
+            None
+        };
+        let skipped = if self.skip_failed_segments {
+            Some(SkippedSegments::default())
+        } else {
+            None
+        };
+        let recipe_index = if recorder.is_some() || skipped.is_some() || budget.is_some() {
+            let mut recipe_index = self.recipe_index.clone();
+            recipe_index.timings = recorder.clone();
+            recipe_index.skipped_segments = skipped.clone();
+            recipe_index.budget = budget.clone();
+            Cow::Owned(recipe_index)
+        } else {
+            Cow::Borrowed(&self.recipe_index)
+        };
 
-        let agg = if total_found <= self.agg_threshold {
-            query
+        let started = Instant::now();
+        let sort = query.sort.clone().unwrap_or(Sort::Relevance);
+        let mut matched_ids = None;
+        let (total, recipe_ids, after) = if query.collapse_variants {
+            let (total, found) = recipe_index.collapsed(&searcher, &interpreted_query, limit)?;
+            let (parent_ids, variant_ids) = found.into_iter().unzip();
+            matched_ids = Some(variant_ids);
+            (TotalCount::exact(total), parent_ids, None)
+        } else if let Some(expression) = score {
+            recipe_index
+                .expression_sorted(
+                    &searcher,
+                    &interpreted_query,
+                    limit,
+                    expression,
+                    query.ascending,
+                    after,
+                )
+                .map(exact_total)?
+        } else if let Some(profile) = query.macro_profile {
+            recipe_index
+                .profile_scored(&searcher, &interpreted_query, limit, profile, after)
+                .map(exact_total)?
+        } else if let Some(field) = query.runtime_sort {
+            recipe_index
+                .runtime_sorted(
+                    &searcher,
+                    &interpreted_query,
+                    limit,
+                    field,
+                    query.ascending,
+                    after,
+                )
+                .map(exact_total)?
+        } else if let Some(center) = query.distance_sort {
+            recipe_index
+                .distance_sorted(&searcher, &interpreted_query, limit, center, after)
+                .map(exact_total)?
+        } else if let Sort::Random = sort {
+            recipe_index
+                .shuffled(
+                    &searcher,
+                    &interpreted_query,
+                    limit,
+                    query.seed.unwrap_or(0),
+                    after,
+                )
+                .map(exact_total)?
+        } else if let (Sort::Relevance, true) = (&sort, query.boost_popular) {
+            self.popularity.refresh()?;
+            let (total, recipe_ids) = recipe_index.popularity_rescored(
+                &searcher,
+                &interpreted_query,
+                limit,
+                POPULARITY_CANDIDATES,
+                Some(&self.popularity),
+            )?;
+            (TotalCount::exact(total), recipe_ids, None)
+        } else if let (Sort::Relevance, Some(boost)) = (&sort, query.recency) {
+            recipe_index
+                .recency_boosted(
+                    &searcher,
+                    &interpreted_query,
+                    limit,
+                    boost,
+                    self.clock.now(),
+                    after,
+                )
+                .map(exact_total)?
+        } else if let (Some(cache), None) = (&self.cache, &recorder) {
+            let key = SearchCache::key(&*interpreted_query, limit, &sort, &after);
+            let output =
+                cache.get_or_search(SearchCache::generation(&searcher), key.clone(), || {
+                    Ok(recipe_index.search_with_total(
+                        &searcher,
+                        &interpreted_query,
+                        limit,
+                        sort,
+                        after,
+                    )?)
+                })?;
+            // Partial results would outlive the flag telling so
+            if skipped
+                .as_ref()
+                .map_or(false, |skipped| !skipped.is_empty())
+                || budget.as_ref().map_or(false, SearchBudget::is_exhausted)
+            {
+                cache.remove(&key);
+            }
+            output
+        } else {
+            recipe_index.search_with_total(&searcher, &interpreted_query, limit, sort, after)?
+        };
+
+        // Annotating the variant that matched, not its base recipe
+        let annotations = if query.annotate {
+            let ids = matched_ids.as_ref().unwrap_or(&recipe_ids);
+            Some(self.matched_clauses(&searcher, &query, ids)?)
+        } else {
+            None
+        };
+        let search = started.elapsed();
+
+        let started = Instant::now();
+        let global_agg = match query.global_agg.clone() {
+            Some(agg_query) => self.global_aggregation(&searcher, &query, agg_query)?,
+            None => None,
+        };
+
+        let (agg, percentiles, histogram, buckets, runtime_percentiles) = if total.value
+            <= self.agg_threshold
+        {
+            let agg = query
                 .agg
                 .map(|agg_query| {
                     self.recipe_index
                         .aggregate_features(&searcher, &interpreted_query, agg_query)
                 })
-                .transpose()?
+                .transpose()?;
+
+            let percentiles = query
+                .percentiles
+                .filter(|features| !features.is_empty())
+                .map(|features| {
+                    self.recipe_index
+                        .feature_percentiles(&searcher, &interpreted_query, &features)
+                })
+                .transpose()?;
+
+            let histogram = query
+                .histogram
+                .map(|interval| {
+                    self.recipe_index
+                        .added_histogram(&searcher, &interpreted_query, interval)
+                        .map(|histogram| histogram.buckets())
+                })
+                .transpose()?;
+
+            let buckets = query
+                .buckets
+                .filter(|buckets| !buckets.is_empty())
+                .map(|buckets| {
+                    self.recipe_index
+                        .filter_buckets(&searcher, &interpreted_query, buckets)
+                })
+                .transpose()?;
+
+            let runtime_percentiles = query
+                .runtime_percentiles
+                .filter(|fields| !fields.is_empty())
+                .map(|fields| {
+                    self.recipe_index
+                        .runtime_percentiles(&searcher, &interpreted_query, &fields)
+                })
+                .transpose()?;
+
+            (agg, percentiles, histogram, buckets, runtime_percentiles)
         } else {
-            None
+            (None, None, None, None, None)
         };
 
-        Ok((total_found, recipe_ids, after, agg))
+        let partial = skipped.map_or(false, |skipped| !skipped.take().is_empty())
+            || budget.map_or(false, |budget| budget.is_exhausted());
+
+        let profile = recorder.map(|recorder| {
+            let timings = recorder.take();
+            SearchProfile {
+                parse_micros: micros(parse),
+                search_micros: micros(search),
+                segment_micros: timings
+                    .iter()
+                    .flat_map(|timings| timings.segments.iter().copied().map(micros))
+                    .collect(),
+                merge_micros: timings.iter().map(|timings| micros(timings.merge)).sum(),
+                aggregation_micros: micros(started.elapsed()),
+                hydration_micros: 0,
+            }
+        });
+
+        Ok(ExecuteResult {
+            total,
+            recipe_ids,
+            after,
+            agg,
+            percentiles,
+            histogram,
+            buckets,
+            global_agg,
+            runtime_percentiles,
+            profile,
+            matched_ids,
+            partial,
+            annotations,
+        })
+    }
+
+    /// Parses a `score` from a search request, with the figures of
+    /// the corpus (see `GlobalStats::constant`) at hand
+    pub fn score_expression(&self, input: &str) -> error::Result<ScoreExpression> {
+        let searcher = self.reader.searcher();
+        let stats = self.stats(&searcher)?;
+        self.recipe_index
+            .score_expression_with_stats(&searcher, input, &stats)
+    }
+
+    pub fn count(&self, query: &SearchQuery) -> Result<usize> {
+        let searcher = self.reader.searcher();
+        let interpreted_query = self.interpret_query(query)?;
+        Ok(self.recipe_index.count(&searcher, &interpreted_query)?)
+    }
+
+    pub fn exists(&self, query: &SearchQuery) -> Result<bool> {
+        let searcher = self.reader.searcher();
+        let interpreted_query = self.interpret_query(query)?;
+        Ok(self.recipe_index.exists(&searcher, &interpreted_query)?)
+    }
+
+    pub fn similar(&self, recipe_id: RecipeId, limit: usize) -> Result<Option<Vec<RecipeId>>> {
+        let searcher = self.reader.searcher();
+        Ok(self.recipe_index.similar_to(&searcher, recipe_id, limit)?)
+    }
+
+    pub fn grouped(&self, query: &GroupedQuery) -> Result<Vec<(u64, usize, Vec<RecipeId>)>> {
+        let searcher = self.reader.searcher();
+        let interpreted_query = self.interpret_query(&query.search)?;
+        Ok(self.recipe_index.grouped(
+            &searcher,
+            &interpreted_query,
+            query.group_by,
+            query.num_groups.unwrap_or(10) as usize,
+            query.per_group.unwrap_or(3) as usize,
+        )?)
+    }
+
+    pub fn explain(
+        &self,
+        query: SearchQuery,
+        recipe_id: RecipeId,
+    ) -> Result<Option<RecipeExplanation>> {
+        let searcher = self.reader.searcher();
+        let (fulltext, filters) = self.clauses(&query);
+
+        Ok(self
+            .recipe_index
+            .explain(&searcher, fulltext.as_ref(), &filters, recipe_id)?)
+    }
+
+    /// Which parts of `query` each of the recipes matches
+    fn matched_clauses(
+        &self,
+        searcher: &Searcher,
+        query: &SearchQuery,
+        recipe_ids: &[RecipeId],
+    ) -> Result<Vec<MatchedClauses>> {
+        let (fulltext, filters) = self.clauses(query);
+
+        let mut annotations = Vec::with_capacity(recipe_ids.len());
+        for &recipe_id in recipe_ids {
+            let explanation =
+                self.recipe_index
+                    .explain(searcher, fulltext.as_ref(), &filters, recipe_id)?;
+            annotations.push(
+                explanation
+                    .as_ref()
+                    .map(MatchedClauses::from)
+                    .unwrap_or_default(),
+            );
+        }
+
+        Ok(annotations)
+    }
+
+    /// The full-text part of `query` and each of its restrictions,
+    /// so that they can be evaluated one by one
+    fn clauses(&self, query: &SearchQuery) -> (Box<dyn Query>, Vec<Box<dyn Query>>) {
+        let fulltext = self
+            .fulltext_query(query)
+            .unwrap_or_else(|| Box::new(AllQuery));
+
+        let mut filters = self
+            .filter_subqueries(query)
+            .into_iter()
+            .map(|(_occur, filter)| filter)
+            .collect::<Vec<_>>();
+
+        if let Some(runtime_filter) = query.runtime_filter.clone().filter(|f| !f.is_empty()) {
+            filters.push(Box::new(RuntimeFilterQuery::new(
+                Box::new(AllQuery),
+                self.recipe_index.features_bincode,
+                runtime_filter,
+            )));
+        }
+
+        (fulltext, filters)
     }
 
     fn interpret_query(&self, query: &SearchQuery) -> Result<Box<dyn Query>> {
         let mut subqueries: Vec<(Occur, Box<dyn Query>)> = Vec::new();
 
-        if let Some(fulltext) = &query.fulltext {
-            if let Some(parsed) = self.query_parser.parse_dixmax(fulltext.as_str(), 0.1) {
-                subqueries.push((Occur::Must, parsed));
+        if let Some(parsed) = self.fulltext_query(query) {
+            subqueries.push((Occur::Must, parsed));
+        }
+
+        subqueries.extend(self.filter_subqueries(query));
+
+        Ok(self.runtime_filtered(query, combine(subqueries)))
+    }
+
+    /// What `fulltext_query` had to guess about
+    pub fn fulltext_issues(&self, query: &SearchQuery) -> Vec<QueryIssue> {
+        query.fulltext.as_ref().map_or_else(Vec::new, |fulltext| {
+            self.query_parser
+                .check(fulltext)
+                .into_iter()
+                .map(|issue| QueryIssue {
+                    position: issue.position,
+                    message: issue.message.to_owned(),
+                })
+                .collect()
+        })
+    }
+
+    fn fulltext_query(&self, query: &SearchQuery) -> Option<Box<dyn Query>> {
+        let ast = self.normalized_fulltext(query)?;
+
+        if let Some(boost) = &query.boost {
+            ast.to_dismax_query(&self.boosted_parser(boost), 0.1)
+        } else {
+            ast.to_dismax_query(&self.query_parser, 0.1)
+        }
+    }
+
+    /// The query's `fulltext` in its canonical form, so that searches
+    /// for `Bacon eggs` and `eggs bacon` are the same (and share their
+    /// cache entries)
+    fn normalized_fulltext(&self, query: &SearchQuery) -> Option<Ast> {
+        let mut ast = self.query_parser.parse_ast(query.fulltext.as_ref()?);
+        ast.normalize();
+        Some(ast)
+    }
+
+    /// A stable hash of the normalized `fulltext`, for grouping
+    /// equivalent searches
+    pub fn fulltext_fingerprint(&self, query: &SearchQuery) -> Option<String> {
+        self.normalized_fulltext(query)
+            .map(|ast| format!("{:016x}", ast.fingerprint()))
+    }
+
+    /// Narrows `restricted` down by the query's `runtime_filter`, if any
+    fn runtime_filtered(&self, query: &SearchQuery, restricted: Box<dyn Query>) -> Box<dyn Query> {
+        match &query.runtime_filter {
+            Some(filters) if !filters.is_empty() => Box::new(RuntimeFilterQuery::new(
+                restricted,
+                self.recipe_index.features_bincode,
+                filters.clone(),
+            )),
+            _ => restricted,
+        }
+    }
+
+    /// The restrictions from `filter` and `added`, i.e.: everything
+    /// in the query except the full-text part
+    fn filter_subqueries(&self, query: &SearchQuery) -> Vec<(Occur, Box<dyn Query>)> {
+        let mut filter = query.filter.clone();
+
+        if let Some(added) = &query.added {
+            let range = added.resolve(self.clock.as_ref());
+            let filter = filter.get_or_insert_with(Default::default);
+
+            filter.added_at = Some(match filter.added_at.take() {
+                Some(given) => given.start.max(range.start)..given.end.min(range.end),
+                None => range,
+            });
+        }
+
+        // Taken out so that it matches either the time from the
+        // source or the estimate
+        let total_time = if query.include_estimated_times {
+            filter.as_mut().and_then(|filter| filter.total_time.take())
+        } else {
+            None
+        };
+
+        let mut subqueries: Vec<(Occur, Box<dyn Query>)> = filter
+            .map(|filter| {
+                self.recipe_index
+                    .features
+                    .interpret(&filter)
+                    .into_iter()
+                    .map(|query| (Occur::Must, query))
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        if let Some(range) = total_time {
+            let range = u64::from(range.start)..u64::from(range.end);
+            let features = &self.recipe_index.features;
+
+            subqueries.push((
+                Occur::Must,
+                Box::new(BooleanQuery::from(vec![
+                    (
+                        Occur::Should,
+                        Box::new(RangeQuery::new_u64(features.total_time, range.clone()))
+                            as Box<dyn Query>,
+                    ),
+                    (
+                        Occur::Should,
+                        Box::new(RangeQuery::new_u64(features.total_time_estimate, range)),
+                    ),
+                ])),
+            ));
+        }
+
+        if let Some(near) = query.near {
+            subqueries.push((
+                Occur::Must,
+                Box::new(GeoDistanceQuery::new(self.recipe_index.origin, near)),
+            ));
+        }
+
+        if let Some(author) = &query.author {
+            if let Some(uuid) = &author.uuid {
+                let author_id = self
+                    .authors
+                    .as_ref()
+                    .and_then(|authors| authors.id_for_uuid(uuid).copied());
+
+                subqueries.push((
+                    Occur::Must,
+                    match author_id {
+                        Some(id) => Box::new(TermQuery::new(
+                            Term::from_field_u64(self.recipe_index.author_id, id),
+                            IndexRecordOption::Basic,
+                        )),
+                        // An unknown author has no recipes
+                        None => Box::new(EmptyQuery),
+                    },
+                ));
             }
+
+            if let Some(verified) = author.verified {
+                subqueries.push((
+                    Occur::Must,
+                    Box::new(TermQuery::new(
+                        Term::from_field_u64(
+                            self.recipe_index.author_verified,
+                            u64::from(verified),
+                        ),
+                        IndexRecordOption::Basic,
+                    )),
+                ));
+            }
+        }
+
+        let exists = query.exists.as_deref().unwrap_or_default();
+        let missing = query.missing.as_deref().unwrap_or_default();
+        if !exists.is_empty() || !missing.is_empty() {
+            subqueries.push((
+                Occur::Must,
+                Box::new(self.recipe_index.presence_query(exists, missing)),
+            ));
+        }
+
+        if let Some(nutrition) = &query.nutrition {
+            subqueries.extend(
+                self.recipe_index
+                    .features
+                    .interpret(&nutrition.to_features_filter())
+                    .into_iter()
+                    .map(|query| (Occur::Must, query)),
+            );
+        }
+
+        if let Some(pantry) = &query.pantry {
+            subqueries.push((
+                Occur::Must,
+                Box::new(PantryQuery::from_filter(
+                    pantry,
+                    self.recipe_index.ingredients,
+                    &self.ingredients_tokenizer,
+                    self.recipe_index.features.num_ingredients,
+                )),
+            ));
         }
 
-        if let Some(filter) = &query.filter {
-            for query in self.recipe_index.features.interpret(filter).into_iter() {
-                subqueries.push((Occur::Must, query));
+        subqueries
+    }
+
+    /// Aggregates over the recipes in the query's `global_scope`,
+    /// regardless of its full-text part. Like the regular aggregation,
+    /// it's skipped if there are too many recipes in the scope
+    fn global_aggregation(
+        &self,
+        searcher: &Searcher,
+        query: &SearchQuery,
+        agg_query: FeaturesAggregationQuery,
+    ) -> Result<Option<FeaturesAggregationResult>> {
+        let (scoped, in_scope) = match query.global_scope {
+            AggregationScope::All => (
+                Box::new(AllQuery) as Box<dyn Query>,
+                searcher.num_docs() as usize,
+            ),
+            AggregationScope::Filters => {
+                let scoped = self.runtime_filtered(query, combine(self.filter_subqueries(query)));
+                let in_scope = searcher.search(&scoped, &Count)?;
+                (scoped, in_scope)
             }
+        };
+
+        if in_scope > self.agg_threshold {
+            return Ok(None);
         }
 
-        match subqueries.len() {
-            0 => Ok(Box::new(AllQuery)),
-            1 => Ok(subqueries.pop().expect("length has been checked").1),
-            _ => Ok(Box::new(BooleanQuery::from(subqueries))),
+        Ok(Some(
+            self.recipe_index
+                .aggregate_features(searcher, &scoped, agg_query)?,
+        ))
+    }
+
+    fn boosted_parser(&self, boost: &FieldBoosts) -> QueryParser {
+        let mut parser = self.query_parser.clone();
+
+        for (field, value) in &[
+            (self.recipe_index.name, boost.name),
+            (self.recipe_index.ingredients, boost.ingredients),
+            (self.recipe_index.instructions, boost.instructions),
+        ] {
+            if let Some(value) = value.filter(|v| v.is_finite() && *v >= 0.0) {
+                parser.set_boost(*field, Some(value));
+            }
+        }
+
+        if boost.normalize {
+            match self.stats(&self.reader.searcher()) {
+                Ok(stats) => normalize_boosts(&mut parser, &stats, &self.recipe_index),
+                Err(err) => log::warn!("Boosts left as they are: {}", err),
+            }
+        }
+
+        parser
+    }
+
+    /// Corpus-wide statistics for what the given searcher sees.
+    /// Recomputed whenever the index gets reloaded
+    pub fn stats(&self, searcher: &Searcher) -> Result<Arc<GlobalStats>> {
+        if let Some(stats) = self.stats.read().unwrap().as_ref() {
+            if stats.is_current(searcher) {
+                return Ok(stats.clone());
+            }
         }
+
+        let stats = Arc::new(GlobalStats::compute(searcher, &self.recipe_index)?);
+        log::debug!("Computed global stats for {} docs", stats.num_docs);
+        *self.stats.write().unwrap() = Some(stats.clone());
+
+        Ok(stats)
+    }
+
+    pub fn freshness(&self) -> Result<Option<Freshness>> {
+        Ok(self.freshness.check(&self.reader.searcher())?)
+    }
+
+    pub fn index_stats(&self) -> Result<IndexStats> {
+        Ok(self
+            .recipe_index
+            .stats(&self.reader.searcher(), &self.index_path)?)
+    }
+
+    pub fn generation_status(&self, generation: u64) -> Result<GenerationStatus> {
+        let visible = self.visibility.visible(&self.reader.searcher())?;
+        Ok(GenerationStatus {
+            generation,
+            searchable: visible >= generation,
+            visible,
+        })
+    }
+
+    pub fn schema_info(&self) -> SchemaInfo {
+        let schema = self.reader.searcher().schema().clone();
+
+        let fields = schema
+            .fields()
+            .map(|(_field, entry)| {
+                let (kind, indexing) = match entry.field_type() {
+                    FieldType::Str(options) => ("text", options.get_indexing_options()),
+                    FieldType::U64 { .. } => ("u64", None),
+                    FieldType::I64 { .. } => ("i64", None),
+                    FieldType::F64 { .. } => ("f64", None),
+                    FieldType::Date { .. } => ("date", None),
+                    FieldType::HierarchicalFacet { .. } => ("facet", None),
+                    FieldType::Bytes { .. } => ("bytes", None),
+                };
+
+                FieldInfo {
+                    name: entry.name().to_owned(),
+                    kind,
+                    indexed: entry.is_indexed(),
+                    stored: entry.is_stored(),
+                    // Bytes fields are always fast in this version
+                    fast: entry.is_int_fast() || kind == "bytes",
+                    tokenizer: indexing.map(|indexing| indexing.tokenizer().to_owned()),
+                    positions: indexing
+                        .map_or(false, |indexing| indexing.index_option().has_positions()),
+                }
+            })
+            .collect();
+
+        let fulltext = self
+            .query_parser
+            .fields()
+            .into_iter()
+            .map(|parser_field| FulltextField {
+                field: schema.get_field_name(parser_field.field).to_owned(),
+                alias: parser_field.name,
+                boost: parser_field.boost,
+                default: parser_field.default,
+            })
+            .collect();
+
+        SchemaInfo { fields, fulltext }
     }
 
-    pub fn index_info(&self) -> Result<IndexInfo> {
+    /// What the index has, as of the current searcher. Recomputed
+    /// whenever the index gets reloaded, like `stats`
+    pub fn index_info(&self) -> Result<Arc<IndexInfo>> {
         let searcher = self.reader.searcher();
+        if let Some(info) = self.info.read().unwrap().as_ref() {
+            if info.stats.is_current(&searcher) {
+                return Ok(info.clone());
+            }
+        }
+
         let features = self.recipe_index.aggregate_features(
             &searcher,
             &AllQuery,
@@ -182,21 +1672,136 @@ impl SearchState {
 
         let sort = Sort::VALUES.to_vec();
 
-        Ok(IndexInfo {
+        let info = Arc::new(IndexInfo {
             total_recipes: searcher.num_docs(),
             features,
             sort,
+            stats: self.stats(&searcher)?,
+        });
+        *self.info.write().unwrap() = Some(info.clone());
+
+        Ok(info)
+    }
+}
+
+/// Scales the boost of each full-text field down the longer the field
+/// is on average, across the whole corpus, relative to the others:
+/// long fields match more terms by chance, so matching there says
+/// less about a recipe
+fn normalize_boosts(parser: &mut QueryParser, stats: &GlobalStats, recipe_index: &RecipeIndex) {
+    let fields = parser
+        .fields()
+        .into_iter()
+        .filter_map(|parser_field| {
+            stats
+                .field(parser_field.field, recipe_index)
+                .map(|field_stats| (parser_field, field_stats.avg_tokens))
         })
+        .collect::<Vec<_>>();
+
+    if fields.is_empty() {
+        return;
+    }
+    let mean = fields.iter().map(|(_field, avg)| avg).sum::<f32>() / fields.len() as f32;
+
+    for (parser_field, avg_tokens) in fields {
+        let factor = mean.ln_1p() / avg_tokens.ln_1p();
+        if factor.is_finite() {
+            parser.set_boost(
+                parser_field.field,
+                Some(parser_field.boost.unwrap_or(1.0) * factor),
+            );
+        }
+    }
+}
+
+fn combine(mut subqueries: Vec<(Occur, Box<dyn Query>)>) -> Box<dyn Query> {
+    match subqueries.len() {
+        0 => Box::new(AllQuery),
+        1 => subqueries.pop().expect("length has been checked").1,
+        _ => Box::new(BooleanQuery::from(subqueries)),
     }
 }
 
 const BASE_DIR: &str = "BASE_DIR";
 const AGG_THRESHOLD: &str = "AGG_THRESHOLD";
+const FIXED_NOW: &str = "FIXED_NOW";
+const MAX_REGEX_LENGTH: &str = "MAX_REGEX_LENGTH";
+const MINIMUM_SHOULD_MATCH: &str = "MINIMUM_SHOULD_MATCH";
+const SYNONYMS: &str = "SYNONYMS";
+const TWO_PHASE_SAMPLE: &str = "TWO_PHASE_SAMPLE";
+const CACHE_SIZE: &str = "CACHE_SIZE";
+const CACHE_TTL: &str = "CACHE_TTL";
+const WARMUP: &str = "WARMUP";
+const LOCK_DATABASE: &str = "LOCK_DATABASE";
+const SKIP_FAILED_SEGMENTS: &str = "SKIP_FAILED_SEGMENTS";
+const FIELD_ROLES: &str = "FIELD_ROLES";
+const QUERY_LOG: &str = "QUERY_LOG";
+#[cfg(feature = "parallel")]
+const PARALLEL_COLLECTION: &str = "PARALLEL_COLLECTION";
+const API_KEYS: &str = "API_KEYS";
+const BEARER: &str = "Bearer ";
+const EXPERIMENT: &str = "EXPERIMENT";
+const USER_TOKEN_HEADER: &str = "X-User-Token";
+const EXPORT_BATCH_SIZE: usize = 1000;
+// How many of the best matches by relevance `boost_popular` reranks
+const POPULARITY_CANDIDATES: usize = 100;
+
+const INSTANT_THREADS: &str = "INSTANT_THREADS";
+const INSTANT_CACHE_SIZE: &str = "INSTANT_CACHE_SIZE";
+const JOB_THREADS: &str = "JOB_THREADS";
+const MAX_QUEUED_JOBS: &str = "MAX_QUEUED_JOBS";
+const JOB_RETENTION: &str = "JOB_RETENTION";
 
 fn get_env(key: &str) -> Result<String> {
     env::var(key).map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, key).into())
 }
 
+/// Reads a synonym dictionary. Files with a `.json` extension are
+/// expected to contain an object mapping words to a list of
+/// synonyms, anything else is read using `SynonymMap`'s text format
+fn load_synonyms(path: &Path) -> Result<SynonymMap> {
+    let contents = fs::read_to_string(path)?;
+
+    if path.extension().map_or(false, |ext| ext == "json") {
+        let mapping: HashMap<String, Vec<String>> = serde_json::from_str(&contents)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+        Ok(SynonymMap::from(mapping))
+    } else {
+        Ok(SynonymMap::parse(&contents))
+    }
+}
+
+/// Warms the index up right away, then again whenever the reader
+/// sees a new generation of it
+fn spawn_warmer(
+    reader: IndexReader,
+    recipe_index: RecipeIndex,
+    options: WarmupOptions,
+) -> Result<()> {
+    let warm = move |reader: &IndexReader| -> Result<u64> {
+        let searcher = reader.searcher();
+        let started = Instant::now();
+        let stats = recipe_index.warm(&searcher, &options)?;
+        log::info!("Warmed up in {:?}: {:?}", started.elapsed(), stats);
+        Ok(SearchCache::generation(&searcher))
+    };
+
+    let mut generation = warm(&reader)?;
+    thread::spawn(move || loop {
+        thread::sleep(Duration::from_secs(5));
+
+        if SearchCache::generation(&reader.searcher()) != generation {
+            match warm(&reader) {
+                Ok(warmed) => generation = warmed,
+                Err(err) => log::error!("Warmup failed: {:?}", err),
+            }
+        }
+    });
+
+    Ok(())
+}
+
 #[actix_rt::main]
 async fn main() -> Result<()> {
     env_logger::init();
@@ -206,18 +1811,140 @@ async fn main() -> Result<()> {
         .ok()
         .map(|v| usize::from_str(&v).expect("valid usize"));
 
+    // Pins the clock used for relative dates. Useful for
+    // backtesting against historical data
+    let fixed_now = get_env(FIXED_NOW)
+        .ok()
+        .map(|v| u64::from_str(&v).expect("valid timestamp"));
+
+    // Enables `/regex/` terms of up to this many characters. For
+    // data cleanup, not for end-users
+    let max_regex_length = get_env(MAX_REGEX_LENGTH)
+        .ok()
+        .map(|v| usize::from_str(&v).expect("valid usize"));
+
+    // How many of the optional terms of a search must match, like
+    // "3" or "75%". Any one of them will do by default
+    let minimum_should_match = get_env(MINIMUM_SHOULD_MATCH)
+        .ok()
+        .map(|v| MinimumShouldMatch::from_str(&v).expect("valid minimum should match"));
+
+    let synonyms_path = get_env(SYNONYMS).ok();
+
+    // A json file with the card fields each role may see. Every
+    // request sees every field without it
+    let field_roles_path = get_env(FIELD_ROLES).ok();
+
+    // A json file with the role of each api key. Without it, every
+    // request gets the default role
+    let api_keys_path = get_env(API_KEYS).ok();
+
+    // A json file describing a relevance experiment. See `Experiment`
+    let experiment_path = get_env(EXPERIMENT).ok();
+
+    // Enables two-phase collection for relevance-sorted searches.
+    // Debug logs show how each phase went
+    let two_phase_sample = get_env(TWO_PHASE_SAMPLE)
+        .ok()
+        .map(|v| usize::from_str(&v).expect("valid usize"));
+
+    // Caches the results of up to this many searches. Disabled
+    // by default
+    let cache_size = get_env(CACHE_SIZE)
+        .ok()
+        .map(|v| usize::from_str(&v).expect("valid usize"))
+        .filter(|&size| size > 0);
+
+    // For how long (in seconds) a cached result is good
+    let cache_ttl = get_env(CACHE_TTL)
+        .ok()
+        .map_or(60, |v| u64::from_str(&v).expect("valid u64"));
+
+    // Serves searches from the healthy segments, flagging results
+    // as partial, when some are broken. Only until a rebuild, please
+    let skip_failed_segments =
+        get_env(SKIP_FAILED_SEGMENTS).map_or(false, |v| v == "1" || v == "true");
+
+    // How many threads are dedicated to instant searches
+    let instant_threads = get_env(INSTANT_THREADS)
+        .ok()
+        .map_or(2, |v| usize::from_str(&v).expect("valid usize"));
+
+    // Instant searches are cached apart from the rest, and always
+    let instant_cache_size = get_env(INSTANT_CACHE_SIZE)
+        .ok()
+        .map_or(10_000, |v| usize::from_str(&v).expect("valid usize"))
+        .max(1);
+
+    // How many background jobs (exports) run at the same time, and
+    // how many may wait for their turn. More get a 429
+    let job_threads = get_env(JOB_THREADS)
+        .ok()
+        .map_or(2, |v| usize::from_str(&v).expect("valid usize"));
+    let max_queued_jobs = get_env(MAX_QUEUED_JOBS)
+        .ok()
+        .map_or(16, |v| usize::from_str(&v).expect("valid usize"));
+
+    // For how long (in seconds) the status of a finished job is kept
+    let job_retention = get_env(JOB_RETENTION)
+        .ok()
+        .map_or(3600, |v| u64::from_str(&v).expect("valid u64"));
+
+    // Logs every search to the database's queries keyspace, for
+    // `top_queries` to report on
+    let log_queries = get_env(QUERY_LOG).map_or(false, |v| v == "1" || v == "true");
+
     log::info!(
-        "Starting with base_dir={} agg_threshold={:?}",
+        "Starting with base_dir={} agg_threshold={:?} fixed_now={:?} \
+         max_regex_length={:?} minimum_should_match={:?} synonyms={:?} \
+         two_phase_sample={:?} cache_size={:?} cache_ttl={} \
+         skip_failed_segments={} instant_threads={} instant_cache_size={} \
+         job_threads={} max_queued_jobs={} job_retention={} \
+         field_roles={:?} api_keys={:?} query_log={} experiment={:?}",
         base_dir,
-        threshold
+        threshold,
+        fixed_now,
+        max_regex_length,
+        minimum_should_match,
+        synonyms_path,
+        two_phase_sample,
+        cache_size,
+        cache_ttl,
+        skip_failed_segments,
+        instant_threads,
+        instant_cache_size,
+        job_threads,
+        max_queued_jobs,
+        job_retention,
+        field_roles_path,
+        api_keys_path,
+        log_queries,
+        experiment_path
     );
 
     let base_path = Path::new(&base_dir);
     let index_path = base_path.join("tantivy");
     let db_path = base_path.join("database");
+    let exports_dir = base_path.join("exports");
+    fs::create_dir_all(&exports_dir)?;
 
     let index = Index::open_in_dir(&index_path)?;
-    let recipe_index = RecipeIndex::try_from(&index.schema())?;
+    Analysis::load(base_path)?.register(&index);
+
+    let recipe_index = RecipeIndex::try_from(&index.schema())?
+        .with_collation(Collation::load(base_path)?)
+        .with_two_phase(two_phase_sample);
+    recipe_index.install_tokenizers(&index)?;
+
+    // Collects the segments of the index in parallel. Only pays off
+    // for indexes with plenty of segments
+    #[cfg(feature = "parallel")]
+    let recipe_index = {
+        let parallel = get_env(PARALLEL_COLLECTION).map_or(false, |v| v == "1" || v == "true");
+        log::info!("Parallel segment collection: {}", parallel);
+        recipe_index.with_parallel_collection(parallel)
+    };
+
     let mut query_parser = QueryParser::new(
         &index,
         vec![
@@ -233,28 +1960,152 @@ async fn main() -> Result<()> {
     // And make name matches slightly more important than ingredient
     query_parser.set_boost(recipe_index.name, Some(1.15));
 
+    // Ranges like `added:>=now-30d` or `calories:<500`
+    query_parser.add_range_field("added", recipe_index.features.added_at);
+    query_parser.add_range_field("calories", recipe_index.features.calories);
+    query_parser.set_now(fixed_now);
+    query_parser.set_max_regex_length(max_regex_length);
+    query_parser.set_minimum_should_match(minimum_should_match);
+
+    if let Some(path) = synonyms_path {
+        let synonyms = load_synonyms(Path::new(&path))?;
+        log::info!("Loaded synonyms for {} words", synonyms.len());
+        query_parser.set_synonyms(Some(Arc::new(synonyms)));
+    }
+
+    let api_keys = match api_keys_path {
+        Some(path) => {
+            let keys = ApiKeys::load(Path::new(&path))?;
+            log::info!("Loaded {} api keys", keys.len());
+            keys
+        }
+        None => ApiKeys::default(),
+    };
+
+    let field_roles = match field_roles_path {
+        Some(path) => {
+            let roles = FieldRoles::load(Path::new(&path))?;
+            log::info!("Loaded the fields of {} roles", roles.len());
+            roles
+        }
+        None => FieldRoles::default(),
+    };
+
     let reader = index.reader()?;
+
+    // Either "all" or a comma-separated list of the fields to warm
+    // up whenever the reader picks up changes
+    if let Ok(names) = get_env(WARMUP) {
+        let options = if names == "all" {
+            WarmupOptions::all(&index.schema())
+        } else {
+            WarmupOptions::from_names(&index.schema(), &names.split(',').collect::<Vec<_>>())?
+        };
+        spawn_warmer(reader.clone(), recipe_index.clone(), options)?;
+    }
+
+    let experiment = match experiment_path {
+        Some(path) => {
+            let experiment = Experiment::load(Path::new(&path))?;
+            log::info!(
+                "Running experiment {} with {} arms",
+                experiment.name,
+                experiment.arms.len()
+            );
+            Some(experiment)
+        }
+        None => None,
+    };
+
+    let query_log = if log_queries {
+        Some(Arc::new(QueryLog::open(&db_path, Box::new(SystemClock))?))
+    } else {
+        None
+    };
+
     let search_state = Arc::new(SearchState {
         reader,
+        visibility: Visibility::new(index.clone()),
+        freshness: FreshnessTracker::new(index.clone(), &db_path),
+        ingredients_tokenizer: index.tokenizer_for_field(recipe_index.ingredients)?,
         recipe_index,
         query_parser,
         agg_threshold: threshold.unwrap_or(std::usize::MAX),
+        clock: fixed_now.map_or_else(
+            || Box::new(SystemClock) as Box<dyn Clock>,
+            |now| Box::new(FixedClock(now)),
+        ),
+        stats: RwLock::new(None),
+        info: RwLock::new(None),
+        authors: authors::open_reader(&db_path)?.map(Arc::new),
+        cache: cache_size.map(|size| SearchCache::new(size, Duration::from_secs(cache_ttl))),
+        skip_failed_segments,
+        instant_cache: SearchCache::new(instant_cache_size, Duration::from_secs(cache_ttl)),
+        exports_dir,
+        index_path,
+        popularity: PopularityStore::open(&db_path)?,
+        field_roles,
+        api_keys,
+        summaries: summaries::open_reader(&db_path)?.map(Arc::new),
+        query_log,
+        experiment,
     });
 
+    // With the current stats at hand, like searches will have them
+    if let Some(experiment) = &search_state.experiment {
+        experiment.check_scores(|input| search_state.score_expression(input))?;
+    }
+
     let database: RecipeDatabase = Arc::new(DatabaseReader::open(&db_path)?);
+    // Recipes are fetched by id, so reading ahead is wasted effort
+    database.advise_random()?;
+    if get_env(LOCK_DATABASE).map_or(false, |v| v == "1" || v == "true") {
+        database.lock_in_memory()?;
+        log::info!("Locked the recipe database in memory");
+    }
 
-    let info = search_state.index_info()?;
+    // Upfront, so that the first `/info` doesn't wait for it
+    search_state.index_info()?;
+    let schema_info = search_state.schema_info();
+    let diversity_metrics = Arc::new(DiversityMetrics::default());
+    let instant_lane = InstantLane::new(instant_threads);
+    // Wall clock, even with FIXED_NOW: retention is about real time
+    let jobs = Arc::new(Jobs::new(
+        job_threads,
+        max_queued_jobs,
+        job_retention,
+        Box::new(SystemClock),
+    )?);
 
     HttpServer::new(move || {
         App::new()
             .wrap(Logger::default())
             .app_data(web::Data::new(search_state.clone()))
             .app_data(web::Data::new(database.clone()))
-            .app_data(web::Data::new(info.clone()))
+            .app_data(web::Data::new(schema_info.clone()))
+            .app_data(web::Data::new(diversity_metrics.clone()))
+            .app_data(web::Data::new(instant_lane.clone()))
+            .app_data(web::Data::new(jobs.clone()))
             .data(web::JsonConfig::default().limit(4096))
+            .service(web::resource("/recipe/{uuid}.jsonld").route(web::get().to(recipe_jsonld)))
+            .service(web::resource("/recipe/{uuid}/similar").route(web::get().to(similar)))
             .service(web::resource("/recipe/{uuid}").route(web::get().to(recipe)))
             .service(web::resource("/search").route(web::post().to(search)))
+            .service(web::resource("/count").route(web::post().to(count)))
+            .service(web::resource("/exists").route(web::post().to(exists)))
+            .service(web::resource("/grouped").route(web::post().to(grouped)))
+            .service(web::resource("/instant").route(web::get().to(instant)))
+            .service(web::resource("/explain/{uuid}").route(web::post().to(explain)))
             .service(web::resource("/info").route(web::get().to(index_info)))
+            .service(web::resource("/labels").route(web::get().to(labels)))
+            .service(web::resource("/admin/schema").route(web::get().to(schema_info)))
+            .service(web::resource("/admin/freshness").route(web::get().to(freshness)))
+            .service(web::resource("/generation/{generation}").route(web::get().to(generation)))
+            .service(web::resource("/metrics").route(web::get().to(metrics)))
+            .service(web::resource("/stats").route(web::get().to(stats)))
+            .service(web::resource("/jobs/export").route(web::post().to(export)))
+            .service(web::resource("/jobs/{id}").route(web::get().to(job)))
+            .configure(prometheus_routes)
     })
     .bind("127.0.0.1:8080")?
     .run()
@@ -0,0 +1,144 @@
+use std::time::Instant;
+
+pub use self::imp::*;
+
+/// Prometheus metrics about searches, the search caches, index
+/// commits, database appends and reader reloads, all in the registry
+/// yielded by `registry`, for the embedding application to scrape
+/// (say: via `prometheus::TextEncoder`)
+#[cfg(feature = "metrics")]
+mod imp {
+    use super::Instant;
+
+    use once_cell::sync::Lazy;
+    use prometheus::{Histogram, HistogramOpts, IntCounter, Registry};
+
+    struct Metrics {
+        registry: Registry,
+        search_seconds: Histogram,
+        cache_hits: IntCounter,
+        cache_misses: IntCounter,
+        commit_seconds: Histogram,
+        appended_records: IntCounter,
+        appended_bytes: IntCounter,
+        reader_reloads: IntCounter,
+    }
+
+    static METRICS: Lazy<Metrics> = Lazy::new(|| {
+        let registry =
+            Registry::new_custom(Some("cantine".to_owned()), None).expect("valid registry prefix");
+
+        let histogram = |name: &str, help: &str| {
+            let histogram =
+                Histogram::with_opts(HistogramOpts::new(name, help)).expect("valid histogram");
+            registry
+                .register(Box::new(histogram.clone()))
+                .expect("metrics are registered once");
+            histogram
+        };
+        let counter = |name: &str, help: &str| {
+            let counter = IntCounter::new(name, help).expect("valid counter");
+            registry
+                .register(Box::new(counter.clone()))
+                .expect("metrics are registered once");
+            counter
+        };
+
+        Metrics {
+            search_seconds: histogram(
+                "search_duration_seconds",
+                "How long top recipes searches take",
+            ),
+            cache_hits: counter("cache_hits_total", "Searches served from a SearchCache"),
+            cache_misses: counter(
+                "cache_misses_total",
+                "Searches a SearchCache didn't have the results of",
+            ),
+            commit_seconds: histogram("commit_duration_seconds", "How long index commits take"),
+            appended_records: counter(
+                "database_appended_records_total",
+                "Items appended to databases",
+            ),
+            appended_bytes: counter(
+                "database_appended_bytes_total",
+                "Bytes of the items appended to databases",
+            ),
+            reader_reloads: counter(
+                "reader_reloads_total",
+                "Index reader reloads done after commits",
+            ),
+            registry,
+        }
+    });
+
+    /// Where every metric is
+    pub fn registry() -> &'static Registry {
+        &METRICS.registry
+    }
+
+    pub(crate) fn search_done(started: Instant) {
+        METRICS
+            .search_seconds
+            .observe(started.elapsed().as_secs_f64());
+    }
+
+    pub(crate) fn cache_lookup(hit: bool) {
+        if hit {
+            METRICS.cache_hits.inc();
+        } else {
+            METRICS.cache_misses.inc();
+        }
+    }
+
+    pub(crate) fn commit_done(started: Instant) {
+        METRICS
+            .commit_seconds
+            .observe(started.elapsed().as_secs_f64());
+    }
+
+    pub(crate) fn appended(num_bytes: usize) {
+        METRICS.appended_records.inc();
+        METRICS.appended_bytes.inc_by(num_bytes as u64);
+    }
+
+    pub(crate) fn reader_reloaded() {
+        METRICS.reader_reloads.inc();
+    }
+}
+
+// Without the feature, instrumenting costs nothing
+#[cfg(not(feature = "metrics"))]
+mod imp {
+    use super::Instant;
+
+    pub(crate) fn search_done(_started: Instant) {}
+
+    pub(crate) fn cache_lookup(_hit: bool) {}
+
+    pub(crate) fn commit_done(_started: Instant) {}
+
+    pub(crate) fn appended(_num_bytes: usize) {}
+
+    pub(crate) fn reader_reloaded() {}
+}
+
+#[cfg(all(test, feature = "metrics"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hooks_feed_the_registry() {
+        cache_lookup(true);
+        search_done(Instant::now());
+
+        let families = registry().gather();
+        let names = families
+            .iter()
+            .map(|family| family.get_name())
+            .collect::<Vec<_>>();
+
+        assert!(names.contains(&"cantine_cache_hits_total"));
+        assert!(names.contains(&"cantine_search_duration_seconds"));
+        assert!(names.contains(&"cantine_reader_reloads_total"));
+    }
+}
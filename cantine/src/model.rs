@@ -1,4 +1,8 @@
-use std::convert::TryInto;
+use std::{
+    collections::{BTreeMap, HashMap},
+    convert::TryInto,
+    ops::Range,
+};
 
 use base64::{self, URL_SAFE_NO_PAD};
 use serde::{
@@ -8,7 +12,18 @@ use serde::{
 use tantivy::Score;
 use uuid::{self, Uuid};
 
-use crate::database::DatabaseRecord;
+use crate::{
+    clock::{self, Clock},
+    database::DatabaseRecord,
+    geo::{GeoDistanceFilter, GeoPoint},
+    histogram::{Bucket, Interval},
+    nutrition::{MacroProfile, NutritionFilter},
+    pantry::PantryFilter,
+    projection::CardField,
+    recency::RecencyBoost,
+    runtime::{RuntimeField, RuntimeFilter},
+    tenant::TenantId,
+};
 use cantine_derive::{Aggregable, Filterable};
 
 #[derive(Deserialize, Serialize, Debug, PartialEq, Clone)]
@@ -26,6 +41,23 @@ pub struct Recipe {
     pub similar_recipe_ids: Vec<u64>,
 
     pub features: Features,
+
+    #[serde(default)]
+    pub author_id: Option<AuthorId>,
+
+    /// The base recipe this one is a variant of (say: its vegan
+    /// take), if any
+    #[serde(default)]
+    pub variant_of: Option<RecipeId>,
+
+    /// Where the dish comes from, for regional recipes
+    #[serde(default)]
+    pub origin: Option<GeoPoint>,
+
+    /// Who the recipe belongs to, when the index is shared by several
+    /// tenants. See `TenantScopedIndex`
+    #[serde(default)]
+    pub tenant_id: Option<TenantId>,
 }
 
 pub type RecipeId = u64;
@@ -37,39 +69,232 @@ impl DatabaseRecord for Recipe {
     fn get_uuid(&self) -> uuid::Bytes {
         *self.uuid.as_bytes()
     }
+    fn get_key(&self) -> Option<&str> {
+        slug(&self.crawl_url)
+    }
 }
 
-#[derive(Serialize, Deserialize, Debug, Default)]
-pub struct RecipeCard {
+#[cfg(test)]
+impl Recipe {
+    /// A plain recipe for tests, to fill in whatever else they need
+    /// via `Recipe { ..Recipe::sample(recipe_id, name) }`
+    pub(crate) fn sample(recipe_id: RecipeId, name: &str) -> Self {
+        Self {
+            uuid: Uuid::new_v4(),
+            recipe_id,
+            name: name.to_owned(),
+            crawl_url: format!("https://example.com/{}", recipe_id),
+            ingredients: vec!["salt".to_owned()],
+            instructions: vec!["add salt".to_owned()],
+            images: Vec::new(),
+            similar_recipe_ids: Vec::new(),
+            features: Features::default(),
+            author_id: None,
+            variant_of: None,
+            origin: None,
+            tenant_id: None,
+        }
+    }
+}
+
+/// What recipes are looked up by key with: their crawl url without
+/// the scheme, the query, the fragment and any trailing slash (say:
+/// "example.com/recipes/shakshuka"). None for empty urls
+pub fn slug(url: &str) -> Option<&str> {
+    let without_scheme = url.splitn(2, "://").last().unwrap_or(url);
+    let slug = without_scheme
+        .split(|c| c == '?' || c == '#')
+        .next()
+        .unwrap_or(without_scheme)
+        .trim_end_matches('/');
+
+    if slug.is_empty() {
+        None
+    } else {
+        Some(slug)
+    }
+}
+
+/// Who wrote a recipe. Kept in a keyspace of its own, apart from
+/// the recipes
+#[derive(Deserialize, Serialize, Debug, PartialEq, Clone)]
+pub struct Author {
+    pub uuid: Uuid,
+
+    pub author_id: AuthorId,
     pub name: String,
+    pub profile_url: Option<String>,
+
+    #[serde(default)]
+    pub verified: bool,
+}
+
+pub type AuthorId = u64;
+
+impl DatabaseRecord for Author {
+    fn get_id(&self) -> u64 {
+        self.author_id
+    }
+    fn get_uuid(&self) -> uuid::Bytes {
+        *self.uuid.as_bytes()
+    }
+}
+
+/// How much a recipe is interacted with, as counted by whatever
+/// tracks it. Records replace the previous counts of the recipe
+#[derive(Deserialize, Serialize, Debug, PartialEq, Clone)]
+pub struct Popularity {
     pub uuid: Uuid,
+    pub recipe_id: RecipeId,
+
+    #[serde(default)]
+    pub clicks: u64,
+    #[serde(default)]
+    pub saves: u64,
+}
+
+impl DatabaseRecord for Popularity {
+    fn get_id(&self) -> u64 {
+        self.recipe_id
+    }
+    fn get_uuid(&self) -> uuid::Bytes {
+        *self.uuid.as_bytes()
+    }
+}
+
+/// What lists of recipes show of each, kept in a keyspace of its own
+/// so that showing them doesn't take decoding whole recipes (say: the
+/// instructions). See `summaries`
+#[derive(Deserialize, Serialize, Debug, PartialEq, Clone)]
+pub struct RecipeSummary {
+    pub uuid: Uuid,
+    pub recipe_id: RecipeId,
+    pub name: String,
     pub crawl_url: String,
+    pub image: Option<String>,
+
     pub num_ingredients: u8,
     pub instructions_length: u32,
+    /// The `total_time`, or its estimate if there's none
+    pub total_time: Option<u32>,
+    pub total_time_estimated: bool,
+    pub calories: Option<u32>,
+
+    pub author_id: Option<AuthorId>,
+    pub origin: Option<GeoPoint>,
+}
+
+impl DatabaseRecord for RecipeSummary {
+    fn get_id(&self) -> u64 {
+        self.recipe_id
+    }
+    fn get_uuid(&self) -> uuid::Bytes {
+        *self.uuid.as_bytes()
+    }
+}
+
+impl From<&Recipe> for RecipeSummary {
+    fn from(src: &Recipe) -> Self {
+        let features = &src.features;
+        Self {
+            uuid: src.uuid,
+            recipe_id: src.recipe_id,
+            name: src.name.clone(),
+            crawl_url: src.crawl_url.clone(),
+            image: src.images.first().cloned(),
+            num_ingredients: features.num_ingredients,
+            instructions_length: features.instructions_length,
+            total_time: features.total_time.or(features.total_time_estimate),
+            total_time_estimated: features.total_time.is_none()
+                && features.total_time_estimate.is_some(),
+            calories: features.calories,
+            author_id: src.author_id,
+            origin: src.origin,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct AuthorCard {
+    pub uuid: Uuid,
+    pub name: String,
+    pub verified: bool,
+}
+
+impl From<Author> for AuthorCard {
+    fn from(src: Author) -> Self {
+        Self {
+            uuid: src.uuid,
+            name: src.name,
+            verified: src.verified,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct RecipeCard {
+    pub uuid: Uuid,
+
+    // Only missing when left out by a `Projection`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub crawl_url: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub num_ingredients: Option<u8>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub instructions_length: Option<u32>,
 
     #[serde(skip_serializing_if = "Option::is_none")]
     pub image: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub total_time: Option<u32>,
+    /// Whether the `total_time` is an estimate instead of coming
+    /// from the source
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub total_time_estimated: bool,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub calories: Option<u32>,
+
+    /// Filled in from the authors keyspace when rendering results
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub author: Option<AuthorCard>,
+
+    /// When collapsing variants, the variant that actually matched
+    /// the search if it wasn't this recipe
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub matched_variant: Option<Uuid>,
+
+    /// For `annotate`d searches, the parts of the search this
+    /// recipe matched
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub matched: Option<MatchedClauses>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Default)]
 pub struct RecipeInfo {
     pub uuid: Uuid,
+    // Empty only when left out by a `Projection`
+    #[serde(skip_serializing_if = "String::is_empty")]
     pub name: String,
+    #[serde(skip_serializing_if = "String::is_empty")]
     pub crawl_url: String,
 
     pub num_ingredients: u8,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
     pub ingredients: Vec<String>,
 
+    #[serde(skip_serializing_if = "Vec::is_empty")]
     pub instructions: Vec<String>,
 
     #[serde(skip_serializing_if = "Vec::is_empty")]
     pub images: Vec<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub total_time: Option<u32>,
+    /// Whether the `total_time` is an estimate instead of coming
+    /// from the source
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub total_time_estimated: bool,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub calories: Option<u32>,
 }
@@ -86,7 +311,9 @@ impl From<Recipe> for RecipeInfo {
             instructions: src.instructions,
 
             num_ingredients: src.features.num_ingredients,
-            total_time: src.features.total_time,
+            total_time: src.features.total_time.or(src.features.total_time_estimate),
+            total_time_estimated: src.features.total_time.is_none()
+                && src.features.total_time_estimate.is_some(),
             calories: src.features.calories,
         }
     }
@@ -94,15 +321,25 @@ impl From<Recipe> for RecipeInfo {
 
 impl From<Recipe> for RecipeCard {
     fn from(src: Recipe) -> Self {
+        RecipeSummary::from(&src).into()
+    }
+}
+
+impl From<RecipeSummary> for RecipeCard {
+    fn from(src: RecipeSummary) -> Self {
         Self {
-            name: src.name,
             uuid: src.uuid,
-            crawl_url: src.crawl_url,
-            image: src.images.into_iter().next(),
-            num_ingredients: src.features.num_ingredients,
-            instructions_length: src.features.instructions_length,
-            total_time: src.features.total_time,
-            calories: src.features.calories,
+            name: Some(src.name),
+            crawl_url: Some(src.crawl_url),
+            image: src.image,
+            num_ingredients: Some(src.num_ingredients),
+            instructions_length: Some(src.instructions_length),
+            total_time: src.total_time,
+            total_time_estimated: src.total_time_estimated,
+            calories: src.calories,
+            author: None,
+            matched_variant: None,
+            matched: None,
         }
     }
 }
@@ -115,6 +352,9 @@ pub struct Features {
     pub prep_time: Option<u32>,
     pub total_time: Option<u32>,
     pub cook_time: Option<u32>,
+    /// A guess of the `total_time`, only for recipes without one.
+    /// See `estimate::fill_in`
+    pub total_time_estimate: Option<u32>,
 
     pub calories: Option<u32>,
     pub fat_content: Option<f32>,
@@ -126,6 +366,9 @@ pub struct Features {
     pub diet_vegan: Option<f32>,
     pub diet_keto: Option<f32>,
     pub diet_paleo: Option<f32>,
+
+    /// When the recipe was added, in seconds since the epoch
+    pub added_at: Option<u64>,
 }
 
 pub type FeaturesFilterQuery = <Features as Filterable>::Query;
@@ -134,6 +377,111 @@ pub type FeaturesFilterFields = <Features as Filterable>::Schema;
 pub type FeaturesAggregationQuery = <Features as Aggregable>::Query;
 pub type FeaturesAggregationResult = <Features as Aggregable>::Agg;
 
+/// The features that can be summarized via percentiles
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum NumericFeature {
+    NumIngredients,
+    InstructionsLength,
+    PrepTime,
+    TotalTime,
+    CookTime,
+    Calories,
+    FatContent,
+    CarbContent,
+    ProteinContent,
+}
+
+impl NumericFeature {
+    pub fn value(self, features: &Features) -> Option<f64> {
+        match self {
+            NumericFeature::NumIngredients => Some(f64::from(features.num_ingredients)),
+            NumericFeature::InstructionsLength => Some(f64::from(features.instructions_length)),
+            NumericFeature::PrepTime => features.prep_time.map(f64::from),
+            NumericFeature::TotalTime => features.total_time.map(f64::from),
+            NumericFeature::CookTime => features.cook_time.map(f64::from),
+            NumericFeature::Calories => features.calories.map(f64::from),
+            NumericFeature::FatContent => features.fat_content.map(f64::from),
+            NumericFeature::CarbContent => features.carb_content.map(f64::from),
+            NumericFeature::ProteinContent => features.protein_content.map(f64::from),
+        }
+    }
+}
+
+/// Approximate percentiles of a feature among the matching recipes.
+/// Recipes without the feature are not counted
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct PercentileSummary {
+    pub count: u64,
+    pub p25: f64,
+    pub p50: f64,
+    pub p75: f64,
+    pub p90: f64,
+    pub p99: f64,
+}
+
+pub type FeaturesPercentiles = HashMap<NumericFeature, PercentileSummary>;
+
+/// Why a recipe matched a search and how it got its score
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct RecipeExplanation {
+    /// The score given by the full-text query. None if the recipe
+    /// doesn't match it
+    pub score: Option<Score>,
+    /// The full-text query terms found in the recipe, by field
+    pub matches: BTreeMap<String, Vec<TermMatch>>,
+    /// Every restriction of the search, in order
+    pub filters: Vec<FilterCheck>,
+}
+
+/// The gist of a `RecipeExplanation`, for labeling search hits
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq)]
+pub struct MatchedClauses {
+    /// The full-text query terms found in the recipe, in any field
+    pub terms: Vec<String>,
+    /// The restrictions the recipe passes, named like in
+    /// `FilterCheck`
+    pub filters: Vec<String>,
+}
+
+impl From<&RecipeExplanation> for MatchedClauses {
+    fn from(explanation: &RecipeExplanation) -> Self {
+        let mut terms = explanation
+            .matches
+            .values()
+            .flatten()
+            .map(|found| found.term.clone())
+            .collect::<Vec<_>>();
+        terms.sort();
+        terms.dedup();
+
+        Self {
+            terms,
+            filters: explanation
+                .filters
+                .iter()
+                .filter(|check| check.passed)
+                .map(|check| check.field.clone())
+                .collect(),
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct TermMatch {
+    pub term: String,
+    /// The score the recipe would get for a query with just
+    /// this term
+    pub score: Score,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct FilterCheck {
+    /// The restricted field
+    pub field: String,
+    pub passed: bool,
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 #[serde(rename_all = "snake_case")]
 pub enum Sort {
@@ -150,6 +498,8 @@ pub enum Sort {
     FatContentAsc,
     InstructionsLength,
     InstructionsLengthAsc,
+    Name,
+    NameAsc,
     NumIngredients,
     NumIngredientsAsc,
     PrepTime,
@@ -158,10 +508,12 @@ pub enum Sort {
     ProteinContentAsc,
     TotalTime,
     TotalTimeAsc,
+    /// A pseudo-random order, stable for a given `seed`
+    Random,
 }
 
 impl Sort {
-    pub const VALUES: [Self; 20] = [
+    pub const VALUES: [Self; 23] = [
         Sort::Relevance,
         Sort::RelevanceAsc,
         Sort::Calories,
@@ -174,6 +526,8 @@ impl Sort {
         Sort::FatContentAsc,
         Sort::InstructionsLength,
         Sort::InstructionsLengthAsc,
+        Sort::Name,
+        Sort::NameAsc,
         Sort::NumIngredients,
         Sort::NumIngredientsAsc,
         Sort::PrepTime,
@@ -182,33 +536,522 @@ impl Sort {
         Sort::ProteinContentAsc,
         Sort::TotalTime,
         Sort::TotalTimeAsc,
+        Sort::Random,
     ];
 }
 
+/// Restricts a search by the attributes of the recipe authors
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct AuthorFilter {
+    /// Only the recipes by this author
+    pub uuid: Option<Uuid>,
+    /// Only the recipes by verified (or unverified) authors
+    pub verified: Option<bool>,
+}
+
+/// Parts of a recipe that may be missing, for the `exists` and
+/// `missing` filters. The values are what gets indexed, so they must
+/// never change
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum PresenceField {
+    Image = 0,
+    /// Every one of calories, fat, carbs and protein
+    Nutrition = 1,
+    PrepTime = 2,
+    CookTime = 3,
+    TotalTime = 4,
+    Author = 5,
+    Origin = 6,
+    AddedAt = 7,
+}
+
+impl PresenceField {
+    pub const VALUES: [Self; 8] = [
+        PresenceField::Image,
+        PresenceField::Nutrition,
+        PresenceField::PrepTime,
+        PresenceField::CookTime,
+        PresenceField::TotalTime,
+        PresenceField::Author,
+        PresenceField::Origin,
+        PresenceField::AddedAt,
+    ];
+
+    /// Whether `recipe` has this part
+    pub fn is_present(self, recipe: &Recipe) -> bool {
+        let features = &recipe.features;
+        match self {
+            PresenceField::Image => !recipe.images.is_empty(),
+            PresenceField::Nutrition => {
+                features.calories.is_some()
+                    && features.fat_content.is_some()
+                    && features.carb_content.is_some()
+                    && features.protein_content.is_some()
+            }
+            PresenceField::PrepTime => features.prep_time.is_some(),
+            PresenceField::CookTime => features.cook_time.is_some(),
+            PresenceField::TotalTime => features.total_time.is_some(),
+            PresenceField::Author => recipe.author_id.is_some(),
+            PresenceField::Origin => recipe.origin.map_or(false, |origin| origin.is_valid()),
+            PresenceField::AddedAt => features.added_at.is_some(),
+        }
+    }
+}
+
+/// Per-request importance of each full-text field. Overrides the
+/// server defaults for the fields that are set.
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct FieldBoosts {
+    pub name: Option<f32>,
+    pub ingredients: Option<f32>,
+    pub instructions: Option<f32>,
+    /// Scales every boost by how long the field is across the whole
+    /// corpus, so that long fields don't win by matching more terms
+    /// by chance
+    #[serde(default)]
+    pub normalize: bool,
+}
+
+/// A date range relative to the time the search is executed
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum RelativeDate {
+    Today,
+    ThisWeek,
+    ThisMonth,
+    ThisSeason,
+    LastDays(u16),
+}
+
+impl RelativeDate {
+    /// Resolves into a timestamp range using the given clock
+    pub fn resolve(&self, clock: &dyn Clock) -> Range<u64> {
+        let now = clock.now();
+        let tomorrow = clock::start_of_day(now) + clock::SECONDS_PER_DAY;
+
+        match self {
+            RelativeDate::Today => clock::start_of_day(now)..tomorrow,
+            RelativeDate::ThisWeek => clock::start_of_week(now)..tomorrow,
+            RelativeDate::ThisMonth => clock::start_of_month(now)..tomorrow,
+            RelativeDate::ThisSeason => clock::start_of_season(now)..tomorrow,
+            RelativeDate::LastDays(days) => {
+                now.saturating_sub(u64::from(*days) * clock::SECONDS_PER_DAY)..now + 1
+            }
+        }
+    }
+}
+
+/// What the `global_agg` of a search is computed over. The
+/// full-text part of the query is always ignored
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum AggregationScope {
+    /// Every recipe in the index
+    All,
+    /// Only the recipes matching the `filter`, `added` and
+    /// `runtime_filter` restrictions
+    Filters,
+}
+
+impl Default for AggregationScope {
+    fn default() -> Self {
+        AggregationScope::All
+    }
+}
+
+/// How a search deals with `fulltext` that isn't valid query syntax
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ParseMode {
+    /// Searches for whatever can be made of it, listing what was
+    /// off in the `warnings` of the result
+    Lenient,
+    /// Rejects it, listing what was off
+    Strict,
+}
+
+impl Default for ParseMode {
+    fn default() -> Self {
+        ParseMode::Lenient
+    }
+}
+
+/// Where and how the `fulltext` of a search isn't valid syntax
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct QueryIssue {
+    /// In characters, from the start of `fulltext`
+    pub position: usize,
+    pub message: String,
+}
+
+/// What a strict search replies with when its `fulltext` isn't valid
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct QueryError {
+    pub issues: Vec<QueryIssue>,
+}
+
 #[derive(Serialize, Deserialize, Debug, Default)]
 #[serde(deny_unknown_fields)]
 pub struct SearchQuery {
     pub fulltext: Option<String>,
+    #[serde(default)]
+    pub parse_mode: ParseMode,
+    pub boost: Option<FieldBoosts>,
     pub num_items: Option<u8>,
     pub filter: Option<FeaturesFilterQuery>,
     pub agg: Option<FeaturesAggregationQuery>,
+    pub percentiles: Option<Vec<NumericFeature>>,
+    /// Count the matching recipes by when they were added
+    pub histogram: Option<Interval>,
+    /// Count the matching recipes that also match each named filter
+    pub buckets: Option<BTreeMap<String, FeaturesFilterQuery>>,
+    /// Like `agg`, but computed over the `global_scope`
+    pub global_agg: Option<FeaturesAggregationQuery>,
+    #[serde(default)]
+    pub global_scope: AggregationScope,
+    /// Restricts the results by fields computed at search time
+    pub runtime_filter: Option<Vec<RuntimeFilter>>,
+    /// Like `percentiles`, but for fields computed at search time
+    pub runtime_percentiles: Option<Vec<RuntimeField>>,
     pub after: Option<SearchCursor>,
+    pub added: Option<RelativeDate>,
+    pub author: Option<AuthorFilter>,
+    /// Only the recipes that have every one of these
+    pub exists: Option<Vec<PresenceField>>,
+    /// Only the recipes that lack every one of these
+    pub missing: Option<Vec<PresenceField>>,
+    /// Lets the `total_time` filter match estimated times too
+    #[serde(default)]
+    pub include_estimated_times: bool,
+    /// Limits on nutrition facts, maybe from a preset
+    pub nutrition: Option<NutritionFilter>,
 
     pub sort: Option<Sort>,
+    /// Picks the order of the `random` sort
+    pub seed: Option<u64>,
+    /// Sorts by a field computed at search time instead of `sort`
+    pub runtime_sort: Option<RuntimeField>,
+    /// Only recipes from around a location
+    pub near: Option<GeoDistanceFilter>,
+    /// Only recipes with some of these ingredients, ranked by how
+    /// much of what they need is at hand
+    pub pantry: Option<PantryFilter>,
+    /// Sorts by the distance of the recipes' origin to a location
+    /// instead of `sort`, closest first
+    pub distance_sort: Option<GeoPoint>,
+    /// Ranks by a score expression instead of `sort`, like
+    /// `"_score * log1p(num_ingredients)"`
+    pub score: Option<String>,
+    /// Ranks by relevance weighted by how close the recipes are to
+    /// these nutrition facts instead of `sort`
+    pub macro_profile: Option<MacroProfile>,
+    /// Favors recently added recipes when sorting by relevance
+    pub recency: Option<RecencyBoost>,
     #[serde(default)]
     pub ascending: bool,
+    /// Reports where the time of the search went
+    #[serde(default)]
+    pub profile: bool,
+    /// Yields each recipe and its variants at most once, by relevance
+    #[serde(default)]
+    pub collapse_variants: bool,
+    /// Reranks the best matches by relevance boosted by popularity
+    #[serde(default)]
+    pub boost_popular: bool,
+    /// Tells which parts of the search each hit matched
+    #[serde(default)]
+    pub annotate: bool,
+    /// Limits how much work the search may do
+    pub options: Option<SearchOptions>,
+    /// Only these fields of the recipe cards, out of the ones the
+    /// role of the request may see. Every one of those by default
+    pub fields: Option<Vec<CardField>>,
+}
+
+/// The budget of a search. When it runs out, the recipes found so
+/// far are returned and the result is flagged as `partial`
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct SearchOptions {
+    /// In milliseconds, counted from when the search starts
+    pub timeout: Option<u64>,
+    /// How many matching recipes to go through at most
+    pub max_docs_visited: Option<u64>,
+}
+
+/// What `/count` replies with
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct CountResult {
+    pub total: usize,
+}
+
+/// What `/exists` replies with
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct ExistsResult {
+    pub exists: bool,
+}
+
+/// What `/grouped` takes: a search and how to group its results
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct GroupedQuery {
+    #[serde(default)]
+    pub search: SearchQuery,
+    pub group_by: GroupField,
+    /// How many groups, the ones with the most recipes first.
+    /// 10 by default
+    pub num_groups: Option<u8>,
+    /// How many recipes per group, by relevance. 3 by default
+    pub per_group: Option<u8>,
+}
+
+/// What search results can be grouped by
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum GroupField {
+    Author,
+}
+
+/// What `/grouped` replies with
+#[derive(Serialize, Debug, Default)]
+pub struct GroupedResult {
+    pub groups: Vec<RecipeGroup>,
+}
+
+/// Recipes that share the value of a `GroupField`
+#[derive(Serialize, Debug)]
+pub struct RecipeGroup {
+    /// For groups by author, when the author is known
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub author: Option<AuthorCard>,
+    /// How many recipes in the group matched the search
+    pub total: usize,
+    pub items: Vec<RecipeCard>,
+}
+
+/// How many recipes matched a search
+#[derive(Serialize, Debug, Default, Clone, Copy, PartialEq)]
+pub struct TotalCount {
+    pub value: usize,
+    pub relation: CountRelation,
+    /// Half the width of a ~95% confidence interval around `value`
+    pub error: usize,
+}
+
+impl TotalCount {
+    pub fn exact(value: usize) -> Self {
+        Self {
+            value,
+            relation: CountRelation::Eq,
+            error: 0,
+        }
+    }
+
+    pub fn approx(value: usize, error: usize) -> Self {
+        Self {
+            value,
+            relation: CountRelation::Approx,
+            error,
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum CountRelation {
+    /// The count is exact
+    Eq,
+    /// The count is an estimate
+    Approx,
+}
+
+impl Default for CountRelation {
+    fn default() -> Self {
+        CountRelation::Eq
+    }
+}
+
+/// How a background job (see `jobs::Jobs`) is doing, as reported by
+/// `/jobs/{id}`
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct JobStatus {
+    pub id: u64,
+    pub state: JobState,
+    /// How many items the job goes through, once known
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub total: Option<usize>,
+    pub processed: usize,
+    /// Where the results are, once done
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub output: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// When the job finished, as a timestamp. Its status is only
+    /// kept for a while after that
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub finished_at: Option<u64>,
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum JobState {
+    /// Waiting for a thread to run on
+    Queued,
+    Running,
+    Done,
+    Failed,
+}
+
+/// The query string of `/recipe/{uuid}/similar`
+#[derive(Deserialize, Debug, Default)]
+pub struct SimilarQuery {
+    /// 10 by default
+    pub num_items: Option<u8>,
+}
+
+/// What `/recipe/{uuid}/similar` replies with
+#[derive(Serialize, Debug, Default)]
+pub struct SimilarResult {
+    pub items: Vec<RecipeCard>,
+}
+
+/// What was typed so far, as sent to `/instant`
+#[derive(Deserialize, Debug, Default)]
+pub struct InstantQuery {
+    pub q: String,
+    /// Capped at `instant::MAX_ITEMS`
+    pub num_items: Option<u8>,
+}
+
+#[derive(Serialize, Debug, Default)]
+pub struct InstantResult {
+    pub items: Vec<InstantItem>,
+}
+
+/// Just enough of a recipe to suggest it while typing
+#[derive(Serialize, Debug, PartialEq)]
+pub struct InstantItem {
+    pub uuid: Uuid,
+    // Empty only when left out by a `Projection`
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub image: Option<String>,
+}
+
+impl From<Recipe> for InstantItem {
+    fn from(src: Recipe) -> Self {
+        Self {
+            uuid: src.uuid,
+            name: src.name,
+            image: src.images.into_iter().next(),
+        }
+    }
+}
+
+/// Whether a commit is searchable, as reported by `/generation`
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+pub struct GenerationStatus {
+    pub generation: u64,
+    pub searchable: bool,
+    /// The latest generation that's searchable
+    pub visible: u64,
 }
 
 #[derive(Serialize, Debug, Default)]
 pub struct SearchResult {
     pub items: Vec<RecipeCard>,
+    /// Same as `total.value`
     pub total_found: usize,
+    pub total: TotalCount,
+    /// Set when some of the index couldn't be searched or the search
+    /// ran out of budget, so the results (and the total) only cover
+    /// part of it
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub partial: bool,
 
     #[serde(skip_serializing_if = "Option::is_none")]
     pub agg: Option<FeaturesAggregationResult>,
 
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub percentiles: Option<FeaturesPercentiles>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub histogram: Option<Vec<Bucket>>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub buckets: Option<BTreeMap<String, u64>>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub global_agg: Option<FeaturesAggregationResult>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub runtime_percentiles: Option<Vec<Option<PercentileSummary>>>,
+
     #[serde(skip_serializing_if = "Option::is_none")]
     pub next: Option<SearchCursor>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub profile: Option<SearchProfile>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub diversity: Option<Diversity>,
+
+    /// What was off with the `fulltext` of a lenient search
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub warnings: Vec<QueryIssue>,
+
+    /// A stable hash of the normalized `fulltext`, the same for every
+    /// search that differs only in letter case or term order
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fingerprint: Option<String>,
+
+    /// The experiment arm whose ranking served the search
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ranking_profile: Option<String>,
+}
+
+/// Where the time of a search went, in microseconds
+#[derive(Serialize, Debug, Default, Clone, PartialEq)]
+pub struct SearchProfile {
+    /// Turning the request into a query
+    pub parse_micros: u64,
+    /// Finding the top recipes
+    pub search_micros: u64,
+    /// Finding the top recipes within each segment of the index
+    pub segment_micros: Vec<u64>,
+    /// Merging the top recipes of every segment
+    pub merge_micros: u64,
+    /// Every requested aggregation
+    pub aggregation_micros: u64,
+    /// Fetching the recipes (and authors) from the database
+    pub hydration_micros: u64,
+}
+
+/// How varied the top results of a search are. See `diversity`
+#[derive(Serialize, Debug, Default, Clone, PartialEq)]
+pub struct Diversity {
+    /// How many results were looked at
+    pub num_items: usize,
+    /// Sites the recipes were crawled from
+    pub distinct_sites: usize,
+    pub distinct_authors: usize,
+    /// Where the dishes come from, for regional recipes
+    pub distinct_origins: usize,
+}
+
+/// The average `Diversity` of every search with results since
+/// startup, as reported by `/metrics`
+#[derive(Serialize, Debug, Default, Clone, PartialEq)]
+pub struct DiversitySummary {
+    pub num_searches: u64,
+    pub mean_items: f64,
+    pub mean_distinct_sites: f64,
+    pub mean_distinct_authors: f64,
+    pub mean_distinct_origins: f64,
 }
 
 #[derive(Debug, PartialEq)]
@@ -326,6 +1169,18 @@ mod tests {
     use quickcheck::{quickcheck, TestResult};
     use serde_json;
 
+    #[test]
+    fn slugs_come_from_the_crawl_url() {
+        assert_eq!(
+            Some("example.com/recipes/shakshuka"),
+            slug("https://example.com/recipes/shakshuka/?ref=feed#steps")
+        );
+        assert_eq!(Some("example.com/1"), slug("http://example.com/1"));
+        assert_eq!(Some("example.com"), slug("example.com/"));
+        assert_eq!(None, slug(""));
+        assert_eq!(None, slug("https://"));
+    }
+
     #[test]
     fn search_cursor_json_round_trip() {
         let roundtrip = |cursor| {
@@ -385,6 +1240,34 @@ mod tests {
         }
     }
 
+    #[test]
+    fn relative_dates_resolve_against_the_clock() {
+        use crate::clock::FixedClock;
+
+        // 2020-02-29T13:37:00Z
+        let clock = FixedClock(1_582_983_420);
+        // 2020-03-01T00:00:00Z
+        let tomorrow = 1_583_020_800;
+
+        assert_eq!(1_582_934_400..tomorrow, RelativeDate::Today.resolve(&clock));
+        assert_eq!(
+            1_582_502_400..tomorrow,
+            RelativeDate::ThisWeek.resolve(&clock)
+        );
+        assert_eq!(
+            1_580_515_200..tomorrow,
+            RelativeDate::ThisMonth.resolve(&clock)
+        );
+        assert_eq!(
+            1_575_158_400..tomorrow,
+            RelativeDate::ThisSeason.resolve(&clock)
+        );
+        assert_eq!(
+            1_582_983_420 - 7 * 86_400..1_582_983_421,
+            RelativeDate::LastDays(7).resolve(&clock)
+        );
+    }
+
     #[test]
     fn search_cursor_deserialization_does_not_crash() {
         quickcheck(search_cursor_from_bytes as fn(Vec<u8>) -> TestResult);
@@ -0,0 +1,209 @@
+use std::ops::Range;
+
+use serde::{Deserialize, Serialize};
+
+use crate::model::{Features, FeaturesFilterQuery};
+
+/// Common nutrition requirements, so that clients don't have to
+/// agree on what "low carb" means
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum NutritionPreset {
+    LowCalorie,
+    HighProtein,
+    LowCarb,
+    LowFat,
+}
+
+impl NutritionPreset {
+    pub fn filter(self) -> NutritionFilter {
+        let filter = NutritionFilter::default();
+        match self {
+            NutritionPreset::LowCalorie => filter.with_max_calories(400),
+            NutritionPreset::HighProtein => filter.with_min_protein(25.0),
+            NutritionPreset::LowCarb => filter.with_carbs(0.0..20.0),
+            NutritionPreset::LowFat => filter.with_max_fat(10.0),
+        }
+    }
+}
+
+/// Limits on the nutrition facts of recipes, per serving. Grams
+/// for everything but calories (kcal). Maximums are inclusive for
+/// calories, exclusive otherwise.
+///
+/// Recipes lacking a fact that's limited never pass
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct NutritionFilter {
+    /// Where limits that aren't given come from
+    pub preset: Option<NutritionPreset>,
+    pub max_calories: Option<u32>,
+    pub min_carbs: Option<f32>,
+    pub max_carbs: Option<f32>,
+    pub min_protein: Option<f32>,
+    pub max_protein: Option<f32>,
+    pub max_fat: Option<f32>,
+}
+
+impl NutritionFilter {
+    pub fn with_max_calories(mut self, kcal: u32) -> Self {
+        self.max_calories = Some(kcal);
+        self
+    }
+
+    pub fn with_carbs(mut self, grams: Range<f32>) -> Self {
+        self.min_carbs = Some(grams.start);
+        self.max_carbs = Some(grams.end);
+        self
+    }
+
+    pub fn with_min_protein(mut self, grams: f32) -> Self {
+        self.min_protein = Some(grams);
+        self
+    }
+
+    pub fn with_max_protein(mut self, grams: f32) -> Self {
+        self.max_protein = Some(grams);
+        self
+    }
+
+    pub fn with_max_fat(mut self, grams: f32) -> Self {
+        self.max_fat = Some(grams);
+        self
+    }
+
+    /// The limits given, plus the ones from the `preset` that weren't
+    pub fn resolved(&self) -> Self {
+        let defaults = self.preset.map(NutritionPreset::filter).unwrap_or_default();
+
+        Self {
+            preset: None,
+            max_calories: self.max_calories.or(defaults.max_calories),
+            min_carbs: self.min_carbs.or(defaults.min_carbs),
+            max_carbs: self.max_carbs.or(defaults.max_carbs),
+            min_protein: self.min_protein.or(defaults.min_protein),
+            max_protein: self.max_protein.or(defaults.max_protein),
+            max_fat: self.max_fat.or(defaults.max_fat),
+        }
+    }
+
+    /// The equivalent restrictions on the (fast field) features
+    pub fn to_features_filter(&self) -> FeaturesFilterQuery {
+        let limits = self.resolved();
+
+        FeaturesFilterQuery {
+            calories: limits.max_calories.map(|max| 0..max.saturating_add(1)),
+            carb_content: f32_range(limits.min_carbs, limits.max_carbs),
+            protein_content: f32_range(limits.min_protein, limits.max_protein),
+            fat_content: f32_range(None, limits.max_fat),
+            ..FeaturesFilterQuery::default()
+        }
+    }
+}
+
+fn f32_range(min: Option<f32>, max: Option<f32>) -> Option<Range<f32>> {
+    if min.is_none() && max.is_none() {
+        None
+    } else {
+        Some(min.unwrap_or(0.0)..max.unwrap_or(std::f32::MAX))
+    }
+}
+
+/// Target nutrition facts, per serving, for ranking recipes by how
+/// close to them they are. See `MacroProfile::closeness`
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct MacroProfile {
+    pub calories: Option<f32>,
+    pub carbs: Option<f32>,
+    pub protein: Option<f32>,
+    pub fat: Option<f32>,
+}
+
+impl MacroProfile {
+    /// How close the features are to the targets, from 0 (far, or
+    /// lacking a targeted fact) to 1 (spot on). Misses are relative
+    /// to each target, so that calories don't outweigh the rest
+    pub fn closeness(&self, features: &Features) -> f64 {
+        let pairs = [
+            (self.calories, features.calories.map(|kcal| kcal as f32)),
+            (self.carbs, features.carb_content),
+            (self.protein, features.protein_content),
+            (self.fat, features.fat_content),
+        ];
+
+        let mut num_targets = 0;
+        let mut total_miss = 0.0;
+        for (target, value) in pairs.iter() {
+            if let Some(target) = target {
+                let value = match value {
+                    Some(value) => f64::from(*value),
+                    None => return 0.0,
+                };
+                let target = f64::from(*target);
+
+                num_targets += 1;
+                total_miss += (value - target).abs() / target.max(1.0);
+            }
+        }
+
+        if num_targets == 0 {
+            1.0
+        } else {
+            1.0 / (1.0 + total_miss / f64::from(num_targets))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn presets_fill_in_missing_limits() {
+        let filter = NutritionFilter {
+            preset: Some(NutritionPreset::LowCarb),
+            max_carbs: Some(10.0),
+            ..NutritionFilter::default()
+        }
+        .with_max_calories(500);
+
+        let features = filter.to_features_filter();
+        assert_eq!(Some(0..501), features.calories);
+        assert_eq!(Some(0.0..10.0), features.carb_content);
+        assert_eq!(None, features.protein_content);
+        assert_eq!(None, features.fat_content);
+
+        let high_protein = NutritionPreset::HighProtein.filter().to_features_filter();
+        assert_eq!(Some(25.0..std::f32::MAX), high_protein.protein_content);
+
+        let unlimited = NutritionFilter::default().to_features_filter();
+        assert!(unlimited.calories.is_none() && unlimited.carb_content.is_none());
+    }
+
+    #[test]
+    fn closeness_to_targets() {
+        let profile = MacroProfile {
+            calories: Some(500.0),
+            protein: Some(30.0),
+            ..MacroProfile::default()
+        };
+
+        let features = |calories, protein| Features {
+            calories,
+            protein_content: protein,
+            fat_content: Some(100.0),
+            ..Features::default()
+        };
+
+        assert!((profile.closeness(&features(Some(500), Some(30.0))) - 1.0).abs() < 1e-9);
+
+        let near = profile.closeness(&features(Some(550), Some(27.0)));
+        let far = profile.closeness(&features(Some(900), Some(10.0)));
+        assert!(near < 1.0 && far < near && far > 0.0);
+
+        assert_eq!(0.0, profile.closeness(&features(Some(500), None)));
+        // Untargeted facts don't matter
+        assert!((MacroProfile::default().closeness(&features(None, None)) - 1.0).abs() < 1e-9);
+    }
+}
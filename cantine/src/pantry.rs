@@ -0,0 +1,325 @@
+use std::collections::BTreeSet;
+
+use serde::{Deserialize, Serialize};
+use tantivy::{
+    fastfield::FastFieldReader,
+    query::{BooleanQuery, Explanation, Occur, Query, Scorer, TermQuery, Weight},
+    schema::{Field, IndexRecordOption},
+    tokenizer::TextAnalyzer,
+    DocId, DocSet, Result, Score, Searcher, SegmentReader, TantivyError, Term, TERMINATED,
+};
+
+/// What's at hand, for finding what can be cooked with it
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct PantryFilter {
+    /// Like `["eggs", "olive oil", "flour"]`
+    pub items: Vec<String>,
+    /// Only the recipes that need nothing else
+    #[serde(default)]
+    pub require_all: bool,
+}
+
+/// Matches documents with any of the pantry items in a text field,
+/// scoring them by the fraction of their ingredients the pantry
+/// covers, as told by a u64 fast field with how many they have.
+///
+/// An item is in a document when every one of its terms is, wherever
+/// they are, and each item found covers a single ingredient. Documents
+/// without ingredients never match
+#[derive(Debug, Clone)]
+pub struct PantryQuery {
+    items: Vec<Vec<Term>>,
+    num_ingredients: Field,
+    require_all: bool,
+}
+
+impl PantryQuery {
+    /// Each item is made of the terms (of a text field indexed with
+    /// frequencies) it was tokenized into. Items without any are
+    /// ignored
+    pub fn new(items: Vec<Vec<Term>>, num_ingredients: Field, require_all: bool) -> Self {
+        Self {
+            items: items
+                .into_iter()
+                .filter(|terms| !terms.is_empty())
+                .collect(),
+            num_ingredients,
+            require_all,
+        }
+    }
+
+    /// Tokenizes the items of `pantry` for searching `field`
+    pub fn from_filter(
+        pantry: &PantryFilter,
+        field: Field,
+        tokenizer: &TextAnalyzer,
+        num_ingredients: Field,
+    ) -> Self {
+        let items = pantry
+            .items
+            .iter()
+            .map(|item| {
+                let mut terms = Vec::new();
+                let mut stream = tokenizer.token_stream(item);
+                while let Some(token) = stream.next() {
+                    terms.push(Term::from_field_text(field, &token.text));
+                }
+                terms
+            })
+            .collect();
+
+        Self::new(items, num_ingredients, pantry.require_all)
+    }
+}
+
+impl Query for PantryQuery {
+    fn weight(&self, searcher: &Searcher, _scoring_enabled: bool) -> Result<Box<dyn Weight>> {
+        let mut items = Vec::with_capacity(self.items.len());
+        for terms in &self.items {
+            let item = BooleanQuery::from(
+                terms
+                    .iter()
+                    .map(|term| {
+                        let query: Box<dyn Query> =
+                            Box::new(TermQuery::new(term.clone(), IndexRecordOption::Basic));
+                        (Occur::Must, query)
+                    })
+                    .collect::<Vec<_>>(),
+            );
+            // Only whether an item is there matters
+            items.push(item.weight(searcher, false)?);
+        }
+
+        Ok(Box::new(PantryWeight {
+            items,
+            num_ingredients: self.num_ingredients,
+            require_all: self.require_all,
+        }))
+    }
+
+    fn query_terms(&self, terms: &mut BTreeSet<Term>) {
+        terms.extend(self.items.iter().flatten().cloned());
+    }
+}
+
+struct PantryWeight {
+    items: Vec<Box<dyn Weight>>,
+    num_ingredients: Field,
+    require_all: bool,
+}
+
+impl Weight for PantryWeight {
+    fn scorer(&self, reader: &SegmentReader, boost: f32) -> Result<Box<dyn Scorer>> {
+        let num_ingredients = reader
+            .fast_fields()
+            .u64(self.num_ingredients)
+            .ok_or_else(|| {
+                TantivyError::SchemaError(format!(
+                    "{:?} is not a u64 fast field",
+                    self.num_ingredients
+                ))
+            })?;
+
+        let mut items = Vec::with_capacity(self.items.len());
+        for item in &self.items {
+            items.push(item.scorer(reader, 1.0)?);
+        }
+
+        Ok(Box::new(PantryScorer::new(
+            items,
+            num_ingredients,
+            self.require_all,
+            boost,
+        )))
+    }
+
+    fn explain(&self, reader: &SegmentReader, doc: DocId) -> Result<Explanation> {
+        let mut scorer = self.scorer(reader, 1.0)?;
+
+        if scorer.doc() > doc || scorer.seek(doc) != doc {
+            return Err(TantivyError::InvalidArgument("Not a match".to_owned()));
+        }
+
+        Ok(Explanation::new("PantryQuery", scorer.score()))
+    }
+}
+
+struct PantryScorer {
+    // Always positioned past `doc`
+    items: Vec<Box<dyn Scorer>>,
+    num_ingredients: FastFieldReader<u64>,
+    require_all: bool,
+    boost: Score,
+    doc: DocId,
+    score: Score,
+}
+
+impl PantryScorer {
+    fn new(
+        items: Vec<Box<dyn Scorer>>,
+        num_ingredients: FastFieldReader<u64>,
+        require_all: bool,
+        boost: Score,
+    ) -> Self {
+        let mut scorer = Self {
+            items,
+            num_ingredients,
+            require_all,
+            boost,
+            doc: 0,
+            score: 0.0,
+        };
+
+        // Scorers start positioned at their first match
+        scorer.next_match();
+        scorer
+    }
+
+    fn next_match(&mut self) -> DocId {
+        loop {
+            let doc = self
+                .items
+                .iter()
+                .map(|item| item.doc())
+                .min()
+                .unwrap_or(TERMINATED);
+
+            if doc == TERMINATED {
+                self.doc = TERMINATED;
+                return TERMINATED;
+            }
+
+            let mut found = 0;
+            for item in self.items.iter_mut().filter(|item| item.doc() == doc) {
+                found += 1;
+                item.advance();
+            }
+
+            let needed = self.num_ingredients.get(doc);
+            if needed == 0 || (self.require_all && found < needed) {
+                continue;
+            }
+
+            self.doc = doc;
+            self.score = self.boost * found.min(needed) as Score / needed as Score;
+            return doc;
+        }
+    }
+}
+
+impl Scorer for PantryScorer {
+    fn score(&mut self) -> Score {
+        self.score
+    }
+}
+
+impl DocSet for PantryScorer {
+    fn advance(&mut self) -> DocId {
+        self.next_match()
+    }
+
+    fn doc(&self) -> DocId {
+        self.doc
+    }
+
+    fn size_hint(&self) -> u32 {
+        self.items
+            .iter()
+            .map(|item| item.size_hint())
+            .fold(0, u32::saturating_add)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use tantivy::{
+        collector::{Count, TopDocs},
+        doc,
+        schema::{SchemaBuilder, Value, FAST, STORED, TEXT},
+        tokenizer::SimpleTokenizer,
+        Index,
+    };
+
+    #[test]
+    fn ranks_by_coverage() -> Result<()> {
+        let mut builder = SchemaBuilder::new();
+        let name = builder.add_text_field("name", STORED);
+        let ingredients = builder.add_text_field("ingredients", TEXT);
+        let num_ingredients = builder.add_u64_field("num_ingredients", FAST);
+        let index = Index::create_in_ram(builder.build());
+        let mut writer = index.writer_with_num_threads(1, 3_000_000)?;
+
+        for (recipe, lines) in &[
+            ("omelette", vec!["3 eggs", "salt"]),
+            ("mayo", vec!["1 egg yolk", "olive oil", "salt", "lemon"]),
+            ("bread", vec!["flour", "water", "salt", "yeast"]),
+            ("pancakes", vec!["flour", "3 eggs", "milk"]),
+        ] {
+            let mut doc = doc!(
+                name => *recipe,
+                num_ingredients => lines.len() as u64
+            );
+            for line in lines {
+                doc.add_text(ingredients, line);
+            }
+            writer.add_document(doc);
+        }
+        writer.add_document(doc!(name => "nothing", num_ingredients => 0u64));
+        writer.commit()?;
+
+        let searcher = index.reader()?.searcher();
+        let tokenizer = TextAnalyzer::from(SimpleTokenizer);
+
+        let search = |items: &[&str], require_all| -> Result<Vec<(String, Score)>> {
+            let pantry = PantryFilter {
+                items: items.iter().map(|item| (*item).to_owned()).collect(),
+                require_all,
+            };
+            let query = PantryQuery::from_filter(&pantry, ingredients, &tokenizer, num_ingredients);
+
+            let mut found = Vec::new();
+            for (score, addr) in searcher.search(&query, &TopDocs::with_limit(10))? {
+                found.push((recipe_name(&searcher.doc(addr)?, name), score));
+            }
+            Ok(found)
+        };
+
+        assert_eq!(
+            vec![
+                ("omelette".to_owned(), 1.0),
+                ("mayo".to_owned(), 0.5),
+                ("pancakes".to_owned(), 1.0 / 3.0),
+                ("bread".to_owned(), 0.25)
+            ],
+            search(&["eggs", "salt", "olive oil"], false)?
+        );
+
+        // Items of many words cover a single ingredient
+        assert_eq!(
+            vec![("mayo".to_owned(), 0.75)],
+            search(&["olive oil", "lemon", "egg yolk", "pepper"], false)?
+        );
+
+        let mut complete = search(&["eggs", "salt", "flour", "milk", "lemon"], true)?;
+        complete.sort_by(|a, b| a.0.cmp(&b.0));
+        assert_eq!(
+            vec![("omelette".to_owned(), 1.0), ("pancakes".to_owned(), 1.0)],
+            complete
+        );
+
+        let nothing = PantryQuery::new(vec![Vec::new()], num_ingredients, false);
+        assert_eq!(0, searcher.search(&nothing, &Count)?);
+
+        Ok(())
+    }
+
+    fn recipe_name(doc: &tantivy::Document, name: Field) -> String {
+        match doc.get_first(name) {
+            Some(Value::Str(text)) => text.clone(),
+            _ => panic!("Document without a name"),
+        }
+    }
+}
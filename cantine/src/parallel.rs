@@ -0,0 +1,74 @@
+use rayon::prelude::*;
+
+use tantivy::{collector::Collector, query::Query, Result, Searcher};
+
+/// Like `Searcher::search`, but collecting every segment of the index
+/// at the same time, in rayon's global pool. Worth it for indexes with
+/// many segments, where collection takes long enough to dwarf the cost
+/// of spreading the work
+pub fn search<C: Collector>(
+    searcher: &Searcher,
+    query: &dyn Query,
+    collector: &C,
+) -> Result<C::Fruit> {
+    let weight = query.weight(searcher, collector.requires_scoring())?;
+
+    let fruits = searcher
+        .segment_readers()
+        .par_iter()
+        .enumerate()
+        .map(|(segment_ord, reader)| {
+            collector.collect_segment(weight.as_ref(), segment_ord as u32, reader)
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    collector.merge_fruits(fruits)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use tantivy::{
+        collector::{Count, TopDocs},
+        doc,
+        query::AllQuery,
+        schema::{SchemaBuilder, FAST, INDEXED},
+        Index,
+    };
+
+    #[test]
+    fn same_fruits_as_sequential_collection() -> Result<()> {
+        let mut builder = SchemaBuilder::new();
+        let id = builder.add_u64_field("id", INDEXED | FAST);
+        let index = Index::create_in_ram(builder.build());
+        let mut writer = index.writer_with_num_threads(1, 3_000_000)?;
+
+        for value in 0..100u64 {
+            writer.add_document(doc!(id => value));
+            // Plenty of segments
+            if value % 10 == 9 {
+                writer.commit()?;
+            }
+        }
+
+        let searcher = index.reader()?.searcher();
+        assert!(searcher.segment_readers().len() > 1);
+
+        let collector = (Count, TopDocs::with_limit(5).order_by_u64_field(id));
+        let (count, top) = search(&searcher, &AllQuery, &collector)?;
+        assert_eq!(
+            searcher.search(&AllQuery, &collector)?,
+            (count, top.clone())
+        );
+        assert_eq!(100, count);
+        assert_eq!(
+            vec![99, 98, 97, 96, 95],
+            top.into_iter()
+                .map(|(value, _addr)| value)
+                .collect::<Vec<_>>()
+        );
+
+        Ok(())
+    }
+}
@@ -0,0 +1,279 @@
+use std::{
+    collections::HashMap,
+    fs,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+    sync::{Arc, RwLock},
+};
+
+use tantivy::{IndexWriter, Term};
+
+use crate::{
+    authors,
+    database::{Checkpoint, DatabaseReader, DatabaseWriter},
+    error::{Error, Result},
+    index::RecipeIndex,
+    model::{Popularity, Recipe, RecipeId},
+};
+
+const POPULARITY_KEYSPACE: &str = "popularity";
+
+// A save says more about a recipe than a click
+const SAVE_WEIGHT: u64 = 5;
+// How much popularity may move a recipe up: the score of one with a
+// popularity of `p` is multiplied by `1 + ln(1 + p) * WEIGHT`
+const POPULARITY_WEIGHT: f32 = 0.1;
+
+/// A single number out of the counts of a recipe
+pub fn popularity_of(counts: &Popularity) -> u64 {
+    counts
+        .clicks
+        .saturating_add(counts.saves.saturating_mul(SAVE_WEIGHT))
+}
+
+/// The relevance `score` of a recipe, boosted by its popularity
+pub fn boost(score: f32, popularity: u64) -> f32 {
+    score * (1.0 + (popularity as f32).ln_1p() * POPULARITY_WEIGHT)
+}
+
+/// Where the popularity counts of the recipe database at `db_path`
+/// are kept
+pub fn popularity_path(db_path: &Path) -> PathBuf {
+    db_path.join(POPULARITY_KEYSPACE)
+}
+
+/// Opens the popularity keyspace for appending, creating it if needed
+pub fn open_writer(db_path: &Path) -> Result<DatabaseWriter<Popularity>> {
+    let path = popularity_path(db_path);
+    fs::create_dir_all(&path)?;
+
+    match DatabaseWriter::open(&path) {
+        Err(Error::Io(err)) if err.kind() == ErrorKind::NotFound => DatabaseWriter::new(&path),
+        other => other,
+    }
+}
+
+/// How far the popularity keyspace goes, for snapshots and followers
+/// to copy. None if there's no popularity keyspace. Counts come from
+/// elsewhere, so like `Checkpoint::of` it's only consistent as of
+/// their last flush
+pub fn checkpoint(db_path: &Path) -> Result<Option<Checkpoint>> {
+    let path = popularity_path(db_path);
+    if !path.is_dir() {
+        return Ok(None);
+    }
+
+    Ok(Some(Checkpoint::of(&path)?))
+}
+
+/// Reads the latest popularity of every recipe that has any. Empty
+/// if there's no popularity keyspace
+pub fn load_all(db_path: &Path) -> Result<HashMap<RecipeId, u64>> {
+    let path = popularity_path(db_path);
+    if !path.is_dir() {
+        return Ok(HashMap::new());
+    }
+
+    let reader = DatabaseReader::<Popularity>::open(&path)?;
+    let ids = reader.ids().copied().collect::<Vec<_>>();
+    Ok(reader
+        .find_many(ids)?
+        .into_iter()
+        .map(|(id, counts)| (id, popularity_of(&counts)))
+        .collect())
+}
+
+/// The popularity keyspace, in memory, for the rescoring stage of
+/// searches to consult. Counts appended to the keyspace are picked up
+/// by `refresh`
+pub struct PopularityStore {
+    db_path: PathBuf,
+    state: RwLock<StoreState>,
+}
+
+struct StoreState {
+    checkpoint: Checkpoint,
+    counts: Arc<HashMap<RecipeId, u64>>,
+}
+
+impl PopularityStore {
+    pub fn open(db_path: &Path) -> Result<Self> {
+        let checkpoint = Checkpoint::of(popularity_path(db_path))?;
+        let counts = load_all(db_path)?;
+
+        Ok(Self {
+            db_path: db_path.to_owned(),
+            state: RwLock::new(StoreState {
+                checkpoint,
+                counts: Arc::new(counts),
+            }),
+        })
+    }
+
+    /// Reloads the counts if the keyspace changed since they were
+    /// loaded. Cheap otherwise, so it's fine to call before every
+    /// search. Returns whether it reloaded
+    pub fn refresh(&self) -> Result<bool> {
+        let on_disk = Checkpoint::of(popularity_path(&self.db_path))?;
+        if on_disk == self.state.read().expect("lock not poisoned").checkpoint {
+            return Ok(false);
+        }
+
+        let counts = load_all(&self.db_path)?;
+        log::info!("Loaded the popularity of {} recipes", counts.len());
+
+        let mut state = self.state.write().expect("lock not poisoned");
+        state.checkpoint = on_disk;
+        state.counts = Arc::new(counts);
+        Ok(true)
+    }
+
+    pub fn get(&self, recipe_id: RecipeId) -> Option<u64> {
+        self.counts().get(&recipe_id).copied()
+    }
+
+    /// Every popularity known as of now, unaffected by refreshes
+    pub fn counts(&self) -> Arc<HashMap<RecipeId, u64>> {
+        self.state.read().expect("lock not poisoned").counts.clone()
+    }
+}
+
+/// Bakes the popularity of every recipe in the keyspace into the
+/// `popularity` fast field of its document, so that searches without
+/// a `PopularityStore` (or recipes it lacks) can use it. Meant to run
+/// periodically, since anything else that reindexes a recipe leaves
+/// it at zero until the next run.
+///
+/// Commits every `commit_every` recipes and once more when done.
+/// Returns how many recipes were reindexed
+pub fn bake(
+    db_path: &Path,
+    writer: &mut IndexWriter,
+    recipe_index: &RecipeIndex,
+    commit_every: usize,
+) -> Result<usize> {
+    let mut counts = load_all(db_path)?.into_iter().collect::<Vec<_>>();
+    counts.sort();
+
+    let reader = DatabaseReader::<Recipe>::open(db_path)?;
+    let authors = authors::load_all(db_path)?;
+
+    let mut num_baked = 0;
+    for (id, popularity) in counts {
+        let recipe = match reader.find_by_id(id) {
+            Some(found) => found?,
+            None => continue,
+        };
+
+        let mut doc = recipe_index
+            .make_document_with_author(&recipe, recipe.author_id.and_then(|id| authors.get(&id)));
+        recipe_index.add_popularity(&mut doc, popularity);
+
+        writer.delete_term(Term::from_field_u64(recipe_index.id, id));
+        writer.add_document(doc);
+
+        num_baked += 1;
+        if num_baked % commit_every == 0 {
+            writer.commit()?;
+            log::info!("Popularity: {} recipes baked so far", num_baked);
+        }
+    }
+
+    writer.commit()?;
+
+    Ok(num_baked)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use tantivy::{collector::Count, query::RangeQuery, schema::SchemaBuilder, Index};
+    use uuid::Uuid;
+
+    fn recipe(recipe_id: RecipeId) -> Recipe {
+        Recipe::sample(recipe_id, &format!("Recipe {}", recipe_id))
+    }
+
+    fn counts(recipe_id: RecipeId, clicks: u64, saves: u64) -> Popularity {
+        Popularity {
+            uuid: Uuid::new_v4(),
+            recipe_id,
+            clicks,
+            saves,
+        }
+    }
+
+    #[test]
+    fn store_picks_up_new_counts() -> Result<()> {
+        let db_dir = tempfile::tempdir()?;
+
+        let store = PopularityStore::open(db_dir.path())?;
+        assert_eq!(None, store.get(1));
+        assert!(!store.refresh()?);
+
+        let mut writer = open_writer(db_dir.path())?;
+        writer.append(&counts(1, 10, 0))?;
+        writer.append(&counts(2, 1, 2))?;
+        writer.flush()?;
+
+        let before = store.counts();
+        assert!(store.refresh()?);
+        assert!(before.is_empty());
+        assert_eq!(Some(10), store.get(1));
+        assert_eq!(Some(11), store.get(2));
+
+        // Newer counts replace older ones
+        writer.append(&counts(1, 20, 1))?;
+        writer.flush()?;
+        assert!(store.refresh()?);
+        assert_eq!(Some(25), store.get(1));
+        assert!(!store.refresh()?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn popular_recipes_get_boosted() {
+        assert!((boost(2.0, 0) - 2.0).abs() < 1e-6);
+        assert!(boost(2.0, 100) > boost(2.0, 10));
+        // But not by that much
+        assert!(boost(1.0, 1_000_000) < 2.5);
+    }
+
+    #[test]
+    fn bake_fills_in_the_fast_field() -> Result<()> {
+        let db_dir = tempfile::tempdir()?;
+
+        let mut builder = SchemaBuilder::new();
+        let recipe_index = RecipeIndex::from(&mut builder);
+        let index = Index::create_in_ram(builder.build());
+        let mut writer = index.writer_with_num_threads(1, 3_000_000)?;
+
+        let mut db = DatabaseWriter::new(db_dir.path())?;
+        for id in 0..10 {
+            let recipe = recipe(id);
+            db.append(&recipe)?;
+            writer.add_document(recipe_index.make_document(&recipe));
+        }
+        drop(db);
+        writer.commit()?;
+
+        let mut popularity = open_writer(db_dir.path())?;
+        for id in 0..5 {
+            popularity.append(&counts(id, id + 1, 0))?;
+        }
+        // Unknown recipes are skipped
+        popularity.append(&counts(42, 1, 0))?;
+        drop(popularity);
+
+        assert_eq!(5, bake(db_dir.path(), &mut writer, &recipe_index, 2)?);
+
+        let searcher = index.reader()?.searcher();
+        assert_eq!(10, searcher.num_docs());
+        let popular = RangeQuery::new_u64(recipe_index.popularity, 1..std::u64::MAX);
+        assert_eq!(5, searcher.search(&popular, &Count)?);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,345 @@
+use std::{
+    collections::{BTreeSet, HashMap},
+    fs,
+    io::{self, ErrorKind},
+    path::Path,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    error::Result,
+    model::{Recipe, RecipeCard},
+};
+
+/// The parts of a recipe that may be left out of responses, be it
+/// as a `RecipeCard` or in full. The uuid is always there: without
+/// it a recipe is of no use
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum CardField {
+    Name,
+    CrawlUrl,
+    NumIngredients,
+    InstructionsLength,
+    Image,
+    /// Along with whether it's an estimate
+    TotalTime,
+    Calories,
+    Author,
+    MatchedVariant,
+    Matched,
+    /// Only shown by full recipes, not cards
+    Ingredients,
+    /// Only shown by full recipes, not cards
+    Instructions,
+}
+
+impl CardField {
+    pub const VALUES: [Self; 12] = [
+        CardField::Name,
+        CardField::CrawlUrl,
+        CardField::NumIngredients,
+        CardField::InstructionsLength,
+        CardField::Image,
+        CardField::TotalTime,
+        CardField::Calories,
+        CardField::Author,
+        CardField::MatchedVariant,
+        CardField::Matched,
+        CardField::Ingredients,
+        CardField::Instructions,
+    ];
+}
+
+/// Which fields of the recipe cards a response may show
+#[derive(Debug, Clone, PartialEq)]
+pub struct Projection(BTreeSet<CardField>);
+
+impl Projection {
+    /// Every field
+    pub fn all() -> Self {
+        Self::only(CardField::VALUES.iter().copied())
+    }
+
+    pub fn only<I: IntoIterator<Item = CardField>>(fields: I) -> Self {
+        Projection(fields.into_iter().collect())
+    }
+
+    pub fn allows(&self, field: CardField) -> bool {
+        self.0.contains(&field)
+    }
+
+    /// Only the given fields, out of the ones allowed. Asking for a
+    /// field doesn't make it allowed
+    pub fn narrowed(&self, requested: &[CardField]) -> Self {
+        Self::only(
+            requested
+                .iter()
+                .copied()
+                .filter(|&field| self.allows(field)),
+        )
+    }
+
+    /// Clears whatever `card` has that's not allowed
+    pub fn apply(&self, card: &mut RecipeCard) {
+        for field in CardField::VALUES
+            .iter()
+            .filter(|&&field| !self.allows(field))
+        {
+            match field {
+                CardField::Name => card.name = None,
+                CardField::CrawlUrl => card.crawl_url = None,
+                CardField::NumIngredients => card.num_ingredients = None,
+                CardField::InstructionsLength => card.instructions_length = None,
+                CardField::Image => card.image = None,
+                CardField::TotalTime => {
+                    card.total_time = None;
+                    card.total_time_estimated = false;
+                }
+                CardField::Calories => card.calories = None,
+                CardField::Author => card.author = None,
+                CardField::MatchedVariant => card.matched_variant = None,
+                CardField::Matched => card.matched = None,
+                CardField::Ingredients | CardField::Instructions => {}
+            }
+        }
+    }
+
+    /// Clears whatever `recipe` has that's not allowed, so that
+    /// anything made out of it (full recipes, json-ld, exports)
+    /// doesn't show it either
+    pub fn apply_to_recipe(&self, recipe: &mut Recipe) {
+        for field in CardField::VALUES
+            .iter()
+            .filter(|&&field| !self.allows(field))
+        {
+            let features = &mut recipe.features;
+            match field {
+                CardField::Name => recipe.name.clear(),
+                CardField::CrawlUrl => recipe.crawl_url.clear(),
+                CardField::NumIngredients => features.num_ingredients = 0,
+                CardField::InstructionsLength => features.instructions_length = 0,
+                CardField::Image => recipe.images.clear(),
+                CardField::TotalTime => {
+                    features.prep_time = None;
+                    features.cook_time = None;
+                    features.total_time = None;
+                    features.total_time_estimate = None;
+                }
+                // Along with the rest of the nutrition facts
+                CardField::Calories => {
+                    features.calories = None;
+                    features.fat_content = None;
+                    features.carb_content = None;
+                    features.protein_content = None;
+                }
+                CardField::Author => recipe.author_id = None,
+                CardField::Ingredients => recipe.ingredients.clear(),
+                CardField::Instructions => recipe.instructions.clear(),
+                CardField::MatchedVariant | CardField::Matched => {}
+            }
+        }
+    }
+}
+
+impl Default for Projection {
+    fn default() -> Self {
+        Self::all()
+    }
+}
+
+// Who asks without a role gets what this one is allowed, if it exists
+const DEFAULT_ROLE: &str = "default";
+
+/// The fields each role may see
+#[derive(Debug, Default, Clone)]
+pub struct FieldRoles(HashMap<String, Projection>);
+
+impl FieldRoles {
+    /// Reads a json object mapping each role to the list of fields
+    /// it may see, say: `{"partner": ["name", "image"]}`
+    pub fn load(path: &Path) -> Result<Self> {
+        let contents = fs::read_to_string(path)?;
+        let roles: HashMap<String, Vec<CardField>> = serde_json::from_str(&contents)
+            .map_err(|err| io::Error::new(ErrorKind::InvalidData, err))?;
+
+        Ok(Self::from(roles))
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// What `role` may see. Without a role, that's what the `default`
+    /// role may see, or everything if there's none. Yields None for
+    /// unknown roles
+    pub fn projection(&self, role: Option<&str>) -> Option<Projection> {
+        match role {
+            Some(role) => self.0.get(role).cloned(),
+            None => Some(self.0.get(DEFAULT_ROLE).cloned().unwrap_or_default()),
+        }
+    }
+}
+
+/// The role of each api key. Requests only get a role other than the
+/// default via the key they authenticate with, never by claiming it
+#[derive(Debug, Default, Clone)]
+pub struct ApiKeys(HashMap<String, String>);
+
+impl ApiKeys {
+    /// Reads a json object mapping each api key to its role, say:
+    /// `{"c2VjcmV0IGtleQ": "partner"}`
+    pub fn load(path: &Path) -> Result<Self> {
+        let contents = fs::read_to_string(path)?;
+        let keys: HashMap<String, String> = serde_json::from_str(&contents)
+            .map_err(|err| io::Error::new(ErrorKind::InvalidData, err))?;
+
+        Ok(ApiKeys(keys))
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// The role of whoever holds `key`. None for unknown keys
+    pub fn role(&self, key: &str) -> Option<&str> {
+        self.0.get(key).map(String::as_str)
+    }
+}
+
+impl From<HashMap<String, String>> for ApiKeys {
+    fn from(keys: HashMap<String, String>) -> Self {
+        ApiKeys(keys)
+    }
+}
+
+impl From<HashMap<String, Vec<CardField>>> for FieldRoles {
+    fn from(roles: HashMap<String, Vec<CardField>>) -> Self {
+        FieldRoles(
+            roles
+                .into_iter()
+                .map(|(role, fields)| (role, Projection::only(fields)))
+                .collect(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::model::{Features, Recipe};
+
+    fn card() -> RecipeCard {
+        RecipeCard::from(recipe())
+    }
+
+    fn recipe() -> Recipe {
+        Recipe {
+            crawl_url: "https://example.com/focaccia".to_owned(),
+            ingredients: vec!["flour".to_owned(), "olive oil".to_owned()],
+            instructions: vec!["knead".to_owned()],
+            images: vec!["https://example.com/focaccia.jpg".to_owned()],
+            features: Features {
+                num_ingredients: 2,
+                total_time_estimate: Some(90),
+                calories: Some(250),
+                ..Features::default()
+            },
+            ..Recipe::sample(1, "Focaccia")
+        }
+    }
+
+    #[test]
+    fn only_allowed_fields_remain() {
+        let mut card = card();
+        let uuid = card.uuid;
+
+        Projection::only(vec![CardField::Name, CardField::Calories]).apply(&mut card);
+
+        assert_eq!(uuid, card.uuid);
+        assert_eq!(Some("Focaccia"), card.name.as_deref());
+        assert_eq!(Some(250), card.calories);
+        assert_eq!(None, card.crawl_url);
+        assert_eq!(None, card.num_ingredients);
+        assert_eq!(None, card.image);
+        assert_eq!(None, card.total_time);
+        assert!(!card.total_time_estimated);
+
+        let mut untouched = self::card();
+        Projection::all().apply(&mut untouched);
+        assert!(untouched.total_time_estimated);
+        assert!(untouched.image.is_some());
+    }
+
+    #[test]
+    fn recipes_lose_what_is_not_allowed() {
+        let mut recipe = recipe();
+        let uuid = recipe.uuid;
+
+        Projection::only(vec![CardField::Name, CardField::Image]).apply_to_recipe(&mut recipe);
+
+        assert_eq!(uuid, recipe.uuid);
+        assert_eq!("Focaccia", recipe.name);
+        assert_eq!(1, recipe.images.len());
+        assert!(recipe.crawl_url.is_empty());
+        assert!(recipe.ingredients.is_empty());
+        assert!(recipe.instructions.is_empty());
+        assert_eq!(0, recipe.features.num_ingredients);
+        assert_eq!(None, recipe.features.total_time_estimate);
+        assert_eq!(None, recipe.features.calories);
+
+        let mut untouched = self::recipe();
+        Projection::all().apply_to_recipe(&mut untouched);
+        assert_eq!(self::recipe(), untouched);
+    }
+
+    #[test]
+    fn api_keys() {
+        let mut config = HashMap::new();
+        config.insert("secret".to_owned(), "partner".to_owned());
+        let keys = ApiKeys::from(config);
+
+        assert_eq!(Some("partner"), keys.role("secret"));
+        assert_eq!(None, keys.role("partner"));
+    }
+
+    #[test]
+    fn requests_can_only_narrow_down() {
+        let allowed = Projection::only(vec![CardField::Name, CardField::Image]);
+        assert_eq!(
+            Projection::only(vec![CardField::Name]),
+            allowed.narrowed(&[CardField::Name, CardField::CrawlUrl])
+        );
+    }
+
+    #[test]
+    fn roles() {
+        let mut config = HashMap::new();
+        config.insert("partner".to_owned(), vec![CardField::Name]);
+        let roles = FieldRoles::from(config.clone());
+
+        assert_eq!(
+            Some(Projection::only(vec![CardField::Name])),
+            roles.projection(Some("partner"))
+        );
+        assert_eq!(None, roles.projection(Some("unknown")));
+        assert_eq!(Some(Projection::all()), roles.projection(None));
+
+        config.insert(DEFAULT_ROLE.to_owned(), vec![CardField::Image]);
+        let roles = FieldRoles::from(config);
+        assert_eq!(
+            Some(Projection::only(vec![CardField::Image])),
+            roles.projection(None)
+        );
+    }
+}
@@ -0,0 +1,165 @@
+use serde::{Deserialize, Serialize};
+use tantivy::{
+    collector::{ScoreSegmentTweaker, ScoreTweaker},
+    fastfield::FastFieldReader,
+    schema::Field,
+    DocId, Result, Score, SegmentReader, TantivyError,
+};
+
+use crate::clock::SECONDS_PER_DAY;
+
+/// How the recency of a recipe combines with its relevance
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum BoostMode {
+    /// `score * (1 + weight * decay)`
+    Multiply,
+    /// `score + weight * decay`
+    Add,
+}
+
+impl Default for BoostMode {
+    fn default() -> Self {
+        BoostMode::Multiply
+    }
+}
+
+/// Favors recent recipes when ranking by relevance. The boost decays
+/// exponentially with age: a recipe `half_life_days` old gets half of
+/// the boost of a brand new one
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct RecencyBoost {
+    pub half_life_days: f32,
+    #[serde(default = "default_weight")]
+    pub weight: f32,
+    #[serde(default)]
+    pub mode: BoostMode,
+}
+
+fn default_weight() -> f32 {
+    1.0
+}
+
+impl RecencyBoost {
+    pub fn is_valid(&self) -> bool {
+        self.half_life_days.is_finite()
+            && self.half_life_days > 0.0
+            && self.weight.is_finite()
+            && self.weight >= 0.0
+    }
+
+    /// From 1 (as of `now` or later) towards 0 (long ago)
+    pub fn decay(&self, timestamp: u64, now: u64) -> f64 {
+        let age_days = now.saturating_sub(timestamp) as f64 / SECONDS_PER_DAY as f64;
+        0.5f64.powf(age_days / f64::from(self.half_life_days))
+    }
+
+    pub fn apply(&self, score: Score, timestamp: u64, now: u64) -> f64 {
+        let boost = f64::from(self.weight) * self.decay(timestamp, now);
+        match self.mode {
+            BoostMode::Multiply => f64::from(score) * (1.0 + boost),
+            BoostMode::Add => f64::from(score) + boost,
+        }
+    }
+
+    /// A score tweaker reading timestamps (in seconds since the epoch)
+    /// from a u64 fast field. Documents without one are as old as it
+    /// gets
+    pub fn tweaker(self, field: Field, now: u64) -> RecencyTweaker {
+        RecencyTweaker {
+            boost: self,
+            field,
+            now,
+        }
+    }
+}
+
+/// See `RecencyBoost::tweaker`
+pub struct RecencyTweaker {
+    boost: RecencyBoost,
+    field: Field,
+    now: u64,
+}
+
+impl ScoreTweaker<f64> for RecencyTweaker {
+    type Child = SegmentRecencyTweaker;
+
+    fn segment_tweaker(&self, reader: &SegmentReader) -> Result<Self::Child> {
+        let timestamps = reader.fast_fields().u64(self.field).ok_or_else(|| {
+            TantivyError::SchemaError(format!("{:?} is not a u64 fast field", self.field))
+        })?;
+
+        Ok(SegmentRecencyTweaker {
+            boost: self.boost,
+            timestamps,
+            now: self.now,
+        })
+    }
+}
+
+pub struct SegmentRecencyTweaker {
+    boost: RecencyBoost,
+    timestamps: FastFieldReader<u64>,
+    now: u64,
+}
+
+impl ScoreSegmentTweaker<f64> for SegmentRecencyTweaker {
+    fn score(&mut self, doc: DocId, score: Score) -> f64 {
+        self.boost.apply(score, self.timestamps.get(doc), self.now)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NOW: u64 = 1_600_000_000;
+
+    fn boost(mode: BoostMode) -> RecencyBoost {
+        RecencyBoost {
+            half_life_days: 30.0,
+            weight: 1.0,
+            mode,
+        }
+    }
+
+    #[test]
+    fn decays_by_half_life() {
+        let boost = boost(BoostMode::Multiply);
+
+        assert!((boost.decay(NOW, NOW) - 1.0).abs() < 1e-9);
+        assert!((boost.decay(NOW + 10, NOW) - 1.0).abs() < 1e-9);
+        assert!((boost.decay(NOW - 30 * SECONDS_PER_DAY, NOW) - 0.5).abs() < 1e-9);
+        assert!((boost.decay(NOW - 60 * SECONDS_PER_DAY, NOW) - 0.25).abs() < 1e-9);
+        assert!(boost.decay(0, NOW) < 1e-9);
+    }
+
+    #[test]
+    fn boost_modes() {
+        let month_ago = NOW - 30 * SECONDS_PER_DAY;
+
+        assert!((boost(BoostMode::Multiply).apply(2.0, month_ago, NOW) - 3.0).abs() < 1e-9);
+        assert!((boost(BoostMode::Add).apply(2.0, month_ago, NOW) - 2.5).abs() < 1e-9);
+
+        // Newer wins ties, but not by much
+        let fresh = boost(BoostMode::Multiply).apply(1.0, NOW, NOW);
+        let stale = boost(BoostMode::Multiply).apply(1.0, 0, NOW);
+        assert!(fresh > stale && fresh <= 2.0 * stale + 1e-9);
+    }
+
+    #[test]
+    fn validation() {
+        assert!(boost(BoostMode::Add).is_valid());
+        for (half_life_days, weight) in
+            &[(0.0, 1.0), (-1.0, 1.0), (30.0, -1.0), (30.0, std::f32::NAN)]
+        {
+            let boost = RecencyBoost {
+                half_life_days: *half_life_days,
+                weight: *weight,
+                mode: BoostMode::Add,
+            };
+            assert!(!boost.is_valid());
+        }
+    }
+}
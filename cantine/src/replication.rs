@@ -0,0 +1,221 @@
+use std::{
+    collections::BTreeSet,
+    fs,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+    sync::{Arc, RwLock},
+};
+
+use serde::{Deserialize, Serialize};
+use tantivy::{Index, Result};
+
+use crate::{
+    authors,
+    database::{Checkpoint, Chunk},
+    popularity,
+    snapshot::{capture_index, serialize_meta, write_synced, DATABASE_DIR, INDEX_DIR, META_FILE},
+};
+
+/// How far a follower got
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq)]
+pub struct Position {
+    pub recipes: Checkpoint,
+    pub authors: Checkpoint,
+    #[serde(default)]
+    pub popularity: Checkpoint,
+    /// The opstamp of the index commit the follower has. None
+    /// before the first one
+    pub opstamp: Option<u64>,
+    /// The files of that commit
+    pub index_files: BTreeSet<PathBuf>,
+}
+
+/// What a follower needs to catch up with the writer: everything
+/// appended to the database since its position and, if there's a
+/// newer one, the last index commit
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Changes {
+    recipes: Chunk,
+    authors: Chunk,
+    /// None if the writer has no popularity keyspace
+    #[serde(default)]
+    popularity: Option<Chunk>,
+    index: Option<IndexCommit>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct IndexCommit {
+    opstamp: u64,
+    meta: String,
+    files: BTreeSet<PathBuf>,
+    /// Only the files the follower doesn't have yet
+    new_files: Vec<(PathBuf, Vec<u8>)>,
+}
+
+impl Changes {
+    pub fn is_empty(&self) -> bool {
+        self.recipes.is_empty()
+            && self.authors.is_empty()
+            && self.popularity.as_ref().map_or(true, Chunk::is_empty)
+            && self.index.is_none()
+    }
+}
+
+/// The database checkpoints as of the last flush by the writer
+pub(crate) type Flushed = Arc<RwLock<(Checkpoint, Checkpoint)>>;
+
+/// The writer side of replication, as created via
+/// `Cantine::replication_source`. Can be moved to other threads
+/// (say: the ones serving followers) while the writer keeps going.
+#[derive(Clone)]
+pub struct ReplicationSource {
+    db_path: PathBuf,
+    index: Index,
+    flushed: Flushed,
+}
+
+impl ReplicationSource {
+    pub(crate) fn new(db_path: &Path, index: Index, flushed: Flushed) -> Self {
+        Self {
+            db_path: db_path.to_owned(),
+            index,
+            flushed,
+        }
+    }
+
+    /// Yields what a follower at `position` is missing
+    pub fn changes_since(&self, position: &Position) -> Result<Changes> {
+        // The index goes first: recipes only get indexed after
+        // they're flushed, so the database is never behind it
+        let (meta, files) = capture_index(&self.index)?;
+
+        let index = if Some(meta.opstamp) == position.opstamp {
+            None
+        } else {
+            let new_files = files
+                .iter()
+                .filter(|(path, _source)| !position.index_files.contains(path))
+                .map(|(path, source)| (path.clone(), source.as_slice().to_vec()))
+                .collect();
+
+            Some(IndexCommit {
+                opstamp: meta.opstamp,
+                meta: serialize_meta(&meta)?,
+                files: files.into_iter().map(|(path, _source)| path).collect(),
+                new_files,
+            })
+        };
+
+        let (recipes, authors) = *self.flushed.read().expect("lock not poisoned");
+
+        // Not written by the writer, so there's no flushed checkpoint
+        let popularity = match popularity::checkpoint(&self.db_path)? {
+            Some(checkpoint) => Some(checkpoint.read_since(
+                popularity::popularity_path(&self.db_path),
+                &position.popularity,
+            )?),
+            None => None,
+        };
+
+        Ok(Changes {
+            recipes: recipes.read_since(&self.db_path, &position.recipes)?,
+            authors: authors.read_since(authors::authors_path(&self.db_path), &position.authors)?,
+            popularity,
+            index,
+        })
+    }
+}
+
+/// The follower side of replication: a read-only copy of the
+/// database and index, laid out like the ones created by `load`,
+/// that's kept up to date by applying `Changes` from the writer's
+/// `ReplicationSource`.
+///
+/// Index readers pick up applied commits like they would from a
+/// local writer. Database readers need to be reopened to see new
+/// recipes.
+pub struct ReplicationSink {
+    db_path: PathBuf,
+    index_path: PathBuf,
+    position: Position,
+}
+
+impl ReplicationSink {
+    /// Opens the follower copy at `base_dir`, creating it if needed
+    pub fn open<P: AsRef<Path>>(base_dir: P) -> Result<Self> {
+        let db_path = base_dir.as_ref().join(DATABASE_DIR);
+        let index_path = base_dir.as_ref().join(INDEX_DIR);
+        fs::create_dir_all(authors::authors_path(&db_path))?;
+        fs::create_dir_all(&index_path)?;
+
+        let mut position = Position {
+            recipes: Checkpoint::of(&db_path)?,
+            authors: Checkpoint::of(authors::authors_path(&db_path))?,
+            popularity: Checkpoint::of(popularity::popularity_path(&db_path))?,
+            ..Position::default()
+        };
+
+        if index_path.join(META_FILE).exists() {
+            let meta = Index::open_in_dir(&index_path)?.load_metas()?;
+            position.opstamp = Some(meta.opstamp);
+            position.index_files = meta
+                .segments
+                .iter()
+                .flat_map(|segment| segment.list_files())
+                .collect();
+        }
+
+        Ok(Self {
+            db_path,
+            index_path,
+            position,
+        })
+    }
+
+    /// What to ask the `ReplicationSource` for
+    pub fn position(&self) -> &Position {
+        &self.position
+    }
+
+    /// Applies changes taken from this sink's `position`
+    pub fn apply(&mut self, changes: &Changes) -> Result<()> {
+        changes.recipes.append_to(&self.db_path)?;
+        self.position.recipes = changes.recipes.until();
+
+        changes
+            .authors
+            .append_to(authors::authors_path(&self.db_path))?;
+        self.position.authors = changes.authors.until();
+
+        if let Some(chunk) = &changes.popularity {
+            let popularity_path = popularity::popularity_path(&self.db_path);
+            fs::create_dir_all(&popularity_path)?;
+            chunk.append_to(&popularity_path)?;
+            self.position.popularity = chunk.until();
+        }
+
+        if let Some(commit) = &changes.index {
+            for (path, bytes) in &commit.new_files {
+                write_synced(&self.index_path.join(path), bytes)?;
+            }
+
+            // Readers watch the meta file, so it's replaced at once
+            let staged_meta = self.index_path.join(META_FILE).with_extension("new");
+            write_synced(&staged_meta, commit.meta.as_bytes())?;
+            fs::rename(&staged_meta, self.index_path.join(META_FILE))?;
+
+            // Open readers keep working with the files they have
+            for path in self.position.index_files.difference(&commit.files) {
+                match fs::remove_file(self.index_path.join(path)) {
+                    Err(err) if err.kind() != ErrorKind::NotFound => return Err(err.into()),
+                    _ => {}
+                }
+            }
+
+            self.position.opstamp = Some(commit.opstamp);
+            self.position.index_files = commit.files.clone();
+        }
+
+        Ok(())
+    }
+}
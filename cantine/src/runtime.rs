@@ -0,0 +1,287 @@
+use std::ops::Range;
+
+use bincode;
+use serde::{Deserialize, Serialize};
+use tantivy::{
+    fastfield::BytesFastFieldReader,
+    query::{Explanation, Query, Scorer, Weight},
+    schema::Field,
+    DocId, DocSet, Result, Score, Searcher, SegmentReader, TantivyError, TERMINATED,
+};
+
+use crate::model::{Features, NumericFeature};
+
+/// A value computed from two features at search time, so that it
+/// can be used without reindexing
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum RuntimeField {
+    Sum(NumericFeature, NumericFeature),
+    Difference(NumericFeature, NumericFeature),
+    Product(NumericFeature, NumericFeature),
+    Ratio(NumericFeature, NumericFeature),
+}
+
+impl RuntimeField {
+    /// The value for the given features. None if any of the features
+    /// is missing or the result is not a finite number (say: a ratio
+    /// with zero calories)
+    pub fn value(self, features: &Features) -> Option<f64> {
+        let (left, right) = match self {
+            RuntimeField::Sum(left, right)
+            | RuntimeField::Difference(left, right)
+            | RuntimeField::Product(left, right)
+            | RuntimeField::Ratio(left, right) => (left.value(features)?, right.value(features)?),
+        };
+
+        let value = match self {
+            RuntimeField::Sum(..) => left + right,
+            RuntimeField::Difference(..) => left - right,
+            RuntimeField::Product(..) => left * right,
+            RuntimeField::Ratio(..) => left / right,
+        };
+
+        if value.is_finite() {
+            Some(value)
+        } else {
+            None
+        }
+    }
+}
+
+/// Restricts the value of a runtime field to a `[start, end)` range
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct RuntimeFilter {
+    pub field: RuntimeField,
+    pub range: Range<f64>,
+}
+
+impl RuntimeFilter {
+    pub fn matches(&self, features: &Features) -> bool {
+        self.field
+            .value(features)
+            .map_or(false, |value| self.range.contains(&value))
+    }
+}
+
+/// Narrows down the documents matched by `inner` to the ones whose
+/// features (decoded from the bincode bytes fast field) pass every
+/// given runtime filter. Scores are left untouched.
+#[derive(Debug)]
+pub struct RuntimeFilterQuery {
+    inner: Box<dyn Query>,
+    features_field: Field,
+    filters: Vec<RuntimeFilter>,
+}
+
+impl RuntimeFilterQuery {
+    pub fn new(inner: Box<dyn Query>, features_field: Field, filters: Vec<RuntimeFilter>) -> Self {
+        Self {
+            inner,
+            features_field,
+            filters,
+        }
+    }
+}
+
+impl Clone for RuntimeFilterQuery {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.box_clone(),
+            features_field: self.features_field,
+            filters: self.filters.clone(),
+        }
+    }
+}
+
+impl Query for RuntimeFilterQuery {
+    fn weight(&self, searcher: &Searcher, scoring_enabled: bool) -> Result<Box<dyn Weight>> {
+        Ok(Box::new(RuntimeFilterWeight {
+            inner: self.inner.weight(searcher, scoring_enabled)?,
+            features_field: self.features_field,
+            filters: self.filters.clone(),
+        }))
+    }
+}
+
+struct RuntimeFilterWeight {
+    inner: Box<dyn Weight>,
+    features_field: Field,
+    filters: Vec<RuntimeFilter>,
+}
+
+impl Weight for RuntimeFilterWeight {
+    fn scorer(&self, reader: &SegmentReader, boost: f32) -> Result<Box<dyn Scorer>> {
+        let features_reader = reader
+            .fast_fields()
+            .bytes(self.features_field)
+            .ok_or_else(|| {
+                TantivyError::SchemaError(format!(
+                    "{:?} is not a bytes fast field",
+                    self.features_field
+                ))
+            })?;
+
+        Ok(Box::new(RuntimeFilterScorer::new(
+            self.inner.scorer(reader, boost)?,
+            features_reader,
+            self.filters.clone(),
+        )))
+    }
+
+    fn explain(&self, reader: &SegmentReader, doc: DocId) -> Result<Explanation> {
+        let mut scorer = self.scorer(reader, 1.0)?;
+
+        if scorer.doc() > doc || scorer.seek(doc) != doc {
+            return Err(TantivyError::InvalidArgument("Not a match".to_owned()));
+        }
+
+        let mut explanation = Explanation::new("RuntimeFilterQuery", scorer.score());
+        explanation.add_detail(self.inner.explain(reader, doc)?);
+        Ok(explanation)
+    }
+}
+
+struct RuntimeFilterScorer {
+    inner: Box<dyn Scorer>,
+    features_reader: BytesFastFieldReader,
+    filters: Vec<RuntimeFilter>,
+}
+
+impl RuntimeFilterScorer {
+    fn new(
+        inner: Box<dyn Scorer>,
+        features_reader: BytesFastFieldReader,
+        filters: Vec<RuntimeFilter>,
+    ) -> Self {
+        let mut scorer = Self {
+            inner,
+            features_reader,
+            filters,
+        };
+
+        // Scorers start positioned at their first match
+        if scorer.doc() != TERMINATED && !scorer.matches(scorer.doc()) {
+            scorer.advance();
+        }
+
+        scorer
+    }
+
+    fn matches(&self, doc: DocId) -> bool {
+        bincode::deserialize::<Features>(self.features_reader.get_bytes(doc))
+            .map(|features| self.filters.iter().all(|filter| filter.matches(&features)))
+            .unwrap_or(false)
+    }
+}
+
+impl Scorer for RuntimeFilterScorer {
+    fn score(&mut self) -> Score {
+        self.inner.score()
+    }
+}
+
+impl DocSet for RuntimeFilterScorer {
+    fn advance(&mut self) -> DocId {
+        loop {
+            let doc = self.inner.advance();
+            if doc == TERMINATED || self.matches(doc) {
+                return doc;
+            }
+        }
+    }
+
+    fn doc(&self) -> DocId {
+        self.inner.doc()
+    }
+
+    fn size_hint(&self) -> u32 {
+        self.inner.size_hint()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use tantivy::{
+        collector::Count,
+        doc,
+        query::AllQuery,
+        schema::{SchemaBuilder, FAST},
+        Index,
+    };
+
+    fn features(protein_content: Option<f32>, calories: Option<u32>) -> Features {
+        Features {
+            protein_content,
+            calories,
+            ..Features::default()
+        }
+    }
+
+    #[test]
+    fn runtime_field_value() {
+        let ratio = RuntimeField::Ratio(NumericFeature::ProteinContent, NumericFeature::Calories);
+
+        assert_eq!(Some(0.1), ratio.value(&features(Some(10.0), Some(100))));
+        assert_eq!(None, ratio.value(&features(Some(10.0), None)));
+        assert_eq!(None, ratio.value(&features(None, Some(100))));
+        // Division by zero
+        assert_eq!(None, ratio.value(&features(Some(10.0), Some(0))));
+
+        assert_eq!(
+            Some(110.0),
+            RuntimeField::Sum(NumericFeature::ProteinContent, NumericFeature::Calories)
+                .value(&features(Some(10.0), Some(100)))
+        );
+    }
+
+    #[test]
+    fn runtime_filter_query() -> Result<()> {
+        let mut builder = SchemaBuilder::new();
+        let features_field = builder.add_bytes_field("features");
+        let calories = builder.add_u64_field("calories", FAST);
+        let index = Index::create_in_ram(builder.build());
+        let mut writer = index.writer_with_num_threads(1, 3_000_000)?;
+
+        let items = [
+            features(Some(30.0), Some(100)),
+            features(Some(1.0), Some(100)),
+            features(Some(30.0), None),
+            features(Some(20.0), Some(100)),
+            features(Some(30.0), Some(0)),
+        ];
+        for (idx, feat) in items.iter().enumerate() {
+            writer.add_document(doc!(
+                features_field => bincode::serialize(feat).unwrap(),
+                calories => u64::from(feat.calories.unwrap_or(0))
+            ));
+            // Multiple segments
+            if idx == 2 {
+                writer.commit()?;
+            }
+        }
+        writer.commit()?;
+
+        let searcher = index.reader()?.searcher();
+
+        let query = RuntimeFilterQuery::new(
+            Box::new(AllQuery),
+            features_field,
+            vec![RuntimeFilter {
+                field: RuntimeField::Ratio(
+                    NumericFeature::ProteinContent,
+                    NumericFeature::Calories,
+                ),
+                range: 0.15..1.0,
+            }],
+        );
+
+        // Only the first and fourth items have enough protein
+        assert_eq!(2, searcher.search(&query, &Count)?);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,223 @@
+use std::{
+    fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+
+use tantivy::{
+    directory::{error::OpenReadError, ReadOnlySource},
+    Directory, Index, IndexMeta, Result, TantivyError,
+};
+
+use crate::{
+    analysis::Analysis,
+    authors,
+    collation::Collation,
+    database::{Checkpoint, StructuredLog},
+    model::RecipeId,
+    popularity,
+    store::{pending_path, PendingEntry},
+};
+
+pub(crate) const DATABASE_DIR: &str = "database";
+pub(crate) const INDEX_DIR: &str = "tantivy";
+pub(crate) const META_FILE: &str = "meta.json";
+
+// Merges may get rid of the files of a commit right after it's read
+const MAX_INDEX_ATTEMPTS: usize = 5;
+
+/// A consistent copy of the database and its index, as created via
+/// `Cantine::snapshot`.
+///
+/// Taking a snapshot is cheap: it only notes how far the database
+/// files go and holds on to the files of the last index commit.
+/// Nothing is copied until `write_to`, which doesn't need the writer,
+/// so it can keep going meanwhile.
+///
+/// Recipes that were upserted but not committed yet are recorded as
+/// pending, so they get indexed when the restored copy is opened.
+pub struct Snapshot {
+    db_path: PathBuf,
+    recipes: Checkpoint,
+    authors: Checkpoint,
+    /// None if there's no popularity keyspace
+    popularity: Option<Checkpoint>,
+    pending: Vec<RecipeId>,
+    index_meta: String,
+    index_files: IndexFiles,
+    analysis: Option<Analysis>,
+    collation: Option<Collation>,
+}
+
+impl Snapshot {
+    pub(crate) fn capture(
+        db_path: &Path,
+        recipes: Checkpoint,
+        authors: Checkpoint,
+        popularity: Option<Checkpoint>,
+        pending: Vec<RecipeId>,
+        index: &Index,
+    ) -> Result<Self> {
+        let (meta, index_files) = capture_index(index)?;
+        let index_meta = serialize_meta(&meta)?;
+
+        Ok(Self {
+            db_path: db_path.to_owned(),
+            recipes,
+            authors,
+            popularity,
+            pending,
+            index_meta,
+            index_files,
+            analysis: None,
+            collation: None,
+        })
+    }
+
+    /// Saves the analysis alongside the index, so that a restored
+    /// copy parses queries like the original does
+    pub fn with_analysis(self, analysis: Analysis) -> Self {
+        Self {
+            analysis: Some(analysis),
+            ..self
+        }
+    }
+
+    /// Records the collation alongside the index, so that a restored
+    /// copy keeps generating the name keys like the original does
+    pub fn with_collation(self, collation: Collation) -> Self {
+        Self {
+            collation: Some(collation),
+            ..self
+        }
+    }
+
+    /// Writes the snapshot to `dest`, a non-existing directory, laid
+    /// out like the ones created by `load`
+    pub fn write_to<P: AsRef<Path>>(&self, dest: P) -> Result<()> {
+        let dest = dest.as_ref();
+        fs::create_dir(dest)?;
+
+        let db_dest = dest.join(DATABASE_DIR);
+        fs::create_dir(&db_dest)?;
+        self.recipes.copy_to(&self.db_path, &db_dest)?;
+
+        let authors_dest = authors::authors_path(&db_dest);
+        fs::create_dir(&authors_dest)?;
+        self.authors
+            .copy_to(authors::authors_path(&self.db_path), &authors_dest)?;
+
+        if let Some(checkpoint) = &self.popularity {
+            let popularity_dest = popularity::popularity_path(&db_dest);
+            fs::create_dir(&popularity_dest)?;
+            checkpoint.copy_to(popularity::popularity_path(&self.db_path), &popularity_dest)?;
+        }
+
+        let mut pending = StructuredLog::new(pending_path(&db_dest))?;
+        for &id in &self.pending {
+            pending.append(&PendingEntry::new(id))?;
+        }
+        pending.sync()?;
+
+        let index_dest = dest.join(INDEX_DIR);
+        fs::create_dir(&index_dest)?;
+        for (path, source) in &self.index_files {
+            write_synced(&index_dest.join(path), source.as_slice())?;
+        }
+        // Last, so that the index is only complete if every file is
+        write_synced(&index_dest.join(META_FILE), self.index_meta.as_bytes())?;
+
+        if let Some(analysis) = &self.analysis {
+            analysis.save(dest)?;
+        }
+
+        if let Some(collation) = &self.collation {
+            collation.save(dest)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Puts the snapshot written at `snapshot_dir` in place as the base
+/// directory `base_dir`, which must not exist.
+///
+/// The snapshot is left untouched: it's copied next to `base_dir`
+/// first and only renamed to it once complete.
+pub fn restore<P: AsRef<Path>, Q: AsRef<Path>>(snapshot_dir: P, base_dir: Q) -> Result<()> {
+    let base_dir = base_dir.as_ref();
+    if base_dir.exists() {
+        return Err(TantivyError::InvalidArgument(format!(
+            "Cannot restore to {:?}: it already exists",
+            base_dir
+        )));
+    }
+
+    let staging = base_dir.with_extension("restoring");
+    if staging.exists() {
+        fs::remove_dir_all(&staging)?;
+    }
+    copy_dir(snapshot_dir.as_ref(), &staging)?;
+
+    // Fails early on incomplete snapshots
+    Index::open_in_dir(staging.join(INDEX_DIR))?;
+
+    fs::rename(&staging, base_dir)?;
+    Ok(())
+}
+
+/// The meta of the last commit of the index, along with its files
+pub(crate) fn capture_index(index: &Index) -> Result<(IndexMeta, IndexFiles)> {
+    let directory = index.directory();
+    let mut attempts = 0;
+
+    'commit: loop {
+        attempts += 1;
+        let metas = index.load_metas()?;
+
+        // Opened files stay readable even if the writer deletes them
+        let mut files = Vec::new();
+        for segment in &metas.segments {
+            for path in segment.list_files() {
+                match directory.open_read(&path) {
+                    Ok(source) => files.push((path, source)),
+                    Err(OpenReadError::FileDoesNotExist(_)) if attempts < MAX_INDEX_ATTEMPTS => {
+                        continue 'commit;
+                    }
+                    Err(err) => return Err(err.into()),
+                }
+            }
+        }
+
+        return Ok((metas, files));
+    }
+}
+
+pub(crate) type IndexFiles = Vec<(PathBuf, ReadOnlySource)>;
+
+pub(crate) fn serialize_meta(meta: &IndexMeta) -> Result<String> {
+    serde_json::to_string_pretty(meta).map_err(|err| TantivyError::SystemError(err.to_string()))
+}
+
+pub(crate) fn write_synced(path: &Path, contents: &[u8]) -> io::Result<()> {
+    let mut file = fs::File::create(path)?;
+    file.write_all(contents)?;
+    file.sync_all()
+}
+
+fn copy_dir(src: &Path, dest: &Path) -> io::Result<()> {
+    fs::create_dir(dest)?;
+
+    for entry in fs::read_dir(src)? {
+        let entry = entry?;
+        let target = dest.join(entry.file_name());
+
+        if entry.file_type()?.is_dir() {
+            copy_dir(&entry.path(), &target)?;
+        } else {
+            fs::copy(entry.path(), &target)?;
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,109 @@
+use std::{
+    collections::hash_map::DefaultHasher,
+    hash::{Hash, Hasher},
+};
+
+use tantivy::query::Query;
+
+#[cfg(feature = "tracing")]
+pub use tracing::Span;
+
+/// Opens an info level `tracing` span, taking the same arguments as
+/// `tracing::info_span!`. Searches go through `parse`, `search`,
+/// `collect`, `merge` and `hydrate` spans, so that slow ones can be
+/// pinned down to a phase.
+///
+/// Without the `tracing` feature it yields a span that does nothing
+/// and the arguments aren't even evaluated
+#[cfg(feature = "tracing")]
+#[macro_export]
+macro_rules! span {
+    ($($args:tt)*) => {
+        $crate::spans::tracing::info_span!($($args)*)
+    };
+}
+
+#[cfg(feature = "tracing")]
+#[doc(hidden)]
+pub use tracing;
+
+#[cfg(not(feature = "tracing"))]
+#[macro_export]
+macro_rules! span {
+    ($($args:tt)*) => {
+        $crate::spans::Span::none()
+    };
+}
+
+/// Stands for `tracing::Span` without the `tracing` feature
+#[cfg(not(feature = "tracing"))]
+#[derive(Debug, Clone)]
+pub struct Span;
+
+#[cfg(not(feature = "tracing"))]
+pub struct Entered;
+
+#[cfg(not(feature = "tracing"))]
+impl Span {
+    pub fn none() -> Self {
+        Span
+    }
+
+    pub fn enter(&self) -> Entered {
+        Entered
+    }
+
+    pub fn in_scope<F: FnOnce() -> T, T>(&self, f: F) -> T {
+        f()
+    }
+
+    pub fn record<V: ?Sized>(&self, _field: &str, _value: &V) -> &Self {
+        self
+    }
+}
+
+/// Tells queries apart in traces without spelling them out. Same
+/// queries hash the same
+pub fn query_hash(query: &dyn Query) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    format!("{:?}", query).hash(&mut hasher);
+    hasher.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use tantivy::{
+        query::{AllQuery, TermQuery},
+        schema::{IndexRecordOption, SchemaBuilder, TEXT},
+        Term,
+    };
+
+    #[test]
+    fn same_queries_hash_the_same() {
+        let mut builder = SchemaBuilder::new();
+        let field = builder.add_text_field("field", TEXT);
+        let term_query =
+            |text| TermQuery::new(Term::from_field_text(field, text), IndexRecordOption::Basic);
+
+        assert_eq!(
+            query_hash(&term_query("salt")),
+            query_hash(&term_query("salt"))
+        );
+        assert_ne!(
+            query_hash(&term_query("salt")),
+            query_hash(&term_query("pepper"))
+        );
+        assert_ne!(query_hash(&AllQuery), query_hash(&term_query("salt")));
+    }
+
+    #[test]
+    fn spans_wrap_what_they_run() {
+        let span = crate::span!("test", hits = 0);
+        assert_eq!(42, span.in_scope(|| 42));
+
+        let _entered = span.enter();
+        span.record("hits", &1u64);
+    }
+}
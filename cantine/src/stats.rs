@@ -0,0 +1,255 @@
+use bincode;
+use serde::Serialize;
+use tantivy::{schema::Field, Result, Searcher, SegmentId};
+
+use crate::{index::RecipeIndex, model::Features};
+
+/// Corpus-level statistics, as opposed to the per-segment ones
+/// tantivy uses for scoring.
+///
+/// Computing it means visiting every live document, so it should be
+/// done once per reload (check `is_current`) and shared.
+#[derive(Debug, Clone, Serialize)]
+pub struct GlobalStats {
+    pub num_docs: u64,
+
+    pub name: FieldStats,
+    pub ingredients: FieldStats,
+    pub instructions: FieldStats,
+
+    pub num_ingredients: Option<Percentiles>,
+    pub instructions_length: Option<Percentiles>,
+    pub total_time: Option<Percentiles>,
+    pub calories: Option<Percentiles>,
+
+    #[serde(skip)]
+    segments: Vec<(SegmentId, u32)>,
+}
+
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct FieldStats {
+    pub total_tokens: u64,
+    pub avg_tokens: f32,
+}
+
+/// The value at every percentile from 0 to 100 (inclusive)
+#[derive(Debug, Clone, Serialize)]
+pub struct Percentiles(Vec<f64>);
+
+impl GlobalStats {
+    pub fn compute(searcher: &Searcher, recipe_index: &RecipeIndex) -> Result<Self> {
+        let num_docs = searcher.num_docs();
+
+        let mut num_ingredients = Vec::new();
+        let mut instructions_length = Vec::new();
+        let mut total_time = Vec::new();
+        let mut calories = Vec::new();
+
+        for reader in searcher.segment_readers() {
+            let features_reader = reader
+                .fast_fields()
+                .bytes(recipe_index.features_bincode)
+                .expect("bytes field is indexed");
+
+            for doc in 0..reader.max_doc() {
+                if reader.is_deleted(doc) {
+                    continue;
+                }
+
+                if let Ok(features) =
+                    bincode::deserialize::<Features>(features_reader.get_bytes(doc))
+                {
+                    num_ingredients.push(f64::from(features.num_ingredients));
+                    instructions_length.push(f64::from(features.instructions_length));
+                    total_time.extend(features.total_time.map(f64::from));
+                    calories.extend(features.calories.map(f64::from));
+                }
+            }
+        }
+
+        Ok(Self {
+            num_docs,
+            name: FieldStats::compute(searcher, recipe_index.name, num_docs),
+            ingredients: FieldStats::compute(searcher, recipe_index.ingredients, num_docs),
+            instructions: FieldStats::compute(searcher, recipe_index.instructions, num_docs),
+            num_ingredients: Percentiles::from_values(num_ingredients),
+            instructions_length: Percentiles::from_values(instructions_length),
+            total_time: Percentiles::from_values(total_time),
+            calories: Percentiles::from_values(calories),
+            segments: segments_of(searcher),
+        })
+    }
+
+    /// Wether these stats were computed from the same documents the
+    /// given searcher sees
+    pub fn is_current(&self, searcher: &Searcher) -> bool {
+        self.segments == segments_of(searcher)
+    }
+
+    /// The figure called `name`, for score expressions: `num_docs` or
+    /// a percentile of a feature, like `calories_p50` or
+    /// `total_time_p90`. None for unknown names and features without
+    /// values
+    pub fn constant(&self, name: &str) -> Option<f64> {
+        if name == "num_docs" {
+            return Some(self.num_docs as f64);
+        }
+
+        let split = name.rfind("_p")?;
+        let percentile = name[split + 2..].parse::<u8>().ok().filter(|&p| p <= 100)?;
+        let percentiles = match &name[..split] {
+            "num_ingredients" => &self.num_ingredients,
+            "instructions_length" => &self.instructions_length,
+            "total_time" => &self.total_time,
+            "calories" => &self.calories,
+            _ => return None,
+        };
+
+        percentiles
+            .as_ref()
+            .map(|percentiles| percentiles.get(percentile))
+    }
+
+    /// Stats for one of the text fields
+    pub fn field(&self, field: Field, recipe_index: &RecipeIndex) -> Option<&FieldStats> {
+        if field == recipe_index.name {
+            Some(&self.name)
+        } else if field == recipe_index.ingredients {
+            Some(&self.ingredients)
+        } else if field == recipe_index.instructions {
+            Some(&self.instructions)
+        } else {
+            None
+        }
+    }
+}
+
+/// Figures about the index itself, for telling how healthy it is.
+/// See `RecipeIndex::stats`
+#[derive(Debug, Clone, Serialize)]
+pub struct IndexStats {
+    pub num_segments: usize,
+    pub num_docs: u64,
+    /// Deleted (or replaced) documents still taking space, until
+    /// their segments get merged
+    pub num_deleted_docs: u64,
+    /// How large the index directory is, in bytes
+    pub size_bytes: u64,
+    /// When the index was last committed to, in seconds since the
+    /// epoch. None if the file system can't tell
+    pub last_commit: Option<u64>,
+}
+
+// A reload either brings new segments or new deletes
+fn segments_of(searcher: &Searcher) -> Vec<(SegmentId, u32)> {
+    searcher
+        .segment_readers()
+        .iter()
+        .map(|reader| (reader.segment_id(), reader.num_deleted_docs()))
+        .collect()
+}
+
+impl FieldStats {
+    fn compute(searcher: &Searcher, field: Field, num_docs: u64) -> Self {
+        let total_tokens = searcher
+            .segment_readers()
+            .iter()
+            .map(|reader| reader.inverted_index(field).total_num_tokens())
+            .sum();
+
+        Self {
+            total_tokens,
+            avg_tokens: if num_docs == 0 {
+                0.0
+            } else {
+                (total_tokens as f64 / num_docs as f64) as f32
+            },
+        }
+    }
+}
+
+impl Percentiles {
+    fn from_values(mut values: Vec<f64>) -> Option<Self> {
+        if values.is_empty() {
+            return None;
+        }
+
+        values.sort_by(|a, b| a.partial_cmp(b).expect("no NaNs in features"));
+
+        let last = values.len() - 1;
+        Some(Self(
+            (0..=100).map(|p| values[(last * p + 50) / 100]).collect(),
+        ))
+    }
+
+    /// The value below which `percentile` percent of the values fall
+    ///
+    /// Panics if `percentile` is greater than 100
+    pub fn get(&self, percentile: u8) -> f64 {
+        self.0[usize::from(percentile)]
+    }
+
+    /// The (approximate) fraction of values that are not greater
+    /// than `value`, from `0.0` to `1.0`
+    pub fn rank(&self, value: f64) -> f32 {
+        let below = self.0.iter().filter(|v| **v <= value).count();
+        below.saturating_sub(1) as f32 / 100.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn constants() {
+        let stats = GlobalStats {
+            num_docs: 1001,
+            name: FieldStats {
+                total_tokens: 0,
+                avg_tokens: 0.0,
+            },
+            ingredients: FieldStats {
+                total_tokens: 0,
+                avg_tokens: 0.0,
+            },
+            instructions: FieldStats {
+                total_tokens: 0,
+                avg_tokens: 0.0,
+            },
+            num_ingredients: None,
+            instructions_length: None,
+            total_time: None,
+            calories: Percentiles::from_values((0..=1000).map(f64::from).collect()),
+            segments: Vec::new(),
+        };
+
+        assert_eq!(Some(1001.0), stats.constant("num_docs"));
+        assert_eq!(Some(500.0), stats.constant("calories_p50"));
+        assert_eq!(Some(1000.0), stats.constant("calories_p100"));
+        assert_eq!(None, stats.constant("calories_p101"));
+        assert_eq!(None, stats.constant("calories"));
+        assert_eq!(None, stats.constant("total_time_p50"));
+        assert_eq!(None, stats.constant("popularity_p50"));
+    }
+
+    #[test]
+    fn percentiles() {
+        assert!(Percentiles::from_values(Vec::new()).is_none());
+
+        let single = Percentiles::from_values(vec![42.0]).unwrap();
+        assert_eq!(42.0, single.get(0));
+        assert_eq!(42.0, single.get(100));
+
+        let perc = Percentiles::from_values((0..=1000).rev().map(f64::from).collect()).unwrap();
+        assert_eq!(0.0, perc.get(0));
+        assert_eq!(500.0, perc.get(50));
+        assert_eq!(990.0, perc.get(99));
+        assert_eq!(1000.0, perc.get(100));
+
+        assert_eq!(0.0, perc.rank(-1.0));
+        assert_eq!(0.0, perc.rank(0.0));
+        assert_eq!(0.5, perc.rank(505.0));
+        assert_eq!(1.0, perc.rank(1000.0));
+    }
+}
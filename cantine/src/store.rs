@@ -0,0 +1,726 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::{Arc, RwLock},
+};
+
+use byteorder::NativeEndian;
+use tantivy::{
+    collector::TopDocs,
+    query::TermQuery,
+    schema::{IndexRecordOption, Value},
+    IndexWriter, Result, TantivyError, Term,
+};
+use zerocopy::U64;
+
+use crate::{
+    authors,
+    clock::{SystemClock, SECONDS_PER_DAY},
+    database::{DatabaseReader, DatabaseWriter, StructuredLog},
+    dedup::{Duplicate, DuplicateDetector},
+    freshness::commit_with_checkpoint,
+    idempotency::IdempotencyKeys,
+    index::RecipeIndex,
+    model::{Author, AuthorId, Recipe, RecipeId, RecipeSummary},
+    popularity,
+    replication::{Flushed, ReplicationSource},
+    snapshot::Snapshot,
+    summaries,
+};
+
+/// Keeps the database and the index in agreement when recipes are
+/// added or changed.
+///
+/// The database is the source of truth, but before a recipe is
+/// written (and flushed) to it, its id goes to a log of pending index
+/// operations that is only cleared after the index commits. Opening
+/// a `Cantine` replays whatever is left in that log, from what the
+/// database has, so a crash at any point during an upsert ends with
+/// the index agreeing with the database.
+///
+/// Authors live in a keyspace of their own and are kept in memory so
+/// that their attributes can be copied into the documents of their
+/// recipes.
+pub struct Cantine {
+    db_path: PathBuf,
+    db: DatabaseWriter<Recipe>,
+    summaries_db: DatabaseWriter<RecipeSummary>,
+    authors_db: DatabaseWriter<Author>,
+    authors: HashMap<AuthorId, Author>,
+    pending: StructuredLog<PendingEntry>,
+    idempotency_keys: IdempotencyKeys,
+    writer: IndexWriter,
+    recipe_index: RecipeIndex,
+    flushed: Flushed,
+    duplicates: Option<DuplicateDetector>,
+}
+
+pub(crate) type PendingEntry = U64<NativeEndian>;
+
+const PENDING_FILE: &str = "pending.bin";
+const IDEMPOTENCY_FILE: &str = "idempotency.bin";
+
+impl Cantine {
+    /// Opens the database at `db_path`, reindexing every recipe that
+    /// didn't make it to the index before the last shutdown
+    ///
+    /// # Errors
+    ///
+    /// Fails if the index can't address documents by id (the id field
+    /// must be `INDEXED`), if a tokenizer its schema needs is missing
+    /// (see `RecipeIndex::install_tokenizers`), or on any io error.
+    pub fn open<P: AsRef<Path>>(
+        db_path: P,
+        mut writer: IndexWriter,
+        recipe_index: RecipeIndex,
+    ) -> Result<Self> {
+        if !writer
+            .index()
+            .schema()
+            .get_field_entry(recipe_index.id)
+            .is_indexed()
+        {
+            return Err(TantivyError::SchemaError(
+                "The id field is not indexed. A full reindex is required".to_owned(),
+            ));
+        }
+
+        // Fails early instead of indexing with missing tokenizers
+        recipe_index.install_tokenizers(writer.index())?;
+
+        let db_path = db_path.as_ref();
+        let authors_db = authors::open_writer(db_path)?;
+        let authors = authors::load_all(db_path)?;
+
+        let mut pending = StructuredLog::new(pending_path(db_path))?;
+
+        let num_replayed = replay(db_path, &pending, &mut writer, &recipe_index, &authors)?;
+        if num_replayed > 0 {
+            writer.commit()?;
+            log::info!("Reindexed {} pending recipes", num_replayed);
+        }
+        pending.clear()?;
+
+        let db = DatabaseWriter::open(db_path)?;
+        let summaries_db = summaries::open_writer(db_path)?;
+        let flushed = Arc::new(RwLock::new((db.checkpoint()?, authors_db.checkpoint()?)));
+
+        Ok(Self {
+            db_path: db_path.to_owned(),
+            db,
+            summaries_db,
+            authors_db,
+            authors,
+            pending,
+            idempotency_keys: IdempotencyKeys::open(
+                db_path.join(IDEMPOTENCY_FILE),
+                SECONDS_PER_DAY,
+                Box::new(SystemClock),
+            )?,
+            writer,
+            recipe_index,
+            flushed,
+            duplicates: None,
+        })
+    }
+
+    /// Adds the recipe, replacing the existing one with the same id
+    ///
+    /// The recipe is durable once this returns, but only searchable
+    /// after `commit`.
+    pub fn upsert(&mut self, recipe: &Recipe) -> Result<()> {
+        // Logged first: replaying an id the database doesn't have (or
+        // only has an older version of) is harmless, missing one isn't
+        self.pending.append(&PendingEntry::new(recipe.recipe_id))?;
+        self.pending.sync()?;
+
+        self.db.append(recipe)?;
+        self.db.flush()?;
+        self.summaries_db.append(&RecipeSummary::from(recipe))?;
+        self.summaries_db.flush()?;
+        self.publish_flushed()?;
+
+        index_recipe(&mut self.writer, &self.recipe_index, &self.authors, recipe);
+
+        if let Some(detector) = &mut self.duplicates {
+            detector.insert(recipe);
+        }
+        Ok(())
+    }
+
+    /// Like `upsert`, but skips recipes that are near duplicates of
+    /// others in the database, yielding the candidates instead (most
+    /// similar first) so that they can be merged or dropped. Newer
+    /// versions of a recipe don't count as duplicates of it.
+    ///
+    /// Only checks anything `with_duplicate_detection`
+    pub fn upsert_unless_duplicate(&mut self, recipe: &Recipe) -> Result<Vec<Duplicate>> {
+        let candidates = self.duplicates_of(recipe);
+        if candidates.is_empty() {
+            self.upsert(recipe)?;
+        }
+        Ok(candidates)
+    }
+
+    /// The recipes in the database that are near duplicates of
+    /// `recipe`, most similar first. Always empty unless created
+    /// `with_duplicate_detection`
+    pub fn duplicates_of(&self, recipe: &Recipe) -> Vec<Duplicate> {
+        self.duplicates
+            .as_ref()
+            .map_or_else(Vec::new, |detector| detector.candidates(recipe))
+    }
+
+    /// Enables duplicate detection, with recipes taken as duplicates
+    /// when the similarity of their text is at least `threshold` (see
+    /// `DuplicateDetector`). Reads every recipe in the database
+    pub fn with_duplicate_detection(mut self, threshold: f32) -> Result<Self> {
+        let mut detector = DuplicateDetector::new(threshold);
+
+        let reader = DatabaseReader::<Recipe>::open(&self.db_path)?;
+        let ids = reader.ids().copied().collect::<Vec<_>>();
+        for id in ids {
+            if let Some(recipe) = reader.find_by_id(id).transpose()? {
+                detector.insert(&recipe);
+            }
+        }
+        log::info!("Fingerprinted {} recipes", detector.len());
+
+        self.duplicates = Some(detector);
+        Ok(self)
+    }
+
+    /// Like `upsert`, but does nothing if a recipe was already
+    /// upserted with the same `key` (say: from an `Idempotency-Key`
+    /// header) recently, so that retried requests don't add new
+    /// versions of the recipe. Yields whether the recipe was added
+    ///
+    /// Keys are remembered for a day, unless told otherwise via
+    /// `with_idempotency_window`
+    pub fn upsert_with_key(&mut self, key: &str, recipe: &Recipe) -> Result<bool> {
+        if self.idempotency_keys.contains(key) {
+            return Ok(false);
+        }
+
+        // A crash right after the upsert means the retry adds the
+        // recipe again, which beats losing it
+        self.upsert(recipe)?;
+        self.idempotency_keys.insert(key)?;
+        Ok(true)
+    }
+
+    /// Changes for how long `upsert_with_key` remembers keys, in
+    /// seconds
+    pub fn with_idempotency_window(mut self, window: u64) -> Self {
+        self.idempotency_keys.set_window(window);
+        self
+    }
+
+    /// Adds the author, replacing the existing one with the same id,
+    /// and reindexes every recipe by them so that searches see the
+    /// new attributes after `commit`
+    pub fn upsert_author(&mut self, author: &Author) -> Result<()> {
+        self.authors_db.append(author)?;
+        self.authors_db.flush()?;
+        self.publish_flushed()?;
+        self.authors.insert(author.author_id, author.clone());
+
+        // Recipes upserted since the last commit aren't searchable
+        // yet, but they are all in the pending log
+        let mut ids = self.indexed_recipes_by(author.author_id)?;
+        self.pending.for_each_entry(|entry| ids.push(entry.get()))?;
+        ids.sort();
+        ids.dedup();
+
+        let reader = DatabaseReader::<Recipe>::open(&self.db_path)?;
+        for id in ids {
+            if let Some(recipe) = reader.find_by_id(id).transpose()? {
+                if recipe.author_id == Some(author.author_id) {
+                    self.pending.append(&PendingEntry::new(id))?;
+                    index_recipe(&mut self.writer, &self.recipe_index, &self.authors, &recipe);
+                }
+            }
+        }
+        self.pending.sync()?;
+
+        Ok(())
+    }
+
+    /// Commits the index, making every upserted recipe searchable
+    ///
+    /// Yields the generation of the commit, which readers can be
+    /// asked about via `generation::Visibility`
+    pub fn commit(&mut self) -> Result<u64> {
+        let generation = commit_with_checkpoint(&mut self.writer, &self.db.checkpoint()?)?;
+        self.pending.clear()?;
+        Ok(generation)
+    }
+
+    /// Takes a snapshot of the database and of the index as of the
+    /// last commit. See `Snapshot`
+    pub fn snapshot(&self) -> Result<Snapshot> {
+        let mut pending = Vec::new();
+        self.pending
+            .for_each_entry(|entry| pending.push(entry.get()))?;
+
+        Snapshot::capture(
+            &self.db_path,
+            self.db.checkpoint()?,
+            self.authors_db.checkpoint()?,
+            popularity::checkpoint(&self.db_path)?,
+            pending,
+            self.writer.index(),
+        )
+    }
+
+    /// Exposes every change to followers, so they can keep copies of
+    /// the database and index. See `ReplicationSink`
+    pub fn replication_source(&self) -> ReplicationSource {
+        ReplicationSource::new(
+            &self.db_path,
+            self.writer.index().clone(),
+            self.flushed.clone(),
+        )
+    }
+
+    fn publish_flushed(&self) -> Result<()> {
+        let checkpoints = (self.db.checkpoint()?, self.authors_db.checkpoint()?);
+        *self.flushed.write().expect("lock not poisoned") = checkpoints;
+        Ok(())
+    }
+
+    fn indexed_recipes_by(&self, author_id: AuthorId) -> Result<Vec<RecipeId>> {
+        let searcher = self.writer.index().reader()?.searcher();
+        let query = TermQuery::new(
+            Term::from_field_u64(self.recipe_index.author_id, author_id),
+            IndexRecordOption::Basic,
+        );
+        let limit = (searcher.num_docs() as usize).max(1);
+
+        let mut ids = Vec::new();
+        for (_score, addr) in searcher.search(&query, &TopDocs::with_limit(limit))? {
+            if let Some(&Value::U64(id)) = searcher.doc(addr)?.get_first(self.recipe_index.id) {
+                ids.push(id);
+            }
+        }
+        Ok(ids)
+    }
+}
+
+pub(crate) fn pending_path(db_path: &Path) -> PathBuf {
+    db_path.join(PENDING_FILE)
+}
+
+fn index_recipe(
+    writer: &mut IndexWriter,
+    recipe_index: &RecipeIndex,
+    authors: &HashMap<AuthorId, Author>,
+    recipe: &Recipe,
+) {
+    let author = recipe.author_id.and_then(|id| authors.get(&id));
+    writer.delete_term(Term::from_field_u64(recipe_index.id, recipe.recipe_id));
+    writer.add_document(recipe_index.make_document_with_author(recipe, author));
+}
+
+fn replay(
+    db_path: &Path,
+    pending: &StructuredLog<PendingEntry>,
+    writer: &mut IndexWriter,
+    recipe_index: &RecipeIndex,
+    authors: &HashMap<AuthorId, Author>,
+) -> Result<usize> {
+    let mut ids: Vec<RecipeId> = Vec::new();
+    pending.for_each_entry(|entry| ids.push(entry.get()))?;
+
+    if ids.is_empty() {
+        return Ok(0);
+    }
+
+    // The same recipe may have been upserted many times, but the
+    // database only yields its latest version
+    ids.sort();
+    ids.dedup();
+
+    let reader = DatabaseReader::<Recipe>::open(db_path)?;
+    let mut num_replayed = 0;
+    for id in ids {
+        if let Some(recipe) = reader.find_by_id(id).transpose()? {
+            index_recipe(writer, recipe_index, authors, &recipe);
+            num_replayed += 1;
+        }
+    }
+
+    Ok(num_replayed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::fs;
+
+    use tantivy::{collector::Count, query::AllQuery, schema::SchemaBuilder, Index};
+    use uuid::Uuid;
+
+    use crate::{database::Checkpoint, replication::ReplicationSink};
+
+    use crate::model::Popularity;
+
+    fn counts(recipe_id: RecipeId, clicks: u64) -> Popularity {
+        Popularity {
+            uuid: Uuid::new_v4(),
+            recipe_id,
+            clicks,
+            saves: 0,
+        }
+    }
+
+    fn num_docs(index: &Index) -> Result<usize> {
+        index.reader()?.searcher().search(&AllQuery, &Count)
+    }
+
+    #[test]
+    fn pending_recipes_are_indexed_on_open() -> Result<()> {
+        let db_dir = tempfile::tempdir()?;
+        DatabaseWriter::<Recipe>::new(db_dir.path())?;
+
+        let mut builder = SchemaBuilder::new();
+        let recipe_index = RecipeIndex::from(&mut builder);
+        let index = Index::create_in_ram(builder.build());
+
+        let mut cantine = Cantine::open(
+            db_dir.path(),
+            index.writer_with_num_threads(1, 3_000_000)?,
+            recipe_index.clone(),
+        )?;
+
+        cantine.upsert(&Recipe::sample(1, "pancakes"))?;
+        cantine.commit()?;
+        assert_eq!(1, num_docs(&index)?);
+
+        cantine.upsert(&Recipe::sample(1, "crepes"))?;
+        cantine.upsert(&Recipe::sample(2, "waffles"))?;
+        // Simulates a crash before the database write of an upsert
+        cantine.pending.append(&PendingEntry::new(3))?;
+        // Simulates a crash: nothing gets committed
+        drop(cantine);
+        assert_eq!(1, num_docs(&index)?);
+
+        let cantine = Cantine::open(
+            db_dir.path(),
+            index.writer_with_num_threads(1, 3_000_000)?,
+            recipe_index,
+        )?;
+        assert_eq!(2, num_docs(&index)?);
+        assert_eq!(0, cantine.pending.len()?);
+
+        let reader = DatabaseReader::<Recipe>::open(db_dir.path())?;
+        assert_eq!(
+            Some("crepes".to_owned()),
+            reader.find_by_id(1).transpose()?.map(|recipe| recipe.name)
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn author_changes_reach_their_recipes() -> Result<()> {
+        let db_dir = tempfile::tempdir()?;
+        DatabaseWriter::<Recipe>::new(db_dir.path())?;
+
+        let mut builder = SchemaBuilder::new();
+        let recipe_index = RecipeIndex::from(&mut builder);
+        let index = Index::create_in_ram(builder.build());
+
+        let num_verified = |index: &Index| -> Result<usize> {
+            let query = TermQuery::new(
+                Term::from_field_u64(recipe_index.author_verified, 1),
+                IndexRecordOption::Basic,
+            );
+            index.reader()?.searcher().search(&query, &Count)
+        };
+
+        let mut cantine = Cantine::open(
+            db_dir.path(),
+            index.writer_with_num_threads(1, 3_000_000)?,
+            recipe_index.clone(),
+        )?;
+
+        let mut author = Author {
+            uuid: Uuid::new_v4(),
+            author_id: 7,
+            name: "Jo".to_owned(),
+            profile_url: None,
+            verified: false,
+        };
+        cantine.upsert_author(&author)?;
+
+        let mut pancakes = Recipe::sample(1, "pancakes");
+        pancakes.author_id = Some(7);
+        cantine.upsert(&pancakes)?;
+        cantine.upsert(&Recipe::sample(2, "waffles"))?;
+        cantine.commit()?;
+        assert_eq!(0, num_verified(&index)?);
+
+        author.verified = true;
+        cantine.upsert_author(&author)?;
+        cantine.commit()?;
+        assert_eq!(1, num_verified(&index)?);
+        assert_eq!(2, num_docs(&index)?);
+
+        // Authors are loaded back from their keyspace
+        drop(cantine);
+        let mut cantine = Cantine::open(
+            db_dir.path(),
+            index.writer_with_num_threads(1, 3_000_000)?,
+            recipe_index.clone(),
+        )?;
+
+        let mut crepes = Recipe::sample(3, "crepes");
+        crepes.author_id = Some(7);
+        cantine.upsert(&crepes)?;
+        cantine.commit()?;
+        assert_eq!(2, num_verified(&index)?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn snapshots_restore_a_consistent_copy() -> Result<()> {
+        let base_dir = tempfile::tempdir()?;
+        let db_path = base_dir.path().join("database");
+        let index_path = base_dir.path().join("tantivy");
+        fs::create_dir(&db_path)?;
+        fs::create_dir(&index_path)?;
+        DatabaseWriter::<Recipe>::new(&db_path)?;
+
+        let mut builder = SchemaBuilder::new();
+        let recipe_index = RecipeIndex::from(&mut builder);
+        let index = Index::create_in_dir(&index_path, builder.build())?;
+
+        let mut cantine = Cantine::open(
+            &db_path,
+            index.writer_with_num_threads(1, 3_000_000)?,
+            recipe_index.clone(),
+        )?;
+
+        cantine.upsert(&Recipe::sample(1, "pancakes"))?;
+        cantine.commit()?;
+        // Pending when the snapshot is taken
+        cantine.upsert(&Recipe::sample(2, "waffles"))?;
+
+        let mut popularity_writer = popularity::open_writer(&db_path)?;
+        popularity_writer.append(&counts(1, 10))?;
+        popularity_writer.flush()?;
+
+        let snapshot = cantine.snapshot()?;
+
+        // The writer keeps going
+        cantine.upsert(&Recipe::sample(3, "crepes"))?;
+        cantine.commit()?;
+        popularity_writer.append(&counts(2, 20))?;
+        popularity_writer.flush()?;
+
+        let backups = tempfile::tempdir()?;
+        let snapshot_dir = backups.path().join("snapshot");
+        snapshot.write_to(&snapshot_dir)?;
+        drop(snapshot);
+
+        let restored = backups.path().join("restored");
+        crate::snapshot::restore(&snapshot_dir, &restored)?;
+        assert!(crate::snapshot::restore(&snapshot_dir, &restored).is_err());
+
+        let restored_index = Index::open_in_dir(restored.join("tantivy"))?;
+        assert_eq!(1, num_docs(&restored_index)?);
+
+        // Opening replays what was pending
+        let restored_db = restored.join("database");
+        Cantine::open(
+            &restored_db,
+            restored_index.writer_with_num_threads(1, 3_000_000)?,
+            recipe_index,
+        )?;
+        assert_eq!(2, num_docs(&restored_index)?);
+
+        let reader = DatabaseReader::<Recipe>::open(&restored_db)?;
+        assert_eq!(2, reader.ids().count());
+        assert_eq!(None, reader.find_by_id(3).transpose()?);
+
+        let restored_popularity = popularity::load_all(&restored_db)?;
+        assert_eq!(1, restored_popularity.len());
+        assert_eq!(Some(&10), restored_popularity.get(&1));
+
+        Ok(())
+    }
+
+    #[test]
+    fn followers_catch_up_via_replication() -> Result<()> {
+        let base_dir = tempfile::tempdir()?;
+        let db_path = base_dir.path().join("database");
+        let index_path = base_dir.path().join("tantivy");
+        fs::create_dir(&db_path)?;
+        fs::create_dir(&index_path)?;
+        DatabaseWriter::<Recipe>::new(&db_path)?;
+
+        let mut builder = SchemaBuilder::new();
+        let recipe_index = RecipeIndex::from(&mut builder);
+        let index = Index::create_in_dir(&index_path, builder.build())?;
+
+        let mut cantine = Cantine::open(
+            &db_path,
+            index.writer_with_num_threads(1, 3_000_000)?,
+            recipe_index,
+        )?;
+        let source = cantine.replication_source();
+
+        let follower_dir = tempfile::tempdir()?;
+        let mut sink = ReplicationSink::open(follower_dir.path())?;
+
+        let catch_up = |sink: &mut ReplicationSink| -> Result<()> {
+            let changes = source.changes_since(sink.position())?;
+            sink.apply(&changes)
+        };
+
+        cantine.upsert(&Recipe::sample(1, "pancakes"))?;
+        cantine.upsert(&Recipe::sample(2, "waffles"))?;
+        cantine.commit()?;
+        // Not committed: only reaches the follower's database
+        cantine.upsert(&Recipe::sample(3, "crepes"))?;
+        catch_up(&mut sink)?;
+
+        let follower_index = Index::open_in_dir(follower_dir.path().join("tantivy"))?;
+        assert_eq!(2, num_docs(&follower_index)?);
+
+        let follower_db = follower_dir.path().join("database");
+        let reader = DatabaseReader::<Recipe>::open(&follower_db)?;
+        assert_eq!(3, reader.ids().count());
+
+        // Nothing new
+        assert!(source.changes_since(sink.position())?.is_empty());
+
+        cantine.upsert(&Recipe::sample(1, "hotcakes"))?;
+        cantine.commit()?;
+        catch_up(&mut sink)?;
+
+        assert_eq!(
+            3,
+            num_docs(&Index::open_in_dir(follower_dir.path().join("tantivy"))?)?
+        );
+        let reader = DatabaseReader::<Recipe>::open(&follower_db)?;
+        assert_eq!(
+            Some("hotcakes".to_owned()),
+            reader.find_by_id(1).transpose()?.map(|recipe| recipe.name)
+        );
+
+        // Popularity counts come from elsewhere, but follow all the same
+        let mut popularity_writer = popularity::open_writer(&db_path)?;
+        popularity_writer.append(&counts(2, 5))?;
+        popularity_writer.flush()?;
+        assert!(!source.changes_since(sink.position())?.is_empty());
+        catch_up(&mut sink)?;
+        assert_eq!(Some(&5), popularity::load_all(&follower_db)?.get(&2));
+        assert!(source.changes_since(sink.position())?.is_empty());
+
+        // Picks up where it left off after reopening
+        let reopened = ReplicationSink::open(follower_dir.path())?;
+        assert_eq!(sink.position(), reopened.position());
+
+        Ok(())
+    }
+
+    #[test]
+    fn retried_upserts_are_only_applied_once() -> Result<()> {
+        let db_dir = tempfile::tempdir()?;
+        DatabaseWriter::<Recipe>::new(db_dir.path())?;
+
+        let mut builder = SchemaBuilder::new();
+        let recipe_index = RecipeIndex::from(&mut builder);
+        let index = Index::create_in_ram(builder.build());
+
+        let mut cantine = Cantine::open(
+            db_dir.path(),
+            index.writer_with_num_threads(1, 3_000_000)?,
+            recipe_index.clone(),
+        )?;
+
+        assert!(cantine.upsert_with_key("req-1", &Recipe::sample(1, "pancakes"))?);
+        let written = Checkpoint::of(db_dir.path())?;
+
+        assert!(!cantine.upsert_with_key("req-1", &Recipe::sample(1, "pancakes"))?);
+        assert_eq!(written, Checkpoint::of(db_dir.path())?);
+
+        assert!(cantine.upsert_with_key("req-2", &Recipe::sample(2, "waffles"))?);
+        cantine.commit()?;
+        assert_eq!(2, num_docs(&index)?);
+
+        // Keys survive restarts
+        drop(cantine);
+        let mut cantine = Cantine::open(
+            db_dir.path(),
+            index.writer_with_num_threads(1, 3_000_000)?,
+            recipe_index,
+        )?;
+        assert!(!cantine.upsert_with_key("req-2", &Recipe::sample(2, "waffles"))?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn near_duplicates_are_not_upserted() -> Result<()> {
+        let db_dir = tempfile::tempdir()?;
+        DatabaseWriter::<Recipe>::new(db_dir.path())?;
+
+        let mut builder = SchemaBuilder::new();
+        let recipe_index = RecipeIndex::from(&mut builder);
+        let index = Index::create_in_ram(builder.build());
+
+        let mut cantine = Cantine::open(
+            db_dir.path(),
+            index.writer_with_num_threads(1, 3_000_000)?,
+            recipe_index.clone(),
+        )?;
+        // Nothing is checked unless asked to
+        assert!(cantine
+            .upsert_unless_duplicate(&Recipe::sample(1, "pancakes"))?
+            .is_empty());
+        cantine.commit()?;
+        drop(cantine);
+
+        // Recipes already in the database are known
+        let mut cantine = Cantine::open(
+            db_dir.path(),
+            index.writer_with_num_threads(1, 3_000_000)?,
+            recipe_index,
+        )?
+        .with_duplicate_detection(0.9)?;
+
+        let found = cantine.upsert_unless_duplicate(&Recipe::sample(2, "Pancakes"))?;
+        assert_eq!(
+            vec![1],
+            found.iter().map(|d| d.recipe_id).collect::<Vec<_>>()
+        );
+
+        let mut stew = Recipe::sample(3, "beef stew");
+        stew.ingredients = vec!["1kg beef".to_owned(), "2 onions".to_owned()];
+        stew.instructions = vec!["simmer the beef with the onions for hours".to_owned()];
+        assert!(cantine.upsert_unless_duplicate(&stew)?.is_empty());
+
+        // New versions are fine
+        assert!(cantine
+            .upsert_unless_duplicate(&Recipe::sample(1, "pancakes"))?
+            .is_empty());
+
+        // Upserted recipes are known right away
+        stew.recipe_id = 4;
+        let found = cantine.duplicates_of(&stew);
+        assert_eq!(
+            vec![3],
+            found.iter().map(|d| d.recipe_id).collect::<Vec<_>>()
+        );
+
+        cantine.commit()?;
+        assert_eq!(2, num_docs(&index)?);
+
+        Ok(())
+    }
+}
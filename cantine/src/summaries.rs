@@ -0,0 +1,134 @@
+use std::{
+    fs,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    database::{DatabaseReader, DatabaseWriter},
+    error::{Error, Result},
+    model::{Recipe, RecipeId, RecipeSummary},
+};
+
+const SUMMARIES_KEYSPACE: &str = "summaries";
+
+/// Where the summaries of the recipe database at `db_path` are kept
+pub fn summaries_path(db_path: &Path) -> PathBuf {
+    db_path.join(SUMMARIES_KEYSPACE)
+}
+
+/// Opens the summaries keyspace for appending, creating it if needed.
+/// Whatever appends a recipe to the database appends its summary here
+pub fn open_writer(db_path: &Path) -> Result<DatabaseWriter<RecipeSummary>> {
+    let path = summaries_path(db_path);
+    fs::create_dir_all(&path)?;
+
+    match DatabaseWriter::open(&path) {
+        Err(Error::Io(err)) if err.kind() == ErrorKind::NotFound => DatabaseWriter::new(&path),
+        other => other,
+    }
+}
+
+/// Opens the summaries keyspace for lookups. None if there's no such
+/// keyspace, like in databases created before it existed
+pub fn open_reader(db_path: &Path) -> Result<Option<DatabaseReader<RecipeSummary>>> {
+    let path = summaries_path(db_path);
+    if path.is_dir() {
+        DatabaseReader::open(&path).map(Some)
+    } else {
+        Ok(None)
+    }
+}
+
+/// Looks up the summary of every given recipe, in the same order (None
+/// for recipes that aren't in the database). Recipes without one in
+/// `summaries` (or all of them, without a keyspace) are read in full
+/// from `recipes` and summarized
+pub fn find_each(
+    summaries: Option<&DatabaseReader<RecipeSummary>>,
+    recipes: &DatabaseReader<Recipe>,
+    ids: &[RecipeId],
+) -> Result<Vec<Option<RecipeSummary>>> {
+    let mut found = match summaries {
+        Some(summaries) => summaries.find_each(ids)?,
+        None => ids.iter().map(|_| None).collect(),
+    };
+
+    let missing = ids
+        .iter()
+        .zip(found.iter())
+        .filter(|(_id, summary)| summary.is_none())
+        .map(|(id, _summary)| *id)
+        .collect::<Vec<_>>();
+
+    if !missing.is_empty() {
+        let mut recipes = recipes.find_each(&missing)?.into_iter();
+        for summary in found.iter_mut().filter(|summary| summary.is_none()) {
+            *summary = recipes
+                .next()
+                .expect("one per missing summary")
+                .map(|recipe| RecipeSummary::from(&recipe));
+        }
+    }
+
+    Ok(found)
+}
+
+/// Like `find_each`, for a single recipe
+pub fn find_by_id(
+    summaries: Option<&DatabaseReader<RecipeSummary>>,
+    recipes: &DatabaseReader<Recipe>,
+    id: RecipeId,
+) -> Result<Option<RecipeSummary>> {
+    Ok(find_each(summaries, recipes, &[id])?.pop().flatten())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn recipe(recipe_id: RecipeId) -> Recipe {
+        Recipe::sample(recipe_id, &format!("Recipe {}", recipe_id))
+    }
+
+    #[test]
+    fn falls_back_to_full_recipes() -> Result<()> {
+        let db_dir = tempfile::tempdir()?;
+
+        let mut db = DatabaseWriter::new(db_dir.path())?;
+        for id in 0..4 {
+            db.append(&recipe(id))?;
+        }
+        drop(db);
+
+        let recipes = DatabaseReader::<Recipe>::open(db_dir.path())?;
+        assert!(open_reader(db_dir.path())?.is_none());
+
+        let without_keyspace = find_each(None, &recipes, &[3, 42, 0])?;
+        assert_eq!("Recipe 3", without_keyspace[0].as_ref().unwrap().name);
+        assert_eq!(None, without_keyspace[1]);
+        assert_eq!("Recipe 0", without_keyspace[2].as_ref().unwrap().name);
+
+        // Summaries win over the recipes, so a summary that says
+        // otherwise shows the keyspace was read
+        let mut writer = open_writer(db_dir.path())?;
+        let mut summary = RecipeSummary::from(&recipe(1));
+        summary.name = "From the keyspace".to_owned();
+        writer.append(&summary)?;
+        drop(writer);
+
+        let summaries = open_reader(db_dir.path())?.expect("keyspace exists");
+        let found = find_each(Some(&summaries), &recipes, &[1, 2, 42])?;
+        assert_eq!("From the keyspace", found[0].as_ref().unwrap().name);
+        assert_eq!("Recipe 2", found[1].as_ref().unwrap().name);
+        assert_eq!(None, found[2]);
+
+        assert_eq!(
+            Some(2),
+            find_by_id(Some(&summaries), &recipes, 2)?.map(|s| s.recipe_id)
+        );
+        assert_eq!(None, find_by_id(Some(&summaries), &recipes, 42)?);
+
+        Ok(())
+    }
+}
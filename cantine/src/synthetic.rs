@@ -0,0 +1,128 @@
+use uuid::Uuid;
+
+use crate::model::{Recipe, RecipeId};
+
+/// Makes up any number of recipes out of a few real ones, for
+/// benchmarks: each is a copy of a template with fresh ids and its
+/// ingredients, name and numbers mixed with those of other templates,
+/// so that terms and fast field values spread like in a real corpus.
+/// The same seed and templates always yield the same recipes
+pub struct Synthetic<'a> {
+    templates: &'a [Recipe],
+    state: u64,
+    next_id: RecipeId,
+}
+
+impl<'a> Synthetic<'a> {
+    /// # Panics
+    ///
+    /// Panics if there are no templates
+    pub fn new(templates: &'a [Recipe], seed: u64) -> Self {
+        assert!(!templates.is_empty(), "Need at least one template recipe");
+        Self {
+            templates,
+            state: seed,
+            next_id: 1,
+        }
+    }
+
+    // splitmix64: tiny, fast and good enough to shuffle test data
+    fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    fn template(&mut self) -> &'a Recipe {
+        let idx = self.next_u64() as usize % self.templates.len();
+        &self.templates[idx]
+    }
+
+    fn scale(&mut self, value: u32) -> u32 {
+        // Somewhere between half and one and a half times the original
+        let factor = 0.5 + (self.next_u64() % 1000) as f64 / 1000.0;
+        (f64::from(value) * factor) as u32
+    }
+}
+
+impl<'a> Iterator for Synthetic<'a> {
+    type Item = Recipe;
+
+    fn next(&mut self) -> Option<Recipe> {
+        let mut recipe = self.template().clone();
+
+        recipe.recipe_id = self.next_id;
+        self.next_id += 1;
+        recipe.uuid =
+            Uuid::from_u128(u128::from(self.next_u64()) << 64 | u128::from(self.next_u64()));
+        recipe.similar_recipe_ids.clear();
+        recipe.variant_of = None;
+
+        let other = self.template();
+        recipe.name = format!("{} {}", recipe.name, other.name);
+        if let Some(ingredient) = other.ingredients.first() {
+            let at = self.next_u64() as usize % (recipe.ingredients.len() + 1);
+            recipe.ingredients.insert(at, ingredient.clone());
+        }
+        recipe.features.num_ingredients = recipe.ingredients.len().min(255) as u8;
+
+        recipe.features.instructions_length = self.scale(recipe.features.instructions_length);
+        recipe.features.total_time = recipe.features.total_time.map(|t| self.scale(t));
+        recipe.features.calories = recipe.features.calories.map(|c| self.scale(c));
+
+        Some(recipe)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::model::Features;
+
+    fn template(name: &str) -> Recipe {
+        Recipe {
+            uuid: Uuid::nil(),
+            crawl_url: String::new(),
+            ingredients: vec![format!("{} flour", name)],
+            instructions: Vec::new(),
+            similar_recipe_ids: vec![1],
+            features: Features {
+                num_ingredients: 1,
+                calories: Some(100),
+                ..Features::default()
+            },
+            variant_of: Some(1),
+            ..Recipe::sample(42, name)
+        }
+    }
+
+    #[test]
+    fn recipes_are_fresh_and_reproducible() {
+        let templates = vec![template("bread"), template("cake")];
+
+        let recipes = Synthetic::new(&templates, 7).take(100).collect::<Vec<_>>();
+        let again = Synthetic::new(&templates, 7).take(100).collect::<Vec<_>>();
+
+        for (idx, (recipe, same)) in recipes.iter().zip(&again).enumerate() {
+            assert_eq!(idx as u64 + 1, recipe.recipe_id);
+            assert_eq!(recipe.uuid, same.uuid);
+            assert_eq!(recipe.name, same.name);
+            assert_eq!(2, recipe.ingredients.len());
+            assert_eq!(2, recipe.features.num_ingredients);
+            assert!(recipe.similar_recipe_ids.is_empty());
+            assert!(recipe.variant_of.is_none());
+
+            let calories = recipe.features.calories.unwrap();
+            assert!(calories >= 50 && calories < 150);
+        }
+
+        let uuids = recipes
+            .iter()
+            .map(|recipe| recipe.uuid)
+            .collect::<std::collections::HashSet<_>>();
+        assert_eq!(100, uuids.len());
+    }
+}
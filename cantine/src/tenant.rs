@@ -0,0 +1,274 @@
+use std::ops::Deref;
+
+use tantivy::{
+    collector::{Collector, SegmentCollector},
+    fastfield::FastFieldReader,
+    query::{BooleanQuery, Occur, Query, RangeQuery},
+    schema::Field,
+    DocId, Result, Score, SegmentLocalId, SegmentReader, TantivyError,
+};
+
+use crate::index::RecipeIndex;
+
+pub type TenantId = u64;
+
+/// Where recipes without a tenant belong
+pub const DEFAULT_TENANT: TenantId = 0;
+
+/// Narrows `query` down to the documents of `tenant`, as recorded in
+/// the u64 fast field `field`. The tenant condition doesn't affect
+/// scores
+pub fn scoped_query(field: Field, tenant: TenantId, query: &dyn Query) -> BooleanQuery {
+    BooleanQuery::from(vec![
+        (Occur::Must, query.box_clone()),
+        (
+            Occur::Must,
+            Box::new(RangeQuery::new_u64(field, tenant..tenant + 1)) as Box<dyn Query>,
+        ),
+    ])
+}
+
+/// Wraps a collector, only letting the documents of a tenant through.
+/// A safety net for when a query isn't (or can't be) narrowed down
+/// via `scoped_query`
+pub struct TenantCollector<C> {
+    field: Field,
+    tenant: TenantId,
+    inner: C,
+}
+
+impl<C: Collector> TenantCollector<C> {
+    /// Creates a collector that only gives `collector` the documents
+    /// with `tenant` in the u64 fast field `field`
+    pub fn new(field: Field, tenant: TenantId, collector: C) -> Self {
+        Self {
+            field,
+            tenant,
+            inner: collector,
+        }
+    }
+}
+
+impl<C: Collector> Collector for TenantCollector<C> {
+    type Fruit = C::Fruit;
+    type Child = TenantSegmentCollector<C::Child>;
+
+    fn for_segment(
+        &self,
+        segment_id: SegmentLocalId,
+        reader: &SegmentReader,
+    ) -> Result<Self::Child> {
+        let tenants = reader.fast_fields().u64(self.field).ok_or_else(|| {
+            TantivyError::SchemaError(format!("{:?} is not a u64 fast field", self.field))
+        })?;
+
+        Ok(TenantSegmentCollector {
+            tenants,
+            tenant: self.tenant,
+            inner: self.inner.for_segment(segment_id, reader)?,
+        })
+    }
+
+    fn requires_scoring(&self) -> bool {
+        self.inner.requires_scoring()
+    }
+
+    fn merge_fruits(
+        &self,
+        fruits: Vec<<Self::Child as SegmentCollector>::Fruit>,
+    ) -> Result<Self::Fruit> {
+        self.inner.merge_fruits(fruits)
+    }
+}
+
+/// The per-segment part of `TenantCollector`
+pub struct TenantSegmentCollector<C> {
+    tenants: FastFieldReader<u64>,
+    tenant: TenantId,
+    inner: C,
+}
+
+impl<C: SegmentCollector> SegmentCollector for TenantSegmentCollector<C> {
+    type Fruit = C::Fruit;
+
+    fn collect(&mut self, doc: DocId, score: Score) {
+        if self.tenants.get(doc) == self.tenant {
+            self.inner.collect(doc, score);
+        }
+    }
+
+    fn harvest(self) -> Self::Fruit {
+        self.inner.harvest()
+    }
+}
+
+/// A `RecipeIndex` that only ever sees the recipes of one tenant.
+///
+/// Every search it does is narrowed down to the tenant and every
+/// collector it uses skips the documents of other tenants, so no
+/// query, however crafted, reaches them. Looking a recipe of another
+/// tenant up (`similar_to`, `explain`) finds nothing
+#[derive(Clone)]
+pub struct TenantScopedIndex {
+    index: RecipeIndex,
+    tenant: TenantId,
+}
+
+impl TenantScopedIndex {
+    pub fn new(index: &RecipeIndex, tenant: TenantId) -> Self {
+        let mut index = index.clone();
+        index.tenant_scope = Some(tenant);
+        Self { index, tenant }
+    }
+
+    pub fn tenant(&self) -> TenantId {
+        self.tenant
+    }
+}
+
+impl Deref for TenantScopedIndex {
+    type Target = RecipeIndex;
+
+    fn deref(&self) -> &RecipeIndex {
+        &self.index
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use tantivy::{
+        collector::{Count, TopDocs},
+        query::{AllQuery, TermQuery},
+        schema::{IndexRecordOption, SchemaBuilder},
+        Index, Term,
+    };
+    use tique::budget::Budget;
+
+    use crate::{
+        index::SearchBudget,
+        model::{Recipe, RecipeId, Sort},
+    };
+
+    fn recipe(recipe_id: RecipeId, tenant_id: Option<TenantId>) -> Recipe {
+        Recipe {
+            ingredients: vec!["garlic".to_owned(), "bread".to_owned()],
+            instructions: vec!["toast the bread with the garlic".to_owned()],
+            tenant_id,
+            ..Recipe::sample(recipe_id, &format!("Garlic bread {}", recipe_id))
+        }
+    }
+
+    #[test]
+    fn scoped_searches_only_see_their_tenant() -> Result<()> {
+        let mut builder = SchemaBuilder::new();
+        let recipe_index = RecipeIndex::from(&mut builder);
+        let index = Index::create_in_ram(builder.build());
+        let mut writer = index.writer_with_num_threads(1, 3_000_000)?;
+
+        for id in 0..10 {
+            let tenant = match id % 3 {
+                0 => None,
+                tenant => Some(tenant),
+            };
+            writer.add_document(recipe_index.make_document(&recipe(id, tenant)));
+            // Multiple segments
+            if id == 4 {
+                writer.commit()?;
+            }
+        }
+        writer.commit()?;
+
+        let searcher = index.reader()?.searcher();
+        assert_eq!(10, searcher.search(&AllQuery, &Count)?);
+
+        let first = TenantScopedIndex::new(&recipe_index, 1);
+        let (total, mut found, _after) =
+            first.search(&searcher, &AllQuery, 10, Sort::Relevance, None)?;
+        found.sort();
+        assert_eq!(vec![1, 4, 7], found);
+        assert_eq!(3, total);
+        assert_eq!(3, first.count(&searcher, &AllQuery)?);
+
+        // Recipes without a tenant are in the default one
+        let default = TenantScopedIndex::new(&recipe_index, DEFAULT_TENANT);
+        assert_eq!(4, default.count(&searcher, &AllQuery)?);
+
+        // Other tenants' recipes can't be looked up, not even by id
+        let by_id = TermQuery::new(
+            Term::from_field_u64(recipe_index.id, 2),
+            IndexRecordOption::Basic,
+        );
+        assert_eq!(0, first.count(&searcher, &by_id)?);
+        assert!(!first.exists(&searcher, &by_id)?);
+        assert!(recipe_index.exists(&searcher, &by_id)?);
+        assert!(first.exists(&searcher, &AllQuery)?);
+        assert!(first.similar_to(&searcher, 2, 10)?.is_none());
+        assert!(first.explain(&searcher, &AllQuery, &[], 2)?.is_none());
+
+        let similar = first.similar_to(&searcher, 1, 10)?.expect("recipe exists");
+        assert!(!similar.is_empty());
+        assert!(similar.iter().all(|id| id % 3 == 1));
+
+        // The unscoped index is left alone
+        assert_eq!(10, recipe_index.count(&searcher, &AllQuery)?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn scoped_searches_stay_within_budget() -> Result<()> {
+        let mut builder = SchemaBuilder::new();
+        let recipe_index = RecipeIndex::from(&mut builder);
+        let index = Index::create_in_ram(builder.build());
+        let mut writer = index.writer_with_num_threads(1, 3_000_000)?;
+
+        for id in 0..10 {
+            writer.add_document(recipe_index.make_document(&recipe(id, Some(id % 2))));
+        }
+        writer.commit()?;
+
+        let searcher = index.reader()?.searcher();
+
+        let budget = SearchBudget::new(Budget::default().with_max_docs_visited(2));
+        let limited = recipe_index.clone().with_budget(budget.clone());
+        let first = TenantScopedIndex::new(&limited, 1);
+        let (total, found, _after) =
+            first.search(&searcher, &AllQuery, 10, Sort::Relevance, None)?;
+        assert!(budget.is_exhausted());
+        assert_eq!(2, total);
+        assert!(found.iter().all(|id| id % 2 == 1));
+
+        // Plenty of budget, every recipe of the tenant
+        let budget = SearchBudget::new(Budget::default().with_max_docs_visited(5));
+        let first = TenantScopedIndex::new(&recipe_index.clone().with_budget(budget.clone()), 1);
+        assert_eq!(5, first.count(&searcher, &AllQuery)?);
+        assert!(!budget.is_exhausted());
+
+        Ok(())
+    }
+
+    #[test]
+    fn collector_skips_other_tenants() -> Result<()> {
+        let mut builder = SchemaBuilder::new();
+        let recipe_index = RecipeIndex::from(&mut builder);
+        let index = Index::create_in_ram(builder.build());
+        let mut writer = index.writer_with_num_threads(1, 3_000_000)?;
+
+        for id in 0..6 {
+            writer.add_document(recipe_index.make_document(&recipe(id, Some(id % 2))));
+        }
+        writer.commit()?;
+
+        let searcher = index.reader()?.searcher();
+        let collector =
+            TenantCollector::new(recipe_index.tenant_id, 1, (Count, TopDocs::with_limit(10)));
+        let (count, top) = searcher.search(&AllQuery, &collector)?;
+
+        assert_eq!(3, count);
+        assert_eq!(3, top.len());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,328 @@
+use std::path::Path;
+
+use tantivy::{query::Query, IndexWriter, Result};
+
+use crate::{
+    backfill,
+    index::RecipeIndex,
+    model::{Features, Recipe, RecipeId, Sort},
+};
+
+/// How many matching ids to fetch per search
+const BATCH_SIZE: usize = 1000;
+
+/// A change to apply to every recipe matching a query. See `run`
+#[derive(Debug, Clone, PartialEq)]
+pub enum Transform {
+    /// Sets an optional feature, like `calories=250`
+    Set { feature: String, value: f64 },
+    /// Unsets an optional feature
+    Clear { feature: String },
+    /// Marks a diet (say: `vegan`) as fitting the recipe or not
+    Flag { diet: String, on: bool },
+}
+
+impl Transform {
+    /// Parses a command line option: `--set FEATURE=VALUE`,
+    /// `--clear FEATURE` or `--flag DIET=on|off`
+    pub fn from_arg(option: &str, arg: &str) -> std::result::Result<Self, String> {
+        let transform = match option {
+            "--set" => {
+                let (feature, value) = split_assignment(arg)?;
+                let value = value
+                    .parse()
+                    .map_err(|_| format!("Invalid value for {}: {}", feature, value))?;
+                Transform::Set {
+                    feature: feature.to_owned(),
+                    value,
+                }
+            }
+            "--clear" => Transform::Clear {
+                feature: arg.to_owned(),
+            },
+            "--flag" => {
+                let (diet, state) = split_assignment(arg)?;
+                let on = match state {
+                    "on" => true,
+                    "off" => false,
+                    _ => return Err(format!("Diet flags are either on or off, not {}", state)),
+                };
+                Transform::Flag {
+                    diet: diet.to_owned(),
+                    on,
+                }
+            }
+            _ => return Err(format!("Unknown transform {}", option)),
+        };
+
+        // Catches unknown features and values that don't fit them
+        // before touching anything
+        transform
+            .try_apply(&mut Features::default())
+            .map(|_changed| transform)
+    }
+
+    /// Applies the change to `recipe`. Yields whether anything changed
+    pub fn apply(&self, recipe: &mut Recipe) -> bool {
+        self.try_apply(&mut recipe.features)
+            .expect("transforms are validated when parsed")
+    }
+
+    fn try_apply(&self, features: &mut Features) -> std::result::Result<bool, String> {
+        match self {
+            Transform::Set { feature, value } => match feature_mut(features, feature) {
+                Some(FeatureMut::U32(current)) => whole(*value, u64::from(std::u32::MAX))
+                    .map(|value| replace(current, Some(value as u32))),
+                Some(FeatureMut::U64(current)) => {
+                    whole(*value, std::u64::MAX).map(|value| replace(current, Some(value)))
+                }
+                Some(FeatureMut::F32(current)) => Ok(replace(current, Some(*value as f32))),
+                None => Err(format!("Unknown feature {}", feature)),
+            },
+            Transform::Clear { feature } => match feature_mut(features, feature) {
+                Some(FeatureMut::U32(current)) => Ok(replace(current, None)),
+                Some(FeatureMut::U64(current)) => Ok(replace(current, None)),
+                Some(FeatureMut::F32(current)) => Ok(replace(current, None)),
+                None => Err(format!("Unknown feature {}", feature)),
+            },
+            Transform::Flag { diet, on } => {
+                match feature_mut(features, &format!("diet_{}", diet)) {
+                    Some(FeatureMut::F32(current)) => {
+                        Ok(replace(current, Some(if *on { 1.0 } else { 0.0 })))
+                    }
+                    _ => Err(format!("Unknown diet {}", diet)),
+                }
+            }
+        }
+    }
+}
+
+/// The ids of every recipe matching `query`, paging through the
+/// results like exports do
+pub fn matching_ids(
+    recipe_index: &RecipeIndex,
+    writer: &IndexWriter,
+    query: &dyn Query,
+) -> Result<Vec<RecipeId>> {
+    let searcher = writer.index().reader()?.searcher();
+
+    let mut ids = Vec::new();
+    let mut after = None;
+    loop {
+        let (_total, found, next) =
+            recipe_index.search(&searcher, query, BATCH_SIZE, Sort::Relevance, after)?;
+        ids.extend(found);
+
+        if next.is_none() {
+            break;
+        }
+        after = next;
+    }
+
+    ids.sort();
+    Ok(ids)
+}
+
+/// Applies every one of `transforms` to the recipes matching `query`,
+/// appending the changed ones to the database at `db_path` and
+/// replacing their documents in the index. The bulk counterpart of
+/// editing recipes one by one.
+///
+/// Recipes are matched once, before changing anything, so a change
+/// that makes more recipes match doesn't cascade. Commits like
+/// `backfill::run` does. Returns how many recipes changed.
+pub fn run(
+    db_path: &Path,
+    writer: &mut IndexWriter,
+    recipe_index: &RecipeIndex,
+    query: &dyn Query,
+    transforms: &[Transform],
+    commit_every: usize,
+) -> Result<usize> {
+    let ids = matching_ids(recipe_index, writer, query)?;
+    log::info!("Update by query: {} recipes match", ids.len());
+
+    let update = |recipe: &mut Recipe| {
+        transforms.iter().fold(false, |changed, transform| {
+            transform.apply(recipe) || changed
+        })
+    };
+
+    backfill::apply(db_path, writer, recipe_index, &ids, &update, commit_every)
+}
+
+enum FeatureMut<'a> {
+    U32(&'a mut Option<u32>),
+    U64(&'a mut Option<u64>),
+    F32(&'a mut Option<f32>),
+}
+
+// Only the optional features: the others are computed from the
+// recipe contents
+fn feature_mut<'a>(features: &'a mut Features, name: &str) -> Option<FeatureMut<'a>> {
+    Some(match name {
+        "prep_time" => FeatureMut::U32(&mut features.prep_time),
+        "total_time" => FeatureMut::U32(&mut features.total_time),
+        "cook_time" => FeatureMut::U32(&mut features.cook_time),
+        "total_time_estimate" => FeatureMut::U32(&mut features.total_time_estimate),
+        "calories" => FeatureMut::U32(&mut features.calories),
+        "fat_content" => FeatureMut::F32(&mut features.fat_content),
+        "carb_content" => FeatureMut::F32(&mut features.carb_content),
+        "protein_content" => FeatureMut::F32(&mut features.protein_content),
+        "diet_lowcarb" => FeatureMut::F32(&mut features.diet_lowcarb),
+        "diet_vegetarian" => FeatureMut::F32(&mut features.diet_vegetarian),
+        "diet_vegan" => FeatureMut::F32(&mut features.diet_vegan),
+        "diet_keto" => FeatureMut::F32(&mut features.diet_keto),
+        "diet_paleo" => FeatureMut::F32(&mut features.diet_paleo),
+        "added_at" => FeatureMut::U64(&mut features.added_at),
+        _ => return None,
+    })
+}
+
+fn replace<T: PartialEq>(current: &mut Option<T>, value: Option<T>) -> bool {
+    if *current == value {
+        false
+    } else {
+        *current = value;
+        true
+    }
+}
+
+fn whole(value: f64, max: u64) -> std::result::Result<u64, String> {
+    if value.fract() == 0.0 && value >= 0.0 && value <= max as f64 {
+        Ok(value as u64)
+    } else {
+        Err(format!("{} is not a valid whole number", value))
+    }
+}
+
+fn split_assignment(arg: &str) -> std::result::Result<(&str, &str), String> {
+    let mut parts = arg.splitn(2, '=');
+    match (parts.next(), parts.next()) {
+        (Some(name), Some(value)) if !name.is_empty() => Ok((name, value)),
+        _ => Err(format!("Expected NAME=VALUE, got {}", arg)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use tantivy::{collector::Count, query::RangeQuery, schema::SchemaBuilder, Index};
+
+    use crate::database::{DatabaseReader, DatabaseWriter};
+
+    fn recipe(recipe_id: u64) -> Recipe {
+        Recipe {
+            features: Features {
+                num_ingredients: (recipe_id % 3) as u8,
+                calories: Some(100),
+                ..Features::default()
+            },
+            ..Recipe::sample(recipe_id, &format!("Recipe {}", recipe_id))
+        }
+    }
+
+    #[test]
+    fn transforms_are_validated() {
+        for (option, arg) in &[
+            ("--set", "calories=250"),
+            ("--set", "fat_content=1.5"),
+            ("--set", "added_at=1600000000"),
+            ("--clear", "cook_time"),
+            ("--flag", "vegan=on"),
+            ("--flag", "keto=off"),
+        ] {
+            assert!(
+                Transform::from_arg(option, arg).is_ok(),
+                "{} {}",
+                option,
+                arg
+            );
+        }
+
+        for (option, arg) in &[
+            ("--set", "calories"),
+            ("--set", "calories=lots"),
+            ("--set", "calories=-1"),
+            ("--set", "calories=1.5"),
+            ("--set", "num_ingredients=3"),
+            ("--clear", "name"),
+            ("--flag", "vegan=yes"),
+            ("--flag", "carnivore=on"),
+            ("--drop", "calories"),
+        ] {
+            assert!(
+                Transform::from_arg(option, arg).is_err(),
+                "{} {}",
+                option,
+                arg
+            );
+        }
+    }
+
+    #[test]
+    fn only_matching_recipes_change() -> Result<()> {
+        let db_dir = tempfile::tempdir()?;
+
+        let mut builder = SchemaBuilder::new();
+        let recipe_index = RecipeIndex::from(&mut builder);
+        let index = Index::create_in_ram(builder.build());
+        let mut writer = index.writer_with_num_threads(1, 3_000_000)?;
+
+        let mut db = DatabaseWriter::new(db_dir.path())?;
+        for id in 0..12 {
+            let recipe = recipe(id);
+            db.append(&recipe)?;
+            writer.add_document(recipe_index.make_document(&recipe));
+        }
+        drop(db);
+        writer.commit()?;
+
+        let transforms = vec![
+            Transform::from_arg("--set", "calories=250").unwrap(),
+            Transform::from_arg("--flag", "vegan=on").unwrap(),
+        ];
+        let query = RangeQuery::new_u64(recipe_index.features.num_ingredients, 0..1);
+
+        let changed = run(
+            db_dir.path(),
+            &mut writer,
+            &recipe_index,
+            &query,
+            &transforms,
+            3,
+        )?;
+        assert_eq!(4, changed);
+
+        let db = DatabaseReader::<Recipe>::open(db_dir.path())?;
+        for id in 0..12 {
+            let found = db.find_by_id(id).unwrap()?;
+            if id % 3 == 0 {
+                assert_eq!(Some(250), found.features.calories);
+                assert_eq!(Some(1.0), found.features.diet_vegan);
+            } else {
+                assert_eq!(Some(100), found.features.calories);
+                assert_eq!(None, found.features.diet_vegan);
+            }
+        }
+
+        let searcher = index.reader()?.searcher();
+        assert_eq!(12, searcher.num_docs());
+        let updated = RangeQuery::new_u64(recipe_index.features.calories, 250..251);
+        assert_eq!(4, searcher.search(&updated, &Count)?);
+
+        // Already up to date
+        let changed = run(
+            db_dir.path(),
+            &mut writer,
+            &recipe_index,
+            &query,
+            &transforms,
+            3,
+        )?;
+        assert_eq!(0, changed);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,194 @@
+use tantivy::{
+    schema::{Field, FieldType, Schema},
+    Result, Searcher, SegmentReader, TantivyError,
+};
+
+/// Which parts of the index `RecipeIndex::warm` reads through
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct WarmupOptions {
+    /// Numeric or bytes fast fields, read for every document
+    pub fast_fields: Vec<Field>,
+    /// Indexed text fields, whose term dictionaries are streamed
+    /// in full
+    pub term_dictionaries: Vec<Field>,
+}
+
+impl WarmupOptions {
+    /// Every fast field and every indexed text field in the schema
+    pub fn all(schema: &Schema) -> Self {
+        let mut options = Self::default();
+        for (field, _entry) in schema.fields() {
+            options.add(schema, field);
+        }
+        options
+    }
+
+    /// Just the given fields, by name. Fails for unknown fields and
+    /// for fields with nothing to warm
+    pub fn from_names<S: AsRef<str>>(schema: &Schema, names: &[S]) -> Result<Self> {
+        let mut options = Self::default();
+
+        for name in names {
+            let name = name.as_ref();
+            let field = schema
+                .get_field(name)
+                .ok_or_else(|| TantivyError::InvalidArgument(format!("Unknown field {}", name)))?;
+
+            if !options.add(schema, field) {
+                return Err(TantivyError::InvalidArgument(format!(
+                    "Field {} is neither fast nor indexed text",
+                    name
+                )));
+            }
+        }
+
+        Ok(options)
+    }
+
+    fn add(&mut self, schema: &Schema, field: Field) -> bool {
+        match schema.get_field_entry(field).field_type() {
+            FieldType::U64(opts)
+            | FieldType::I64(opts)
+            | FieldType::F64(opts)
+            | FieldType::Date(opts)
+                if opts.is_fast() =>
+            {
+                self.fast_fields.push(field);
+                true
+            }
+            FieldType::Bytes => {
+                self.fast_fields.push(field);
+                true
+            }
+            FieldType::Str(opts) if opts.get_indexing_options().is_some() => {
+                self.term_dictionaries.push(field);
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+/// How much of the index a warmup went through
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct WarmupStats {
+    pub num_segments: usize,
+    /// Values read from fast fields
+    pub num_values: u64,
+    /// Terms read from term dictionaries
+    pub num_terms: u64,
+}
+
+/// Reads through the parts of every segment in `options`, so that
+/// their pages are loaded before any search needs them
+pub fn warm(searcher: &Searcher, options: &WarmupOptions) -> Result<WarmupStats> {
+    let mut stats = WarmupStats::default();
+
+    for reader in searcher.segment_readers() {
+        stats.num_segments += 1;
+
+        for &field in &options.fast_fields {
+            stats.num_values += warm_fast_field(reader, field)?;
+        }
+
+        for &field in &options.term_dictionaries {
+            let inverted_index = reader.inverted_index(field);
+            let mut stream = inverted_index.terms().stream();
+            while stream.next().is_some() {
+                stats.num_terms += 1;
+            }
+        }
+    }
+
+    Ok(stats)
+}
+
+fn warm_fast_field(reader: &SegmentReader, field: Field) -> Result<u64> {
+    let fast_fields = reader.fast_fields();
+    let max_doc = reader.max_doc();
+
+    // What's read is summed up just so it can't be optimized away
+    let mut checksum = 0u64;
+
+    if let Some(values) = fast_fields.u64_lenient(field) {
+        for doc in 0..max_doc {
+            checksum = checksum.wrapping_add(values.get(doc));
+        }
+    } else if let Some(values) = fast_fields.bytes(field) {
+        for doc in 0..max_doc {
+            checksum = checksum.wrapping_add(values.get_bytes(doc).len() as u64);
+        }
+    } else {
+        return Err(TantivyError::SchemaError(format!(
+            "{:?} is not a single-valued fast field",
+            field
+        )));
+    }
+
+    log::trace!("Warmed {:?}: checksum={}", field, checksum);
+
+    Ok(u64::from(max_doc))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use tantivy::{
+        doc,
+        schema::{SchemaBuilder, FAST, INDEXED, STORED, TEXT},
+        Index,
+    };
+
+    #[test]
+    fn options_and_stats() -> Result<()> {
+        let mut builder = SchemaBuilder::new();
+        let id = builder.add_u64_field("id", FAST | STORED);
+        let body = builder.add_text_field("body", TEXT);
+        let rank = builder.add_f64_field("rank", FAST);
+        let blob = builder.add_bytes_field("blob");
+        builder.add_u64_field("plain", INDEXED);
+        let schema = builder.build();
+
+        let options = WarmupOptions::all(&schema);
+        assert_eq!(vec![id, rank, blob], options.fast_fields);
+        assert_eq!(vec![body], options.term_dictionaries);
+
+        assert_eq!(
+            WarmupOptions {
+                fast_fields: vec![rank],
+                term_dictionaries: vec![body],
+            },
+            WarmupOptions::from_names(&schema, &["body", "rank"])?
+        );
+        assert!(WarmupOptions::from_names(&schema, &["nope"]).is_err());
+        assert!(WarmupOptions::from_names(&schema, &["plain"]).is_err());
+
+        let index = Index::create_in_ram(schema);
+        let mut writer = index.writer_with_num_threads(1, 3_000_000)?;
+        for i in 0..10u64 {
+            writer.add_document(doc!(
+                id => i,
+                body => format!("word{} common", i % 5),
+                rank => i as f64,
+                blob => vec![1u8, 2, 3]
+            ));
+            // Multiple segments
+            if i == 4 {
+                writer.commit()?;
+            }
+        }
+        writer.commit()?;
+
+        let searcher = index.reader()?.searcher();
+        let stats = warm(&searcher, &options)?;
+
+        assert_eq!(2, stats.num_segments);
+        // 3 fast fields, 10 docs
+        assert_eq!(30, stats.num_values);
+        // 5 distinct words + "common" in each segment
+        assert_eq!(12, stats.num_terms);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,339 @@
+use std::{
+    io,
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+use crossbeam_channel::{bounded, unbounded, Receiver, RecvTimeoutError, Sender};
+use tantivy::{IndexReader, IndexWriter, Result, TantivyError, Term};
+
+use crate::{
+    index::RecipeIndex,
+    metrics,
+    model::{Recipe, RecipeId},
+};
+
+/// A change to be applied to the index
+#[derive(Debug)]
+pub enum Operation {
+    /// Indexes a new recipe
+    Add(Recipe),
+    /// Replaces the recipe with the same id (or indexes it if there
+    /// was none)
+    Update(Recipe),
+    /// Removes the recipe with the given id, if any
+    Delete(RecipeId),
+}
+
+/// When to commit pending operations
+#[derive(Debug, Clone, Copy)]
+pub struct CommitPolicy {
+    /// Commit as soon as this many operations are pending
+    pub max_pending: usize,
+    /// Commit when the oldest pending operation is this old
+    pub max_delay: Duration,
+}
+
+impl Default for CommitPolicy {
+    fn default() -> Self {
+        Self {
+            max_pending: 10_000,
+            max_delay: Duration::from_secs(1),
+        }
+    }
+}
+
+enum Message {
+    Apply(Operation),
+    Commit(Sender<Result<u64>>),
+}
+
+/// Owns an `IndexWriter` in a background thread that applies the
+/// operations it receives, commits according to a `CommitPolicy`
+/// and reloads the reader after every commit.
+///
+/// Dropping the handle (or calling `close`) commits whatever is
+/// pending and waits for the thread to finish.
+pub struct IndexWriterHandle {
+    sender: Option<Sender<Message>>,
+    worker: Option<JoinHandle<Result<()>>>,
+}
+
+impl IndexWriterHandle {
+    /// Moves the writer into a new thread. The reader should use
+    /// `ReloadPolicy::Manual`: it gets reloaded after every commit.
+    pub fn spawn(
+        writer: IndexWriter,
+        reader: IndexReader,
+        recipe_index: RecipeIndex,
+        policy: CommitPolicy,
+    ) -> Self {
+        let (sender, receiver) = unbounded();
+
+        let worker = thread::spawn(move || {
+            Worker {
+                reader,
+                recipe_index,
+                generation: writer.commit_opstamp(),
+                writer,
+                policy,
+                pending: 0,
+                deadline: None,
+            }
+            .run(receiver)
+        });
+
+        Self {
+            sender: Some(sender),
+            worker: Some(worker),
+        }
+    }
+
+    pub fn add(&self, recipe: Recipe) -> Result<()> {
+        self.send(Message::Apply(Operation::Add(recipe)))
+    }
+
+    pub fn update(&self, recipe: Recipe) -> Result<()> {
+        self.send(Message::Apply(Operation::Update(recipe)))
+    }
+
+    pub fn delete(&self, recipe_id: RecipeId) -> Result<()> {
+        self.send(Message::Apply(Operation::Delete(recipe_id)))
+    }
+
+    pub fn apply(&self, operation: Operation) -> Result<()> {
+        self.send(Message::Apply(operation))
+    }
+
+    /// Commits every operation sent so far and waits until the
+    /// reader reflects them. Yields the generation of the commit
+    /// (see `generation::Visibility`), which is the one of the last
+    /// commit if nothing was pending
+    pub fn commit(&self) -> Result<u64> {
+        let (ack_sender, ack_receiver) = bounded(1);
+        self.send(Message::Commit(ack_sender))?;
+        ack_receiver.recv().map_err(|_| worker_gone())?
+    }
+
+    /// Commits pending operations and stops the background thread,
+    /// yielding any error it found
+    pub fn close(mut self) -> Result<()> {
+        self.shutdown()
+    }
+
+    fn send(&self, message: Message) -> Result<()> {
+        self.sender
+            .as_ref()
+            .expect("sender is only taken on shutdown")
+            .send(message)
+            .map_err(|_| worker_gone())
+    }
+
+    fn shutdown(&mut self) -> Result<()> {
+        // Disconnecting makes the worker commit and exit
+        drop(self.sender.take());
+
+        match self.worker.take().map(JoinHandle::join) {
+            Some(Ok(result)) => result,
+            Some(Err(_)) => Err(TantivyError::ErrorInThread(
+                "Index writer thread panicked".to_owned(),
+            )),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Drop for IndexWriterHandle {
+    fn drop(&mut self) {
+        if let Err(err) = self.shutdown() {
+            log::error!("Index writer thread failed: {}", err);
+        }
+    }
+}
+
+fn worker_gone() -> TantivyError {
+    io::Error::new(io::ErrorKind::BrokenPipe, "Index writer thread is gone").into()
+}
+
+struct Worker {
+    writer: IndexWriter,
+    reader: IndexReader,
+    recipe_index: RecipeIndex,
+    policy: CommitPolicy,
+    pending: usize,
+    deadline: Option<Instant>,
+    /// Of the last commit
+    generation: u64,
+}
+
+impl Worker {
+    fn run(mut self, receiver: Receiver<Message>) -> Result<()> {
+        loop {
+            let received = match self.deadline {
+                Some(deadline) => receiver.recv_deadline(deadline),
+                None => receiver.recv().map_err(|_| RecvTimeoutError::Disconnected),
+            };
+
+            match received {
+                Ok(Message::Apply(operation)) => {
+                    self.apply(operation);
+                    if self.pending >= self.policy.max_pending {
+                        self.commit()?;
+                    }
+                }
+                Ok(Message::Commit(ack)) => {
+                    let result = self.commit();
+                    let failed = result.is_err();
+                    // The caller may have given up waiting
+                    let _ = ack.send(result);
+                    if failed {
+                        return Err(TantivyError::ErrorInThread("Commit failed".to_owned()));
+                    }
+                }
+                Err(RecvTimeoutError::Timeout) => {
+                    self.commit()?;
+                }
+                Err(RecvTimeoutError::Disconnected) => return self.commit().map(|_| ()),
+            }
+        }
+    }
+
+    fn apply(&mut self, operation: Operation) {
+        match operation {
+            Operation::Add(recipe) => {
+                self.writer
+                    .add_document(self.recipe_index.make_document(&recipe));
+            }
+            Operation::Update(recipe) => {
+                self.writer
+                    .delete_term(Term::from_field_u64(self.recipe_index.id, recipe.recipe_id));
+                self.writer
+                    .add_document(self.recipe_index.make_document(&recipe));
+            }
+            Operation::Delete(recipe_id) => {
+                self.writer
+                    .delete_term(Term::from_field_u64(self.recipe_index.id, recipe_id));
+            }
+        }
+
+        self.pending += 1;
+        if self.deadline.is_none() {
+            self.deadline = Some(Instant::now() + self.policy.max_delay);
+        }
+    }
+
+    fn commit(&mut self) -> Result<u64> {
+        if self.pending > 0 {
+            let started = Instant::now();
+            self.generation = self.writer.commit()?;
+            metrics::commit_done(started);
+            self.reader.reload()?;
+            metrics::reader_reloaded();
+            log::debug!(
+                "Committed {} operations (generation {})",
+                self.pending,
+                self.generation
+            );
+        }
+
+        self.pending = 0;
+        self.deadline = None;
+        Ok(self.generation)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use tantivy::{query::TermQuery, schema::IndexRecordOption, schema::SchemaBuilder, Index};
+
+    fn setup(policy: CommitPolicy) -> Result<(IndexWriterHandle, IndexReader, RecipeIndex)> {
+        let mut builder = SchemaBuilder::new();
+        let recipe_index = RecipeIndex::from(&mut builder);
+        let index = Index::create_in_ram(builder.build());
+
+        let reader = index
+            .reader_builder()
+            .reload_policy(tantivy::ReloadPolicy::Manual)
+            .try_into()?;
+        let writer = index.writer_with_num_threads(1, 3_000_000)?;
+
+        let handle = IndexWriterHandle::spawn(writer, reader.clone(), recipe_index.clone(), policy);
+
+        Ok((handle, reader, recipe_index))
+    }
+
+    fn count_named(reader: &IndexReader, recipe_index: &RecipeIndex, word: &str) -> usize {
+        let query = TermQuery::new(
+            Term::from_field_text(recipe_index.name, word),
+            IndexRecordOption::Basic,
+        );
+        reader
+            .searcher()
+            .search(&query, &tantivy::collector::Count)
+            .unwrap()
+    }
+
+    #[test]
+    fn operations_are_visible_after_commit() -> Result<()> {
+        let (handle, reader, recipe_index) = setup(CommitPolicy {
+            max_pending: 1000,
+            max_delay: Duration::from_secs(3600),
+        })?;
+
+        handle.add(Recipe::sample(1, "pancakes"))?;
+        handle.add(Recipe::sample(2, "waffles"))?;
+        handle.add(Recipe::sample(3, "crepes"))?;
+        assert_eq!(0, reader.searcher().num_docs());
+
+        let first = handle.commit()?;
+        assert_eq!(3, reader.searcher().num_docs());
+
+        handle.update(Recipe::sample(1, "fluffy pancakes"))?;
+        handle.delete(2)?;
+        let second = handle.commit()?;
+        assert!(second > first);
+        // Nothing new to commit
+        assert_eq!(second, handle.commit()?);
+
+        assert_eq!(2, reader.searcher().num_docs());
+        assert_eq!(1, count_named(&reader, &recipe_index, "fluffy"));
+        assert_eq!(0, count_named(&reader, &recipe_index, "waffles"));
+
+        handle.add(Recipe::sample(4, "omelette"))?;
+        handle.close()?;
+        reader.reload()?;
+        assert_eq!(3, reader.searcher().num_docs());
+
+        Ok(())
+    }
+
+    #[test]
+    fn commit_policy_is_respected() -> Result<()> {
+        let (handle, reader, _recipe_index) = setup(CommitPolicy {
+            max_pending: 2,
+            max_delay: Duration::from_millis(50),
+        })?;
+
+        handle.add(Recipe::sample(1, "pancakes"))?;
+        handle.add(Recipe::sample(2, "waffles"))?;
+
+        // Size threshold
+        let start = Instant::now();
+        while reader.searcher().num_docs() != 2 {
+            assert!(start.elapsed() < Duration::from_secs(10));
+            thread::sleep(Duration::from_millis(5));
+        }
+
+        // Timer
+        handle.add(Recipe::sample(3, "crepes"))?;
+        let start = Instant::now();
+        while reader.searcher().num_docs() != 3 {
+            assert!(start.elapsed() < Duration::from_secs(10));
+            thread::sleep(Duration::from_millis(5));
+        }
+
+        Ok(())
+    }
+}
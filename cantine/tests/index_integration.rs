@@ -2,19 +2,38 @@ use serde_json;
 
 use once_cell::sync::Lazy;
 use std::cmp::Ordering;
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::convert::TryFrom;
 use tantivy::{
-    query::{AllQuery, RangeQuery},
-    schema::SchemaBuilder,
-    Index, Result,
+    collector::Count,
+    query::{AllQuery, Query, RangeQuery, TermQuery},
+    schema::{IndexRecordOption, SchemaBuilder, TextFieldIndexing, TextOptions, Value},
+    tokenizer::NgramTokenizer,
+    Index, Result, Term,
 };
 
 use cantine::{
-    index::RecipeIndex,
-    model::{Recipe, RecipeId, Sort},
+    analysis::{Analysis, Language},
+    collation::Collation,
+    database::{DatabaseReader, DatabaseWriter},
+    error::Error,
+    estimate,
+    geo::{GeoDistanceFilter, GeoDistanceQuery, GeoPoint},
+    index::{RecipeIndex, SearchBudget, TimingsRecorder},
+    model::{
+        FeaturesFilterQuery, GroupField, MatchedClauses, NumericFeature, PresenceField, Recipe,
+        RecipeCard, RecipeId, Sort,
+    },
+    pantry::{PantryFilter, PantryQuery},
+    runtime::{RuntimeField, RuntimeFilter, RuntimeFilterQuery},
+    stats::GlobalStats,
 };
 
-use tique::QueryParser;
+use tique::{
+    budget::Budget,
+    search::{MultiIndex, Normalization},
+    QueryParser,
+};
 
 struct GlobalData {
     index: Index,
@@ -128,6 +147,61 @@ fn num_ingredients_sort() -> Result<()> {
     Ok(())
 }
 
+#[test]
+fn name_sort_uses_collation_keys() -> Result<()> {
+    let reader = GLOBAL.index.reader()?;
+    let searcher = reader.searcher();
+
+    let (_total, found_ids, _next) =
+        GLOBAL
+            .cantine
+            .search(&searcher, &AllQuery, INDEX_SIZE, Sort::NameAsc, None)?;
+
+    assert_eq!(INDEX_SIZE, found_ids.len());
+
+    let mut last = None;
+    for id in found_ids {
+        let recipe = GLOBAL.db.get(&id).unwrap();
+        let current = Collation::Root.sort_key(recipe.name.as_str());
+        if let Some(prev) = last {
+            assert!(current >= prev);
+        }
+        last = Some(current)
+    }
+
+    Ok(())
+}
+
+#[test]
+fn name_sort_paginates_on_the_full_key() -> Result<()> {
+    let reader = GLOBAL.index.reader()?;
+    let searcher = reader.searcher();
+
+    let (_total, everything, _next) =
+        GLOBAL
+            .cantine
+            .search(&searcher, &AllQuery, INDEX_SIZE, Sort::Name, None)?;
+
+    let mut paginated = Vec::with_capacity(INDEX_SIZE);
+    let mut after = None;
+    loop {
+        let (_total, found_ids, next) =
+            GLOBAL
+                .cantine
+                .search(&searcher, &AllQuery, 7, Sort::Name, after)?;
+        paginated.extend(found_ids);
+
+        if next.is_none() {
+            break;
+        }
+        after = next;
+    }
+
+    assert_eq!(everything, paginated);
+
+    Ok(())
+}
+
 macro_rules! stress_sort_pagination {
     ($name: ident, $sort: expr, $field: ident, $type: ident, $range: ident, $order: expr) => {
         #[test]
@@ -327,3 +401,1119 @@ fn ascending_sort_works_for_relevance() -> Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn analysis_is_used_at_index_and_query_time() -> Result<()> {
+    let analysis = Analysis {
+        stemmer: Some(Language::English),
+        stopwords: vec!["with".to_owned()],
+        ..Analysis::default()
+    };
+
+    let mut builder = SchemaBuilder::new();
+    let cantine = RecipeIndex::create(&mut builder, &analysis);
+    let index = Index::create_in_ram(builder.build());
+    analysis.register(&index);
+
+    let mut writer = index.writer_with_num_threads(1, 50_000_000)?;
+    for recipe in GLOBAL.db.values() {
+        writer.add_document(cantine.make_document(recipe));
+    }
+    writer.commit()?;
+
+    let count = |index: &Index, cantine: &RecipeIndex, input: &str| -> Result<usize> {
+        let parser = QueryParser::new(index, vec![cantine.name, cantine.ingredients])?;
+        let searcher = index.reader()?.searcher();
+        Ok(parser
+            .parse(input)
+            .map_or(Ok(0), |query| searcher.search(&query, &Count))?)
+    };
+
+    let stemmed = count(&index, &cantine, "potatoes")?;
+    assert_eq!(stemmed, count(&index, &cantine, "potato")?);
+    assert!(stemmed > count(&GLOBAL.index, &GLOBAL.cantine, "potatoes")?);
+
+    // Stopwords are gone from both the index and the queries
+    assert_eq!(0, count(&index, &cantine, "with")?);
+    assert!(count(&GLOBAL.index, &GLOBAL.cantine, "with")? > 0);
+
+    Ok(())
+}
+
+#[test]
+fn two_phase_collection_finds_the_same_recipes() -> Result<()> {
+    let reader = GLOBAL.index.reader()?;
+    let searcher = reader.searcher();
+
+    let parser = QueryParser::new(
+        &GLOBAL.index,
+        vec![GLOBAL.cantine.name, GLOBAL.cantine.ingredients],
+    )?;
+    let query = parser.parse("salt sugar butter egg flour").unwrap();
+
+    let two_phase = GLOBAL.cantine.clone().with_two_phase(Some(5));
+
+    let mut after = None;
+    let mut two_phase_after = None;
+    loop {
+        let (_total, found_ids, next) =
+            GLOBAL
+                .cantine
+                .search(&searcher, &query, 10, Sort::Relevance, after)?;
+
+        let (_total, two_phase_ids, two_phase_next) =
+            two_phase.search(&searcher, &query, 10, Sort::Relevance, two_phase_after)?;
+
+        assert_eq!(found_ids, two_phase_ids);
+        assert_eq!(next.is_some(), two_phase_next.is_some());
+
+        if next.is_none() {
+            break;
+        }
+        after = next;
+        two_phase_after = two_phase_next;
+    }
+
+    Ok(())
+}
+
+#[test]
+fn federated_search_across_recipe_indices() -> Result<()> {
+    let mut builder = SchemaBuilder::new();
+    let cantine = RecipeIndex::from(&mut builder);
+    let schema = builder.build();
+
+    // Same schema, different sources: even and odd ids
+    let even = Index::create_in_ram(schema.clone());
+    let odd = Index::create_in_ram(schema);
+
+    let mut even_writer = even.writer_with_num_threads(1, 50_000_000)?;
+    let mut odd_writer = odd.writer_with_num_threads(1, 50_000_000)?;
+    for recipe in GLOBAL.db.values() {
+        if recipe.recipe_id % 2 == 0 {
+            even_writer.add_document(cantine.make_document(recipe));
+        } else {
+            odd_writer.add_document(cantine.make_document(recipe));
+        }
+    }
+    even_writer.commit()?;
+    odd_writer.commit()?;
+
+    let mut multi = MultiIndex::new(Normalization::Max);
+    let even_source = multi.add_source("even", even.reader()?);
+    multi.add_source("odd", odd.reader()?);
+
+    let parser = QueryParser::new(&even, vec![cantine.name, cantine.ingredients])?;
+    let query = parser.parse("chicken").unwrap();
+
+    let result = multi.search(&query, INDEX_SIZE)?;
+    let wanted = GLOBAL.index.reader()?.searcher().search(&query, &Count)?;
+    assert_eq!(wanted, result.total);
+    assert_eq!(wanted, result.items.len());
+
+    for (_score, addr) in &result.items {
+        let doc = result.doc(*addr)?;
+        let id = match doc.get_first(cantine.id) {
+            Some(Value::U64(id)) => *id,
+            _ => panic!("Found doc with non-U64 id field"),
+        };
+        assert_eq!(addr.source == even_source, id % 2 == 0);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn feature_percentiles_are_close_to_the_exact_ones() -> Result<()> {
+    let reader = GLOBAL.index.reader()?;
+    let searcher = reader.searcher();
+
+    let percentiles = GLOBAL.cantine.feature_percentiles(
+        &searcher,
+        &AllQuery,
+        &[NumericFeature::Calories, NumericFeature::NumIngredients],
+    )?;
+
+    let mut calories = GLOBAL
+        .db
+        .values()
+        .filter_map(|recipe| recipe.features.calories)
+        .collect::<Vec<_>>();
+    calories.sort();
+
+    let summary = &percentiles[&NumericFeature::Calories];
+    assert_eq!(calories.len() as u64, summary.count);
+
+    // Small inputs are kept as-is, so the only error comes from
+    // interpolating between neighbours
+    let exact = |q: f64| f64::from(calories[(q * (calories.len() - 1) as f64).round() as usize]);
+    let tolerance = |q: f64| {
+        let idx = (q * (calories.len() - 1) as f64) as usize;
+        f64::from(calories[(idx + 1).min(calories.len() - 1)] - calories[idx.saturating_sub(1)])
+    };
+
+    for &(q, found) in &[(0.5, summary.p50), (0.9, summary.p90)] {
+        assert!(
+            (exact(q) - found).abs() <= tolerance(q),
+            "q={} exact={} found={}",
+            q,
+            exact(q),
+            found
+        );
+    }
+
+    assert_eq!(
+        INDEX_SIZE as u64,
+        percentiles[&NumericFeature::NumIngredients].count
+    );
+
+    Ok(())
+}
+
+#[test]
+fn filter_buckets_agree_with_range_queries() -> Result<()> {
+    let reader = GLOBAL.index.reader()?;
+    let searcher = reader.searcher();
+
+    let ranges = vec![
+        ("quick", 0..16),
+        ("weeknight", 0..31),
+        ("project", 121..1_000_000),
+    ];
+
+    let mut buckets = BTreeMap::new();
+    for (name, range) in ranges.iter() {
+        buckets.insert(
+            name.to_string(),
+            FeaturesFilterQuery {
+                total_time: Some(range.clone()),
+                ..FeaturesFilterQuery::default()
+            },
+        );
+    }
+
+    let counts = GLOBAL
+        .cantine
+        .filter_buckets(&searcher, &AllQuery, buckets)?;
+
+    for (name, range) in ranges {
+        let query = RangeQuery::new_u64(
+            GLOBAL.cantine.features.total_time,
+            u64::from(range.start)..u64::from(range.end),
+        );
+        let expected = searcher.search(&query, &Count)?;
+        assert_eq!(
+            Some(&(expected as u64)),
+            counts.get(name),
+            "bucket {}",
+            name
+        );
+    }
+
+    Ok(())
+}
+
+#[test]
+fn shuffled_pagination_is_stable_per_seed() -> Result<()> {
+    let reader = GLOBAL.index.reader()?;
+    let searcher = reader.searcher();
+
+    let paginate = |seed: u64| -> Result<Vec<RecipeId>> {
+        let mut after = None;
+        let mut ids = Vec::with_capacity(INDEX_SIZE);
+
+        loop {
+            let (_total, found_ids, next) = GLOBAL
+                .cantine
+                .shuffled(&searcher, &AllQuery, 10, seed, after)?;
+
+            ids.extend(found_ids);
+
+            if let Some(new_after) = next {
+                after = Some(new_after);
+            } else {
+                break;
+            }
+        }
+
+        Ok(ids)
+    };
+
+    let shuffled = paginate(42)?;
+    assert_eq!(INDEX_SIZE, shuffled.len());
+    assert_eq!(INDEX_SIZE, shuffled.iter().collect::<HashSet<_>>().len());
+
+    assert_eq!(shuffled, paginate(42)?);
+    assert_ne!(shuffled, paginate(7)?);
+
+    let (_total, first_page, _next) = GLOBAL
+        .cantine
+        .shuffled(&searcher, &AllQuery, 10, 42, None)?;
+    assert_eq!(&shuffled[..10], &first_page[..]);
+
+    Ok(())
+}
+
+#[test]
+fn runtime_fields_filter_and_sort() -> Result<()> {
+    let reader = GLOBAL.index.reader()?;
+    let searcher = reader.searcher();
+
+    let protein_per_calorie =
+        RuntimeField::Ratio(NumericFeature::ProteinContent, NumericFeature::Calories);
+    let value_of = |id: &RecipeId| protein_per_calorie.value(&GLOBAL.db[id].features);
+
+    let query = RuntimeFilterQuery::new(
+        Box::new(AllQuery),
+        GLOBAL.cantine.features_bincode,
+        vec![RuntimeFilter {
+            field: protein_per_calorie,
+            range: 0.05..1.0,
+        }],
+    );
+
+    let expected = GLOBAL
+        .db
+        .keys()
+        .filter(|id| value_of(id).map_or(false, |value| value >= 0.05 && value < 1.0))
+        .count();
+    assert_eq!(expected, searcher.search(&query, &Count)?);
+
+    // Paginating the sorted results yields every recipe once, the
+    // ones without a value for the field last
+    let mut after = None;
+    let mut found = Vec::with_capacity(INDEX_SIZE);
+    loop {
+        let (_total, found_ids, next) = GLOBAL.cantine.runtime_sorted(
+            &searcher,
+            &AllQuery,
+            10,
+            protein_per_calorie,
+            false,
+            after,
+        )?;
+
+        found.extend(found_ids);
+
+        if let Some(new_after) = next {
+            after = Some(new_after);
+        } else {
+            break;
+        }
+    }
+
+    assert_eq!(INDEX_SIZE, found.len());
+    assert_eq!(INDEX_SIZE, found.iter().collect::<HashSet<_>>().len());
+
+    let values = found.iter().map(value_of).collect::<Vec<_>>();
+    let num_valued = values.iter().filter(|value| value.is_some()).count();
+    assert!(values[num_valued..].iter().all(Option::is_none));
+    for pair in values[..num_valued].windows(2) {
+        assert!(pair[0] >= pair[1]);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn explain_breaks_down_matches_and_filters() -> Result<()> {
+    let reader = GLOBAL.index.reader()?;
+    let searcher = reader.searcher();
+    let parser = QueryParser::new(&GLOBAL.index, vec![GLOBAL.cantine.name])?;
+
+    let (recipe, word) = GLOBAL
+        .db
+        .values()
+        .find_map(|recipe| {
+            recipe
+                .name
+                .split_whitespace()
+                .find(|word| word.len() > 3 && word.chars().all(|c| c.is_ascii_alphabetic()))
+                .map(|word| (recipe, word))
+        })
+        .expect("a recipe with a plain word in its name");
+    let query = parser.parse(word).expect("a valid query");
+
+    let num_ingredients = u64::from(recipe.features.num_ingredients);
+    let filters: Vec<Box<dyn Query>> = vec![
+        Box::new(RangeQuery::new_u64(
+            GLOBAL.cantine.features.num_ingredients,
+            num_ingredients..num_ingredients + 1,
+        )),
+        Box::new(RangeQuery::new_u64(
+            GLOBAL.cantine.features.num_ingredients,
+            num_ingredients + 1..num_ingredients + 2,
+        )),
+    ];
+
+    let explanation = GLOBAL
+        .cantine
+        .explain(&searcher, &query, &filters, recipe.recipe_id)?
+        .expect("recipe is indexed");
+
+    assert!(explanation.score.is_some());
+    let name_matches = &explanation.matches["name"];
+    assert_eq!(1, name_matches.len());
+    assert_eq!(word.to_lowercase(), name_matches[0].term);
+
+    let field_name = searcher
+        .schema()
+        .get_field_name(GLOBAL.cantine.features.num_ingredients);
+    assert_eq!(2, explanation.filters.len());
+    assert!(explanation
+        .filters
+        .iter()
+        .all(|check| check.field == field_name));
+    assert!(explanation.filters[0].passed);
+    assert!(!explanation.filters[1].passed);
+
+    // What search hits get annotated with
+    let matched = MatchedClauses::from(&explanation);
+    assert_eq!(vec![word.to_lowercase()], matched.terms);
+    assert_eq!(vec![field_name.to_owned()], matched.filters);
+
+    // Compound filters are named after their field
+    let presence: Vec<Box<dyn Query>> = vec![Box::new(
+        GLOBAL
+            .cantine
+            .presence_query(&[], &[PresenceField::Nutrition]),
+    )];
+    let explanation = GLOBAL
+        .cantine
+        .explain(&searcher, &query, &presence, recipe.recipe_id)?
+        .expect("recipe is indexed");
+    assert_eq!("present", explanation.filters[0].field);
+
+    // Unknown recipes can't be explained
+    assert_eq!(
+        None,
+        GLOBAL
+            .cantine
+            .explain(&searcher, &query, &[], std::u64::MAX)?
+    );
+
+    Ok(())
+}
+
+#[test]
+fn score_expressions_use_feature_names() -> Result<()> {
+    let reader = GLOBAL.index.reader()?;
+    let searcher = reader.searcher();
+
+    for invalid in &["Filterable_field_num_ingredients", "num_ingredients *"] {
+        match GLOBAL.cantine.score_expression(&searcher, invalid) {
+            Err(Error::QueryParse(_)) => {}
+            other => panic!(
+                "Expected a parse error for {}, got {:?}",
+                invalid,
+                other.err()
+            ),
+        }
+    }
+
+    let expression = GLOBAL
+        .cantine
+        .score_expression(&searcher, "num_ingredients * 2 - 1")?;
+    let (total, found_ids, _next) = GLOBAL
+        .cantine
+        .expression_sorted(&searcher, &AllQuery, INDEX_SIZE, expression, true, None)?;
+    assert_eq!(INDEX_SIZE, total);
+
+    let num_ingredients = found_ids
+        .iter()
+        .map(|id| GLOBAL.db[id].features.num_ingredients)
+        .collect::<Vec<_>>();
+    for pair in num_ingredients.windows(2) {
+        assert!(pair[0] <= pair[1]);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn score_expressions_can_use_global_stats() -> Result<()> {
+    let reader = GLOBAL.index.reader()?;
+    let searcher = reader.searcher();
+    let stats = GlobalStats::compute(&searcher, &GLOBAL.cantine)?;
+
+    // Unknown without the stats
+    assert!(GLOBAL
+        .cantine
+        .score_expression(&searcher, "num_ingredients - num_ingredients_p50")
+        .is_err());
+
+    let expression = GLOBAL.cantine.score_expression_with_stats(
+        &searcher,
+        "num_ingredients - num_ingredients_p50",
+        &stats,
+    )?;
+    let (total, found_ids, _next) = GLOBAL
+        .cantine
+        .expression_sorted(&searcher, &AllQuery, 1, expression, false, None)?;
+    assert_eq!(INDEX_SIZE, total);
+
+    let most_ingredients = GLOBAL
+        .db
+        .values()
+        .map(|recipe| recipe.features.num_ingredients)
+        .max();
+    assert_eq!(
+        most_ingredients,
+        found_ids
+            .first()
+            .map(|id| GLOBAL.db[id].features.num_ingredients)
+    );
+
+    Ok(())
+}
+
+#[test]
+fn timings_are_recorded_per_search() -> Result<()> {
+    let reader = GLOBAL.index.reader()?;
+    let searcher = reader.searcher();
+
+    let recorder = TimingsRecorder::default();
+    let timed = GLOBAL.cantine.clone().with_timings(recorder.clone());
+
+    let (total, found_ids, _next) = timed.search(&searcher, &AllQuery, 10, Sort::Calories, None)?;
+    let (untimed_total, untimed_ids, _next) =
+        GLOBAL
+            .cantine
+            .search(&searcher, &AllQuery, 10, Sort::Calories, None)?;
+    assert_eq!(untimed_total, total);
+    assert_eq!(untimed_ids, found_ids);
+
+    timed.shuffled(&searcher, &AllQuery, 10, 42, None)?;
+
+    let timings = recorder.take();
+    assert_eq!(2, timings.len());
+    for timing in timings {
+        assert_eq!(searcher.segment_readers().len(), timing.segments.len());
+    }
+
+    // Taking clears the recorder
+    assert!(recorder.take().is_empty());
+
+    Ok(())
+}
+
+#[test]
+fn budget_is_enforced_when_timed() -> Result<()> {
+    let reader = GLOBAL.index.reader()?;
+    let searcher = reader.searcher();
+
+    let recorder = TimingsRecorder::default();
+    let budget = SearchBudget::new(Budget::default().with_max_docs_visited(10));
+    let limited = GLOBAL
+        .cantine
+        .clone()
+        .with_timings(recorder.clone())
+        .with_budget(budget.clone());
+
+    let (total, found_ids, _next) =
+        limited.search(&searcher, &AllQuery, INDEX_SIZE, Sort::Calories, None)?;
+    assert!(budget.is_exhausted());
+    assert_eq!(10, total);
+    assert_eq!(10, found_ids.len());
+    assert_eq!(1, recorder.take().len());
+
+    Ok(())
+}
+
+#[test]
+fn collapsed_search_yields_each_family_once() -> Result<()> {
+    let mut builder = SchemaBuilder::new();
+    let cantine = RecipeIndex::from(&mut builder);
+    let index = Index::create_in_ram(builder.build());
+
+    // Odd ids become variants of the recipe before them, when there's one
+    let parent_of = |id: RecipeId| {
+        if id % 2 == 1 && GLOBAL.db.contains_key(&(id - 1)) {
+            id - 1
+        } else {
+            id
+        }
+    };
+
+    let mut writer = index.writer_with_num_threads(1, 50_000_000)?;
+    for recipe in GLOBAL.db.values() {
+        let mut recipe = recipe.clone();
+        let parent_id = parent_of(recipe.recipe_id);
+        if parent_id != recipe.recipe_id {
+            recipe.variant_of = Some(parent_id);
+        }
+        writer.add_document(cantine.make_document(&recipe));
+    }
+    writer.commit()?;
+
+    let searcher = index.reader()?.searcher();
+    let (total, found) = cantine.collapsed(&searcher, &AllQuery, INDEX_SIZE)?;
+    assert_eq!(INDEX_SIZE, total);
+
+    let num_families = GLOBAL
+        .db
+        .keys()
+        .map(|&id| parent_of(id))
+        .collect::<HashSet<_>>()
+        .len();
+    assert!(num_families < INDEX_SIZE);
+    assert_eq!(num_families, found.len());
+
+    let mut seen = HashSet::new();
+    for (parent_id, matched_id) in found {
+        assert!(seen.insert(parent_id));
+        assert_eq!(parent_id, parent_of(matched_id));
+    }
+
+    Ok(())
+}
+
+#[test]
+fn geo_filter_and_distance_sort() -> Result<()> {
+    let mut builder = SchemaBuilder::new();
+    let cantine = RecipeIndex::from(&mut builder);
+    let index = Index::create_in_ram(builder.build());
+
+    // Spread along the greenwich meridian. Every third recipe has
+    // no origin at all
+    let origin_of = |id: RecipeId| {
+        if id % 3 == 0 {
+            None
+        } else {
+            Some(GeoPoint {
+                lat: (id % 90) as f64 - 45.0,
+                lon: 0.0,
+            })
+        }
+    };
+
+    let mut writer = index.writer_with_num_threads(1, 50_000_000)?;
+    for recipe in GLOBAL.db.values() {
+        let mut recipe = recipe.clone();
+        recipe.origin = origin_of(recipe.recipe_id);
+        writer.add_document(cantine.make_document(&recipe));
+    }
+    writer.commit()?;
+
+    let searcher = index.reader()?.searcher();
+    let center = GeoPoint { lat: 0.0, lon: 0.0 };
+
+    let near = GeoDistanceFilter {
+        center,
+        radius_km: 1_000.0,
+    };
+    let expected = GLOBAL
+        .db
+        .keys()
+        .filter(|&&id| origin_of(id).map_or(false, |origin| near.matches(origin)))
+        .count();
+    assert!(expected > 0);
+    assert_eq!(
+        expected,
+        searcher.search(&GeoDistanceQuery::new(cantine.origin, near), &Count)?
+    );
+
+    let (total, found_ids, _next) =
+        cantine.distance_sorted(&searcher, &AllQuery, INDEX_SIZE, center, None)?;
+    assert_eq!(INDEX_SIZE, total);
+
+    let distances = found_ids
+        .iter()
+        .map(|&id| origin_of(id).map_or(std::f64::INFINITY, |o| center.distance_km(o)))
+        .collect::<Vec<_>>();
+    for pair in distances.windows(2) {
+        assert!(pair[0] <= pair[1]);
+    }
+    assert!(distances[INDEX_SIZE - 1].is_infinite());
+
+    Ok(())
+}
+
+#[test]
+fn exists_and_missing_filters() -> Result<()> {
+    let searcher = GLOBAL.index.reader()?.searcher();
+    let cantine = &GLOBAL.cantine;
+
+    for &field in PresenceField::VALUES.iter() {
+        let expected = GLOBAL
+            .db
+            .values()
+            .filter(|recipe| field.is_present(recipe))
+            .count();
+
+        let with = searcher.search(&cantine.presence_query(&[field], &[]), &Count)?;
+        let without = searcher.search(&cantine.presence_query(&[], &[field]), &Count)?;
+
+        assert_eq!(expected, with, "{:?}", field);
+        assert_eq!(INDEX_SIZE - expected, without, "{:?}", field);
+    }
+
+    let expected = GLOBAL
+        .db
+        .values()
+        .filter(|recipe| {
+            PresenceField::Image.is_present(recipe) && !PresenceField::Nutrition.is_present(recipe)
+        })
+        .count();
+    assert_eq!(
+        expected,
+        searcher.search(
+            &cantine.presence_query(&[PresenceField::Image], &[PresenceField::Nutrition]),
+            &Count
+        )?
+    );
+
+    // Can't have and lack something at once
+    assert_eq!(
+        0,
+        searcher.search(
+            &cantine.presence_query(&[PresenceField::Image], &[PresenceField::Image]),
+            &Count
+        )?
+    );
+
+    Ok(())
+}
+
+#[test]
+fn rebuild_from_database() -> Result<()> {
+    let db_dir = tempfile::tempdir()?;
+    let mut db = DatabaseWriter::new(db_dir.path())?;
+    for recipe in GLOBAL.db.values() {
+        db.append(recipe)?;
+    }
+    drop(db);
+
+    let database = DatabaseReader::<Recipe>::open(db_dir.path())?;
+
+    // Picking up an analysis change
+    let analysis = Analysis {
+        stemmer: Some(Language::English),
+        ..Analysis::default()
+    };
+
+    let mut builder = SchemaBuilder::new();
+    let cantine = RecipeIndex::create(&mut builder, &analysis);
+    let index = Index::create_in_ram(builder.build());
+    analysis.register(&index);
+
+    let mut writer = index.writer_with_num_threads(2, 50_000_000)?;
+    let num_indexed = cantine.rebuild_from(
+        &database,
+        &HashMap::new(),
+        &HashMap::new(),
+        &mut writer,
+        100,
+    )?;
+    assert_eq!(INDEX_SIZE, num_indexed);
+
+    let searcher = index.reader()?.searcher();
+    assert_eq!(INDEX_SIZE, searcher.search(&AllQuery, &Count)?);
+
+    // Same results as the index the fixture built (modulo ties)
+    let (_total, rebuilt_ids, _next) =
+        cantine.search(&searcher, &AllQuery, 20, Sort::Calories, None)?;
+    let (_total, fixture_ids, _next) = GLOBAL.cantine.search(
+        &GLOBAL.index.reader()?.searcher(),
+        &AllQuery,
+        20,
+        Sort::Calories,
+        None,
+    )?;
+    let calories_of = |ids: Vec<RecipeId>| {
+        ids.into_iter()
+            .map(|id| GLOBAL.db[&id].features.calories)
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(calories_of(fixture_ids), calories_of(rebuilt_ids));
+
+    Ok(())
+}
+
+#[test]
+fn missing_total_times_are_estimated() -> Result<()> {
+    let mut builder = SchemaBuilder::new();
+    let cantine = RecipeIndex::from(&mut builder);
+    let index = Index::create_in_ram(builder.build());
+    let mut writer = index.writer_with_num_threads(1, 50_000_000)?;
+
+    let mut num_estimated = 0;
+    for recipe in GLOBAL.db.values() {
+        let mut recipe = recipe.clone();
+        if estimate::fill_in(&mut recipe) {
+            num_estimated += 1;
+
+            let card = RecipeCard::from(recipe.clone());
+            assert!(card.total_time_estimated);
+            assert_eq!(recipe.features.total_time_estimate, card.total_time);
+        } else {
+            assert_eq!(None, recipe.features.total_time_estimate);
+        }
+        writer.add_document(cantine.make_document(&recipe));
+    }
+    writer.commit()?;
+
+    let has_total_time = GLOBAL
+        .db
+        .values()
+        .filter(|recipe| recipe.features.total_time.is_some())
+        .count();
+    assert!(num_estimated > 0);
+    assert_eq!(INDEX_SIZE, has_total_time + num_estimated);
+
+    // The estimates are searchable on their own field
+    let searcher = index.reader()?.searcher();
+    let estimated = RangeQuery::new_u64(cantine.features.total_time_estimate, 0..u64::MAX);
+    assert_eq!(num_estimated, searcher.search(&estimated, &Count)?);
+
+    Ok(())
+}
+
+#[test]
+fn custom_tokenizers_are_validated() -> Result<()> {
+    let analysis = Analysis {
+        ascii_folding: true,
+        ..Analysis::default()
+    };
+
+    let mut builder = SchemaBuilder::new();
+    let cantine = RecipeIndex::create(&mut builder, &analysis)
+        .register_tokenizer("prefixes", NgramTokenizer::new(2, 5, true));
+    let prefixes = builder.add_text_field(
+        "name_prefixes",
+        TextOptions::default().set_indexing_options(
+            TextFieldIndexing::default()
+                .set_tokenizer("prefixes")
+                .set_index_option(IndexRecordOption::Basic),
+        ),
+    );
+    let index = Index::create_in_ram(builder.build());
+
+    let required: Vec<_> = RecipeIndex::required_tokenizers(&index.schema())
+        .into_iter()
+        .collect();
+    assert_eq!(vec!["cantine", "prefixes"], required);
+
+    // The analysis isn't registered yet
+    assert!(cantine.install_tokenizers(&index).is_err());
+
+    // Nor is every recipe field in an empty schema
+    match RecipeIndex::try_from(&SchemaBuilder::new().build()) {
+        Err(Error::UnknownField(_)) => {}
+        other => panic!("Expected an unknown field, got {:?}", other.err()),
+    }
+
+    analysis.register(&index);
+    cantine.install_tokenizers(&index)?;
+
+    let mut writer = index.writer_with_num_threads(1, 50_000_000)?;
+    let mut doc = cantine.make_document(GLOBAL.db.values().next().unwrap());
+    doc.add_text(prefixes, "pancakes");
+    writer.add_document(doc);
+    writer.commit()?;
+
+    let query = TermQuery::new(
+        Term::from_field_text(prefixes, "panc"),
+        IndexRecordOption::Basic,
+    );
+    assert_eq!(1, index.reader()?.searcher().search(&query, &Count)?);
+
+    Ok(())
+}
+
+#[test]
+fn instant_search_matches_name_prefixes() -> Result<()> {
+    let reader = GLOBAL.index.reader()?;
+    let searcher = reader.searcher();
+
+    for recipe in GLOBAL.db.values().take(20) {
+        let words: Vec<_> = recipe.name.split_whitespace().collect();
+        if words.is_empty() {
+            continue;
+        }
+
+        // Every full word but the last, which is being typed
+        let last = words[words.len() - 1];
+        let typed = format!(
+            "{} {}",
+            words[..words.len() - 1].join(" "),
+            last.chars().take(2).collect::<String>()
+        );
+
+        let found = GLOBAL.cantine.instant_search(&searcher, &typed, &[], 10)?;
+        // At least the recipe itself
+        assert!(!found.is_empty());
+
+        // The ones that match with typos (if any) come last
+        let matches = |recipe_id: &u64| {
+            let name = GLOBAL.db[recipe_id].name.to_lowercase();
+            cantine::instant::prefix_terms(&typed)
+                .iter()
+                .all(|prefix| cantine::instant::name_prefixes(&name).contains(prefix))
+        };
+        let num_exact = found.iter().take_while(|&id| matches(id)).count();
+        assert!(num_exact > 0, "{} found nothing exact", typed);
+        assert!(
+            found[num_exact..].iter().all(|id| !matches(id)),
+            "exact matches for {} came after typos",
+            typed
+        );
+    }
+
+    assert!(GLOBAL
+        .cantine
+        .instant_search(&searcher, "  ", &[], 10)?
+        .is_empty());
+    assert!(GLOBAL
+        .cantine
+        .instant_search(&searcher, "zzyzzx", &[], 10)?
+        .is_empty());
+
+    // Capped
+    assert!(
+        GLOBAL
+            .cantine
+            .instant_search(&searcher, "a", &[], 1000)?
+            .len()
+            <= 10
+    );
+
+    Ok(())
+}
+
+#[test]
+fn instant_search_forgives_typos() -> Result<()> {
+    let reader = GLOBAL.index.reader()?;
+    let searcher = reader.searcher();
+
+    let mut checked = 0;
+    for (recipe_id, recipe) in GLOBAL.db.iter().take(50) {
+        let words = cantine::instant::prefix_terms(&recipe.name);
+        let long = match words.iter().find(|word| word.chars().count() >= 6) {
+            Some(word) => word,
+            None => continue,
+        };
+
+        // Drops a letter past the ones that must be right
+        let mut typo: Vec<char> = long.chars().collect();
+        typo.remove(3);
+        let typed = words
+            .iter()
+            .map(|word| {
+                if word == long {
+                    typo.iter().collect()
+                } else {
+                    word.clone()
+                }
+            })
+            .collect::<Vec<_>>()
+            .join(" ");
+
+        let found = GLOBAL.cantine.instant_search(&searcher, &typed, &[], 10)?;
+        if found.len() < 10 {
+            assert!(found.contains(recipe_id), "{} didn't find {}", typed, long);
+            checked += 1;
+        }
+    }
+    assert!(checked > 0);
+
+    Ok(())
+}
+
+#[test]
+fn instant_search_ranks_up_recent_searches() -> Result<()> {
+    let reader = GLOBAL.index.reader()?;
+    let searcher = reader.searcher();
+
+    let plain = GLOBAL.cantine.instant_search(&searcher, "a", &[], 10)?;
+    let last = *plain.last().unwrap();
+    let hinted = [(GLOBAL.db[&last].name.clone(), 20)];
+
+    let ranked = GLOBAL.cantine.instant_search(&searcher, "a", &hinted, 10)?;
+    assert!(
+        ranked.iter().position(|id| *id == last) < plain.iter().position(|id| *id == last),
+        "{:?} didn't rank up {}",
+        hinted,
+        last
+    );
+
+    Ok(())
+}
+
+#[test]
+fn count_and_exists() -> Result<()> {
+    let searcher = GLOBAL.index.reader()?.searcher();
+    let cantine = &GLOBAL.cantine;
+
+    assert_eq!(INDEX_SIZE, cantine.count(&searcher, &AllQuery)?);
+    assert!(cantine.exists(&searcher, &AllQuery)?);
+
+    for num_ingredients in 0..15 {
+        let query = RangeQuery::new_u64(
+            cantine.features.num_ingredients,
+            num_ingredients..(num_ingredients + 1),
+        );
+        let expected = GLOBAL
+            .db
+            .values()
+            .filter(|recipe| u64::from(recipe.features.num_ingredients) == num_ingredients)
+            .count();
+
+        assert_eq!(expected, cantine.count(&searcher, &query)?);
+        assert_eq!(expected > 0, cantine.exists(&searcher, &query)?);
+    }
+
+    let nothing = TermQuery::new(
+        Term::from_field_text(cantine.name, "notawordinthesamples"),
+        IndexRecordOption::Basic,
+    );
+    assert_eq!(0, cantine.count(&searcher, &nothing)?);
+    assert!(!cantine.exists(&searcher, &nothing)?);
+
+    Ok(())
+}
+
+#[test]
+fn grouped_by_author() -> Result<()> {
+    let mut builder = SchemaBuilder::new();
+    let cantine = RecipeIndex::from(&mut builder);
+    let index = Index::create_in_ram(builder.build());
+    let mut writer = index.writer_with_num_threads(1, 50_000_000)?;
+
+    // Authors 0 to 4, plus a few recipes without one
+    let mut wanted: HashMap<u64, usize> = HashMap::new();
+    for recipe in GLOBAL.db.values() {
+        let mut recipe = recipe.clone();
+        recipe.author_id = if recipe.recipe_id % 7 == 0 {
+            None
+        } else {
+            Some(recipe.recipe_id % 5)
+        };
+
+        if let Some(author_id) = recipe.author_id {
+            *wanted.entry(author_id).or_insert(0) += 1;
+        }
+        writer.add_document(cantine.make_document(&recipe));
+    }
+    writer.commit()?;
+
+    let searcher = index.reader()?.searcher();
+    let groups = cantine.grouped(&searcher, &AllQuery, GroupField::Author, 10, 3)?;
+
+    assert_eq!(wanted.len(), groups.len());
+    assert!(groups.windows(2).all(|pair| pair[0].1 >= pair[1].1));
+    for (author_id, total, recipe_ids) in groups {
+        assert_eq!(wanted[&author_id], total);
+        assert_eq!(3, recipe_ids.len());
+        for recipe_id in recipe_ids {
+            assert_eq!(author_id, recipe_id % 5);
+            assert_ne!(0, recipe_id % 7);
+        }
+    }
+
+    let groups = cantine.grouped(&searcher, &AllQuery, GroupField::Author, 2, 0)?;
+    assert_eq!(2, groups.len());
+    assert!(groups
+        .iter()
+        .all(|(_, _, recipe_ids)| recipe_ids.is_empty()));
+
+    Ok(())
+}
+
+#[test]
+fn similar_recipes() -> Result<()> {
+    let searcher = GLOBAL.index.reader()?.searcher();
+    let cantine = &GLOBAL.cantine;
+
+    let mut num_found = 0;
+    for &recipe_id in GLOBAL.db.keys() {
+        let similar = cantine
+            .similar_to(&searcher, recipe_id, 5)?
+            .expect("recipe is in the index");
+
+        assert!(similar.len() <= 5);
+        assert!(!similar.contains(&recipe_id));
+        assert!(similar.iter().all(|id| GLOBAL.db.contains_key(id)));
+
+        if !similar.is_empty() {
+            num_found += 1;
+        }
+    }
+    // Recipes have words in common
+    assert!(num_found > INDEX_SIZE / 2);
+
+    assert_eq!(None, cantine.similar_to(&searcher, std::u64::MAX, 5)?);
+
+    Ok(())
+}
+
+#[test]
+fn pantry_covering_every_ingredient() -> Result<()> {
+    let searcher = GLOBAL.index.reader()?.searcher();
+    let cantine = &GLOBAL.cantine;
+    let tokenizer = GLOBAL.index.tokenizer_for_field(cantine.ingredients)?;
+
+    let mut num_checked = 0;
+    for (&recipe_id, recipe) in GLOBAL.db.iter() {
+        // Every ingredient line must become an item
+        if usize::from(recipe.features.num_ingredients) != recipe.ingredients.len()
+            || recipe
+                .ingredients
+                .iter()
+                .any(|line| tokenizer.token_stream(line).next().is_none())
+        {
+            continue;
+        }
+
+        let pantry = PantryFilter {
+            items: recipe.ingredients.clone(),
+            require_all: true,
+        };
+        let query = PantryQuery::from_filter(
+            &pantry,
+            cantine.ingredients,
+            &tokenizer,
+            cantine.features.num_ingredients,
+        );
+
+        let (total, found, _after) =
+            cantine.search(&searcher, &query, INDEX_SIZE, Sort::Relevance, None)?;
+        assert!(total > 0);
+        assert!(found.contains(&recipe_id));
+
+        num_checked += 1;
+    }
+    assert!(num_checked > 0);
+
+    Ok(())
+}
+
+#[test]
+fn scan_goes_through_every_match_in_batches() -> Result<()> {
+    let searcher = GLOBAL.index.reader()?.searcher();
+    let cantine = &GLOBAL.cantine;
+
+    let mut seen = HashSet::with_capacity(INDEX_SIZE);
+    for batch in cantine.scan(&searcher, &AllQuery, 7)? {
+        let batch = batch?;
+        assert!(!batch.is_empty() && batch.len() <= 7);
+        seen.extend(batch);
+    }
+    assert_eq!(INDEX_SIZE, seen.len());
+
+    let query = RangeQuery::new_u64(cantine.features.num_ingredients, 0..6);
+    let expected = GLOBAL
+        .db
+        .values()
+        .filter(|recipe| recipe.features.num_ingredients < 6)
+        .map(|recipe| recipe.recipe_id)
+        .collect::<HashSet<_>>();
+
+    let mut found = HashSet::new();
+    for batch in cantine.scan(&searcher, &query, 10)? {
+        found.extend(batch?);
+    }
+    assert_eq!(expected, found);
+
+    Ok(())
+}
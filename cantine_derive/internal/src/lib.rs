@@ -233,6 +233,56 @@ fn make_filter_query(feat: &Ident, fields: &[FieldInfo]) -> TokenStream2 {
         }
     });
 
+    let matches_code = fields.iter().map(|field| {
+        let name = field.ident;
+
+        if field.is_optional {
+            quote_spanned! { field.span()=>
+                if let Some(ref rr) = self.#name {
+                    match feat.#name {
+                        Some(ref value) if rr.contains(value) => {}
+                        _ => return false,
+                    }
+                }
+            }
+        } else {
+            quote_spanned! { field.span()=>
+                if let Some(ref rr) = self.#name {
+                    if !rr.contains(&feat.#name) {
+                        return false;
+                    }
+                }
+            }
+        }
+    });
+
+    let optionals = fields.iter().filter(|f| f.is_optional).collect::<Vec<_>>();
+    if optionals.len() > 64 {
+        return quote! {
+            compile_error!("Only up to 64 optional fields are supported");
+        };
+    }
+
+    let presence_code = optionals.iter().enumerate().map(|(bit, field)| {
+        let name = field.ident;
+        let mask = 1u64 << bit;
+        quote_spanned! { field.span()=>
+            if feat.#name.is_some() {
+                bits |= #mask;
+            }
+        }
+    });
+
+    let presence_mask_code = optionals.iter().enumerate().map(|(bit, field)| {
+        let name = field.ident;
+        let mask = 1u64 << bit;
+        quote_spanned! { field.span()=>
+            if field == self.#name {
+                return Some(#mask);
+            }
+        }
+    });
+
     quote! {
         #[derive(serde::Serialize, serde::Deserialize, Default, Debug, Clone)]
         #[serde(deny_unknown_fields)]
@@ -240,6 +290,15 @@ fn make_filter_query(feat: &Ident, fields: &[FieldInfo]) -> TokenStream2 {
             #(#query_fields),*
         }
 
+        impl #name {
+            /// Tells whether `feat` would be matched by the query
+            /// generated from this filter, without touching the index
+            pub fn matches(&self, feat: &#feat) -> bool {
+                #(#matches_code)*
+                true
+            }
+        }
+
         #[derive(Clone, Debug, PartialEq)]
         pub struct #index_name {
             #(#index_fields),*
@@ -296,6 +355,25 @@ fn make_filter_query(feat: &Ident, fields: &[FieldInfo]) -> TokenStream2 {
                 #(#add_to_doc_code);*
             }
 
+            /// A bit set for every optional field that `feat` has, so
+            /// that it can be indexed as a fast field: tantivy reads
+            /// missing values as zero otherwise
+            #[allow(unused_variables)]
+            pub fn presence(feat: &#feat) -> u64 {
+                #[allow(unused_mut)]
+                let mut bits = 0u64;
+                #(#presence_code)*
+                bits
+            }
+
+            /// The bit `presence` uses for `field`. None for fields
+            /// that are always present
+            #[allow(unused_variables)]
+            pub fn presence_mask(&self, field: tantivy::schema::Field) -> Option<u64> {
+                #(#presence_mask_code)*
+                None
+            }
+
             pub fn with_flags<O: Into<tantivy::schema::IntOptions>>(
                 builder: &mut tantivy::schema::SchemaBuilder,
                 flags: O
@@ -345,13 +423,18 @@ fn make_agg_query(feat: &Ident, fields: &[FieldInfo]) -> TokenStream2 {
         #[derive(serde::Serialize, serde::Deserialize, Default, Debug, Clone, PartialEq)]
         #[serde(deny_unknown_fields)]
         pub struct #name {
-            #(#query_fields),*
+            #(#query_fields,)*
+            /// Count how many items lack each aggregated optional field
+            /// instead of just leaving them out
+            #[serde(default)]
+            pub count_missing: bool
         }
 
         impl #name {
             pub fn full_range() -> Self {
                 Self {
-                    #(#full_range),*
+                    #(#full_range,)*
+                    count_missing: false
                 }
             }
         }
@@ -393,9 +476,20 @@ fn make_agg_result(feature: &Ident, fields: &[FieldInfo]) -> TokenStream2 {
         }
     });
 
+    let missing_init_code = fields.iter().filter(|f| f.is_optional).map(|field| {
+        let name = &field.ident;
+        let quoted = name.to_string();
+        quote_spanned! { field.span()=>
+            if !src.#name.is_empty() {
+                missing.insert(#quoted, 0);
+            }
+        }
+    });
+
     let collect_code = fields.iter().map(|field| {
         let name = &field.ident;
         if field.is_optional {
+            let quoted = name.to_string();
             quote_spanned! { field.span()=>
                 if let Some(feat) = feature.#name {
                     for (idx, range) in query.#name.iter().enumerate() {
@@ -403,6 +497,8 @@ fn make_agg_result(feature: &Ident, fields: &[FieldInfo]) -> TokenStream2 {
                             self.#name[idx].collect(feat);
                         }
                     }
+                } else if let Some(count) = self.missing.get_mut(#quoted) {
+                    *count += 1;
                 }
             }
         } else {
@@ -419,7 +515,11 @@ fn make_agg_result(feature: &Ident, fields: &[FieldInfo]) -> TokenStream2 {
     quote! {
         #[derive(serde::Serialize, Default, Debug, Clone)]
         pub struct #name {
-            #(#agg_fields),*
+            #(#agg_fields,)*
+            /// How many items lacked each optional field. Only filled
+            /// when the query asks to `count_missing`
+            #[serde(skip_serializing_if = "std::collections::BTreeMap::is_empty")]
+            pub missing: std::collections::BTreeMap<&'static str, u64>
         }
 
         impl cantine_derive::Aggregable for #feature {
@@ -444,6 +544,10 @@ fn make_agg_result(feature: &Ident, fields: &[FieldInfo]) -> TokenStream2 {
         impl #name {
             fn merge_same_size(&mut self, other: &Self) {
                 #(#merge_code);*
+
+                for (name, count) in other.missing.iter() {
+                    *self.missing.entry(*name).or_insert(0) += count;
+                }
             }
 
             fn collect(&mut self, query: &#agg_query, feature: &#feature) {
@@ -454,8 +558,15 @@ fn make_agg_result(feature: &Ident, fields: &[FieldInfo]) -> TokenStream2 {
 
         impl From<&#agg_query> for #name {
             fn from(src: &#agg_query) -> Self {
+                #[allow(unused_mut)]
+                let mut missing = std::collections::BTreeMap::new();
+                if src.count_missing {
+                    #(#missing_init_code)*
+                }
+
                 Self {
-                    #(#convert_code),*
+                    #(#convert_code,)*
+                    missing
                 }
             }
         }
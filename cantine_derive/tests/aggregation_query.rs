@@ -98,6 +98,36 @@ fn collect_works_as_intended() {
     assert_eq!(vec![2], agg_counts(&agg.c));
 }
 
+#[test]
+fn missing_values_are_only_counted_when_asked() {
+    let feat = Feat::default();
+
+    let query = FeatAggregationQuery {
+        b: vec![0..10],
+        ..FeatAggregationQuery::default()
+    };
+    let mut agg = FeatAggregationResult::from(&query);
+    agg.collect(&query, &feat);
+    assert!(agg.missing.is_empty());
+
+    let query = FeatAggregationQuery {
+        count_missing: true,
+        ..query
+    };
+    let mut agg = FeatAggregationResult::from(&query);
+    agg.collect(&query, &feat);
+    agg.collect(&query, &feat);
+
+    // `d` isn't being aggregated, so it's not reported
+    assert_eq!(1, agg.missing.len());
+    assert_eq!(Some(&2), agg.missing.get("b"));
+
+    let mut other = FeatAggregationResult::from(&query);
+    other.collect(&query, &feat);
+    agg.merge_same_size(&other);
+    assert_eq!(Some(&3), agg.missing.get("b"));
+}
+
 #[test]
 fn agg_query_full_range_generation() {
     assert_eq!(
@@ -106,6 +136,7 @@ fn agg_query_full_range_generation() {
             b: vec![std::i16::MIN..std::i16::MAX],
             c: vec![std::f32::MIN..std::f32::MAX],
             d: vec![std::f64::MIN..std::f64::MAX],
+            count_missing: false,
         },
         FeatAggregationQuery::full_range(),
     );
@@ -157,6 +188,7 @@ fn collector_integration() -> tantivy::Result<()> {
         b: vec![0..3],
         c: vec![0.0..0.1, 1.0..3.1],
         d: vec![42.0..100.0],
+        count_missing: true,
     };
 
     let collector =
@@ -175,5 +207,9 @@ fn collector_integration() -> tantivy::Result<()> {
     assert_eq!(vec![0, 3], agg_counts(&agg_result.c));
     assert_eq!(vec![0], agg_counts(&agg_result.d));
 
+    // Only the first doc lacks `b`, every doc has `d`
+    assert_eq!(Some(&1), agg_result.missing.get("b"));
+    assert_eq!(Some(&2), agg_result.missing.get("d"));
+
     Ok(())
 }
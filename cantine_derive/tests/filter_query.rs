@@ -82,3 +82,68 @@ fn add_to_doc_sets_fields_properly() {
     // Unsed optional values aren't added
     assert_eq!(None, doc.get_first(fields.b));
 }
+
+#[test]
+fn matches_checks_every_range() {
+    let feat = Feat {
+        a: 10,
+        d: Some(0.42),
+        ..Feat::default()
+    };
+
+    assert!(Query::default().matches(&feat));
+
+    assert!(Query {
+        a: Some(0..11),
+        d: Some(0.0..1.0),
+        ..Query::default()
+    }
+    .matches(&feat));
+
+    // Ranges are half-open, like the generated RangeQuery
+    assert!(!Query {
+        a: Some(0..10),
+        ..Query::default()
+    }
+    .matches(&feat));
+
+    // Unset optional values never match
+    assert!(!Query {
+        b: Some(-10..10),
+        ..Query::default()
+    }
+    .matches(&feat));
+}
+
+#[test]
+fn presence_has_a_bit_per_optional_field() {
+    let mut builder = SchemaBuilder::new();
+    let fields = Feat::create_schema(&mut builder, INDEXED);
+
+    // Only `b` and `d` are optional
+    assert_eq!(None, fields.presence_mask(fields.a));
+    assert_eq!(None, fields.presence_mask(fields.c));
+
+    let b_mask = fields.presence_mask(fields.b).unwrap();
+    let d_mask = fields.presence_mask(fields.d).unwrap();
+    assert_ne!(b_mask, d_mask);
+
+    type Fields = <Feat as cantine_derive::Filterable>::Schema;
+
+    assert_eq!(0, Fields::presence(&Feat::default()));
+    assert_eq!(
+        d_mask,
+        Fields::presence(&Feat {
+            d: Some(0.0),
+            ..Feat::default()
+        })
+    );
+    assert_eq!(
+        b_mask | d_mask,
+        Fields::presence(&Feat {
+            b: Some(0),
+            d: Some(0.0),
+            ..Feat::default()
+        })
+    );
+}
@@ -0,0 +1,991 @@
+//! Group the matching documents into buckets, keeping the best of each
+//!
+//! `TopHitsPerBucket` is a terms aggregation with a nested top-k:
+//! documents are grouped by a key (usually a fast field holding a
+//! category id), the buckets with the most documents are kept and
+//! each of them comes with its best scoring documents.
+//!
+//! Good for "browse by category" pages: a single query yields every
+//! category along with a preview of what's in it.
+//!
+//! When there are too many keys to show at once (think: thousands of
+//! authors), `CompositeCollector` pages through every key in order
+//! instead, counting the documents of each.
+//!
+//! And to run any other collector within each bucket (say: the stats
+//! of the calories per cuisine), there's `NestedCollector`.
+//!
+//! Ordering the buckets of `TopHitsPerBucket` by their best hit
+//! instead of by size (via `BucketOrder::TopScore`) collapses the
+//! results: say, to show a single document per family of near
+//! duplicates.
+//!
+//! ```no_run
+//! # use tantivy::{query::AllQuery, schema::Field, Searcher, Result};
+//! # use tique::{buckets::NestedCollector, metrics::StatsCollector};
+//! # fn example(searcher: &Searcher, cuisine: Field, calories: Field) -> Result<()> {
+//! let per_cuisine = searcher.search(
+//!     &AllQuery,
+//!     &NestedCollector::u64_field(cuisine, 10, StatsCollector::f64_field(calories)),
+//! )?;
+//!
+//! for bucket in per_cuisine {
+//!     println!("{}: {:?} average calories", bucket.key, bucket.fruit[0].mean());
+//! }
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! ```no_run
+//! # use tantivy::{query::AllQuery, schema::Field, Searcher, Result};
+//! # use tique::buckets::CompositeCollector;
+//! # fn example(searcher: &Searcher, author: Field) -> Result<()> {
+//! let mut after = None;
+//! loop {
+//!     let page = searcher.search(&AllQuery, &CompositeCollector::u64_field(author, 100, after))?;
+//!     for (key, count) in page.buckets {
+//!         // ...
+//!     }
+//!
+//!     if page.after_key.is_none() {
+//!         break;
+//!     }
+//!     after = page.after_key;
+//! }
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Keys may also come from a hierarchical facet field, via
+//! `FacetBucket`: the key of each bucket is then the position of its
+//! facet in the list of facets asked for.
+//!
+//! ```no_run
+//! # use tantivy::{query::AllQuery, schema::{Facet, Field}, Searcher, Result};
+//! # use tique::buckets::TopHitsPerBucket;
+//! # fn example(searcher: &Searcher, cuisine: Field) -> Result<()> {
+//! let cuisines = vec![Facet::from("/cuisine/italian"), Facet::from("/cuisine/thai")];
+//! // The top 3 docs of each cuisine
+//! let buckets = searcher.search(&AllQuery, &TopHitsPerBucket::facet_field(cuisine, cuisines.clone(), 3))?;
+//!
+//! for bucket in buckets {
+//!     println!("{}: {} docs", cuisines[bucket.key as usize], bucket.count);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! ```no_run
+//! # use tantivy::{query::AllQuery, schema::Field, Searcher, Result};
+//! # use tique::buckets::TopHitsPerBucket;
+//! # fn example(searcher: &Searcher, category: Field) -> Result<()> {
+//! // The 10 largest categories and the top 3 docs of each
+//! let buckets = searcher.search(&AllQuery, &TopHitsPerBucket::u64_field(category, 10, 3))?;
+//!
+//! for bucket in buckets {
+//!     println!("{}: {} docs", bucket.key, bucket.count);
+//!     for (score, addr) in bucket.hits {
+//!         // ...
+//!     }
+//! }
+//! # Ok(())
+//! # }
+//! ```
+use std::{
+    cell::RefCell,
+    cmp::Ordering,
+    collections::{hash_map::Entry, BTreeMap, HashMap},
+    sync::Arc,
+};
+
+use tantivy::{
+    collector::{Collector, SegmentCollector},
+    fastfield::{FacetReader, FastFieldReader},
+    schema::{Facet, Field},
+    termdict::TermOrdinal,
+    DocAddress, DocId, Result, Score, SegmentLocalId, SegmentReader, TantivyError,
+};
+
+use crate::conditional_collector::{
+    topk::{TopK, TopKProvider},
+    Descending,
+};
+
+/// A group of documents that share the same key
+#[derive(Debug, Clone, PartialEq)]
+pub struct Bucket {
+    /// What the documents in this bucket have in common
+    pub key: u64,
+    /// How many matching documents have this key
+    pub count: usize,
+    /// The best documents with this key, by descending score
+    pub hits: Vec<(Score, DocAddress)>,
+}
+
+/// Extracts the bucket key from the documents of a segment
+pub trait BucketForSegment: Sync {
+    /// The per-segment key reader
+    type Reader: BucketForDoc;
+    /// Prepares to read keys from the given segment
+    fn for_segment(&self, reader: &SegmentReader) -> Result<Self::Reader>;
+}
+
+/// Reads the key of a document within a segment
+pub trait BucketForDoc: 'static {
+    /// The key for the given doc. Documents without one are
+    /// counted in no bucket
+    fn bucket(&self, doc: DocId) -> Option<u64>;
+}
+
+impl<F, R> BucketForSegment for F
+where
+    F: Sync + Fn(&SegmentReader) -> Result<R>,
+    R: BucketForDoc,
+{
+    type Reader = R;
+
+    fn for_segment(&self, reader: &SegmentReader) -> Result<Self::Reader> {
+        (self)(reader)
+    }
+}
+
+impl<F> BucketForDoc for F
+where
+    F: 'static + Fn(DocId) -> Option<u64>,
+{
+    fn bucket(&self, doc: DocId) -> Option<u64> {
+        (self)(doc)
+    }
+}
+
+/// A `BucketForSegment` that uses the value of a u64 fast field
+/// as the key
+#[derive(Debug, Clone, Copy)]
+pub struct FastFieldBucket(pub Field);
+
+impl BucketForSegment for FastFieldBucket {
+    type Reader = FastFieldReader<u64>;
+
+    fn for_segment(&self, reader: &SegmentReader) -> Result<Self::Reader> {
+        reader.fast_fields().u64(self.0).ok_or_else(|| {
+            TantivyError::SchemaError(format!("{:?} is not a u64 fast field", self.0))
+        })
+    }
+}
+
+impl BucketForDoc for FastFieldReader<u64> {
+    fn bucket(&self, doc: DocId) -> Option<u64> {
+        Some(self.get(doc))
+    }
+}
+
+/// A `BucketForSegment` over a hierarchical facet field. The key is
+/// the position, within the given list, of the first of the
+/// document's facets that's in it. Only exact matches count: a
+/// document faceted `/cuisine/italian/sicilian` is in no bucket
+/// for `/cuisine/italian`
+#[derive(Debug, Clone)]
+pub struct FacetBucket {
+    field: Field,
+    facets: Vec<Facet>,
+}
+
+impl FacetBucket {
+    /// Buckets by the given facets of `field`
+    pub fn new(field: Field, facets: Vec<Facet>) -> Self {
+        Self { field, facets }
+    }
+
+    /// The facet a key stands for
+    pub fn facet(&self, key: u64) -> Option<&Facet> {
+        self.facets.get(key as usize)
+    }
+}
+
+impl BucketForSegment for FacetBucket {
+    type Reader = FacetBucketReader;
+
+    fn for_segment(&self, reader: &SegmentReader) -> Result<Self::Reader> {
+        let facet_reader = reader.facet_reader(self.field)?;
+
+        // Facet ordinals are local to the segment, so they can't be
+        // the keys themselves
+        let mut keys = HashMap::with_capacity(self.facets.len());
+        for (key, facet) in self.facets.iter().enumerate() {
+            if let Some(ord) = facet_reader.facet_dict().term_ord(facet.encoded_str()) {
+                keys.entry(ord).or_insert(key as u64);
+            }
+        }
+
+        Ok(FacetBucketReader {
+            keys,
+            reader: RefCell::new((facet_reader, Vec::new())),
+        })
+    }
+}
+
+/// The per-segment part of `FacetBucket`
+pub struct FacetBucketReader {
+    keys: HashMap<TermOrdinal, u64>,
+    // Reading ordinals requires `&mut`, buffer included
+    reader: RefCell<(FacetReader, Vec<u64>)>,
+}
+
+impl BucketForDoc for FacetBucketReader {
+    fn bucket(&self, doc: DocId) -> Option<u64> {
+        if self.keys.is_empty() {
+            return None;
+        }
+
+        let mut state = self.reader.borrow_mut();
+        let (reader, ords) = &mut *state;
+        reader.facet_ords(doc, ords);
+        ords.iter()
+            .filter_map(|ord| self.keys.get(ord))
+            .min()
+            .copied()
+    }
+}
+
+/// Which buckets `TopHitsPerBucket` keeps
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BucketOrder {
+    /// The ones with the most documents
+    Count,
+    /// The ones with the best scoring documents
+    TopScore,
+}
+
+/// Collects the largest buckets and their top hits
+pub struct TopHitsPerBucket<B> {
+    num_buckets: usize,
+    hits_per_bucket: usize,
+    bucket_for_segment: B,
+    order: BucketOrder,
+}
+
+impl TopHitsPerBucket<FastFieldBucket> {
+    /// Buckets by the value of the given u64 fast field
+    pub fn u64_field(field: Field, num_buckets: usize, hits_per_bucket: usize) -> Self {
+        Self::new(FastFieldBucket(field), num_buckets, hits_per_bucket)
+    }
+}
+
+impl TopHitsPerBucket<FacetBucket> {
+    /// Buckets by the given facets, one bucket per facet that any
+    /// matching document has. See `FacetBucket`
+    ///
+    /// # Panics
+    ///
+    /// Panics if `facets` is empty
+    pub fn facet_field(field: Field, facets: Vec<Facet>, hits_per_bucket: usize) -> Self {
+        let num_buckets = facets.len();
+        Self::new(
+            FacetBucket::new(field, facets),
+            num_buckets,
+            hits_per_bucket,
+        )
+    }
+}
+
+impl<B: BucketForSegment> TopHitsPerBucket<B> {
+    /// Creates a collector that yields up to `num_buckets` buckets
+    /// (the ones with the most documents) with up to
+    /// `hits_per_bucket` documents each
+    pub fn new(bucket_for_segment: B, num_buckets: usize, hits_per_bucket: usize) -> Self {
+        if num_buckets < 1 {
+            panic!("Number of buckets must be greater than 0");
+        }
+
+        Self {
+            num_buckets,
+            hits_per_bucket,
+            bucket_for_segment,
+            order: BucketOrder::Count,
+        }
+    }
+
+    /// Changes which buckets are kept (and in which order they are
+    /// yielded). `BucketOrder::Count` by default
+    ///
+    /// # Panics
+    ///
+    /// Panics if ordering by `BucketOrder::TopScore` without any
+    /// hits per bucket
+    pub fn with_order(mut self, order: BucketOrder) -> Self {
+        if order == BucketOrder::TopScore && self.hits_per_bucket < 1 {
+            panic!("Ordering by the top score requires hits per bucket");
+        }
+
+        self.order = order;
+        self
+    }
+}
+
+impl<B: BucketForSegment> Collector for TopHitsPerBucket<B> {
+    type Fruit = Vec<Bucket>;
+    type Child = TopHitsPerBucketSegmentCollector<B::Reader>;
+
+    fn for_segment(
+        &self,
+        segment_id: SegmentLocalId,
+        reader: &SegmentReader,
+    ) -> Result<Self::Child> {
+        Ok(TopHitsPerBucketSegmentCollector {
+            segment_id,
+            hits_per_bucket: self.hits_per_bucket,
+            reader: self.bucket_for_segment.for_segment(reader)?,
+            buckets: HashMap::new(),
+        })
+    }
+
+    fn requires_scoring(&self) -> bool {
+        true
+    }
+
+    fn merge_fruits(&self, fruits: Vec<Vec<Bucket>>) -> Result<Vec<Bucket>> {
+        let mut merged: HashMap<u64, (usize, Vec<(Score, DocAddress)>)> = HashMap::new();
+
+        for bucket in fruits.into_iter().flatten() {
+            let entry = merged.entry(bucket.key).or_default();
+            entry.0 += bucket.count;
+            entry.1.extend(bucket.hits);
+        }
+
+        let mut buckets = merged
+            .into_iter()
+            .map(|(key, (count, hits))| {
+                let mut topk =
+                    <Descending as TopKProvider<Score, DocAddress>>::new_topk(self.hits_per_bucket);
+                for (score, addr) in hits {
+                    TopK::visit(&mut topk, addr, score);
+                }
+
+                Bucket {
+                    key,
+                    count,
+                    hits: TopK::into_sorted_vec(topk)
+                        .into_iter()
+                        .map(|(addr, score)| (score, addr))
+                        .collect(),
+                }
+            })
+            .collect::<Vec<_>>();
+
+        match self.order {
+            // Largest first, smaller keys first on ties so that the
+            // result doesn't depend on the hashing order
+            BucketOrder::Count => {
+                buckets.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.key.cmp(&b.key)))
+            }
+            BucketOrder::TopScore => buckets.sort_by(|a, b| {
+                top_score(b)
+                    .partial_cmp(&top_score(a))
+                    .unwrap_or(Ordering::Equal)
+                    .then_with(|| a.key.cmp(&b.key))
+            }),
+        }
+        buckets.truncate(self.num_buckets);
+
+        Ok(buckets)
+    }
+}
+
+// Hits are sorted and buckets are never empty
+fn top_score(bucket: &Bucket) -> Score {
+    bucket
+        .hits
+        .first()
+        .map_or(std::f32::MIN, |(score, _addr)| *score)
+}
+
+type SegmentTopK = <Descending as TopKProvider<Score, DocId>>::Child;
+
+/// The per-segment part of `TopHitsPerBucket`
+pub struct TopHitsPerBucketSegmentCollector<R> {
+    segment_id: SegmentLocalId,
+    hits_per_bucket: usize,
+    reader: R,
+    buckets: HashMap<u64, (usize, SegmentTopK)>,
+}
+
+impl<R: BucketForDoc> SegmentCollector for TopHitsPerBucketSegmentCollector<R> {
+    type Fruit = Vec<Bucket>;
+
+    fn collect(&mut self, doc: DocId, score: Score) {
+        if let Some(key) = self.reader.bucket(doc) {
+            let hits_per_bucket = self.hits_per_bucket;
+            let (count, topk) = self.buckets.entry(key).or_insert_with(|| {
+                (
+                    0,
+                    <Descending as TopKProvider<Score, DocId>>::new_topk(hits_per_bucket),
+                )
+            });
+
+            *count += 1;
+            TopK::visit(topk, doc, score);
+        }
+    }
+
+    fn harvest(self) -> Vec<Bucket> {
+        let segment_id = self.segment_id;
+        self.buckets
+            .into_iter()
+            .map(|(key, (count, topk))| Bucket {
+                key,
+                count,
+                hits: TopK::into_vec(topk)
+                    .into_iter()
+                    .map(|(doc, score)| (score, DocAddress(segment_id, doc)))
+                    .collect(),
+            })
+            .collect()
+    }
+}
+
+/// A bucket along with the result of a sub-aggregation over its
+/// documents
+#[derive(Debug, Clone, PartialEq)]
+pub struct SubBucket<F> {
+    /// What the documents in this bucket have in common
+    pub key: u64,
+    /// How many matching documents have this key
+    pub count: usize,
+    /// What the nested collector yielded for the bucket's documents
+    pub fruit: F,
+}
+
+/// Groups the matching documents by key and runs a nested collector
+/// over each of the largest groups
+pub struct NestedCollector<B, C> {
+    num_buckets: usize,
+    bucket_for_segment: B,
+    nested: Arc<C>,
+}
+
+impl<C: 'static + Send + Collector> NestedCollector<FastFieldBucket, C> {
+    /// Buckets by the value of the given u64 fast field
+    pub fn u64_field(field: Field, num_buckets: usize, nested: C) -> Self {
+        Self::new(FastFieldBucket(field), num_buckets, nested)
+    }
+}
+
+impl<B: BucketForSegment, C: 'static + Send + Collector> NestedCollector<B, C> {
+    /// Creates a collector that yields up to `num_buckets` buckets
+    /// (the ones with the most documents), each with what `nested`
+    /// collected from its documents
+    pub fn new(bucket_for_segment: B, num_buckets: usize, nested: C) -> Self {
+        if num_buckets < 1 {
+            panic!("Number of buckets must be greater than 0");
+        }
+
+        Self {
+            num_buckets,
+            bucket_for_segment,
+            nested: Arc::new(nested),
+        }
+    }
+}
+
+impl<B, C> Collector for NestedCollector<B, C>
+where
+    B: BucketForSegment,
+    C: 'static + Send + Collector,
+{
+    type Fruit = Vec<SubBucket<C::Fruit>>;
+    type Child = NestedSegmentCollector<B::Reader, C>;
+
+    fn for_segment(
+        &self,
+        segment_id: SegmentLocalId,
+        reader: &SegmentReader,
+    ) -> Result<Self::Child> {
+        // Children are created as new keys show up, which can't fail
+        // if creating one upfront didn't
+        let spare = self.nested.for_segment(segment_id, reader)?;
+
+        Ok(NestedSegmentCollector {
+            segment_id,
+            segment_reader: reader.clone(),
+            reader: self.bucket_for_segment.for_segment(reader)?,
+            nested: self.nested.clone(),
+            spare: Some(spare),
+            buckets: HashMap::new(),
+        })
+    }
+
+    fn requires_scoring(&self) -> bool {
+        self.nested.requires_scoring()
+    }
+
+    fn merge_fruits(
+        &self,
+        fruits: Vec<Vec<SubBucket<C::Fruit>>>,
+    ) -> Result<Vec<SubBucket<C::Fruit>>> {
+        let mut merged: HashMap<u64, (usize, Vec<C::Fruit>)> = HashMap::new();
+
+        for bucket in fruits.into_iter().flatten() {
+            let entry = merged.entry(bucket.key).or_insert_with(|| (0, Vec::new()));
+            entry.0 += bucket.count;
+            entry.1.push(bucket.fruit);
+        }
+
+        let mut buckets = Vec::with_capacity(merged.len());
+        for (key, (count, fruits)) in merged.into_iter() {
+            buckets.push(SubBucket {
+                key,
+                count,
+                fruit: self.nested.merge_fruits(fruits)?,
+            });
+        }
+
+        // Same order as `TopHitsPerBucket`
+        buckets.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.key.cmp(&b.key)));
+        buckets.truncate(self.num_buckets);
+
+        Ok(buckets)
+    }
+}
+
+/// The per-segment part of `NestedCollector`
+pub struct NestedSegmentCollector<R, C: Collector> {
+    segment_id: SegmentLocalId,
+    segment_reader: SegmentReader,
+    reader: R,
+    nested: Arc<C>,
+    spare: Option<C::Child>,
+    buckets: HashMap<u64, (usize, C::Child)>,
+}
+
+impl<R, C> SegmentCollector for NestedSegmentCollector<R, C>
+where
+    R: BucketForDoc,
+    C: 'static + Send + Collector,
+{
+    type Fruit = Vec<SubBucket<C::Fruit>>;
+
+    fn collect(&mut self, doc: DocId, score: Score) {
+        let key = match self.reader.bucket(doc) {
+            Some(key) => key,
+            None => return,
+        };
+
+        let (count, child) = match self.buckets.entry(key) {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => {
+                let child = match self.spare.take() {
+                    Some(child) => child,
+                    None => self
+                        .nested
+                        .for_segment(self.segment_id, &self.segment_reader)
+                        .expect("Creating the nested segment collector worked before"),
+                };
+                entry.insert((0, child))
+            }
+        };
+
+        *count += 1;
+        child.collect(doc, score);
+    }
+
+    fn harvest(self) -> Vec<SubBucket<C::Fruit>> {
+        self.buckets
+            .into_iter()
+            .map(|(key, (count, child))| SubBucket {
+                key,
+                count,
+                fruit: child.harvest(),
+            })
+            .collect()
+    }
+}
+
+/// Counts documents per key, yielding the keys in ascending order
+/// one page at a time
+pub struct CompositeCollector<B> {
+    size: usize,
+    after: Option<u64>,
+    bucket_for_segment: B,
+}
+
+/// A page of keys and their document counts
+#[derive(Debug, Clone, PartialEq)]
+pub struct CompositePage {
+    /// `(key, count)` pairs, by ascending key
+    pub buckets: Vec<(u64, usize)>,
+    /// Where the next page starts. `None` when there are no more
+    /// keys. Note that a page may be full and still be the last one,
+    /// in which case the next will be empty
+    pub after_key: Option<u64>,
+}
+
+impl CompositeCollector<FastFieldBucket> {
+    /// Pages through the values of the given u64 fast field
+    pub fn u64_field(field: Field, size: usize, after: Option<u64>) -> Self {
+        Self::new(FastFieldBucket(field), size, after)
+    }
+}
+
+impl<B: BucketForSegment> CompositeCollector<B> {
+    /// Creates a collector that yields up to `size` keys, starting
+    /// right after `after` (or from the smallest one, if `None`)
+    pub fn new(bucket_for_segment: B, size: usize, after: Option<u64>) -> Self {
+        if size < 1 {
+            panic!("Page size must be greater than 0");
+        }
+
+        Self {
+            size,
+            after,
+            bucket_for_segment,
+        }
+    }
+}
+
+impl<B: BucketForSegment> Collector for CompositeCollector<B> {
+    type Fruit = CompositePage;
+    type Child = CompositeSegmentCollector<B::Reader>;
+
+    fn for_segment(
+        &self,
+        _segment_id: SegmentLocalId,
+        reader: &SegmentReader,
+    ) -> Result<Self::Child> {
+        Ok(CompositeSegmentCollector {
+            size: self.size,
+            after: self.after,
+            reader: self.bucket_for_segment.for_segment(reader)?,
+            counts: BTreeMap::new(),
+            bound: None,
+        })
+    }
+
+    fn requires_scoring(&self) -> bool {
+        false
+    }
+
+    fn merge_fruits(&self, fruits: Vec<CompositePage>) -> Result<CompositePage> {
+        let mut counts = BTreeMap::new();
+        for (key, count) in fruits.into_iter().flat_map(|page| page.buckets) {
+            *counts.entry(key).or_insert(0) += count;
+        }
+
+        let buckets = counts.into_iter().take(self.size).collect::<Vec<_>>();
+        let after_key = if buckets.len() == self.size {
+            buckets.last().map(|(key, _count)| *key)
+        } else {
+            None
+        };
+
+        Ok(CompositePage { buckets, after_key })
+    }
+}
+
+/// The per-segment part of `CompositeCollector`
+pub struct CompositeSegmentCollector<R> {
+    size: usize,
+    after: Option<u64>,
+    reader: R,
+    counts: BTreeMap<u64, usize>,
+    // Only the smallest `size` keys can make it to the page, so once
+    // that many are known every larger key is ignored for good. The
+    // counts of the remaining keys are always complete
+    bound: Option<u64>,
+}
+
+impl<R: BucketForDoc> SegmentCollector for CompositeSegmentCollector<R> {
+    type Fruit = CompositePage;
+
+    fn collect(&mut self, doc: DocId, _score: Score) {
+        let key = match self.reader.bucket(doc) {
+            Some(key) => key,
+            None => return,
+        };
+
+        if self.after.map_or(false, |after| key <= after)
+            || self.bound.map_or(false, |bound| key > bound)
+        {
+            return;
+        }
+
+        *self.counts.entry(key).or_insert(0) += 1;
+
+        if self.counts.len() > self.size {
+            let largest = *self.counts.keys().next_back().expect("counts is not empty");
+            self.counts.remove(&largest);
+        }
+
+        if self.counts.len() == self.size {
+            self.bound = self.counts.keys().next_back().copied();
+        }
+    }
+
+    fn harvest(self) -> CompositePage {
+        CompositePage {
+            buckets: self.counts.into_iter().collect(),
+            after_key: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use tantivy::{
+        doc,
+        query::{AllQuery, TermQuery},
+        schema::{IndexRecordOption, SchemaBuilder, Value, FAST, STORED, TEXT},
+        Index, Term,
+    };
+
+    #[test]
+    fn top_hits_per_bucket() -> Result<()> {
+        let mut builder = SchemaBuilder::new();
+        let body = builder.add_text_field("body", TEXT | STORED);
+        let category = builder.add_u64_field("category", FAST | STORED);
+        let index = Index::create_in_ram(builder.build());
+        let mut writer = index.writer_with_num_threads(1, 3_000_000)?;
+
+        // Category `i % 3`, gets more relevant the larger `i` is
+        for i in 0..30u64 {
+            let text = "cheese ".repeat(i as usize + 1);
+            writer.add_document(doc!(body => text, category => i % 3));
+            // Multiple segments
+            if i % 10 == 9 {
+                writer.commit()?;
+            }
+        }
+        // A smaller category
+        writer.add_document(doc!(body => "cheese", category => 42u64));
+        writer.commit()?;
+
+        let searcher = index.reader()?.searcher();
+        let query = TermQuery::new(
+            Term::from_field_text(body, "cheese"),
+            IndexRecordOption::WithFreqs,
+        );
+
+        let buckets = searcher.search(&query, &TopHitsPerBucket::u64_field(category, 3, 2))?;
+
+        assert_eq!(
+            vec![(0, 10), (1, 10), (2, 10)],
+            buckets
+                .iter()
+                .map(|bucket| (bucket.key, bucket.count))
+                .collect::<Vec<_>>()
+        );
+
+        for bucket in buckets.iter() {
+            assert_eq!(2, bucket.hits.len());
+            assert!(bucket.hits[0].0 >= bucket.hits[1].0);
+
+            // The best hits are the last ones added for the category
+            for (_score, addr) in bucket.hits.iter() {
+                let doc = searcher.doc(*addr)?;
+                assert_eq!(Some(&Value::U64(bucket.key)), doc.get_first(category));
+                let num_words = doc
+                    .get_first(body)
+                    .and_then(|v| v.text())
+                    .map_or(0, |text| text.split_whitespace().count());
+                assert!(num_words > 24);
+            }
+        }
+
+        let everything =
+            searcher.search(&AllQuery, &TopHitsPerBucket::u64_field(category, 10, 1))?;
+        assert_eq!(4, everything.len());
+        assert_eq!(42, everything[3].key);
+        assert_eq!(1, everything[3].count);
+
+        Ok(())
+    }
+
+    #[test]
+    fn top_hits_per_facet() -> Result<()> {
+        let mut builder = SchemaBuilder::new();
+        let body = builder.add_text_field("body", TEXT);
+        let cuisine = builder.add_facet_field("cuisine");
+        let index = Index::create_in_ram(builder.build());
+        let mut writer = index.writer_with_num_threads(1, 3_000_000)?;
+
+        let names = ["/italian", "/thai", "/italian/sicilian", "/greek"];
+        // Cuisine `i % 4`, gets more relevant the larger `i` is
+        for i in 0..40usize {
+            let text = "pasta ".repeat(i + 1);
+            writer.add_document(doc!(body => text, cuisine => Facet::from(names[i % 4])));
+            // Multiple segments, some without every facet
+            if i % 10 == 9 || i == 2 {
+                writer.commit()?;
+            }
+        }
+        writer.commit()?;
+
+        let searcher = index.reader()?.searcher();
+        assert!(searcher.segment_readers().len() > 1);
+        let query = TermQuery::new(
+            Term::from_field_text(body, "pasta"),
+            IndexRecordOption::WithFreqs,
+        );
+
+        let wanted = vec![
+            Facet::from("/greek"),
+            Facet::from("/italian"),
+            Facet::from("/mexican"),
+        ];
+        let buckets =
+            searcher.search(&query, &TopHitsPerBucket::facet_field(cuisine, wanted, 2))?;
+
+        // No bucket for the missing cuisine, nor for the sicilian
+        // recipes under `/italian`
+        assert_eq!(
+            vec![(0, 10), (1, 10)],
+            buckets
+                .iter()
+                .map(|bucket| (bucket.key, bucket.count))
+                .collect::<Vec<_>>()
+        );
+
+        for bucket in buckets.iter() {
+            assert_eq!(2, bucket.hits.len());
+            assert!(bucket.hits[0].0 >= bucket.hits[1].0);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn buckets_by_top_score() -> Result<()> {
+        let mut builder = SchemaBuilder::new();
+        let body = builder.add_text_field("body", TEXT);
+        let family = builder.add_u64_field("family", FAST);
+        let index = Index::create_in_ram(builder.build());
+        let mut writer = index.writer_with_num_threads(1, 3_000_000)?;
+
+        // Family 1 is the largest, family 3 has the best match
+        for (times, key) in &[(1, 1u64), (2, 1), (1, 1), (2, 2), (6, 3), (3, 2)] {
+            writer.add_document(doc!(body => "cheese ".repeat(*times), family => *key));
+            writer.commit()?;
+        }
+
+        let searcher = index.reader()?.searcher();
+        let query = TermQuery::new(
+            Term::from_field_text(body, "cheese"),
+            IndexRecordOption::WithFreqs,
+        );
+
+        let keys = |order| -> Result<Vec<u64>> {
+            let collector = TopHitsPerBucket::u64_field(family, 2, 1).with_order(order);
+            Ok(searcher
+                .search(&query, &collector)?
+                .into_iter()
+                .map(|bucket| bucket.key)
+                .collect())
+        };
+
+        assert_eq!(vec![1, 2], keys(BucketOrder::Count)?);
+        assert_eq!(vec![3, 2], keys(BucketOrder::TopScore)?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn composite_pagination() -> Result<()> {
+        let mut builder = SchemaBuilder::new();
+        let author = builder.add_u64_field("author", FAST);
+        let index = Index::create_in_ram(builder.build());
+        let mut writer = index.writer_with_num_threads(1, 3_000_000)?;
+
+        // Author `i` has `i % 4 + 1` docs, spread across segments
+        let mut wanted = BTreeMap::new();
+        for round in 0..4 {
+            for i in (0..100u64).rev() {
+                if round <= i % 4 {
+                    writer.add_document(doc!(author => i * 10));
+                    *wanted.entry(i * 10).or_insert(0) += 1;
+                }
+            }
+            writer.commit()?;
+        }
+
+        let searcher = index.reader()?.searcher();
+        assert!(searcher.segment_readers().len() > 1);
+
+        for &size in &[1, 7, 25, 100, 1000] {
+            let mut found = BTreeMap::new();
+            let mut after = None;
+            loop {
+                let page = searcher.search(
+                    &AllQuery,
+                    &CompositeCollector::u64_field(author, size, after),
+                )?;
+                assert!(page.buckets.len() <= size);
+                assert!(page.buckets.windows(2).all(|pair| pair[0].0 < pair[1].0));
+
+                for (key, count) in page.buckets {
+                    assert!(after.map_or(true, |after| key > after));
+                    assert_eq!(None, found.insert(key, count));
+                }
+
+                if page.after_key.is_none() {
+                    break;
+                }
+                after = page.after_key;
+            }
+
+            assert_eq!(wanted, found, "size={}", size);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn nested_stats_per_bucket() -> Result<()> {
+        use crate::metrics::StatsCollector;
+
+        let mut builder = SchemaBuilder::new();
+        let cuisine = builder.add_u64_field("cuisine", FAST);
+        let calories = builder.add_f64_field("calories", FAST);
+        let index = Index::create_in_ram(builder.build());
+        let mut writer = index.writer_with_num_threads(1, 3_000_000)?;
+
+        // Cuisine `i % 3`, with `i * 10` calories
+        for i in 0..30u64 {
+            writer.add_document(doc!(cuisine => i % 3, calories => (i * 10) as f64));
+            // Multiple segments
+            if i % 10 == 9 {
+                writer.commit()?;
+            }
+        }
+        writer.add_document(doc!(cuisine => 42u64, calories => 1.0));
+        writer.commit()?;
+
+        let searcher = index.reader()?.searcher();
+        assert!(searcher.segment_readers().len() > 1);
+
+        let buckets = searcher.search(
+            &AllQuery,
+            &NestedCollector::u64_field(cuisine, 3, StatsCollector::f64_field(calories)),
+        )?;
+
+        assert_eq!(
+            vec![(0, 10), (1, 10), (2, 10)],
+            buckets
+                .iter()
+                .map(|bucket| (bucket.key, bucket.count))
+                .collect::<Vec<_>>()
+        );
+
+        for bucket in buckets.iter() {
+            let stats = &bucket.fruit[0];
+            assert_eq!(10, stats.count);
+            // 0, 30, .., 270 offset by ten times the key
+            let min = (bucket.key * 10) as f64;
+            assert_eq!(Some(min), stats.min);
+            assert_eq!(Some(min + 270.0), stats.max);
+            assert_eq!(Some(min + 135.0), stats.mean());
+        }
+
+        Ok(())
+    }
+}
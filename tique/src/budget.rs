@@ -0,0 +1,244 @@
+//! Searching within a budget
+//!
+//! `BudgetCollector` wraps any collector so that collection stops
+//! early once a search takes too long or visits too many documents,
+//! yielding whatever was collected so far alongside a flag telling
+//! it's partial. Useful to keep a few pathological queries from
+//! hogging the threads everyone else is waiting for.
+//!
+//! ```no_run
+//! # use std::time::Duration;
+//! # use tantivy::{collector::Count, query::AllQuery, Searcher};
+//! # use tique::budget::{Budget, BudgetCollector};
+//! # fn example(searcher: &Searcher) -> tantivy::Result<()> {
+//! let budget = Budget::default()
+//!     .with_timeout(Duration::from_millis(50))
+//!     .with_max_docs_visited(100_000);
+//! let (count, exhausted) = searcher.search(&AllQuery, &BudgetCollector::new(Count, budget))?;
+//! if exhausted {
+//!     println!("Counted at least {} docs", count);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! The clock starts when the budget is created, so a budget can be
+//! shared by the searches of a single request. Documents are only
+//! counted per collector, though. The time is checked every few
+//! documents and before each segment, so a search may go over the
+//! timeout by as long as visiting these takes.
+//!
+//! The budget is enforced by `BudgetCollector::collect_segment`, so
+//! collectors wrapping it must forward theirs to it, like
+//! `TimedCollector` and `PartialCollector` do. Collectors that only
+//! need to see the documents, like a filtering one, go inside it.
+use std::{
+    sync::atomic::{AtomicBool, AtomicU64, Ordering},
+    time::{Duration, Instant},
+};
+
+use tantivy::{
+    collector::{Collector, SegmentCollector},
+    query::Weight,
+    DocSet, Result, SegmentLocalId, SegmentReader, TERMINATED,
+};
+
+// How many documents to visit between checking the clock
+const CLOCK_CHECK_INTERVAL: u64 = 1024;
+
+/// How much a search is allowed to do. Unlimited by default
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Budget {
+    deadline: Option<Instant>,
+    max_docs_visited: Option<u64>,
+}
+
+impl Budget {
+    /// Stops searches once `timeout` has passed since now
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.deadline = Some(Instant::now() + timeout);
+        self
+    }
+
+    /// Stops searches after visiting `max_docs` matching documents
+    pub fn with_max_docs_visited(mut self, max_docs: u64) -> Self {
+        self.max_docs_visited = Some(max_docs);
+        self
+    }
+
+    /// Whether there's no time left
+    pub fn has_expired(&self) -> bool {
+        self.deadline
+            .map_or(false, |deadline| Instant::now() >= deadline)
+    }
+}
+
+/// Wraps a collector, stopping it when the budget is exhausted
+pub struct BudgetCollector<C> {
+    inner: C,
+    budget: Budget,
+    visited: AtomicU64,
+    exhausted: AtomicBool,
+}
+
+impl<C: Collector> BudgetCollector<C> {
+    /// Creates a new collector that limits the given one to `budget`
+    pub fn new(collector: C, budget: Budget) -> Self {
+        Self {
+            inner: collector,
+            budget,
+            visited: AtomicU64::new(0),
+            exhausted: AtomicBool::new(false),
+        }
+    }
+
+    // Whether there's room to visit another document
+    fn visit(&self) -> bool {
+        if self.exhausted.load(Ordering::Relaxed) {
+            return false;
+        }
+
+        let visited = self.visited.fetch_add(1, Ordering::Relaxed) + 1;
+        let exhausted = self
+            .budget
+            .max_docs_visited
+            .map_or(false, |max| visited > max)
+            || (visited % CLOCK_CHECK_INTERVAL == 0 && self.budget.has_expired());
+
+        if exhausted {
+            self.exhausted.store(true, Ordering::Relaxed);
+        }
+        !exhausted
+    }
+}
+
+type SegmentFruit<C> = <<C as Collector>::Child as SegmentCollector>::Fruit;
+
+impl<C: Collector> Collector for BudgetCollector<C> {
+    /// The result and whether collection stopped early
+    type Fruit = (C::Fruit, bool);
+    type Child = C::Child;
+
+    fn for_segment(
+        &self,
+        segment_id: SegmentLocalId,
+        reader: &SegmentReader,
+    ) -> Result<Self::Child> {
+        self.inner.for_segment(segment_id, reader)
+    }
+
+    fn requires_scoring(&self) -> bool {
+        self.inner.requires_scoring()
+    }
+
+    // Like the default, but driving the scorer by hand so that it
+    // can be stopped halfway
+    fn collect_segment(
+        &self,
+        weight: &dyn Weight,
+        segment_ord: u32,
+        reader: &SegmentReader,
+    ) -> Result<SegmentFruit<C>> {
+        let mut collector = self.for_segment(segment_ord, reader)?;
+
+        if self.budget.has_expired() {
+            self.exhausted.store(true, Ordering::Relaxed);
+            return Ok(collector.harvest());
+        }
+
+        let mut scorer = weight.scorer(reader, 1.0)?;
+        let delete_bitset = reader.delete_bitset();
+
+        let mut doc = scorer.doc();
+        while doc != TERMINATED && self.visit() {
+            if delete_bitset.map_or(true, |deleted| deleted.is_alive(doc)) {
+                collector.collect(doc, scorer.score());
+            }
+            doc = scorer.advance();
+        }
+
+        Ok(collector.harvest())
+    }
+
+    fn merge_fruits(&self, fruits: Vec<SegmentFruit<C>>) -> Result<Self::Fruit> {
+        Ok((
+            self.inner.merge_fruits(fruits)?,
+            self.exhausted.load(Ordering::Relaxed),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::timing::TimedCollector;
+
+    use tantivy::{
+        collector::{Count, TopDocs},
+        doc,
+        query::AllQuery,
+        schema::{SchemaBuilder, INDEXED},
+        Index,
+    };
+
+    #[test]
+    fn integration() -> Result<()> {
+        let mut builder = SchemaBuilder::new();
+        let id = builder.add_u64_field("id", INDEXED);
+        let index = Index::create_in_ram(builder.build());
+        let mut writer = index.writer_with_num_threads(1, 3_000_000)?;
+
+        for i in 0..30u64 {
+            writer.add_document(doc!(id => i));
+            // Multiple segments
+            if i % 10 == 9 {
+                writer.commit()?;
+            }
+        }
+
+        let searcher = index.reader()?.searcher();
+        assert!(searcher.segment_readers().len() > 1);
+
+        // Results are untouched when there's enough budget
+        let (top, exhausted) = searcher.search(
+            &AllQuery,
+            &BudgetCollector::new(TopDocs::with_limit(5), Budget::default()),
+        )?;
+        assert!(!exhausted);
+        assert_eq!(searcher.search(&AllQuery, &TopDocs::with_limit(5))?, top);
+
+        let budget = Budget::default()
+            .with_timeout(Duration::from_secs(60))
+            .with_max_docs_visited(30);
+        let (count, exhausted) =
+            searcher.search(&AllQuery, &BudgetCollector::new(Count, budget))?;
+        assert!(!exhausted);
+        assert_eq!(30, count);
+
+        let budget = Budget::default().with_max_docs_visited(12);
+        let (count, exhausted) =
+            searcher.search(&AllQuery, &BudgetCollector::new(Count, budget))?;
+        assert!(exhausted);
+        assert_eq!(12, count);
+
+        let budget = Budget::default().with_timeout(Duration::from_secs(0));
+        assert!(budget.has_expired());
+        let (count, exhausted) =
+            searcher.search(&AllQuery, &BudgetCollector::new(Count, budget))?;
+        assert!(exhausted);
+        assert_eq!(0, count);
+
+        // Still enforced when wrapped
+        let budget = Budget::default().with_max_docs_visited(12);
+        let ((count, exhausted), timings) = searcher.search(
+            &AllQuery,
+            &TimedCollector::new(BudgetCollector::new(Count, budget)),
+        )?;
+        assert!(exhausted);
+        assert_eq!(12, count);
+        assert_eq!(searcher.segment_readers().len(), timings.segments.len());
+
+        Ok(())
+    }
+}
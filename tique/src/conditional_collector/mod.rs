@@ -96,11 +96,21 @@
 //! going without ever having to increase `limit`.
 //!
 //! Check `examples/conditional_collector_tutorial.rs` for more details.
+//!
+//! # Two-Phase Collection
+//!
+//! Queries that match most of the index spend most of their time
+//! scoring documents that never make it to the top. `TwoPhaseTopDocs`
+//! samples the matches first to find a score the top documents must
+//! beat, then lets tantivy skip everything that can't.
 mod custom_score;
 mod top_collector;
 pub(crate) mod topk;
 mod traits;
+mod tweaked_score;
+mod two_phase;
 
 pub use top_collector::{CollectionResult, TopCollector};
 pub use topk::{Ascending, Descending};
 pub use traits::*;
+pub use two_phase::{TwoPhaseStats, TwoPhaseTopDocs};
@@ -1,7 +1,7 @@
 use std::marker::PhantomData;
 
 use tantivy::{
-    collector::{Collector, CustomScorer, SegmentCollector},
+    collector::{Collector, CustomScorer, ScoreTweaker, SegmentCollector},
     DocAddress, DocId, Result, Score, SegmentLocalId, SegmentReader,
 };
 
@@ -9,6 +9,7 @@ use super::{
     custom_score::CustomScoreTopCollector,
     topk::{TopK, TopKProvider},
     traits::{CheckCondition, ConditionForSegment},
+    tweaked_score::TweakedScoreTopCollector,
 };
 
 /// A TopCollector like tantivy's, with added support for ordering
@@ -61,6 +62,25 @@ use super::{
 ///         .with_custom_scorer(scorer);
 /// ```
 ///
+/// ## Tweaking the original Score
+///
+/// ```no_run
+/// # use tique::conditional_collector::{TopCollector, Descending};
+/// # use tantivy::{SegmentReader, DocId, Score};
+/// # let num_ratings = tantivy::schema::Field::from_field_id(0);
+/// # let limit = 10;
+/// # let condition = true;
+/// // Any `tantivy::collector::ScoreTweaker` is valid
+/// let tweaker = move |reader: &SegmentReader| {
+///     let ratings = reader.fast_fields().u64(num_ratings).unwrap();
+///     move |doc_id: DocId, score: Score| score * (1.0 + ratings.get(doc_id) as f32).ln()
+/// };
+///
+/// let tweaked_collector =
+///     TopCollector::<Score, Descending, _>::new(limit, condition)
+///         .with_score_tweaker(tweaker);
+/// ```
+///
 /// ## Using a fast field as the score
 ///
 /// One typical use-case for customizing scores is sorting by a
@@ -130,6 +150,20 @@ where
             custom_scorer,
         )
     }
+
+    /// Transforms this collector into one that ranks by a function
+    /// of each document and its original score, like tantivy's
+    /// `TopDocs::tweak_score`.
+    pub fn with_score_tweaker<W: Send + ScoreTweaker<T>>(
+        self,
+        score_tweaker: W,
+    ) -> impl Collector<Fruit = CollectionResult<T>> {
+        TweakedScoreTopCollector::<T, P, _, _>::new(
+            self.limit,
+            self.condition_for_segment,
+            score_tweaker,
+        )
+    }
 }
 
 macro_rules! impl_top_fast_field {
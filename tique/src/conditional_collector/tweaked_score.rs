@@ -0,0 +1,195 @@
+use std::marker::PhantomData;
+
+use tantivy::{
+    collector::{Collector, ScoreSegmentTweaker, ScoreTweaker, SegmentCollector},
+    DocId, Result, Score, SegmentLocalId, SegmentReader,
+};
+
+use super::{
+    top_collector::TopSegmentCollector,
+    topk::{TopK, TopKProvider},
+    traits::{CheckCondition, ConditionForSegment},
+    CollectionResult,
+};
+
+pub(crate) struct TweakedScoreTopCollector<T, P, C, W>
+where
+    T: PartialOrd,
+    P: TopKProvider<T, DocId>,
+    C: ConditionForSegment<T>,
+{
+    limit: usize,
+    score_tweaker: W,
+    condition_for_segment: C,
+    _score: PhantomData<T>,
+    _provider: PhantomData<P>,
+}
+
+impl<T, P, C, W> TweakedScoreTopCollector<T, P, C, W>
+where
+    T: PartialOrd,
+    P: TopKProvider<T, DocId>,
+    C: ConditionForSegment<T>,
+{
+    pub fn new(limit: usize, condition_for_segment: C, score_tweaker: W) -> Self {
+        Self {
+            limit,
+            score_tweaker,
+            condition_for_segment,
+            _score: PhantomData,
+            _provider: PhantomData,
+        }
+    }
+}
+
+impl<T, P, C, W> Collector for TweakedScoreTopCollector<T, P, C, W>
+where
+    T: 'static + PartialOrd + Copy + Send + Sync,
+    P: 'static + Send + Sync + TopKProvider<T, DocId>,
+    C: Send + Sync + ConditionForSegment<T>,
+    W: Send + ScoreTweaker<T>,
+{
+    type Fruit = CollectionResult<T>;
+    type Child = TweakedScoreTopSegmentCollector<T, C::Type, W::Child, P::Child>;
+
+    fn requires_scoring(&self) -> bool {
+        true
+    }
+
+    fn merge_fruits(&self, children: Vec<Self::Fruit>) -> Result<Self::Fruit> {
+        Ok(P::merge_many(self.limit, children))
+    }
+
+    fn for_segment(
+        &self,
+        segment_id: SegmentLocalId,
+        reader: &SegmentReader,
+    ) -> Result<Self::Child> {
+        let tweaker = self.score_tweaker.segment_tweaker(reader)?;
+        Ok(TweakedScoreTopSegmentCollector::new(
+            segment_id,
+            P::new_topk(self.limit),
+            tweaker,
+            self.condition_for_segment.for_segment(reader),
+        ))
+    }
+}
+
+pub struct TweakedScoreTopSegmentCollector<T, C, W, K>
+where
+    C: CheckCondition<T>,
+    K: TopK<T, DocId>,
+{
+    tweaker: W,
+    collector: TopSegmentCollector<T, K, C>,
+}
+
+impl<T, C, W, K> TweakedScoreTopSegmentCollector<T, C, W, K>
+where
+    T: Copy,
+    C: CheckCondition<T>,
+    K: TopK<T, DocId>,
+{
+    pub fn new(segment_id: SegmentLocalId, topk: K, tweaker: W, condition: C) -> Self {
+        Self {
+            tweaker,
+            collector: TopSegmentCollector::new(segment_id, topk, condition),
+        }
+    }
+}
+
+impl<T, C, W, K> SegmentCollector for TweakedScoreTopSegmentCollector<T, C, W, K>
+where
+    T: 'static + PartialOrd + Copy + Send + Sync,
+    K: 'static + TopK<T, DocId>,
+    C: CheckCondition<T>,
+    W: ScoreSegmentTweaker<T>,
+{
+    type Fruit = CollectionResult<T>;
+
+    fn collect(&mut self, doc: DocId, score: Score) {
+        let tweaked = self.tweaker.score(doc, score);
+        self.collector.collect(doc, tweaked);
+    }
+
+    fn harvest(self) -> Self::Fruit {
+        self.collector.into_unsorted_collection_result()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::conditional_collector::{topk::AscendingTopK, Descending};
+
+    use tantivy::{
+        doc,
+        query::TermQuery,
+        schema::{IndexRecordOption, SchemaBuilder, FAST, TEXT},
+        Index, Term,
+    };
+
+    #[test]
+    fn segment_tweaker_sees_the_score() {
+        let mut collector = TweakedScoreTopSegmentCollector::new(
+            0,
+            AscendingTopK::new(1),
+            // Mixes the doc_id into the score
+            |doc_id: DocId, score: Score| f64::from(score) + f64::from(doc_id),
+            true,
+        );
+
+        collector.collect(1, 41.0);
+        let res = collector.harvest();
+        assert_eq!(1, res.total);
+        assert_eq!(42.0, res.items[0].0);
+    }
+
+    #[test]
+    fn tweaked_top_scorer_integration() -> Result<()> {
+        let mut builder = SchemaBuilder::new();
+        let body = builder.add_text_field("body", TEXT);
+        let num_ratings = builder.add_u64_field("num_ratings", FAST);
+        let index = Index::create_in_ram(builder.build());
+
+        let mut writer = index.writer_with_num_threads(1, 3_000_000)?;
+
+        // Same text, so the same score. Only the ratings differ
+        for ratings in &[0u64, 10, 1000, 100] {
+            writer.add_document(doc!(body => "cheese", num_ratings => *ratings));
+        }
+        writer.commit()?;
+
+        let searcher = index.reader()?.searcher();
+        let query = TermQuery::new(
+            Term::from_field_text(body, "cheese"),
+            IndexRecordOption::Basic,
+        );
+
+        let collector = TweakedScoreTopCollector::<_, Descending, _, _>::new(
+            2,
+            true,
+            move |reader: &SegmentReader| {
+                let ratings_reader = reader.fast_fields().u64(num_ratings).unwrap();
+                move |doc: DocId, score: Score| {
+                    score * (1.0 + ratings_reader.get(doc) as Score).ln()
+                }
+            },
+        );
+
+        let result = searcher.search(&query, &collector)?;
+
+        assert_eq!(4, result.total);
+        assert_eq!(
+            vec![2, 3],
+            result
+                .items
+                .iter()
+                .map(|(_score, addr)| addr.1)
+                .collect::<Vec<_>>()
+        );
+        assert!(result.items[0].0 > result.items[1].0);
+
+        Ok(())
+    }
+}
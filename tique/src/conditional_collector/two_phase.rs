@@ -0,0 +1,414 @@
+use std::time::{Duration, Instant};
+
+use tantivy::{
+    query::Query, DocAddress, DocId, DocSet, Result, Score, Searcher, SegmentLocalId, TERMINATED,
+};
+
+use super::{
+    topk::{DescendingTopK, TopK, TopKProvider},
+    traits::{CheckCondition, ConditionForSegment},
+    CollectionResult, Descending,
+};
+
+/// Finds the top documents by relevance in two passes, trading an
+/// accurate `total` for speed on queries that match a lot of
+/// documents (think long OR queries).
+///
+/// The first pass scores a sample of the matching documents (the
+/// first `sample_size` of every segment) to find a lower bound for
+/// the score of the k-th best document. The second pass scores every
+/// document, but lets tantivy skip the ones that can't beat that
+/// bound. The top documents are exact: the same (and in the same
+/// order) as `TopCollector::<Score, Descending, _>` would yield.
+///
+/// Only descending relevance benefits from this, so there's no
+/// ordering nor custom scoring support.
+///
+/// ```no_run
+/// # use tantivy::{query::AllQuery, Searcher};
+/// # use tique::conditional_collector::TwoPhaseTopDocs;
+/// # fn test(searcher: &Searcher) -> tantivy::Result<()> {
+/// let (result, stats) = TwoPhaseTopDocs::new(10, true)
+///     .with_sample_size(1000)
+///     .search(searcher, &AllQuery)?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct TwoPhaseTopDocs<CF> {
+    limit: usize,
+    sample_size: usize,
+    condition_for_segment: CF,
+}
+
+/// What happened during each phase of a `TwoPhaseTopDocs` search
+#[derive(Debug, Clone, Default)]
+pub struct TwoPhaseStats {
+    /// How many documents were scored in the sampling phase
+    pub sampled: usize,
+    /// The lower bound found via sampling. `None` when the sample
+    /// didn't have enough documents to yield one
+    pub threshold: Option<Score>,
+    /// Time spent sampling
+    pub sample_time: Duration,
+    /// How many documents the exact phase had to look at
+    pub scored: usize,
+    /// Time spent in the exact phase
+    pub exact_time: Duration,
+    /// How many documents match, extrapolated from how dense the
+    /// matches are within the sample of each segment. The same as
+    /// `total` when nothing was skipped
+    pub estimated_total: usize,
+    /// Half the width of a ~95% confidence interval around
+    /// `estimated_total`. Zero when it's exact
+    pub estimated_total_error: usize,
+}
+
+const DEFAULT_SAMPLE_SIZE: usize = 1000;
+
+impl<CF> TwoPhaseTopDocs<CF>
+where
+    CF: ConditionForSegment<Score>,
+{
+    /// Creates a new TwoPhaseTopDocs that collects up to `limit`
+    /// documents respecting the given `ConditionForSegment`
+    pub fn new(limit: usize, condition_for_segment: CF) -> Self {
+        if limit < 1 {
+            panic!("Limit must be greater than 0");
+        }
+
+        Self {
+            limit,
+            sample_size: DEFAULT_SAMPLE_SIZE,
+            condition_for_segment,
+        }
+    }
+
+    /// How many matching documents to score per segment when sampling
+    pub fn with_sample_size(mut self, sample_size: usize) -> Self {
+        self.sample_size = sample_size;
+        self
+    }
+
+    /// Executes the search
+    ///
+    /// The resulting `items` and `has_next()` are exact, but `total`
+    /// only counts the documents that weren't skipped, so it's a
+    /// lower bound (see `TwoPhaseStats::estimated_total` for a better
+    /// guess). `visited` is only meaningful for `has_next()`.
+    pub fn search(
+        &self,
+        searcher: &Searcher,
+        query: &dyn Query,
+    ) -> Result<(CollectionResult<Score>, TwoPhaseStats)> {
+        let mut stats = TwoPhaseStats::default();
+        let weight = query.weight(searcher, true)?;
+
+        // One extra item tells whether there's a next page without
+        // relying on `visited`, which pruning makes inexact
+        let wanted = self.limit + 1;
+
+        let start = Instant::now();
+        let mut sample = DescendingTopK::new(wanted);
+        let mut num_in_sample = 0;
+        let mut estimate = Estimate::default();
+        for (segment_ord, reader) in searcher.segment_readers().iter().enumerate() {
+            let segment_id = segment_ord as SegmentLocalId;
+            let condition = self.condition_for_segment.for_segment(reader);
+            let mut scorer = weight.scorer(reader, 1.0)?;
+
+            let mut num_scored = 0;
+            let mut doc = scorer.doc();
+            while doc != TERMINATED && num_scored < self.sample_size {
+                if !reader.is_deleted(doc) {
+                    num_scored += 1;
+                    let score = scorer.score();
+                    if condition.check(segment_id, doc, score, false) {
+                        num_in_sample += 1;
+                        TopK::visit(&mut sample, (segment_id, doc), score);
+                    }
+                }
+                doc = scorer.advance();
+            }
+            stats.sampled += num_scored;
+
+            if doc == TERMINATED {
+                estimate.add_exact(num_scored);
+            } else {
+                // Every doc before the current one was looked at
+                estimate.add_sampled(num_scored, doc, reader.max_doc());
+            }
+        }
+
+        // Every doc in the sample is a real match, so the top docs
+        // can't score any lower than the worst of the sample's top
+        if num_in_sample >= wanted {
+            stats.threshold = TopK::into_vec(sample)
+                .into_iter()
+                .map(|(_doc, score)| score)
+                .fold(None, |min: Option<Score>, score| {
+                    Some(min.map_or(score, |min| min.min(score)))
+                });
+        }
+        stats.sample_time = start.elapsed();
+
+        let start = Instant::now();
+        // Pruning skips docs that don't score *above* the threshold,
+        // but the ones that tie must be kept for exact tie-breaking
+        let threshold = stats.threshold.map_or(Score::MIN, just_below);
+
+        let mut children = Vec::with_capacity(searcher.segment_readers().len());
+        for (segment_ord, reader) in searcher.segment_readers().iter().enumerate() {
+            let segment_id = segment_ord as SegmentLocalId;
+            let condition = self.condition_for_segment.for_segment(reader);
+            let mut topk = <Descending as TopKProvider<Score, DocId>>::new_topk(wanted);
+            let mut total = 0;
+            let mut visited = 0;
+
+            weight.for_each_pruning(threshold, reader, &mut |doc, score| {
+                if !reader.is_deleted(doc) {
+                    total += 1;
+                    if condition.check(segment_id, doc, score, false) {
+                        visited += 1;
+                        TopK::visit(&mut topk, doc, score);
+                    }
+                }
+                threshold
+            })?;
+
+            stats.scored += total;
+            children.push(CollectionResult {
+                total,
+                visited,
+                items: TopK::into_vec(topk)
+                    .into_iter()
+                    .map(|(doc, score)| (score, DocAddress(segment_id, doc)))
+                    .collect(),
+            });
+        }
+
+        let mut result = <Descending as TopKProvider<Score, DocId>>::merge_many(wanted, children);
+        // Makes `has_next()` agree with the extra item we asked for
+        result.visited = result.items.len();
+        result.items.truncate(self.limit);
+        stats.exact_time = start.elapsed();
+
+        if stats.threshold.is_some() {
+            let (value, error) = estimate.finish();
+            // Every scored doc is a match, so there's no going lower
+            stats.estimated_total = value.max(stats.scored);
+            stats.estimated_total_error = error;
+        } else {
+            stats.estimated_total = stats.scored;
+        }
+
+        Ok((result, stats))
+    }
+}
+
+// 95% of a normal distribution is within this many standard
+// deviations of the mean
+const CONFIDENCE_Z: f64 = 1.96;
+
+#[derive(Default)]
+struct Estimate {
+    value: f64,
+    variance: f64,
+}
+
+impl Estimate {
+    fn add_exact(&mut self, num_matches: usize) {
+        self.value += num_matches as f64;
+    }
+
+    // Treats the first `num_seen` docs as a sample of the whole
+    // segment, extrapolating the matches for the rest of it
+    fn add_sampled(&mut self, num_matches: usize, num_seen: DocId, max_doc: DocId) {
+        let num_matches = num_matches as f64;
+        let num_seen = f64::from(num_seen.max(1));
+        let num_unseen = f64::from(max_doc) - num_seen;
+
+        let density = num_matches / num_seen;
+        self.value += num_matches + density * num_unseen;
+
+        // Binomial variance, with the finite population correction
+        self.variance += num_unseen.powi(2) * density * (1.0 - density) / num_seen
+            * (num_unseen / f64::from(max_doc));
+    }
+
+    fn finish(self) -> (usize, usize) {
+        (
+            self.value.round() as usize,
+            (CONFIDENCE_Z * self.variance.sqrt()).ceil() as usize,
+        )
+    }
+}
+
+// The largest score that's smaller than the given one
+fn just_below(score: Score) -> Score {
+    if score > 0.0 && score.is_finite() {
+        Score::from_bits(score.to_bits() - 1)
+    } else {
+        // Not worth the trouble: BM25 scores are positive
+        Score::MIN
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use tantivy::{
+        doc,
+        query::{BooleanQuery, Occur, TermQuery},
+        schema::{IndexRecordOption, SchemaBuilder, TEXT},
+        Index, SegmentReader, Term,
+    };
+
+    use crate::conditional_collector::TopCollector;
+
+    #[test]
+    fn just_below_is_below() {
+        for score in &[0.1, 1.0, 42.42, 1e-30] {
+            assert!(just_below(*score) < *score);
+        }
+        assert_eq!(Score::MIN, just_below(0.0));
+    }
+
+    #[test]
+    fn estimate() {
+        let mut estimate = Estimate::default();
+        estimate.add_exact(5);
+        // A tenth of the first 100 docs matched
+        estimate.add_sampled(10, 100, 1000);
+
+        assert_eq!((105, 51), estimate.finish());
+
+        let mut estimate = Estimate::default();
+        estimate.add_exact(5);
+        assert_eq!((5, 0), estimate.finish());
+    }
+
+    #[test]
+    fn same_results_as_a_single_pass() -> Result<()> {
+        let mut builder = SchemaBuilder::new();
+        let field = builder.add_text_field("field", TEXT);
+        let index = Index::create_in_ram(builder.build());
+        let mut writer = index.writer_with_num_threads(1, 3_000_000)?;
+
+        let words = ["apple", "banana", "cherry", "date", "elderberry"];
+        for i in 0..500usize {
+            let text = words
+                .iter()
+                .enumerate()
+                .filter(|(idx, _)| i % (idx + 2) == 0)
+                .map(|(_, word)| *word)
+                .collect::<Vec<_>>()
+                .join(" ");
+            writer.add_document(doc!(field => text));
+            // Multiple segments
+            if i % 200 == 199 {
+                writer.commit()?;
+            }
+        }
+        writer.commit()?;
+
+        let searcher = index.reader()?.searcher();
+
+        let query = BooleanQuery::from(
+            words
+                .iter()
+                .map(|word| {
+                    let query: Box<dyn Query> = Box::new(TermQuery::new(
+                        Term::from_field_text(field, word),
+                        IndexRecordOption::WithFreqs,
+                    ));
+                    (Occur::Should, query)
+                })
+                .collect::<Vec<_>>(),
+        );
+
+        let odd_docs = |_: &SegmentReader| {
+            |_: SegmentLocalId, doc_id: DocId, _: Score, _: bool| doc_id % 2 == 1
+        };
+
+        for &limit in &[1, 7, 30, 1000] {
+            for &sample_size in &[0, 10, 100] {
+                let wanted = searcher.search(
+                    &query,
+                    &TopCollector::<Score, Descending, _>::new(limit, true),
+                )?;
+                let (found, stats) = TwoPhaseTopDocs::new(limit, true)
+                    .with_sample_size(sample_size)
+                    .search(&searcher, &query)?;
+
+                assert_eq!(wanted.items, found.items, "limit={}", limit);
+                assert_eq!(wanted.has_next(), found.has_next());
+                assert!(found.total <= wanted.total);
+                assert!(stats.scored <= wanted.total);
+                assert!(stats.estimated_total >= stats.scored);
+                if stats.threshold.is_none() {
+                    // Nothing skipped: exact
+                    assert_eq!(wanted.total, stats.estimated_total);
+                    assert_eq!(0, stats.estimated_total_error);
+                }
+
+                let wanted = searcher.search(
+                    &query,
+                    &TopCollector::<Score, Descending, _>::new(limit, odd_docs),
+                )?;
+                let (found, _stats) = TwoPhaseTopDocs::new(limit, odd_docs)
+                    .with_sample_size(sample_size)
+                    .search(&searcher, &query)?;
+
+                assert_eq!(wanted.items, found.items);
+                assert_eq!(wanted.has_next(), found.has_next());
+            }
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn pagination() -> Result<()> {
+        let mut builder = SchemaBuilder::new();
+        let field = builder.add_text_field("field", TEXT);
+        let index = Index::create_in_ram(builder.build());
+        let mut writer = index.writer_with_num_threads(1, 3_000_000)?;
+
+        for i in 0..50 {
+            writer.add_document(doc!(field => "word ".repeat(i % 7 + 1)));
+        }
+        writer.commit()?;
+
+        let searcher = index.reader()?.searcher();
+        let query = TermQuery::new(
+            Term::from_field_text(field, "word"),
+            IndexRecordOption::WithFreqs,
+        );
+
+        let mut seen = Vec::new();
+        let mut after: Option<(Score, DocAddress)> = None;
+        loop {
+            let (result, _stats) = match after {
+                Some(after) => TwoPhaseTopDocs::new(10, after)
+                    .with_sample_size(5)
+                    .search(&searcher, &query)?,
+                None => TwoPhaseTopDocs::new(10, true)
+                    .with_sample_size(5)
+                    .search(&searcher, &query)?,
+            };
+
+            seen.extend(result.items.iter().copied());
+            if !result.has_next() {
+                break;
+            }
+            after = result.items.last().copied();
+        }
+
+        let everything =
+            searcher.search(&query, &TopCollector::<Score, Descending, _>::new(50, true))?;
+        assert_eq!(everything.items, seen);
+
+        Ok(())
+    }
+}
@@ -1,7 +1,9 @@
+use std::collections::BTreeSet;
+
 use tantivy::{
     self,
     query::{EmptyScorer, Explanation, Query, Scorer, Weight},
-    DocId, DocSet, Result, Score, Searcher, SegmentReader, TantivyError, TERMINATED,
+    DocId, DocSet, Result, Score, Searcher, SegmentReader, TantivyError, Term, TERMINATED,
 };
 
 /// A Maximum Disjunction query, as popularized by Lucene/Solr
@@ -53,6 +55,12 @@ impl Query for DisMaxQuery {
             self.tiebreaker,
         )))
     }
+
+    fn query_terms(&self, term_set: &mut BTreeSet<Term>) {
+        for disjunct in self.disjuncts.iter() {
+            disjunct.query_terms(term_set);
+        }
+    }
 }
 
 struct DisMaxWeight {
@@ -305,6 +313,26 @@ mod tests {
         );
     }
 
+    #[test]
+    fn query_terms_come_from_every_disjunct() {
+        let mut builder = SchemaBuilder::new();
+        let field = builder.add_text_field("field", TEXT);
+
+        let foo = Term::from_field_text(field, "foo");
+        let bar = Term::from_field_text(field, "bar");
+        let dismax = DisMaxQuery::new(
+            vec![
+                Box::new(TermQuery::new(foo.clone(), IndexRecordOption::Basic)),
+                Box::new(TermQuery::new(bar.clone(), IndexRecordOption::Basic)),
+            ],
+            0.0,
+        );
+
+        let mut terms = BTreeSet::new();
+        dismax.query_terms(&mut terms);
+        assert_eq!(vec![&bar, &foo], terms.iter().collect::<Vec<_>>());
+    }
+
     #[test]
     #[ignore = "TermQuery::explain bug triggers a debug_assert! crash. Fix at tantivy rev 730ccefffb"]
     fn explaination() -> Result<()> {
@@ -0,0 +1,355 @@
+//! Offline relevance evaluation from judgment files
+//!
+//! A judgments file lists, for each query, which documents are
+//! relevant and how much. Run every query through your search,
+//! feed the ranked ids to an `Evaluation` and get the mean NDCG@k,
+//! MRR and recall@k over all of them, so that ranking changes can be
+//! measured before they reach anyone.
+//!
+//! ```no_run
+//! # use tique::eval::{Evaluation, Judgments};
+//! # fn search(query: &str) -> Vec<String> { unimplemented!() }
+//! # fn example() -> std::io::Result<()> {
+//! let judgments = Judgments::load("judgments.tsv")?;
+//!
+//! let mut evaluation = Evaluation::new(10);
+//! for (query, grades) in judgments.iter() {
+//!     evaluation.add(&search(query), grades);
+//! }
+//!
+//! let report = evaluation.report();
+//! println!("ndcg@10={:.3} mrr={:.3}", report.ndcg, report.mrr);
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! The file format is one judgment per line: the query, the id of a
+//! document and its grade, separated by tabs. Grades are non-negative
+//! integers, zero meaning "not relevant"; documents without a
+//! judgment are taken as not relevant too. Empty lines and lines
+//! starting with `#` are skipped.
+//!
+//! ```text
+//! # query	id	grade
+//! pancakes	3b8a5c04	3
+//! pancakes	9f1c22e7	1
+//! vegan lasagna	51d0a9b3	2
+//! ```
+use std::{
+    collections::HashMap,
+    fs,
+    io::{self, ErrorKind},
+    path::Path,
+};
+
+/// How relevant each judged document (by id) is to a query
+pub type Grades = HashMap<String, u32>;
+
+/// Relevance judgments for a set of queries
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Judgments {
+    queries: Vec<(String, Grades)>,
+}
+
+impl Judgments {
+    /// Reads a judgments file. See the module docs for the format
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Self::parse(&fs::read_to_string(path)?)
+    }
+
+    /// Parses judgments in the format described in the module docs.
+    /// Queries show up in the order they are first mentioned
+    pub fn parse(input: &str) -> io::Result<Self> {
+        let mut queries: Vec<(String, Grades)> = Vec::new();
+        let mut positions = HashMap::new();
+
+        for (num, line) in input.lines().enumerate() {
+            if line.trim().is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let invalid = |message: &str| {
+                io::Error::new(
+                    ErrorKind::InvalidData,
+                    format!("line {}: {}", num + 1, message),
+                )
+            };
+
+            let mut parts = line.split('\t');
+            let (query, id, grade) = match (parts.next(), parts.next(), parts.next(), parts.next())
+            {
+                (Some(query), Some(id), Some(grade), None) => (query, id, grade),
+                _ => return Err(invalid("expected query, id and grade separated by tabs")),
+            };
+            let grade = grade
+                .trim()
+                .parse::<u32>()
+                .map_err(|_| invalid("grade must be a non-negative integer"))?;
+
+            let position = *positions.entry(query.to_owned()).or_insert_with(|| {
+                queries.push((query.to_owned(), Grades::new()));
+                queries.len() - 1
+            });
+            queries[position].1.insert(id.trim().to_owned(), grade);
+        }
+
+        Ok(Self { queries })
+    }
+
+    /// Every query along with its judgments
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &Grades)> {
+        self.queries
+            .iter()
+            .map(|(query, grades)| (query.as_str(), grades))
+    }
+
+    /// How many queries were judged
+    pub fn len(&self) -> usize {
+        self.queries.len()
+    }
+
+    /// Whether there's no query at all
+    pub fn is_empty(&self) -> bool {
+        self.queries.is_empty()
+    }
+}
+
+fn grade_of<S: AsRef<str>>(id: S, grades: &Grades) -> u32 {
+    grades.get(id.as_ref()).copied().unwrap_or(0)
+}
+
+fn gain(grade: u32, rank: usize) -> f64 {
+    (2f64.powi(grade as i32) - 1.0) / (rank as f64 + 2.0).log2()
+}
+
+/// Normalized discounted cumulative gain of the top `k` of `ranked`:
+/// 1.0 when they are the best possible ranking, 0.0 when none is
+/// relevant or nothing is
+pub fn ndcg<S: AsRef<str>>(ranked: &[S], grades: &Grades, k: usize) -> f64 {
+    let dcg: f64 = ranked
+        .iter()
+        .take(k)
+        .enumerate()
+        .map(|(rank, id)| gain(grade_of(id, grades), rank))
+        .sum();
+
+    let mut ideal = grades.values().copied().collect::<Vec<_>>();
+    ideal.sort_unstable_by(|a, b| b.cmp(a));
+    let idcg: f64 = ideal
+        .into_iter()
+        .take(k)
+        .enumerate()
+        .map(|(rank, grade)| gain(grade, rank))
+        .sum();
+
+    if idcg > 0.0 {
+        dcg / idcg
+    } else {
+        0.0
+    }
+}
+
+/// One over the rank of the first relevant document within the top
+/// `k` of `ranked`, 0.0 if there's none
+pub fn reciprocal_rank<S: AsRef<str>>(ranked: &[S], grades: &Grades, k: usize) -> f64 {
+    ranked
+        .iter()
+        .take(k)
+        .position(|id| grade_of(id, grades) > 0)
+        .map_or(0.0, |rank| 1.0 / (rank as f64 + 1.0))
+}
+
+/// The fraction of the relevant documents that are within the top `k`
+/// of `ranked`. 0.0 when nothing is relevant
+pub fn recall<S: AsRef<str>>(ranked: &[S], grades: &Grades, k: usize) -> f64 {
+    let num_relevant = grades.values().filter(|grade| **grade > 0).count();
+    if num_relevant == 0 {
+        return 0.0;
+    }
+
+    let found = ranked
+        .iter()
+        .take(k)
+        .filter(|id| grade_of(id, grades) > 0)
+        .count();
+    found as f64 / num_relevant as f64
+}
+
+/// How a single query did
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct QueryScores {
+    /// NDCG@k
+    pub ndcg: f64,
+    /// Reciprocal rank, looking at the top k only
+    pub reciprocal_rank: f64,
+    /// Recall@k
+    pub recall: f64,
+}
+
+/// The mean scores over every query of an `Evaluation`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Report {
+    /// How many of the top results were looked at
+    pub k: usize,
+    /// How many queries were evaluated
+    pub num_queries: usize,
+    /// Mean NDCG@k
+    pub ndcg: f64,
+    /// Mean reciprocal rank
+    pub mrr: f64,
+    /// Mean recall@k
+    pub recall: f64,
+}
+
+/// Accumulates the scores of many queries
+#[derive(Debug, Clone)]
+pub struct Evaluation {
+    k: usize,
+    num_queries: usize,
+    ndcg: f64,
+    reciprocal_rank: f64,
+    recall: f64,
+}
+
+impl Evaluation {
+    /// Creates an evaluation that looks at the top `k` results of
+    /// each query
+    ///
+    /// # Panics
+    ///
+    /// Panics if `k` is zero
+    pub fn new(k: usize) -> Self {
+        assert!(k > 0, "k must be greater than zero");
+        Self {
+            k,
+            num_queries: 0,
+            ndcg: 0.0,
+            reciprocal_rank: 0.0,
+            recall: 0.0,
+        }
+    }
+
+    /// Scores the results of a query, best first, against its
+    /// judgments
+    pub fn add<S: AsRef<str>>(&mut self, ranked: &[S], grades: &Grades) -> QueryScores {
+        let scores = QueryScores {
+            ndcg: ndcg(ranked, grades, self.k),
+            reciprocal_rank: reciprocal_rank(ranked, grades, self.k),
+            recall: recall(ranked, grades, self.k),
+        };
+
+        self.num_queries += 1;
+        self.ndcg += scores.ndcg;
+        self.reciprocal_rank += scores.reciprocal_rank;
+        self.recall += scores.recall;
+
+        scores
+    }
+
+    /// The mean scores of the queries added so far. All zeros if
+    /// there was none
+    pub fn report(&self) -> Report {
+        let mean = |total: f64| {
+            if self.num_queries > 0 {
+                total / self.num_queries as f64
+            } else {
+                0.0
+            }
+        };
+
+        Report {
+            k: self.k,
+            num_queries: self.num_queries,
+            ndcg: mean(self.ndcg),
+            mrr: mean(self.reciprocal_rank),
+            recall: mean(self.recall),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn grades(judged: &[(&str, u32)]) -> Grades {
+        judged
+            .iter()
+            .map(|(id, grade)| ((*id).to_owned(), *grade))
+            .collect()
+    }
+
+    fn assert_close(expected: f64, got: f64) {
+        assert!((expected - got).abs() < 1e-9, "{} != {}", expected, got);
+    }
+
+    #[test]
+    fn parses_judgments() -> io::Result<()> {
+        let judgments = Judgments::parse(
+            "# query\tid\tgrade\n\
+             pancakes\ta\t3\n\
+             \n\
+             lasagna\tb\t1\n\
+             pancakes\tc\t0\n",
+        )?;
+
+        assert_eq!(2, judgments.len());
+        let queries = judgments.iter().collect::<Vec<_>>();
+        assert_eq!(("pancakes", &grades(&[("a", 3), ("c", 0)])), queries[0]);
+        assert_eq!(("lasagna", &grades(&[("b", 1)])), queries[1]);
+
+        assert!(Judgments::parse("pancakes\ta").is_err());
+        assert!(Judgments::parse("pancakes\ta\tgood").is_err());
+        assert!(Judgments::parse("pancakes\ta\t1\textra").is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn perfect_rankings_score_one() {
+        let grades = grades(&[("a", 3), ("b", 2), ("c", 1)]);
+        let ranked = ["a", "b", "c", "d"];
+
+        assert_close(1.0, ndcg(&ranked, &grades, 10));
+        assert_close(1.0, reciprocal_rank(&ranked, &grades, 10));
+        assert_close(1.0, recall(&ranked, &grades, 10));
+    }
+
+    #[test]
+    fn scores_only_look_at_the_top_k() {
+        let grades = grades(&[("a", 1), ("b", 0)]);
+        let ranked = ["b", "x", "a"];
+
+        assert_close(0.0, ndcg(&ranked, &grades, 2));
+        assert_close(0.0, reciprocal_rank(&ranked, &grades, 2));
+        assert_close(0.0, recall(&ranked, &grades, 2));
+
+        assert_close(0.5, ndcg(&ranked, &grades, 3));
+        assert_close(1.0 / 3.0, reciprocal_rank(&ranked, &grades, 3));
+        assert_close(1.0, recall(&ranked, &grades, 3));
+    }
+
+    #[test]
+    fn swapped_grades_lower_ndcg() {
+        let grades = grades(&[("a", 3), ("b", 1)]);
+
+        // Gains are 2^grade - 1, discounted by log2(rank + 1)
+        let expected = (1.0 + 7.0 / 3f64.log2()) / (7.0 + 1.0 / 3f64.log2());
+        assert_close(expected, ndcg(&["b", "a"], &grades, 2));
+        assert!(expected < 1.0);
+    }
+
+    #[test]
+    fn evaluation_averages_queries() {
+        let mut evaluation = Evaluation::new(5);
+        assert_eq!(0, evaluation.report().num_queries);
+
+        evaluation.add(&["a"], &grades(&[("a", 1)]));
+        evaluation.add(&["x", "b"], &grades(&[("b", 1), ("c", 1)]));
+        evaluation.add(&Vec::<String>::new(), &grades(&[]));
+
+        let report = evaluation.report();
+        assert_eq!(3, report.num_queries);
+        assert_close((1.0 + 0.5 + 0.0) / 3.0, report.mrr);
+        assert_close((1.0 + 0.5 + 0.0) / 3.0, report.recall);
+    }
+}
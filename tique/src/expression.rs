@@ -0,0 +1,758 @@
+//! Score functions written as plain text
+//!
+//! A `ScoreExpression` is a tiny arithmetic language over the query
+//! score and numeric fast fields, meant to be taken as user (say: a
+//! search request) input so that tweaking relevance doesn't require
+//! changing any code:
+//!
+//! ```no_run
+//! # use tantivy::{collector::TopDocs, query::AllQuery, Searcher};
+//! # use tique::expression::ScoreExpression;
+//! # fn example(searcher: &Searcher) -> tantivy::Result<()> {
+//! let expression = ScoreExpression::parse(
+//!     "_score * log1p(popularity) + 0.1 * is_featured",
+//!     &searcher.schema(),
+//! )?;
+//!
+//! let top = searcher.search(&AllQuery, &TopDocs::with_limit(10).tweak_score(expression))?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! The language supports:
+//!
+//! * Numbers (`0.1`, `42`) and the operators `+`, `-`, `*`, `/` and
+//!   `^` (power), with the usual precedence and parentheses
+//! * `_score`: the score given by the query
+//! * The name of any single-valued u64, i64 or f64 fast field
+//! * Named constants (say: figures about the whole corpus), when
+//!   parsed via `ScoreExpression::parse_with_constants`
+//! * The functions `abs`, `exp`, `ln`, `log1p` and `sqrt`, taking one
+//!   argument, and `min` and `max`, taking two
+//!
+//! Expressions are limited in size and nesting depth, so evaluating
+//! one is always cheap. Operations without a meaningful result, like
+//! `ln(-1)`, make the whole expression evaluate to zero.
+use tantivy::{
+    collector::{ScoreSegmentTweaker, ScoreTweaker},
+    fastfield::FastFieldReader,
+    schema::{Cardinality, Field, FieldType, Schema},
+    DocId, Result, Score, SegmentReader, TantivyError,
+};
+
+/// The maximum number of operations, functions, numbers and
+/// variables an expression may have
+pub const MAX_NODES: usize = 64;
+
+/// How deep parentheses, function calls and negations may be nested
+pub const MAX_DEPTH: usize = 16;
+
+const SCORE_VARIABLE: &str = "_score";
+
+/// A parsed score function, ready to be used as a `ScoreTweaker`
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScoreExpression {
+    root: Node,
+}
+
+impl ScoreExpression {
+    /// Parses `input`, resolving the variables to the fast fields with
+    /// the same name in `schema`.
+    ///
+    /// Fails with `TantivyError::InvalidArgument` on syntax errors,
+    /// unknown or unsuitable fields and expressions over the size
+    /// limits.
+    pub fn parse(input: &str, schema: &Schema) -> Result<Self> {
+        Self::parse_with(input, schema, |name| schema.get_field(name))
+    }
+
+    /// Like `parse`, but with the variable names resolved by `resolve`
+    /// instead of being taken as field names. Useful for exposing
+    /// the fields under friendlier names, or just a few of them
+    pub fn parse_with<F>(input: &str, schema: &Schema, resolve: F) -> Result<Self>
+    where
+        F: Fn(&str) -> Option<Field>,
+    {
+        Self::parse_with_constants(input, schema, resolve, |_name| None)
+    }
+
+    /// Like `parse_with`, but with the variables `constant` knows
+    /// about taken as numbers instead of fields. Constants are fixed
+    /// when parsing, so they're as cheap as numbers written out
+    pub fn parse_with_constants<F, G>(
+        input: &str,
+        schema: &Schema,
+        resolve: F,
+        constant: G,
+    ) -> Result<Self>
+    where
+        F: Fn(&str) -> Option<Field>,
+        G: Fn(&str) -> Option<f64>,
+    {
+        let tokens = tokenize(input)?;
+        let mut parser = Parser {
+            tokens,
+            pos: 0,
+            num_nodes: 0,
+            schema,
+            resolve: &resolve,
+            constant: &constant,
+        };
+
+        let root = parser.parse_sum(0)?;
+        if let Some((offset, _token)) = parser.tokens.get(parser.pos) {
+            return Err(invalid(format!("Unexpected input at {}", offset)));
+        }
+
+        Ok(Self { root })
+    }
+
+    /// Whether the expression uses the query score. If not, it may be
+    /// used to rank documents without computing scores at all
+    pub fn uses_score(&self) -> bool {
+        self.root.uses_score()
+    }
+}
+
+impl ScoreTweaker<f64> for ScoreExpression {
+    type Child = SegmentScoreExpression;
+
+    fn segment_tweaker(&self, reader: &SegmentReader) -> Result<Self::Child> {
+        Ok(SegmentScoreExpression {
+            root: self.root.for_segment(reader)?,
+        })
+    }
+}
+
+/// A `ScoreExpression` bound to the fast fields of a segment
+pub struct SegmentScoreExpression {
+    root: SegmentNode,
+}
+
+impl ScoreSegmentTweaker<f64> for SegmentScoreExpression {
+    fn score(&mut self, doc: DocId, score: Score) -> f64 {
+        let value = self.root.eval(doc, f64::from(score));
+        if value.is_nan() {
+            0.0
+        } else {
+            value
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Func {
+    Neg,
+    Abs,
+    Exp,
+    Ln,
+    Log1p,
+    Sqrt,
+}
+
+impl Func {
+    fn apply(self, value: f64) -> f64 {
+        match self {
+            Func::Neg => -value,
+            Func::Abs => value.abs(),
+            Func::Exp => value.exp(),
+            Func::Ln => value.ln(),
+            Func::Log1p => value.ln_1p(),
+            Func::Sqrt => value.sqrt(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Op {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Pow,
+    Min,
+    Max,
+}
+
+impl Op {
+    fn apply(self, left: f64, right: f64) -> f64 {
+        match self {
+            Op::Add => left + right,
+            Op::Sub => left - right,
+            Op::Mul => left * right,
+            Op::Div => left / right,
+            Op::Pow => left.powf(right),
+            Op::Min => left.min(right),
+            Op::Max => left.max(right),
+        }
+    }
+}
+
+fn function(name: &str) -> Option<(usize, Option<Func>, Option<Op>)> {
+    match name {
+        "abs" => Some((1, Some(Func::Abs), None)),
+        "exp" => Some((1, Some(Func::Exp), None)),
+        "ln" => Some((1, Some(Func::Ln), None)),
+        "log1p" => Some((1, Some(Func::Log1p), None)),
+        "sqrt" => Some((1, Some(Func::Sqrt), None)),
+        "min" => Some((2, None, Some(Op::Min))),
+        "max" => Some((2, None, Some(Op::Max))),
+        _ => None,
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum FieldKind {
+    U64,
+    I64,
+    F64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Node {
+    Const(f64),
+    Score,
+    Field(Field, FieldKind),
+    Unary(Func, Box<Node>),
+    Binary(Op, Box<Node>, Box<Node>),
+}
+
+impl Node {
+    fn uses_score(&self) -> bool {
+        match self {
+            Node::Score => true,
+            Node::Const(_) | Node::Field(..) => false,
+            Node::Unary(_, node) => node.uses_score(),
+            Node::Binary(_, left, right) => left.uses_score() || right.uses_score(),
+        }
+    }
+
+    fn for_segment(&self, reader: &SegmentReader) -> Result<SegmentNode> {
+        let missing =
+            |field: &Field| TantivyError::SchemaError(format!("{:?} is not a fast field", field));
+
+        Ok(match self {
+            Node::Const(value) => SegmentNode::Const(*value),
+            Node::Score => SegmentNode::Score,
+            Node::Field(field, FieldKind::U64) => SegmentNode::U64(
+                reader
+                    .fast_fields()
+                    .u64(*field)
+                    .ok_or_else(|| missing(field))?,
+            ),
+            Node::Field(field, FieldKind::I64) => SegmentNode::I64(
+                reader
+                    .fast_fields()
+                    .i64(*field)
+                    .ok_or_else(|| missing(field))?,
+            ),
+            Node::Field(field, FieldKind::F64) => SegmentNode::F64(
+                reader
+                    .fast_fields()
+                    .f64(*field)
+                    .ok_or_else(|| missing(field))?,
+            ),
+            Node::Unary(func, node) => {
+                SegmentNode::Unary(*func, Box::new(node.for_segment(reader)?))
+            }
+            Node::Binary(op, left, right) => SegmentNode::Binary(
+                *op,
+                Box::new(left.for_segment(reader)?),
+                Box::new(right.for_segment(reader)?),
+            ),
+        })
+    }
+}
+
+enum SegmentNode {
+    Const(f64),
+    Score,
+    U64(FastFieldReader<u64>),
+    I64(FastFieldReader<i64>),
+    F64(FastFieldReader<f64>),
+    Unary(Func, Box<SegmentNode>),
+    Binary(Op, Box<SegmentNode>, Box<SegmentNode>),
+}
+
+impl SegmentNode {
+    fn eval(&self, doc: DocId, score: f64) -> f64 {
+        match self {
+            SegmentNode::Const(value) => *value,
+            SegmentNode::Score => score,
+            SegmentNode::U64(reader) => reader.get(doc) as f64,
+            SegmentNode::I64(reader) => reader.get(doc) as f64,
+            SegmentNode::F64(reader) => reader.get(doc),
+            SegmentNode::Unary(func, node) => func.apply(node.eval(doc, score)),
+            SegmentNode::Binary(op, left, right) => {
+                op.apply(left.eval(doc, score), right.eval(doc, score))
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(f64),
+    Ident(String),
+    Symbol(char),
+}
+
+fn invalid(message: String) -> TantivyError {
+    TantivyError::InvalidArgument(message)
+}
+
+// Every token comes with its offset in the input, for error messages
+fn tokenize(input: &str) -> Result<Vec<(usize, Token)>> {
+    let mut tokens = Vec::new();
+    let mut chars = input.char_indices().peekable();
+
+    while let Some(&(offset, c)) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+        } else if c.is_ascii_digit() || c == '.' {
+            let mut end = offset;
+            while let Some(&(idx, c)) = chars.peek() {
+                if c.is_ascii_digit() || c == '.' {
+                    end = idx + c.len_utf8();
+                    chars.next();
+                } else {
+                    break;
+                }
+            }
+
+            let number = input[offset..end]
+                .parse()
+                .map_err(|_| invalid(format!("Invalid number at {}", offset)))?;
+            tokens.push((offset, Token::Number(number)));
+        } else if c.is_ascii_alphabetic() || c == '_' {
+            let mut end = offset;
+            while let Some(&(idx, c)) = chars.peek() {
+                if c.is_ascii_alphanumeric() || c == '_' {
+                    end = idx + c.len_utf8();
+                    chars.next();
+                } else {
+                    break;
+                }
+            }
+            tokens.push((offset, Token::Ident(input[offset..end].to_owned())));
+        } else if "+-*/^(),".contains(c) {
+            tokens.push((offset, Token::Symbol(c)));
+            chars.next();
+        } else {
+            return Err(invalid(format!("Unexpected '{}' at {}", c, offset)));
+        }
+    }
+
+    Ok(tokens)
+}
+
+struct Parser<'a> {
+    tokens: Vec<(usize, Token)>,
+    pos: usize,
+    num_nodes: usize,
+    schema: &'a Schema,
+    resolve: &'a dyn Fn(&str) -> Option<Field>,
+    constant: &'a dyn Fn(&str) -> Option<f64>,
+}
+
+impl<'a> Parser<'a> {
+    fn peek_symbol(&self) -> Option<char> {
+        match self.tokens.get(self.pos) {
+            Some((_, Token::Symbol(symbol))) => Some(*symbol),
+            _ => None,
+        }
+    }
+
+    // Where the next token starts, for error messages
+    fn offset(&self) -> usize {
+        self.tokens
+            .get(self.pos)
+            .or_else(|| self.tokens.last())
+            .map_or(0, |(offset, _)| *offset)
+    }
+
+    fn expect_symbol(&mut self, symbol: char) -> Result<()> {
+        if self.peek_symbol() == Some(symbol) {
+            self.pos += 1;
+            Ok(())
+        } else {
+            Err(invalid(format!(
+                "Expected '{}' at {}",
+                symbol,
+                self.offset()
+            )))
+        }
+    }
+
+    fn node(&mut self, node: Node) -> Result<Node> {
+        self.num_nodes += 1;
+        if self.num_nodes > MAX_NODES {
+            Err(invalid(format!(
+                "Expression is too long: more than {} items",
+                MAX_NODES
+            )))
+        } else {
+            Ok(node)
+        }
+    }
+
+    fn check_depth(&self, depth: usize) -> Result<()> {
+        if depth > MAX_DEPTH {
+            Err(invalid(format!(
+                "Expression is nested too deeply at {}",
+                self.offset()
+            )))
+        } else {
+            Ok(())
+        }
+    }
+
+    // sum := product (('+' | '-') product)*
+    fn parse_sum(&mut self, depth: usize) -> Result<Node> {
+        self.check_depth(depth)?;
+
+        let mut node = self.parse_product(depth)?;
+        while let Some(symbol) = self.peek_symbol().filter(|&s| s == '+' || s == '-') {
+            self.pos += 1;
+            let op = if symbol == '+' { Op::Add } else { Op::Sub };
+            let right = self.parse_product(depth)?;
+            node = self.node(Node::Binary(op, Box::new(node), Box::new(right)))?;
+        }
+
+        Ok(node)
+    }
+
+    // product := unary (('*' | '/') unary)*
+    fn parse_product(&mut self, depth: usize) -> Result<Node> {
+        let mut node = self.parse_unary(depth)?;
+        while let Some(symbol) = self.peek_symbol().filter(|&s| s == '*' || s == '/') {
+            self.pos += 1;
+            let op = if symbol == '*' { Op::Mul } else { Op::Div };
+            let right = self.parse_unary(depth)?;
+            node = self.node(Node::Binary(op, Box::new(node), Box::new(right)))?;
+        }
+
+        Ok(node)
+    }
+
+    // unary := '-' unary | power
+    fn parse_unary(&mut self, depth: usize) -> Result<Node> {
+        if self.peek_symbol() == Some('-') {
+            self.pos += 1;
+            self.check_depth(depth + 1)?;
+            let node = self.parse_unary(depth + 1)?;
+            self.node(Node::Unary(Func::Neg, Box::new(node)))
+        } else {
+            self.parse_power(depth)
+        }
+    }
+
+    // power := atom ('^' unary)?
+    fn parse_power(&mut self, depth: usize) -> Result<Node> {
+        let base = self.parse_atom(depth)?;
+        if self.peek_symbol() == Some('^') {
+            self.pos += 1;
+            self.check_depth(depth + 1)?;
+            let exponent = self.parse_unary(depth + 1)?;
+            self.node(Node::Binary(Op::Pow, Box::new(base), Box::new(exponent)))
+        } else {
+            Ok(base)
+        }
+    }
+
+    // atom := number | variable | function '(' sum (',' sum)* ')' | '(' sum ')'
+    fn parse_atom(&mut self, depth: usize) -> Result<Node> {
+        let offset = self.offset();
+        let token = match self.tokens.get(self.pos) {
+            Some((_, token)) => token.clone(),
+            None => return Err(invalid(format!("Unexpected end of input at {}", offset))),
+        };
+        self.pos += 1;
+
+        match token {
+            Token::Number(value) => self.node(Node::Const(value)),
+            Token::Symbol('(') => {
+                let node = self.parse_sum(depth + 1)?;
+                self.expect_symbol(')')?;
+                Ok(node)
+            }
+            Token::Ident(name) if self.peek_symbol() == Some('(') => {
+                self.pos += 1;
+                self.parse_call(&name, offset, depth)
+            }
+            Token::Ident(name) => {
+                let node = self.variable(&name, offset)?;
+                self.node(node)
+            }
+            Token::Symbol(symbol) => Err(invalid(format!("Unexpected '{}' at {}", symbol, offset))),
+        }
+    }
+
+    fn parse_call(&mut self, name: &str, offset: usize, depth: usize) -> Result<Node> {
+        let (arity, func, op) = function(name)
+            .ok_or_else(|| invalid(format!("Unknown function '{}' at {}", name, offset)))?;
+
+        let mut args = Vec::with_capacity(arity);
+        loop {
+            args.push(self.parse_sum(depth + 1)?);
+            if self.peek_symbol() == Some(',') {
+                self.pos += 1;
+            } else {
+                break;
+            }
+        }
+        self.expect_symbol(')')?;
+
+        if args.len() != arity {
+            return Err(invalid(format!(
+                "Function '{}' at {} takes {} argument(s), got {}",
+                name,
+                offset,
+                arity,
+                args.len()
+            )));
+        }
+
+        let mut args = args.into_iter();
+        let first = Box::new(args.next().expect("arity is at least one"));
+        let node = match (func, op) {
+            (Some(func), _) => Node::Unary(func, first),
+            (None, Some(op)) => {
+                Node::Binary(op, first, Box::new(args.next().expect("arity is two")))
+            }
+            (None, None) => unreachable!("every function is either unary or binary"),
+        };
+
+        self.node(node)
+    }
+
+    fn variable(&self, name: &str, offset: usize) -> Result<Node> {
+        if name == SCORE_VARIABLE {
+            return Ok(Node::Score);
+        }
+
+        if let Some(value) = (self.constant)(name) {
+            return Ok(Node::Const(value));
+        }
+
+        let field = (self.resolve)(name)
+            .ok_or_else(|| invalid(format!("Unknown field '{}' at {}", name, offset)))?;
+
+        let (kind, options) = match self.schema.get_field_entry(field).field_type() {
+            FieldType::U64(options) => (FieldKind::U64, options),
+            FieldType::I64(options) => (FieldKind::I64, options),
+            FieldType::F64(options) => (FieldKind::F64, options),
+            _ => {
+                return Err(invalid(format!(
+                    "Field '{}' at {} is not numeric",
+                    name, offset
+                )))
+            }
+        };
+
+        if options.get_fastfield_cardinality() == Some(Cardinality::SingleValue) {
+            Ok(Node::Field(field, kind))
+        } else {
+            Err(invalid(format!(
+                "Field '{}' at {} is not a single-valued fast field",
+                name, offset
+            )))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use tantivy::{
+        collector::TopDocs,
+        doc,
+        query::AllQuery,
+        schema::{SchemaBuilder, FAST, INDEXED},
+        Index,
+    };
+
+    fn schema() -> (Schema, Field, Field) {
+        let mut builder = SchemaBuilder::new();
+        let popularity = builder.add_u64_field("popularity", FAST);
+        let rating = builder.add_f64_field("rating", FAST);
+        builder.add_u64_field("not_fast", INDEXED);
+        builder.add_text_field("body", tantivy::schema::TEXT);
+        (builder.build(), popularity, rating)
+    }
+
+    fn parse(input: &str) -> Result<ScoreExpression> {
+        ScoreExpression::parse(input, &schema().0)
+    }
+
+    fn constant(input: &str) -> f64 {
+        let expression = parse(input).unwrap();
+        assert!(!expression.uses_score());
+
+        // Constant expressions don't need a segment to be evaluated
+        fn fold(node: &Node) -> f64 {
+            match node {
+                Node::Const(value) => *value,
+                Node::Unary(func, node) => func.apply(fold(node)),
+                Node::Binary(op, left, right) => op.apply(fold(left), fold(right)),
+                _ => panic!("Not a constant: {:?}", node),
+            }
+        }
+
+        fold(&expression.root)
+    }
+
+    #[test]
+    #[allow(clippy::float_cmp)]
+    fn precedence_and_associativity() {
+        assert_eq!(7.0, constant("1 + 2 * 3"));
+        assert_eq!(9.0, constant("(1 + 2) * 3"));
+        assert_eq!(2.0, constant("8 / 2 / 2"));
+        assert_eq!(-4.0, constant("-2 ^ 2 * 1"));
+        assert_eq!(0.25, constant("2 ^ -2"));
+        assert_eq!(5.0, constant("1 - -4"));
+        assert_eq!(3.0, constant("max(1, min(3, 4))"));
+        assert_eq!(2.0, constant("sqrt(abs(-4))"));
+        assert_eq!(0.0, constant("log1p(0) + ln(1)"));
+    }
+
+    #[test]
+    fn variables_must_be_numeric_fast_fields() {
+        let (schema, popularity, rating) = schema();
+        let expression =
+            ScoreExpression::parse("_score * log1p(popularity) + rating", &schema).unwrap();
+        assert!(expression.uses_score());
+        assert_eq!(
+            Node::Binary(
+                Op::Add,
+                Box::new(Node::Binary(
+                    Op::Mul,
+                    Box::new(Node::Score),
+                    Box::new(Node::Unary(
+                        Func::Log1p,
+                        Box::new(Node::Field(popularity, FieldKind::U64))
+                    ))
+                )),
+                Box::new(Node::Field(rating, FieldKind::F64))
+            ),
+            expression.root
+        );
+
+        assert!(parse("unknown").is_err());
+        assert!(parse("not_fast").is_err());
+        assert!(parse("body").is_err());
+
+        let renamed = |name: &str| {
+            if name == "pop" {
+                Some(popularity)
+            } else {
+                None
+            }
+        };
+        assert_eq!(
+            Node::Field(popularity, FieldKind::U64),
+            ScoreExpression::parse_with("pop", &schema, renamed)
+                .unwrap()
+                .root
+        );
+        assert!(ScoreExpression::parse_with("popularity", &schema, renamed).is_err());
+    }
+
+    #[test]
+    fn constants_are_resolved_when_parsing() {
+        let (schema, popularity, _rating) = schema();
+        let constant = |name: &str| if name == "median" { Some(42.0) } else { None };
+        let parsed = |input| {
+            ScoreExpression::parse_with_constants(
+                input,
+                &schema,
+                |name| schema.get_field(name),
+                constant,
+            )
+        };
+
+        assert_eq!(
+            Node::Binary(
+                Op::Div,
+                Box::new(Node::Field(popularity, FieldKind::U64)),
+                Box::new(Node::Const(42.0))
+            ),
+            parsed("popularity / median").unwrap().root
+        );
+        assert!(!parsed("median").unwrap().uses_score());
+        assert!(parsed("mean").is_err());
+    }
+
+    #[test]
+    fn rejects_invalid_input() {
+        for input in &[
+            "",
+            "1 +",
+            "(1",
+            "1)",
+            "1 2",
+            "1..2",
+            "popularity $ 2",
+            "nope(1)",
+            "min(1)",
+            "log1p(1, 2)",
+            "log1p",
+            "*",
+        ] {
+            assert!(
+                parse(input).is_err(),
+                "input {:?} should be rejected",
+                input
+            );
+        }
+    }
+
+    #[test]
+    fn complexity_is_bounded() {
+        let long = vec!["1"; MAX_NODES + 1].join(" + ");
+        assert!(parse(&long).is_err());
+        assert!(parse(&vec!["1"; MAX_NODES / 2].join(" + ")).is_ok());
+
+        let deep = "(".repeat(MAX_DEPTH + 1) + "1" + &")".repeat(MAX_DEPTH + 1);
+        assert!(parse(&deep).is_err());
+        assert!(parse(&"-".repeat(MAX_DEPTH + 1)).is_err());
+
+        // Way past the limits, but must not overflow the stack
+        assert!(parse(&"(".repeat(100_000)).is_err());
+        assert!(parse(&"-".repeat(100_000)).is_err());
+    }
+
+    #[test]
+    fn integration() -> Result<()> {
+        let (schema, popularity, rating) = schema();
+        let index = Index::create_in_ram(schema.clone());
+        let mut writer = index.writer_with_num_threads(1, 3_000_000)?;
+
+        for (pop, rat) in &[(10u64, 1.0f64), (0, 5.0), (3, 3.0)] {
+            writer.add_document(doc!(popularity => *pop, rating => *rat));
+        }
+        writer.commit()?;
+
+        let searcher = index.reader()?.searcher();
+        let top_by = |input: &str| -> Result<Vec<f64>> {
+            let expression = ScoreExpression::parse(input, &schema)?;
+            Ok(searcher
+                .search(&AllQuery, &TopDocs::with_limit(3).tweak_score(expression))?
+                .into_iter()
+                .map(|(score, _addr)| score)
+                .collect())
+        };
+
+        assert_eq!(vec![10.0, 3.0, 0.0], top_by("popularity")?);
+        assert_eq!(vec![-1.0, -3.0, -5.0], top_by("-rating")?);
+        assert_eq!(
+            vec![11.0, 6.0, 5.0],
+            top_by("_score * (popularity + rating) - 1 + 1")?
+        );
+        // NaN becomes zero
+        assert_eq!(vec![0.0, 0.0, 0.0], top_by("ln(-1 - rating)")?);
+
+        Ok(())
+    }
+}
@@ -8,6 +8,38 @@
 //! Here's a brief overview of the functionality we provide. Check the
 //! module docs for more details and examples.
 //!
+//! ## budget
+//!
+//! Keep expensive queries in check: wrap any collector to stop
+//! collecting after a timeout or a number of visited documents,
+//! getting what was found so far.
+//!
+//! ```no_run
+//! # use std::time::Duration;
+//! # use tantivy::{collector::Count, query::AllQuery, Searcher};
+//! # use tique::budget::{Budget, BudgetCollector};
+//! # fn example(searcher: &Searcher) -> tantivy::Result<()> {
+//! let budget = Budget::default().with_timeout(Duration::from_millis(50));
+//! let (count, exhausted) = searcher.search(&AllQuery, &BudgetCollector::new(Count, budget))?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! ## buckets
+//!
+//! Group the matching documents by a key (say: a category id), keeping
+//! the top documents of each of the largest groups. Or nest any other
+//! collector within each group.
+//!
+//! ```no_run
+//! # use tantivy::{query::AllQuery, schema::Field, Searcher};
+//! # use tique::buckets::TopHitsPerBucket;
+//! # fn example(searcher: &Searcher, category: Field) -> tantivy::Result<()> {
+//! let buckets = searcher.search(&AllQuery, &TopHitsPerBucket::u64_field(category, 10, 3))?;
+//! # Ok(())
+//! # }
+//! ```
+//!
 //! ## conditional_collector
 //!
 //! Collectors with built-in support for changing the ordering and
@@ -22,6 +54,151 @@
 //!     TopCollector::<f64, Ascending, _>::new(10, true).top_fast_field(f64_field);
 //! ```
 //!
+//! ## eval
+//!
+//! Measure ranking changes offline: score the results of judged
+//! queries by NDCG@k, MRR and recall@k.
+//!
+//! ```no_run
+//! # use tique::eval::{Evaluation, Judgments};
+//! # fn search(query: &str) -> Vec<String> { unimplemented!() }
+//! # fn example() -> std::io::Result<()> {
+//! let judgments = Judgments::load("judgments.tsv")?;
+//! let mut evaluation = Evaluation::new(10);
+//! for (query, grades) in judgments.iter() {
+//!     evaluation.add(&search(query), grades);
+//! }
+//! let ndcg = evaluation.report().ndcg;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! ## expression
+//!
+//! Score functions over the query score and fast fields, parsed from
+//! plain text at runtime so that relevance tweaks don't require code
+//! changes.
+//!
+//! ```no_run
+//! # use tantivy::{collector::TopDocs, query::AllQuery, Searcher};
+//! # use tique::expression::ScoreExpression;
+//! # fn example(searcher: &Searcher) -> tantivy::Result<()> {
+//! let expression = ScoreExpression::parse("_score * log1p(popularity)", &searcher.schema())?;
+//! let top = searcher.search(&AllQuery, &TopDocs::with_limit(10).tweak_score(expression))?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! ## metrics
+//!
+//! Count, sum, min, max and mean of numeric values (say: fast fields)
+//! of the documents matching a query. Or the (approximate) number of
+//! distinct values they have.
+//!
+//! ```no_run
+//! # use tantivy::{query::AllQuery, schema::Field, Searcher};
+//! # use tique::metrics::StatsCollector;
+//! # fn example(searcher: &Searcher, price: Field) -> tantivy::Result<()> {
+//! let stats = searcher.search(&AllQuery, &StatsCollector::f64_field(price))?;
+//! let average_price = stats[0].mean();
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! ## partial
+//!
+//! Keep searching when a segment is broken: wrap any collector to
+//! skip the segments it fails on, getting the failures alongside
+//! the result from the healthy ones.
+//!
+//! ```no_run
+//! # use tantivy::{collector::Count, query::AllQuery, Searcher};
+//! # use tique::partial::PartialCollector;
+//! # fn example(searcher: &Searcher) -> tantivy::Result<()> {
+//! let (count, failures) = searcher.search(&AllQuery, &PartialCollector::new(Count))?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! ## percentiles
+//!
+//! Approximate percentiles (say: the median of a fast field) of the
+//! documents matching a query via a mergeable t-digest.
+//!
+//! ```no_run
+//! # use tantivy::{query::AllQuery, schema::Field, Searcher};
+//! # use tique::percentiles::PercentileCollector;
+//! # fn example(searcher: &Searcher, price: Field) -> tantivy::Result<()> {
+//! let digest = searcher.search(&AllQuery, &PercentileCollector::f64_field(price))?;
+//! let p90 = digest.quantile(0.9);
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! ## rescore
+//!
+//! Rerank the best documents of a search with a score of your own,
+//! say: blending relevance with popularity, in a single pass.
+//!
+//! ```no_run
+//! # use tantivy::{query::AllQuery, schema::Field, DocId, Score, Searcher, SegmentReader, TantivyError};
+//! # use tique::rescore::RescoringCollector;
+//! # fn example(searcher: &Searcher, popularity: Field) -> tantivy::Result<()> {
+//! let collector = RescoringCollector::new(100, 10, move |reader: &SegmentReader| {
+//!     let popularity_reader = reader.fast_fields().u64(popularity).unwrap();
+//!     Ok::<_, TantivyError>(move |doc: DocId, score: Score| score + popularity_reader.get(doc) as f32)
+//! });
+//! let top = searcher.search(&AllQuery, &collector)?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! ## sampling
+//!
+//! A uniform random sample of the documents matching a query, for
+//! when the top ones aren't what you're after.
+//!
+//! ```no_run
+//! # use tantivy::{query::AllQuery, Searcher};
+//! # use tique::sampling::SampleCollector;
+//! # fn example(searcher: &Searcher, seed: u64) -> tantivy::Result<()> {
+//! let sample = searcher.search(&AllQuery, &SampleCollector::new(10, seed))?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! ## search
+//!
+//! Search multiple indices at once, merging the results with score
+//! normalization.
+//!
+//! ```no_run
+//! # use tantivy::{query::AllQuery, Index};
+//! # use tique::search::{MultiIndex, Normalization};
+//! # fn example(first: &Index, second: &Index) -> tantivy::Result<()> {
+//! let mut multi = MultiIndex::new(Normalization::Max);
+//! multi.add_source("first", first.reader()?);
+//! multi.add_source("second", second.reader()?);
+//!
+//! let result = multi.search(&AllQuery, 10)?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! ## timing
+//!
+//! Find out where the time of a search goes: wrap any collector to
+//! get how long each segment and the final merge took.
+//!
+//! ```no_run
+//! # use tantivy::{collector::Count, query::AllQuery, Searcher};
+//! # use tique::timing::TimedCollector;
+//! # fn example(searcher: &Searcher) -> tantivy::Result<()> {
+//! let (count, timings) = searcher.search(&AllQuery, &TimedCollector::new(Count))?;
+//! # Ok(())
+//! # }
+//! ```
+//!
 //! ## topterms
 //!
 //! Uses your index to find keywords and similar items to your documents
@@ -40,13 +217,29 @@
 //! let similarity_query = keywords.into_boosted_query(1.0);
 //! # Ok::<(), tantivy::TantivyError>(())
 //!```
+pub mod buckets;
+pub mod budget;
 pub mod conditional_collector;
+pub mod eval;
+pub mod expression;
+pub mod metrics;
+pub mod partial;
+pub mod percentiles;
+pub mod rescore;
+pub mod sampling;
+pub mod search;
+pub mod timing;
 pub mod topterms;
 
 #[cfg(feature = "queryparser")]
 mod queryparser;
 #[cfg(feature = "queryparser")]
-pub use queryparser::QueryParser;
+pub use queryparser::{
+    Ast, AstItem, Fuzziness, FuzzyPrefixQuery, MinimumShouldMatch, ParserField, QueryParser,
+    SynonymMap, SynonymProvider, SyntaxIssue,
+};
+#[cfg(feature = "querydsl")]
+pub use queryparser::{RangeClause, RangeValue, StructuredQuery, TextClause};
 
 mod dismax;
 pub use dismax::DisMaxQuery;
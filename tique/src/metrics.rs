@@ -0,0 +1,533 @@
+//! Simple statistics of numeric values among the matching docs
+//!
+//! `StatsCollector` computes the count, sum, min, max and mean of
+//! one or more values (usually fast fields) in a single pass, without
+//! keeping any documents around.
+//!
+//! `CardinalityCollector` estimates how many distinct values (say:
+//! category ids) the matching documents have, using a fixed amount
+//! of memory no matter how many documents match.
+//!
+//! ```no_run
+//! # use tantivy::{query::AllQuery, schema::Field, Searcher, Result};
+//! # use tique::{metrics::StatsCollector, percentiles::FastFieldValue};
+//! # fn example(searcher: &Searcher, calories: Field, rating: Field) -> Result<()> {
+//! let collector = StatsCollector::new(vec![
+//!     FastFieldValue::U64(calories),
+//!     FastFieldValue::F64(rating),
+//! ]);
+//!
+//! let stats = searcher.search(&AllQuery, &collector)?;
+//! let average_calories = stats[0].mean();
+//! let best_rating = stats[1].max;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! ```no_run
+//! # use tantivy::{query::AllQuery, schema::Field, Searcher, Result};
+//! # use tique::metrics::CardinalityCollector;
+//! # fn example(searcher: &Searcher, category: Field) -> Result<()> {
+//! let hll = searcher.search(&AllQuery, &CardinalityCollector::u64_field(category))?;
+//! println!("Results span ~{} categories", hll.estimate());
+//! # Ok(())
+//! # }
+//! ```
+use tantivy::{
+    collector::{Collector, SegmentCollector},
+    schema::Field,
+    DocId, Result, Score, SegmentLocalId, SegmentReader,
+};
+
+use crate::{
+    buckets::{BucketForDoc, BucketForSegment, FastFieldBucket},
+    percentiles::{FastFieldValue, ValueForDoc, ValueForSegment},
+};
+
+/// Statistics of a set of values
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Stats {
+    /// How many values were seen
+    pub count: u64,
+    /// The sum of every value
+    pub sum: f64,
+    /// The smallest value, if any
+    pub min: Option<f64>,
+    /// The largest value, if any
+    pub max: Option<f64>,
+}
+
+impl Stats {
+    /// Accounts for a new value. NaNs are ignored
+    pub fn add(&mut self, value: f64) {
+        if value.is_nan() {
+            return;
+        }
+
+        self.count += 1;
+        self.sum += value;
+        self.min = Some(self.min.map_or(value, |min| min.min(value)));
+        self.max = Some(self.max.map_or(value, |max| max.max(value)));
+    }
+
+    /// Accounts for every value seen by `other`
+    pub fn merge(&mut self, other: &Stats) {
+        self.count += other.count;
+        self.sum += other.sum;
+        self.min = match (self.min, other.min) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
+        };
+        self.max = match (self.max, other.max) {
+            (Some(a), Some(b)) => Some(a.max(b)),
+            (a, b) => a.or(b),
+        };
+    }
+
+    /// The average of the values, if any
+    pub fn mean(&self) -> Option<f64> {
+        if self.count == 0 {
+            None
+        } else {
+            Some(self.sum / self.count as f64)
+        }
+    }
+}
+
+/// Computes the `Stats` of each of the given values for the
+/// matching documents. The result is in the same order as the
+/// values
+pub struct StatsCollector<V> {
+    values: Vec<V>,
+}
+
+impl StatsCollector<FastFieldValue> {
+    /// Computes the stats of a single `u64` fast field
+    pub fn u64_field(field: Field) -> Self {
+        Self::new(vec![FastFieldValue::U64(field)])
+    }
+
+    /// Computes the stats of a single `i64` fast field
+    pub fn i64_field(field: Field) -> Self {
+        Self::new(vec![FastFieldValue::I64(field)])
+    }
+
+    /// Computes the stats of a single `f64` fast field
+    pub fn f64_field(field: Field) -> Self {
+        Self::new(vec![FastFieldValue::F64(field)])
+    }
+}
+
+impl<V: ValueForSegment> StatsCollector<V> {
+    /// Creates a collector for the given values. Documents without a
+    /// value are not accounted for
+    pub fn new(values: Vec<V>) -> Self {
+        Self { values }
+    }
+}
+
+impl<V: ValueForSegment> Collector for StatsCollector<V> {
+    type Fruit = Vec<Stats>;
+    type Child = StatsSegmentCollector<V::Reader>;
+
+    fn for_segment(
+        &self,
+        _segment_id: SegmentLocalId,
+        reader: &SegmentReader,
+    ) -> Result<Self::Child> {
+        let mut readers = Vec::with_capacity(self.values.len());
+        for value in self.values.iter() {
+            readers.push(value.for_segment(reader)?);
+        }
+
+        Ok(StatsSegmentCollector {
+            stats: vec![Stats::default(); readers.len()],
+            readers,
+        })
+    }
+
+    fn requires_scoring(&self) -> bool {
+        false
+    }
+
+    fn merge_fruits(&self, fruits: Vec<Vec<Stats>>) -> Result<Vec<Stats>> {
+        let mut merged = vec![Stats::default(); self.values.len()];
+        for fruit in fruits.iter() {
+            for (stats, other) in merged.iter_mut().zip(fruit.iter()) {
+                stats.merge(other);
+            }
+        }
+        Ok(merged)
+    }
+}
+
+/// The per-segment part of `StatsCollector`
+pub struct StatsSegmentCollector<R> {
+    readers: Vec<R>,
+    stats: Vec<Stats>,
+}
+
+impl<R: ValueForDoc> SegmentCollector for StatsSegmentCollector<R> {
+    type Fruit = Vec<Stats>;
+
+    fn collect(&mut self, doc: DocId, _score: Score) {
+        for (reader, stats) in self.readers.iter().zip(self.stats.iter_mut()) {
+            if let Some(value) = reader.value(doc) {
+                stats.add(value);
+            }
+        }
+    }
+
+    fn harvest(self) -> Vec<Stats> {
+        self.stats
+    }
+}
+
+/// An approximate distinct counter (HyperLogLog)
+///
+/// Uses `2^precision` bytes and estimates the number of distinct
+/// values with a standard error of about `1.04 / sqrt(2^precision)`:
+/// roughly 0.8% for the default precision of 14 (16KiB).
+#[derive(Debug, Clone, PartialEq)]
+pub struct HyperLogLog {
+    precision: u8,
+    registers: Vec<u8>,
+}
+
+/// Precision used by `HyperLogLog::default()`
+pub const DEFAULT_PRECISION: u8 = 14;
+
+impl Default for HyperLogLog {
+    fn default() -> Self {
+        Self::new(DEFAULT_PRECISION)
+    }
+}
+
+impl HyperLogLog {
+    /// Creates an empty counter. Precision must be within `4..=16`
+    pub fn new(precision: u8) -> Self {
+        if !(4..=16).contains(&precision) {
+            panic!("Precision must be between 4 and 16");
+        }
+
+        Self {
+            precision,
+            registers: vec![0; 1 << precision],
+        }
+    }
+
+    /// Accounts for a value
+    pub fn insert(&mut self, value: u64) {
+        let hash = mix(value);
+        let precision = u32::from(self.precision);
+
+        let idx = (hash >> (64 - precision)) as usize;
+        // The guard bit caps the rank for when every remaining bit
+        // is zero
+        let remaining = (hash << precision) | (1 << (precision - 1));
+        let rank = remaining.leading_zeros() as u8 + 1;
+
+        if self.registers[idx] < rank {
+            self.registers[idx] = rank;
+        }
+    }
+
+    /// Accounts for every value seen by `other`
+    ///
+    /// Panics if the precisions differ
+    pub fn merge(&mut self, other: &HyperLogLog) {
+        if self.precision != other.precision {
+            panic!("Can't merge HyperLogLogs of different precisions");
+        }
+
+        for (mine, theirs) in self.registers.iter_mut().zip(other.registers.iter()) {
+            if *mine < *theirs {
+                *mine = *theirs;
+            }
+        }
+    }
+
+    /// The estimated number of distinct values seen
+    pub fn estimate(&self) -> u64 {
+        let m = self.registers.len() as f64;
+        let alpha = 0.7213 / (1.0 + 1.079 / m);
+
+        let mut sum = 0.0;
+        let mut num_zeros = 0;
+        for register in self.registers.iter() {
+            sum += 1.0 / (1u64 << register) as f64;
+            if *register == 0 {
+                num_zeros += 1;
+            }
+        }
+
+        let raw = alpha * m * m / sum;
+
+        // Linear counting is much better for small cardinalities
+        let estimate = if raw <= 2.5 * m && num_zeros > 0 {
+            m * (m / f64::from(num_zeros)).ln()
+        } else {
+            raw
+        };
+
+        estimate.round() as u64
+    }
+}
+
+// splitmix64's finalizer: values are often sequential ids, which
+// need to be spread over the whole range
+fn mix(value: u64) -> u64 {
+    let mut z = value.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
+
+/// Estimates how many distinct keys the matching documents have
+pub struct CardinalityCollector<B> {
+    precision: u8,
+    bucket_for_segment: B,
+}
+
+impl CardinalityCollector<FastFieldBucket> {
+    /// Counts the distinct values of the given u64 fast field
+    pub fn u64_field(field: Field) -> Self {
+        Self::new(FastFieldBucket(field))
+    }
+}
+
+impl<B: BucketForSegment> CardinalityCollector<B> {
+    /// Creates a collector that counts the distinct keys read via the
+    /// given `BucketForSegment`. Documents without a key are skipped
+    pub fn new(bucket_for_segment: B) -> Self {
+        Self {
+            precision: DEFAULT_PRECISION,
+            bucket_for_segment,
+        }
+    }
+
+    /// Sets the precision of the resulting `HyperLogLog`
+    pub fn with_precision(mut self, precision: u8) -> Self {
+        // Fail early instead of at search time
+        HyperLogLog::new(precision);
+        self.precision = precision;
+        self
+    }
+}
+
+impl<B: BucketForSegment> Collector for CardinalityCollector<B> {
+    type Fruit = HyperLogLog;
+    type Child = CardinalitySegmentCollector<B::Reader>;
+
+    fn for_segment(
+        &self,
+        _segment_id: SegmentLocalId,
+        reader: &SegmentReader,
+    ) -> Result<Self::Child> {
+        Ok(CardinalitySegmentCollector {
+            hll: HyperLogLog::new(self.precision),
+            reader: self.bucket_for_segment.for_segment(reader)?,
+        })
+    }
+
+    fn requires_scoring(&self) -> bool {
+        false
+    }
+
+    fn merge_fruits(&self, fruits: Vec<HyperLogLog>) -> Result<HyperLogLog> {
+        let mut merged = HyperLogLog::new(self.precision);
+        for fruit in fruits.iter() {
+            merged.merge(fruit);
+        }
+        Ok(merged)
+    }
+}
+
+/// The per-segment part of `CardinalityCollector`
+pub struct CardinalitySegmentCollector<R> {
+    hll: HyperLogLog,
+    reader: R,
+}
+
+impl<R: BucketForDoc> SegmentCollector for CardinalitySegmentCollector<R> {
+    type Fruit = HyperLogLog;
+
+    fn collect(&mut self, doc: DocId, _score: Score) {
+        if let Some(key) = self.reader.bucket(doc) {
+            self.hll.insert(key);
+        }
+    }
+
+    fn harvest(self) -> HyperLogLog {
+        self.hll
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use tantivy::{
+        doc,
+        query::{AllQuery, TermQuery},
+        schema::{IndexRecordOption, SchemaBuilder, FAST, STRING},
+        Index, Term,
+    };
+
+    #[test]
+    fn stats_arithmetic() {
+        let mut stats = Stats::default();
+        assert_eq!(None, stats.mean());
+
+        for value in &[1.0, 2.0, f64::NAN, 6.0] {
+            stats.add(*value);
+        }
+
+        assert_eq!(3, stats.count);
+        assert_eq!(9.0, stats.sum);
+        assert_eq!(Some(3.0), stats.mean());
+        assert_eq!(Some(1.0), stats.min);
+        assert_eq!(Some(6.0), stats.max);
+
+        let mut other = Stats::default();
+        other.add(-10.0);
+        stats.merge(&other);
+        stats.merge(&Stats::default());
+
+        assert_eq!(4, stats.count);
+        assert_eq!(Some(-10.0), stats.min);
+        assert_eq!(Some(6.0), stats.max);
+    }
+
+    #[test]
+    fn collector() -> Result<()> {
+        let mut builder = SchemaBuilder::new();
+        let kind = builder.add_text_field("kind", STRING);
+        let calories = builder.add_u64_field("calories", FAST);
+        let rating = builder.add_f64_field("rating", FAST);
+        let index = Index::create_in_ram(builder.build());
+        let mut writer = index.writer_with_num_threads(1, 3_000_000)?;
+
+        for i in 1..=100u64 {
+            let label = if i % 2 == 0 { "even" } else { "odd" };
+            writer.add_document(doc!(kind => label, calories => i, rating => i as f64 / 10.0));
+            // Multiple segments
+            if i % 30 == 0 {
+                writer.commit()?;
+            }
+        }
+        writer.commit()?;
+
+        let searcher = index.reader()?.searcher();
+
+        let collector = StatsCollector::new(vec![
+            FastFieldValue::U64(calories),
+            FastFieldValue::F64(rating),
+        ]);
+        let stats = searcher.search(&AllQuery, &collector)?;
+
+        assert_eq!(2, stats.len());
+        assert_eq!(100, stats[0].count);
+        assert_eq!(5050.0, stats[0].sum);
+        assert_eq!(Some(50.5), stats[0].mean());
+        assert_eq!(Some(1.0), stats[0].min);
+        assert_eq!(Some(100.0), stats[0].max);
+        assert_eq!(Some(10.0), stats[1].max);
+
+        let even = TermQuery::new(
+            Term::from_field_text(kind, "even"),
+            IndexRecordOption::Basic,
+        );
+        let stats = searcher.search(&even, &StatsCollector::u64_field(calories))?;
+        assert_eq!(50, stats[0].count);
+        assert_eq!(Some(2.0), stats[0].min);
+        assert_eq!(Some(51.0), stats[0].mean());
+
+        Ok(())
+    }
+
+    fn assert_within(wanted: u64, found: u64, tolerance: f64) {
+        let error = (wanted as f64 - found as f64).abs() / wanted as f64;
+        assert!(
+            error <= tolerance,
+            "wanted {}, found {} (error: {})",
+            wanted,
+            found,
+            error
+        );
+    }
+
+    #[test]
+    fn hyperloglog_estimates() {
+        let mut hll = HyperLogLog::default();
+        assert_eq!(0, hll.estimate());
+
+        for _ in 0..3 {
+            for value in 0..10 {
+                hll.insert(value);
+            }
+        }
+        assert_eq!(10, hll.estimate());
+
+        for &wanted in &[1_000u64, 50_000, 1_000_000] {
+            let mut hll = HyperLogLog::default();
+            for value in 0..wanted {
+                hll.insert(value * 7);
+            }
+            assert_within(wanted, hll.estimate(), 0.03);
+        }
+    }
+
+    #[test]
+    fn hyperloglog_merge() {
+        let mut left = HyperLogLog::new(12);
+        let mut right = HyperLogLog::new(12);
+        let mut whole = HyperLogLog::new(12);
+
+        for value in 0..20_000 {
+            whole.insert(value);
+            if value < 15_000 {
+                left.insert(value);
+            }
+            if value >= 5_000 {
+                right.insert(value);
+            }
+        }
+
+        left.merge(&right);
+        assert_eq!(whole, left);
+    }
+
+    #[test]
+    fn cardinality_collector() -> Result<()> {
+        let mut builder = SchemaBuilder::new();
+        let kind = builder.add_text_field("kind", STRING);
+        let category = builder.add_u64_field("category", FAST);
+        let index = Index::create_in_ram(builder.build());
+        let mut writer = index.writer_with_num_threads(1, 3_000_000)?;
+
+        for i in 0..5000u64 {
+            let label = if i % 2 == 0 { "even" } else { "odd" };
+            writer.add_document(doc!(kind => label, category => i % 1000));
+            // Multiple segments
+            if i % 1500 == 0 {
+                writer.commit()?;
+            }
+        }
+        writer.commit()?;
+
+        let searcher = index.reader()?.searcher();
+
+        let hll = searcher.search(&AllQuery, &CardinalityCollector::u64_field(category))?;
+        assert_within(1000, hll.estimate(), 0.03);
+
+        let even = TermQuery::new(
+            Term::from_field_text(kind, "even"),
+            IndexRecordOption::Basic,
+        );
+        let hll = searcher.search(&even, &CardinalityCollector::u64_field(category))?;
+        assert_within(500, hll.estimate(), 0.03);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,217 @@
+//! Searching what can be searched
+//!
+//! `PartialCollector` wraps any collector so that a segment that
+//! fails to be collected (say: because some of its files are
+//! corrupt) is skipped instead of failing the whole search. The
+//! result comes with the segments that were skipped and why, so
+//! that callers can flag it as partial and alert.
+//!
+//! ```no_run
+//! # use tantivy::{collector::Count, query::AllQuery, Searcher};
+//! # use tique::partial::PartialCollector;
+//! # fn example(searcher: &Searcher) -> tantivy::Result<()> {
+//! let (count, failures) = searcher.search(&AllQuery, &PartialCollector::new(Count))?;
+//! if !failures.is_empty() {
+//!     println!("Counted {} docs, skipping {:?}", count, failures);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Only errors are handled: a collector that panics still brings
+//! the search down.
+use tantivy::{
+    collector::{Collector, SegmentCollector},
+    query::Weight,
+    DocId, Result, Score, SegmentLocalId, SegmentReader,
+};
+
+/// A segment that was skipped
+#[derive(Debug, Clone, PartialEq)]
+pub struct SegmentFailure {
+    /// The segment, as in `SegmentLocalId`
+    pub segment: SegmentLocalId,
+    /// What went wrong, as reported by the error
+    pub error: String,
+}
+
+/// Wraps a collector, skipping the segments it fails on
+pub struct PartialCollector<C>(C);
+
+impl<C: Collector> PartialCollector<C> {
+    /// Creates a new collector that tolerates the segment failures
+    /// of the given one
+    pub fn new(collector: C) -> Self {
+        Self(collector)
+    }
+}
+
+type SegmentFruit<C> = <<C as Collector>::Child as SegmentCollector>::Fruit;
+
+impl<C: Collector> Collector for PartialCollector<C> {
+    type Fruit = (C::Fruit, Vec<SegmentFailure>);
+    type Child = PartialSegmentCollector<C::Child>;
+
+    fn for_segment(
+        &self,
+        segment_id: SegmentLocalId,
+        reader: &SegmentReader,
+    ) -> Result<Self::Child> {
+        Ok(match self.0.for_segment(segment_id, reader) {
+            Ok(child) => PartialSegmentCollector::Healthy(child),
+            Err(err) => PartialSegmentCollector::Failed(failure(segment_id, &err)),
+        })
+    }
+
+    fn requires_scoring(&self) -> bool {
+        self.0.requires_scoring()
+    }
+
+    // Failures may happen outside of the segment collector too, like
+    // when reading the postings for the query
+    fn collect_segment(
+        &self,
+        weight: &dyn Weight,
+        segment_ord: u32,
+        reader: &SegmentReader,
+    ) -> Result<std::result::Result<SegmentFruit<C>, SegmentFailure>> {
+        Ok(self
+            .0
+            .collect_segment(weight, segment_ord, reader)
+            .map_err(|err| failure(segment_ord, &err)))
+    }
+
+    fn merge_fruits(
+        &self,
+        fruits: Vec<std::result::Result<SegmentFruit<C>, SegmentFailure>>,
+    ) -> Result<Self::Fruit> {
+        let mut healthy = Vec::with_capacity(fruits.len());
+        let mut failures = Vec::new();
+
+        for fruit in fruits {
+            match fruit {
+                Ok(fruit) => healthy.push(fruit),
+                Err(failure) => failures.push(failure),
+            }
+        }
+
+        Ok((self.0.merge_fruits(healthy)?, failures))
+    }
+}
+
+fn failure(segment: SegmentLocalId, err: &tantivy::TantivyError) -> SegmentFailure {
+    SegmentFailure {
+        segment,
+        error: err.to_string(),
+    }
+}
+
+/// The per-segment part of `PartialCollector`
+pub enum PartialSegmentCollector<C> {
+    /// Collecting as usual
+    Healthy(C),
+    /// The segment is being skipped
+    Failed(SegmentFailure),
+}
+
+impl<C: SegmentCollector> SegmentCollector for PartialSegmentCollector<C> {
+    type Fruit = std::result::Result<C::Fruit, SegmentFailure>;
+
+    fn collect(&mut self, doc: DocId, score: Score) {
+        if let PartialSegmentCollector::Healthy(inner) = self {
+            inner.collect(doc, score);
+        }
+    }
+
+    fn harvest(self) -> Self::Fruit {
+        match self {
+            PartialSegmentCollector::Healthy(inner) => Ok(inner.harvest()),
+            PartialSegmentCollector::Failed(failure) => Err(failure),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use tantivy::{
+        collector::{Count, TopDocs},
+        doc,
+        query::AllQuery,
+        schema::{SchemaBuilder, INDEXED},
+        Index, TantivyError,
+    };
+
+    // Fails on every segment but the first
+    struct FirstSegmentOnly<C>(C);
+
+    impl<C: Collector> Collector for FirstSegmentOnly<C> {
+        type Fruit = C::Fruit;
+        type Child = C::Child;
+
+        fn for_segment(
+            &self,
+            segment_id: SegmentLocalId,
+            reader: &SegmentReader,
+        ) -> Result<Self::Child> {
+            if segment_id == 0 {
+                self.0.for_segment(segment_id, reader)
+            } else {
+                Err(TantivyError::SystemError("corrupt segment".to_owned()))
+            }
+        }
+
+        fn requires_scoring(&self) -> bool {
+            self.0.requires_scoring()
+        }
+
+        fn merge_fruits(&self, fruits: Vec<SegmentFruit<C>>) -> Result<Self::Fruit> {
+            self.0.merge_fruits(fruits)
+        }
+    }
+
+    #[test]
+    fn failed_segments_are_skipped() -> Result<()> {
+        let mut builder = SchemaBuilder::new();
+        let id = builder.add_u64_field("id", INDEXED);
+        let index = Index::create_in_ram(builder.build());
+        let mut writer = index.writer_with_num_threads(1, 3_000_000)?;
+
+        for i in 0..30u64 {
+            writer.add_document(doc!(id => i));
+            // Multiple segments
+            if i % 10 == 9 {
+                writer.commit()?;
+            }
+        }
+
+        let searcher = index.reader()?.searcher();
+        let segments = searcher.segment_readers();
+        assert!(segments.len() > 1);
+
+        // Nothing to skip
+        let (count, failures) = searcher.search(&AllQuery, &PartialCollector::new(Count))?;
+        assert_eq!(30, count);
+        assert!(failures.is_empty());
+
+        assert!(searcher
+            .search(&AllQuery, &FirstSegmentOnly(Count))
+            .is_err());
+
+        let (count, failures) =
+            searcher.search(&AllQuery, &PartialCollector::new(FirstSegmentOnly(Count)))?;
+        assert_eq!(segments[0].num_docs() as usize, count);
+        assert_eq!(segments.len() - 1, failures.len());
+        assert!(failures
+            .iter()
+            .all(|failure| failure.segment > 0 && failure.error.contains("corrupt segment")));
+
+        // Results from the healthy segments are untouched
+        let (top, _failures) =
+            searcher.search(&AllQuery, &PartialCollector::new(TopDocs::with_limit(5)))?;
+        assert_eq!(searcher.search(&AllQuery, &TopDocs::with_limit(5))?, top);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,555 @@
+//! Approximate percentiles of numeric values among the matching docs
+//!
+//! The `PercentileCollector` feeds a `TDigest`: a compact summary of
+//! a distribution that answers quantile queries with good accuracy
+//! (specially near the extremes, like p99) using bounded memory. The
+//! per-segment digests are merged into the final one, so the result
+//! doesn't depend on how the index is segmented.
+//!
+//! ```no_run
+//! # use tantivy::{query::AllQuery, schema::Field, Searcher, Result};
+//! # use tique::percentiles::PercentileCollector;
+//! # fn example(searcher: &Searcher, calories: Field) -> Result<()> {
+//! let digest = searcher.search(&AllQuery, &PercentileCollector::u64_field(calories))?;
+//!
+//! let median = digest.quantile(0.5);
+//! let p90 = digest.quantile(0.9);
+//! # Ok(())
+//! # }
+//! ```
+use std::cmp::Ordering;
+
+use tantivy::{
+    collector::{Collector, SegmentCollector},
+    fastfield::FastFieldReader,
+    schema::Field,
+    DocId, Result, Score, SegmentLocalId, SegmentReader, TantivyError,
+};
+
+/// A mergeable summary of a distribution of numbers
+///
+/// This is the "merging" variant of Ted Dunning's t-digest: values
+/// are buffered and periodically merged into a sorted list of
+/// centroids whose sizes are bounded by the `compression` factor,
+/// smaller towards the tails.
+#[derive(Debug, Clone)]
+pub struct TDigest {
+    compression: f64,
+    centroids: Vec<Centroid>,
+    buffer: Vec<Centroid>,
+    count: u64,
+    min: f64,
+    max: f64,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Centroid {
+    mean: f64,
+    weight: f64,
+}
+
+/// Compression used by `TDigest::default()`. Keeps up to a few
+/// hundred centroids, with errors well under 1% for most inputs
+pub const DEFAULT_COMPRESSION: f64 = 100.0;
+
+impl Default for TDigest {
+    fn default() -> Self {
+        Self::new(DEFAULT_COMPRESSION)
+    }
+}
+
+impl TDigest {
+    /// Creates an empty digest. Higher `compression` means more
+    /// accuracy and memory usage
+    pub fn new(compression: f64) -> Self {
+        if compression.is_nan() || compression < 1.0 {
+            panic!("Compression must be at least 1");
+        }
+
+        Self {
+            compression,
+            centroids: Vec::new(),
+            buffer: Vec::new(),
+            count: 0,
+            min: f64::INFINITY,
+            max: f64::NEG_INFINITY,
+        }
+    }
+
+    /// Adds a value to the digest. NaNs and infinities are ignored
+    pub fn add(&mut self, value: f64) {
+        if !value.is_finite() {
+            return;
+        }
+
+        self.push(Centroid {
+            mean: value,
+            weight: 1.0,
+        });
+        self.count += 1;
+        self.min = self.min.min(value);
+        self.max = self.max.max(value);
+    }
+
+    /// Adds every value from `other` to this digest
+    pub fn merge(&mut self, other: &TDigest) {
+        for centroid in other.centroids.iter().chain(other.buffer.iter()) {
+            self.push(*centroid);
+        }
+        self.count += other.count;
+        self.min = self.min.min(other.min);
+        self.max = self.max.max(other.max);
+    }
+
+    /// How many values were added
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    /// Check if no values were added
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    /// The smallest value added, if any
+    pub fn min(&self) -> Option<f64> {
+        if self.is_empty() {
+            None
+        } else {
+            Some(self.min)
+        }
+    }
+
+    /// The largest value added, if any
+    pub fn max(&self) -> Option<f64> {
+        if self.is_empty() {
+            None
+        } else {
+            Some(self.max)
+        }
+    }
+
+    /// Estimates the value below which the fraction `q` of the values
+    /// fall: `quantile(0.5)` is the median, `quantile(0.9)` the p90.
+    ///
+    /// Yields `None` if the digest is empty or `q` is not within
+    /// `[0.0, 1.0]`
+    pub fn quantile(&self, q: f64) -> Option<f64> {
+        if self.is_empty() || !(0.0..=1.0).contains(&q) {
+            return None;
+        }
+
+        let mut centroids = self.centroids.clone();
+        centroids.extend_from_slice(&self.buffer);
+        sort_by_mean(&mut centroids);
+
+        let total: f64 = centroids.iter().map(|c| c.weight).sum();
+        let target = q * total;
+
+        // Each centroid is taken to be centered at the middle of the
+        // (cumulative) weight it spans and values are interpolated
+        // between the neighbouring centers. The tails interpolate
+        // against the extremes: even a single-value centroid may not
+        // be the extreme one when the buffer hasn't been merged yet
+        let first = centroids[0];
+        if target <= first.weight / 2.0 {
+            return Some(interpolate(
+                self.min,
+                first.mean,
+                target / (first.weight / 2.0),
+            ));
+        }
+
+        let mut cumulative = 0.0;
+        for pair in centroids.windows(2) {
+            let (left, right) = (pair[0], pair[1]);
+            let left_center = cumulative + left.weight / 2.0;
+            let right_center = cumulative + left.weight + right.weight / 2.0;
+
+            if target <= right_center {
+                let fraction = (target - left_center) / (right_center - left_center);
+                return Some(interpolate(left.mean, right.mean, fraction));
+            }
+
+            cumulative += left.weight;
+        }
+
+        let last = centroids[centroids.len() - 1];
+        let last_center = total - last.weight / 2.0;
+        Some(interpolate(
+            last.mean,
+            self.max,
+            (target - last_center) / (last.weight / 2.0),
+        ))
+    }
+
+    fn push(&mut self, centroid: Centroid) {
+        self.buffer.push(centroid);
+        if self.buffer.len() >= self.buffer_limit() {
+            self.compress();
+        }
+    }
+
+    fn buffer_limit(&self) -> usize {
+        (self.compression * 5.0) as usize
+    }
+
+    fn compress(&mut self) {
+        if self.buffer.is_empty() {
+            return;
+        }
+
+        let mut all = std::mem::take(&mut self.centroids);
+        all.append(&mut self.buffer);
+        sort_by_mean(&mut all);
+
+        let total: f64 = all.iter().map(|c| c.weight).sum();
+
+        let mut merged = Vec::with_capacity(all.len().min(self.buffer_limit()));
+        let mut iter = all.into_iter();
+        let mut current = iter.next().expect("buffer is not empty");
+        let mut weight_before = 0.0;
+        let mut limit = self.q_limit(0.0);
+
+        for next in iter {
+            let q = (weight_before + current.weight + next.weight) / total;
+            if q <= limit {
+                let weight = current.weight + next.weight;
+                current.mean += (next.mean - current.mean) * next.weight / weight;
+                current.weight = weight;
+            } else {
+                weight_before += current.weight;
+                merged.push(current);
+                limit = self.q_limit(weight_before / total);
+                current = next;
+            }
+        }
+        merged.push(current);
+
+        self.centroids = merged;
+    }
+
+    // The largest quantile a centroid starting at `q` may reach, as
+    // given by the k1 scale function: k(q) = δ/2π · asin(2q - 1)
+    fn q_limit(&self, q: f64) -> f64 {
+        let scale = self.compression / (2.0 * std::f64::consts::PI);
+        let k = scale * (2.0 * q - 1.0).asin();
+        let angle = ((k + 1.0) / scale).min(std::f64::consts::FRAC_PI_2);
+        (angle.sin() + 1.0) / 2.0
+    }
+}
+
+fn sort_by_mean(centroids: &mut [Centroid]) {
+    centroids.sort_by(|a, b| a.mean.partial_cmp(&b.mean).unwrap_or(Ordering::Equal));
+}
+
+fn interpolate(from: f64, to: f64, fraction: f64) -> f64 {
+    from + (to - from) * fraction.max(0.0).min(1.0)
+}
+
+/// Extracts the value to summarize from the documents of a segment
+pub trait ValueForSegment: Sync {
+    /// The per-segment value reader
+    type Reader: ValueForDoc;
+    /// Prepares to read values from the given segment
+    fn for_segment(&self, reader: &SegmentReader) -> Result<Self::Reader>;
+}
+
+/// Reads the value for a document within a segment
+pub trait ValueForDoc: 'static {
+    /// The value for the given doc, if it has any
+    fn value(&self, doc: DocId) -> Option<f64>;
+}
+
+impl<F, R> ValueForSegment for F
+where
+    F: Sync + Fn(&SegmentReader) -> Result<R>,
+    R: ValueForDoc,
+{
+    type Reader = R;
+
+    fn for_segment(&self, reader: &SegmentReader) -> Result<Self::Reader> {
+        (self)(reader)
+    }
+}
+
+impl<F> ValueForDoc for F
+where
+    F: 'static + Fn(DocId) -> Option<f64>,
+{
+    fn value(&self, doc: DocId) -> Option<f64> {
+        (self)(doc)
+    }
+}
+
+/// A `ValueForSegment` that reads a numeric fast field
+#[derive(Debug, Clone, Copy)]
+pub enum FastFieldValue {
+    /// A `u64` fast field
+    U64(Field),
+    /// An `i64` fast field
+    I64(Field),
+    /// An `f64` fast field
+    F64(Field),
+}
+
+/// Reads the values of a `FastFieldValue`
+pub enum FastFieldValueReader {
+    /// See `FastFieldValue::U64`
+    U64(FastFieldReader<u64>),
+    /// See `FastFieldValue::I64`
+    I64(FastFieldReader<i64>),
+    /// See `FastFieldValue::F64`
+    F64(FastFieldReader<f64>),
+}
+
+impl ValueForSegment for FastFieldValue {
+    type Reader = FastFieldValueReader;
+
+    fn for_segment(&self, reader: &SegmentReader) -> Result<Self::Reader> {
+        let fast_fields = reader.fast_fields();
+        let found = match *self {
+            FastFieldValue::U64(field) => fast_fields.u64(field).map(FastFieldValueReader::U64),
+            FastFieldValue::I64(field) => fast_fields.i64(field).map(FastFieldValueReader::I64),
+            FastFieldValue::F64(field) => fast_fields.f64(field).map(FastFieldValueReader::F64),
+        };
+
+        found.ok_or_else(|| {
+            TantivyError::SchemaError(format!("{:?} is not a fast field of that type", self))
+        })
+    }
+}
+
+impl ValueForDoc for FastFieldValueReader {
+    fn value(&self, doc: DocId) -> Option<f64> {
+        Some(match self {
+            FastFieldValueReader::U64(reader) => reader.get(doc) as f64,
+            FastFieldValueReader::I64(reader) => reader.get(doc) as f64,
+            FastFieldValueReader::F64(reader) => reader.get(doc),
+        })
+    }
+}
+
+/// Summarizes a value of every matching document into a `TDigest`
+pub struct PercentileCollector<V> {
+    compression: f64,
+    value_for_segment: V,
+}
+
+impl PercentileCollector<FastFieldValue> {
+    /// Summarizes the given `u64` fast field
+    pub fn u64_field(field: Field) -> Self {
+        Self::new(FastFieldValue::U64(field))
+    }
+
+    /// Summarizes the given `i64` fast field
+    pub fn i64_field(field: Field) -> Self {
+        Self::new(FastFieldValue::I64(field))
+    }
+
+    /// Summarizes the given `f64` fast field
+    pub fn f64_field(field: Field) -> Self {
+        Self::new(FastFieldValue::F64(field))
+    }
+}
+
+impl<V: ValueForSegment> PercentileCollector<V> {
+    /// Creates a collector that summarizes the values read via the
+    /// given `ValueForSegment`. Documents without a value are skipped
+    pub fn new(value_for_segment: V) -> Self {
+        Self {
+            compression: DEFAULT_COMPRESSION,
+            value_for_segment,
+        }
+    }
+
+    /// Sets the compression of the resulting digest
+    pub fn with_compression(mut self, compression: f64) -> Self {
+        self.compression = compression;
+        self
+    }
+}
+
+impl<V: ValueForSegment> Collector for PercentileCollector<V> {
+    type Fruit = TDigest;
+    type Child = PercentileSegmentCollector<V::Reader>;
+
+    fn for_segment(
+        &self,
+        _segment_id: SegmentLocalId,
+        reader: &SegmentReader,
+    ) -> Result<Self::Child> {
+        Ok(PercentileSegmentCollector {
+            digest: TDigest::new(self.compression),
+            reader: self.value_for_segment.for_segment(reader)?,
+        })
+    }
+
+    fn requires_scoring(&self) -> bool {
+        false
+    }
+
+    fn merge_fruits(&self, fruits: Vec<TDigest>) -> Result<TDigest> {
+        let mut merged = TDigest::new(self.compression);
+        for fruit in fruits.iter() {
+            merged.merge(fruit);
+        }
+        Ok(merged)
+    }
+}
+
+/// The per-segment part of a `PercentileCollector`
+pub struct PercentileSegmentCollector<R> {
+    digest: TDigest,
+    reader: R,
+}
+
+impl<R: ValueForDoc> SegmentCollector for PercentileSegmentCollector<R> {
+    type Fruit = TDigest;
+
+    fn collect(&mut self, doc: DocId, _score: Score) {
+        if let Some(value) = self.reader.value(doc) {
+            self.digest.add(value);
+        }
+    }
+
+    fn harvest(self) -> TDigest {
+        self.digest
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use tantivy::{
+        doc,
+        query::{AllQuery, TermQuery},
+        schema::{IndexRecordOption, SchemaBuilder, FAST, STRING},
+        Index, Term,
+    };
+
+    fn assert_close(wanted: f64, found: Option<f64>, tolerance: f64) {
+        let found = found.expect("digest is not empty");
+        assert!(
+            (wanted - found).abs() <= tolerance,
+            "wanted {} ± {}, found {}",
+            wanted,
+            tolerance,
+            found
+        );
+    }
+
+    #[test]
+    fn empty_and_tiny_digests() {
+        let mut digest = TDigest::default();
+        assert_eq!(None, digest.quantile(0.5));
+        assert_eq!(None, digest.min());
+
+        digest.add(42.0);
+        digest.add(f64::NAN);
+        assert_eq!(1, digest.count());
+        for &q in &[0.0, 0.3, 1.0] {
+            assert_eq!(Some(42.0), digest.quantile(q));
+        }
+        assert_eq!(None, digest.quantile(1.1));
+
+        digest.add(10.0);
+        assert_eq!(Some(10.0), digest.quantile(0.0));
+        assert_eq!(Some(42.0), digest.quantile(1.0));
+        assert_eq!(Some(26.0), digest.quantile(0.5));
+    }
+
+    #[test]
+    fn accuracy() {
+        let mut digest = TDigest::default();
+        // Not in order, so that compression sees some mixing
+        for i in 0..100_000u64 {
+            digest.add(((i * 7919) % 100_000) as f64);
+        }
+
+        assert_eq!(100_000, digest.count());
+        assert!(digest.centroids.len() + digest.buffer.len() < 1000);
+
+        assert_close(0.0, digest.quantile(0.0), 0.0);
+        assert_close(99_999.0, digest.quantile(1.0), 0.0);
+        for &q in &[0.01, 0.1, 0.25, 0.5, 0.75, 0.9, 0.99] {
+            assert_close(q * 100_000.0, digest.quantile(q), 500.0);
+        }
+    }
+
+    #[test]
+    fn merging_is_like_adding() {
+        let mut left = TDigest::default();
+        let mut right = TDigest::default();
+        let mut whole = TDigest::default();
+
+        for i in 0..10_000u64 {
+            let value = ((i * 31) % 1000) as f64;
+            whole.add(value);
+            if i % 3 == 0 {
+                left.add(value);
+            } else {
+                right.add(value);
+            }
+        }
+
+        left.merge(&right);
+        assert_eq!(whole.count(), left.count());
+        assert_eq!(whole.min(), left.min());
+        assert_eq!(whole.max(), left.max());
+
+        for &q in &[0.1, 0.5, 0.9] {
+            assert_close(whole.quantile(q).unwrap(), left.quantile(q), 10.0);
+        }
+    }
+
+    #[test]
+    fn collector() -> Result<()> {
+        let mut builder = SchemaBuilder::new();
+        let kind = builder.add_text_field("kind", STRING);
+        let calories = builder.add_u64_field("calories", FAST);
+        let index = Index::create_in_ram(builder.build());
+        let mut writer = index.writer_with_num_threads(1, 3_000_000)?;
+
+        for i in 0..1000u64 {
+            let label = if i % 2 == 0 { "even" } else { "odd" };
+            writer.add_document(doc!(kind => label, calories => i));
+            // Multiple segments
+            if i % 300 == 299 {
+                writer.commit()?;
+            }
+        }
+        writer.commit()?;
+
+        let searcher = index.reader()?.searcher();
+        assert!(searcher.segment_readers().len() > 1);
+
+        let digest = searcher.search(&AllQuery, &PercentileCollector::u64_field(calories))?;
+        assert_eq!(1000, digest.count());
+        assert_close(500.0, digest.quantile(0.5), 5.0);
+        assert_close(900.0, digest.quantile(0.9), 5.0);
+
+        let even = TermQuery::new(
+            Term::from_field_text(kind, "even"),
+            IndexRecordOption::Basic,
+        );
+        let only_small = PercentileCollector::new(move |reader: &SegmentReader| {
+            let calories = reader.fast_fields().u64(calories).expect("fast field");
+            Ok::<_, TantivyError>(move |doc: DocId| {
+                Some(calories.get(doc))
+                    .filter(|c| *c < 100)
+                    .map(|c| c as f64)
+            })
+        });
+        let digest = searcher.search(&even, &only_small)?;
+        assert_eq!(50, digest.count());
+        assert_eq!(Some(98.0), digest.max());
+
+        assert!(searcher
+            .search(&AllQuery, &PercentileCollector::f64_field(calories))
+            .is_err());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,435 @@
+use std::fmt;
+
+use tantivy::query::{BooleanQuery, Occur, Query};
+
+use super::{
+    parser::QueryParser,
+    raw::{regex_pattern, RawQuery},
+};
+use crate::DisMaxQuery;
+
+/// A query as parsed by `QueryParser::parse_ast`, before any field
+/// analyzer gets to see it
+///
+/// Its items can be inspected and rewritten freely, like requiring
+/// a field to have a certain value regardless of what the user
+/// typed, and then turned into a tantivy query via `Ast::to_query`.
+/// `Display` yields the query in the syntax `QueryParser` takes.
+///
+/// `Ast::normalize` brings equivalent queries to the same form, and
+/// `Ast::fingerprint` hashes that, for things like cache keys.
+///
+/// ```no_run
+/// # use tique::{Ast, AstItem, QueryParser};
+/// # use tantivy::query::Occur;
+/// # fn example(parser: &QueryParser) {
+/// let mut ast = parser.parse_ast("bacon -egg");
+/// ast.items
+///     .push(AstItem::term("acme").with_field("tenant").with_occur(Occur::Must));
+/// assert_eq!("bacon -egg +tenant:acme", ast.to_string());
+///
+/// let query = ast.to_query(&parser);
+/// # }
+/// ```
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Ast {
+    /// Every item of the query, in order
+    pub items: Vec<AstItem>,
+}
+
+/// A term, phrase or group of an `Ast`
+#[derive(Debug, Clone, PartialEq)]
+pub struct AstItem {
+    /// What to look for, as typed
+    pub text: String,
+    /// Whether it's a `"quoted phrase"`
+    pub is_phrase: bool,
+    /// The field it's restricted to, like in `name:text`. The
+    /// parser's default fields are used otherwise
+    pub field: Option<String>,
+    /// `Occur::Must` for `+text`, `Occur::MustNot` for `-text`
+    pub occur: Occur,
+    /// Like in `text^2`
+    pub boost: Option<f32>,
+    /// The edit distance of `text~1`. Phrases are never fuzzy
+    pub fuzzy: Option<u8>,
+    /// The items of a `(group)`, which match like a query of their
+    /// own. Groups have no `text`, `field` nor `fuzzy`
+    pub group: Option<Vec<AstItem>>,
+}
+
+impl AstItem {
+    /// A plain, optional, term
+    pub fn term<T: Into<String>>(text: T) -> Self {
+        Self {
+            text: text.into(),
+            is_phrase: false,
+            field: None,
+            occur: Occur::Should,
+            boost: None,
+            fuzzy: None,
+            group: None,
+        }
+    }
+
+    /// A plain, optional, phrase
+    pub fn phrase<T: Into<String>>(text: T) -> Self {
+        Self {
+            is_phrase: true,
+            ..Self::term(text)
+        }
+    }
+
+    /// An optional group of `items`, like `(a b)`
+    pub fn group(items: Vec<AstItem>) -> Self {
+        Self {
+            group: Some(items),
+            ..Self::term("")
+        }
+    }
+
+    /// Restricts the item to the field named `name`
+    pub fn with_field<T: Into<String>>(mut self, name: T) -> Self {
+        self.field = Some(name.into());
+        self
+    }
+
+    /// Makes the item required or prohibited
+    pub fn with_occur(mut self, occur: Occur) -> Self {
+        self.occur = occur;
+        self
+    }
+
+    /// Changes the importance of the item
+    pub fn with_boost(mut self, boost: f32) -> Self {
+        self.boost = Some(boost);
+        self
+    }
+
+    /// Makes the term fuzzy
+    pub fn with_fuzzy(mut self, distance: u8) -> Self {
+        self.fuzzy = Some(distance);
+        self
+    }
+
+    fn normalize(&mut self) {
+        if let Some(items) = &mut self.group {
+            normalize_items(items);
+        }
+
+        // Regexes are taken as they are
+        if self.is_phrase || regex_pattern(&self.text).is_none() {
+            self.text = self
+                .text
+                .split_whitespace()
+                .map(str::to_lowercase)
+                .collect::<Vec<_>>()
+                .join(" ");
+        }
+
+        if self.is_phrase {
+            self.fuzzy = None;
+        }
+        if self.fuzzy == Some(0) {
+            self.fuzzy = None;
+        }
+        if self.boost == Some(1.0) {
+            self.boost = None;
+        }
+    }
+
+    fn as_raw(&self) -> RawQuery<'_> {
+        RawQuery {
+            input: self.text.as_str(),
+            is_phrase: self.is_phrase,
+            field_name: self.field.as_deref(),
+            occur: self.occur,
+            boost: self.boost,
+            fuzzy: self.fuzzy,
+            group: self
+                .group
+                .as_ref()
+                .map(|items| items.iter().map(AstItem::as_raw).collect()),
+        }
+    }
+}
+
+fn normalize_items(items: &mut Vec<AstItem>) {
+    items.iter_mut().for_each(AstItem::normalize);
+    items.sort_by_cached_key(AstItem::to_string);
+    items.dedup();
+}
+
+impl<'a> From<RawQuery<'a>> for AstItem {
+    fn from(raw: RawQuery<'a>) -> Self {
+        if let Some(items) = raw.group {
+            let group = Self::group(items.into_iter().map(AstItem::from).collect());
+            return Self {
+                occur: raw.occur,
+                boost: raw.boost,
+                ..group
+            };
+        }
+
+        Self {
+            text: raw.input.to_owned(),
+            is_phrase: raw.is_phrase,
+            field: raw.field_name.map(str::to_owned),
+            occur: raw.occur,
+            boost: raw.boost,
+            fuzzy: raw.fuzzy,
+        }
+    }
+}
+
+impl Ast {
+    /// Interprets the query like `QueryParser::parse` would, with
+    /// the analyzers and settings of `parser`
+    pub fn to_query(&self, parser: &QueryParser) -> Option<Box<dyn Query>> {
+        parser.interpret(&self.raw_items(), &|queries| {
+            Box::new(BooleanQuery::from(
+                queries
+                    .into_iter()
+                    .map(|q| (Occur::Should, q))
+                    .collect::<Vec<_>>(),
+            ))
+        })
+    }
+
+    /// Interprets the query like `QueryParser::parse_dixmax` would
+    ///
+    /// Panics when `tiebreaker` is lower than zero or greater than one.
+    pub fn to_dismax_query(&self, parser: &QueryParser, tiebreaker: f32) -> Option<Box<dyn Query>> {
+        assert!(
+            (0.0..=1.0).contains(&tiebreaker),
+            "tiebreaker must be between 0 and 1.0"
+        );
+        parser.interpret(&self.raw_items(), &|queries| {
+            Box::new(DisMaxQuery::new(queries, tiebreaker))
+        })
+    }
+
+    /// Rewrites the query to a canonical form, so that queries that
+    /// only differ in letter case, spacing, item order or repeated
+    /// items end up equal (and display the same). Items within groups
+    /// too
+    ///
+    /// Items are lowercased, so this assumes the fields searched
+    /// lowercase their input too, like with tantivy's default
+    /// tokenizer. Settings that change nothing, like `^1`, are dropped.
+    pub fn normalize(&mut self) {
+        normalize_items(&mut self.items);
+    }
+
+    /// A hash of the query, as displayed. Unlike `std`'s hashers it
+    /// is stable across runs and builds, so it can be stored or used
+    /// to group queries. Call `normalize` first so that equivalent
+    /// queries share their fingerprint
+    pub fn fingerprint(&self) -> u64 {
+        // 64-bit FNV-1a
+        self.to_string()
+            .bytes()
+            .fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+                (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+            })
+    }
+
+    fn raw_items(&self) -> Vec<RawQuery<'_>> {
+        self.items.iter().map(AstItem::as_raw).collect()
+    }
+}
+
+impl fmt::Display for AstItem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.occur {
+            Occur::Must => write!(f, "+")?,
+            Occur::MustNot => write!(f, "-")?,
+            Occur::Should => {}
+        }
+
+        if let Some(field) = self.field.as_ref().filter(|_| self.group.is_none()) {
+            write!(f, "{}:", field)?;
+        }
+
+        if let Some(items) = &self.group {
+            write!(f, "(")?;
+            write_items(f, items)?;
+            write!(f, ")")?;
+        } else if self.is_phrase {
+            write!(f, "\"{}\"", self.text)?;
+        } else {
+            write!(f, "{}", self.text)?;
+            if let Some(distance) = self.fuzzy {
+                write!(f, "~{}", distance)?;
+            }
+        }
+
+        if let Some(boost) = self.boost {
+            write!(f, "^{}", boost)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl fmt::Display for Ast {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write_items(f, &self.items)
+    }
+}
+
+fn write_items(f: &mut fmt::Formatter<'_>, items: &[AstItem]) -> fmt::Result {
+    for (idx, item) in items.iter().enumerate() {
+        if idx > 0 {
+            write!(f, " ")?;
+        }
+        write!(f, "{}", item)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use tantivy::{
+        collector::Count,
+        doc,
+        schema::{SchemaBuilder, STRING, TEXT},
+        Index, Result,
+    };
+
+    #[test]
+    fn display_roundtrips() -> Result<()> {
+        let mut builder = SchemaBuilder::new();
+        let name = builder.add_text_field("name", TEXT);
+        let index = Index::create_in_ram(builder.build());
+        let parser = QueryParser::new(&index, vec![name])?;
+
+        for input in &[
+            "bacon",
+            "+bacon -egg",
+            "name:garlic^3 \"olive oil\"^0.5",
+            "-name:\"deep fry\" garlc~1 tomatoe~2^1.5",
+            "unknown:field",
+            "+(chicken turkey) -fried",
+            "a (+b +c)^2 -(d (e f))",
+        ] {
+            let ast = parser.parse_ast(input);
+            assert_eq!(*input, ast.to_string());
+            assert_eq!(ast, parser.parse_ast(&ast.to_string()));
+        }
+
+        assert_eq!(
+            Ast {
+                items: vec![
+                    AstItem::term("garlic").with_field("name").with_boost(3.0),
+                    AstItem::phrase("olive oil").with_occur(Occur::MustNot),
+                ]
+            },
+            parser.parse_ast("name:garlic^3 -\"olive oil\"")
+        );
+
+        assert_eq!(Ast::default(), parser.parse_ast("  "));
+
+        let ast = parser.parse_ast("(chicken OR turkey) AND -fried");
+        assert_eq!(
+            Ast {
+                items: vec![
+                    AstItem::group(vec![AstItem::term("chicken"), AstItem::term("turkey")])
+                        .with_occur(Occur::Must),
+                    AstItem::term("fried").with_occur(Occur::MustNot),
+                ]
+            },
+            ast
+        );
+        assert_eq!("+(chicken turkey) -fried", ast.to_string());
+
+        Ok(())
+    }
+
+    #[test]
+    fn normalization() -> Result<()> {
+        let mut builder = SchemaBuilder::new();
+        let name = builder.add_text_field("name", TEXT);
+        let index = Index::create_in_ram(builder.build());
+        let parser = QueryParser::new(&index, vec![name])?;
+
+        let normalized = |input: &str| {
+            let mut ast = parser.parse_ast(input);
+            ast.normalize();
+            ast
+        };
+
+        let expected = normalized("bacon -\"olive oil\" name:garlic~1");
+        assert_eq!("-\"olive oil\" bacon name:garlic~1", expected.to_string());
+
+        for input in &[
+            "name:Garlic~1 -\"Olive  OIL\" BACON",
+            "bacon^1 name:garlic~1 bacon -\"olive oil\" -\"olive oil\"",
+            "  -\"olive oil\"^1 name:garlic~1   bacon~0",
+        ] {
+            let ast = normalized(input);
+            assert_eq!(expected, ast, "{}", input);
+            assert_eq!(expected.fingerprint(), ast.fingerprint());
+        }
+
+        // Within groups too
+        assert_eq!(
+            normalized("-fried +(chicken turkey)"),
+            normalized("(Turkey OR chicken OR chicken)^1 AND -fried")
+        );
+
+        // Regexes are case-sensitive
+        assert_eq!("name:/Ca[kc]e/", normalized("name:/Ca[kc]e/").to_string());
+
+        // Still different queries
+        for input in &["+bacon -\"olive oil\" name:garlic~1", "bacon name:garlic~1"] {
+            assert_ne!(expected.fingerprint(), normalized(input).fingerprint());
+        }
+
+        // Stable, regardless of the process
+        assert_eq!(0xcbf2_9ce4_8422_2325, Ast::default().fingerprint());
+
+        Ok(())
+    }
+
+    #[test]
+    fn rewritten_queries_are_interpreted() -> Result<()> {
+        let mut builder = SchemaBuilder::new();
+        let name = builder.add_text_field("name", TEXT);
+        let tenant = builder.add_text_field("tenant", STRING);
+        let index = Index::create_in_ram(builder.build());
+
+        let mut writer = index.writer_with_num_threads(1, 3_000_000)?;
+        writer.add_document(doc!(name => "bacon and eggs", tenant => "acme"));
+        writer.add_document(doc!(name => "bacon sandwich", tenant => "acme"));
+        writer.add_document(doc!(name => "bacon pancakes", tenant => "other"));
+        writer.commit()?;
+        let searcher = index.reader()?.searcher();
+
+        let mut parser = QueryParser::new(&index, vec![name, tenant])?;
+        parser.set_default_fields(vec![name]);
+
+        let mut ast = parser.parse_ast("bacon -eggs");
+        let query = ast.to_query(&parser).expect("something to search");
+        assert_eq!(2, searcher.search(&query, &Count)?);
+
+        ast.items.push(
+            AstItem::term("acme")
+                .with_field("tenant")
+                .with_occur(Occur::Must),
+        );
+        let query = ast.to_query(&parser).expect("something to search");
+        assert_eq!(1, searcher.search(&query, &Count)?);
+
+        let query = ast
+            .to_dismax_query(&parser, 0.1)
+            .expect("something to search");
+        assert_eq!(1, searcher.search(&query, &Count)?);
+
+        assert!(Ast::default().to_query(&parser).is_none());
+
+        Ok(())
+    }
+}
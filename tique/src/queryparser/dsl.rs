@@ -0,0 +1,522 @@
+use std::{convert::TryFrom, ops::Bound};
+
+use serde::Deserialize;
+use tantivy::{
+    query::{AllQuery, BooleanQuery, Occur, Query, RangeQuery},
+    schema::FieldType,
+    Result, TantivyError,
+};
+
+use super::{
+    parser::QueryParser,
+    range::{self, Side},
+    raw::{FieldNameValidator, RawQuery},
+};
+
+/// A query as a structure instead of a string, for programmatic
+/// clients that would rather not build (and escape) query strings
+///
+/// Deserializes from json like:
+///
+/// ```json
+/// {
+///   "bool": {
+///     "must": [{ "term": { "value": "bacon" } }],
+///     "should": [{ "phrase": { "value": "deep fry", "field": "instructions" } }],
+///     "must_not": [{ "range": { "field": "calories", "gte": 500 } }]
+///   }
+/// }
+/// ```
+///
+/// Terms and phrases are interpreted like the items of the string
+/// syntax, so field boosts, fuzziness and synonyms apply just the
+/// same. Turn it into a tantivy query via `QueryParser::compile`.
+///
+/// **NOTE**: Requires the `querydsl` compilation feature.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+pub enum StructuredQuery {
+    /// Combines other queries like a `BooleanQuery` does: without
+    /// any `must` clause, matching one of the `should` ones is
+    /// required
+    Bool {
+        /// Clauses that documents must match
+        #[serde(default)]
+        must: Vec<StructuredQuery>,
+        /// Clauses that make documents score higher
+        #[serde(default)]
+        should: Vec<StructuredQuery>,
+        /// Clauses that documents must not match
+        #[serde(default)]
+        must_not: Vec<StructuredQuery>,
+    },
+    /// Same as `value` in the string syntax
+    Term(TextClause),
+    /// Same as `"value"` in the string syntax
+    Phrase(TextClause),
+    /// Restricts the values of any indexed field of the schema, not
+    /// only the ones the parser knows about
+    Range(RangeClause),
+}
+
+/// What to look for with `StructuredQuery::Term` and
+/// `StructuredQuery::Phrase`
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TextClause {
+    /// Analyzed with the tokenizer of each field searched. Terms
+    /// with wildcards, like `garl*`, match like in the string syntax
+    pub value: String,
+    /// A field name, like in `field:value`. The default fields are
+    /// searched if not set
+    #[serde(default)]
+    pub field: Option<String>,
+    /// Like `value^boost`
+    #[serde(default)]
+    pub boost: Option<f32>,
+    /// Like `value~fuzzy`. Phrases are never fuzzy
+    #[serde(default)]
+    pub fuzzy: Option<u8>,
+}
+
+/// The bounds of a `StructuredQuery::Range`. Missing bounds mean
+/// the range is open ended
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RangeClause {
+    /// The schema name of the field
+    pub field: String,
+    /// Exclusive lower bound
+    #[serde(default)]
+    pub gt: Option<RangeValue>,
+    /// Inclusive lower bound
+    #[serde(default)]
+    pub gte: Option<RangeValue>,
+    /// Exclusive upper bound
+    #[serde(default)]
+    pub lt: Option<RangeValue>,
+    /// Inclusive upper bound
+    #[serde(default)]
+    pub lte: Option<RangeValue>,
+}
+
+/// A bound of a `RangeClause`, taken as whatever the json had.
+/// Numbers are converted to the type of the field when they fit it
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(untagged)]
+pub enum RangeValue {
+    /// Fits u64, i64 (when small enough) and f64 fields
+    U64(u64),
+    /// Fits i64 and f64 fields
+    I64(i64),
+    /// Fits f64 fields
+    F64(f64),
+    /// Fits text fields and, as dates or date math like `now-30d`,
+    /// integer fields. See `QueryParser` for the date syntax
+    Str(String),
+}
+
+impl RangeValue {
+    fn as_u64(&self) -> Option<u64> {
+        match *self {
+            RangeValue::U64(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    fn as_i64(&self) -> Option<i64> {
+        match *self {
+            RangeValue::U64(value) if value <= std::i64::MAX as u64 => Some(value as i64),
+            RangeValue::I64(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    fn as_f64(&self) -> Option<f64> {
+        match *self {
+            RangeValue::U64(value) => Some(value as f64),
+            RangeValue::I64(value) => Some(value as f64),
+            RangeValue::F64(value) => Some(value),
+            RangeValue::Str(_) => None,
+        }
+    }
+
+    fn as_str(&self) -> Option<&str> {
+        match self {
+            RangeValue::Str(value) => Some(value.as_str()),
+            _ => None,
+        }
+    }
+}
+
+impl QueryParser {
+    /// Turn a `StructuredQuery` into a tantivy query
+    ///
+    /// Like with `QueryParser::parse`, clauses the analyzers emit no
+    /// tokens for are dropped, so `None` means that nothing was left
+    /// to search for.
+    ///
+    /// # Errors
+    ///
+    /// Will yield an error when a clause refers to a field the parser
+    /// (or, for ranges, the schema) doesn't know about, or when the
+    /// bounds of a range don't fit the type of its field
+    pub fn compile(&self, query: &StructuredQuery) -> Result<Option<Box<dyn Query>>> {
+        match query {
+            StructuredQuery::Bool {
+                must,
+                should,
+                must_not,
+            } => self.compile_bool(must, should, must_not),
+            StructuredQuery::Term(clause) => self.compile_text(clause, false),
+            StructuredQuery::Phrase(clause) => self.compile_text(clause, true),
+            StructuredQuery::Range(clause) => self.compile_range(clause).map(Some),
+        }
+    }
+
+    fn compile_bool(
+        &self,
+        must: &[StructuredQuery],
+        should: &[StructuredQuery],
+        must_not: &[StructuredQuery],
+    ) -> Result<Option<Box<dyn Query>>> {
+        let mut clauses = Vec::new();
+        for (occur, queries) in &[
+            (Occur::Must, must),
+            (Occur::Should, should),
+            (Occur::MustNot, must_not),
+        ] {
+            for query in queries.iter() {
+                if let Some(compiled) = self.compile(query)? {
+                    clauses.push((*occur, compiled));
+                }
+            }
+        }
+
+        if clauses.is_empty() {
+            return Ok(None);
+        }
+
+        // Nothing matches a query made of exclusions only
+        if clauses.iter().all(|(occur, _)| *occur == Occur::MustNot) {
+            clauses.push((Occur::Must, Box::new(AllQuery)));
+        }
+
+        Ok(Some(Box::new(BooleanQuery::from(clauses))))
+    }
+
+    fn compile_text(&self, clause: &TextClause, is_phrase: bool) -> Result<Option<Box<dyn Query>>> {
+        if let Some(name) = &clause.field {
+            if !FieldNameValidator::check(self, name) {
+                return Err(unknown_field(name));
+            }
+        }
+
+        let mut queries = self.expanded_queries(&RawQuery {
+            input: clause.value.as_str(),
+            is_phrase,
+            field_name: clause.field.as_deref(),
+            occur: Occur::Should,
+            boost: clause.boost,
+            fuzzy: clause.fuzzy,
+            group: None,
+        });
+
+        Ok(match queries.len() {
+            0 => None,
+            1 => queries.pop(),
+            _ => Some(Box::new(BooleanQuery::from(
+                queries
+                    .into_iter()
+                    .map(|query| (Occur::Should, query))
+                    .collect::<Vec<_>>(),
+            ))),
+        })
+    }
+
+    fn compile_range(&self, clause: &RangeClause) -> Result<Box<dyn Query>> {
+        let field = self
+            .schema
+            .get_field(&clause.field)
+            .ok_or_else(|| unknown_field(&clause.field))?;
+
+        let lower = match (&clause.gt, &clause.gte) {
+            (Some(_), Some(_)) => return Err(invalid_range(clause, "both gt and gte are set")),
+            (Some(value), None) => Bound::Excluded(value),
+            (None, Some(value)) => Bound::Included(value),
+            (None, None) => Bound::Unbounded,
+        };
+
+        let upper = match (&clause.lt, &clause.lte) {
+            (Some(_), Some(_)) => return Err(invalid_range(clause, "both lt and lte are set")),
+            (Some(value), None) => Bound::Excluded(value),
+            (None, Some(value)) => Bound::Included(value),
+            (None, None) => Bound::Unbounded,
+        };
+
+        let mismatch = || invalid_range(clause, "bounds don't fit the field type");
+        let now = self.now();
+        // Strings are dates for integer fields, like in the string syntax
+        let date = |bound, side| range::date_bound(bound, now, side);
+        let signed_date =
+            |bound, side| convert_date(date(bound, side)?, |ts| i64::try_from(ts).ok());
+
+        let query = match self.schema.get_field_entry(field).field_type() {
+            FieldType::U64(_) => RangeQuery::new_u64_bounds(
+                field,
+                convert_or_date(lower, RangeValue::as_u64, |b| date(b, Side::Lower))
+                    .ok_or_else(mismatch)?,
+                convert_or_date(upper, RangeValue::as_u64, |b| date(b, Side::Upper))
+                    .ok_or_else(mismatch)?,
+            ),
+            FieldType::I64(_) => RangeQuery::new_i64_bounds(
+                field,
+                convert_or_date(lower, RangeValue::as_i64, |b| signed_date(b, Side::Lower))
+                    .ok_or_else(mismatch)?,
+                convert_or_date(upper, RangeValue::as_i64, |b| signed_date(b, Side::Upper))
+                    .ok_or_else(mismatch)?,
+            ),
+            FieldType::F64(_) => RangeQuery::new_f64_bounds(
+                field,
+                convert(lower, RangeValue::as_f64).ok_or_else(mismatch)?,
+                convert(upper, RangeValue::as_f64).ok_or_else(mismatch)?,
+            ),
+            FieldType::Str(_) => RangeQuery::new_str_bounds(
+                field,
+                convert(lower, RangeValue::as_str).ok_or_else(mismatch)?,
+                convert(upper, RangeValue::as_str).ok_or_else(mismatch)?,
+            ),
+            _ => return Err(invalid_range(clause, "unsupported field type")),
+        };
+
+        Ok(Box::new(query))
+    }
+}
+
+// None when the bound value can't be converted
+fn convert<'a, T, F>(bound: Bound<&'a RangeValue>, conversion: F) -> Option<Bound<T>>
+where
+    F: Fn(&'a RangeValue) -> Option<T>,
+{
+    Some(match bound {
+        Bound::Included(value) => Bound::Included(conversion(value)?),
+        Bound::Excluded(value) => Bound::Excluded(conversion(value)?),
+        Bound::Unbounded => Bound::Unbounded,
+    })
+}
+
+// Like `convert`, but string bounds go through `date`
+fn convert_or_date<'a, T, F, D>(
+    bound: Bound<&'a RangeValue>,
+    conversion: F,
+    date: D,
+) -> Option<Bound<T>>
+where
+    F: Fn(&'a RangeValue) -> Option<T>,
+    D: Fn(Bound<&'a str>) -> Option<Bound<T>>,
+{
+    match bound {
+        Bound::Included(RangeValue::Str(value)) => date(Bound::Included(value.as_str())),
+        Bound::Excluded(RangeValue::Str(value)) => date(Bound::Excluded(value.as_str())),
+        bound => convert(bound, conversion),
+    }
+}
+
+fn convert_date<T, F: Fn(u64) -> Option<T>>(bound: Bound<u64>, conversion: F) -> Option<Bound<T>> {
+    Some(match bound {
+        Bound::Included(value) => Bound::Included(conversion(value)?),
+        Bound::Excluded(value) => Bound::Excluded(conversion(value)?),
+        Bound::Unbounded => Bound::Unbounded,
+    })
+}
+
+fn unknown_field(name: &str) -> TantivyError {
+    TantivyError::SchemaError(format!("Unknown field: {}", name))
+}
+
+fn invalid_range(clause: &RangeClause, reason: &str) -> TantivyError {
+    TantivyError::InvalidArgument(format!("Invalid range on {}: {}", clause.field, reason))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use tantivy::{
+        collector::TopDocs,
+        doc,
+        schema::{SchemaBuilder, INDEXED, TEXT},
+        DocAddress, Index,
+    };
+
+    fn structured(input: &str) -> StructuredQuery {
+        serde_json::from_str(input).expect("valid structured query")
+    }
+
+    #[test]
+    fn deserializes_from_json() {
+        assert_eq!(
+            StructuredQuery::Bool {
+                must: vec![StructuredQuery::Term(TextClause {
+                    value: "bacon".to_owned(),
+                    field: None,
+                    boost: Some(2.0),
+                    fuzzy: None,
+                })],
+                should: Vec::new(),
+                must_not: vec![StructuredQuery::Range(RangeClause {
+                    field: "calories".to_owned(),
+                    gt: None,
+                    gte: Some(RangeValue::U64(500)),
+                    lt: Some(RangeValue::F64(800.5)),
+                    lte: None,
+                })],
+            },
+            structured(
+                r#"{ "bool": {
+                    "must": [{ "term": { "value": "bacon", "boost": 2 } }],
+                    "must_not": [{ "range": { "field": "calories", "gte": 500, "lt": 800.5 } }]
+                } }"#
+            )
+        );
+
+        assert!(serde_json::from_str::<StructuredQuery>(r#"{ "wildcard": {} }"#).is_err());
+        assert!(
+            serde_json::from_str::<StructuredQuery>(r#"{ "term": { "valeu": "typo" } }"#).is_err()
+        );
+    }
+
+    #[test]
+    fn index_integration() -> Result<()> {
+        let mut builder = SchemaBuilder::new();
+        let title = builder.add_text_field("title", TEXT);
+        let plot = builder.add_text_field("plot", TEXT);
+        let year = builder.add_u64_field("year", INDEXED);
+        let index = Index::create_in_ram(builder.build());
+        let mut writer = index.writer_with_num_threads(1, 3_000_000)?;
+
+        writer.add_document(doc!(
+            title => "Across the Universe",
+            plot => "A musical love story set to the songs of The Beatles",
+            year => 2007u64
+        ));
+        writer.add_document(doc!(
+            title => "Moulin Rouge!",
+            plot => "A poet falls for a courtesan in this stylish musical",
+            year => 2001u64
+        ));
+        writer.add_document(doc!(
+            title => "Once",
+            plot => "A busker and an immigrant write songs and fall in love",
+            year => 2007u64
+        ));
+        writer.commit()?;
+
+        let searcher = index.reader()?.searcher();
+        let parser = QueryParser::new(&index, vec![title, plot])?;
+
+        let search = |input: &str| -> Result<Vec<u32>> {
+            let query = parser
+                .compile(&structured(input))?
+                .expect("something to search");
+            let mut found = searcher
+                .search(&query, &TopDocs::with_limit(3))?
+                .into_iter()
+                .map(|(_score, DocAddress(_segment, doc))| doc)
+                .collect::<Vec<_>>();
+            found.sort();
+            Ok(found)
+        };
+
+        assert_eq!(vec![0, 1], search(r#"{ "term": { "value": "musical" } }"#)?);
+        assert_eq!(
+            vec![0],
+            search(r#"{ "phrase": { "value": "love story", "field": "plot" } }"#)?
+        );
+        assert_eq!(
+            vec![0, 2],
+            search(r#"{ "range": { "field": "year", "gte": 2005 } }"#)?
+        );
+        assert_eq!(
+            vec![2],
+            search(
+                r#"{ "bool": {
+                    "must": [{ "term": { "value": "songs" } }],
+                    "must_not": [{ "term": { "value": "musical" } }]
+                } }"#
+            )?
+        );
+        assert_eq!(
+            vec![1],
+            search(
+                r#"{ "bool": { "must_not": [{ "range": { "field": "year", "gt": 2001 } }] } }"#
+            )?
+        );
+        assert_eq!(
+            vec![0, 1, 2],
+            search(
+                r#"{ "bool": { "should": [
+                    { "term": { "value": "poet" } },
+                    { "term": { "value": "immigrnt", "fuzzy": 1 } },
+                    { "term": { "value": "universe", "field": "title" } }
+                ] } }"#
+            )?
+        );
+
+        // Nothing to search for
+        assert!(parser.compile(&structured(r#"{ "bool": {} }"#))?.is_none());
+
+        for invalid in &[
+            r#"{ "term": { "value": "songs", "field": "director" } }"#,
+            r#"{ "range": { "field": "director", "gt": 1 } }"#,
+            r#"{ "range": { "field": "year", "gt": "recent" } }"#,
+            r#"{ "range": { "field": "year", "gt": -1 } }"#,
+            r#"{ "range": { "field": "year", "gt": 1, "gte": 2 } }"#,
+        ] {
+            assert!(parser.compile(&structured(invalid)).is_err(), "{}", invalid);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn date_ranges() -> Result<()> {
+        let mut builder = SchemaBuilder::new();
+        let title = builder.add_text_field("title", TEXT);
+        let added = builder.add_i64_field("added", INDEXED);
+        let index = Index::create_in_ram(builder.build());
+        let mut writer = index.writer_with_num_threads(1, 3_000_000)?;
+
+        // 2023-01-15 and 2023-03-01
+        writer.add_document(doc!(title => "pancakes", added => 1_673_740_800i64));
+        writer.add_document(doc!(title => "waffles", added => 1_677_628_800i64));
+        writer.commit()?;
+
+        let searcher = index.reader()?.searcher();
+        let mut parser = QueryParser::new(&index, vec![title])?;
+        // 2023-03-02
+        parser.set_now(Some(1_677_715_200));
+
+        let count = |input: &str| -> Result<usize> {
+            let query = parser
+                .compile(&structured(input))?
+                .expect("something to search");
+            searcher.search(&query, &tantivy::collector::Count)
+        };
+
+        assert_eq!(
+            1,
+            count(r#"{ "range": { "field": "added", "gte": "now-1w" } }"#)?
+        );
+        assert_eq!(
+            1,
+            count(r#"{ "range": { "field": "added", "lte": "2023-01" } }"#)?
+        );
+        assert_eq!(
+            2,
+            count(r#"{ "range": { "field": "added", "gt": 1600000000, "lt": "now" } }"#)?
+        );
+
+        Ok(())
+    }
+}
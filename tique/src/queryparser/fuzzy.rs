@@ -0,0 +1,281 @@
+use std::{collections::HashMap, str};
+
+use tantivy::{
+    query::{BooleanQuery, Query, Weight},
+    Result, Searcher, Term,
+};
+
+/// How many terms a fuzzy term expands into at most, unless told
+/// otherwise via `FuzzyPrefixQuery::with_max_expansions`
+pub const DEFAULT_MAX_EXPANSIONS: usize = 50;
+
+/// The shortest prefix a `FuzzyPrefixQuery` takes: without one, a
+/// short term may expand into a good chunk of the term dictionary
+pub const MIN_PREFIX_LENGTH: usize = 1;
+
+/// A fuzzy query that requires the first `prefix_length` characters
+/// to match exactly.
+///
+/// tantivy's `FuzzyTermQuery` has no support for a fixed prefix, so
+/// this one walks the term dictionary of each segment starting at
+/// the prefix and expands into the terms that are close enough:
+/// the closest `max_expansions` of them, the most frequent first
+/// when as close.
+///
+/// Used by `QueryParser` for fuzzy terms, but works just as well on
+/// its own:
+///
+/// ```no_run
+/// # use tantivy::{schema::Field, Term};
+/// # use tique::FuzzyPrefixQuery;
+/// # let name = Field::from_field_id(0);
+/// // Finds "garlic", but not "barlic"
+/// let query = FuzzyPrefixQuery::new(Term::from_field_text(name, "garlc"), 1, 1, true)
+///     .with_max_expansions(10);
+/// ```
+#[derive(Debug, Clone)]
+pub struct FuzzyPrefixQuery {
+    term: Term,
+    distance: u8,
+    prefix_length: usize,
+    transposition_cost_one: bool,
+    max_expansions: usize,
+}
+
+impl FuzzyPrefixQuery {
+    /// Matches the terms within `distance` edits of `term` that
+    /// share its first `prefix_length` characters. Prefixes shorter
+    /// than `MIN_PREFIX_LENGTH` are taken as that long
+    pub fn new(
+        term: Term,
+        distance: u8,
+        prefix_length: usize,
+        transposition_cost_one: bool,
+    ) -> Self {
+        Self {
+            term,
+            distance,
+            prefix_length: prefix_length.max(MIN_PREFIX_LENGTH),
+            transposition_cost_one,
+            max_expansions: DEFAULT_MAX_EXPANSIONS,
+        }
+    }
+
+    /// Expands into no more than `max_expansions` terms
+    pub fn with_max_expansions(self, max_expansions: usize) -> Self {
+        Self {
+            max_expansions,
+            ..self
+        }
+    }
+
+    fn expand(&self, searcher: &Searcher) -> Vec<Term> {
+        let field = self.term.field();
+        let text = self.term.text();
+
+        let prefix = text
+            .char_indices()
+            .nth(self.prefix_length)
+            .map_or(text, |(idx, _)| &text[..idx]);
+        let suffix = text[prefix.len()..].chars().collect::<Vec<_>>();
+
+        // Candidate suffix => (distance, doc freq over every segment)
+        let mut found: HashMap<String, (usize, u32)> = HashMap::new();
+        for reader in searcher.segment_readers() {
+            let inverted_index = reader.inverted_index(field);
+            let mut stream = inverted_index
+                .terms()
+                .range()
+                .ge(prefix.as_bytes())
+                .into_stream();
+
+            while stream.advance() {
+                let key = stream.key();
+                if !key.starts_with(prefix.as_bytes()) {
+                    break;
+                }
+
+                if let Ok(candidate) = str::from_utf8(&key[prefix.len()..]) {
+                    if let Some(distance) = distance_within(
+                        &suffix,
+                        candidate,
+                        self.distance,
+                        self.transposition_cost_one,
+                    ) {
+                        let entry = found.entry(candidate.to_owned()).or_insert((distance, 0));
+                        entry.1 += stream.value().doc_freq;
+                    }
+                }
+            }
+        }
+
+        let mut closest: Vec<_> = found.into_iter().collect();
+        closest.sort_by(
+            |(term, (distance, doc_freq)), (other, (other_distance, other_freq))| {
+                distance
+                    .cmp(other_distance)
+                    .then(other_freq.cmp(doc_freq))
+                    .then_with(|| term.cmp(other))
+            },
+        );
+
+        closest
+            .into_iter()
+            .take(self.max_expansions)
+            .map(|(candidate, _)| Term::from_field_text(field, &[prefix, &candidate].concat()))
+            .collect()
+    }
+}
+
+impl Query for FuzzyPrefixQuery {
+    fn weight(&self, searcher: &Searcher, scoring_enabled: bool) -> Result<Box<dyn Weight>> {
+        BooleanQuery::new_multiterms_query(self.expand(searcher)).weight(searcher, scoring_enabled)
+    }
+}
+
+// Optimal string alignment distance, if it's within `max`. Gives up
+// as soon as every path is more than `max` edits away
+fn distance_within(
+    source: &[char],
+    target: &str,
+    max: u8,
+    transposition_cost_one: bool,
+) -> Option<usize> {
+    let target = target.chars().collect::<Vec<_>>();
+    let max = usize::from(max);
+
+    let len_diff = if source.len() > target.len() {
+        source.len() - target.len()
+    } else {
+        target.len() - source.len()
+    };
+
+    if len_diff > max {
+        return None;
+    }
+
+    let width = target.len() + 1;
+    let mut rows = vec![vec![0usize; width]; source.len() + 1];
+
+    for (j, cell) in rows[0].iter_mut().enumerate() {
+        *cell = j;
+    }
+
+    for i in 1..=source.len() {
+        rows[i][0] = i;
+        let mut row_min = i;
+
+        for j in 1..width {
+            let cost = if source[i - 1] == target[j - 1] { 0 } else { 1 };
+
+            let mut value = (rows[i - 1][j] + 1)
+                .min(rows[i][j - 1] + 1)
+                .min(rows[i - 1][j - 1] + cost);
+
+            if transposition_cost_one
+                && i > 1
+                && j > 1
+                && source[i - 1] == target[j - 2]
+                && source[i - 2] == target[j - 1]
+            {
+                value = value.min(rows[i - 2][j - 2] + 1);
+            }
+
+            rows[i][j] = value;
+            row_min = row_min.min(value);
+        }
+
+        if row_min > max {
+            return None;
+        }
+    }
+
+    Some(rows[source.len()][target.len()]).filter(|&distance| distance <= max)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use tantivy::{
+        doc,
+        schema::{SchemaBuilder, TEXT},
+        Index,
+    };
+
+    fn expanded(query: &FuzzyPrefixQuery, searcher: &Searcher) -> Vec<String> {
+        query
+            .expand(searcher)
+            .iter()
+            .map(|term| term.text().to_owned())
+            .collect()
+    }
+
+    #[test]
+    fn expansions_are_capped_to_the_closest() -> Result<()> {
+        let mut builder = SchemaBuilder::new();
+        let field = builder.add_text_field("field", TEXT);
+        let index = Index::create_in_ram(builder.build());
+        let mut writer = index.writer_with_num_threads(1, 3_000_000)?;
+
+        writer.add_document(doc!(field => "gaelic garl"));
+        writer.add_document(doc!(field => "garlic garl"));
+        writer.add_document(doc!(field => "barlic"));
+        writer.commit()?;
+
+        let searcher = index.reader()?.searcher();
+        let term = Term::from_field_text(field, "garlc");
+
+        let query = FuzzyPrefixQuery::new(term.clone(), 2, 1, true);
+        assert_eq!(
+            vec!["garl", "garlic", "gaelic"],
+            expanded(&query, &searcher)
+        );
+
+        // As close, but "garl" is more frequent
+        let query = query.with_max_expansions(2);
+        assert_eq!(vec!["garl", "garlic"], expanded(&query, &searcher));
+
+        // Without a prefix, "garlic" would be a single edit away
+        let query = FuzzyPrefixQuery::new(Term::from_field_text(field, "barlic"), 1, 0, true);
+        assert_eq!(vec!["barlic"], expanded(&query, &searcher));
+
+        Ok(())
+    }
+
+    fn check(source: &str, target: &str, max: u8, transposition_cost_one: bool) -> bool {
+        distance_within(
+            &source.chars().collect::<Vec<_>>(),
+            target,
+            max,
+            transposition_cost_one,
+        )
+        .is_some()
+    }
+
+    #[test]
+    fn distance_check() {
+        assert!(check("garlic", "garlic", 0, true));
+        assert!(!check("garlc", "garlic", 0, true));
+        assert!(check("garlc", "garlic", 1, true));
+        assert!(check("", "a", 1, true));
+        assert!(!check("", "ab", 1, true));
+        assert!(check("tomatoe", "tomato", 1, true));
+        assert!(!check("potatoe", "tomato", 1, true));
+        assert!(check("potatoe", "tomato", 3, true));
+    }
+
+    #[test]
+    fn transpositions() {
+        assert!(check("grailc", "garlic", 2, true));
+        assert!(check("agrlic", "garlic", 1, true));
+        assert!(!check("agrlic", "garlic", 1, false));
+        assert!(check("agrlic", "garlic", 2, false));
+    }
+
+    #[test]
+    fn multibyte_chars() {
+        assert!(check("crème", "creme", 1, true));
+        assert!(!check("crème", "cream", 1, true));
+    }
+}
@@ -0,0 +1,243 @@
+use std::collections::BTreeSet;
+
+use tantivy::{
+    query::{EmptyScorer, Explanation, Query, Scorer, Weight},
+    DocId, DocSet, Result, Score, Searcher, SegmentReader, TantivyError, Term, TERMINATED,
+};
+
+/// A disjunction that only matches documents matched by at least
+/// `minimum` of its sub-queries, scored as the sum of their scores.
+///
+/// tantivy's `BooleanQuery` has no notion of a minimum number of
+/// "should" clauses, so this one steps through the union of every
+/// sub-query and skips the documents too few of them are on.
+#[derive(Debug)]
+pub(crate) struct MinShouldMatchQuery {
+    queries: Vec<Box<dyn Query>>,
+    minimum: usize,
+}
+
+impl MinShouldMatchQuery {
+    pub fn new(queries: Vec<Box<dyn Query>>, minimum: usize) -> Self {
+        Self { queries, minimum }
+    }
+}
+
+impl Clone for MinShouldMatchQuery {
+    fn clone(&self) -> Self {
+        Self {
+            queries: self.queries.iter().map(|q| q.box_clone()).collect(),
+            minimum: self.minimum,
+        }
+    }
+}
+
+impl Query for MinShouldMatchQuery {
+    fn weight(&self, searcher: &Searcher, scoring_enabled: bool) -> Result<Box<dyn Weight>> {
+        Ok(Box::new(MinShouldMatchWeight {
+            weights: self
+                .queries
+                .iter()
+                .map(|q| q.weight(searcher, scoring_enabled))
+                .collect::<Result<Vec<_>>>()?,
+            minimum: self.minimum,
+        }))
+    }
+
+    fn query_terms(&self, term_set: &mut BTreeSet<Term>) {
+        for query in self.queries.iter() {
+            query.query_terms(term_set);
+        }
+    }
+}
+
+struct MinShouldMatchWeight {
+    weights: Vec<Box<dyn Weight>>,
+    minimum: usize,
+}
+
+impl Weight for MinShouldMatchWeight {
+    fn scorer(&self, reader: &SegmentReader, boost: f32) -> Result<Box<dyn Scorer>> {
+        if self.weights.len() < self.minimum {
+            return Ok(Box::new(EmptyScorer));
+        }
+
+        Ok(Box::new(MinShouldMatchScorer::new(
+            self.weights
+                .iter()
+                .map(|w| w.scorer(reader, boost))
+                .collect::<Result<Vec<_>>>()?,
+            self.minimum,
+        )))
+    }
+
+    fn explain(&self, reader: &SegmentReader, doc: DocId) -> Result<Explanation> {
+        let mut scorer = self.scorer(reader, 1.0)?;
+
+        if scorer.doc() > doc || scorer.seek(doc) != doc {
+            return Err(TantivyError::InvalidArgument("Not a match".to_owned()));
+        }
+
+        let mut explanation = Explanation::new(
+            format!("MinShouldMatchQuery. At least {} matches", self.minimum),
+            scorer.score(),
+        );
+
+        for weight in &self.weights {
+            if let Ok(sub_explanation) = weight.explain(reader, doc) {
+                explanation.add_detail(sub_explanation);
+            }
+        }
+
+        Ok(explanation)
+    }
+}
+
+struct MinShouldMatchScorer {
+    scorers: Vec<Box<dyn Scorer>>,
+    minimum: usize,
+    current: DocId,
+}
+
+impl MinShouldMatchScorer {
+    fn new(scorers: Vec<Box<dyn Scorer>>, minimum: usize) -> Self {
+        let mut scorer = Self {
+            scorers,
+            minimum,
+            current: TERMINATED,
+        };
+        scorer.current = scorer.next_match();
+        scorer
+    }
+
+    // The first document at least `minimum` scorers are on, moving
+    // the ones that are on a document with too few matches along
+    fn next_match(&mut self) -> DocId {
+        loop {
+            self.scorers.retain(|scorer| scorer.doc() != TERMINATED);
+            if self.scorers.len() < self.minimum {
+                return TERMINATED;
+            }
+
+            let candidate = self
+                .scorers
+                .iter()
+                .map(|scorer| scorer.doc())
+                .min()
+                .unwrap_or(TERMINATED);
+
+            let num_matches = self
+                .scorers
+                .iter()
+                .filter(|scorer| scorer.doc() == candidate)
+                .count();
+
+            if num_matches >= self.minimum {
+                return candidate;
+            }
+
+            for scorer in self.scorers.iter_mut() {
+                if scorer.doc() == candidate {
+                    scorer.advance();
+                }
+            }
+        }
+    }
+}
+
+impl Scorer for MinShouldMatchScorer {
+    fn score(&mut self) -> Score {
+        let current = self.current;
+        self.scorers
+            .iter_mut()
+            .filter(|scorer| scorer.doc() == current)
+            .map(|scorer| scorer.score())
+            .sum()
+    }
+}
+
+impl DocSet for MinShouldMatchScorer {
+    fn advance(&mut self) -> DocId {
+        if self.current == TERMINATED {
+            return TERMINATED;
+        }
+
+        for scorer in self.scorers.iter_mut() {
+            if scorer.doc() == self.current {
+                scorer.advance();
+            }
+        }
+
+        self.current = self.next_match();
+        self.current
+    }
+
+    fn doc(&self) -> DocId {
+        self.current
+    }
+
+    fn size_hint(&self) -> u32 {
+        self.scorers
+            .iter()
+            .map(|scorer| scorer.size_hint())
+            .max()
+            .unwrap_or(0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use tantivy::{
+        collector::{Count, TopDocs},
+        doc,
+        query::TermQuery,
+        schema::{IndexRecordOption, SchemaBuilder, TEXT},
+        DocAddress, Index,
+    };
+
+    #[test]
+    fn requires_the_minimum_number_of_matches() -> Result<()> {
+        let mut builder = SchemaBuilder::new();
+        let field = builder.add_text_field("field", TEXT);
+        let index = Index::create_in_ram(builder.build());
+        let mut writer = index.writer_with_num_threads(1, 3_000_000)?;
+
+        writer.add_document(doc!(field => "a"));
+        writer.add_document(doc!(field => "a b"));
+        writer.add_document(doc!(field => "b c"));
+        writer.add_document(doc!(field => "a b c"));
+        writer.add_document(doc!(field => "c"));
+        writer.commit()?;
+
+        let reader = index.reader()?;
+        let searcher = reader.searcher();
+
+        let query = |minimum| {
+            MinShouldMatchQuery::new(
+                ["a", "b", "c"]
+                    .iter()
+                    .map(|text| {
+                        Box::new(TermQuery::new(
+                            Term::from_field_text(field, text),
+                            IndexRecordOption::WithFreqs,
+                        )) as Box<dyn Query>
+                    })
+                    .collect(),
+                minimum,
+            )
+        };
+
+        assert_eq!(5, searcher.search(&query(1), &Count)?);
+        assert_eq!(3, searcher.search(&query(2), &Count)?);
+        assert_eq!(1, searcher.search(&query(3), &Count)?);
+        assert_eq!(0, searcher.search(&query(4), &Count)?);
+
+        // More matches, higher scores
+        let top = searcher.search(&query(2), &TopDocs::with_limit(3))?;
+        assert_eq!(DocAddress(0, 3), top[0].1);
+
+        Ok(())
+    }
+}
@@ -4,9 +4,10 @@
 //! end-users, with no knowledge about IR, your index nor boolean
 //! logic.
 //!
-//! Supports multiple fields, boosts, required (+) and restricted (-)
-//! items and can generate queries using `DisMaxQuery` for better
-//! results when you have fields with very similar vocabularies.
+//! Supports multiple fields, boosts, fuzzy and wildcard terms, synonyms, required (+)
+//! and restricted (-) items, a minimum number of optional items to match and can
+//! generate queries using `DisMaxQuery` for better results when you have fields
+//! with very similar vocabularies.
 //!
 //! **NOTE**: Requires the `queryparser` compilation feature.
 //!
@@ -24,7 +25,22 @@
 //! # Ok(())
 //! # }
 //! ```
+mod ast;
+#[cfg(feature = "querydsl")]
+mod dsl;
+mod fuzzy;
+mod minmatch;
 mod parser;
+mod range;
 mod raw;
+mod synonyms;
+mod wildcard;
 
-pub use parser::QueryParser;
+pub use ast::{Ast, AstItem};
+pub use fuzzy::FuzzyPrefixQuery;
+pub use parser::{Fuzziness, MinimumShouldMatch, ParserField, QueryParser};
+pub use raw::SyntaxIssue;
+pub use synonyms::{SynonymMap, SynonymProvider};
+
+#[cfg(feature = "querydsl")]
+pub use dsl::{RangeClause, RangeValue, StructuredQuery, TextClause};
@@ -1,10 +1,20 @@
-use super::raw::{parse_query, FieldNameValidator, RawQuery};
+use std::{str::FromStr, sync::Arc};
+
+use super::{
+    ast::{Ast, AstItem},
+    fuzzy::{FuzzyPrefixQuery, DEFAULT_MAX_EXPANSIONS, MIN_PREFIX_LENGTH},
+    minmatch::MinShouldMatchQuery,
+    range::{self, parse_range},
+    raw::{find_issues, parse_query, regex_pattern, FieldNameValidator, RawQuery, SyntaxIssue},
+    synonyms::SynonymProvider,
+    wildcard,
+};
 use crate::DisMaxQuery;
 
 use tantivy::{
     self,
-    query::{AllQuery, BooleanQuery, BoostQuery, Occur, PhraseQuery, Query, TermQuery},
-    schema::{Field, IndexRecordOption},
+    query::{AllQuery, BooleanQuery, BoostQuery, Occur, PhraseQuery, Query, RegexQuery, TermQuery},
+    schema::{Field, IndexRecordOption, Schema},
     tokenizer::TextAnalyzer,
     Index, Result, Term,
 };
@@ -33,11 +43,187 @@ use tantivy::{
 /// Which ends up prohibiting documents with "egg" in the "ingredients"
 /// field from showing up.
 ///
+/// Items can also be made more (or less) important by suffixing
+/// them with `^` and a boost factor:
+///
+/// > name:garlic^3 "olive oil"^0.5
+///
+/// The item boost is combined with the field boost configured
+/// via `QueryParser::set_boost`.
+///
+/// Misspellings can be tolerated by suffixing a term with `~` and
+/// the maximum number of edits allowed:
+///
+/// > garlc~1 tomatoe~
+///
+/// Where `term~` is the same as `term~1`. Check `Fuzziness` to learn
+/// how to tune fuzzy matching or to make every term fuzzy by default.
+///
+/// Terms with wildcards match every term they could stand for:
+///
+/// > garl* ga?lic
+///
+/// `*` is any number of characters and `?` a single one (but not at
+/// the end of a term, where it's taken as punctuation). Wildcard terms
+/// skip the field analyzer, being only lowercased, and need at least
+/// a couple of characters before the first wildcard so that they don't
+/// scan the whole term dictionary. See `set_min_wildcard_prefix`.
+///
+/// Regular expressions, enabled via `set_max_regex_length`, match
+/// the indexed terms of a field:
+///
+/// > name:/ca[kc]e/
+///
+/// They're meant for power users and data cleanup: unlike anything
+/// else, they see terms exactly as indexed (likely lowercased) and
+/// a loose pattern can go through every term of a field.
+///
+/// When configured with a `SynonymProvider` via `set_synonyms`, every
+/// term or phrase also matches its synonyms.
+///
+/// Numeric fields registered via `add_range_field` can be restricted
+/// with ranges instead:
+///
+/// > calories:<500 added:>=now-30d added:2023-01..2023-06
+///
+/// Bounds are numbers or, for integer fields, dates in seconds since
+/// the epoch: date math relative to the time of the query (`now`,
+/// `now-30d`, `now-1w+2h`, with `s`, `m`, `h`, `d` and `w` units)
+/// or UTC dates (`2023`, `2023-06`, `2023-06-15` or
+/// `2023-06-15T10:30:00`). Dates stand for their whole span, so the
+/// last example above covers everything from January to the end of
+/// June. Bare integers are always taken as numbers.
+///
+/// Items can be combined with `AND` and `OR` (uppercase, with spaces
+/// around them) and grouped within parentheses:
+///
+/// > (chicken OR turkey) AND -fried
+///
+/// `AND` binds tighter than `OR`, which binds tighter than the spaces
+/// between items: `a OR b AND c` is `a OR (b AND c)` and `a b AND c`
+/// is `a +b +c`. Both sides of an `AND` are required unless
+/// prohibited, and a group is an item like any other, so it can be
+/// required, prohibited or boosted: `-(deep fry)^2`.
+///
+/// Like the rest of the syntax, this is lenient: a group that isn't
+/// closed ends with the input, while an operator missing a side and a
+/// closing parenthesis without a group are taken as terms.
+///
+/// Long queries made of optional items alone match plenty of documents
+/// that contain a single one of them. `set_minimum_should_match` makes
+/// a share of them required instead.
+///
+#[derive(Clone)]
 pub struct QueryParser {
     state: Vec<(Option<String>, Option<f32>, Interpreter)>,
     default_indices: Vec<usize>,
+    fuzziness: Fuzziness,
+    synonyms: Option<Arc<dyn SynonymProvider>>,
+    range_fields: Vec<(String, Field)>,
+    now: Option<u64>,
+    min_wildcard_prefix: Option<usize>,
+    max_regex_length: Option<usize>,
+    minimum_should_match: Option<MinimumShouldMatch>,
+    // For the fields structured queries can refer to besides ours
+    pub(super) schema: Schema,
+}
+
+/// Controls how fuzzy terms are matched
+///
+/// Phrases are never fuzzy, only plain terms are.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Fuzziness {
+    /// The edit distance used for terms without an explicit `~`.
+    /// Zero (the default) means that only terms with the `~`
+    /// suffix are fuzzy.
+    pub distance: u8,
+    /// How many leading characters must match exactly. Besides
+    /// cutting down on nonsense matches, a longer prefix makes
+    /// expanding the fuzzy term considerably cheaper. Never less
+    /// than one (the default): see `FuzzyPrefixQuery`
+    pub prefix_length: usize,
+    /// Wether swapping two adjacent characters counts as a single
+    /// edit instead of two
+    pub transposition_cost_one: bool,
+    /// How many terms a fuzzy term may expand into. The closest ones
+    /// are kept
+    pub max_expansions: usize,
+}
+
+impl Default for Fuzziness {
+    fn default() -> Self {
+        Self {
+            distance: 0,
+            prefix_length: MIN_PREFIX_LENGTH,
+            transposition_cost_one: true,
+            max_expansions: DEFAULT_MAX_EXPANSIONS,
+        }
+    }
+}
+
+/// How many of the optional items of a query a document must match.
+/// See `QueryParser::set_minimum_should_match`
+///
+/// Parses from strings like `"3"` and `"75%"`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MinimumShouldMatch {
+    /// At least this many, or all of them when there are fewer
+    Count(usize),
+    /// At least this percentage (from 0 to 100) of them, rounded down
+    Percent(u8),
+}
+
+impl MinimumShouldMatch {
+    /// How many of `num_optional` items must match
+    pub fn required(&self, num_optional: usize) -> usize {
+        match *self {
+            MinimumShouldMatch::Count(count) => count.min(num_optional),
+            MinimumShouldMatch::Percent(percent) => {
+                num_optional * usize::from(percent.min(100)) / 100
+            }
+        }
+    }
 }
 
+impl FromStr for MinimumShouldMatch {
+    type Err = String;
+
+    fn from_str(input: &str) -> std::result::Result<Self, Self::Err> {
+        let input = input.trim();
+        let invalid = || format!("Invalid minimum should match: {}", input);
+
+        if let Some(percent) = input.strip_suffix('%') {
+            match percent.trim_end().parse::<u8>() {
+                Ok(percent) if percent <= 100 => Ok(MinimumShouldMatch::Percent(percent)),
+                _ => Err(invalid()),
+            }
+        } else {
+            input
+                .parse::<usize>()
+                .map(MinimumShouldMatch::Count)
+                .map_err(|_| invalid())
+        }
+    }
+}
+
+/// How a `QueryParser` treats one of its fields. See
+/// `QueryParser::fields`
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParserField {
+    pub field: Field,
+    /// The name queries use to refer to the field, if they can. See
+    /// `QueryParser::set_name`
+    pub name: Option<String>,
+    pub boost: Option<f32>,
+    /// Whether items without a field name are looked up in it
+    pub default: bool,
+}
+
+// Past this, fuzzy terms match about anything
+const MAX_FUZZY_DISTANCE: u8 = 2;
+
+const DEFAULT_MIN_WILDCARD_PREFIX: usize = 2;
+
 impl QueryParser {
     /// Create a QueryParser that knows about the given fields and queries
     /// them by default.
@@ -52,6 +238,14 @@ impl QueryParser {
         let mut parser = QueryParser {
             default_indices: (0..fields.len()).collect(),
             state: Vec::with_capacity(fields.len()),
+            fuzziness: Fuzziness::default(),
+            synonyms: None,
+            range_fields: Vec::new(),
+            now: None,
+            min_wildcard_prefix: Some(DEFAULT_MIN_WILDCARD_PREFIX),
+            max_regex_length: None,
+            minimum_should_match: None,
+            schema: schema.clone(),
         };
 
         for field in fields {
@@ -99,6 +293,25 @@ impl QueryParser {
         }
     }
 
+    /// Change how the input is analyzed for a specific field
+    ///
+    /// By default, every field uses the tokenizer it's indexed with,
+    /// as registered in the index when the parser was created. Setting
+    /// a different one makes sense when that tokenizer shouldn't run
+    /// at query time as is: a field indexed with edge ngrams for
+    /// search-as-you-type wants the input taken as a single lowercase
+    /// token, not split into ngrams itself. Either way, the analyzer
+    /// must emit tokens the same way they were indexed, or nothing
+    /// matches.
+    pub fn set_analyzer(&mut self, field: Field, analyzer: TextAnalyzer) {
+        if let Some(row) = self
+            .position_by_field(field)
+            .and_then(|pos| self.state.get_mut(pos))
+        {
+            row.2.analyzer = analyzer;
+        }
+    }
+
     /// Configure which fields are queried by default
     ///
     /// When a query input doesn't specify a field name explicitly, the
@@ -116,6 +329,86 @@ impl QueryParser {
         self.default_indices = indices;
     }
 
+    /// Configure fuzzy matching
+    ///
+    /// Edit distances greater than 2 are treated as 2.
+    pub fn set_fuzziness(&mut self, fuzziness: Fuzziness) {
+        self.fuzziness = fuzziness;
+    }
+
+    /// Configure where to look up synonyms
+    ///
+    /// Each parsed item becomes a group that matches the item itself
+    /// or any of its synonyms. Multi-word synonyms are searched as
+    /// phrases. Prohibited items (`-term`) exclude their synonyms too.
+    pub fn set_synonyms(&mut self, synonyms: Option<Arc<dyn SynonymProvider>>) {
+        self.synonyms = synonyms;
+    }
+
+    /// Lets queries restrict `field` (a numeric field, `INDEXED`) by
+    /// range, as in `name:>value` or `name:low..high`
+    ///
+    /// Ranges are only recognized for fields added this way, under
+    /// the given `name`, which replaces any previous one.
+    pub fn add_range_field(&mut self, name: &str, field: Field) {
+        self.range_fields.retain(|(existing, _)| existing != name);
+        self.range_fields.push((name.to_owned(), field));
+    }
+
+    /// Pins what `now` means in date math, in seconds since the epoch
+    ///
+    /// By default it's the time each query is interpreted at.
+    pub fn set_now(&mut self, now: Option<u64>) {
+        self.now = now;
+    }
+
+    /// How many characters must come before the first wildcard of
+    /// a term for it to be taken as one. Otherwise (and with `None`)
+    /// wildcards are taken literally, as part of the term. Defaults
+    /// to 2: with shorter prefixes a single term might expand into
+    /// a good chunk of the index
+    pub fn set_min_wildcard_prefix(&mut self, length: Option<usize>) {
+        self.min_wildcard_prefix = length;
+    }
+
+    /// Enables `/regex/` terms, with patterns of up to `length`
+    /// characters. Disabled (`None`) by default, so that slashes are
+    /// taken literally
+    ///
+    /// Patterns that are too long or invalid make for no query at
+    /// all. Besides the length, tantivy limits how big a pattern may
+    /// get once compiled
+    pub fn set_max_regex_length(&mut self, length: Option<usize>) {
+        self.max_regex_length = length;
+    }
+
+    /// Requires documents to match some of the optional items of a
+    /// query, not just any one of them. `None` (the default) leaves
+    /// them all optional
+    ///
+    /// Only the items at the top level of the input count: groups
+    /// and the alternatives of each item (its fields and synonyms)
+    /// are left alone. Since spaces already mean "or", an input made
+    /// of `OR`s alone, like `a OR b OR c`, counts as three items
+    pub fn set_minimum_should_match(&mut self, minimum: Option<MinimumShouldMatch>) {
+        self.minimum_should_match = minimum;
+    }
+
+    /// Every field the parser knows about, in the order they were
+    /// given when creating it
+    pub fn fields(&self) -> Vec<ParserField> {
+        self.state
+            .iter()
+            .enumerate()
+            .map(|(idx, (name, boost, interpreter))| ParserField {
+                field: interpreter.field,
+                name: name.clone(),
+                boost: *boost,
+                default: self.default_indices.contains(&idx),
+            })
+            .collect()
+    }
+
     /// Parse arbitrary user input into a tantivy query
     ///
     /// `None` may happen when the input is empty or the field analyzers end up
@@ -132,6 +425,13 @@ impl QueryParser {
         })
     }
 
+    /// Lists the parts of `input` that aren't valid syntax, which
+    /// `parse` takes literally instead of rejecting. Useful for
+    /// telling users what went wrong when they do want to know
+    pub fn check(&self, input: &str) -> Vec<SyntaxIssue> {
+        find_issues(input, self)
+    }
+
     /// Parse a query, taking multiple fields with similar vocabularies into
     /// account.
     ///
@@ -155,19 +455,49 @@ impl QueryParser {
         })
     }
 
+    /// Parse user input without interpreting it, so that the query
+    /// can be inspected or rewritten before turning it into a tantivy
+    /// query via `Ast::to_query`
+    ///
+    /// Items are split exactly like `parse` does, so a `field:` prefix
+    /// is only recognized for the fields this parser knows by name.
+    pub fn parse_ast(&self, input: &str) -> Ast {
+        let items = parse_query(input, self)
+            .map(|(_, parsed)| parsed.into_iter().map(AstItem::from).collect())
+            .unwrap_or_default();
+        Ast { items }
+    }
+
     fn parse_inner<F: Fn(Vec<Box<dyn Query>>) -> Box<dyn Query>>(
         &self,
         input: &str,
-        // Guaranteed to receive a vec of len > 1 if called
         many_handler: F,
     ) -> Option<Box<dyn Query>> {
         let (_, parsed) = parse_query(input, self).ok()?;
+        self.interpret(&parsed, &many_handler)
+    }
+
+    pub(super) fn interpret<F: Fn(Vec<Box<dyn Query>>) -> Box<dyn Query>>(
+        &self,
+        parsed: &[RawQuery],
+        // Guaranteed to receive a vec of len > 1 if called
+        many_handler: &F,
+    ) -> Option<Box<dyn Query>> {
+        self.interpret_items(parsed, many_handler, self.minimum_should_match)
+    }
+
+    fn interpret_items<F: Fn(Vec<Box<dyn Query>>) -> Box<dyn Query>>(
+        &self,
+        parsed: &[RawQuery],
+        many_handler: &F,
+        minimum_should_match: Option<MinimumShouldMatch>,
+    ) -> Option<Box<dyn Query>> {
         let mut clauses = Vec::new();
         let mut num_must_not = 0;
 
         parsed
-            .into_iter()
-            .map(|raw| (self.queries_from_raw(&raw), raw))
+            .iter()
+            .map(|raw| (self.item_queries(raw, many_handler), raw))
             .filter(|(queries, _)| !queries.is_empty())
             .for_each(|(queries, raw)| {
                 if raw.occur == Occur::MustNot {
@@ -184,6 +514,10 @@ impl QueryParser {
                 }
             });
 
+        if let Some(minimum_should_match) = minimum_should_match {
+            clauses = require_optional(clauses, minimum_should_match);
+        }
+
         match clauses.len() {
             0 => None,
             1 => {
@@ -207,6 +541,53 @@ impl QueryParser {
         }
     }
 
+    fn item_queries<F: Fn(Vec<Box<dyn Query>>) -> Box<dyn Query>>(
+        &self,
+        raw_query: &RawQuery,
+        many_handler: &F,
+    ) -> Vec<Box<dyn Query>> {
+        let items = match &raw_query.group {
+            Some(items) => items,
+            None => return self.expanded_queries(raw_query),
+        };
+
+        self.interpret_items(items, many_handler, None)
+            .map(|query| match raw_query.boost {
+                Some(boost) => Box::new(BoostQuery::new(query, boost)) as Box<dyn Query>,
+                None => query,
+            })
+            .into_iter()
+            .collect()
+    }
+
+    pub(super) fn expanded_queries(&self, raw_query: &RawQuery) -> Vec<Box<dyn Query>> {
+        if let Some(field) = raw_query
+            .field_name
+            .and_then(|field_name| self.range_field(field_name))
+        {
+            return self.range_query(field, raw_query).into_iter().collect();
+        }
+
+        let mut queries = self.queries_from_raw(raw_query);
+
+        if let Some(synonyms) = self
+            .synonyms
+            .as_ref()
+            .and_then(|provider| provider.synonyms(raw_query.input))
+        {
+            for synonym in synonyms {
+                queries.extend(self.queries_from_raw(&RawQuery {
+                    input: synonym,
+                    is_phrase: synonym.contains(char::is_whitespace),
+                    group: None,
+                    ..*raw_query
+                }));
+            }
+        }
+
+        queries
+    }
+
     fn queries_from_raw(&self, raw_query: &RawQuery) -> Vec<Box<dyn Query>> {
         let indices = if let Some(position) = raw_query
             .field_name
@@ -217,13 +598,40 @@ impl QueryParser {
             self.default_indices.clone()
         };
 
+        // Regexes and wildcard terms skip the analyzers
+        let pattern = match self.regex_pattern(raw_query) {
+            Some(pattern) => {
+                if self
+                    .max_regex_length
+                    .map_or(true, |max| pattern.chars().count() > max)
+                {
+                    return Vec::new();
+                }
+                Some(pattern.to_owned())
+            }
+            None => self
+                .min_wildcard_prefix
+                .filter(|_| !raw_query.is_phrase)
+                .and_then(|min_prefix| wildcard::to_regex(raw_query.input, min_prefix)),
+        };
+
         indices
             .into_iter()
             .flat_map(|i| self.state.get(i))
-            .flat_map(|(_, boost, interpreter)| {
-                interpreter.to_query(raw_query).map(|query| {
+            .flat_map(|(_, field_boost, interpreter)| {
+                let boost = match (*field_boost, raw_query.boost) {
+                    (Some(field), Some(item)) => Some(field * item),
+                    (field, item) => field.or(item),
+                };
+
+                let query = match &pattern {
+                    Some(pattern) => interpreter.to_regex_query(pattern),
+                    None => interpreter.to_query(raw_query, &self.fuzziness),
+                };
+
+                query.map(|query| {
                     if let Some(val) = boost {
-                        Box::new(BoostQuery::new(query, *val))
+                        Box::new(BoostQuery::new(query, val))
                     } else {
                         query
                     }
@@ -232,6 +640,38 @@ impl QueryParser {
             .collect()
     }
 
+    fn regex_pattern<'a>(&self, raw_query: &RawQuery<'a>) -> Option<&'a str> {
+        if raw_query.is_phrase || self.max_regex_length.is_none() {
+            None
+        } else {
+            regex_pattern(raw_query.input)
+        }
+    }
+
+    // Bounds that don't fit the field make for no query at all
+    fn range_query(&self, field: Field, raw_query: &RawQuery) -> Option<Box<dyn Query>> {
+        let (lower, upper) = parse_range(raw_query.input)?;
+        let now = self.now();
+        let query: Box<dyn Query> =
+            Box::new(range::range_query(&self.schema, field, lower, upper, now)?);
+
+        Some(match raw_query.boost {
+            Some(boost) => Box::new(BoostQuery::new(query, boost)),
+            None => query,
+        })
+    }
+
+    pub(super) fn now(&self) -> u64 {
+        self.now.unwrap_or_else(range::now)
+    }
+
+    fn range_field(&self, field_name: &str) -> Option<Field> {
+        self.range_fields
+            .iter()
+            .find(|(name, _field)| name == field_name)
+            .map(|(_name, field)| *field)
+    }
+
     fn position_by_name(&self, field_name: &str) -> Option<usize> {
         self.state
             .iter()
@@ -249,21 +689,83 @@ impl QueryParser {
 
 impl FieldNameValidator for QueryParser {
     fn check(&self, field_name: &str) -> bool {
-        self.state
-            .iter()
-            .any(|(opt_name, _opt_boost, _interpreter)| {
-                opt_name.as_ref().map_or(false, |name| name == field_name)
+        self.is_range_field(field_name)
+            || self
+                .state
+                .iter()
+                .any(|(opt_name, _opt_boost, _interpreter)| {
+                    opt_name.as_ref().map_or(false, |name| name == field_name)
+                })
+    }
+
+    fn is_range_field(&self, field_name: &str) -> bool {
+        self.range_field(field_name).is_some()
+    }
+
+    fn min_wildcard_prefix(&self) -> Option<usize> {
+        self.min_wildcard_prefix
+    }
+
+    fn max_regex_length(&self) -> Option<usize> {
+        self.max_regex_length
+    }
+}
+
+// Makes `minimum_should_match` of the optional clauses required
+fn require_optional(
+    clauses: Vec<(Occur, Box<dyn Query>)>,
+    minimum_should_match: MinimumShouldMatch,
+) -> Vec<(Occur, Box<dyn Query>)> {
+    let num_optional = clauses
+        .iter()
+        .filter(|(occur, _)| *occur == Occur::Should)
+        .count();
+    let required = minimum_should_match.required(num_optional);
+
+    // Without required clauses, one of the optional ones must match
+    // anyway
+    let has_required = clauses.iter().any(|(occur, _)| *occur == Occur::Must);
+    if required == 0 || (required == 1 && !has_required) {
+        return clauses;
+    }
+
+    if required == num_optional {
+        return clauses
+            .into_iter()
+            .map(|(occur, query)| match occur {
+                Occur::Should => (Occur::Must, query),
+                occur => (occur, query),
             })
+            .collect();
     }
+
+    let (optional, mut others): (Vec<_>, Vec<_>) = clauses
+        .into_iter()
+        .partition(|(occur, _)| *occur == Occur::Should);
+
+    others.push((
+        Occur::Must,
+        Box::new(MinShouldMatchQuery::new(
+            optional.into_iter().map(|(_, query)| query).collect(),
+            required,
+        )),
+    ));
+    others
 }
 
+#[derive(Clone)]
 struct Interpreter {
     field: Field,
     analyzer: TextAnalyzer,
 }
 
 impl Interpreter {
-    fn to_query(&self, raw_query: &RawQuery) -> Option<Box<dyn Query>> {
+    fn to_regex_query(&self, pattern: &str) -> Option<Box<dyn Query>> {
+        let query = RegexQuery::from_pattern(pattern, self.field).ok()?;
+        Some(Box::new(query))
+    }
+
+    fn to_query(&self, raw_query: &RawQuery, fuzziness: &Fuzziness) -> Option<Box<dyn Query>> {
         let mut terms = Vec::new();
         let mut stream = self.analyzer.token_stream(raw_query.input);
 
@@ -275,7 +777,28 @@ impl Interpreter {
             return None;
         }
 
-        let query: Box<dyn Query> = if terms.len() == 1 {
+        let distance = raw_query
+            .fuzzy
+            .unwrap_or(fuzziness.distance)
+            .min(MAX_FUZZY_DISTANCE);
+
+        let query: Box<dyn Query> = if distance > 0 && !raw_query.is_phrase {
+            let mut fuzzy_queries = terms
+                .into_iter()
+                .map(|term| fuzzy_query(term, distance, fuzziness))
+                .collect::<Vec<_>>();
+
+            if fuzzy_queries.len() == 1 {
+                fuzzy_queries.pop().unwrap()
+            } else {
+                Box::new(BooleanQuery::from(
+                    fuzzy_queries
+                        .into_iter()
+                        .map(|q| (Occur::Should, q))
+                        .collect::<Vec<_>>(),
+                ))
+            }
+        } else if terms.len() == 1 {
             Box::new(TermQuery::new(
                 terms.pop().unwrap(),
                 IndexRecordOption::WithFreqs,
@@ -293,10 +816,23 @@ impl Interpreter {
     }
 }
 
+fn fuzzy_query(term: Term, distance: u8, fuzziness: &Fuzziness) -> Box<dyn Query> {
+    Box::new(
+        FuzzyPrefixQuery::new(
+            term,
+            distance,
+            fuzziness.prefix_length,
+            fuzziness.transposition_cost_one,
+        )
+        .with_max_expansions(fuzziness.max_expansions),
+    )
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    use crate::queryparser::synonyms::SynonymMap;
     use tantivy::tokenizer::TokenizerManager;
 
     fn test_interpreter() -> Interpreter {
@@ -308,13 +844,15 @@ mod tests {
 
     #[test]
     fn empty_raw_is_none() {
-        assert!(test_interpreter().to_query(&RawQuery::new("")).is_none());
+        assert!(test_interpreter()
+            .to_query(&RawQuery::new(""), &Fuzziness::default())
+            .is_none());
     }
 
     #[test]
     fn simple_raw_is_termquery() {
         let query = test_interpreter()
-            .to_query(&RawQuery::new("word"))
+            .to_query(&RawQuery::new("word"), &Fuzziness::default())
             .expect("parses to a Some(Query)");
 
         assert!(query.as_any().downcast_ref::<TermQuery>().is_some());
@@ -323,7 +861,10 @@ mod tests {
     #[test]
     fn phrase_raw_is_phrasequery() {
         let query = test_interpreter()
-            .to_query(&RawQuery::new("sweet potato").phrase())
+            .to_query(
+                &RawQuery::new("sweet potato").phrase(),
+                &Fuzziness::default(),
+            )
             .expect("parses to a Some(Query)");
 
         assert!(query.as_any().downcast_ref::<PhraseQuery>().is_some());
@@ -332,14 +873,59 @@ mod tests {
     #[test]
     fn single_word_raw_phrase_is_termquery() {
         let query = test_interpreter()
-            .to_query(&RawQuery::new("single").phrase())
+            .to_query(&RawQuery::new("single").phrase(), &Fuzziness::default())
             .expect("parses to a Some(Query)");
 
         assert!(query.as_any().downcast_ref::<TermQuery>().is_some());
     }
 
+    #[test]
+    fn fuzzy_raw_is_fuzzyquery() {
+        let fuzzy = RawQuery::new("garlc").with_fuzzy(1);
+        let query = test_interpreter()
+            .to_query(&fuzzy, &Fuzziness::default())
+            .expect("parses to a Some(Query)");
+
+        assert!(query.as_any().downcast_ref::<FuzzyPrefixQuery>().is_some());
+
+        let query = test_interpreter()
+            .to_query(
+                &fuzzy,
+                &Fuzziness {
+                    prefix_length: 2,
+                    ..Fuzziness::default()
+                },
+            )
+            .expect("parses to a Some(Query)");
+
+        assert!(query.as_any().downcast_ref::<FuzzyPrefixQuery>().is_some());
+    }
+
+    #[test]
+    fn fuzzy_phrase_is_not_fuzzy() {
+        let query = test_interpreter()
+            .to_query(
+                &RawQuery::new("sweet potato").phrase(),
+                &Fuzziness {
+                    distance: 1,
+                    ..Fuzziness::default()
+                },
+            )
+            .expect("parses to a Some(Query)");
+
+        assert!(query.as_any().downcast_ref::<PhraseQuery>().is_some());
+    }
+
     fn single_field_test_parser() -> QueryParser {
         QueryParser {
+            fuzziness: Fuzziness::default(),
+            synonyms: None,
+            range_fields: Vec::new(),
+            now: None,
+            min_wildcard_prefix: None,
+            max_regex_length: None,
+            minimum_should_match: None,
+            schema: SchemaBuilder::new().build(),
             default_indices: vec![0],
             state: vec![(
                 None,
@@ -360,7 +946,8 @@ mod tests {
     use tantivy::{
         collector::TopDocs,
         doc,
-        schema::{SchemaBuilder, TEXT},
+        schema::{SchemaBuilder, TextFieldIndexing, TextOptions, INDEXED, TEXT},
+        tokenizer::{LowerCaser, NgramTokenizer, RawTokenizer},
         DocAddress,
     };
 
@@ -408,6 +995,24 @@ mod tests {
                 .expect("working index")
         };
 
+        assert_eq!(
+            vec![
+                ParserField {
+                    field: title,
+                    name: Some("title".to_owned()),
+                    boost: None,
+                    default: true,
+                },
+                ParserField {
+                    field: plot,
+                    name: Some("plot".to_owned()),
+                    boost: None,
+                    default: true,
+                },
+            ],
+            parser.fields()
+        );
+
         let found = search("+title:Once musical", 2);
         // Even if "musical" matches every document,
         // there's a MUST query that only one matches
@@ -428,6 +1033,34 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn fields_reflect_the_configuration() -> Result<()> {
+        let mut builder = SchemaBuilder::new();
+        let title = builder.add_text_field("title", TEXT);
+        let plot = builder.add_text_field("plot", TEXT);
+        let index = Index::create_in_ram(builder.build());
+
+        let mut parser = QueryParser::new(&index, vec![title, plot])?;
+        parser.set_name(title, Some("t".to_owned()));
+        parser.set_name(plot, None);
+        parser.set_boost(plot, Some(0.5));
+        parser.set_default_fields(vec![plot]);
+
+        let fields = parser.fields();
+        assert_eq!(2, fields.len());
+
+        assert_eq!(title, fields[0].field);
+        assert_eq!(Some("t".to_owned()), fields[0].name);
+        assert!(!fields[0].default);
+
+        assert_eq!(plot, fields[1].field);
+        assert_eq!(None, fields[1].name);
+        assert_eq!(Some(0.5), fields[1].boost);
+        assert!(fields[1].default);
+
+        Ok(())
+    }
+
     #[test]
     fn field_boosting() -> Result<()> {
         let mut builder = SchemaBuilder::new();
@@ -477,4 +1110,441 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn fuzzy_matching() -> Result<()> {
+        let mut builder = SchemaBuilder::new();
+        let field = builder.add_text_field("field", TEXT);
+        let index = Index::create_in_ram(builder.build());
+        let mut writer = index.writer_with_num_threads(1, 3_000_000)?;
+
+        writer.add_document(doc!(field => "garlic"));
+        writer.add_document(doc!(field => "onion"));
+        writer.commit()?;
+
+        let mut parser = QueryParser::new(&index, vec![field])?;
+
+        let reader = index.reader()?;
+        let searcher = reader.searcher();
+
+        let count = |parser: &QueryParser, input| {
+            let query = parser.parse(input).expect("given input yields Some()");
+            searcher
+                .search(&query, &tantivy::collector::Count)
+                .expect("working index")
+        };
+
+        assert_eq!(0, count(&parser, "garlc"));
+        assert_eq!(1, count(&parser, "garlc~"));
+        assert_eq!(0, count(&parser, "grlc~1"));
+        assert_eq!(1, count(&parser, "grlc~2"));
+        assert_eq!(2, count(&parser, "garlc~ onoin~"));
+
+        parser.set_fuzziness(Fuzziness {
+            distance: 1,
+            prefix_length: 2,
+            ..Fuzziness::default()
+        });
+
+        assert_eq!(1, count(&parser, "garlc"));
+        assert_eq!(1, count(&parser, "onoin"));
+        // Within distance, but the prefix doesn't match
+        assert_eq!(0, count(&parser, "barlic"));
+        // Explicit distance wins over the configured default
+        assert_eq!(0, count(&parser, "garlc~0"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn item_boosting() -> Result<()> {
+        let mut builder = SchemaBuilder::new();
+        let field = builder.add_text_field("field", TEXT);
+        let index = Index::create_in_ram(builder.build());
+        let mut writer = index.writer_with_num_threads(1, 3_000_000)?;
+
+        writer.add_document(doc!(field => "foo"));
+        writer.add_document(doc!(field => "bar"));
+        writer.commit()?;
+
+        let parser = QueryParser::new(&index, vec![field])?;
+
+        let reader = index.reader()?;
+        let searcher = reader.searcher();
+
+        let search = |input| {
+            let query = parser.parse(input).expect("given input yields Some()");
+            searcher
+                .search(&query, &TopDocs::with_limit(1))
+                .expect("working index")
+        };
+
+        assert_eq!(DocAddress(0, 0), search("foo^2 bar")[0].1);
+        assert_eq!(DocAddress(0, 1), search("foo bar^2")[0].1);
+        assert_eq!(DocAddress(0, 1), search("field:foo^0.1 bar")[0].1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn synonym_expansion() -> Result<()> {
+        let mut builder = SchemaBuilder::new();
+        let field = builder.add_text_field("field", TEXT);
+        let index = Index::create_in_ram(builder.build());
+        let mut writer = index.writer_with_num_threads(1, 3_000_000)?;
+
+        writer.add_document(doc!(field => "grilled eggplant"));
+        writer.add_document(doc!(field => "aubergine parmigiana"));
+        writer.add_document(doc!(field => "spring onion pancakes"));
+        writer.add_document(doc!(field => "onion rings"));
+        writer.commit()?;
+
+        let mut parser = QueryParser::new(&index, vec![field])?;
+
+        let reader = index.reader()?;
+        let searcher = reader.searcher();
+
+        let count = |parser: &QueryParser, input| {
+            let query = parser.parse(input).expect("given input yields Some()");
+            searcher
+                .search(&query, &tantivy::collector::Count)
+                .expect("working index")
+        };
+
+        assert_eq!(1, count(&parser, "aubergine"));
+        assert_eq!(1, count(&parser, "scallion"));
+
+        parser.set_synonyms(Some(Arc::new(SynonymMap::parse(
+            "aubergine, eggplant\nscallion => spring onion",
+        ))));
+
+        assert_eq!(2, count(&parser, "aubergine"));
+        assert_eq!(2, count(&parser, "Eggplant"));
+        // Multi-word synonyms are phrases, so "onion rings" is left out
+        assert_eq!(1, count(&parser, "scallion"));
+        assert_eq!(1, count(&parser, "+grilled +aubergine"));
+        // Prohibiting a term prohibits its synonyms
+        assert_eq!(2, count(&parser, "-eggplant"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn boolean_operators() -> Result<()> {
+        let mut builder = SchemaBuilder::new();
+        let field = builder.add_text_field("field", TEXT);
+        let index = Index::create_in_ram(builder.build());
+        let mut writer = index.writer_with_num_threads(1, 3_000_000)?;
+
+        writer.add_document(doc!(field => "chicken soup"));
+        writer.add_document(doc!(field => "fried chicken"));
+        writer.add_document(doc!(field => "turkey sandwich"));
+        writer.add_document(doc!(field => "fried turkey"));
+        writer.add_document(doc!(field => "fried tofu"));
+        writer.commit()?;
+
+        let parser = QueryParser::new(&index, vec![field])?;
+        let reader = index.reader()?;
+        let searcher = reader.searcher();
+
+        let count = |input| {
+            let query = parser.parse(input).expect("given input yields Some()");
+            searcher
+                .search(&query, &tantivy::collector::Count)
+                .expect("working index")
+        };
+
+        assert_eq!(4, count("chicken OR turkey"));
+        assert_eq!(2, count("(chicken OR turkey) AND -fried"));
+        assert_eq!(1, count("-(chicken OR turkey)"));
+        // AND binds tighter
+        assert_eq!(2, count("fried AND chicken OR tofu"));
+        assert_eq!(2, count("fried AND (chicken OR tofu)"));
+        assert_eq!(3, count("soup OR fried AND turkey OR sandwich"));
+        assert_eq!(1, count("(soup OR sandwich) AND chicken"));
+
+        // Unclosed groups end with the input
+        assert_eq!(2, count("-fried AND (chicken OR turkey"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn wildcard_terms() -> Result<()> {
+        let mut builder = SchemaBuilder::new();
+        let field = builder.add_text_field("field", TEXT);
+        let index = Index::create_in_ram(builder.build());
+        let mut writer = index.writer_with_num_threads(1, 3_000_000)?;
+
+        writer.add_document(doc!(field => "garlic bread"));
+        writer.add_document(doc!(field => "a garland of herbs"));
+        writer.add_document(doc!(field => "ginger ale"));
+        writer.commit()?;
+
+        let mut parser = QueryParser::new(&index, vec![field])?;
+        let reader = index.reader()?;
+        let searcher = reader.searcher();
+
+        let count = |parser: &QueryParser, input| {
+            parser.parse(input).map_or(0, |query| {
+                searcher
+                    .search(&query, &tantivy::collector::Count)
+                    .expect("working index")
+            })
+        };
+
+        assert_eq!(2, count(&parser, "garl*"));
+        assert_eq!(2, count(&parser, "GARL*"));
+        assert_eq!(1, count(&parser, "ga?lic"));
+        assert_eq!(1, count(&parser, "field:gar*c^2"));
+        assert_eq!(3, count(&parser, "garl* OR gin*"));
+        // Phrases and trailing question marks are taken literally
+        assert_eq!(0, count(&parser, "\"garl*\""));
+        assert_eq!(1, count(&parser, "bread?"));
+
+        // Prefixes that are too short are not wildcards
+        assert_eq!(0, count(&parser, "g*"));
+        assert_eq!(
+            vec![SyntaxIssue {
+                position: 0,
+                message: "Wildcard prefix too short",
+            }],
+            parser.check("g* garl*")
+        );
+
+        parser.set_min_wildcard_prefix(Some(1));
+        assert_eq!(3, count(&parser, "g*"));
+        assert!(parser.check("g* garl*").is_empty());
+
+        parser.set_min_wildcard_prefix(None);
+        assert_eq!(0, count(&parser, "garl*"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn per_field_analyzers() -> Result<()> {
+        let mut builder = SchemaBuilder::new();
+        let prefixes = builder.add_text_field(
+            "prefixes",
+            TextOptions::default().set_indexing_options(
+                TextFieldIndexing::default()
+                    .set_tokenizer("prefixes")
+                    .set_index_option(IndexRecordOption::Basic),
+            ),
+        );
+        let index = Index::create_in_ram(builder.build());
+        index.tokenizers().register(
+            "prefixes",
+            TextAnalyzer::from(NgramTokenizer::new(2, 5, true)).filter(LowerCaser),
+        );
+        let mut writer = index.writer_with_num_threads(1, 3_000_000)?;
+
+        writer.add_document(doc!(prefixes => "Garlic bread"));
+        writer.add_document(doc!(prefixes => "Gas stove"));
+        writer.commit()?;
+
+        let mut parser = QueryParser::new(&index, vec![prefixes])?;
+        let reader = index.reader()?;
+        let searcher = reader.searcher();
+
+        let count = |parser: &QueryParser, input| {
+            parser.parse(input).map_or(0, |query| {
+                searcher
+                    .search(&query, &tantivy::collector::Count)
+                    .expect("working index")
+            })
+        };
+
+        // The index tokenizer turns the input into ngrams too, and
+        // "ga" is enough of a match
+        assert_eq!(2, count(&parser, "garx"));
+
+        parser.set_analyzer(
+            prefixes,
+            TextAnalyzer::from(RawTokenizer).filter(LowerCaser),
+        );
+        assert_eq!(0, count(&parser, "garx"));
+        assert_eq!(1, count(&parser, "Garl"));
+        assert_eq!(1, count(&parser, "gas"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn minimum_should_match() -> Result<()> {
+        let mut builder = SchemaBuilder::new();
+        let field = builder.add_text_field("field", TEXT);
+        let index = Index::create_in_ram(builder.build());
+        let mut writer = index.writer_with_num_threads(1, 3_000_000)?;
+
+        writer.add_document(doc!(field => "easy quick chicken dinner"));
+        writer.add_document(doc!(field => "chicken"));
+        writer.add_document(doc!(field => "quick rice"));
+        writer.add_document(doc!(field => "easy chicken rice"));
+        writer.commit()?;
+
+        let mut parser = QueryParser::new(&index, vec![field])?;
+        let reader = index.reader()?;
+        let searcher = reader.searcher();
+
+        let count = |parser: &QueryParser, input| {
+            parser.parse(input).map_or(0, |query| {
+                searcher
+                    .search(&query, &tantivy::collector::Count)
+                    .expect("working index")
+            })
+        };
+
+        assert_eq!(4, count(&parser, "easy quick chicken rice"));
+
+        parser.set_minimum_should_match(Some(MinimumShouldMatch::Count(2)));
+        assert_eq!(3, count(&parser, "easy quick chicken rice"));
+        // Prohibited items don't count
+        assert_eq!(2, count(&parser, "easy quick chicken rice -dinner"));
+        // Nor do the items within groups
+        assert_eq!(4, count(&parser, "(easy quick chicken rice)"));
+
+        parser.set_minimum_should_match(Some(MinimumShouldMatch::Percent(75)));
+        assert_eq!(2, count(&parser, "easy quick chicken rice"));
+        // Half of two optional items, on top of the required one
+        parser.set_minimum_should_match(Some(MinimumShouldMatch::Percent(50)));
+        assert_eq!(2, count(&parser, "+chicken easy quick"));
+
+        // Every item, when there aren't as many
+        parser.set_minimum_should_match(Some(MinimumShouldMatch::Count(10)));
+        assert_eq!(1, count(&parser, "easy chicken rice"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn minimum_should_match_from_str() {
+        assert_eq!(Ok(MinimumShouldMatch::Count(3)), "3".parse());
+        assert_eq!(Ok(MinimumShouldMatch::Percent(75)), " 75 %".parse());
+        assert!("150%".parse::<MinimumShouldMatch>().is_err());
+        assert!("-1".parse::<MinimumShouldMatch>().is_err());
+        assert!("most".parse::<MinimumShouldMatch>().is_err());
+
+        assert_eq!(5, MinimumShouldMatch::Percent(75).required(7));
+        assert_eq!(0, MinimumShouldMatch::Percent(40).required(2));
+        assert_eq!(2, MinimumShouldMatch::Count(3).required(2));
+    }
+
+    #[test]
+    fn regex_terms() -> Result<()> {
+        let mut builder = SchemaBuilder::new();
+        let field = builder.add_text_field("field", TEXT);
+        let index = Index::create_in_ram(builder.build());
+        let mut writer = index.writer_with_num_threads(1, 3_000_000)?;
+
+        writer.add_document(doc!(field => "carrot cake"));
+        writer.add_document(doc!(field => "cace"));
+        writer.add_document(doc!(field => "coke float"));
+        writer.commit()?;
+
+        let mut parser = QueryParser::new(&index, vec![field])?;
+        let reader = index.reader()?;
+        let searcher = reader.searcher();
+
+        let count = |parser: &QueryParser, input| {
+            parser.parse(input).map_or(0, |query| {
+                searcher
+                    .search(&query, &tantivy::collector::Count)
+                    .expect("working index")
+            })
+        };
+
+        // Disabled by default: slashes are just punctuation
+        assert_eq!(1, count(&parser, "/cake/"));
+        assert_eq!(0, count(&parser, "/ca[kc]e/"));
+        assert!(parser.check("/ca[/").is_empty());
+
+        parser.set_max_regex_length(Some(10));
+        assert_eq!(2, count(&parser, "/ca[kc]e/"));
+        assert_eq!(2, count(&parser, "field:/c.ke/^2"));
+        assert_eq!(3, count(&parser, "/ca[kc]e/ OR float"));
+        assert_eq!(0, count(&parser, "\"/cake/\""));
+        // Indexed terms are lowercase
+        assert_eq!(0, count(&parser, "/CAKE/"));
+        // Too long
+        assert_eq!(0, count(&parser, "/c.*e.*e.*e.*/"));
+
+        assert_eq!(
+            vec![
+                SyntaxIssue {
+                    position: 0,
+                    message: "Regex too long",
+                },
+                SyntaxIssue {
+                    position: 15,
+                    message: "Invalid regex",
+                }
+            ],
+            parser.check("/c.*e.*e.*e.*/ /ca[/ /cake/")
+        );
+
+        parser.set_max_regex_length(None);
+        assert_eq!(0, count(&parser, "/ca[kc]e/"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn range_items() -> Result<()> {
+        let mut builder = SchemaBuilder::new();
+        let name = builder.add_text_field("name", TEXT);
+        let calories = builder.add_u64_field("calories", INDEXED);
+        let added = builder.add_u64_field("added", INDEXED);
+        let index = Index::create_in_ram(builder.build());
+        let mut writer = index.writer_with_num_threads(1, 3_000_000)?;
+
+        // 2023-01-15, 2023-03-01 and 2023-03-01T12:00:00
+        writer
+            .add_document(doc!(name => "pancakes", calories => 300u64, added => 1_673_740_800u64));
+        writer.add_document(doc!(name => "waffles", calories => 450u64, added => 1_677_628_800u64));
+        writer.add_document(doc!(name => "crepes", calories => 200u64, added => 1_677_672_000u64));
+        writer.commit()?;
+
+        let mut parser = QueryParser::new(&index, vec![name])?;
+        parser.add_range_field("calories", calories);
+        parser.add_range_field("added", added);
+        // 2023-03-02
+        parser.set_now(Some(1_677_715_200));
+
+        let reader = index.reader()?;
+        let searcher = reader.searcher();
+
+        let count = |input| {
+            let query = parser.parse(input).expect("given input yields Some()");
+            searcher
+                .search(&query, &tantivy::collector::Count)
+                .expect("working index")
+        };
+
+        assert_eq!(2, count("calories:<450"));
+        assert_eq!(2, count("calories:<=300"));
+        assert_eq!(2, count("calories:250..*"));
+        assert_eq!(1, count("+pancakes +calories:>100"));
+        assert_eq!(2, count("-calories:>=400"));
+
+        assert_eq!(2, count("added:2023-03..2023-03"));
+        assert_eq!(2, count("added:2023-03-01..2023-03-01"));
+        assert_eq!(1, count("added:<2023-03-01"));
+        assert_eq!(1, count("added:>2023-03-01T06:00:00"));
+        assert_eq!(2, count("added:>=now-1d"));
+        assert_eq!(3, count("added:2023-01..now"));
+
+        // Neither a range nor a term
+        assert!(parser.parse("added:>yesterday").is_none());
+        assert_eq!(
+            vec![SyntaxIssue {
+                position: 6,
+                message: "Invalid range",
+            }],
+            parser.check("added:>yesterday")
+        );
+
+        Ok(())
+    }
 }
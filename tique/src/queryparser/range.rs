@@ -0,0 +1,412 @@
+use std::{
+    convert::TryFrom,
+    ops::Bound,
+    str::FromStr,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use tantivy::{
+    query::RangeQuery,
+    schema::{Field, FieldType, Schema},
+};
+
+// What the string syntax takes for a range: `>v`, `>=v`, `<v`, `<=v`
+// or `a..b`, where `*` leaves a side of `a..b` open
+pub(super) fn parse_range(input: &str) -> Option<(Bound<&str>, Bound<&str>)> {
+    let value = |v: &str| if v.is_empty() { None } else { Some(v) };
+
+    let bounds = if let Some(rest) = input.strip_prefix(">=") {
+        (Bound::Included(value(rest)?), Bound::Unbounded)
+    } else if let Some(rest) = input.strip_prefix('>') {
+        (Bound::Excluded(value(rest)?), Bound::Unbounded)
+    } else if let Some(rest) = input.strip_prefix("<=") {
+        (Bound::Unbounded, Bound::Included(value(rest)?))
+    } else if let Some(rest) = input.strip_prefix('<') {
+        (Bound::Unbounded, Bound::Excluded(value(rest)?))
+    } else {
+        let idx = input.find("..")?;
+        let side = |v| match value(v)? {
+            "*" => Some(Bound::Unbounded),
+            v => Some(Bound::Included(v)),
+        };
+        (side(&input[..idx])?, side(&input[idx + 2..])?)
+    };
+
+    Some(bounds)
+}
+
+// Whether `input` is a range whose bounds are numbers or dates
+pub(super) fn is_valid_range(input: &str) -> bool {
+    parse_range(input).map_or(false, |(lower, upper)| {
+        [lower, upper].iter().all(|bound| match bound {
+            Bound::Included(v) | Bound::Excluded(v) => {
+                v.parse::<f64>().is_ok() || parse_date(v, now()).is_some()
+            }
+            Bound::Unbounded => true,
+        })
+    })
+}
+
+pub(super) fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs())
+}
+
+// A range over `field` from bounds as typed. Numbers are taken as
+// they are and, for integer fields, anything else as a date (in
+// seconds since the epoch). None if a bound doesn't fit the field
+pub(super) fn range_query(
+    schema: &Schema,
+    field: Field,
+    lower: Bound<&str>,
+    upper: Bound<&str>,
+    now: u64,
+) -> Option<RangeQuery> {
+    Some(match schema.get_field_entry(field).field_type() {
+        FieldType::U64(_) => RangeQuery::new_u64_bounds(
+            field,
+            number_or_date(lower, now, Side::Lower)?,
+            number_or_date(upper, now, Side::Upper)?,
+        ),
+        FieldType::I64(_) => RangeQuery::new_i64_bounds(
+            field,
+            number_or_date(lower, now, Side::Lower)?,
+            number_or_date(upper, now, Side::Upper)?,
+        ),
+        FieldType::F64(_) => {
+            RangeQuery::new_f64_bounds(field, parse_bound(lower)?, parse_bound(upper)?)
+        }
+        _ => return None,
+    })
+}
+
+fn parse_bound<T: FromStr>(bound: Bound<&str>) -> Option<Bound<T>> {
+    Some(match bound {
+        Bound::Included(value) => Bound::Included(value.parse().ok()?),
+        Bound::Excluded(value) => Bound::Excluded(value.parse().ok()?),
+        Bound::Unbounded => Bound::Unbounded,
+    })
+}
+
+#[derive(Clone, Copy)]
+pub(super) enum Side {
+    Lower,
+    Upper,
+}
+
+fn number_or_date<T: FromStr + TryFrom<u64>>(
+    bound: Bound<&str>,
+    now: u64,
+    side: Side,
+) -> Option<Bound<T>> {
+    match bound {
+        Bound::Included(value) | Bound::Excluded(value) if value.parse::<T>().is_ok() => {
+            parse_bound(bound)
+        }
+        _ => {
+            let convert = |value: u64| T::try_from(value).ok();
+            Some(match date_bound(bound, now, side)? {
+                Bound::Included(value) => Bound::Included(convert(value)?),
+                Bound::Excluded(value) => Bound::Excluded(convert(value)?),
+                Bound::Unbounded => Bound::Unbounded,
+            })
+        }
+    }
+}
+
+// Dates stand for a span of time: `2023-06` is the whole of June. A
+// bound takes as much of the span as it should, so that `2023-01..
+// 2023-06` goes from the start of January to the end of June and
+// `>2023-06` starts in July
+pub(super) fn date_bound(bound: Bound<&str>, now: u64, side: Side) -> Option<Bound<u64>> {
+    Some(match (side, bound) {
+        (_, Bound::Unbounded) => Bound::Unbounded,
+        (Side::Lower, Bound::Included(value)) => Bound::Included(parse_date(value, now)?.0),
+        (Side::Lower, Bound::Excluded(value)) => Bound::Included(parse_date(value, now)?.1),
+        (Side::Upper, Bound::Included(value)) => Bound::Excluded(parse_date(value, now)?.1),
+        (Side::Upper, Bound::Excluded(value)) => Bound::Excluded(parse_date(value, now)?.0),
+    })
+}
+
+const MINUTE: u64 = 60;
+const HOUR: u64 = 60 * MINUTE;
+const DAY: u64 = 24 * HOUR;
+const WEEK: u64 = 7 * DAY;
+
+// The span of time (in seconds since the epoch, the end excluded)
+// `input` stands for. Either date math relative to `now`, like
+// `now-30d`, or a UTC date: `2023`, `2023-06`, `2023-06-15` or
+// `2023-06-15T10:30:00`
+pub(super) fn parse_date(input: &str, now: u64) -> Option<(u64, u64)> {
+    if input
+        .get(..3)
+        .map_or(false, |start| start.eq_ignore_ascii_case("now"))
+    {
+        return date_math(&input[3..], now).map(|instant| (instant, instant + 1));
+    }
+
+    let (date, time) = match input.find(|c| c == 'T' || c == 't') {
+        Some(idx) => (&input[..idx], Some(&input[idx + 1..])),
+        None => (input, None),
+    };
+
+    let mut parts = date.splitn(3, '-');
+    let year = parts.next().filter(|y| y.len() == 4)?.parse::<i64>().ok()?;
+    let month = match parts.next() {
+        Some(m) if m.len() == 2 => Some(m.parse::<u32>().ok().filter(|m| (1..=12).contains(m))?),
+        Some(_) => return None,
+        None => None,
+    };
+    let day = match parts.next() {
+        Some(d) if d.len() == 2 => Some(d.parse::<u32>().ok()?),
+        Some(_) => return None,
+        None => None,
+    };
+
+    let seconds = |days: i64| {
+        if days >= 0 {
+            Some(days as u64 * DAY)
+        } else {
+            None
+        }
+    };
+
+    match (month, day, time) {
+        (None, None, None) => Some((
+            seconds(days_from_civil(year, 1, 1))?,
+            seconds(days_from_civil(year + 1, 1, 1))?,
+        )),
+        (Some(month), None, None) => {
+            let (next_year, next_month) = if month == 12 {
+                (year + 1, 1)
+            } else {
+                (year, month + 1)
+            };
+            Some((
+                seconds(days_from_civil(year, month, 1))?,
+                seconds(days_from_civil(next_year, next_month, 1))?,
+            ))
+        }
+        (Some(month), Some(day), time) => {
+            if day == 0 || day > days_in_month(year, month) {
+                return None;
+            }
+            let start = seconds(days_from_civil(year, month, day))?;
+            match time {
+                None => Some((start, start + DAY)),
+                Some(time) => {
+                    let instant = start + time_of_day(time)?;
+                    Some((instant, instant + 1))
+                }
+            }
+        }
+        _ => None,
+    }
+}
+
+// `HH:MM:SS`, maybe followed by a `Z`
+fn time_of_day(input: &str) -> Option<u64> {
+    let input = input
+        .strip_suffix('Z')
+        .or_else(|| input.strip_suffix('z'))
+        .unwrap_or(input);
+
+    let mut parts = input.split(':');
+    let mut component = |max: u64| {
+        parts
+            .next()
+            .filter(|part| part.len() == 2)
+            .and_then(|part| part.parse::<u64>().ok())
+            .filter(|value| *value <= max)
+    };
+    let seconds = component(23)? * HOUR + component(59)? * MINUTE + component(59)?;
+
+    if parts.next().is_some() {
+        None
+    } else {
+        Some(seconds)
+    }
+}
+
+// Any number of `+N<unit>` or `-N<unit>` steps, units being `s`,
+// `m`, `h`, `d` and `w`
+fn date_math(mut input: &str, now: u64) -> Option<u64> {
+    let mut instant = now;
+
+    while !input.is_empty() {
+        let add = match input.as_bytes()[0] {
+            b'+' => true,
+            b'-' => false,
+            _ => return None,
+        };
+
+        let digits = input[1..]
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(input.len() - 1);
+        let amount = input[1..=digits].parse::<u64>().ok()?;
+        let unit = match input[1 + digits..].chars().next()?.to_ascii_lowercase() {
+            's' => 1,
+            'm' => MINUTE,
+            'h' => HOUR,
+            'd' => DAY,
+            'w' => WEEK,
+            _ => return None,
+        };
+
+        let delta = amount.checked_mul(unit)?;
+        instant = if add {
+            instant.checked_add(delta)?
+        } else {
+            instant.checked_sub(delta)?
+        };
+        input = &input[2 + digits..];
+    }
+
+    Some(instant)
+}
+
+fn is_leap_year(year: i64) -> bool {
+    (year % 4 == 0 && year % 100 != 0) || year % 400 == 0
+}
+
+fn days_in_month(year: i64, month: u32) -> u32 {
+    match month {
+        2 if is_leap_year(year) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+// Days since 1970-01-01 of a date in the proleptic Gregorian calendar,
+// as in http://howardhinnant.github.io/date_algorithms.html
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = if year >= 0 { year } else { year - 399 } / 400;
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * i64::from((month + 9) % 12) + 2) / 5 + i64::from(day) - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 2020-10-15T12:00:00Z
+    const NOW: u64 = 1_602_763_200;
+
+    #[test]
+    fn range_syntax() {
+        use Bound::*;
+
+        assert_eq!(Some((Excluded("5"), Unbounded)), parse_range(">5"));
+        assert_eq!(Some((Included("5"), Unbounded)), parse_range(">=5"));
+        assert_eq!(Some((Unbounded, Excluded("5"))), parse_range("<5"));
+        assert_eq!(Some((Unbounded, Included("5"))), parse_range("<=5"));
+        assert_eq!(Some((Included("1"), Included("5"))), parse_range("1..5"));
+        assert_eq!(Some((Unbounded, Included("5"))), parse_range("*..5"));
+        assert_eq!(
+            Some((Excluded("now-30d"), Unbounded)),
+            parse_range(">now-30d")
+        );
+
+        for invalid in &["", ">", "<=", "5", "..5", "1..", "1.5"] {
+            assert_eq!(None, parse_range(invalid), "{}", invalid);
+        }
+
+        assert!(is_valid_range(">now-1w"));
+        assert!(is_valid_range("2023-01..2023-06"));
+        assert!(is_valid_range("<=4.5"));
+        assert!(!is_valid_range(">soon"));
+        assert!(!is_valid_range("2023-13..*"));
+    }
+
+    #[test]
+    fn dates() {
+        assert_eq!(Some((0, DAY)), parse_date("1970-01-01", NOW));
+        assert_eq!(
+            Some((NOW - NOW % DAY, NOW - NOW % DAY + DAY)),
+            parse_date("2020-10-15", NOW)
+        );
+        assert_eq!(
+            Some((NOW, NOW + 1)),
+            parse_date("2020-10-15T12:00:00Z", NOW)
+        );
+        assert_eq!(Some((NOW, NOW + 1)), parse_date("2020-10-15t12:00:00", NOW));
+
+        // 2020 is a leap year
+        let (start, end) = parse_date("2020-02", NOW).unwrap();
+        assert_eq!(29 * DAY, end - start);
+        let (start, end) = parse_date("2020", NOW).unwrap();
+        assert_eq!(366 * DAY, end - start);
+        assert_eq!(parse_date("2021-01", NOW).unwrap().0, end);
+        assert_eq!(parse_date("2020-12", NOW).unwrap().1, end);
+
+        for invalid in &[
+            "20-01-01",
+            "2020-1",
+            "2020-02-30",
+            "2020-00",
+            "2020-01-01T25:00:00",
+            "2020-01-01T10:00",
+            "1969-12-31",
+        ] {
+            assert_eq!(None, parse_date(invalid, NOW), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn date_math_is_relative_to_now() {
+        assert_eq!(Some((NOW, NOW + 1)), parse_date("now", NOW));
+        assert_eq!(Some((NOW, NOW + 1)), parse_date("NOW", NOW));
+        assert_eq!(
+            Some(NOW - 30 * DAY),
+            parse_date("now-30d", NOW).map(|s| s.0)
+        );
+        assert_eq!(
+            Some(NOW - WEEK + 2 * HOUR),
+            parse_date("now-1w+2h", NOW).map(|s| s.0)
+        );
+        assert_eq!(Some(NOW + 90), parse_date("now+90s", NOW).map(|s| s.0));
+        assert_eq!(
+            Some(NOW - 5 * MINUTE),
+            parse_date("now-5m", NOW).map(|s| s.0)
+        );
+
+        for invalid in &[
+            "now-",
+            "now-d",
+            "now-30",
+            "now-30y",
+            "now*2d",
+            "now-9999999999999w",
+        ] {
+            assert_eq!(None, parse_date(invalid, NOW), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn date_bounds_cover_the_whole_span() {
+        let june = parse_date("2023-06", NOW).unwrap();
+        let bound = |side, bound| date_bound(bound, NOW, side).unwrap();
+
+        assert_eq!(
+            Bound::Included(june.0),
+            bound(Side::Lower, Bound::Included("2023-06"))
+        );
+        assert_eq!(
+            Bound::Included(june.1),
+            bound(Side::Lower, Bound::Excluded("2023-06"))
+        );
+        assert_eq!(
+            Bound::Excluded(june.1),
+            bound(Side::Upper, Bound::Included("2023-06"))
+        );
+        assert_eq!(
+            Bound::Excluded(june.0),
+            bound(Side::Upper, Bound::Excluded("2023-06"))
+        );
+    }
+}
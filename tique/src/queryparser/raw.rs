@@ -1,14 +1,20 @@
 use nom::{
     self,
     branch::alt,
-    bytes::complete::take_while1,
-    character::complete::{char as is_char, multispace0},
-    combinator::{map, map_res},
+    bytes::complete::{tag, take_while1},
+    character::complete::{char as is_char, multispace0, multispace1},
+    combinator::{map, map_res, opt, verify},
+    error::{Error, ErrorKind},
     multi::many0,
-    sequence::{delimited, preceded, separated_pair},
+    sequence::{delimited, pair, preceded, separated_pair, terminated},
     IResult,
 };
-use tantivy::query::Occur;
+use tantivy::{
+    query::{Occur, RegexQuery},
+    schema::Field,
+};
+
+use super::{range::is_valid_range, wildcard::is_prefix_too_short};
 
 #[derive(Debug, PartialEq)]
 pub struct RawQuery<'a> {
@@ -16,9 +22,20 @@ pub struct RawQuery<'a> {
     pub is_phrase: bool,
     pub field_name: Option<&'a str>,
     pub occur: Occur,
+    pub boost: Option<f32>,
+    pub fuzzy: Option<u8>,
+    // The items of a `(group)`, or of an `AND` within an `OR`
+    pub group: Option<Vec<RawQuery<'a>>>,
 }
 
 const FIELD_SEP: char = ':';
+const BOOST_SEP: char = '^';
+const FUZZY_SEP: char = '~';
+const DEFAULT_FUZZY_DISTANCE: u8 = 1;
+const AND: &str = "AND";
+const OR: &str = "OR";
+// Deeper groups are taken literally
+const MAX_DEPTH: usize = 32;
 
 impl<'a> RawQuery<'a> {
     pub fn new(input: &'a str) -> Self {
@@ -27,6 +44,18 @@ impl<'a> RawQuery<'a> {
             is_phrase: false,
             field_name: None,
             occur: Occur::Should,
+            boost: None,
+            fuzzy: None,
+            group: None,
+        }
+    }
+
+    // Groups parsed from the input take it as their `input` (which
+    // tells whether they were closed) and the others an empty one
+    pub fn group(input: &'a str, items: Vec<RawQuery<'a>>) -> Self {
+        Self {
+            group: Some(items),
+            ..Self::new(input)
         }
     }
 
@@ -53,10 +82,39 @@ impl<'a> RawQuery<'a> {
         self.field_name = Some(name);
         self
     }
+
+    pub fn with_boost(mut self, boost: f32) -> Self {
+        debug_assert_eq!(None, self.boost);
+        self.boost = Some(boost);
+        self
+    }
+
+    pub fn with_fuzzy(mut self, distance: u8) -> Self {
+        debug_assert_eq!(None, self.fuzzy);
+        self.fuzzy = Some(distance);
+        self
+    }
 }
 
 pub trait FieldNameValidator {
     fn check(&self, field_name: &str) -> bool;
+
+    // Whether `field:value` items are ranges, like `field:>value`
+    fn is_range_field(&self, _field_name: &str) -> bool {
+        false
+    }
+
+    // How many characters a wildcard term needs before its first
+    // wildcard. None when wildcards aren't supported
+    fn min_wildcard_prefix(&self) -> Option<usize> {
+        None
+    }
+
+    // How long the pattern of a `/regex/` term may be. None when
+    // regexes aren't supported
+    fn max_regex_length(&self) -> Option<usize> {
+        None
+    }
 }
 
 impl<T> FieldNameValidator for Vec<T>
@@ -78,28 +136,292 @@ pub fn parse_query<'a, C: FieldNameValidator>(
     input: &'a str,
     validator: &'a C,
 ) -> IResult<&'a str, Vec<RawQuery<'a>>> {
-    many0(delimited(
+    parse_clauses(input, validator, 0)
+}
+
+// Clauses are separated by whitespace, which binds looser than `OR`,
+// which binds looser than `AND`. Within a group (`depth > 0`) terms
+// end at a closing parenthesis
+fn parse_clauses<'a, C: FieldNameValidator>(
+    input: &'a str,
+    validator: &'a C,
+    depth: usize,
+) -> IResult<&'a str, Vec<RawQuery<'a>>> {
+    let (rest, clauses) = many0(delimited(
         multispace0,
         alt((
-            |input| prohibited_query(input, validator),
-            |input| mandatory_query(input, validator),
-            |input| field_prefixed_query(input, validator),
-            any_field_query,
+            |input| or_clause(input, validator, depth),
+            // Operators without operands are taken literally
+            map(move |input| parse_term(input, depth), |raw| vec![vec![raw]]),
         )),
         multispace0,
+    ))(input)?;
+
+    let num_clauses = clauses.len();
+    let mut items = Vec::new();
+    for mut alternatives in clauses {
+        if alternatives.len() == 1 {
+            // `a AND b` is the same as `+a +b`
+            items.append(&mut alternatives[0]);
+        } else if num_clauses == 1 {
+            // And a lone `a OR b` the same as `a b`
+            items.extend(alternatives.into_iter().map(and_group));
+        } else {
+            let alternatives = alternatives.into_iter().map(and_group).collect();
+            items.push(RawQuery::group("", alternatives));
+        }
+    }
+
+    Ok((rest, items))
+}
+
+// The operands of each `AND` between `OR`s
+fn or_clause<'a, C: FieldNameValidator>(
+    input: &'a str,
+    validator: &'a C,
+    depth: usize,
+) -> IResult<&'a str, Vec<Vec<RawQuery<'a>>>> {
+    let and = move |input| and_clause(input, validator, depth);
+    let (rest, first) = and(input)?;
+    let (rest, mut others) = many0(preceded(|input| keyword(input, OR), and))(rest)?;
+
+    others.insert(0, first);
+    Ok((rest, others))
+}
+
+fn and_clause<'a, C: FieldNameValidator>(
+    input: &'a str,
+    validator: &'a C,
+    depth: usize,
+) -> IResult<&'a str, Vec<RawQuery<'a>>> {
+    let operand = move |input| parse_operand(input, validator, depth);
+    let (rest, first) = operand(input)?;
+    let (rest, others) = many0(preceded(|input| keyword(input, AND), operand))(rest)?;
+
+    if others.is_empty() {
+        return Ok((rest, vec![first]));
+    }
+
+    let operands = Some(first)
+        .into_iter()
+        .chain(others)
+        .map(|raw| {
+            // Unless prohibited
+            if raw.occur == Occur::Should {
+                raw.must()
+            } else {
+                raw
+            }
+        })
+        .collect();
+    Ok((rest, operands))
+}
+
+fn and_group(mut operands: Vec<RawQuery>) -> RawQuery {
+    if operands.len() == 1 {
+        operands.pop().expect("has an operand")
+    } else {
+        RawQuery::group("", operands)
+    }
+}
+
+// An operator, surrounded by whitespace
+fn keyword<'a>(input: &'a str, word: &'static str) -> IResult<&'a str, &'a str> {
+    delimited(multispace1, tag(word), multispace1)(input)
+}
+
+fn is_keyword(input: &str) -> bool {
+    input == AND || input == OR
+}
+
+// The pattern of a `/regex/` term
+pub fn regex_pattern(input: &str) -> Option<&str> {
+    if input.len() > 2 && input.starts_with('/') && input.ends_with('/') {
+        Some(&input[1..input.len() - 1])
+    } else {
+        None
+    }
+}
+
+fn parse_operand<'a, C: FieldNameValidator>(
+    input: &'a str,
+    validator: &'a C,
+    depth: usize,
+) -> IResult<&'a str, RawQuery<'a>> {
+    alt((
+        |input| prohibited_query(input, validator, depth),
+        |input| mandatory_query(input, validator, depth),
+        |input| parse_group(input, validator, depth),
+        |input| field_prefixed_query(input, validator, depth),
+        verify(
+            move |input| any_field_query(input, depth),
+            |raw: &RawQuery| raw.is_phrase || !is_keyword(raw.input),
+        ),
     ))(input)
 }
 
+// Like `(a b)^2`. Groups that aren't closed end with the input
+fn parse_group<'a, C: FieldNameValidator>(
+    input: &'a str,
+    validator: &'a C,
+    depth: usize,
+) -> IResult<&'a str, RawQuery<'a>> {
+    if depth >= MAX_DEPTH {
+        return Err(nom::Err::Error(Error::new(input, ErrorKind::TooLarge)));
+    }
+
+    // Only stops at a closing parenthesis or at the end
+    let (rest, items) = terminated(
+        preceded(is_char('('), move |input| {
+            parse_clauses(input, validator, depth + 1)
+        }),
+        opt(is_char(')')),
+    )(input)?;
+
+    if items.is_empty() {
+        return Err(nom::Err::Error(Error::new(input, ErrorKind::Verify)));
+    }
+
+    let group = RawQuery::group(&input[..input.len() - rest.len()], items);
+    let boost: IResult<&str, Option<f32>> = opt(map_res(
+        preceded(is_char(BOOST_SEP), take_while1(term_char(depth))),
+        parse_boost,
+    ))(rest);
+    let (rest, boost) = boost?;
+
+    Ok((
+        rest,
+        match boost {
+            Some(boost) => group.with_boost(boost),
+            None => group,
+        },
+    ))
+}
+
+/// Something in the input that isn't valid syntax. The parser takes
+/// it as part of a term instead
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SyntaxIssue {
+    /// Where in the input, in characters
+    pub position: usize,
+    pub message: &'static str,
+}
+
+/// Lists what `parse_query` could only make sense of by taking it
+/// literally, in input order
+pub fn find_issues<'a, C: FieldNameValidator>(
+    input: &'a str,
+    validator: &'a C,
+) -> Vec<SyntaxIssue> {
+    let parsed = parse_query(input, validator)
+        .map(|(_, parsed)| parsed)
+        .unwrap_or_default();
+
+    let mut issues = Vec::new();
+    collect_issues(input, &parsed, validator, &mut issues);
+
+    issues.sort_by_key(|issue| issue.position);
+    issues
+}
+
+fn collect_issues<C: FieldNameValidator>(
+    input: &str,
+    parsed: &[RawQuery],
+    validator: &C,
+    issues: &mut Vec<SyntaxIssue>,
+) {
+    for raw in parsed.iter().filter(|raw| !raw.is_phrase) {
+        // Terms and groups parsed from the input are slices of it
+        let position = |offset: usize| {
+            let start = raw.input.as_ptr() as usize - input.as_ptr() as usize;
+            input[..start + offset].chars().count()
+        };
+        let mut issue = |offset: usize, message| {
+            issues.push(SyntaxIssue {
+                position: position(offset),
+                message,
+            })
+        };
+
+        if let Some(items) = &raw.group {
+            if raw.input.starts_with('(') && !raw.input.ends_with(')') {
+                issue(0, "Unbalanced parenthesis");
+            }
+            collect_issues(input, items, validator, issues);
+            continue;
+        }
+
+        let is_literal_keyword =
+            raw.field_name.is_none() && raw.occur == Occur::Should && is_keyword(raw.input);
+        if raw.input == "-" || raw.input == "+" || is_literal_keyword {
+            issue(0, "Operator without a term");
+            continue;
+        }
+
+        if let Some(field_name) = raw.field_name {
+            if validator.is_range_field(field_name) {
+                if !is_valid_range(raw.input) {
+                    issue(0, "Invalid range");
+                }
+                continue;
+            }
+        }
+
+        if let Some(max_length) = validator.max_regex_length() {
+            if let Some(pattern) = regex_pattern(raw.input) {
+                if pattern.chars().count() > max_length {
+                    issue(0, "Regex too long");
+                } else if RegexQuery::from_pattern(pattern, Field::from_field_id(0)).is_err() {
+                    issue(0, "Invalid regex");
+                }
+                continue;
+            }
+        }
+
+        if let Some(idx) = raw.input.find('"') {
+            issue(idx, "Unbalanced quote");
+        }
+
+        if let Some(idx) = raw.input.find(|c| c == '(' || c == ')') {
+            issue(idx, "Unbalanced parenthesis");
+        }
+
+        if let Some(min_prefix) = validator.min_wildcard_prefix() {
+            if is_prefix_too_short(raw.input, min_prefix) {
+                issue(0, "Wildcard prefix too short");
+            }
+        }
+
+        if raw.field_name.is_none() {
+            match raw.input.find(FIELD_SEP) {
+                Some(idx) if idx + 1 == raw.input.len() => issue(0, "Field without a term"),
+                Some(idx) if idx > 0 => issue(0, "Unknown field"),
+                _ => {}
+            }
+        }
+
+        // Valid modifiers have been extracted already
+        if let Some(idx) = raw.input.find(BOOST_SEP) {
+            issue(idx, "Invalid boost");
+        }
+        if let Some(idx) = raw.input.find(FUZZY_SEP) {
+            issue(idx, "Invalid edit distance");
+        }
+    }
+}
+
 fn prohibited_query<'a, C: FieldNameValidator>(
     input: &'a str,
     validator: &'a C,
+    depth: usize,
 ) -> IResult<&'a str, RawQuery<'a>> {
     map(
         preceded(
             is_char('-'),
             alt((
-                |input| field_prefixed_query(input, validator),
-                any_field_query,
+                |input| parse_group(input, validator, depth),
+                |input| field_prefixed_query(input, validator, depth),
+                move |input| any_field_query(input, depth),
             )),
         ),
         RawQuery::must_not,
@@ -109,13 +431,15 @@ fn prohibited_query<'a, C: FieldNameValidator>(
 fn mandatory_query<'a, C: FieldNameValidator>(
     input: &'a str,
     validator: &'a C,
+    depth: usize,
 ) -> IResult<&'a str, RawQuery<'a>> {
     map(
         preceded(
             is_char('+'),
             alt((
-                |input| field_prefixed_query(input, validator),
-                any_field_query,
+                |input| parse_group(input, validator, depth),
+                |input| field_prefixed_query(input, validator, depth),
+                move |input| any_field_query(input, depth),
             )),
         ),
         RawQuery::must,
@@ -125,12 +449,14 @@ fn mandatory_query<'a, C: FieldNameValidator>(
 fn field_prefixed_query<'a, C: FieldNameValidator>(
     input: &'a str,
     validator: &'a C,
+    depth: usize,
 ) -> IResult<&'a str, RawQuery<'a>> {
+    let is_name_char = term_char(depth);
     map_res(
         separated_pair(
-            take_while1(|c| c != FIELD_SEP && is_term_char(c)),
+            take_while1(move |c| c != FIELD_SEP && is_name_char(c)),
             is_char(FIELD_SEP),
-            any_field_query,
+            move |input| any_field_query(input, depth),
         ),
         |(name, term)| {
             if validator.check(name) {
@@ -142,25 +468,95 @@ fn field_prefixed_query<'a, C: FieldNameValidator>(
     )(input)
 }
 
-fn any_field_query(input: &str) -> IResult<&str, RawQuery> {
-    alt((parse_phrase, parse_term))(input)
+fn any_field_query(input: &str, depth: usize) -> IResult<&str, RawQuery> {
+    alt((
+        move |input| parse_phrase(input, depth),
+        move |input| parse_term(input, depth),
+    ))(input)
 }
 
-fn parse_phrase(input: &str) -> IResult<&str, RawQuery> {
+fn parse_phrase(input: &str, depth: usize) -> IResult<&str, RawQuery> {
     map(
-        delimited(is_char('"'), take_while1(|c| c != '"'), is_char('"')),
-        |s| RawQuery::new(s).phrase(),
+        pair(
+            delimited(is_char('"'), take_while1(|c| c != '"'), is_char('"')),
+            opt(map_res(
+                preceded(is_char(BOOST_SEP), take_while1(term_char(depth))),
+                parse_boost,
+            )),
+        ),
+        |(s, boost)| {
+            let raw = RawQuery::new(s).phrase();
+            if let Some(boost) = boost {
+                raw.with_boost(boost)
+            } else {
+                raw
+            }
+        },
     )(input)
 }
 
-fn parse_term(input: &str) -> IResult<&str, RawQuery> {
-    map(take_while1(is_term_char), RawQuery::new)(input)
+fn parse_term(input: &str, depth: usize) -> IResult<&str, RawQuery> {
+    map(take_while1(term_char(depth)), term_with_modifiers)(input)
+}
+
+fn term_with_modifiers(input: &str) -> RawQuery {
+    // Modifiers are only recognized in the `term~1^2` order
+    let (input, boost) = split_modifier(input, BOOST_SEP, parse_boost);
+    let (input, fuzzy) = split_modifier(input, FUZZY_SEP, parse_fuzzy);
+
+    let mut raw = RawQuery::new(input);
+
+    if let Some(distance) = fuzzy {
+        raw = raw.with_fuzzy(distance);
+    }
+
+    if let Some(boost) = boost {
+        raw = raw.with_boost(boost);
+    }
+
+    raw
+}
+
+// A trailing "<sep><valid modifier>" is extracted from the term,
+// anything else is considered part of it
+fn split_modifier<T>(
+    input: &str,
+    sep: char,
+    parse: fn(&str) -> Result<T, &'static str>,
+) -> (&str, Option<T>) {
+    if let Some(idx) = input.rfind(sep) {
+        if idx > 0 {
+            if let Ok(value) = parse(&input[idx + 1..]) {
+                return (&input[..idx], Some(value));
+            }
+        }
+    }
+    (input, None)
+}
+
+fn parse_fuzzy(input: &str) -> Result<u8, &'static str> {
+    if input.is_empty() {
+        Ok(DEFAULT_FUZZY_DISTANCE)
+    } else {
+        input.parse::<u8>().map_err(|_| "Invalid edit distance")
+    }
+}
+
+fn parse_boost(input: &str) -> Result<f32, &'static str> {
+    match input.parse::<f32>() {
+        Ok(boost) if boost.is_finite() && boost >= 0.0 => Ok(boost),
+        _ => Err("Invalid boost"),
+    }
 }
 
 fn is_term_char(c: char) -> bool {
     !(c == ' ' || c == '\t' || c == '\r' || c == '\n')
 }
 
+fn term_char(depth: usize) -> impl Fn(char) -> bool {
+    move |c| is_term_char(c) && (depth == 0 || c != ')')
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -294,6 +690,176 @@ mod tests {
         );
     }
 
+    #[test]
+    fn issues_are_located() {
+        let issue = |position, message| SyntaxIssue { position, message };
+
+        assert!(find_issues("+name:garlic^3 -\"olive oil\" on~1", &true).is_empty());
+        assert!(find_issues("", &true).is_empty());
+
+        assert_eq!(
+            vec![
+                issue(0, "Operator without a term"),
+                issue(3, "Field without a term"),
+                issue(9, "Unknown field"),
+                issue(19, "Unbalanced quote"),
+                issue(30, "Invalid boost"),
+                issue(36, "Invalid edit distance"),
+            ],
+            find_issues("- -name: title:pão \"açúcar mel^x sal~x", &vec!["name"])
+        );
+
+        assert!(find_issues("(a OR b) AND -(c d)", &true).is_empty());
+        assert_eq!(
+            vec![
+                issue(1, "Unbalanced parenthesis"),
+                issue(3, "Unbalanced parenthesis"),
+                issue(6, "Operator without a term"),
+            ],
+            find_issues("a) (b OR", &true)
+        );
+    }
+
+    #[test]
+    fn operators_and_groups() {
+        assert_eq!(
+            parse_no_fields("(chicken OR turkey) AND -fried"),
+            Ok((
+                "",
+                vec![
+                    RawQuery::group(
+                        "(chicken OR turkey)",
+                        vec![RawQuery::new("chicken"), RawQuery::new("turkey")]
+                    )
+                    .must(),
+                    RawQuery::new("fried").must_not(),
+                ]
+            ))
+        );
+
+        assert_eq!(
+            parse_no_fields("-(a (b c)^2) d"),
+            Ok((
+                "",
+                vec![
+                    RawQuery::group(
+                        "(a (b c)^2)",
+                        vec![
+                            RawQuery::new("a"),
+                            RawQuery::group("(b c)", vec![RawQuery::new("b"), RawQuery::new("c")])
+                                .with_boost(2.0),
+                        ]
+                    )
+                    .must_not(),
+                    RawQuery::new("d"),
+                ]
+            ))
+        );
+    }
+
+    #[test]
+    fn operator_precedence() {
+        let (a, b, c, d) = (
+            || RawQuery::new("a"),
+            || RawQuery::new("b"),
+            || RawQuery::new("c"),
+            || RawQuery::new("d"),
+        );
+
+        // A lone OR is the same as whitespace
+        assert_eq!(parse_no_fields("a OR b"), Ok(("", vec![a(), b()])));
+        assert_eq!(
+            parse_no_fields("d a OR b"),
+            Ok(("", vec![d(), RawQuery::group("", vec![a(), b()])]))
+        );
+
+        // AND binds tighter than OR
+        assert_eq!(
+            parse_no_fields("a OR b AND c"),
+            Ok((
+                "",
+                vec![a(), RawQuery::group("", vec![b().must(), c().must()])]
+            ))
+        );
+        assert_eq!(
+            parse_no_fields("a AND b OR c d"),
+            Ok((
+                "",
+                vec![
+                    RawQuery::group(
+                        "",
+                        vec![RawQuery::group("", vec![a().must(), b().must()]), c()]
+                    ),
+                    d(),
+                ]
+            ))
+        );
+
+        // And whitespace binds looser than both
+        assert_eq!(
+            parse_no_fields("a b AND -c"),
+            Ok(("", vec![a(), b().must(), c().must_not()]))
+        );
+    }
+
+    #[test]
+    fn unbalanced_syntax_is_lenient() {
+        // Operators need something on both sides, and are only
+        // recognized in uppercase
+        assert_eq!(
+            parse_no_fields("OR chicken AND"),
+            Ok((
+                "",
+                vec![
+                    RawQuery::new("OR"),
+                    RawQuery::new("chicken"),
+                    RawQuery::new("AND"),
+                ]
+            ))
+        );
+        assert_eq!(
+            parse_no_fields("a and b ORANGE"),
+            Ok((
+                "",
+                vec![
+                    RawQuery::new("a"),
+                    RawQuery::new("and"),
+                    RawQuery::new("b"),
+                    RawQuery::new("ORANGE"),
+                ]
+            ))
+        );
+
+        // Groups are closed at the end of the input, stray closing
+        // parentheses are part of the term
+        assert_eq!(
+            parse_no_fields("soup) (chicken OR turkey"),
+            Ok((
+                "",
+                vec![
+                    RawQuery::new("soup)"),
+                    RawQuery::group(
+                        "(chicken OR turkey",
+                        vec![RawQuery::new("chicken"), RawQuery::new("turkey")]
+                    ),
+                ]
+            ))
+        );
+        assert_eq!(
+            parse_no_fields("() (x"),
+            Ok((
+                "",
+                vec![
+                    RawQuery::new("()"),
+                    RawQuery::group("(x", vec![RawQuery::new("x")])
+                ]
+            ))
+        );
+
+        // Nesting is limited
+        assert!(parse_no_fields(&"(".repeat(10_000)).is_ok());
+    }
+
     #[test]
     fn parse_term_with_field() {
         assert_eq!(
@@ -310,6 +876,57 @@ mod tests {
         );
     }
 
+    #[test]
+    fn boost_extraction() {
+        assert_eq!(
+            parse_query("name:garlic^3 +\"olive oil\"^0.5 -onion^2", &true),
+            Ok((
+                "",
+                vec![
+                    RawQuery::new("garlic").with_field("name").with_boost(3.0),
+                    RawQuery::new("olive oil").must().phrase().with_boost(0.5),
+                    RawQuery::new("onion").must_not().with_boost(2.0),
+                ]
+            ))
+        );
+    }
+
+    #[test]
+    fn invalid_boosts_are_part_of_the_term() {
+        assert_eq!(
+            parse_no_fields("^2 x^ y^-1 z^nan \"w\"^a"),
+            Ok((
+                "",
+                vec![
+                    RawQuery::new("^2"),
+                    RawQuery::new("x^"),
+                    RawQuery::new("y^-1"),
+                    RawQuery::new("z^nan"),
+                    RawQuery::new("w").phrase(),
+                    RawQuery::new("^a"),
+                ]
+            ))
+        );
+    }
+
+    #[test]
+    fn fuzzy_extraction() {
+        assert_eq!(
+            parse_query("garlc~ ingredient:tomatoe~2 +onoin~1^3 x~y", &true),
+            Ok((
+                "",
+                vec![
+                    RawQuery::new("garlc").with_fuzzy(1),
+                    RawQuery::new("tomatoe")
+                        .with_field("ingredient")
+                        .with_fuzzy(2),
+                    RawQuery::new("onoin").must().with_fuzzy(1).with_boost(3.0),
+                    RawQuery::new("x~y"),
+                ]
+            ))
+        );
+    }
+
     use quickcheck::QuickCheck;
 
     #[test]
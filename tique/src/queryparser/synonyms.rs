@@ -0,0 +1,168 @@
+use std::collections::HashMap;
+
+/// Provides alternatives to what the user typed
+///
+/// The `QueryParser` consults its provider for every term or phrase
+/// it parses and searches for any of them: with a provider that
+/// knows that "aubergine" is the same as "eggplant", the input
+/// `aubergine` behaves like `aubergine OR eggplant` would.
+pub trait SynonymProvider: Send + Sync {
+    /// Alternatives for the given input (as typed, before being
+    /// analyzed), without the input itself
+    fn synonyms(&self, input: &str) -> Option<&[String]>;
+}
+
+/// A case-insensitive dictionary of synonyms
+///
+/// Can be built programmatically or parsed from a simple text format
+/// where each line is either a group of equivalent words:
+///
+/// ```text
+/// aubergine, eggplant
+/// ```
+///
+/// Or a one-way mapping, so that searching for "scallion" also
+/// searches for "spring onion" and "green onion" but not the other
+/// way around:
+///
+/// ```text
+/// scallion => spring onion, green onion
+/// ```
+///
+/// Empty lines and lines starting with `#` are ignored. Multi-word
+/// alternatives are searched as phrases.
+#[derive(Debug, Clone, Default)]
+pub struct SynonymMap(HashMap<String, Vec<String>>);
+
+impl SynonymMap {
+    /// Creates an empty dictionary
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Parses the text format described above
+    pub fn parse(input: &str) -> Self {
+        let mut map = Self::new();
+
+        for line in input.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            if let Some(idx) = line.find("=>") {
+                let alternatives = split_words(&line[idx + 2..]);
+                for word in split_words(&line[..idx]) {
+                    map.add(&word, &alternatives);
+                }
+            } else {
+                map.add_group(&split_words(line));
+            }
+        }
+
+        map
+    }
+
+    /// Makes searching for `word` also search for each of the given
+    /// alternatives
+    pub fn add<S: AsRef<str>>(&mut self, word: &str, alternatives: &[S]) {
+        let key = word.to_lowercase();
+        let entry = self.0.entry(key.clone()).or_insert_with(Vec::new);
+
+        for alternative in alternatives {
+            let alternative = alternative.as_ref().to_lowercase();
+            if alternative != key && !entry.contains(&alternative) {
+                entry.push(alternative);
+            }
+        }
+    }
+
+    /// Makes every word in `words` equivalent to each other
+    pub fn add_group<S: AsRef<str>>(&mut self, words: &[S]) {
+        for word in words {
+            self.add(word.as_ref(), words);
+        }
+    }
+
+    /// How many words have synonyms
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    /// Check if this dictionary is empty
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl SynonymProvider for SynonymMap {
+    fn synonyms(&self, input: &str) -> Option<&[String]> {
+        self.0
+            .get(&input.to_lowercase())
+            .map(Vec::as_slice)
+            .filter(|alternatives| !alternatives.is_empty())
+    }
+}
+
+impl From<HashMap<String, Vec<String>>> for SynonymMap {
+    fn from(src: HashMap<String, Vec<String>>) -> Self {
+        let mut map = Self::new();
+        for (word, alternatives) in src {
+            map.add(&word, &alternatives);
+        }
+        map
+    }
+}
+
+fn split_words(input: &str) -> Vec<String> {
+    input
+        .split(',')
+        .map(|word| word.split_whitespace().collect::<Vec<_>>().join(" "))
+        .filter(|word| !word.is_empty())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn get<'a>(map: &'a SynonymMap, word: &str) -> Vec<&'a str> {
+        map.synonyms(word)
+            .map(|found| found.iter().map(String::as_str).collect())
+            .unwrap_or_default()
+    }
+
+    #[test]
+    fn parse_groups_and_mappings() {
+        let map = SynonymMap::parse(
+            "# A comment, to be ignored\n\
+             \n\
+             Aubergine, eggplant\n\
+             courgette,zucchini, marrow\n\
+             scallion => spring   onion, green onion\n",
+        );
+
+        assert_eq!(vec!["eggplant"], get(&map, "aubergine"));
+        assert_eq!(vec!["aubergine"], get(&map, "EggPlant"));
+        assert_eq!(vec!["zucchini", "marrow"], get(&map, "courgette"));
+        assert_eq!(vec!["courgette", "zucchini"], get(&map, "marrow"));
+        assert_eq!(vec!["spring onion", "green onion"], get(&map, "scallion"));
+
+        // One-way only
+        assert!(get(&map, "spring onion").is_empty());
+        assert!(get(&map, "a comment").is_empty());
+    }
+
+    #[test]
+    fn no_self_references_nor_duplicates() {
+        let mut map = SynonymMap::new();
+
+        map.add("bun", &["bun", "roll", "Roll"]);
+        map.add_group(&["bun", "roll"]);
+
+        assert_eq!(vec!["roll"], get(&map, "bun"));
+        assert_eq!(vec!["bun"], get(&map, "roll"));
+
+        map.add("lonely", &["lonely"]);
+        assert!(map.synonyms("lonely").is_none());
+    }
+}
@@ -0,0 +1,80 @@
+// Wildcard terms, like `garl*` or `ga?lic`: `*` stands for any number
+// of characters and `?` for exactly one. Except at the end of a term,
+// where `?` is taken as punctuation, so that `bread?` is just bread
+
+const ANY: char = '*';
+const ONE: char = '?';
+
+// Where (in bytes) the first wildcard of `input` is, if any
+pub(super) fn first_wildcard(input: &str) -> Option<usize> {
+    input
+        .char_indices()
+        .find(|&(idx, c)| c == ANY || (c == ONE && idx + 1 < input.len()))
+        .map(|(idx, _)| idx)
+}
+
+// Whether `input` has a wildcard, but too few characters before it
+pub(super) fn is_prefix_too_short(input: &str, min_prefix: usize) -> bool {
+    first_wildcard(input).map_or(false, |idx| input[..idx].chars().count() < min_prefix)
+}
+
+// A regex for the (lowercased) terms `input` matches, when it has a
+// wildcard after at least `min_prefix` characters
+pub(super) fn to_regex(input: &str, min_prefix: usize) -> Option<String> {
+    if first_wildcard(input).is_none() || is_prefix_too_short(input, min_prefix) {
+        return None;
+    }
+
+    let mut regex = String::with_capacity(input.len() * 2);
+    for (idx, c) in input.char_indices() {
+        match c {
+            ANY => regex.push_str(".*"),
+            ONE if idx + 1 < input.len() => regex.push('.'),
+            ONE => {}
+            c => {
+                for lowercase in c.to_lowercase() {
+                    if is_meta(lowercase) {
+                        regex.push('\\');
+                    }
+                    regex.push(lowercase);
+                }
+            }
+        }
+    }
+
+    Some(regex)
+}
+
+// What regex-syntax takes as meta characters
+fn is_meta(c: char) -> bool {
+    "\\.+*?()|[]{}^$#&-~".contains(c)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wildcards_become_regexes() {
+        assert_eq!(Some("garl.*".to_owned()), to_regex("garl*", 2));
+        assert_eq!(Some("ga.lic".to_owned()), to_regex("Ga?lic", 2));
+        assert_eq!(Some("pão.*de.*".to_owned()), to_regex("PÃO*DE*", 2));
+        assert_eq!(Some("a\\.b\\-c.*".to_owned()), to_regex("a.b-c*", 0));
+        // A trailing ? is punctuation
+        assert_eq!(Some("brea.*".to_owned()), to_regex("brea*?", 2));
+        assert_eq!(None, to_regex("bread?", 0));
+        assert_eq!(None, to_regex("bread", 0));
+    }
+
+    #[test]
+    fn prefix_length_is_enforced() {
+        assert_eq!(None, to_regex("g*", 2));
+        assert_eq!(None, to_regex("*garlic", 2));
+        assert_eq!(Some(".*garlic".to_owned()), to_regex("*garlic", 0));
+
+        assert!(is_prefix_too_short("g*", 2));
+        assert!(is_prefix_too_short("ç?b", 2));
+        assert!(!is_prefix_too_short("ga*", 2));
+        assert!(!is_prefix_too_short("g", 2));
+    }
+}
@@ -0,0 +1,255 @@
+//! Rerank the best documents of a search with a custom score
+//!
+//! `RescoringCollector` keeps the top `num_candidates` documents by
+//! their regular (say: BM25) score, then rescores only those with
+//! a function of your choosing, yielding the best `limit` documents
+//! by the new score.
+//!
+//! Good for blending relevance with signals that live in fast fields,
+//! like popularity or freshness, without paying to compute them for
+//! every single match: it's all done in the same search pass.
+//!
+//! ```no_run
+//! # use tantivy::{query::AllQuery, schema::Field, DocId, Score, Searcher, SegmentReader, Result, TantivyError};
+//! # use tique::rescore::RescoringCollector;
+//! # fn example(searcher: &Searcher, popularity: Field) -> Result<()> {
+//! let collector = RescoringCollector::new(100, 10, move |reader: &SegmentReader| {
+//!     let popularity_reader = reader.fast_fields().u64(popularity).unwrap();
+//!     Ok::<_, TantivyError>(move |doc: DocId, score: Score| {
+//!         score * (1.0 + (popularity_reader.get(doc) as f32).ln_1p())
+//!     })
+//! });
+//!
+//! for rescored in searcher.search(&AllQuery, &collector)? {
+//!     println!("{:?}: {} (was {})", rescored.doc, rescored.score, rescored.original);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+use std::cmp::Ordering;
+
+use tantivy::{
+    collector::{Collector, SegmentCollector},
+    DocAddress, DocId, Result, Score, SegmentLocalId, SegmentReader,
+};
+
+use crate::conditional_collector::{
+    topk::{TopK, TopKProvider},
+    Descending,
+};
+
+/// A document that made it past the first pass
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Rescored {
+    /// The score given by the rescorer
+    pub score: Score,
+    /// The score given by the query
+    pub original: Score,
+    /// Where to find the document
+    pub doc: DocAddress,
+}
+
+/// Prepares the rescoring function for a segment
+pub trait RescorerForSegment: Sync {
+    /// The per-segment rescoring function
+    type Rescorer: RescoreDoc;
+    /// Prepares to rescore documents from the given segment
+    fn for_segment(&self, reader: &SegmentReader) -> Result<Self::Rescorer>;
+}
+
+/// Computes the new score of a document within a segment
+pub trait RescoreDoc: 'static {
+    /// The new score of `doc`, given its original `score`
+    fn rescore(&self, doc: DocId, score: Score) -> Score;
+}
+
+impl<F, R> RescorerForSegment for F
+where
+    F: Sync + Fn(&SegmentReader) -> Result<R>,
+    R: RescoreDoc,
+{
+    type Rescorer = R;
+
+    fn for_segment(&self, reader: &SegmentReader) -> Result<Self::Rescorer> {
+        (self)(reader)
+    }
+}
+
+impl<F> RescoreDoc for F
+where
+    F: 'static + Fn(DocId, Score) -> Score,
+{
+    fn rescore(&self, doc: DocId, score: Score) -> Score {
+        (self)(doc, score)
+    }
+}
+
+/// Collects the top `num_candidates` documents by score and yields
+/// the top `limit` of those after rescoring them
+pub struct RescoringCollector<R> {
+    num_candidates: usize,
+    limit: usize,
+    rescorer: R,
+}
+
+impl<R: RescorerForSegment> RescoringCollector<R> {
+    /// Creates a new collector that reranks the best
+    /// `num_candidates` documents via `rescorer`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `limit` is zero or greater than `num_candidates`
+    pub fn new(num_candidates: usize, limit: usize, rescorer: R) -> Self {
+        assert!(limit > 0, "Limit must be greater than zero");
+        assert!(
+            limit <= num_candidates,
+            "Limit must not be greater than the number of candidates"
+        );
+
+        Self {
+            num_candidates,
+            limit,
+            rescorer,
+        }
+    }
+}
+
+impl<R: RescorerForSegment> Collector for RescoringCollector<R> {
+    type Fruit = Vec<Rescored>;
+    type Child = RescoringSegmentCollector<R::Rescorer>;
+
+    fn for_segment(
+        &self,
+        segment_id: SegmentLocalId,
+        reader: &SegmentReader,
+    ) -> Result<Self::Child> {
+        Ok(RescoringSegmentCollector {
+            segment_id,
+            rescorer: self.rescorer.for_segment(reader)?,
+            topk: <Descending as TopKProvider<Score, DocId>>::new_topk(self.num_candidates),
+        })
+    }
+
+    fn requires_scoring(&self) -> bool {
+        true
+    }
+
+    fn merge_fruits(&self, fruits: Vec<Vec<Rescored>>) -> Result<Vec<Rescored>> {
+        // Every segment sends its own best candidates, so the actual
+        // top candidates are picked here, by the original score
+        let mut result = fruits.into_iter().flatten().collect::<Vec<_>>();
+        result
+            .sort_by(|a, b| by_desc_score(a.original, b.original).then_with(|| a.doc.cmp(&b.doc)));
+        result.truncate(self.num_candidates);
+
+        result.sort_by(|a, b| by_desc_score(a.score, b.score).then_with(|| a.doc.cmp(&b.doc)));
+        result.truncate(self.limit);
+
+        Ok(result)
+    }
+}
+
+// Best first, like the other top collectors
+fn by_desc_score(a: Score, b: Score) -> Ordering {
+    b.partial_cmp(&a).unwrap_or(Ordering::Equal)
+}
+
+type SegmentTopK = <Descending as TopKProvider<Score, DocId>>::Child;
+
+/// The per-segment part of `RescoringCollector`
+pub struct RescoringSegmentCollector<R> {
+    segment_id: SegmentLocalId,
+    rescorer: R,
+    topk: SegmentTopK,
+}
+
+impl<R: RescoreDoc> SegmentCollector for RescoringSegmentCollector<R> {
+    type Fruit = Vec<Rescored>;
+
+    fn collect(&mut self, doc: DocId, score: Score) {
+        TopK::visit(&mut self.topk, doc, score);
+    }
+
+    fn harvest(self) -> Vec<Rescored> {
+        let segment_id = self.segment_id;
+        let rescorer = self.rescorer;
+        TopK::into_vec(self.topk)
+            .into_iter()
+            .map(|(doc, score)| Rescored {
+                score: rescorer.rescore(doc, score),
+                original: score,
+                doc: DocAddress(segment_id, doc),
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use tantivy::{
+        doc,
+        query::TermQuery,
+        schema::{IndexRecordOption, SchemaBuilder, Value, FAST, STORED, TEXT},
+        Index, TantivyError, Term,
+    };
+
+    #[test]
+    fn integration() -> Result<()> {
+        let mut builder = SchemaBuilder::new();
+        let body = builder.add_text_field("body", TEXT);
+        let popularity = builder.add_u64_field("popularity", FAST | STORED);
+        let index = Index::create_in_ram(builder.build());
+        let mut writer = index.writer_with_num_threads(1, 3_000_000)?;
+
+        // The more "cheese", the higher the score, but the less
+        // popular it is
+        for (idx, times) in (1..=6).enumerate() {
+            let text = vec!["cheese"; times].join(" ") + " bread";
+            writer.add_document(doc!(body => text, popularity => 10 - times as u64));
+            // Multiple segments
+            if idx % 2 == 1 {
+                writer.commit()?;
+            }
+        }
+        writer.commit()?;
+
+        let searcher = index.reader()?.searcher();
+        let query = TermQuery::new(
+            Term::from_field_text(body, "cheese"),
+            IndexRecordOption::WithFreqs,
+        );
+
+        let by_popularity = move |reader: &SegmentReader| {
+            let popularity_reader = reader.fast_fields().u64(popularity).unwrap();
+            Ok::<_, TantivyError>(move |doc: DocId, _score: Score| {
+                popularity_reader.get(doc) as Score
+            })
+        };
+
+        let popularity_of = |items: &[Rescored]| -> Result<Vec<u64>> {
+            let mut found = Vec::with_capacity(items.len());
+            for item in items {
+                match searcher.doc(item.doc)?.get_first(popularity) {
+                    Some(Value::U64(value)) => found.push(*value),
+                    _ => panic!("Found doc with non-U64 popularity"),
+                }
+            }
+            Ok(found)
+        };
+
+        // Only the 3 most relevant docs (6, 5 and 4 "cheese") are
+        // candidates, so the most popular ones never make it
+        let top = searcher.search(&query, &RescoringCollector::new(3, 2, by_popularity))?;
+        assert_eq!(2, top.len());
+        assert!(top[0].score >= top[1].score);
+        assert_eq!(vec![6, 5], popularity_of(&top)?);
+
+        // With every doc as a candidate, popularity wins
+        let top = searcher.search(&query, &RescoringCollector::new(10, 2, by_popularity))?;
+        assert_eq!(vec![9, 8], popularity_of(&top)?);
+
+        Ok(())
+    }
+}
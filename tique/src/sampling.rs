@@ -0,0 +1,318 @@
+//! Uniform random samples of the documents matching a query
+//!
+//! Instead of the top documents by score, `SampleCollector` picks
+//! any `k` of the matching documents, each with the same chance of
+//! being picked. Useful for "surprise me" features or for estimating
+//! properties of a large result set without looking at all of it.
+//!
+//! ```no_run
+//! # use tantivy::{query::AllQuery, Searcher};
+//! # use tique::sampling::SampleCollector;
+//! # fn example(searcher: &Searcher) -> tantivy::Result<()> {
+//! let sample = searcher.search(&AllQuery, &SampleCollector::new(10, 42))?;
+//! println!("Picked {} of {} docs", sample.docs.len(), sample.total);
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Sampling is deterministic given the seed and the index, so make
+//! sure to vary it (say: the current time) for actually random
+//! results.
+use tantivy::{
+    collector::{Collector, SegmentCollector},
+    DocAddress, DocId, Result, Score, SegmentLocalId, SegmentReader,
+};
+
+/// A sample of the documents that matched a query
+#[derive(Debug, Clone, PartialEq)]
+pub struct Sample {
+    /// The sampled documents, in no particular order
+    pub docs: Vec<DocAddress>,
+    /// How many documents matched the query
+    pub total: usize,
+}
+
+/// Collects a uniform random sample of up to `k` of the matching
+/// documents via reservoir sampling
+pub struct SampleCollector {
+    k: usize,
+    seed: u64,
+}
+
+impl SampleCollector {
+    /// Creates a new collector that samples `k` documents. Samples
+    /// are reproducible: the same seed over the same index always
+    /// picks the same documents
+    ///
+    /// # Panics
+    ///
+    /// Panics if `k` is zero
+    pub fn new(k: usize, seed: u64) -> Self {
+        assert!(k > 0, "Sample size must be greater than zero");
+        Self { k, seed }
+    }
+}
+
+impl Collector for SampleCollector {
+    type Fruit = Sample;
+    type Child = SampleSegmentCollector;
+
+    fn for_segment(
+        &self,
+        segment_id: SegmentLocalId,
+        _reader: &SegmentReader,
+    ) -> Result<Self::Child> {
+        Ok(SampleSegmentCollector {
+            k: self.k,
+            segment_id,
+            rng: Rng::new(self.seed ^ (u64::from(segment_id) + 1).wrapping_mul(GOLDEN)),
+            reservoir: Vec::with_capacity(self.k),
+            seen: 0,
+        })
+    }
+
+    fn requires_scoring(&self) -> bool {
+        false
+    }
+
+    fn merge_fruits(&self, fruits: Vec<Sample>) -> Result<Sample> {
+        Ok(merge(self.k, Rng::new(self.seed), fruits))
+    }
+}
+
+/// A sample of a single segment, via `SampleCollector`
+pub struct SampleSegmentCollector {
+    k: usize,
+    segment_id: SegmentLocalId,
+    rng: Rng,
+    reservoir: Vec<DocAddress>,
+    seen: usize,
+}
+
+impl SegmentCollector for SampleSegmentCollector {
+    type Fruit = Sample;
+
+    fn collect(&mut self, doc: DocId, _score: Score) {
+        self.seen += 1;
+
+        if self.reservoir.len() < self.k {
+            self.reservoir.push(DocAddress(self.segment_id, doc));
+        } else {
+            // Replaces a random entry with probability k/seen
+            let pos = self.rng.below(self.seen);
+            if pos < self.k {
+                self.reservoir[pos] = DocAddress(self.segment_id, doc);
+            }
+        }
+    }
+
+    fn harvest(self) -> Sample {
+        Sample {
+            docs: self.reservoir,
+            total: self.seen,
+        }
+    }
+}
+
+// Merging picks each of the `k` items from one of the samples with a
+// probability proportional to how many of its documents haven't been
+// picked yet, which is what sampling from their union would do
+fn merge(k: usize, mut rng: Rng, samples: Vec<Sample>) -> Sample {
+    let total = samples.iter().map(|sample| sample.total).sum();
+
+    let mut remaining = samples
+        .iter()
+        .map(|sample| sample.total)
+        .collect::<Vec<_>>();
+    let mut reservoirs = samples
+        .into_iter()
+        .map(|sample| sample.docs)
+        .collect::<Vec<_>>();
+
+    let mut docs = Vec::with_capacity(k.min(total));
+    let mut left = total;
+
+    while docs.len() < k && left > 0 {
+        let mut pick = rng.below(left);
+        let idx = remaining
+            .iter()
+            .position(|&count| {
+                if pick < count {
+                    true
+                } else {
+                    pick -= count;
+                    false
+                }
+            })
+            .expect("pick is always below the sum of the remaining counts");
+
+        // Every reservoir is a uniform sample of its segment, so a
+        // random entry is a uniform pick among its unpicked documents
+        let reservoir = &mut reservoirs[idx];
+        let pos = rng.below(reservoir.len());
+        docs.push(reservoir.swap_remove(pos));
+
+        remaining[idx] -= 1;
+        left -= 1;
+    }
+
+    Sample { docs, total }
+}
+
+const GOLDEN: u64 = 0x9E37_79B9_7F4A_7C15;
+
+// splitmix64: tiny, fast and good enough for picking documents
+struct Rng(u64);
+
+impl Rng {
+    fn new(seed: u64) -> Self {
+        Self(seed)
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(GOLDEN);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    // A number in [0, bound), with negligible bias for the sizes
+    // we deal with
+    fn below(&mut self, bound: usize) -> usize {
+        ((u128::from(self.next_u64()) * bound as u128) >> 64) as usize
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use tantivy::{
+        doc,
+        query::AllQuery,
+        schema::{SchemaBuilder, INDEXED},
+        Index,
+    };
+
+    fn segment_sample(segment_id: SegmentLocalId, k: usize, size: DocId, seed: u64) -> Sample {
+        let mut collector = SampleSegmentCollector {
+            k,
+            segment_id,
+            rng: Rng::new(seed),
+            reservoir: Vec::new(),
+            seen: 0,
+        };
+        for doc in 0..size {
+            collector.collect(doc, 0.0);
+        }
+        collector.harvest()
+    }
+
+    fn num_unique(docs: &[DocAddress]) -> usize {
+        let mut docs = docs.to_vec();
+        docs.sort();
+        docs.dedup();
+        docs.len()
+    }
+
+    #[test]
+    fn small_inputs_are_kept_whole() {
+        let sample = segment_sample(0, 10, 4, 1);
+        assert_eq!(4, sample.total);
+        assert_eq!(
+            (0..4).map(|doc| DocAddress(0, doc)).collect::<Vec<_>>(),
+            sample.docs
+        );
+
+        let merged = merge(
+            10,
+            Rng::new(1),
+            vec![
+                sample,
+                segment_sample(1, 10, 3, 2),
+                segment_sample(2, 10, 0, 3),
+            ],
+        );
+        assert_eq!(7, merged.total);
+        assert_eq!(7, merged.docs.len());
+    }
+
+    #[test]
+    fn samples_are_uniform() {
+        // Uneven segments: 10 + 40 + 150 documents
+        let sizes = [10, 40, 150];
+        let total: DocId = sizes.iter().sum();
+        let k = 20;
+        let rounds = 5_000;
+
+        let mut hits = vec![0usize; total as usize];
+        for round in 0..rounds {
+            let seed = round as u64 * 3;
+            let samples = sizes
+                .iter()
+                .enumerate()
+                .map(|(id, &size)| segment_sample(id as u32, k, size, seed + id as u64))
+                .collect();
+
+            let merged = merge(k, Rng::new(seed), samples);
+            assert_eq!(k, merged.docs.len());
+
+            assert_eq!(k, num_unique(&merged.docs));
+
+            for DocAddress(segment_id, doc) in merged.docs {
+                let offset: DocId = sizes[..segment_id as usize].iter().sum();
+                hits[(offset + doc) as usize] += 1;
+            }
+        }
+
+        // Every doc is expected to be picked rounds * k / total = 500
+        // times. Being off by 20% is way past 4 standard deviations
+        let expected = rounds * k / total as usize;
+        for (doc, &count) in hits.iter().enumerate() {
+            assert!(
+                count > expected * 8 / 10 && count < expected * 12 / 10,
+                "doc={} count={} expected={}",
+                doc,
+                count,
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn integration() -> Result<()> {
+        let mut builder = SchemaBuilder::new();
+        let id = builder.add_u64_field("id", INDEXED);
+        let index = Index::create_in_ram(builder.build());
+        let mut writer = index.writer_with_num_threads(1, 3_000_000)?;
+
+        for i in 0..100u64 {
+            writer.add_document(doc!(id => i));
+            // Multiple segments
+            if i % 30 == 0 {
+                writer.commit()?;
+            }
+        }
+        writer.commit()?;
+
+        let searcher = index.reader()?.searcher();
+
+        let sample = searcher.search(&AllQuery, &SampleCollector::new(10, 42))?;
+        assert_eq!(100, sample.total);
+        assert_eq!(10, sample.docs.len());
+        assert_eq!(10, num_unique(&sample.docs));
+
+        // Same seed, same sample
+        assert_eq!(
+            sample,
+            searcher.search(&AllQuery, &SampleCollector::new(10, 42))?
+        );
+
+        // Asking for more than there is yields everything
+        let everything = searcher.search(&AllQuery, &SampleCollector::new(1_000, 7))?;
+        assert_eq!(100, everything.docs.len());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,303 @@
+//! Search across multiple indices as if they were one
+//!
+//! `MultiIndex` fans a query out to every index it knows about
+//! (say: one per data source) and merges the results into a single
+//! ranking, remembering which index each hit came from.
+//!
+//! Relevance scores from different indices are not directly
+//! comparable: each index has its own term statistics, so a score
+//! of `2.0` may be great for one and mediocre for another. The
+//! scores are normalized before merging to make the ranking fair.
+//!
+//! ```no_run
+//! # use tantivy::{query::AllQuery, Index, Result};
+//! # use tique::search::{MultiIndex, Normalization};
+//! # fn example(recipes: &Index, blog_posts: &Index) -> Result<()> {
+//! let mut multi = MultiIndex::new(Normalization::MinMax);
+//! let recipes_id = multi.add_source("recipes", recipes.reader()?);
+//! multi.add_source("blog", blog_posts.reader()?);
+//!
+//! let result = multi.search(&AllQuery, 10)?;
+//! for (score, addr) in &result.items {
+//!     let doc = result.doc(*addr)?;
+//!     if addr.source == recipes_id {
+//!         // ...
+//!     }
+//! }
+//! # Ok(())
+//! # }
+//! ```
+use std::cmp::Ordering;
+
+use tantivy::{
+    query::Query, DocAddress, Document, IndexReader, Result, Score, Searcher, TantivyError,
+};
+
+use crate::conditional_collector::{CollectionResult, Descending, TopCollector};
+
+/// How to make scores from different indices comparable
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Normalization {
+    /// Use the scores as they are. Only sensible when every index
+    /// has very similar contents
+    None,
+    /// Divide every score by the best score from the same index
+    Max,
+    /// Scale the scores from each index so that the best one is
+    /// `1.0` and the worst (among the collected) is `0.0`
+    MinMax,
+}
+
+/// Where a merged hit came from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct SourceAddress {
+    /// The source index, as returned by `MultiIndex::add_source`
+    pub source: usize,
+    /// The address of the document within the source index
+    pub doc: DocAddress,
+}
+
+/// A set of indices that are searched together
+pub struct MultiIndex {
+    sources: Vec<(String, IndexReader)>,
+    normalization: Normalization,
+}
+
+/// The merged results of a `MultiIndex` search
+pub struct MultiCollectionResult {
+    /// How many documents matched, across every source
+    pub total: usize,
+    /// How many documents matched in each source, in the order
+    /// they were added
+    pub totals: Vec<usize>,
+    /// The top items, ranked by their normalized score
+    pub items: Vec<(Score, SourceAddress)>,
+    searchers: Vec<Searcher>,
+}
+
+impl MultiCollectionResult {
+    /// Retrieves a stored document from the index it came from
+    ///
+    /// The result holds on to the searchers used for the search, so
+    /// the addresses stay valid even if the indices change.
+    pub fn doc(&self, addr: SourceAddress) -> Result<Document> {
+        self.searchers
+            .get(addr.source)
+            .ok_or_else(|| {
+                TantivyError::InvalidArgument(format!("Unknown source {}", addr.source))
+            })?
+            .doc(addr.doc)
+    }
+
+    /// The searcher used for the given source
+    pub fn searcher(&self, source: usize) -> Option<&Searcher> {
+        self.searchers.get(source)
+    }
+}
+
+impl MultiIndex {
+    /// Creates an empty MultiIndex that merges results using the
+    /// given normalization
+    pub fn new(normalization: Normalization) -> Self {
+        Self {
+            sources: Vec::new(),
+            normalization,
+        }
+    }
+
+    /// Adds an index to be searched. Returns the identifier used to
+    /// tag hits from it.
+    pub fn add_source<S: Into<String>>(&mut self, name: S, reader: IndexReader) -> usize {
+        self.sources.push((name.into(), reader));
+        self.sources.len() - 1
+    }
+
+    /// The name of the given source
+    pub fn source_name(&self, source: usize) -> Option<&str> {
+        self.sources
+            .get(source)
+            .map(|(name, _reader)| name.as_str())
+    }
+
+    /// How many indices are searched
+    pub fn len(&self) -> usize {
+        self.sources.len()
+    }
+
+    /// Check if there are no indices to search
+    pub fn is_empty(&self) -> bool {
+        self.sources.is_empty()
+    }
+
+    /// Searches every source for the top `limit` documents
+    ///
+    /// The query is used as-is for every source, so all of them must
+    /// share the schema (or at least the fields the query uses). Check
+    /// `search_with` otherwise.
+    pub fn search(&self, query: &dyn Query, limit: usize) -> Result<MultiCollectionResult> {
+        self.search_with(|_source| Ok(query.box_clone()), limit)
+    }
+
+    /// Like `search`, but builds a query for each source
+    pub fn search_with<F>(&self, query_for_source: F, limit: usize) -> Result<MultiCollectionResult>
+    where
+        F: Fn(usize) -> Result<Box<dyn Query>>,
+    {
+        let mut totals = Vec::with_capacity(self.sources.len());
+        let mut searchers = Vec::with_capacity(self.sources.len());
+        let mut items = Vec::new();
+
+        for (source, (_name, reader)) in self.sources.iter().enumerate() {
+            let searcher = reader.searcher();
+            let query = query_for_source(source)?;
+
+            let result = searcher.search(
+                query.as_ref(),
+                &TopCollector::<Score, Descending, _>::new(limit, true),
+            )?;
+
+            totals.push(result.total);
+            items.extend(
+                self.normalize(result)
+                    .into_iter()
+                    .map(|(score, doc)| (score, SourceAddress { source, doc })),
+            );
+            searchers.push(searcher);
+        }
+
+        items.sort_by(|(score_a, addr_a), (score_b, addr_b)| {
+            score_b
+                .partial_cmp(score_a)
+                .unwrap_or(Ordering::Equal)
+                .then_with(|| addr_a.cmp(addr_b))
+        });
+        items.truncate(limit);
+
+        Ok(MultiCollectionResult {
+            total: totals.iter().sum(),
+            totals,
+            items,
+            searchers,
+        })
+    }
+
+    // Items arrive sorted, best first
+    fn normalize(&self, result: CollectionResult<Score>) -> Vec<(Score, DocAddress)> {
+        let best = result.items.first().map(|(score, _)| *score);
+        let worst = result.items.last().map(|(score, _)| *score);
+
+        let scale = |score: Score| match (self.normalization, best, worst) {
+            (Normalization::Max, Some(best), _) if best > 0.0 => score / best,
+            (Normalization::MinMax, Some(best), Some(worst)) if best > worst => {
+                (score - worst) / (best - worst)
+            }
+            // Every score is the same
+            (Normalization::MinMax, Some(_), Some(_)) => 1.0,
+            _ => score,
+        };
+
+        result
+            .items
+            .into_iter()
+            .map(|(score, doc)| (scale(score), doc))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use tantivy::{
+        doc,
+        query::{AllQuery, TermQuery},
+        schema::{IndexRecordOption, SchemaBuilder, TEXT},
+        Index, Term,
+    };
+
+    fn index_with(texts: &[&str]) -> Result<(Index, tantivy::schema::Field)> {
+        let mut builder = SchemaBuilder::new();
+        let field = builder.add_text_field("text", TEXT);
+        let index = Index::create_in_ram(builder.build());
+        let mut writer = index.writer_with_num_threads(1, 3_000_000)?;
+
+        for text in texts {
+            writer.add_document(doc!(field => *text));
+        }
+        writer.commit()?;
+
+        Ok((index, field))
+    }
+
+    #[test]
+    fn merges_and_tags_results() -> Result<()> {
+        let (first, field) = index_with(&["apple pie", "apple apple crumble", "pear tart"])?;
+        let (second, _) = index_with(&["apple", "banana bread"])?;
+
+        let mut multi = MultiIndex::new(Normalization::Max);
+        assert_eq!(0, multi.add_source("first", first.reader()?));
+        assert_eq!(1, multi.add_source("second", second.reader()?));
+
+        assert_eq!(Some("second"), multi.source_name(1));
+        assert_eq!(None, multi.source_name(2));
+
+        let query = TermQuery::new(
+            Term::from_field_text(field, "apple"),
+            IndexRecordOption::WithFreqs,
+        );
+
+        let result = multi.search(&query, 10)?;
+
+        assert_eq!(3, result.total);
+        assert_eq!(vec![2, 1], result.totals);
+        assert_eq!(3, result.items.len());
+
+        // The best of each source is normalized to 1.0, ties are
+        // broken by the source order
+        assert_eq!(1.0, result.items[0].0);
+        assert_eq!(0, result.items[0].1.source);
+        assert_eq!(1.0, result.items[1].0);
+        assert_eq!(1, result.items[1].1.source);
+        assert!(result.items[2].0 < 1.0);
+
+        let doc = result.doc(result.items[1].1)?;
+        assert_eq!(Some("apple"), doc.get_first(field).and_then(|v| v.text()));
+
+        Ok(())
+    }
+
+    #[test]
+    fn normalization() -> Result<()> {
+        let (first, _) = index_with(&["a", "b", "c"])?;
+
+        let mut multi = MultiIndex::new(Normalization::MinMax);
+        multi.add_source("first", first.reader()?);
+
+        // Every score is the same
+        let result = multi.search(&AllQuery, 10)?;
+        assert!(result.items.iter().all(|(score, _)| *score == 1.0));
+
+        let none = MultiIndex::new(Normalization::None);
+        assert!(none.is_empty());
+        assert_eq!(0, none.search(&AllQuery, 10)?.total);
+
+        Ok(())
+    }
+
+    #[test]
+    fn limit_is_respected() -> Result<()> {
+        let (first, _) = index_with(&["a", "b", "c"])?;
+        let (second, _) = index_with(&["d", "e"])?;
+
+        let mut multi = MultiIndex::new(Normalization::None);
+        multi.add_source("first", first.reader()?);
+        multi.add_source("second", second.reader()?);
+
+        let result = multi.search(&AllQuery, 2)?;
+        assert_eq!(5, result.total);
+        assert_eq!(2, result.items.len());
+        assert!(result.searcher(1).is_some());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,172 @@
+//! Where the time of a search goes
+//!
+//! `TimedCollector` wraps any collector and reports, alongside its
+//! usual result, how long the search took in each segment and how
+//! long it took to merge the per-segment results.
+//!
+//! ```no_run
+//! # use tantivy::{collector::Count, query::AllQuery, Searcher};
+//! # use tique::timing::TimedCollector;
+//! # fn example(searcher: &Searcher) -> tantivy::Result<()> {
+//! let (count, timings) = searcher.search(&AllQuery, &TimedCollector::new(Count))?;
+//! println!("Counted {} docs in {:?}", count, timings.total());
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! The time of a segment goes from the moment its collector is
+//! created until it's harvested, so it includes iterating over the
+//! matching documents (and scoring them), not just collecting.
+//!
+//! Collectors that drive the scorer by themselves, like
+//! `BudgetCollector`, keep doing so when timed: the segments are
+//! collected by the wrapped collector's `collect_segment`.
+use std::time::{Duration, Instant};
+
+use tantivy::{
+    collector::{Collector, SegmentCollector},
+    query::Weight,
+    DocId, Result, Score, SegmentLocalId, SegmentReader,
+};
+
+/// How long each part of a search took
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CollectionTimings {
+    /// The time spent searching each segment, in segment order
+    pub segments: Vec<Duration>,
+    /// The time spent merging the results of every segment
+    pub merge: Duration,
+}
+
+impl CollectionTimings {
+    /// The sum of every segment and merge time. Segments may be
+    /// searched in parallel, so this is not necessarily the time
+    /// the whole search took
+    pub fn total(&self) -> Duration {
+        self.segments.iter().sum::<Duration>() + self.merge
+    }
+}
+
+/// Wraps a collector, timing its work
+pub struct TimedCollector<C>(C);
+
+impl<C: Collector> TimedCollector<C> {
+    /// Creates a new collector that times the given one
+    pub fn new(collector: C) -> Self {
+        Self(collector)
+    }
+}
+
+impl<C: Collector> Collector for TimedCollector<C> {
+    type Fruit = (C::Fruit, CollectionTimings);
+    type Child = TimedSegmentCollector<C::Child>;
+
+    fn for_segment(
+        &self,
+        segment_id: SegmentLocalId,
+        reader: &SegmentReader,
+    ) -> Result<Self::Child> {
+        let start = Instant::now();
+        Ok(TimedSegmentCollector {
+            inner: self.0.for_segment(segment_id, reader)?,
+            start,
+        })
+    }
+
+    fn requires_scoring(&self) -> bool {
+        self.0.requires_scoring()
+    }
+
+    fn collect_segment(
+        &self,
+        weight: &dyn Weight,
+        segment_ord: u32,
+        reader: &SegmentReader,
+    ) -> Result<<Self::Child as SegmentCollector>::Fruit> {
+        let start = Instant::now();
+        let fruit = self.0.collect_segment(weight, segment_ord, reader)?;
+        Ok((fruit, start.elapsed()))
+    }
+
+    fn merge_fruits(
+        &self,
+        fruits: Vec<<Self::Child as SegmentCollector>::Fruit>,
+    ) -> Result<Self::Fruit> {
+        let (fruits, segments): (Vec<_>, Vec<_>) = fruits.into_iter().unzip();
+
+        let start = Instant::now();
+        let merged = self.0.merge_fruits(fruits)?;
+
+        Ok((
+            merged,
+            CollectionTimings {
+                segments,
+                merge: start.elapsed(),
+            },
+        ))
+    }
+}
+
+/// The per-segment part of `TimedCollector`
+pub struct TimedSegmentCollector<C> {
+    inner: C,
+    start: Instant,
+}
+
+impl<C: SegmentCollector> SegmentCollector for TimedSegmentCollector<C> {
+    type Fruit = (C::Fruit, Duration);
+
+    fn collect(&mut self, doc: DocId, score: Score) {
+        self.inner.collect(doc, score);
+    }
+
+    fn harvest(self) -> Self::Fruit {
+        let fruit = self.inner.harvest();
+        (fruit, self.start.elapsed())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use tantivy::{
+        collector::{Count, TopDocs},
+        doc,
+        query::AllQuery,
+        schema::{SchemaBuilder, INDEXED},
+        Index,
+    };
+
+    #[test]
+    fn integration() -> Result<()> {
+        let mut builder = SchemaBuilder::new();
+        let id = builder.add_u64_field("id", INDEXED);
+        let index = Index::create_in_ram(builder.build());
+        let mut writer = index.writer_with_num_threads(1, 3_000_000)?;
+
+        for i in 0..30u64 {
+            writer.add_document(doc!(id => i));
+            // Multiple segments
+            if i % 10 == 9 {
+                writer.commit()?;
+            }
+        }
+
+        let searcher = index.reader()?.searcher();
+        let num_segments = searcher.segment_readers().len();
+        assert!(num_segments > 1);
+
+        let (count, timings) = searcher.search(&AllQuery, &TimedCollector::new(Count))?;
+        assert_eq!(30, count);
+        assert_eq!(num_segments, timings.segments.len());
+        assert!(timings.total() >= timings.merge);
+
+        // Results are untouched
+        let (top, _timings) =
+            searcher.search(&AllQuery, &TimedCollector::new(TopDocs::with_limit(5)))?;
+        assert_eq!(searcher.search(&AllQuery, &TopDocs::with_limit(5))?, top);
+
+        Ok(())
+    }
+}
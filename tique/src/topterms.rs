@@ -190,23 +190,48 @@ impl TopTerms {
         addr: DocAddress,
         acceptor: &F,
     ) -> Keywords {
-        let searcher = self.reader.searcher();
-        let num_docs = searcher.num_docs();
-
-        let mut keywords = DescendingTopK::new(limit);
+        let fields = self
+            .field_tokenizers
+            .iter()
+            .map(|(field, _tokenizer)| *field)
+            .collect::<Vec<_>>();
 
-        for (field, _tokenizer) in &self.field_tokenizers {
-            termfreq_for_doc(&searcher, *field, addr, |term, term_freq| {
-                let doc_freq = searcher.doc_freq(&term);
-                if acceptor.accept(&term, term_freq, doc_freq, num_docs) {
-                    let score = term_freq as f32 * idf(doc_freq, num_docs);
-                    keywords.visit(term, score);
-                }
-            });
-        }
+        extract_from_searcher(&self.reader.searcher(), &fields, limit, addr, acceptor)
+    }
+}
 
-        keywords.into()
+/// Same as `TopTerms::extract_filtered_from_doc`, but reading from
+/// the given searcher: handy when there's no `TopTerms` around, since
+/// creating one requires an `IndexReader` of its own.
+///
+/// The fields must be text fields with frequencies (`TEXT`), like
+/// for `TopTerms::new`. Other fields yield no keywords
+pub fn extract_from_searcher<F: KeywordAcceptor>(
+    searcher: &Searcher,
+    fields: &[Field],
+    limit: usize,
+    addr: DocAddress,
+    acceptor: &F,
+) -> Keywords {
+    let num_docs = searcher.num_docs();
+    let schema = searcher.schema();
+
+    let mut keywords = DescendingTopK::new(limit);
+
+    for &field in fields
+        .iter()
+        .filter(|&&field| field_is_valid(schema, field))
+    {
+        termfreq_for_doc(searcher, field, addr, |term, term_freq| {
+            let doc_freq = searcher.doc_freq(&term);
+            if acceptor.accept(&term, term_freq, doc_freq, num_docs) {
+                let score = term_freq as f32 * idf(doc_freq, num_docs);
+                keywords.visit(term, score);
+            }
+        });
     }
+
+    keywords.into()
 }
 
 /// Keywords is a collection of Term objects found via TopTerms
@@ -475,6 +500,18 @@ mod tests {
             "expected groucho's to be the most similar to its own keyword set"
         );
 
+        // Same keywords when going straight through a searcher
+        for doc_id in 0..3 {
+            let addr = DocAddress(0, doc_id);
+            assert_eq!(
+                topterms
+                    .extract_filtered_from_doc(5, addr, &keyword_filter)
+                    .into_sorted_vec(),
+                extract_from_searcher(&searcher, &[source, quote], 5, addr, &keyword_filter)
+                    .into_sorted_vec()
+            );
+        }
+
         Ok(())
     }
 }